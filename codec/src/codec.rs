@@ -57,6 +57,7 @@ impl<'a> Reader<'a> {
 pub struct Writer<'a> {
     buf: &'a mut [u8],
     offs: usize,
+    overflowed: bool,
 }
 
 impl<'a> Writer<'a> {
@@ -64,11 +65,13 @@ impl<'a> Writer<'a> {
         Writer {
             buf: bytes,
             offs: 0,
+            overflowed: false,
         }
     }
 
     pub fn extend_from_slice(&mut self, value: &[u8]) -> Option<usize> {
         if self.left() < value.len() {
+            self.overflowed = true;
             return None;
         }
         let added = value.len();
@@ -81,6 +84,7 @@ impl<'a> Writer<'a> {
 
     pub fn push(&mut self, value: u8) -> Option<u8> {
         if self.left() < 1 {
+            self.overflowed = true;
             return None;
         }
         self.buf[self.offs] = value;
@@ -95,6 +99,11 @@ impl<'a> Writer<'a> {
     pub fn used(&self) -> usize {
         self.offs
     }
+
+    /// Whether data was dropped because it didn't fit.
+    pub fn is_overflowed(&self) -> bool {
+        self.overflowed
+    }
 }
 
 /// Things we can encode and read from a Reader.
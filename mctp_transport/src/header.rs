@@ -42,8 +42,40 @@ impl Codec for MctpMessageHeader {
     }
 }
 
+// SOM / EOM / packet sequence flags of the MCTP transport header, carried in
+// the first byte of each fragment if the MTU is set.
+const MCTP_FRAGMENT_SOM: u8 = 0x80;
+const MCTP_FRAGMENT_EOM: u8 = 0x40;
+const MCTP_FRAGMENT_SEQ_SHIFT: u8 = 4;
+const MCTP_FRAGMENT_SEQ_MASK: u8 = 0x3;
+const MCTP_FRAGMENT_HEADER_SIZE: usize = 1;
+
+// MCTP baseline transmission unit
+pub const MCTP_BASELINE_MTU: usize = 64;
+
+// Secured message fields of the MCTP binding, DSP0275: a 2-byte sequence
+// number in the record header and up to 32 bytes of random padding.
+pub const MCTP_SEQUENCE_NUMBER_COUNT: u8 = 2;
+pub const MCTP_MAX_RANDOM_COUNT: u16 = 32;
+
 #[derive(Debug, Copy, Clone, Default)]
-pub struct MctpTransportEncap {}
+pub struct MctpTransportEncap {
+    // transport messages are fragmented into packets of this payload size
+    mtu: Option<usize>,
+    // packet sequence numbers of the last fragment sent / received
+    tx_seq: u8,
+    rx_seq: u8,
+}
+
+impl MctpTransportEncap {
+    pub fn new(mtu: Option<usize>) -> Self {
+        MctpTransportEncap {
+            mtu: mtu.filter(|mtu| *mtu > 0),
+            tx_seq: 0,
+            rx_seq: 0,
+        }
+    }
+}
 
 impl SpdmTransportEncap for MctpTransportEncap {
     fn encap(
@@ -133,10 +165,143 @@ impl SpdmTransportEncap for MctpTransportEncap {
         Ok(payload_size)
     }
 
+    // The application message starts with its MCTP message type, e.g. PLDM.
+    fn encap_app_message(
+        &mut self,
+        app_message: &[u8],
+        app_buffer: &mut [u8],
+    ) -> SpdmResult<usize> {
+        match MctpMessageType::read(&mut Reader::init(app_message)) {
+            Some(MctpMessageType::MctpMessageTypeSpdm)
+            | Some(MctpMessageType::MctpMessageTypeSecuredMctp)
+            | None => return spdm_result_err!(EINVAL),
+            Some(_) => {}
+        }
+        if app_buffer.len() < app_message.len() {
+            return spdm_result_err!(EINVAL);
+        }
+        app_buffer[..app_message.len()].copy_from_slice(app_message);
+        Ok(app_message.len())
+    }
+
+    fn decap_app_message(
+        &mut self,
+        app_buffer: &[u8],
+        app_message: &mut [u8],
+    ) -> SpdmResult<Option<usize>> {
+        match MctpMessageType::read(&mut Reader::init(app_buffer)) {
+            Some(MctpMessageType::MctpMessageTypeSpdm) => return Ok(None),
+            Some(MctpMessageType::MctpMessageTypeSecuredMctp) | None => {
+                return spdm_result_err!(EINVAL)
+            }
+            Some(_) => {}
+        }
+        if app_message.len() < app_buffer.len() {
+            return spdm_result_err!(EINVAL);
+        }
+        app_message[..app_buffer.len()].copy_from_slice(app_buffer);
+        Ok(Some(app_buffer.len()))
+    }
+
     fn get_sequence_number_count(&mut self) -> u8 {
-        2
+        MCTP_SEQUENCE_NUMBER_COUNT
     }
     fn get_max_random_count(&mut self) -> u16 {
-        32
+        MCTP_MAX_RANDOM_COUNT
+    }
+
+    fn get_max_fragment_size(&mut self) -> Option<usize> {
+        self.mtu.map(|mtu| MCTP_FRAGMENT_HEADER_SIZE + mtu)
+    }
+
+    fn encap_fragment(
+        &mut self,
+        transport_buffer: &[u8],
+        offset: usize,
+        fragment_buffer: &mut [u8],
+    ) -> SpdmResult<(usize, usize)> {
+        let mtu = self.mtu.ok_or(spdm_err!(EINVAL))?;
+        if fragment_buffer.len() < MCTP_FRAGMENT_HEADER_SIZE || offset > transport_buffer.len() {
+            return spdm_result_err!(EINVAL);
+        }
+        let size = (transport_buffer.len() - offset)
+            .min(mtu)
+            .min(fragment_buffer.len() - MCTP_FRAGMENT_HEADER_SIZE);
+
+        let mut flags = 0u8;
+        if offset == 0 {
+            self.tx_seq = 0;
+            flags |= MCTP_FRAGMENT_SOM;
+        } else {
+            self.tx_seq = self.tx_seq.wrapping_add(1) & MCTP_FRAGMENT_SEQ_MASK;
+        }
+        flags |= self.tx_seq << MCTP_FRAGMENT_SEQ_SHIFT;
+        if offset + size == transport_buffer.len() {
+            flags |= MCTP_FRAGMENT_EOM;
+        }
+        fragment_buffer[0] = flags;
+        fragment_buffer[MCTP_FRAGMENT_HEADER_SIZE..(MCTP_FRAGMENT_HEADER_SIZE + size)]
+            .copy_from_slice(&transport_buffer[offset..(offset + size)]);
+        Ok((MCTP_FRAGMENT_HEADER_SIZE + size, size))
+    }
+
+    fn decap_fragment(
+        &mut self,
+        fragment: &[u8],
+        transport_buffer: &mut [u8],
+        offset: usize,
+    ) -> SpdmResult<(usize, bool)> {
+        if fragment.len() < MCTP_FRAGMENT_HEADER_SIZE {
+            return spdm_result_err!(EIO);
+        }
+        let flags = fragment[0];
+        let seq = (flags >> MCTP_FRAGMENT_SEQ_SHIFT) & MCTP_FRAGMENT_SEQ_MASK;
+        let som = flags & MCTP_FRAGMENT_SOM != 0;
+        if som != (offset == 0)
+            || (!som && seq != (self.rx_seq.wrapping_add(1) & MCTP_FRAGMENT_SEQ_MASK))
+        {
+            return spdm_result_err!(EINVAL);
+        }
+        self.rx_seq = seq;
+
+        let payload = &fragment[MCTP_FRAGMENT_HEADER_SIZE..];
+        let size = payload.len();
+        if transport_buffer.len() < offset + size {
+            return spdm_result_err!(EINVAL);
+        }
+        transport_buffer[offset..(offset + size)].copy_from_slice(payload);
+        Ok((size, flags & MCTP_FRAGMENT_EOM != 0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_type() {
+        let mut encap = MctpTransportEncap::default();
+        let mut transport_buffer = [0u8; 8];
+        let mut spdm_buffer = [0u8; 8];
+
+        for (secured_message, r#type) in [(false, 0x05u8), (true, 0x06u8)].iter() {
+            let used = encap
+                .encap(&[0x12, 0x84], &mut transport_buffer, *secured_message)
+                .unwrap();
+            assert_eq!(&transport_buffer[..used], &[*r#type, 0x12, 0x84]);
+            let (used, secured) = encap
+                .decap(&transport_buffer[..used], &mut spdm_buffer)
+                .unwrap();
+            assert_eq!(&spdm_buffer[..used], &[0x12, 0x84]);
+            assert_eq!(secured, *secured_message);
+        }
+
+        // PLDM, or SPDM with the integrity check bit set
+        assert!(encap.decap(&[0x01, 0x12], &mut spdm_buffer).is_err());
+        assert!(encap.decap(&[0x85, 0x12], &mut spdm_buffer).is_err());
+        assert!(encap.decap(&[], &mut spdm_buffer).is_err());
+        assert!(encap
+            .encap(&[0x12; 8], &mut transport_buffer, false)
+            .is_err());
     }
 }
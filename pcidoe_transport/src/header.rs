@@ -28,11 +28,21 @@ enum_builder! {
     }
 }
 
+// The Length field counts DW including the 2 DW header. It has 18 bits, 0
+// stands for the maximum of 2^18 DW.
+const PCI_DOE_LENGTH_MASK: u32 = 0x3FFFF;
+const PCI_DOE_MAX_LENGTH_IN_DW: u32 = 0x40000;
+const PCI_DOE_HEADER_SIZE: usize = 8;
+
+/// Largest payload of one data object.
+pub const PCI_DOE_MAX_PAYLOAD_SIZE: usize =
+    ((PCI_DOE_MAX_LENGTH_IN_DW as usize) << 2) - PCI_DOE_HEADER_SIZE;
+
 #[derive(Debug, Copy, Clone, Default)]
 pub struct PciDoeMessageHeader {
     pub vendor_id: PciDoeVendorId,
     pub data_object_type: PciDoeDataObjectType,
-    pub payload_length: u32, // in bytes
+    pub payload_length: u32, // in bytes, DW aligned
 }
 
 impl Codec for PciDoeMessageHeader {
@@ -40,28 +50,22 @@ impl Codec for PciDoeMessageHeader {
         self.vendor_id.encode(bytes);
         self.data_object_type.encode(bytes);
         0u8.encode(bytes);
-        let mut length = (self.payload_length + 8) >> 2;
-        if length > 0x100000 {
-            panic!();
-        }
-        if length == 0x100000 {
-            length = 0;
-        }
-        length.encode(bytes);
+        let length = (self.payload_length + PCI_DOE_HEADER_SIZE as u32) >> 2;
+        (length & PCI_DOE_LENGTH_MASK).encode(bytes);
     }
 
     fn read(r: &mut Reader) -> Option<PciDoeMessageHeader> {
         let vendor_id = PciDoeVendorId::read(r)?;
         let data_object_type = PciDoeDataObjectType::read(r)?;
         u8::read(r)?;
-        let mut length = u32::read(r)?;
+        let mut length = u32::read(r)? & PCI_DOE_LENGTH_MASK;
         if length == 0 {
-            length = 0x40000;
+            length = PCI_DOE_MAX_LENGTH_IN_DW;
         }
         if length < 2 {
             return None;
         }
-        let payload_length = (length << 2) - 8;
+        let payload_length = (length << 2) - PCI_DOE_HEADER_SIZE as u32;
         Some(PciDoeMessageHeader {
             vendor_id,
             data_object_type,
@@ -82,6 +86,11 @@ impl SpdmTransportEncap for PciDoeTransportEncap {
     ) -> SpdmResult<usize> {
         let payload_len = spdm_buffer.len();
         let aligned_payload_len = (payload_len + 3) / 4 * 4;
+        if aligned_payload_len > PCI_DOE_MAX_PAYLOAD_SIZE
+            || transport_buffer.len() < PCI_DOE_HEADER_SIZE + aligned_payload_len
+        {
+            return spdm_result_err!(EINVAL);
+        }
         let mut writer = Writer::init(&mut transport_buffer[..]);
         let pcidoe_header = PciDoeMessageHeader {
             vendor_id: PciDoeVendorId::PciDoeVendorIdPciSig,
@@ -94,10 +103,13 @@ impl SpdmTransportEncap for PciDoeTransportEncap {
         };
         pcidoe_header.encode(&mut writer);
         let header_size = writer.used();
-        if transport_buffer.len() < header_size + aligned_payload_len {
-            return spdm_result_err!(EINVAL);
-        }
         transport_buffer[header_size..(header_size + payload_len)].copy_from_slice(spdm_buffer);
+        // the data object is padded to DW with zeros
+        for b in transport_buffer[(header_size + payload_len)..(header_size + aligned_payload_len)]
+            .iter_mut()
+        {
+            *b = 0;
+        }
         Ok(header_size + aligned_payload_len)
     }
 
@@ -108,8 +120,10 @@ impl SpdmTransportEncap for PciDoeTransportEncap {
     ) -> SpdmResult<(usize, bool)> {
         let mut reader = Reader::init(&transport_buffer[..]);
         let secured_message;
+        let payload_size;
         match PciDoeMessageHeader::read(&mut reader) {
             Some(pcidoe_header) => {
+                payload_size = pcidoe_header.payload_length as usize;
                 match pcidoe_header.vendor_id {
                     PciDoeVendorId::PciDoeVendorIdPciSig => {}
                     _ => return spdm_result_err!(EINVAL),
@@ -123,12 +137,15 @@ impl SpdmTransportEncap for PciDoeTransportEncap {
             None => return spdm_result_err!(EIO),
         }
         let header_size = reader.used();
-        let payload_size = transport_buffer.len() - header_size;
-        // TBD : check payload_size with Length field;
+        // truncated data object
+        if transport_buffer.len() < header_size + payload_size {
+            return spdm_result_err!(EIO);
+        }
         if spdm_buffer.len() < payload_size {
             return spdm_result_err!(EINVAL);
         }
-        let payload = &transport_buffer[header_size..];
+        // anything after the data object is not part of it
+        let payload = &transport_buffer[header_size..(header_size + payload_size)];
         spdm_buffer[..payload_size].copy_from_slice(payload);
         Ok((payload_size, secured_message))
     }
@@ -150,3 +167,118 @@ impl SpdmTransportEncap for PciDoeTransportEncap {
         0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spdmlib::error::SpdmErrorNum;
+
+    #[test]
+    fn test_length() {
+        // 2^18 DW are encoded as 0
+        let header = PciDoeMessageHeader {
+            vendor_id: PciDoeVendorId::PciDoeVendorIdPciSig,
+            data_object_type: PciDoeDataObjectType::PciDoeDataObjectTypeSpdm,
+            payload_length: PCI_DOE_MAX_PAYLOAD_SIZE as u32,
+        };
+        let mut bytes = [0xFFu8; 8];
+        header.encode(&mut Writer::init(&mut bytes));
+        assert_eq!(bytes, [0x01, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        let header = PciDoeMessageHeader::read(&mut Reader::init(&bytes)).unwrap();
+        assert_eq!(header.payload_length as usize, PCI_DOE_MAX_PAYLOAD_SIZE);
+
+        // the reserved upper bits are ignored, less than the header is invalid
+        let header =
+            PciDoeMessageHeader::read(&mut Reader::init(&[1, 0, 1, 0, 3, 0, 0xFC, 0xFF])).unwrap();
+        assert_eq!(header.payload_length, 4);
+        assert!(PciDoeMessageHeader::read(&mut Reader::init(&[1, 0, 1, 0, 1, 0, 0, 0])).is_none());
+    }
+
+    #[test]
+    fn test_padding() {
+        let mut encap = PciDoeTransportEncap::default();
+        let mut transport_buffer = [0xFFu8; 20];
+        let mut spdm_buffer = [0u8; 16];
+
+        let used = encap
+            .encap(&[0x12, 0x84, 0x00, 0x00, 0x5A], &mut transport_buffer, true)
+            .unwrap();
+        assert_eq!(
+            &transport_buffer[..used],
+            &[1, 0, 2, 0, 4, 0, 0, 0, 0x12, 0x84, 0x00, 0x00, 0x5A, 0, 0, 0]
+        );
+
+        // the padding is left to the SPDM message to ignore
+        let (size, secured_message) = encap
+            .decap(&transport_buffer[..used], &mut spdm_buffer)
+            .unwrap();
+        assert_eq!(
+            &spdm_buffer[..size],
+            &[0x12, 0x84, 0x00, 0x00, 0x5A, 0, 0, 0]
+        );
+        assert!(secured_message);
+    }
+
+    #[test]
+    fn test_truncated() {
+        let mut encap = PciDoeTransportEncap::default();
+        let mut spdm_buffer = [0u8; 16];
+
+        // 4 DW declared, 3 DW received
+        let transport_buffer = [1, 0, 1, 0, 4, 0, 0, 0, 0x12, 0x84, 0x00, 0x00];
+        assert_eq!(
+            encap
+                .decap(&transport_buffer, &mut spdm_buffer)
+                .unwrap_err()
+                .num(),
+            SpdmErrorNum::EIO
+        );
+        // 2^18 DW declared
+        let transport_buffer = [1, 0, 1, 0, 0, 0, 0, 0, 0x12, 0x84, 0x00, 0x00];
+        assert_eq!(
+            encap
+                .decap(&transport_buffer, &mut spdm_buffer)
+                .unwrap_err()
+                .num(),
+            SpdmErrorNum::EIO
+        );
+        assert_eq!(
+            encap
+                .decap(&[1, 0, 1, 0], &mut spdm_buffer)
+                .unwrap_err()
+                .num(),
+            SpdmErrorNum::EIO
+        );
+    }
+
+    #[test]
+    fn test_buffer_too_small() {
+        let mut encap = PciDoeTransportEncap::default();
+
+        // the padded payload doesn't fit
+        let mut transport_buffer = [0u8; 12];
+        assert_eq!(
+            encap
+                .encap(
+                    &[0x12, 0x84, 0x00, 0x00, 0x5A],
+                    &mut transport_buffer,
+                    false
+                )
+                .unwrap_err()
+                .num(),
+            SpdmErrorNum::EINVAL
+        );
+
+        let transport_buffer = [
+            1, 0, 1, 0, 4, 0, 0, 0, 0x12, 0x84, 0x00, 0x00, 0x5A, 0, 0, 0,
+        ];
+        let mut spdm_buffer = [0u8; 4];
+        assert_eq!(
+            encap
+                .decap(&transport_buffer, &mut spdm_buffer)
+                .unwrap_err()
+                .num(),
+            SpdmErrorNum::EINVAL
+        );
+    }
+}
@@ -6,33 +6,62 @@
 
 use crate::common;
 use crate::config;
-use crate::msgs::SpdmCodec;
+use crate::error::SpdmResult;
 pub use crate::msgs::*;
 
 use codec::{Codec, Reader, Writer};
 
+// Read `count` extended algorithms, keeping those that fit in `ext_algs`.
+fn read_ext_algs(r: &mut Reader, count: u8, ext_algs: &mut [SpdmExtAlgStruct]) -> Option<()> {
+    for i in 0..(count as usize) {
+        let ext_alg = SpdmExtAlgStruct::read(r)?;
+        if let Some(entry) = ext_algs.get_mut(i) {
+            *entry = ext_alg;
+        }
+    }
+    Some(())
+}
+
 #[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmNegotiateAlgorithmsRequestPayload {
     pub measurement_specification: SpdmMeasurementSpecification,
+    pub other_params_support: SpdmOpaqueSupport,
     pub base_asym_algo: SpdmBaseAsymAlgo,
     pub base_hash_algo: SpdmBaseHashAlgo,
+    // extended algorithms beyond MAX_SPDM_EXTEND_*_ALGO_COUNT are dropped
+    pub ext_asym_count: u8,
+    pub ext_asym: [SpdmExtAlgStruct; config::MAX_SPDM_EXTEND_ASYM_ALGO_COUNT],
+    pub ext_hash_count: u8,
+    pub ext_hash: [SpdmExtAlgStruct; config::MAX_SPDM_EXTEND_HASH_ALGO_COUNT],
     pub alg_struct_count: u8,
     pub alg_struct: [SpdmAlgStruct; config::MAX_SPDM_ALG_STRUCT_COUNT],
 }
 
 impl SpdmCodec for SpdmNegotiateAlgorithmsRequestPayload {
-    fn spdm_encode(&self, _context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         self.alg_struct_count.encode(bytes); // param1
         0u8.encode(bytes); // param1
 
-        let mut length: u16 = 32;
+        let ext_asym = &self.ext_asym[..(self.ext_asym_count as usize).min(self.ext_asym.len())];
+        let ext_hash = &self.ext_hash[..(self.ext_hash_count as usize).min(self.ext_hash.len())];
+        let mut length: u16 = 32 + 4 * ext_asym.len() as u16 + 4 * ext_hash.len() as u16;
         for algo in self.alg_struct.iter().take(self.alg_struct_count as usize) {
             length += 2 + algo.alg_fixed_count as u16;
         }
         length.encode(bytes);
 
         self.measurement_specification.encode(bytes);
-        0u8.encode(bytes); // reserved
+        if context.negotiate_info.spdm_version_sel.get_u8() >= SpdmVersion::SpdmVersion12.get_u8() {
+            self.other_params_support.encode(bytes);
+        } else {
+            0u8.encode(bytes); // reserved
+        }
 
         self.base_asym_algo.encode(bytes);
         self.base_hash_algo.encode(bytes);
@@ -40,19 +69,22 @@ impl SpdmCodec for SpdmNegotiateAlgorithmsRequestPayload {
             0u8.encode(bytes); // reserved2
         }
 
-        0u8.encode(bytes); // ext_asym_count
-
-        0u8.encode(bytes); // ext_hash_count
-
+        (ext_asym.len() as u8).encode(bytes);
+        (ext_hash.len() as u8).encode(bytes);
         0u16.encode(bytes); // reserved3
 
+        for algo in ext_asym.iter().chain(ext_hash) {
+            algo.encode(bytes);
+        }
+
         for algo in self.alg_struct.iter().take(self.alg_struct_count as usize) {
             algo.encode(bytes);
         }
+        spdm_encoded_size(bytes, start)
     }
 
     fn spdm_read(
-        _context: &mut common::SpdmContext,
+        context: &mut common::SpdmContext,
         r: &mut Reader,
     ) -> Option<SpdmNegotiateAlgorithmsRequestPayload> {
         let alg_struct_count = u8::read(r)?; // param1
@@ -60,7 +92,14 @@ impl SpdmCodec for SpdmNegotiateAlgorithmsRequestPayload {
 
         let length = u16::read(r)?;
         let measurement_specification = SpdmMeasurementSpecification::read(r)?;
-        u8::read(r)?; // reserved
+        let other_params_support = if context.negotiate_info.spdm_version_sel.get_u8()
+            >= SpdmVersion::SpdmVersion12.get_u8()
+        {
+            SpdmOpaqueSupport::read(r)?
+        } else {
+            u8::read(r)?; // reserved
+            SpdmOpaqueSupport::empty()
+        };
 
         let base_asym_algo = SpdmBaseAsymAlgo::read(r)?;
         let base_hash_algo = SpdmBaseHashAlgo::read(r)?;
@@ -70,17 +109,14 @@ impl SpdmCodec for SpdmNegotiateAlgorithmsRequestPayload {
         }
 
         let ext_asym_count = u8::read(r)?;
-        for _ in 0..(ext_asym_count as usize) {
-            SpdmExtAlgStruct::read(r)?;
-        }
-
         let ext_hash_count = u8::read(r)?;
-        for _ in 0..(ext_hash_count as usize) {
-            SpdmExtAlgStruct::read(r)?;
-        }
-
         u16::read(r)?; // reserved3
 
+        let mut ext_asym = [SpdmExtAlgStruct::default(); config::MAX_SPDM_EXTEND_ASYM_ALGO_COUNT];
+        read_ext_algs(r, ext_asym_count, &mut ext_asym)?;
+        let mut ext_hash = [SpdmExtAlgStruct::default(); config::MAX_SPDM_EXTEND_HASH_ALGO_COUNT];
+        read_ext_algs(r, ext_hash_count, &mut ext_hash)?;
+
         let mut alg_struct = [SpdmAlgStruct::default(); config::MAX_SPDM_ALG_STRUCT_COUNT];
         for algo in alg_struct.iter_mut().take(alg_struct_count as usize) {
             *algo = SpdmAlgStruct::read(r)?;
@@ -100,8 +136,13 @@ impl SpdmCodec for SpdmNegotiateAlgorithmsRequestPayload {
 
         Some(SpdmNegotiateAlgorithmsRequestPayload {
             measurement_specification,
+            other_params_support,
             base_asym_algo,
             base_hash_algo,
+            ext_asym_count: ext_asym_count.min(ext_asym.len() as u8),
+            ext_asym,
+            ext_hash_count: ext_hash_count.min(ext_hash.len() as u8),
+            ext_hash,
             alg_struct_count,
             alg_struct,
         })
@@ -109,28 +150,48 @@ impl SpdmCodec for SpdmNegotiateAlgorithmsRequestPayload {
 }
 
 #[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmAlgorithmsResponsePayload {
     pub measurement_specification_sel: SpdmMeasurementSpecification,
+    pub other_params_selection: SpdmOpaqueSupport,
     pub measurement_hash_algo: SpdmMeasurementHashAlgo,
     pub base_asym_sel: SpdmBaseAsymAlgo,
     pub base_hash_sel: SpdmBaseHashAlgo,
+    // at most one of each is selected
+    pub ext_asym_sel_count: u8,
+    pub ext_asym_sel: [SpdmExtAlgStruct; config::MAX_SPDM_EXTEND_ASYM_ALGO_COUNT],
+    pub ext_hash_sel_count: u8,
+    pub ext_hash_sel: [SpdmExtAlgStruct; config::MAX_SPDM_EXTEND_HASH_ALGO_COUNT],
     pub alg_struct_count: u8,
     pub alg_struct: [SpdmAlgStruct; config::MAX_SPDM_ALG_STRUCT_COUNT],
 }
 
 impl SpdmCodec for SpdmAlgorithmsResponsePayload {
-    fn spdm_encode(&self, _context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         self.alg_struct_count.encode(bytes); // param1
         0u8.encode(bytes); // param2
 
-        let mut length: u16 = 36;
+        let ext_asym_sel =
+            &self.ext_asym_sel[..(self.ext_asym_sel_count as usize).min(self.ext_asym_sel.len())];
+        let ext_hash_sel =
+            &self.ext_hash_sel[..(self.ext_hash_sel_count as usize).min(self.ext_hash_sel.len())];
+        let mut length: u16 = 36 + 4 * ext_asym_sel.len() as u16 + 4 * ext_hash_sel.len() as u16;
         for alg in self.alg_struct.iter().take(self.alg_struct_count as usize) {
             length += 2 + alg.alg_fixed_count as u16;
         }
         length.encode(bytes);
 
         self.measurement_specification_sel.encode(bytes);
-        0u8.encode(bytes); // reserved
+        if context.negotiate_info.spdm_version_sel.get_u8() >= SpdmVersion::SpdmVersion12.get_u8() {
+            self.other_params_selection.encode(bytes);
+        } else {
+            0u8.encode(bytes); // reserved
+        }
 
         self.measurement_hash_algo.encode(bytes);
         self.base_asym_sel.encode(bytes);
@@ -139,19 +200,22 @@ impl SpdmCodec for SpdmAlgorithmsResponsePayload {
             0u8.encode(bytes); // reserved2
         }
 
-        0u8.encode(bytes); // ext_asym_count
-
-        0u8.encode(bytes); // ext_hash_count
-
+        (ext_asym_sel.len() as u8).encode(bytes);
+        (ext_hash_sel.len() as u8).encode(bytes);
         0u16.encode(bytes); // reserved3
 
+        for algo in ext_asym_sel.iter().chain(ext_hash_sel) {
+            algo.encode(bytes);
+        }
+
         for algo in self.alg_struct.iter().take(self.alg_struct_count as usize) {
             algo.encode(bytes);
         }
+        spdm_encoded_size(bytes, start)
     }
 
     fn spdm_read(
-        _context: &mut common::SpdmContext,
+        context: &mut common::SpdmContext,
         r: &mut Reader,
     ) -> Option<SpdmAlgorithmsResponsePayload> {
         let alg_struct_count = u8::read(r)?; // param1
@@ -160,7 +224,14 @@ impl SpdmCodec for SpdmAlgorithmsResponsePayload {
         let length = u16::read(r)?;
 
         let measurement_specification_sel = SpdmMeasurementSpecification::read(r)?;
-        u8::read(r)?; // reserved
+        let other_params_selection = if context.negotiate_info.spdm_version_sel.get_u8()
+            >= SpdmVersion::SpdmVersion12.get_u8()
+        {
+            SpdmOpaqueSupport::read(r)?
+        } else {
+            u8::read(r)?; // reserved
+            SpdmOpaqueSupport::empty()
+        };
 
         let measurement_hash_algo = SpdmMeasurementHashAlgo::read(r)?;
         let base_asym_sel = SpdmBaseAsymAlgo::read(r)?;
@@ -171,17 +242,14 @@ impl SpdmCodec for SpdmAlgorithmsResponsePayload {
         }
 
         let ext_asym_count = u8::read(r)?;
-        for _ in 0..(ext_asym_count as usize) {
-            SpdmExtAlgStruct::read(r)?;
-        }
-
         let ext_hash_count = u8::read(r)?;
-        for _ in 0..(ext_hash_count as usize) {
-            SpdmExtAlgStruct::read(r)?;
-        }
-
         u16::read(r)?; // reserved3
 
+        let mut ext_asym = [SpdmExtAlgStruct::default(); config::MAX_SPDM_EXTEND_ASYM_ALGO_COUNT];
+        read_ext_algs(r, ext_asym_count, &mut ext_asym)?;
+        let mut ext_hash = [SpdmExtAlgStruct::default(); config::MAX_SPDM_EXTEND_HASH_ALGO_COUNT];
+        read_ext_algs(r, ext_hash_count, &mut ext_hash)?;
+
         let mut alg_struct = [SpdmAlgStruct::default(); config::MAX_SPDM_ALG_STRUCT_COUNT];
         for algo in alg_struct.iter_mut().take(alg_struct_count as usize) {
             *algo = SpdmAlgStruct::read(r)?;
@@ -198,9 +266,14 @@ impl SpdmCodec for SpdmAlgorithmsResponsePayload {
 
         Some(SpdmAlgorithmsResponsePayload {
             measurement_specification_sel,
+            other_params_selection,
             measurement_hash_algo,
             base_asym_sel,
             base_hash_sel,
+            ext_asym_sel_count: ext_asym_count.min(ext_asym.len() as u8),
+            ext_asym_sel: ext_asym,
+            ext_hash_sel_count: ext_hash_count.min(ext_hash.len() as u8),
+            ext_hash_sel: ext_hash,
             alg_struct_count,
             alg_struct,
         })
@@ -5,7 +5,8 @@
 #![forbid(unsafe_code)]
 
 use crate::common;
-use crate::msgs::SpdmCodec;
+use crate::error::SpdmResult;
+use crate::msgs::{spdm_encoded_size, SpdmCodec, SpdmVersion};
 use codec::{Codec, Reader, Writer};
 
 bitflags! {
@@ -24,6 +25,7 @@ bitflags! {
         const KEY_UPD_CAP = 0b0100_0000_0000_0000;
         const HANDSHAKE_IN_THE_CLEAR_CAP = 0b1000_0000_0000_0000;
         const PUB_KEY_ID_CAP = 0b0000_0001_0000_0000_0000_0000;
+        const CHUNK_CAP = 0b0000_0010_0000_0000_0000_0000;
     }
 }
 
@@ -40,35 +42,78 @@ impl Codec for SpdmRequestCapabilityFlags {
 }
 
 #[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmGetCapabilitiesRequestPayload {
     pub ct_exponent: u8,
     pub flags: SpdmRequestCapabilityFlags,
+    // SPDM 1.2 only
+    pub data_transfer_size: u32,
+    pub max_spdm_msg_size: u32,
 }
 
 impl SpdmCodec for SpdmGetCapabilitiesRequestPayload {
-    fn spdm_encode(&self, _context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         0u8.encode(bytes); // param1
         0u8.encode(bytes); // param2
 
+        // the 1.0 request is the header only
+        if context.negotiate_info.spdm_version_sel == SpdmVersion::SpdmVersion10 {
+            return spdm_encoded_size(bytes, start);
+        }
+
         0u8.encode(bytes); // reserved
         self.ct_exponent.encode(bytes);
         0u16.encode(bytes); // reserved2
         self.flags.encode(bytes);
+
+        if context.negotiate_info.spdm_version_sel.get_u8() >= SpdmVersion::SpdmVersion12.get_u8() {
+            self.data_transfer_size.encode(bytes);
+            self.max_spdm_msg_size.encode(bytes);
+        }
+        spdm_encoded_size(bytes, start)
     }
 
     fn spdm_read(
-        _context: &mut common::SpdmContext,
+        context: &mut common::SpdmContext,
         r: &mut Reader,
     ) -> Option<SpdmGetCapabilitiesRequestPayload> {
         u8::read(r)?; // param1
         u8::read(r)?; // param2
 
+        if context.negotiate_info.spdm_version_sel == SpdmVersion::SpdmVersion10 {
+            return Some(SpdmGetCapabilitiesRequestPayload {
+                ct_exponent: 0,
+                flags: SpdmRequestCapabilityFlags::empty(),
+                data_transfer_size: 0,
+                max_spdm_msg_size: 0,
+            });
+        }
+
         u8::read(r)?; // reserved
         let ct_exponent = u8::read(r)?;
         u16::read(r)?; // reserved2
         let flags = SpdmRequestCapabilityFlags::read(r)?;
 
-        Some(SpdmGetCapabilitiesRequestPayload { ct_exponent, flags })
+        let (data_transfer_size, max_spdm_msg_size) =
+            if context.negotiate_info.spdm_version_sel.get_u8()
+                >= SpdmVersion::SpdmVersion12.get_u8()
+            {
+                (u32::read(r)?, u32::read(r)?)
+            } else {
+                (0, 0)
+            };
+
+        Some(SpdmGetCapabilitiesRequestPayload {
+            ct_exponent,
+            flags,
+            data_transfer_size,
+            max_spdm_msg_size,
+        })
     }
 }
 
@@ -94,6 +139,7 @@ bitflags! {
         const KEY_UPD_CAP = 0b0100_0000_0000_0000;
         const HANDSHAKE_IN_THE_CLEAR_CAP = 0b1000_0000_0000_0000;
         const PUB_KEY_ID_CAP = 0b0000_0001_0000_0000_0000_0000;
+        const CHUNK_CAP = 0b0000_0010_0000_0000_0000_0000;
     }
 }
 
@@ -110,13 +156,22 @@ impl Codec for SpdmResponseCapabilityFlags {
 }
 
 #[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmCapabilitiesResponsePayload {
     pub ct_exponent: u8,
     pub flags: SpdmResponseCapabilityFlags,
+    // SPDM 1.2 only
+    pub data_transfer_size: u32,
+    pub max_spdm_msg_size: u32,
 }
 
 impl SpdmCodec for SpdmCapabilitiesResponsePayload {
-    fn spdm_encode(&self, _context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         0u8.encode(bytes); // param1
         0u8.encode(bytes); // param2
 
@@ -124,10 +179,16 @@ impl SpdmCodec for SpdmCapabilitiesResponsePayload {
         self.ct_exponent.encode(bytes);
         0u16.encode(bytes); // reserved2
         self.flags.encode(bytes);
+
+        if context.negotiate_info.spdm_version_sel.get_u8() >= SpdmVersion::SpdmVersion12.get_u8() {
+            self.data_transfer_size.encode(bytes);
+            self.max_spdm_msg_size.encode(bytes);
+        }
+        spdm_encoded_size(bytes, start)
     }
 
     fn spdm_read(
-        _context: &mut common::SpdmContext,
+        context: &mut common::SpdmContext,
         r: &mut Reader,
     ) -> Option<SpdmCapabilitiesResponsePayload> {
         u8::read(r)?; // param1
@@ -138,6 +199,20 @@ impl SpdmCodec for SpdmCapabilitiesResponsePayload {
         u16::read(r)?; // reserved2
         let flags = SpdmResponseCapabilityFlags::read(r)?;
 
-        Some(SpdmCapabilitiesResponsePayload { ct_exponent, flags })
+        let (data_transfer_size, max_spdm_msg_size) =
+            if context.negotiate_info.spdm_version_sel.get_u8()
+                >= SpdmVersion::SpdmVersion12.get_u8()
+            {
+                (u32::read(r)?, u32::read(r)?)
+            } else {
+                (0, 0)
+            };
+
+        Some(SpdmCapabilitiesResponsePayload {
+            ct_exponent,
+            flags,
+            data_transfer_size,
+            max_spdm_msg_size,
+        })
     }
 }
@@ -6,10 +6,12 @@
 
 use crate::common;
 use crate::config;
-use crate::msgs::SpdmCodec;
+use crate::error::SpdmResult;
+use crate::msgs::{spdm_encoded_size, SpdmCodec};
 use codec::{Codec, Reader, Writer};
 
 #[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmGetCertificateRequestPayload {
     pub slot_id: u8,
     pub offset: u16,
@@ -17,11 +19,17 @@ pub struct SpdmGetCertificateRequestPayload {
 }
 
 impl SpdmCodec for SpdmGetCertificateRequestPayload {
-    fn spdm_encode(&self, _context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        _context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         self.slot_id.encode(bytes); // param1
         0u8.encode(bytes); // param2
         self.offset.encode(bytes);
         self.length.encode(bytes);
+        spdm_encoded_size(bytes, start)
     }
 
     fn spdm_read(
@@ -60,7 +68,12 @@ impl Default for SpdmCertificateResponsePayload {
 }
 
 impl SpdmCodec for SpdmCertificateResponsePayload {
-    fn spdm_encode(&self, _context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        _context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         self.slot_id.encode(bytes); // param1
         0u8.encode(bytes); // param2
         self.portion_length.encode(bytes);
@@ -69,6 +82,7 @@ impl SpdmCodec for SpdmCertificateResponsePayload {
         for d in self.cert_chain.iter().take(self.portion_length as usize) {
             d.encode(bytes);
         }
+        spdm_encoded_size(bytes, start)
     }
 
     fn spdm_read(
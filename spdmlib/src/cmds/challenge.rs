@@ -5,7 +5,8 @@
 #![forbid(unsafe_code)]
 
 use crate::common;
-use crate::msgs::SpdmCodec;
+use crate::error::SpdmResult;
+use crate::msgs::{spdm_encoded_size, SpdmCodec};
 use crate::msgs::{
     SpdmDigestStruct, SpdmMeasurementSummaryHashType, SpdmNonceStruct, SpdmOpaqueStruct,
     SpdmSignatureStruct,
@@ -13,6 +14,7 @@ use crate::msgs::{
 use codec::{Codec, Reader, Writer};
 
 #[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmChallengeRequestPayload {
     pub slot_id: u8,
     pub measurement_summary_hash_type: SpdmMeasurementSummaryHashType,
@@ -20,10 +22,16 @@ pub struct SpdmChallengeRequestPayload {
 }
 
 impl SpdmCodec for SpdmChallengeRequestPayload {
-    fn spdm_encode(&self, _context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        _context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         self.slot_id.encode(bytes); // param1
         self.measurement_summary_hash_type.encode(bytes); // param2
         self.nonce.encode(bytes);
+        spdm_encoded_size(bytes, start)
     }
 
     fn spdm_read(
@@ -50,6 +58,7 @@ bitflags! {
 }
 
 #[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmChallengeAuthResponsePayload {
     pub slot_id: u8,
     pub slot_mask: u8,
@@ -62,17 +71,23 @@ pub struct SpdmChallengeAuthResponsePayload {
 }
 
 impl SpdmCodec for SpdmChallengeAuthResponsePayload {
-    fn spdm_encode(&self, context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         let param1 = self.slot_id + self.challenge_auth_attribute.bits();
         param1.encode(bytes);
         self.slot_mask.encode(bytes); // param2
-        self.cert_chain_hash.spdm_encode(context, bytes);
+        self.cert_chain_hash.spdm_encode(context, bytes)?;
         self.nonce.encode(bytes);
         if context.runtime_info.need_measurement_summary_hash {
-            self.measurement_summary_hash.spdm_encode(context, bytes);
+            self.measurement_summary_hash.spdm_encode(context, bytes)?;
         }
-        self.opaque.spdm_encode(context, bytes);
-        self.signature.spdm_encode(context, bytes);
+        self.opaque.spdm_encode(context, bytes)?;
+        self.signature.spdm_encode(context, bytes)?;
+        spdm_encoded_size(bytes, start)
     }
 
     fn spdm_read(
@@ -0,0 +1,308 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+#![forbid(unsafe_code)]
+
+use crate::common;
+use crate::config;
+use crate::error::SpdmResult;
+use crate::msgs::{spdm_encoded_size, SpdmCodec};
+use codec::{Codec, Reader, Writer};
+
+bitflags! {
+    #[derive(Default)]
+    pub struct SpdmChunkAttributes: u8 {
+        const LAST_CHUNK = 0b0000_0001;
+    }
+}
+
+impl Codec for SpdmChunkAttributes {
+    fn encode(&self, bytes: &mut Writer) {
+        self.bits().encode(bytes);
+    }
+
+    fn read(r: &mut Reader) -> Option<SpdmChunkAttributes> {
+        let bits = u8::read(r)?;
+
+        SpdmChunkAttributes::from_bits(bits)
+    }
+}
+
+bitflags! {
+    #[derive(Default)]
+    pub struct SpdmChunkSendAckAttributes: u8 {
+        const EARLY_ERROR_DETECTED = 0b0000_0001;
+    }
+}
+
+impl Codec for SpdmChunkSendAckAttributes {
+    fn encode(&self, bytes: &mut Writer) {
+        self.bits().encode(bytes);
+    }
+
+    fn read(r: &mut Reader) -> Option<SpdmChunkSendAckAttributes> {
+        let bits = u8::read(r)?;
+
+        SpdmChunkSendAckAttributes::from_bits(bits)
+    }
+}
+
+// CHUNK_SEND and CHUNK_RESPONSE carry the same chunk layout:
+// ChunkSeqNo, Reserved, ChunkSize, LargeMessageSize (first chunk only), SPDMchunk
+fn encode_chunk(
+    chunk_seq_no: u16,
+    large_message_size: u32,
+    chunk_size: u32,
+    chunk: &[u8],
+    bytes: &mut Writer,
+) {
+    chunk_seq_no.encode(bytes);
+    0u16.encode(bytes); // reserved
+    chunk_size.encode(bytes);
+    if chunk_seq_no == 0 {
+        large_message_size.encode(bytes);
+    }
+    for d in chunk.iter().take(chunk_size as usize) {
+        d.encode(bytes);
+    }
+}
+
+fn read_chunk(r: &mut Reader) -> Option<(u16, u32, u32, [u8; config::MAX_SPDM_TRANSPORT_SIZE])> {
+    let chunk_seq_no = u16::read(r)?;
+    u16::read(r)?; // reserved
+    let chunk_size = u32::read(r)?;
+    let large_message_size = if chunk_seq_no == 0 { u32::read(r)? } else { 0 };
+    if chunk_size as usize > config::MAX_SPDM_TRANSPORT_SIZE {
+        return None;
+    }
+    let mut chunk = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+    for d in chunk.iter_mut().take(chunk_size as usize) {
+        *d = u8::read(r)?;
+    }
+    Some((chunk_seq_no, large_message_size, chunk_size, chunk))
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct SpdmChunkSendRequestPayload {
+    pub attributes: SpdmChunkAttributes,
+    pub handle: u8,
+    pub chunk_seq_no: u16,
+    // only sent in the first chunk
+    pub large_message_size: u32,
+    pub chunk_size: u32,
+    pub chunk: [u8; config::MAX_SPDM_TRANSPORT_SIZE],
+}
+
+impl Default for SpdmChunkSendRequestPayload {
+    fn default() -> SpdmChunkSendRequestPayload {
+        SpdmChunkSendRequestPayload {
+            attributes: SpdmChunkAttributes::default(),
+            handle: 0,
+            chunk_seq_no: 0,
+            large_message_size: 0,
+            chunk_size: 0,
+            chunk: [0u8; config::MAX_SPDM_TRANSPORT_SIZE],
+        }
+    }
+}
+
+impl SpdmCodec for SpdmChunkSendRequestPayload {
+    fn spdm_encode(
+        &self,
+        _context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
+        self.attributes.encode(bytes); // param1
+        self.handle.encode(bytes); // param2
+        encode_chunk(
+            self.chunk_seq_no,
+            self.large_message_size,
+            self.chunk_size,
+            &self.chunk,
+            bytes,
+        );
+        spdm_encoded_size(bytes, start)
+    }
+
+    fn spdm_read(
+        _context: &mut common::SpdmContext,
+        r: &mut Reader,
+    ) -> Option<SpdmChunkSendRequestPayload> {
+        let attributes = SpdmChunkAttributes::read(r)?; // param1
+        let handle = u8::read(r)?; // param2
+        let (chunk_seq_no, large_message_size, chunk_size, chunk) = read_chunk(r)?;
+
+        Some(SpdmChunkSendRequestPayload {
+            attributes,
+            handle,
+            chunk_seq_no,
+            large_message_size,
+            chunk_size,
+            chunk,
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct SpdmChunkSendAckResponsePayload {
+    pub attributes: SpdmChunkSendAckAttributes,
+    pub handle: u8,
+    pub chunk_seq_no: u16,
+    // response to the large request, only sent with the last chunk
+    // or when an early error is detected
+    pub response_size: u32,
+    pub response: [u8; config::MAX_SPDM_TRANSPORT_SIZE],
+}
+
+impl Default for SpdmChunkSendAckResponsePayload {
+    fn default() -> SpdmChunkSendAckResponsePayload {
+        SpdmChunkSendAckResponsePayload {
+            attributes: SpdmChunkSendAckAttributes::default(),
+            handle: 0,
+            chunk_seq_no: 0,
+            response_size: 0,
+            response: [0u8; config::MAX_SPDM_TRANSPORT_SIZE],
+        }
+    }
+}
+
+impl SpdmCodec for SpdmChunkSendAckResponsePayload {
+    fn spdm_encode(
+        &self,
+        _context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
+        self.attributes.encode(bytes); // param1
+        self.handle.encode(bytes); // param2
+        self.chunk_seq_no.encode(bytes);
+        for d in self.response.iter().take(self.response_size as usize) {
+            d.encode(bytes);
+        }
+        spdm_encoded_size(bytes, start)
+    }
+
+    fn spdm_read(
+        _context: &mut common::SpdmContext,
+        r: &mut Reader,
+    ) -> Option<SpdmChunkSendAckResponsePayload> {
+        let attributes = SpdmChunkSendAckAttributes::read(r)?; // param1
+        let handle = u8::read(r)?; // param2
+        let chunk_seq_no = u16::read(r)?;
+
+        // the response takes the rest of the message
+        let rest = r.rest();
+        if rest.len() > config::MAX_SPDM_TRANSPORT_SIZE {
+            return None;
+        }
+        let mut response = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        response[..rest.len()].copy_from_slice(rest);
+
+        Some(SpdmChunkSendAckResponsePayload {
+            attributes,
+            handle,
+            chunk_seq_no,
+            response_size: rest.len() as u32,
+            response,
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
+pub struct SpdmChunkGetRequestPayload {
+    pub handle: u8,
+    pub chunk_seq_no: u16,
+}
+
+impl SpdmCodec for SpdmChunkGetRequestPayload {
+    fn spdm_encode(
+        &self,
+        _context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
+        0u8.encode(bytes); // param1
+        self.handle.encode(bytes); // param2
+        self.chunk_seq_no.encode(bytes);
+        spdm_encoded_size(bytes, start)
+    }
+
+    fn spdm_read(
+        _context: &mut common::SpdmContext,
+        r: &mut Reader,
+    ) -> Option<SpdmChunkGetRequestPayload> {
+        u8::read(r)?; // param1
+        let handle = u8::read(r)?; // param2
+        let chunk_seq_no = u16::read(r)?;
+
+        Some(SpdmChunkGetRequestPayload {
+            handle,
+            chunk_seq_no,
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct SpdmChunkResponsePayload {
+    pub attributes: SpdmChunkAttributes,
+    pub handle: u8,
+    pub chunk_seq_no: u16,
+    // only sent in the first chunk
+    pub large_message_size: u32,
+    pub chunk_size: u32,
+    pub chunk: [u8; config::MAX_SPDM_TRANSPORT_SIZE],
+}
+
+impl Default for SpdmChunkResponsePayload {
+    fn default() -> SpdmChunkResponsePayload {
+        SpdmChunkResponsePayload {
+            attributes: SpdmChunkAttributes::default(),
+            handle: 0,
+            chunk_seq_no: 0,
+            large_message_size: 0,
+            chunk_size: 0,
+            chunk: [0u8; config::MAX_SPDM_TRANSPORT_SIZE],
+        }
+    }
+}
+
+impl SpdmCodec for SpdmChunkResponsePayload {
+    fn spdm_encode(
+        &self,
+        _context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
+        self.attributes.encode(bytes); // param1
+        self.handle.encode(bytes); // param2
+        encode_chunk(
+            self.chunk_seq_no,
+            self.large_message_size,
+            self.chunk_size,
+            &self.chunk,
+            bytes,
+        );
+        spdm_encoded_size(bytes, start)
+    }
+
+    fn spdm_read(
+        _context: &mut common::SpdmContext,
+        r: &mut Reader,
+    ) -> Option<SpdmChunkResponsePayload> {
+        let attributes = SpdmChunkAttributes::read(r)?; // param1
+        let handle = u8::read(r)?; // param2
+        let (chunk_seq_no, large_message_size, chunk_size, chunk) = read_chunk(r)?;
+
+        Some(SpdmChunkResponsePayload {
+            attributes,
+            handle,
+            chunk_seq_no,
+            large_message_size,
+            chunk_size,
+            chunk,
+        })
+    }
+}
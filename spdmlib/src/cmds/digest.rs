@@ -5,17 +5,25 @@
 #![forbid(unsafe_code)]
 
 use crate::common;
-use crate::msgs::SpdmCodec;
+use crate::error::SpdmResult;
+use crate::msgs::{spdm_encoded_size, SpdmCodec};
 use crate::msgs::{SpdmDigestStruct, SPDM_MAX_SLOT_NUMBER};
 use codec::{Codec, Reader, Writer};
 
 #[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmGetDigestsRequestPayload {}
 
 impl SpdmCodec for SpdmGetDigestsRequestPayload {
-    fn spdm_encode(&self, _context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        _context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         0u8.encode(bytes); // param1
         0u8.encode(bytes); // param2
+        spdm_encoded_size(bytes, start)
     }
 
     fn spdm_read(
@@ -30,6 +38,7 @@ impl SpdmCodec for SpdmGetDigestsRequestPayload {
 }
 
 #[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmDigestsResponsePayload {
     pub slot_mask: u8,
     pub slot_count: u8,
@@ -37,7 +46,12 @@ pub struct SpdmDigestsResponsePayload {
 }
 
 impl SpdmCodec for SpdmDigestsResponsePayload {
-    fn spdm_encode(&self, context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         0u8.encode(bytes); // param1
         self.slot_mask.encode(bytes); // param2
 
@@ -49,12 +63,13 @@ impl SpdmCodec for SpdmDigestsResponsePayload {
         }
 
         if count != self.slot_count {
-            panic!();
+            return spdm_result_err!(EINVAL);
         }
 
         for digest in self.digests.iter().take(count as usize) {
-            digest.spdm_encode(context, bytes);
+            digest.spdm_encode(context, bytes)?;
         }
+        spdm_encoded_size(bytes, start)
     }
 
     fn spdm_read(
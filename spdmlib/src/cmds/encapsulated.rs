@@ -0,0 +1,226 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+#![forbid(unsafe_code)]
+
+use crate::common;
+use crate::config;
+use crate::error::SpdmResult;
+use crate::msgs::{spdm_encoded_size, SpdmCodec, SpdmVersion};
+use codec::enum_builder;
+use codec::{Codec, Reader, Writer};
+
+enum_builder! {
+    @U8
+    EnumName: SpdmEncapsulatedResponseAckPayloadType;
+    EnumVal{
+        SpdmEncapsulatedPayloadAbsent => 0x0,
+        SpdmEncapsulatedPayloadPresent => 0x1,
+        SpdmEncapsulatedPayloadReqSlotNumber => 0x2
+    }
+}
+
+// The encapsulated message takes the rest of the message.
+fn encode_encapsulated(message_size: u32, message: &[u8], bytes: &mut Writer) {
+    for d in message.iter().take(message_size as usize) {
+        d.encode(bytes);
+    }
+}
+
+fn read_encapsulated(r: &mut Reader) -> Option<(u32, [u8; config::MAX_SPDM_TRANSPORT_SIZE])> {
+    let rest = r.rest();
+    if rest.len() > config::MAX_SPDM_TRANSPORT_SIZE {
+        return None;
+    }
+    let mut message = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+    message[..rest.len()].copy_from_slice(rest);
+    Some((rest.len() as u32, message))
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
+pub struct SpdmGetEncapsulatedRequestPayload {}
+
+impl SpdmCodec for SpdmGetEncapsulatedRequestPayload {
+    fn spdm_encode(
+        &self,
+        _context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
+        0u8.encode(bytes); // param1
+        0u8.encode(bytes); // param2
+        spdm_encoded_size(bytes, start)
+    }
+
+    fn spdm_read(
+        _context: &mut common::SpdmContext,
+        r: &mut Reader,
+    ) -> Option<SpdmGetEncapsulatedRequestPayload> {
+        u8::read(r)?; // param1
+        u8::read(r)?; // param2
+
+        Some(SpdmGetEncapsulatedRequestPayload {})
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct SpdmEncapsulatedRequestPayload {
+    pub request_id: u8,
+    pub request_size: u32,
+    pub request: [u8; config::MAX_SPDM_TRANSPORT_SIZE],
+}
+
+impl Default for SpdmEncapsulatedRequestPayload {
+    fn default() -> SpdmEncapsulatedRequestPayload {
+        SpdmEncapsulatedRequestPayload {
+            request_id: 0,
+            request_size: 0,
+            request: [0u8; config::MAX_SPDM_TRANSPORT_SIZE],
+        }
+    }
+}
+
+impl SpdmCodec for SpdmEncapsulatedRequestPayload {
+    fn spdm_encode(
+        &self,
+        _context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
+        self.request_id.encode(bytes); // param1
+        0u8.encode(bytes); // param2
+        encode_encapsulated(self.request_size, &self.request, bytes);
+        spdm_encoded_size(bytes, start)
+    }
+
+    fn spdm_read(
+        _context: &mut common::SpdmContext,
+        r: &mut Reader,
+    ) -> Option<SpdmEncapsulatedRequestPayload> {
+        let request_id = u8::read(r)?; // param1
+        u8::read(r)?; // param2
+        let (request_size, request) = read_encapsulated(r)?;
+
+        Some(SpdmEncapsulatedRequestPayload {
+            request_id,
+            request_size,
+            request,
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct SpdmDeliverEncapsulatedResponsePayload {
+    pub request_id: u8,
+    pub response_size: u32,
+    pub response: [u8; config::MAX_SPDM_TRANSPORT_SIZE],
+}
+
+impl Default for SpdmDeliverEncapsulatedResponsePayload {
+    fn default() -> SpdmDeliverEncapsulatedResponsePayload {
+        SpdmDeliverEncapsulatedResponsePayload {
+            request_id: 0,
+            response_size: 0,
+            response: [0u8; config::MAX_SPDM_TRANSPORT_SIZE],
+        }
+    }
+}
+
+impl SpdmCodec for SpdmDeliverEncapsulatedResponsePayload {
+    fn spdm_encode(
+        &self,
+        _context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
+        self.request_id.encode(bytes); // param1
+        0u8.encode(bytes); // param2
+        encode_encapsulated(self.response_size, &self.response, bytes);
+        spdm_encoded_size(bytes, start)
+    }
+
+    fn spdm_read(
+        _context: &mut common::SpdmContext,
+        r: &mut Reader,
+    ) -> Option<SpdmDeliverEncapsulatedResponsePayload> {
+        let request_id = u8::read(r)?; // param1
+        u8::read(r)?; // param2
+        let (response_size, response) = read_encapsulated(r)?;
+
+        Some(SpdmDeliverEncapsulatedResponsePayload {
+            request_id,
+            response_size,
+            response,
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct SpdmEncapsulatedResponseAckPayload {
+    // identifies the next encapsulated request
+    pub request_id: u8,
+    pub payload_type: SpdmEncapsulatedResponseAckPayloadType,
+    // request ID of the delivered response, SPDM 1.2 only
+    pub ack_request_id: u8,
+    // next encapsulated request, or the slot number for ReqSlotNumber
+    pub request_size: u32,
+    pub request: [u8; config::MAX_SPDM_TRANSPORT_SIZE],
+}
+
+impl Default for SpdmEncapsulatedResponseAckPayload {
+    fn default() -> SpdmEncapsulatedResponseAckPayload {
+        SpdmEncapsulatedResponseAckPayload {
+            request_id: 0,
+            payload_type: SpdmEncapsulatedResponseAckPayloadType::default(),
+            ack_request_id: 0,
+            request_size: 0,
+            request: [0u8; config::MAX_SPDM_TRANSPORT_SIZE],
+        }
+    }
+}
+
+impl SpdmCodec for SpdmEncapsulatedResponseAckPayload {
+    fn spdm_encode(
+        &self,
+        context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
+        self.request_id.encode(bytes); // param1
+        self.payload_type.encode(bytes); // param2
+        if context.negotiate_info.spdm_version_sel.get_u8() >= SpdmVersion::SpdmVersion12.get_u8() {
+            self.ack_request_id.encode(bytes);
+            for _i in 0..3 {
+                0u8.encode(bytes); // reserved
+            }
+        }
+        encode_encapsulated(self.request_size, &self.request, bytes);
+        spdm_encoded_size(bytes, start)
+    }
+
+    fn spdm_read(
+        context: &mut common::SpdmContext,
+        r: &mut Reader,
+    ) -> Option<SpdmEncapsulatedResponseAckPayload> {
+        let request_id = u8::read(r)?; // param1
+        let payload_type = SpdmEncapsulatedResponseAckPayloadType::read(r)?; // param2
+        let mut ack_request_id = 0u8;
+        if context.negotiate_info.spdm_version_sel.get_u8() >= SpdmVersion::SpdmVersion12.get_u8() {
+            ack_request_id = u8::read(r)?;
+            for _i in 0..3 {
+                u8::read(r)?; // reserved
+            }
+        }
+        let (request_size, request) = read_encapsulated(r)?;
+
+        Some(SpdmEncapsulatedResponseAckPayload {
+            request_id,
+            payload_type,
+            ack_request_id,
+            request_size,
+            request,
+        })
+    }
+}
@@ -5,7 +5,8 @@
 #![forbid(unsafe_code)]
 
 use crate::common;
-use crate::msgs::SpdmCodec;
+use crate::error::SpdmResult;
+use crate::msgs::{spdm_encoded_size, SpdmCodec};
 use codec::{Codec, Reader, Writer};
 
 bitflags! {
@@ -28,14 +29,21 @@ impl Codec for SpdmEndSessionRequestAttributes {
 }
 
 #[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmEndSessionRequestPayload {
     pub end_session_request_attributes: SpdmEndSessionRequestAttributes,
 }
 
 impl SpdmCodec for SpdmEndSessionRequestPayload {
-    fn spdm_encode(&self, _context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        _context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         self.end_session_request_attributes.encode(bytes); // param1
         0u8.encode(bytes); // param2
+        spdm_encoded_size(bytes, start)
     }
 
     fn spdm_read(
@@ -52,12 +60,19 @@ impl SpdmCodec for SpdmEndSessionRequestPayload {
 }
 
 #[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmEndSessionResponsePayload {}
 
 impl SpdmCodec for SpdmEndSessionResponsePayload {
-    fn spdm_encode(&self, _context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        _context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         0u8.encode(bytes); // param1
         0u8.encode(bytes); // param2
+        spdm_encoded_size(bytes, start)
     }
 
     fn spdm_read(
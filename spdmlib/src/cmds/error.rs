@@ -5,7 +5,8 @@
 #![forbid(unsafe_code)]
 
 use crate::common;
-use crate::msgs::SpdmCodec;
+use crate::error::SpdmResult;
+use crate::msgs::{spdm_encoded_size, SpdmCodec};
 use codec::enum_builder;
 use codec::{Codec, Reader, Writer};
 
@@ -23,6 +24,9 @@ enum_builder! {
         SpdmErrorRequestInFlight => 0x8,
         SpdmErrorInvalidResponseCode => 0x9,
         SpdmErrorSessionLimitExceeded => 0xA,
+        SpdmErrorSessionRequired => 0xB,
+        SpdmErrorRequestTooLarge => 0xE,
+        SpdmErrorLargeResponse => 0xF,
         SpdmErrorMajorVersionMismatch => 0x41,
         SpdmErrorResponseNotReady => 0x42,
         SpdmErrorRequestResynch => 0x43,
@@ -31,10 +35,17 @@ enum_builder! {
 }
 
 #[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmErrorResponseNoneExtData {}
 
 impl SpdmCodec for SpdmErrorResponseNoneExtData {
-    fn spdm_encode(&self, _context: &mut common::SpdmContext, _bytes: &mut Writer) {}
+    fn spdm_encode(
+        &self,
+        _context: &mut common::SpdmContext,
+        _bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        Ok(0)
+    }
 
     fn spdm_read(
         _context: &mut common::SpdmContext,
@@ -45,6 +56,7 @@ impl SpdmCodec for SpdmErrorResponseNoneExtData {
 }
 
 #[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmErrorResponseNotReadyExtData {
     pub rdt_exponent: u8,
     pub request_code: u8,
@@ -53,11 +65,17 @@ pub struct SpdmErrorResponseNotReadyExtData {
 }
 
 impl SpdmCodec for SpdmErrorResponseNotReadyExtData {
-    fn spdm_encode(&self, _context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        _context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         self.rdt_exponent.encode(bytes);
         self.request_code.encode(bytes);
         self.token.encode(bytes);
         self.tdtm.encode(bytes);
+        spdm_encoded_size(bytes, start)
     }
 
     fn spdm_read(
@@ -79,16 +97,50 @@ impl SpdmCodec for SpdmErrorResponseNotReadyExtData {
 }
 
 #[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
+pub struct SpdmErrorResponseLargeResponseExtData {
+    pub handle: u8,
+}
+
+impl SpdmCodec for SpdmErrorResponseLargeResponseExtData {
+    fn spdm_encode(
+        &self,
+        _context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
+        self.handle.encode(bytes);
+        spdm_encoded_size(bytes, start)
+    }
+
+    fn spdm_read(
+        _context: &mut common::SpdmContext,
+        r: &mut Reader,
+    ) -> Option<SpdmErrorResponseLargeResponseExtData> {
+        let handle = u8::read(r)?;
+
+        Some(SpdmErrorResponseLargeResponseExtData { handle })
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmErrorResponseVendorExtData {
     pub data_size: u8,
     pub data: [u8; 32],
 }
 
 impl SpdmCodec for SpdmErrorResponseVendorExtData {
-    fn spdm_encode(&self, _context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        _context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         for d in self.data.iter().take(self.data_size as usize) {
             d.encode(bytes);
         }
+        spdm_encoded_size(bytes, start)
     }
 
     fn spdm_read(
@@ -116,9 +168,11 @@ impl SpdmCodec for SpdmErrorResponseVendorExtData {
 }
 
 #[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub enum SpdmErrorResponseExtData {
     SpdmErrorExtDataNone(SpdmErrorResponseNoneExtData),
     SpdmErrorExtDataNotReady(SpdmErrorResponseNotReadyExtData),
+    SpdmErrorExtDataLargeResponse(SpdmErrorResponseLargeResponseExtData),
     SpdmErrorExtDataVendorDefined(SpdmErrorResponseVendorExtData),
 }
 impl Default for SpdmErrorResponseExtData {
@@ -128,6 +182,7 @@ impl Default for SpdmErrorResponseExtData {
 }
 
 #[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmErrorResponsePayload {
     pub error_code: SpdmErrorCode,
     pub error_data: u8,
@@ -135,21 +190,30 @@ pub struct SpdmErrorResponsePayload {
 }
 
 impl SpdmCodec for SpdmErrorResponsePayload {
-    fn spdm_encode(&self, context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         self.error_code.encode(bytes); // param1
         self.error_data.encode(bytes); // param2
 
         match &self.extended_data {
             SpdmErrorResponseExtData::SpdmErrorExtDataNotReady(extended_data) => {
-                extended_data.spdm_encode(context, bytes);
+                extended_data.spdm_encode(context, bytes)?;
+            }
+            SpdmErrorResponseExtData::SpdmErrorExtDataLargeResponse(extended_data) => {
+                extended_data.spdm_encode(context, bytes)?;
             }
             SpdmErrorResponseExtData::SpdmErrorExtDataVendorDefined(extended_data) => {
-                extended_data.spdm_encode(context, bytes);
+                extended_data.spdm_encode(context, bytes)?;
             }
             SpdmErrorResponseExtData::SpdmErrorExtDataNone(extended_data) => {
-                extended_data.spdm_encode(context, bytes);
+                extended_data.spdm_encode(context, bytes)?;
             }
         }
+        spdm_encoded_size(bytes, start)
     }
 
     fn spdm_read(
@@ -165,6 +229,11 @@ impl SpdmCodec for SpdmErrorResponsePayload {
                     SpdmErrorResponseNotReadyExtData::spdm_read(context, r)?,
                 ))
             }
+            SpdmErrorCode::SpdmErrorLargeResponse => {
+                Some(SpdmErrorResponseExtData::SpdmErrorExtDataLargeResponse(
+                    SpdmErrorResponseLargeResponseExtData::spdm_read(context, r)?,
+                ))
+            }
             SpdmErrorCode::SpdmErrorVendorDefined => {
                 Some(SpdmErrorResponseExtData::SpdmErrorExtDataVendorDefined(
                     SpdmErrorResponseVendorExtData::spdm_read(context, r)?,
@@ -5,7 +5,8 @@
 #![forbid(unsafe_code)]
 
 use crate::common;
-use crate::msgs::SpdmCodec;
+use crate::error::SpdmResult;
+use crate::msgs::{spdm_encoded_size, SpdmCodec};
 use crate::msgs::{
     SpdmDigestStruct, SpdmRequestCapabilityFlags, SpdmResponseCapabilityFlags, SpdmSignatureStruct,
 };
@@ -31,6 +32,7 @@ impl Codec for SpdmFinishRequestAttributes {
 }
 
 #[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmFinishRequestPayload {
     pub finish_request_attributes: SpdmFinishRequestAttributes,
     pub req_slot_id: u8,
@@ -39,16 +41,22 @@ pub struct SpdmFinishRequestPayload {
 }
 
 impl SpdmCodec for SpdmFinishRequestPayload {
-    fn spdm_encode(&self, context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         self.finish_request_attributes.encode(bytes); // param1
         self.req_slot_id.encode(bytes); // param2
         if self
             .finish_request_attributes
             .contains(SpdmFinishRequestAttributes::SIGNATURE_INCLUDED)
         {
-            self.signature.spdm_encode(context, bytes);
+            self.signature.spdm_encode(context, bytes)?;
         }
-        self.verify_data.spdm_encode(context, bytes);
+        self.verify_data.spdm_encode(context, bytes)?;
+        spdm_encoded_size(bytes, start)
     }
 
     fn spdm_read(
@@ -59,7 +67,14 @@ impl SpdmCodec for SpdmFinishRequestPayload {
         let req_slot_id = u8::read(r)?; // param2
         let mut signature = SpdmSignatureStruct::default();
         if finish_request_attributes.contains(SpdmFinishRequestAttributes::SIGNATURE_INCLUDED) {
-            signature = SpdmSignatureStruct::spdm_read(context, r)?;
+            // signed with the requester key
+            if context.negotiate_info.req_asym_sel.is_empty() {
+                return None;
+            }
+            signature.data_size = context.get_req_asym_key_size();
+            for d in signature.data.iter_mut().take(signature.data_size as usize) {
+                *d = u8::read(r)?;
+            }
         }
         let verify_data = SpdmDigestStruct::spdm_read(context, r)?;
 
@@ -73,12 +88,18 @@ impl SpdmCodec for SpdmFinishRequestPayload {
 }
 
 #[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmFinishResponsePayload {
     pub verify_data: SpdmDigestStruct,
 }
 
 impl SpdmCodec for SpdmFinishResponsePayload {
-    fn spdm_encode(&self, context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         0u8.encode(bytes); // param1
         0u8.encode(bytes); // param2
         let in_clear_text = context
@@ -90,8 +111,9 @@ impl SpdmCodec for SpdmFinishResponsePayload {
                 .rsp_capabilities_sel
                 .contains(SpdmResponseCapabilityFlags::HANDSHAKE_IN_THE_CLEAR_CAP);
         if in_clear_text {
-            self.verify_data.spdm_encode(context, bytes);
+            self.verify_data.spdm_encode(context, bytes)?;
         }
+        spdm_encoded_size(bytes, start)
     }
 
     fn spdm_read(
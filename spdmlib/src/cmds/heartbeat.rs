@@ -5,16 +5,24 @@
 #![forbid(unsafe_code)]
 
 use crate::common;
-use crate::msgs::SpdmCodec;
+use crate::error::SpdmResult;
+use crate::msgs::{spdm_encoded_size, SpdmCodec};
 use codec::{Codec, Reader, Writer};
 
 #[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmHeartbeatRequestPayload {}
 
 impl SpdmCodec for SpdmHeartbeatRequestPayload {
-    fn spdm_encode(&self, _context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        _context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         0u8.encode(bytes); // param1
         0u8.encode(bytes); // param2
+        spdm_encoded_size(bytes, start)
     }
 
     fn spdm_read(
@@ -29,12 +37,19 @@ impl SpdmCodec for SpdmHeartbeatRequestPayload {
 }
 
 #[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmHeartbeatResponsePayload {}
 
 impl SpdmCodec for SpdmHeartbeatResponsePayload {
-    fn spdm_encode(&self, _context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        _context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         0u8.encode(bytes); // param1
         0u8.encode(bytes); // param2
+        spdm_encoded_size(bytes, start)
     }
 
     fn spdm_read(
@@ -5,33 +5,47 @@
 #![forbid(unsafe_code)]
 
 use crate::common;
-use crate::msgs::SpdmCodec;
+use crate::error::SpdmResult;
+use crate::msgs::{spdm_encoded_size, SpdmCodec};
 use crate::msgs::{
     SpdmDheExchangeStruct, SpdmDigestStruct, SpdmMeasurementSummaryHashType, SpdmOpaqueStruct,
-    SpdmRandomStruct, SpdmSignatureStruct,
+    SpdmRandomStruct, SpdmSignatureStruct, SpdmVersion,
 };
 use codec::{Codec, Reader, Writer};
 
 #[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmKeyExchangeRequestPayload {
     pub measurement_summary_hash_type: SpdmMeasurementSummaryHashType,
     pub slot_id: u8,
     pub req_session_id: u16,
+    pub session_policy: SpdmKeyExchangeSessionPolicy,
     pub random: SpdmRandomStruct,
     pub exchange: SpdmDheExchangeStruct,
     pub opaque: SpdmOpaqueStruct,
 }
 
 impl SpdmCodec for SpdmKeyExchangeRequestPayload {
-    fn spdm_encode(&self, context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         self.measurement_summary_hash_type.encode(bytes); // param1
         self.slot_id.encode(bytes); // param2
         self.req_session_id.encode(bytes);
-        0u16.encode(bytes); // reserved
+        if context.negotiate_info.spdm_version_sel.get_u8() >= SpdmVersion::SpdmVersion12.get_u8() {
+            self.session_policy.encode(bytes);
+        } else {
+            0u8.encode(bytes); // reserved
+        }
+        0u8.encode(bytes); // reserved
 
         self.random.encode(bytes);
-        self.exchange.spdm_encode(context, bytes);
-        self.opaque.spdm_encode(context, bytes);
+        self.exchange.spdm_encode(context, bytes)?;
+        self.opaque.spdm_encode(context, bytes)?;
+        spdm_encoded_size(bytes, start)
     }
 
     fn spdm_read(
@@ -41,7 +55,15 @@ impl SpdmCodec for SpdmKeyExchangeRequestPayload {
         let measurement_summary_hash_type = SpdmMeasurementSummaryHashType::read(r)?; // param1
         let slot_id = u8::read(r)?; // param2
         let req_session_id = u16::read(r)?;
-        u16::read(r)?;
+        let session_policy = if context.negotiate_info.spdm_version_sel.get_u8()
+            >= SpdmVersion::SpdmVersion12.get_u8()
+        {
+            SpdmKeyExchangeSessionPolicy::read(r)?
+        } else {
+            u8::read(r)?; // reserved
+            SpdmKeyExchangeSessionPolicy::empty()
+        };
+        u8::read(r)?; // reserved
 
         let random = SpdmRandomStruct::read(r)?;
         let exchange = SpdmDheExchangeStruct::spdm_read(context, r)?;
@@ -51,6 +73,7 @@ impl SpdmCodec for SpdmKeyExchangeRequestPayload {
             measurement_summary_hash_type,
             slot_id,
             req_session_id,
+            session_policy,
             random,
             exchange,
             opaque,
@@ -58,6 +81,26 @@ impl SpdmCodec for SpdmKeyExchangeRequestPayload {
     }
 }
 
+bitflags! {
+    #[derive(Default)]
+    pub struct SpdmKeyExchangeSessionPolicy: u8 {
+        // terminate the session when the measurements change at runtime
+        const TERMINATION_POLICY_RUNTIME_UPDATE = 0b0000_0001;
+    }
+}
+
+impl Codec for SpdmKeyExchangeSessionPolicy {
+    fn encode(&self, bytes: &mut Writer) {
+        self.bits().encode(bytes);
+    }
+
+    fn read(r: &mut Reader) -> Option<SpdmKeyExchangeSessionPolicy> {
+        let bits = u8::read(r)?;
+
+        SpdmKeyExchangeSessionPolicy::from_bits(bits)
+    }
+}
+
 bitflags! {
     #[derive(Default)]
     pub struct SpdmKeyExchangeMutAuthAttributes: u8 {
@@ -80,6 +123,7 @@ impl Codec for SpdmKeyExchangeMutAuthAttributes {
 }
 
 #[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmKeyExchangeResponsePayload {
     pub heartbeat_period: u8,
     pub rsp_session_id: u16,
@@ -94,7 +138,12 @@ pub struct SpdmKeyExchangeResponsePayload {
 }
 
 impl SpdmCodec for SpdmKeyExchangeResponsePayload {
-    fn spdm_encode(&self, context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         self.heartbeat_period.encode(bytes); // param1
         0u8.encode(bytes); // param2
         self.rsp_session_id.encode(bytes);
@@ -102,13 +151,14 @@ impl SpdmCodec for SpdmKeyExchangeResponsePayload {
         self.req_slot_id.encode(bytes);
 
         self.random.encode(bytes);
-        self.exchange.spdm_encode(context, bytes);
+        self.exchange.spdm_encode(context, bytes)?;
         if context.runtime_info.need_measurement_summary_hash {
-            self.measurement_summary_hash.spdm_encode(context, bytes);
+            self.measurement_summary_hash.spdm_encode(context, bytes)?;
         }
-        self.opaque.spdm_encode(context, bytes);
-        self.signature.spdm_encode(context, bytes);
-        self.verify_data.spdm_encode(context, bytes);
+        self.opaque.spdm_encode(context, bytes)?;
+        self.signature.spdm_encode(context, bytes)?;
+        self.verify_data.spdm_encode(context, bytes)?;
+        spdm_encoded_size(bytes, start)
     }
 
     fn spdm_read(
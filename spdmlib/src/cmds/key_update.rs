@@ -5,7 +5,8 @@
 #![forbid(unsafe_code)]
 
 use crate::common;
-use crate::msgs::SpdmCodec;
+use crate::error::SpdmResult;
+use crate::msgs::{spdm_encoded_size, SpdmCodec};
 use codec::enum_builder;
 use codec::{Codec, Reader, Writer};
 
@@ -20,15 +21,22 @@ enum_builder! {
 }
 
 #[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmKeyUpdateRequestPayload {
     pub key_update_operation: SpdmKeyUpdateOperation,
     pub tag: u8,
 }
 
 impl SpdmCodec for SpdmKeyUpdateRequestPayload {
-    fn spdm_encode(&self, _context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        _context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         self.key_update_operation.encode(bytes); // param1
         self.tag.encode(bytes); // param2
+        spdm_encoded_size(bytes, start)
     }
 
     fn spdm_read(
@@ -46,15 +54,22 @@ impl SpdmCodec for SpdmKeyUpdateRequestPayload {
 }
 
 #[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmKeyUpdateResponsePayload {
     pub key_update_operation: SpdmKeyUpdateOperation,
     pub tag: u8,
 }
 
 impl SpdmCodec for SpdmKeyUpdateResponsePayload {
-    fn spdm_encode(&self, _context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        _context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         self.key_update_operation.encode(bytes); // param1
         self.tag.encode(bytes); // param2
+        spdm_encoded_size(bytes, start)
     }
 
     fn spdm_read(
@@ -5,7 +5,8 @@
 #![forbid(unsafe_code)]
 
 use crate::common;
-use crate::msgs::SpdmCodec;
+use crate::error::SpdmResult;
+use crate::msgs::{spdm_encoded_size, SpdmCodec};
 use crate::msgs::{
     SpdmMeasurementRecordStructure, SpdmNonceStruct, SpdmOpaqueStruct, SpdmSignatureStruct,
 };
@@ -41,6 +42,7 @@ enum_builder! {
 }
 
 #[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmGetMeasurementsRequestPayload {
     pub measurement_attributes: SpdmMeasurementeAttributes,
     pub measurement_operation: SpdmMeasurementOperation,
@@ -49,7 +51,12 @@ pub struct SpdmGetMeasurementsRequestPayload {
 }
 
 impl SpdmCodec for SpdmGetMeasurementsRequestPayload {
-    fn spdm_encode(&self, _context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        _context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         self.measurement_attributes.encode(bytes); // param1
         self.measurement_operation.encode(bytes); // param2
         if self
@@ -59,6 +66,7 @@ impl SpdmCodec for SpdmGetMeasurementsRequestPayload {
             self.nonce.encode(bytes);
             self.slot_id.encode(bytes);
         }
+        spdm_encoded_size(bytes, start)
     }
 
     fn spdm_read(
@@ -90,6 +98,7 @@ impl SpdmCodec for SpdmGetMeasurementsRequestPayload {
 }
 
 #[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmMeasurementsResponsePayload {
     pub number_of_measurement: u8,
     pub slot_id: u8,
@@ -100,17 +109,23 @@ pub struct SpdmMeasurementsResponsePayload {
 }
 
 impl SpdmCodec for SpdmMeasurementsResponsePayload {
-    fn spdm_encode(&self, context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         self.number_of_measurement.encode(bytes); // param1
         self.slot_id.encode(bytes); // param2
-        self.measurement_record.spdm_encode(context, bytes);
+        self.measurement_record.spdm_encode(context, bytes)?;
         if context.runtime_info.need_measurement_signature {
             self.nonce.encode(bytes);
         }
-        self.opaque.spdm_encode(context, bytes);
+        self.opaque.spdm_encode(context, bytes)?;
         if context.runtime_info.need_measurement_signature {
-            self.signature.spdm_encode(context, bytes);
+            self.signature.spdm_encode(context, bytes)?;
         }
+        spdm_encoded_size(bytes, start)
     }
 
     fn spdm_read(
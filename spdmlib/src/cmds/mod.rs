@@ -11,11 +11,14 @@ pub mod certificate;
 pub mod challenge;
 pub mod digest;
 pub mod measurement;
+pub mod respond_if_ready;
+pub mod vendor;
 pub mod version;
 
 pub mod error;
 
 // SPDM 1.1
+pub mod encapsulated;
 pub mod end_session;
 pub mod finish;
 pub mod heartbeat;
@@ -23,3 +26,6 @@ pub mod key_exchange;
 pub mod key_update;
 pub mod psk_exchange;
 pub mod psk_finish;
+
+// SPDM 1.2
+pub mod chunk;
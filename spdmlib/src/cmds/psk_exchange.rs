@@ -5,7 +5,8 @@
 #![forbid(unsafe_code)]
 
 use crate::common;
-use crate::msgs::SpdmCodec;
+use crate::error::SpdmResult;
+use crate::msgs::{spdm_encoded_size, SpdmCodec};
 use crate::msgs::{
     SpdmDigestStruct, SpdmMeasurementSummaryHashType, SpdmOpaqueStruct, SpdmPskContextStruct,
     SpdmPskHintStruct,
@@ -13,6 +14,7 @@ use crate::msgs::{
 use codec::{Codec, Reader, Writer};
 
 #[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmPskExchangeRequestPayload {
     pub measurement_summary_hash_type: SpdmMeasurementSummaryHashType,
     pub req_session_id: u16,
@@ -22,7 +24,12 @@ pub struct SpdmPskExchangeRequestPayload {
 }
 
 impl SpdmCodec for SpdmPskExchangeRequestPayload {
-    fn spdm_encode(&self, _context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        _context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         self.measurement_summary_hash_type.encode(bytes); // param1
         0u8.encode(bytes); // param2
         self.req_session_id.encode(bytes);
@@ -50,6 +57,7 @@ impl SpdmCodec for SpdmPskExchangeRequestPayload {
         for d in self.opaque.data.iter().take(self.opaque.data_size as usize) {
             d.encode(bytes);
         }
+        spdm_encoded_size(bytes, start)
     }
 
     fn spdm_read(
@@ -93,6 +101,7 @@ impl SpdmCodec for SpdmPskExchangeRequestPayload {
 }
 
 #[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmPskExchangeResponsePayload {
     pub heartbeat_period: u8,
     pub rsp_session_id: u16,
@@ -103,7 +112,12 @@ pub struct SpdmPskExchangeResponsePayload {
 }
 
 impl SpdmCodec for SpdmPskExchangeResponsePayload {
-    fn spdm_encode(&self, context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         self.heartbeat_period.encode(bytes); // param1
         0u8.encode(bytes); // param2
         self.rsp_session_id.encode(bytes);
@@ -113,7 +127,7 @@ impl SpdmCodec for SpdmPskExchangeResponsePayload {
         self.opaque.data_size.encode(bytes);
 
         if context.runtime_info.need_measurement_summary_hash {
-            self.measurement_summary_hash.spdm_encode(context, bytes);
+            self.measurement_summary_hash.spdm_encode(context, bytes)?;
         }
         for d in self
             .psk_context
@@ -126,7 +140,8 @@ impl SpdmCodec for SpdmPskExchangeResponsePayload {
         for d in self.opaque.data.iter().take(self.opaque.data_size as usize) {
             d.encode(bytes);
         }
-        self.verify_data.spdm_encode(context, bytes);
+        self.verify_data.spdm_encode(context, bytes)?;
+        spdm_encoded_size(bytes, start)
     }
 
     fn spdm_read(
@@ -5,20 +5,28 @@
 #![forbid(unsafe_code)]
 
 use crate::common;
-use crate::msgs::SpdmCodec;
+use crate::error::SpdmResult;
 use crate::msgs::SpdmDigestStruct;
+use crate::msgs::{spdm_encoded_size, SpdmCodec};
 use codec::{Codec, Reader, Writer};
 
 #[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmPskFinishRequestPayload {
     pub verify_data: SpdmDigestStruct,
 }
 
 impl SpdmCodec for SpdmPskFinishRequestPayload {
-    fn spdm_encode(&self, context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         0u8.encode(bytes); // param1
         0u8.encode(bytes); // param2
-        self.verify_data.spdm_encode(context, bytes);
+        self.verify_data.spdm_encode(context, bytes)?;
+        spdm_encoded_size(bytes, start)
     }
 
     fn spdm_read(
@@ -34,12 +42,19 @@ impl SpdmCodec for SpdmPskFinishRequestPayload {
 }
 
 #[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmPskFinishResponsePayload {}
 
 impl SpdmCodec for SpdmPskFinishResponsePayload {
-    fn spdm_encode(&self, _context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        _context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         0u8.encode(bytes); // param1
         0u8.encode(bytes); // param2
+        spdm_encoded_size(bytes, start)
     }
 
     fn spdm_read(
@@ -0,0 +1,44 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+#![forbid(unsafe_code)]
+
+use crate::common;
+use crate::error::SpdmResult;
+use crate::msgs::{spdm_encoded_size, SpdmCodec};
+use codec::{Codec, Reader, Writer};
+
+#[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
+pub struct SpdmRespondIfReadyRequestPayload {
+    // request code and token from ERROR(ResponseNotReady)
+    pub request_code: u8,
+    pub token: u8,
+}
+
+impl SpdmCodec for SpdmRespondIfReadyRequestPayload {
+    fn spdm_encode(
+        &self,
+        _context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
+        self.request_code.encode(bytes); // param1
+        self.token.encode(bytes); // param2
+        spdm_encoded_size(bytes, start)
+    }
+
+    fn spdm_read(
+        _context: &mut common::SpdmContext,
+        r: &mut Reader,
+    ) -> Option<SpdmRespondIfReadyRequestPayload> {
+        let request_code = u8::read(r)?; // param1
+        let token = u8::read(r)?; // param2
+
+        Some(SpdmRespondIfReadyRequestPayload {
+            request_code,
+            token,
+        })
+    }
+}
@@ -0,0 +1,187 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+#![forbid(unsafe_code)]
+
+use crate::common;
+use crate::config;
+use crate::error::SpdmResult;
+use crate::msgs::{spdm_encoded_size, SpdmCodec, SpdmStandardId};
+use codec::{Codec, Reader, Writer};
+
+// StandardID is 2 bytes here, the registry is the one of SpdmExtAlgStruct.
+fn encode_standard_id(standard_id: SpdmStandardId, bytes: &mut Writer) {
+    (standard_id.get_u8() as u16).encode(bytes);
+}
+
+fn read_standard_id(r: &mut Reader) -> Option<SpdmStandardId> {
+    let standard_id = u16::read(r)?;
+    if standard_id > u8::MAX as u16 {
+        return None;
+    }
+    SpdmStandardId::read(&mut Reader::init(&[standard_id as u8]))
+}
+
+// The registered vendor IDs are at most 4 bytes.
+pub const SPDM_MAX_VENDOR_ID_SIZE: usize = 16;
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct SpdmVendorIdStruct {
+    pub data_size: u16,
+    pub data: [u8; SPDM_MAX_VENDOR_ID_SIZE],
+}
+
+impl SpdmCodec for SpdmVendorIdStruct {
+    fn spdm_encode(
+        &self,
+        _context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
+        (self.data_size as u8).encode(bytes); // Len
+        for d in self.data.iter().take(self.data_size as usize) {
+            d.encode(bytes);
+        }
+        spdm_encoded_size(bytes, start)
+    }
+
+    fn spdm_read(_context: &mut common::SpdmContext, r: &mut Reader) -> Option<SpdmVendorIdStruct> {
+        let data_size = u8::read(r)? as u16; // Len
+        if data_size as usize > SPDM_MAX_VENDOR_ID_SIZE {
+            return None;
+        }
+        let mut data = [0u8; SPDM_MAX_VENDOR_ID_SIZE];
+        for d in data.iter_mut().take(data_size as usize) {
+            *d = u8::read(r)?;
+        }
+        Some(SpdmVendorIdStruct { data_size, data })
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct SpdmVendorDefinedPayloadStruct {
+    pub data_size: u16,
+    pub data: [u8; config::MAX_SPDM_TRANSPORT_SIZE],
+}
+
+impl Default for SpdmVendorDefinedPayloadStruct {
+    fn default() -> SpdmVendorDefinedPayloadStruct {
+        SpdmVendorDefinedPayloadStruct {
+            data_size: 0,
+            data: [0u8; config::MAX_SPDM_TRANSPORT_SIZE],
+        }
+    }
+}
+
+impl SpdmCodec for SpdmVendorDefinedPayloadStruct {
+    fn spdm_encode(
+        &self,
+        _context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
+        self.data_size.encode(bytes); // ReqLength / RespLength
+        for d in self.data.iter().take(self.data_size as usize) {
+            d.encode(bytes);
+        }
+        spdm_encoded_size(bytes, start)
+    }
+
+    fn spdm_read(
+        _context: &mut common::SpdmContext,
+        r: &mut Reader,
+    ) -> Option<SpdmVendorDefinedPayloadStruct> {
+        let data_size = u16::read(r)?; // ReqLength / RespLength
+        if data_size as usize > config::MAX_SPDM_TRANSPORT_SIZE {
+            return None;
+        }
+        let mut data = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        for d in data.iter_mut().take(data_size as usize) {
+            *d = u8::read(r)?;
+        }
+        Some(SpdmVendorDefinedPayloadStruct { data_size, data })
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
+pub struct SpdmVendorDefinedRequestPayload {
+    pub standard_id: SpdmStandardId,
+    pub vendor_id: SpdmVendorIdStruct,
+    pub req_payload: SpdmVendorDefinedPayloadStruct,
+}
+
+impl SpdmCodec for SpdmVendorDefinedRequestPayload {
+    fn spdm_encode(
+        &self,
+        context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
+        0u8.encode(bytes); // param1
+        0u8.encode(bytes); // param2
+        encode_standard_id(self.standard_id, bytes);
+        self.vendor_id.spdm_encode(context, bytes)?;
+        self.req_payload.spdm_encode(context, bytes)?;
+        spdm_encoded_size(bytes, start)
+    }
+
+    fn spdm_read(
+        context: &mut common::SpdmContext,
+        r: &mut Reader,
+    ) -> Option<SpdmVendorDefinedRequestPayload> {
+        u8::read(r)?; // param1
+        u8::read(r)?; // param2
+        let standard_id = read_standard_id(r)?;
+        let vendor_id = SpdmVendorIdStruct::spdm_read(context, r)?;
+        let req_payload = SpdmVendorDefinedPayloadStruct::spdm_read(context, r)?;
+
+        Some(SpdmVendorDefinedRequestPayload {
+            standard_id,
+            vendor_id,
+            req_payload,
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
+pub struct SpdmVendorDefinedResponsePayload {
+    pub standard_id: SpdmStandardId,
+    pub vendor_id: SpdmVendorIdStruct,
+    pub rsp_payload: SpdmVendorDefinedPayloadStruct,
+}
+
+impl SpdmCodec for SpdmVendorDefinedResponsePayload {
+    fn spdm_encode(
+        &self,
+        context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
+        0u8.encode(bytes); // param1
+        0u8.encode(bytes); // param2
+        encode_standard_id(self.standard_id, bytes);
+        self.vendor_id.spdm_encode(context, bytes)?;
+        self.rsp_payload.spdm_encode(context, bytes)?;
+        spdm_encoded_size(bytes, start)
+    }
+
+    fn spdm_read(
+        context: &mut common::SpdmContext,
+        r: &mut Reader,
+    ) -> Option<SpdmVendorDefinedResponsePayload> {
+        u8::read(r)?; // param1
+        u8::read(r)?; // param2
+        let standard_id = read_standard_id(r)?;
+        let vendor_id = SpdmVendorIdStruct::spdm_read(context, r)?;
+        let rsp_payload = SpdmVendorDefinedPayloadStruct::spdm_read(context, r)?;
+
+        Some(SpdmVendorDefinedResponsePayload {
+            standard_id,
+            vendor_id,
+            rsp_payload,
+        })
+    }
+}
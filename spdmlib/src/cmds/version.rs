@@ -6,17 +6,25 @@
 
 use crate::common;
 use crate::config;
-use crate::msgs::SpdmCodec;
+use crate::error::SpdmResult;
 use crate::msgs::SpdmVersion;
+use crate::msgs::{spdm_encoded_size, SpdmCodec};
 use codec::{Codec, Reader, Writer};
 
 #[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmGetVersionRequestPayload {}
 
 impl SpdmCodec for SpdmGetVersionRequestPayload {
-    fn spdm_encode(&self, _context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        _context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         0u8.encode(bytes); // param1
         0u8.encode(bytes); // param2
+        spdm_encoded_size(bytes, start)
     }
 
     fn spdm_read(
@@ -31,6 +39,7 @@ impl SpdmCodec for SpdmGetVersionRequestPayload {
 }
 
 #[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmVersionStruct {
     pub update: u8,
     pub version: SpdmVersion,
@@ -49,13 +58,19 @@ impl Codec for SpdmVersionStruct {
 }
 
 #[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmVersionResponsePayload {
     pub version_number_entry_count: u8,
     pub versions: [SpdmVersionStruct; config::MAX_SPDM_VERSION_COUNT],
 }
 
 impl SpdmCodec for SpdmVersionResponsePayload {
-    fn spdm_encode(&self, _context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        _context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         0u8.encode(bytes); // param1
         0u8.encode(bytes); // param2
 
@@ -69,6 +84,7 @@ impl SpdmCodec for SpdmVersionResponsePayload {
         {
             version.encode(bytes);
         }
+        spdm_encoded_size(bytes, start)
     }
 
     fn spdm_read(
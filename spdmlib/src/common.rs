@@ -4,11 +4,17 @@
 #![forbid(unsafe_code)]
 
 use crate::config;
-use crate::crypto;
+use crate::crypto::{SpdmCertChainConfig, SpdmCryptoProvider, SpdmHashCtx};
 use crate::error::SpdmResult;
 use crate::msgs::*;
 use crate::session::*;
-use codec::Writer;
+use crate::trace::{SpdmMessageDirection, SpdmTraceRecorder};
+use codec::{Codec, Reader, Writer};
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
 
 pub const OPAQUE_DATA_SUPPORT_VERSION: [u8; 20] = [
     0x46, 0x54, 0x4d, 0x44, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x05, 0x00, 0x01, 0x01, 0x01, 0x00,
@@ -17,12 +23,49 @@ pub const OPAQUE_DATA_SUPPORT_VERSION: [u8; 20] = [
 pub const OPAQUE_DATA_VERSION_SELECTION: [u8; 16] = [
     0x46, 0x54, 0x4d, 0x44, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x01, 0x00, 0x00, 0x11,
 ];
+// SPDM 1.2 general opaque data format (OpaqueDataFmt1)
+pub const OPAQUE_DATA_SUPPORT_VERSION_FMT1: [u8; 16] = [
+    0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x00, 0x01, 0x01, 0x01, 0x00, 0x11, 0x00, 0x00, 0x00,
+];
+pub const OPAQUE_DATA_VERSION_SELECTION_FMT1: [u8; 12] = [
+    0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x01, 0x00, 0x00, 0x11,
+];
+// Opaque data element of MEASUREMENTS carrying the measurement generation,
+// a DMTF element (like the secured message version ones) with a data ID
+// outside of the ones the spec defines, followed by the generation (u32 LE).
+pub const OPAQUE_DATA_MEASUREMENT_GENERATION_ID: u8 = 0x80;
+const OPAQUE_DATA_SPEC_ID: [u8; 4] = [0x46, 0x54, 0x4d, 0x44];
+
+// SPDM 1.2 signing context, see "Signature generation" in the SPDM 1.2 spec.
+pub const SPDM_VERSION_1_2_SIGNING_PREFIX_CONTEXT: &[u8; 16] = b"dmtf-spdm-v1.2.*";
+pub const SPDM_VERSION_1_2_SIGNING_PREFIX_CONTEXT_COUNT: usize = 4;
+pub const SPDM_VERSION_1_2_SIGNING_CONTEXT_SIZE: usize = 100;
+pub const SPDM_CHALLENGE_AUTH_SIGN_CONTEXT: &[u8] = b"responder-challenge_auth signing";
+pub const SPDM_MEASUREMENTS_SIGN_CONTEXT: &[u8] = b"responder-measurements signing";
+pub const SPDM_KEY_EXCHANGE_RSP_SIGN_CONTEXT: &[u8] = b"responder-key_exchange_rsp signing";
+pub const SPDM_FINISH_SIGN_CONTEXT: &[u8] = b"requester-finish signing";
+
+// ST1, the time a responder has for a response without cryptographic
+// processing, see "Timing requirements" in the SPDM spec.
+pub const SPDM_ST1_US: usize = 100_000;
 
 pub trait SpdmDeviceIo {
     fn send(&mut self, buffer: &[u8]) -> SpdmResult;
 
     fn receive(&mut self, buffer: &mut [u8]) -> Result<usize, usize>;
 
+    /// Like receive(), but returns Err(0) if nothing is received within
+    /// `timeout_us`. A timeout of 0 polls without waiting.
+    ///
+    /// Devices that can only block don't need to implement it.
+    fn receive_with_timeout(
+        &mut self,
+        buffer: &mut [u8],
+        _timeout_us: usize,
+    ) -> Result<usize, usize> {
+        self.receive(buffer)
+    }
+
     fn flush_all(&mut self) -> SpdmResult;
 }
 
@@ -51,9 +94,75 @@ pub trait SpdmTransportEncap {
 
     fn decap_app(&mut self, app_buffer: &[u8], spdm_buffer: &mut [u8]) -> SpdmResult<usize>;
 
+    // for application messages other than SPDM in a session
+
+    /// Encode the application message `app_message`, with whatever header
+    /// tells it apart from SPDM, as the application data of a secured
+    /// message. Fails with ENOSYS if the transport only carries SPDM in
+    /// sessions.
+    fn encap_app_message(
+        &mut self,
+        _app_message: &[u8],
+        _app_buffer: &mut [u8],
+    ) -> SpdmResult<usize> {
+        spdm_result_err!(ENOSYS)
+    }
+
+    /// Decode the application message of `app_buffer` into `app_message`,
+    /// `None` if `app_buffer` holds an SPDM message.
+    fn decap_app_message(
+        &mut self,
+        _app_buffer: &[u8],
+        _app_message: &mut [u8],
+    ) -> SpdmResult<Option<usize>> {
+        Ok(None)
+    }
+
     // for session
     fn get_sequence_number_count(&mut self) -> u8;
     fn get_max_random_count(&mut self) -> u16;
+
+    // for transports with an MTU smaller than a transport message
+
+    /// Largest fragment one device_io transaction carries, or None if
+    /// transport messages are never fragmented.
+    fn get_max_fragment_size(&mut self) -> Option<usize> {
+        None
+    }
+
+    /// Encode the fragment of `transport_buffer` starting at `offset` in
+    /// `fragment_buffer`, return the fragment size and the number of
+    /// transport message bytes it carries.
+    fn encap_fragment(
+        &mut self,
+        transport_buffer: &[u8],
+        offset: usize,
+        fragment_buffer: &mut [u8],
+    ) -> SpdmResult<(usize, usize)> {
+        let size = transport_buffer.len() - offset;
+        if fragment_buffer.len() < size {
+            return spdm_result_err!(EINVAL);
+        }
+        fragment_buffer[..size].copy_from_slice(&transport_buffer[offset..]);
+        Ok((size, size))
+    }
+
+    /// Append `fragment` to the transport message received so far in
+    /// `transport_buffer[..offset]`, return the number of bytes appended and
+    /// whether the transport message is complete.
+    fn decap_fragment(
+        &mut self,
+        fragment: &[u8],
+        transport_buffer: &mut [u8],
+        offset: usize,
+    ) -> SpdmResult<(usize, bool)> {
+        let size = fragment.len();
+        if transport_buffer.len() < offset + size {
+            return spdm_result_err!(EINVAL);
+        }
+        transport_buffer[offset..(offset + size)].copy_from_slice(fragment);
+        Ok((size, true))
+    }
 }
 
 impl Debug for dyn SpdmTransportEncap {
@@ -62,6 +171,40 @@ impl Debug for dyn SpdmTransportEncap {
     }
 }
 
+/// Transcript data handed to the transcript sink once it is final.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpdmTranscriptSegment {
+    /// VCA, GET_VERSION to ALGORITHMS.
+    Vca,
+    /// M1/M2, the transcript the CHALLENGE_AUTH signature covers.
+    M1M2,
+    /// L1/L2, the transcript the MEASUREMENTS signature covers.
+    L1L2,
+    /// A measurement block received in MEASUREMENTS, as encoded on the wire.
+    MeasurementBlock,
+}
+
+/// Receives the transcript segments of a context, e.g. to extend them into a TPM PCR.
+/// Signed segments are only emitted once the signature is generated or verified.
+#[derive(Clone, Copy)]
+pub struct SpdmTranscriptSink {
+    pub segment_cb: fn(segment: SpdmTranscriptSegment, data: &[u8]),
+}
+
+/// Told about every HEARTBEAT exchanged in a session of a context, e.g. to
+/// show on a dashboard which devices are alive.
+#[derive(Clone, Copy)]
+pub struct SpdmLivenessCallback {
+    pub heartbeat_cb: fn(session_id: u32, liveness_stats: SpdmSessionLivenessStats),
+}
+
+/// Told when a session is terminated because the peer cert chain it is
+/// authenticated with no longer verifies, e.g. it expired or was revoked.
+#[derive(Clone, Copy)]
+pub struct SpdmPeerCertCallback {
+    pub cert_invalid_cb: fn(session_id: u32, slot_id: u8),
+}
+
 pub struct SpdmContext<'a> {
     pub device_io: &'a mut dyn SpdmDeviceIo,
     pub transport_encap: &'a mut dyn SpdmTransportEncap,
@@ -74,8 +217,47 @@ pub struct SpdmContext<'a> {
     pub peer_info: SpdmPeerInfo,
 
     pub session: [SpdmSession; config::MAX_SPDM_SESSION_COUNT],
+
+    pub chunk_context: SpdmChunkContext,
+
+    // overrides the global crypto registry for this context, see set_crypto_provider()
+    pub(crate) crypto_provider: SpdmCryptoProvider,
+
+    pub(crate) transcript_sink: Option<SpdmTranscriptSink>,
+
+    pub(crate) liveness_callback: Option<SpdmLivenessCallback>,
+
+    pub(crate) peer_cert_callback: Option<SpdmPeerCertCallback>,
+
+    trace_recorder: Option<&'a mut dyn SpdmTraceRecorder>,
+
+    // how far the connection is set up, reset by GET_VERSION
+    pub(crate) connection_state: SpdmConnectionState,
+
+    // scratch buffers of the send and receive paths, reused instead of
+    // allocated per message
+    #[cfg(feature = "alloc")]
+    transport_buffers: Vec<SpdmTransportBuffer>,
+    #[cfg(feature = "alloc")]
+    message_buffers: Vec<SpdmMessageBuffer>,
 }
 
+/// Scratch buffer for a transport message: a stack array or, with the
+/// "alloc" feature, a heap buffer of `SpdmConfigInfo::transport_buffer_size`
+/// bytes.
+#[cfg(feature = "alloc")]
+pub type SpdmTransportBuffer = Vec<u8>;
+#[cfg(not(feature = "alloc"))]
+pub type SpdmTransportBuffer = [u8; config::MAX_SPDM_TRANSPORT_SIZE];
+
+/// Scratch buffer for a SPDM or an application message: a stack array or,
+/// with the "alloc" feature, a heap buffer of
+/// `SpdmConfigInfo::message_buffer_size` bytes.
+#[cfg(feature = "alloc")]
+pub type SpdmMessageBuffer = Vec<u8>;
+#[cfg(not(feature = "alloc"))]
+pub type SpdmMessageBuffer = [u8; config::MAX_SPDM_MESSAGE_BUFFER_SIZE];
+
 impl<'a> SpdmContext<'a> {
     pub fn new(
         device_io: &'a mut dyn SpdmDeviceIo,
@@ -83,7 +265,7 @@ impl<'a> SpdmContext<'a> {
         config_info: SpdmConfigInfo,
         provision_info: SpdmProvisionInfo,
     ) -> Self {
-        SpdmContext {
+        let mut context = SpdmContext {
             device_io,
             transport_encap,
             config_info,
@@ -91,7 +273,283 @@ impl<'a> SpdmContext<'a> {
             runtime_info: SpdmRuntimeInfo::default(),
             provision_info,
             peer_info: SpdmPeerInfo::default(),
-            session: [SpdmSession::new(); config::MAX_SPDM_SESSION_COUNT],
+            session: Default::default(),
+            chunk_context: SpdmChunkContext::default(),
+            crypto_provider: SpdmCryptoProvider::default(),
+            transcript_sink: None,
+            liveness_callback: None,
+            peer_cert_callback: None,
+            trace_recorder: None,
+            connection_state: SpdmConnectionState::NotStarted,
+            #[cfg(feature = "alloc")]
+            transport_buffers: Vec::new(),
+            #[cfg(feature = "alloc")]
+            message_buffers: Vec::new(),
+        };
+        context.reset_runtime_info();
+        #[cfg(feature = "alloc")]
+        {
+            // enough for the deepest path, answering a secured request with
+            // a secured response; the pools grow if a path needs more
+            for _ in 0..3 {
+                let transport_buffer = context.new_transport_buffer();
+                context.transport_buffers.push(transport_buffer);
+                let message_buffer = context.new_message_buffer();
+                context.message_buffers.push(message_buffer);
+            }
+        }
+        context
+    }
+
+    #[cfg(feature = "alloc")]
+    fn new_transport_buffer(&self) -> SpdmTransportBuffer {
+        alloc::vec![0u8; self.config_info.get_transport_buffer_size()]
+    }
+
+    #[cfg(feature = "alloc")]
+    fn new_message_buffer(&self) -> SpdmMessageBuffer {
+        alloc::vec![0u8; self.config_info.get_message_buffer_size()]
+    }
+
+    /// Zeroed scratch buffer for a transport message, hand it back with
+    /// `put_transport_buffer()` to reuse it.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn take_transport_buffer(&mut self) -> SpdmTransportBuffer {
+        match self.transport_buffers.pop() {
+            Some(buffer) => buffer,
+            None => self.new_transport_buffer(),
+        }
+    }
+
+    #[cfg(not(feature = "alloc"))]
+    pub(crate) fn take_transport_buffer(&mut self) -> SpdmTransportBuffer {
+        [0u8; config::MAX_SPDM_TRANSPORT_SIZE]
+    }
+
+    #[cfg(feature = "alloc")]
+    pub(crate) fn put_transport_buffer(&mut self, mut buffer: SpdmTransportBuffer) {
+        for byte in buffer.iter_mut() {
+            *byte = 0;
+        }
+        self.transport_buffers.push(buffer);
+    }
+
+    #[cfg(not(feature = "alloc"))]
+    pub(crate) fn put_transport_buffer(&mut self, _buffer: SpdmTransportBuffer) {}
+
+    /// Zeroed scratch buffer for a SPDM or an application message, hand it
+    /// back with `put_message_buffer()` to reuse it.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn take_message_buffer(&mut self) -> SpdmMessageBuffer {
+        match self.message_buffers.pop() {
+            Some(buffer) => buffer,
+            None => self.new_message_buffer(),
+        }
+    }
+
+    #[cfg(not(feature = "alloc"))]
+    pub(crate) fn take_message_buffer(&mut self) -> SpdmMessageBuffer {
+        [0u8; config::MAX_SPDM_MESSAGE_BUFFER_SIZE]
+    }
+
+    #[cfg(feature = "alloc")]
+    pub(crate) fn put_message_buffer(&mut self, mut buffer: SpdmMessageBuffer) {
+        for byte in buffer.iter_mut() {
+            *byte = 0;
+        }
+        self.message_buffers.push(buffer);
+    }
+
+    #[cfg(not(feature = "alloc"))]
+    pub(crate) fn put_message_buffer(&mut self, _buffer: SpdmMessageBuffer) {}
+
+    /// Use `crypto_provider` for this context and all of its sessions.
+    /// Callbacks left as `None` fall back to the global crypto registry.
+    pub fn set_crypto_provider(&mut self, crypto_provider: SpdmCryptoProvider) {
+        self.crypto_provider = crypto_provider;
+        for session in self.session.iter_mut() {
+            session.set_crypto_provider(crypto_provider);
+        }
+    }
+
+    pub fn get_crypto_provider(&self) -> &SpdmCryptoProvider {
+        &self.crypto_provider
+    }
+
+    /// AEAD algorithms offered in NEGOTIATE_ALGORITHMS and ALGORITHMS, the
+    /// configured ones the crypto callbacks support.
+    pub fn get_aead_algo(&self) -> SpdmAeadAlgo {
+        self.config_info.aead_algo & self.crypto_provider.aead_supported_algo()
+    }
+
+    /// DHE groups offered in NEGOTIATE_ALGORITHMS and ALGORITHMS, the
+    /// configured ones the crypto callbacks support.
+    pub fn get_dhe_algo(&self) -> SpdmDheAlgo {
+        self.config_info.dhe_algo & self.crypto_provider.dhe_supported_algo()
+    }
+
+    /// The extended algorithms of `ext_algs` the crypto provider implements.
+    pub fn get_ext_algs<'b>(
+        &'b self,
+        ext_algs: &'b [SpdmExtAlgStruct],
+    ) -> impl Iterator<Item = SpdmExtAlgStruct> + 'b {
+        let crypto_provider = &self.crypto_provider;
+        ext_algs
+            .iter()
+            .filter(move |ext_alg| crypto_provider.is_ext_alg_supported(ext_alg))
+            .copied()
+    }
+
+    pub fn set_transcript_sink(&mut self, transcript_sink: SpdmTranscriptSink) {
+        self.transcript_sink = Some(transcript_sink);
+    }
+
+    pub(crate) fn emit_transcript(&self, segment: SpdmTranscriptSegment, data: &[u8]) {
+        if let Some(transcript_sink) = self.transcript_sink {
+            (transcript_sink.segment_cb)(segment, data);
+        }
+    }
+
+    /// Give `trace_recorder` the transport messages sent and received from
+    /// now on.
+    pub fn attach_trace_recorder(&mut self, trace_recorder: &'a mut dyn SpdmTraceRecorder) {
+        self.trace_recorder = Some(trace_recorder);
+    }
+
+    pub fn detach_trace_recorder(&mut self) {
+        self.trace_recorder = None;
+    }
+
+    fn record_transport_message(
+        &mut self,
+        direction: SpdmMessageDirection,
+        transport_message: &[u8],
+    ) {
+        if let Some(trace_recorder) = self.trace_recorder.as_mut() {
+            trace_recorder.record(direction, transport_message);
+        }
+    }
+
+    pub fn set_liveness_callback(&mut self, liveness_callback: SpdmLivenessCallback) {
+        self.liveness_callback = Some(liveness_callback);
+    }
+
+    pub fn set_peer_cert_callback(&mut self, peer_cert_callback: SpdmPeerCertCallback) {
+        self.peer_cert_callback = Some(peer_cert_callback);
+    }
+
+    // a HEARTBEAT_ACK is received or a HEARTBEAT answered in `session_id`
+    pub(crate) fn record_heartbeat(&mut self, session_id: u32) {
+        let liveness_stats = match self.get_session_via_id(session_id) {
+            Some(session) => session.record_heartbeat(),
+            None => return,
+        };
+        if let Some(liveness_callback) = self.liveness_callback {
+            (liveness_callback.heartbeat_cb)(session_id, liveness_stats);
+        }
+    }
+
+    /// Send a transport message, fragmented if the transport has an MTU.
+    pub fn send_transport_message(&mut self, transport_buffer: &[u8]) -> SpdmResult {
+        self.record_transport_message(SpdmMessageDirection::Send, transport_buffer);
+        let max_fragment_size = match self.transport_encap.get_max_fragment_size() {
+            Some(max_fragment_size) => max_fragment_size,
+            None => return self.device_io.send(transport_buffer),
+        };
+
+        let mut fragment_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        let max_fragment_size = max_fragment_size.min(fragment_buffer.len());
+        let mut offset = 0;
+        loop {
+            let (fragment_size, used) = self.transport_encap.encap_fragment(
+                transport_buffer,
+                offset,
+                &mut fragment_buffer[..max_fragment_size],
+            )?;
+            self.device_io.send(&fragment_buffer[..fragment_size])?;
+            offset += used;
+            if offset >= transport_buffer.len() {
+                return Ok(());
+            }
+            if used == 0 {
+                return spdm_result_err!(EFAULT);
+            }
+        }
+    }
+
+    /// Receive a transport message, reassembled from fragments if the
+    /// transport has an MTU.
+    ///
+    /// As for SpdmDeviceIo::receive(), the data that can't be received or
+    /// reassembled is handed back in `transport_buffer` on error.
+    pub fn receive_transport_message(
+        &mut self,
+        transport_buffer: &mut [u8],
+    ) -> Result<usize, usize> {
+        let timeout_us = self.config_info.receive_timeout_us;
+        self.receive_transport_message_timeout(transport_buffer, timeout_us)
+    }
+
+    /// Like receive_transport_message(), but each fragment is waited for
+    /// `timeout_us` at most, 0 blocks.
+    pub fn receive_transport_message_timeout(
+        &mut self,
+        transport_buffer: &mut [u8],
+        timeout_us: usize,
+    ) -> Result<usize, usize> {
+        let used = self.receive_reassembled_transport_message(transport_buffer, timeout_us)?;
+        self.record_transport_message(SpdmMessageDirection::Receive, &transport_buffer[..used]);
+        Ok(used)
+    }
+
+    fn receive_reassembled_transport_message(
+        &mut self,
+        transport_buffer: &mut [u8],
+        timeout_us: usize,
+    ) -> Result<usize, usize> {
+        if self.transport_encap.get_max_fragment_size().is_none() {
+            return self.receive_from_device(transport_buffer, timeout_us);
+        }
+
+        let mut fragment_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        let mut offset = 0;
+        loop {
+            let fragment_size = self
+                .receive_from_device(&mut fragment_buffer, timeout_us)
+                .map_err(|used| {
+                    let used = used.min(transport_buffer.len());
+                    transport_buffer[..used].copy_from_slice(&fragment_buffer[..used]);
+                    used
+                })?;
+            match self.transport_encap.decap_fragment(
+                &fragment_buffer[..fragment_size],
+                transport_buffer,
+                offset,
+            ) {
+                Ok((used, complete)) => {
+                    offset += used;
+                    if complete {
+                        return Ok(offset);
+                    }
+                }
+                Err(_) => {
+                    error!("!!! transport fragment : fail !!!\n");
+                    let used = fragment_size.min(transport_buffer.len());
+                    transport_buffer[..used].copy_from_slice(&fragment_buffer[..used]);
+                    return Err(used);
+                }
+            }
+        }
+    }
+
+    fn receive_from_device(
+        &mut self,
+        buffer: &mut [u8],
+        timeout_us: usize,
+    ) -> Result<usize, usize> {
+        match timeout_us {
+            0 => self.device_io.receive(buffer),
+            timeout_us => self.device_io.receive_with_timeout(buffer, timeout_us),
         }
     }
 
@@ -101,12 +559,213 @@ impl<'a> SpdmContext<'a> {
     pub fn get_asym_key_size(&self) -> u16 {
         self.negotiate_info.base_asym_sel.get_size()
     }
+    pub fn get_req_asym_key_size(&self) -> u16 {
+        self.negotiate_info.req_asym_sel.get_size()
+    }
     pub fn get_dhe_key_size(&self) -> u16 {
         self.negotiate_info.dhe_sel.get_size()
     }
 
+    /// Both sides support CHUNK_SEND / CHUNK_GET (SPDM 1.2 only).
+    pub fn is_chunk_cap_negotiated(&self) -> bool {
+        self.negotiate_info.spdm_version_sel.get_u8() >= SpdmVersion::SpdmVersion12.get_u8()
+            && self
+                .negotiate_info
+                .req_capabilities_sel
+                .contains(SpdmRequestCapabilityFlags::CHUNK_CAP)
+            && self
+                .negotiate_info
+                .rsp_capabilities_sel
+                .contains(SpdmResponseCapabilityFlags::CHUNK_CAP)
+    }
+
+    /// Both sides support mutual authentication with encapsulated requests.
+    pub fn is_mut_auth_cap_negotiated(&self) -> bool {
+        self.negotiate_info.req_capabilities_sel.contains(
+            SpdmRequestCapabilityFlags::MUT_AUTH_CAP | SpdmRequestCapabilityFlags::ENCAP_CAP,
+        ) && self.negotiate_info.rsp_capabilities_sel.contains(
+            SpdmResponseCapabilityFlags::MUT_AUTH_CAP | SpdmResponseCapabilityFlags::ENCAP_CAP,
+        )
+    }
+
+    pub fn is_heartbeat_cap_negotiated(&self) -> bool {
+        self.negotiate_info
+            .req_capabilities_sel
+            .contains(SpdmRequestCapabilityFlags::HBEAT_CAP)
+            && self
+                .negotiate_info
+                .rsp_capabilities_sel
+                .contains(SpdmResponseCapabilityFlags::HBEAT_CAP)
+    }
+
+    /// Heartbeat period of a new session, 0 if either side doesn't support
+    /// HEARTBEAT.
+    pub fn get_heartbeat_period(&self) -> u8 {
+        if self.is_heartbeat_cap_negotiated() {
+            self.config_info.heartbeat_period
+        } else {
+            0
+        }
+    }
+
+    /// Maximum random padding of the secured messages of a new session, the
+    /// configured one if any or else the transport's.
+    pub fn get_max_random_count(&mut self) -> u16 {
+        match self.config_info.max_random_count {
+            Some(max_random_count) => max_random_count,
+            None => self.transport_encap.get_max_random_count(),
+        }
+    }
+
+    /// Secured message version list for KEY_EXCHANGE / PSK_EXCHANGE,
+    /// in the opaque data format negotiated in ALGORITHMS.
+    pub fn get_opaque_data_support_version(&self) -> &'static [u8] {
+        if self.negotiate_info.opaque_support_sel == SpdmOpaqueSupport::OPAQUE_DATA_FMT1 {
+            &OPAQUE_DATA_SUPPORT_VERSION_FMT1
+        } else {
+            &OPAQUE_DATA_SUPPORT_VERSION
+        }
+    }
+
+    /// Secured message version selection for KEY_EXCHANGE_RSP / PSK_EXCHANGE_RSP,
+    /// in the opaque data format negotiated in ALGORITHMS.
+    pub fn get_opaque_data_version_selection(&self) -> &'static [u8] {
+        if self.negotiate_info.opaque_support_sel == SpdmOpaqueSupport::OPAQUE_DATA_FMT1 {
+            &OPAQUE_DATA_VERSION_SELECTION_FMT1
+        } else {
+            &OPAQUE_DATA_VERSION_SELECTION
+        }
+    }
+
+    /// MEASUREMENTS opaque data reporting `generation`, in the opaque data
+    /// format negotiated in ALGORITHMS.
+    pub fn encode_measurement_generation(&self, generation: u32) -> SpdmOpaqueStruct {
+        let header: &[u8] =
+            if self.negotiate_info.opaque_support_sel == SpdmOpaqueSupport::OPAQUE_DATA_FMT1 {
+                &[0x01, 0x00, 0x00, 0x00]
+            } else {
+                &[0x46, 0x54, 0x4d, 0x44, 0x01, 0x01, 0x00, 0x00]
+            };
+        let g = generation.to_le_bytes();
+        let element = [
+            0x00,
+            0x00,
+            0x06,
+            0x00,
+            0x01,
+            OPAQUE_DATA_MEASUREMENT_GENERATION_ID,
+            g[0],
+            g[1],
+            g[2],
+            g[3],
+            0x00,
+            0x00,
+        ];
+
+        let mut opaque = SpdmOpaqueStruct::default();
+        opaque.data[..header.len()].copy_from_slice(header);
+        opaque.data[header.len()..header.len() + element.len()].copy_from_slice(&element);
+        opaque.data_size = (header.len() + element.len()) as u16;
+        opaque
+    }
+
+    /// The generation in the MEASUREMENTS opaque data `opaque`, if the
+    /// responder reports one.
+    pub fn decode_measurement_generation(&self, opaque: &SpdmOpaqueStruct) -> Option<u32> {
+        let mut reader = Reader::init(opaque.as_ref());
+        let total_elements =
+            if self.negotiate_info.opaque_support_sel == SpdmOpaqueSupport::OPAQUE_DATA_FMT1 {
+                let total_elements = u8::read(&mut reader)?;
+                reader.take(3)?;
+                total_elements
+            } else {
+                if reader.take(4)? != OPAQUE_DATA_SPEC_ID || u8::read(&mut reader)? != 0x01 {
+                    return None;
+                }
+                let total_elements = u8::read(&mut reader)?;
+                reader.take(2)?;
+                total_elements
+            };
+        for _ in 0..total_elements {
+            let id = u8::read(&mut reader)?;
+            let vendor_len = u8::read(&mut reader)? as usize;
+            reader.take(vendor_len)?;
+            let element_len = u16::read(&mut reader)? as usize;
+            let element = reader.take(element_len)?;
+            if id == 0
+                && vendor_len == 0
+                && element_len == 6
+                && element[1] == OPAQUE_DATA_MEASUREMENT_GENERATION_ID
+            {
+                return u32::read_bytes(&element[2..]);
+            }
+            // elements are padded to 4 bytes
+            reader.take((4 - (4 + vendor_len + element_len) % 4) % 4)?;
+        }
+        None
+    }
+
     pub fn reset_runtime_info(&mut self) {
-        self.runtime_info = SpdmRuntimeInfo::default();
+        self.runtime_info = SpdmRuntimeInfo {
+            message_a: self.new_managed_buffer(),
+            message_b: self.new_managed_buffer(),
+            message_c: self.new_managed_buffer(),
+            message_m: self.new_managed_buffer(),
+            peer_digests: self.runtime_info.peer_digests,
+            ..Default::default()
+        };
+        self.connection_state = SpdmConnectionState::NotStarted;
+    }
+
+    /// Start the connection over, as GET_VERSION does: the sessions end,
+    /// the transcripts including message_m are cleared and the version has
+    /// to be negotiated again.
+    pub fn reset_connection(&mut self) {
+        for session in self.session.iter_mut() {
+            if session.get_session_id() != 0 {
+                session.teardown();
+            }
+        }
+        self.reset_runtime_info();
+        self.negotiate_info.spdm_version_sel = SpdmVersion::default();
+        self.chunk_context.reset();
+    }
+
+    pub fn get_connection_state(&self) -> SpdmConnectionState {
+        self.connection_state
+    }
+
+    /// Fails with EPERM unless the connection got to `connection_state`,
+    /// e.g. the algorithms have to be negotiated before CHALLENGE.
+    pub fn check_connection_state(&self, connection_state: SpdmConnectionState) -> SpdmResult {
+        if self.connection_state < connection_state {
+            error!(
+                "!!! connection state {:?}, {:?} required !!!\n",
+                self.connection_state, connection_state
+            );
+            return spdm_result_err!(
+                EPERM,
+                "connection not set up that far, see init_connection()"
+            );
+        }
+        Ok(())
+    }
+
+    // after the exchange completing `connection_state`, repeated exchanges
+    // don't move the connection back
+    pub(crate) fn advance_connection_state(&mut self, connection_state: SpdmConnectionState) {
+        self.connection_state = self.connection_state.max(connection_state);
+    }
+
+    /// Transcript buffer of the kind selected by the config info.
+    pub fn new_managed_buffer(&self) -> ManagedBuffer {
+        #[cfg(feature = "alloc")]
+        {
+            if self.config_info.use_heap_buffer {
+                return ManagedBuffer::new_heap();
+            }
+        }
+        ManagedBuffer::default()
     }
 
     pub fn get_session_via_id(&mut self, session_id: u32) -> Option<&mut SpdmSession> {
@@ -118,120 +777,585 @@ impl<'a> SpdmContext<'a> {
         None
     }
 
+    /// Derive the resumption PSK of the established KEY_EXCHANGE session
+    /// `session_id`, replacing the one of an earlier session.
+    pub(crate) fn save_resumption_psk(&mut self, session_id: u32) {
+        let resumption_psk = self
+            .get_session_via_id(session_id)
+            .and_then(|session| session.derive_resumption_psk());
+        if resumption_psk.is_some() {
+            self.peer_info.resumption_psk = resumption_psk;
+        }
+    }
+
+    /// The resumption PSK identified by `psk_hint`, if it can be used with
+    /// the negotiated hash algorithm.
+    pub(crate) fn get_resumption_psk(&self, psk_hint: &[u8]) -> Option<SpdmResumptionPsk> {
+        self.peer_info
+            .resumption_psk
+            .as_ref()
+            .filter(|resumption_psk| {
+                constant_time_eq(resumption_psk.psk_hint.as_ref(), psk_hint)
+                    && resumption_psk.base_hash_algo == self.negotiate_info.base_hash_sel
+            })
+            .cloned()
+    }
+
     pub fn get_next_avaiable_session(&mut self) -> Option<&mut SpdmSession> {
         self.get_session_via_id(0)
     }
 
-    pub fn calc_req_transcript_data(
+    /// IDs of the sessions that are set up, including those still in the
+    /// handshake. Each has its own keys and sequence numbers.
+    pub fn get_active_session_ids(&self) -> impl Iterator<Item = u32> + '_ {
+        self.session
+            .iter()
+            .map(|session| session.get_session_id())
+            .filter(|session_id| *session_id != 0)
+    }
+
+    /// Our half of the session ID of a new session, None if all session
+    /// slots are in use. The requester's half is the upper 16 bits and the
+    /// responder's the lower 16 bits. It counts down from `start`, so the
+    /// first session gets the usual ID.
+    pub(crate) fn get_free_half_session_id(&self, start: u16, requester: bool) -> Option<u16> {
+        if self.get_active_session_ids().count() >= config::MAX_SPDM_SESSION_COUNT {
+            return None;
+        }
+        let half_session_id = |session_id: u32| {
+            if requester {
+                (session_id >> 16) as u16
+            } else {
+                session_id as u16
+            }
+        };
+        (1..=start).rev().find(|half| {
+            self.get_active_session_ids()
+                .all(|session_id| half_session_id(session_id) != *half)
+        })
+    }
+
+    /// Take a free session slot for `session_id`.
+    ///
+    /// The returned guard tears the session down again when dropped,
+    /// unless the handshake is far enough to `commit()` it.
+    pub fn setup_session(&mut self, session_id: u32) -> SpdmResult<SpdmSessionGuard<'a, '_>> {
+        if self.get_session_via_id(session_id).is_some() {
+            return spdm_result_err!(EEXIST, "session id in use");
+        }
+        self.get_next_avaiable_session()
+            .ok_or_else(|| spdm_err!(EBUSY, "too many sessions"))?
+            .setup(session_id)?;
+        Ok(SpdmSessionGuard {
+            context: self,
+            session_id,
+            committed: false,
+        })
+    }
+
+    //
+    // update cert chain - append root cert hash
+    // The hash algorithm is only known after NEGOTIATE_ALGORITHMS.
+    //
+    pub(crate) fn update_my_cert_chain(&mut self) {
+        if self.negotiate_info.base_hash_sel.is_empty() {
+            return;
+        }
+        for slot_id in 0..SPDM_MAX_SLOT_NUMBER {
+            if self.runtime_info.my_cert_chain[slot_id].is_some() {
+                continue;
+            }
+            let cert_chain = match self.provision_info.my_cert_chain_data[slot_id] {
+                Some(cert_chain) => cert_chain,
+                None => continue,
+            };
+            // a slot without root cert hash is left out, its requests fail
+            let root_cert = match self
+                .crypto_provider
+                .get_cert_from_cert_chain(cert_chain.as_slice(), 0)
+                .ok()
+                .and_then(|(begin, end)| cert_chain.as_slice().get(begin..end))
+            {
+                Some(root_cert) => root_cert,
+                None => {
+                    error!("my_cert_chain[{}] - no root cert\n", slot_id);
+                    continue;
+                }
+            };
+            let root_hash = match self
+                .crypto_provider
+                .hash_all(self.negotiate_info.base_hash_sel, root_cert)
+            {
+                Some(root_hash) => root_hash,
+                None => {
+                    error!("my_cert_chain[{}] - root cert hash fail\n", slot_id);
+                    continue;
+                }
+            };
+            let data_size = 4 + root_hash.data_size as usize + cert_chain.data_size as usize;
+            if data_size > config::MAX_SPDM_CERT_CHAIN_DATA_SIZE {
+                error!("my_cert_chain[{}] - too large\n", slot_id);
+                continue;
+            }
+            let data_size = data_size as u16;
+            let mut data = [0u8; config::MAX_SPDM_CERT_CHAIN_DATA_SIZE];
+            data[0] = (data_size & 0xFF) as u8;
+            data[1] = (data_size >> 8) as u8;
+            data[4..(4 + root_hash.data_size as usize)].copy_from_slice(root_hash.as_slice());
+            data[(4 + root_hash.data_size as usize)..(data_size as usize)]
+                .copy_from_slice(cert_chain.as_slice());
+            self.runtime_info.my_cert_chain[slot_id] = Some(SpdmCertChainData { data_size, data });
+            debug!(
+                "my_cert_chain[{}] - {:02x?}\n",
+                slot_id,
+                &data[..(data_size as usize)]
+            );
+        }
+    }
+
+    /// Bit n is set if a cert chain is provisioned in slot n.
+    pub fn get_my_slot_mask(&self) -> u8 {
+        self.provision_info
+            .my_cert_chain_data
+            .iter()
+            .enumerate()
+            .filter(|(_, cert_chain_data)| cert_chain_data.is_some())
+            .fold(0u8, |slot_mask, (slot_id, _)| slot_mask | (1 << slot_id))
+    }
+
+    /// Select the provisioned slot signatures and transcripts use from now on.
+    pub fn set_my_cert_chain_slot_id(&mut self, slot_id: u8) -> SpdmResult {
+        match self.provision_info.my_cert_chain_data.get(slot_id as usize) {
+            Some(Some(_)) => {
+                self.runtime_info.my_cert_chain_slot_id = slot_id;
+                Ok(())
+            }
+            _ => spdm_result_err!(EINVAL, "no cert chain provisioned in slot"),
+        }
+    }
+
+    /// Select the slot to sign for `purpose` with, the requester asked for
+    /// `slot_id`. Fails if a different slot is dedicated to `purpose`.
+    pub fn set_my_signing_slot_id(
         &mut self,
+        purpose: SpdmSigningPurpose,
+        slot_id: u8,
+    ) -> SpdmResult {
+        match self.provision_info.signing_slot_ids.get(purpose) {
+            Some(signing_slot_id) if signing_slot_id != slot_id => {
+                spdm_result_err!(EINVAL, "slot not used for this signature")
+            }
+            _ => self.set_my_cert_chain_slot_id(slot_id),
+        }
+    }
+
+    fn get_my_cert_chain_data(&self) -> SpdmResult<SpdmCertChainData> {
+        self.provision_info
+            .my_cert_chain_data
+            .get(self.runtime_info.my_cert_chain_slot_id as usize)
+            .cloned()
+            .flatten()
+            .ok_or_else(|| spdm_err!(EINVAL))
+    }
+
+    /// The certificates of the peer cert chain signatures are verified
+    /// with, without its length and root hash header.
+    fn get_peer_cert_chain_data(&self) -> SpdmResult<&[u8]> {
+        let hash_size = self.negotiate_info.base_hash_sel.get_size() as usize;
+        self.peer_info
+            .peer_cert_chain
+            .cert_chain
+            .as_slice()
+            .get((4 + hash_size)..)
+            .filter(|cert_chain_data| !cert_chain_data.is_empty())
+            .ok_or(spdm_err!(EINVAL, "no peer cert chain retrieved"))
+    }
+
+    fn get_peer_cert_chain_hash(&self) -> SpdmResult<SpdmDigestStruct> {
+        let cert_chain_data = self.get_peer_cert_chain_data()?;
+        debug!("peer cert_chain_data - {:02x?}", cert_chain_data);
+        self.crypto_provider
+            .hash_all(self.negotiate_info.base_hash_sel, cert_chain_data)
+            .ok_or_else(|| spdm_err!(EFAULT))
+    }
+
+    fn get_my_cert_chain_hash(&self) -> SpdmResult<SpdmDigestStruct> {
+        let my_cert_chain_data = self.get_my_cert_chain_data()?;
+        let cert_chain_data = my_cert_chain_data.as_ref();
+        debug!("my cert_chain_data - {:02x?}", cert_chain_data);
+        self.crypto_provider
+            .hash_all(self.negotiate_info.base_hash_sel, cert_chain_data)
+            .ok_or_else(|| spdm_err!(EFAULT))
+    }
+
+    // the hash of the responder cert chain in the TH transcript
+    fn get_rsp_cert_chain_hash(&self, is_requester: bool) -> SpdmResult<SpdmDigestStruct> {
+        if is_requester {
+            self.get_peer_cert_chain_hash()
+        } else {
+            self.get_my_cert_chain_hash()
+        }
+    }
+
+    // the hash of the requester cert chain in the TH transcript with mut auth
+    fn get_req_cert_chain_hash(&self, is_requester: bool) -> SpdmResult<SpdmDigestStruct> {
+        if is_requester {
+            self.get_my_cert_chain_hash()
+        } else {
+            self.get_peer_cert_chain_hash()
+        }
+    }
+
+    /// message_k of a new session. With `running_transcript_hash` its TH
+    /// digest starts with message_a and, unless `use_psk`, the hash of the
+    /// responder cert chain, and fails if the hash callbacks can't start
+    /// one.
+    pub fn new_message_k(&self, is_requester: bool, use_psk: bool) -> SpdmResult<SpdmTranscript> {
+        let mut message_k = SpdmTranscript {
+            message: self.new_managed_buffer(),
+            running_hash: None,
+        };
+        if self.config_info.running_transcript_hash {
+            let mut running_hash =
+                SpdmRunningHash::new(self.crypto_provider, self.negotiate_info.base_hash_sel)?;
+            running_hash.update(self.runtime_info.message_a.as_ref())?;
+            if !use_psk {
+                running_hash.update(self.get_rsp_cert_chain_hash(is_requester)?.as_ref())?;
+            }
+            message_k.running_hash = Some(running_hash);
+        }
+        Ok(message_k)
+    }
+
+    /// message_f following `message_k`, `mut_auth` adds the hash of the
+    /// requester cert chain to the TH digest in between.
+    pub fn new_message_f(
+        &self,
+        is_requester: bool,
+        mut_auth: bool,
+        message_k: &SpdmTranscript,
+    ) -> SpdmResult<SpdmTranscript> {
+        let mut message_f = SpdmTranscript {
+            message: self.new_managed_buffer(),
+            running_hash: None,
+        };
+        if self.config_info.running_transcript_hash {
+            let mut running_hash = match &message_k.running_hash {
+                Some(running_hash) => running_hash.try_clone()?,
+                None => return spdm_result_err!(EINVAL, "message_k without running hash"),
+            };
+            if mut_auth {
+                running_hash.update(self.get_req_cert_chain_hash(is_requester)?.as_ref())?;
+            }
+            message_f.running_hash = Some(running_hash);
+        }
+        Ok(message_f)
+    }
+
+    // The TH transcript in order: message_a, the responder cert chain hash,
+    // message_k, the requester cert chain hash with `mut_auth` and message_f.
+    fn calc_transcript_data(
+        &self,
+        is_requester: bool,
         use_psk: bool,
-        message_k: &ManagedBuffer,
-        message_f: Option<&ManagedBuffer>,
+        mut_auth: bool,
+        message_k: &SpdmTranscript,
+        message_f: Option<&SpdmTranscript>,
     ) -> SpdmResult<ManagedBuffer> {
-        let mut message = ManagedBuffer::default();
-        message
-            .append_message(self.runtime_info.message_a.as_ref())
-            .ok_or(spdm_err!(ENOMEM))?;
+        let mut message = self.new_managed_buffer();
+        let mut append = |data: &[u8]| {
+            message
+                .append_message(data)
+                .map(|_| ())
+                .ok_or_else(|| spdm_err!(ENOMEM))
+        };
+        append(self.runtime_info.message_a.as_ref())?;
         debug!("message_a - {:02x?}", self.runtime_info.message_a.as_ref());
         if !use_psk {
-            let cert_chain_data = &self.peer_info.peer_cert_chain.cert_chain.data[(4usize
-                + self.negotiate_info.base_hash_sel.get_size() as usize)
-                ..(self.peer_info.peer_cert_chain.cert_chain.data_size as usize)];
-            let cert_chain_hash =
-                crypto::hash::hash_all(self.negotiate_info.base_hash_sel, cert_chain_data)
-                    .ok_or_else(|| spdm_err!(EFAULT))?;
-            message
-                .append_message(cert_chain_hash.as_ref())
-                .ok_or_else(|| spdm_err!(ENOMEM))?;
-            debug!("cert_chain_data - {:02x?}", cert_chain_data);
+            append(self.get_rsp_cert_chain_hash(is_requester)?.as_ref())?;
         }
-        message
-            .append_message(message_k.as_ref())
-            .ok_or_else(|| spdm_err!(ENOMEM))?;
+        append(message_k.as_ref())?;
         debug!("message_k - {:02x?}", message_k.as_ref());
-        if message_f.is_some() {
-            message
-                .append_message(message_f.unwrap().as_ref())
-                .ok_or_else(|| spdm_err!(ENOMEM))?;
-            debug!("message_f - {:02x?}", message_f.unwrap().as_ref());
+        if mut_auth {
+            append(self.get_req_cert_chain_hash(is_requester)?.as_ref())?;
+        }
+        if let Some(message_f) = message_f {
+            append(message_f.as_ref())?;
+            debug!("message_f - {:02x?}", message_f.as_ref());
+        }
+        Ok(message)
+    }
+
+    fn calc_transcript_hash(
+        &self,
+        is_requester: bool,
+        use_psk: bool,
+        mut_auth: bool,
+        message_k: &SpdmTranscript,
+        message_f: Option<&SpdmTranscript>,
+    ) -> SpdmResult<SpdmDigestStruct> {
+        if self.config_info.running_transcript_hash {
+            // the cert chain hashes were fed when message_k and message_f were created
+            return match &message_f.unwrap_or(message_k).running_hash {
+                Some(running_hash) => running_hash.digest(),
+                None => spdm_result_err!(EINVAL, "transcript without running hash"),
+            };
+        }
+
+        let message =
+            self.calc_transcript_data(is_requester, use_psk, mut_auth, message_k, message_f)?;
+        self.crypto_provider
+            .hash_all(self.negotiate_info.base_hash_sel, message.as_ref())
+            .ok_or_else(|| spdm_err!(EFAULT))
+    }
+
+    /// `mut_auth` adds the hash of the requester cert chain before `message_f`.
+    pub fn calc_req_transcript_data(
+        &mut self,
+        use_psk: bool,
+        mut_auth: bool,
+        message_k: &SpdmTranscript,
+        message_f: Option<&SpdmTranscript>,
+    ) -> SpdmResult<ManagedBuffer> {
+        self.calc_transcript_data(true, use_psk, mut_auth, message_k, message_f)
+    }
+
+    /// `mut_auth` adds the hash of the requester cert chain before `message_f`.
+    pub fn calc_rsp_transcript_data(
+        &mut self,
+        use_psk: bool,
+        mut_auth: bool,
+        message_k: &SpdmTranscript,
+        message_f: Option<&SpdmTranscript>,
+    ) -> SpdmResult<ManagedBuffer> {
+        self.calc_transcript_data(false, use_psk, mut_auth, message_k, message_f)
+    }
+
+    pub fn calc_req_transcript_hash(
+        &mut self,
+        use_psk: bool,
+        mut_auth: bool,
+        message_k: &SpdmTranscript,
+        message_f: Option<&SpdmTranscript>,
+    ) -> SpdmResult<SpdmDigestStruct> {
+        self.calc_transcript_hash(true, use_psk, mut_auth, message_k, message_f)
+    }
+
+    pub fn calc_rsp_transcript_hash(
+        &mut self,
+        use_psk: bool,
+        mut_auth: bool,
+        message_k: &SpdmTranscript,
+        message_f: Option<&SpdmTranscript>,
+    ) -> SpdmResult<SpdmDigestStruct> {
+        self.calc_transcript_hash(false, use_psk, mut_auth, message_k, message_f)
+    }
+
+    /// Verify the peer cert chain retrieved with GET_CERTIFICATE: the root
+    /// hash in its header, the chain itself, and the provisioned pinned
+    /// chain, root hash, root store and verification policy, if any.
+    pub fn verify_peer_cert_chain(&self) -> SpdmResult {
+        let hash_size = self.negotiate_info.base_hash_sel.get_size() as usize;
+        let peer_cert_chain = self.peer_info.peer_cert_chain.cert_chain.as_slice();
+        if peer_cert_chain.len() < 4 + hash_size {
+            return spdm_result_err!(EINVAL);
+        }
+        let embedded_root_hash = &peer_cert_chain[4..(4 + hash_size)];
+        let cert_chain = &peer_cert_chain[(4 + hash_size)..];
+
+        let (root_cert_begin, root_cert_end) = self
+            .crypto_provider
+            .get_cert_from_cert_chain(cert_chain, 0)?;
+        let root_cert = &cert_chain[root_cert_begin..root_cert_end];
+        let root_hash = self
+            .crypto_provider
+            .hash_all(self.negotiate_info.base_hash_sel, root_cert)
+            .ok_or_else(|| spdm_err!(EFAULT))?;
+        if !constant_time_eq(root_hash.as_slice(), embedded_root_hash) {
+            error!("root_hash - fail!\n");
+            return spdm_result_err!(EINVAL);
+        }
+
+        if let Some(peer_cert_chain_root_hash) = &self.provision_info.peer_cert_chain_root_hash {
+            if !constant_time_eq(root_hash.as_slice(), peer_cert_chain_root_hash.as_slice()) {
+                error!("provision root_hash - fail!\n");
+                return spdm_result_err!(EINVAL);
+            }
+        }
+
+        if let Some(peer_cert_chain_data) = &self.provision_info.peer_cert_chain_data {
+            if cert_chain != peer_cert_chain_data.as_slice() {
+                error!("cert_chain data - fail!\n");
+                debug!(
+                    "provision cert_chain data size - {:?}\n",
+                    peer_cert_chain_data.data_size
+                );
+                debug!("runtime cert_chain data size - {:?}\n", cert_chain.len());
+                return spdm_result_err!(EINVAL);
+            }
+        }
+
+        if let Some(peer_root_cert_store) = self.provision_info.peer_root_cert_store {
+            if !peer_root_cert_store.contains(&root_cert) {
+                error!("root cert not trusted - fail!\n");
+                return spdm_result_err!(EPERM, "untrusted root cert");
+            }
+        }
+
+        if self
+            .crypto_provider
+            .verify_cert_chain(cert_chain, &self.config_info.cert_chain_config)
+            .is_err()
+        {
+            error!("cert_chain verification - fail!\n");
+            return spdm_result_err!(EFAULT);
         }
 
-        Ok(message)
+        if let Some(policy) = self.provision_info.cert_verification_policy {
+            let (leaf_cert_begin, leaf_cert_end) = self
+                .crypto_provider
+                .get_cert_from_cert_chain(cert_chain, -1)?;
+            let leaf_cert = &cert_chain[leaf_cert_begin..leaf_cert_end];
+            if let Err(e) = (policy.verify_cb)(
+                self.peer_info.peer_cert_chain_slot_id,
+                cert_chain,
+                leaf_cert,
+            ) {
+                error!("cert_chain policy - fail!\n");
+                return Err(e);
+            }
+        }
+        info!("cert_chain verification - pass!\n");
+
+        Ok(())
     }
 
-    pub fn calc_rsp_transcript_data(
+    /// Verify again the cert chain the peer authenticated `session_id`
+    /// with, e.g. whether it expired or was revoked in a long-lived
+    /// session. PSK sessions, and those of a responder not requesting
+    /// mutual authentication, have none and pass.
+    /// The session is left to the caller to terminate.
+    pub fn reverify_session_peer_cert_chain(
         &mut self,
-        use_psk: bool,
-        message_k: &ManagedBuffer,
-        message_f: Option<&ManagedBuffer>,
-    ) -> SpdmResult<ManagedBuffer> {
-        if !use_psk && self.provision_info.my_cert_chain_data.is_none() {
-            return spdm_result_err!(EINVAL);
+        session_id: u32,
+        is_requester: bool,
+    ) -> SpdmResult {
+        let session = self
+            .get_session_via_id(session_id)
+            .ok_or(spdm_err!(EINVAL))?;
+        let slot_id = session.get_slot_id();
+        if session.get_use_psk() || (!is_requester && session.get_mut_auth_requested().is_empty()) {
+            return Ok(());
         }
-        let mut message = ManagedBuffer::default();
-        message
-            .append_message(self.runtime_info.message_a.as_ref())
-            .ok_or(spdm_err!(ENOMEM))?;
-        debug!("message_a - {:02x?}", self.runtime_info.message_a.as_ref());
-        if !use_psk {
-            let my_cert_chain_data = self.provision_info.my_cert_chain_data.unwrap();
-            let cert_chain_data = my_cert_chain_data.as_ref();
-            let cert_chain_hash =
-                crypto::hash::hash_all(self.negotiate_info.base_hash_sel, cert_chain_data)
-                    .ok_or_else(|| spdm_err!(EFAULT))?;
 
-            message
-                .append_message(cert_chain_hash.as_ref())
-                .ok_or_else(|| spdm_err!(ENOMEM))?;
-            debug!("cert_chain_data - {:02x?}", cert_chain_data);
+        // a requester may have retrieved another slot since
+        let peer_info = &self.peer_info;
+        let peer_cert_chain = match peer_info.peer_cert_chains.get(slot_id as usize) {
+            Some(Some(cert_chain)) if is_requester => cert_chain,
+            _ => &peer_info.peer_cert_chain.cert_chain,
+        };
+        let hash_size = self.negotiate_info.base_hash_sel.get_size() as usize;
+        let peer_cert_chain = peer_cert_chain.as_slice();
+        let r = if peer_cert_chain.len() <= 4 + hash_size {
+            spdm_result_err!(EINVAL, "no peer cert chain retrieved")
+        } else {
+            self.crypto_provider.verify_cert_chain(
+                &peer_cert_chain[(4 + hash_size)..],
+                &self.config_info.cert_chain_config,
+            )
+        };
+        if r.is_err() {
+            error!(
+                "!!! session {:08x} : peer cert_chain verification - fail !!!\n",
+                session_id
+            );
+            if let Some(peer_cert_callback) = self.peer_cert_callback {
+                (peer_cert_callback.cert_invalid_cb)(session_id, slot_id);
+            }
         }
-        message
-            .append_message(message_k.as_ref())
-            .ok_or_else(|| spdm_err!(ENOMEM))?;
-        debug!("message_k - {:02x?}", message_k.as_ref());
-        if message_f.is_some() {
-            message
-                .append_message(message_f.unwrap().as_ref())
-                .ok_or_else(|| spdm_err!(ENOMEM))?;
-            debug!("message_f - {:02x?}", message_f.unwrap().as_ref());
+        r
+    }
+
+    /// Check `cert_chain_hash` reported by the responder against the cert
+    /// chain retrieved for `slot_id`, a different hash fails with
+    /// EKEYREJECTED.
+    pub fn verify_peer_cert_chain_hash(
+        &self,
+        slot_id: u8,
+        cert_chain_hash: &SpdmDigestStruct,
+    ) -> SpdmResult {
+        if slot_id != self.peer_info.peer_cert_chain_slot_id
+            || self.peer_info.peer_cert_chain.cert_chain.data_size == 0
+        {
+            return spdm_result_err!(ENOENT, "no cert chain retrieved for slot");
         }
 
-        Ok(message)
+        let calc_cert_chain_hash = self
+            .crypto_provider
+            .hash_all(
+                self.negotiate_info.base_hash_sel,
+                self.peer_info.peer_cert_chain.cert_chain.as_ref(),
+            )
+            .ok_or_else(|| spdm_err!(EFAULT))?;
+
+        if !constant_time_eq(calc_cert_chain_hash.as_ref(), cert_chain_hash.as_ref()) {
+            debug!(
+                "calc cert_chain_hash - {:02x?}",
+                calc_cert_chain_hash.as_ref()
+            );
+            debug!("peer cert_chain_hash - {:02x?}", cert_chain_hash.as_ref());
+            return spdm_result_err!(EKEYREJECTED, "cert chain hash mismatch");
+        }
+        Ok(())
     }
 
-    pub fn calc_req_transcript_hash(
-        &mut self,
-        use_psk: bool,
-        message_k: &ManagedBuffer,
-        message_f: Option<&ManagedBuffer>,
-    ) -> SpdmResult<SpdmDigestStruct> {
-        let message = self.calc_req_transcript_data(use_psk, message_k, message_f)?;
+    /// Build the data to be signed / verified from the transcript `message`.
+    /// Before SPDM 1.2 it is the transcript itself.
+    /// From SPDM 1.2 on it is combined_spdm_prefix || hash(message), where
+    /// combined_spdm_prefix is 4 copies of the version prefix, zero padding and `sign_context`.
+    pub fn create_signing_data(
+        &self,
+        sign_context: &[u8],
+        message: ManagedBuffer,
+    ) -> SpdmResult<ManagedBuffer> {
+        if self.negotiate_info.spdm_version_sel.get_u8() < SpdmVersion::SpdmVersion12.get_u8() {
+            return Ok(message);
+        }
 
-        let transcript_hash =
-            crypto::hash::hash_all(self.negotiate_info.base_hash_sel, message.as_ref())
-                .ok_or_else(|| spdm_err!(EFAULT))?;
-        Ok(transcript_hash)
-    }
+        let message_hash = self
+            .crypto_provider
+            .hash_all(self.negotiate_info.base_hash_sel, message.as_ref())
+            .ok_or_else(|| spdm_err!(EFAULT))?;
 
-    pub fn calc_rsp_transcript_hash(
-        &mut self,
-        use_psk: bool,
-        message_k: &ManagedBuffer,
-        message_f: Option<&ManagedBuffer>,
-    ) -> SpdmResult<SpdmDigestStruct> {
-        let message = self.calc_rsp_transcript_data(use_psk, message_k, message_f)?;
+        let mut combined_spdm_prefix = [0u8; SPDM_VERSION_1_2_SIGNING_CONTEXT_SIZE];
+        let prefix_size = SPDM_VERSION_1_2_SIGNING_PREFIX_CONTEXT.len()
+            * SPDM_VERSION_1_2_SIGNING_PREFIX_CONTEXT_COUNT;
+        if sign_context.len() > SPDM_VERSION_1_2_SIGNING_CONTEXT_SIZE - prefix_size {
+            return spdm_result_err!(EINVAL);
+        }
+        for chunk in combined_spdm_prefix[..prefix_size]
+            .chunks_mut(SPDM_VERSION_1_2_SIGNING_PREFIX_CONTEXT.len())
+        {
+            chunk.copy_from_slice(SPDM_VERSION_1_2_SIGNING_PREFIX_CONTEXT);
+        }
+        combined_spdm_prefix[(SPDM_VERSION_1_2_SIGNING_CONTEXT_SIZE - sign_context.len())..]
+            .copy_from_slice(sign_context);
 
-        let transcript_hash =
-            crypto::hash::hash_all(self.negotiate_info.base_hash_sel, message.as_ref())
-                .ok_or_else(|| spdm_err!(EFAULT))?;
-        Ok(transcript_hash)
+        let mut signing_data = ManagedBuffer::default();
+        signing_data
+            .append_message(&combined_spdm_prefix)
+            .ok_or_else(|| spdm_err!(ENOMEM))?;
+        signing_data
+            .append_message(message_hash.as_ref())
+            .ok_or_else(|| spdm_err!(ENOMEM))?;
+        Ok(signing_data)
     }
 
     pub fn verify_challenge_auth_signature(
         &mut self,
         signature: &SpdmSignatureStruct,
     ) -> SpdmResult {
-        let mut message = ManagedBuffer::default();
+        let mut message = self.new_managed_buffer();
         message
             .append_message(self.runtime_info.message_a.as_ref())
             .ok_or_else(|| spdm_err!(ENOMEM))?;
@@ -243,26 +1367,30 @@ impl<'a> SpdmContext<'a> {
             .ok_or_else(|| spdm_err!(ENOMEM))?;
         // we dont need create message hash for verify
         // we just print message hash for debug purpose
-        let message_hash =
-            crypto::hash::hash_all(self.negotiate_info.base_hash_sel, message.as_ref())
-                .ok_or_else(|| spdm_err!(EFAULT))?;
+        let message_hash = self
+            .crypto_provider
+            .hash_all(self.negotiate_info.base_hash_sel, message.as_ref())
+            .ok_or_else(|| spdm_err!(EFAULT))?;
         debug!("message_hash - {:02x?}", message_hash.as_ref());
 
-        let cert_chain_data = &self.peer_info.peer_cert_chain.cert_chain.data[(4usize
-            + self.negotiate_info.base_hash_sel.get_size() as usize)
-            ..(self.peer_info.peer_cert_chain.cert_chain.data_size as usize)];
+        let cert_chain_data = self.get_peer_cert_chain_data()?;
 
-        crypto::asym_verify::verify(
+        let signing_data =
+            self.create_signing_data(SPDM_CHALLENGE_AUTH_SIGN_CONTEXT, message.clone())?;
+
+        self.crypto_provider.asym_verify(
             self.negotiate_info.base_hash_sel,
             self.negotiate_info.base_asym_sel,
             cert_chain_data,
-            message.as_ref(),
+            signing_data.as_ref(),
             signature,
-        )
+        )?;
+        self.emit_transcript(SpdmTranscriptSegment::M1M2, message.as_ref());
+        Ok(())
     }
 
     pub fn generate_challenge_auth_signature(&mut self) -> SpdmResult<SpdmSignatureStruct> {
-        let mut message = ManagedBuffer::default();
+        let mut message = self.new_managed_buffer();
         message
             .append_message(self.runtime_info.message_a.as_ref())
             .ok_or_else(|| spdm_err!(ENOMEM))?;
@@ -274,82 +1402,113 @@ impl<'a> SpdmContext<'a> {
             .ok_or_else(|| spdm_err!(ENOMEM))?;
         // we dont need create message hash for verify
         // we just print message hash for debug purpose
-        let message_hash =
-            crypto::hash::hash_all(self.negotiate_info.base_hash_sel, message.as_ref())
-                .ok_or_else(|| spdm_err!(EFAULT))?;
+        let message_hash = self
+            .crypto_provider
+            .hash_all(self.negotiate_info.base_hash_sel, message.as_ref())
+            .ok_or_else(|| spdm_err!(EFAULT))?;
         debug!("message_hash - {:02x?}", message_hash.as_ref());
 
-        crypto::asym_sign::sign(
-            self.negotiate_info.base_hash_sel,
-            self.negotiate_info.base_asym_sel,
-            message.as_ref(),
-        )
-        .ok_or_else(|| spdm_err!(EFAULT))
+        let signing_data =
+            self.create_signing_data(SPDM_CHALLENGE_AUTH_SIGN_CONTEXT, message.clone())?;
+
+        let signature = self
+            .crypto_provider
+            .asym_sign(
+                self.runtime_info.my_cert_chain_slot_id,
+                self.negotiate_info.base_hash_sel,
+                self.negotiate_info.base_asym_sel,
+                signing_data.as_ref(),
+            )
+            .ok_or_else(|| spdm_err!(EFAULT))?;
+        self.emit_transcript(SpdmTranscriptSegment::M1M2, message.as_ref());
+        Ok(signature)
     }
 
-    pub fn verify_measurement_signature(&mut self, signature: &SpdmSignatureStruct) -> SpdmResult {
-        let mut message = ManagedBuffer::default();
+    /// L1/L2. From SPDM 1.2 on it also covers VCA (message_a).
+    fn calc_measurement_transcript_data(&self) -> SpdmResult<ManagedBuffer> {
+        let mut message = self.new_managed_buffer();
+        if self.negotiate_info.spdm_version_sel.get_u8() >= SpdmVersion::SpdmVersion12.get_u8() {
+            message
+                .append_message(self.runtime_info.message_a.as_ref())
+                .ok_or_else(|| spdm_err!(ENOMEM))?;
+        }
         message
             .append_message(self.runtime_info.message_m.as_ref())
             .ok_or_else(|| spdm_err!(ENOMEM))?;
+        Ok(message)
+    }
+
+    pub fn verify_measurement_signature(&mut self, signature: &SpdmSignatureStruct) -> SpdmResult {
+        let message = self.calc_measurement_transcript_data()?;
         // we dont need create message hash for verify
         // we just print message hash for debug purpose
-        let message_hash =
-            crypto::hash::hash_all(self.negotiate_info.base_hash_sel, message.as_ref())
-                .ok_or_else(|| spdm_err!(EFAULT))?;
+        let message_hash = self
+            .crypto_provider
+            .hash_all(self.negotiate_info.base_hash_sel, message.as_ref())
+            .ok_or_else(|| spdm_err!(EFAULT))?;
         debug!("message_hash - {:02x?}", message_hash.as_ref());
 
-        let cert_chain_data = &self.peer_info.peer_cert_chain.cert_chain.data[(4usize
-            + self.negotiate_info.base_hash_sel.get_size() as usize)
-            ..(self.peer_info.peer_cert_chain.cert_chain.data_size as usize)];
+        let cert_chain_data = self.get_peer_cert_chain_data()?;
 
-        crypto::asym_verify::verify(
+        let signing_data =
+            self.create_signing_data(SPDM_MEASUREMENTS_SIGN_CONTEXT, message.clone())?;
+
+        self.crypto_provider.asym_verify(
             self.negotiate_info.base_hash_sel,
             self.negotiate_info.base_asym_sel,
             cert_chain_data,
-            message.as_ref(),
+            signing_data.as_ref(),
             signature,
-        )
+        )?;
+        self.emit_transcript(SpdmTranscriptSegment::L1L2, message.as_ref());
+        Ok(())
     }
 
     pub fn generate_measurement_signature(&mut self) -> SpdmResult<SpdmSignatureStruct> {
-        let mut message = ManagedBuffer::default();
-        message
-            .append_message(self.runtime_info.message_m.as_ref())
-            .ok_or_else(|| spdm_err!(ENOMEM))?;
+        let message = self.calc_measurement_transcript_data()?;
         // we dont need create message hash for verify
         // we just print message hash for debug purpose
-        let message_hash =
-            crypto::hash::hash_all(self.negotiate_info.base_hash_sel, message.as_ref())
-                .ok_or_else(|| spdm_err!(EFAULT))?;
+        let message_hash = self
+            .crypto_provider
+            .hash_all(self.negotiate_info.base_hash_sel, message.as_ref())
+            .ok_or_else(|| spdm_err!(EFAULT))?;
         debug!("message_hash - {:02x?}", message_hash.as_ref());
 
-        crypto::asym_sign::sign(
-            self.negotiate_info.base_hash_sel,
-            self.negotiate_info.base_asym_sel,
-            message.as_ref(),
-        )
-        .ok_or_else(|| spdm_err!(EFAULT))
+        let signing_data =
+            self.create_signing_data(SPDM_MEASUREMENTS_SIGN_CONTEXT, message.clone())?;
+
+        let signature = self
+            .crypto_provider
+            .asym_sign(
+                self.runtime_info.my_cert_chain_slot_id,
+                self.negotiate_info.base_hash_sel,
+                self.negotiate_info.base_asym_sel,
+                signing_data.as_ref(),
+            )
+            .ok_or_else(|| spdm_err!(EFAULT))?;
+        self.emit_transcript(SpdmTranscriptSegment::L1L2, message.as_ref());
+        Ok(signature)
     }
 
     pub fn verify_key_exchange_rsp_signature(
         &mut self,
-        message_k: &ManagedBuffer,
+        message_k: &SpdmTranscript,
         signature: &SpdmSignatureStruct,
     ) -> SpdmResult {
-        let message = self.calc_req_transcript_data(false, message_k, None)?;
+        let message = self.calc_req_transcript_data(false, false, message_k, None)?;
         // we dont need create message hash for verify
         // we just print message hash for debug purpose
-        let message_hash =
-            crypto::hash::hash_all(self.negotiate_info.base_hash_sel, message.as_ref())
-                .ok_or_else(|| spdm_err!(EFAULT))?;
+        let message_hash = self
+            .crypto_provider
+            .hash_all(self.negotiate_info.base_hash_sel, message.as_ref())
+            .ok_or_else(|| spdm_err!(EFAULT))?;
         debug!("message_hash - {:02x?}", message_hash.as_ref());
 
-        let cert_chain_data = &self.peer_info.peer_cert_chain.cert_chain.data[(4usize
-            + self.negotiate_info.base_hash_sel.get_size() as usize)
-            ..(self.peer_info.peer_cert_chain.cert_chain.data_size as usize)];
+        let cert_chain_data = self.get_peer_cert_chain_data()?;
 
-        crypto::asym_verify::verify(
+        let message = self.create_signing_data(SPDM_KEY_EXCHANGE_RSP_SIGN_CONTEXT, message)?;
+
+        self.crypto_provider.asym_verify(
             self.negotiate_info.base_hash_sel,
             self.negotiate_info.base_asym_sel,
             cert_chain_data,
@@ -360,32 +1519,165 @@ impl<'a> SpdmContext<'a> {
 
     pub fn generate_key_exchange_rsp_signature(
         &mut self,
-        message_k: &ManagedBuffer,
+        message_k: &SpdmTranscript,
     ) -> SpdmResult<SpdmSignatureStruct> {
-        let message = self.calc_rsp_transcript_data(false, message_k, None)?;
+        let message = self.calc_rsp_transcript_data(false, false, message_k, None)?;
         // we dont need create message hash for verify
         // we just print message hash for debug purpose
-        let message_hash =
-            crypto::hash::hash_all(self.negotiate_info.base_hash_sel, message.as_ref())
-                .ok_or_else(|| spdm_err!(EFAULT))?;
+        let message_hash = self
+            .crypto_provider
+            .hash_all(self.negotiate_info.base_hash_sel, message.as_ref())
+            .ok_or_else(|| spdm_err!(EFAULT))?;
+        debug!("message_hash - {:02x?}", message_hash.as_ref());
+
+        let message = self.create_signing_data(SPDM_KEY_EXCHANGE_RSP_SIGN_CONTEXT, message)?;
+
+        self.crypto_provider
+            .asym_sign(
+                self.runtime_info.my_cert_chain_slot_id,
+                self.negotiate_info.base_hash_sel,
+                self.negotiate_info.base_asym_sel,
+                message.as_ref(),
+            )
+            .ok_or_else(|| spdm_err!(EFAULT))
+    }
+
+    pub fn generate_finish_req_signature(
+        &mut self,
+        message_k: &SpdmTranscript,
+        message_f: &SpdmTranscript,
+    ) -> SpdmResult<SpdmSignatureStruct> {
+        let message = self.calc_req_transcript_data(false, true, message_k, Some(message_f))?;
+        // we dont need create message hash for verify
+        // we just print message hash for debug purpose
+        let message_hash = self
+            .crypto_provider
+            .hash_all(self.negotiate_info.base_hash_sel, message.as_ref())
+            .ok_or_else(|| spdm_err!(EFAULT))?;
+        debug!("message_hash - {:02x?}", message_hash.as_ref());
+
+        let message = self.create_signing_data(SPDM_FINISH_SIGN_CONTEXT, message)?;
+
+        self.crypto_provider
+            .asym_sign(
+                self.runtime_info.my_cert_chain_slot_id,
+                self.negotiate_info.base_hash_sel,
+                self.negotiate_info.req_asym_sel.into(),
+                message.as_ref(),
+            )
+            .ok_or_else(|| spdm_err!(EFAULT))
+    }
+
+    pub fn verify_finish_req_signature(
+        &mut self,
+        message_k: &SpdmTranscript,
+        message_f: &SpdmTranscript,
+        signature: &SpdmSignatureStruct,
+    ) -> SpdmResult {
+        let message = self.calc_rsp_transcript_data(false, true, message_k, Some(message_f))?;
+        // we dont need create message hash for verify
+        // we just print message hash for debug purpose
+        let message_hash = self
+            .crypto_provider
+            .hash_all(self.negotiate_info.base_hash_sel, message.as_ref())
+            .ok_or_else(|| spdm_err!(EFAULT))?;
         debug!("message_hash - {:02x?}", message_hash.as_ref());
 
-        crypto::asym_sign::sign(
+        let cert_chain_data = self.get_peer_cert_chain_data()?;
+
+        let message = self.create_signing_data(SPDM_FINISH_SIGN_CONTEXT, message)?;
+
+        self.crypto_provider.asym_verify(
             self.negotiate_info.base_hash_sel,
-            self.negotiate_info.base_asym_sel,
+            self.negotiate_info.req_asym_sel.into(),
+            cert_chain_data,
             message.as_ref(),
+            signature,
         )
-        .ok_or_else(|| spdm_err!(EFAULT))
     }
 }
 
-#[derive(Debug, Default)]
+/// Half-open session created by `SpdmContext::setup_session()`.
+pub struct SpdmSessionGuard<'a, 'b> {
+    context: &'b mut SpdmContext<'a>,
+    session_id: u32,
+    committed: bool,
+}
+
+impl<'a, 'b> SpdmSessionGuard<'a, 'b> {
+    pub fn get_session_id(&self) -> u32 {
+        self.session_id
+    }
+
+    pub fn context(&mut self) -> &mut SpdmContext<'a> {
+        self.context
+    }
+
+    pub fn session(&mut self) -> &mut SpdmSession {
+        let session_id = self.session_id;
+        self.context
+            .get_session_via_id(session_id)
+            .expect("guarded session released")
+    }
+
+    /// Keep the session beyond the lifetime of the guard.
+    pub fn commit(mut self) -> u32 {
+        self.committed = true;
+        self.session_id
+    }
+}
+
+impl Drop for SpdmSessionGuard<'_, '_> {
+    fn drop(&mut self) {
+        if self.committed {
+            return;
+        }
+        if let Some(session) = self.context.get_session_via_id(self.session_id) {
+            session.teardown();
+        }
+    }
+}
+
+/// How a responder answers a request its crypto backend failed on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpdmCryptoFailurePolicy {
+    /// ERROR(Unspecified).
+    Unspecified,
+    /// ERROR(Busy), for a backend that fails while it is overloaded. The
+    /// requester sends the request again later.
+    Busy,
+    /// ERROR(RequestResynch), the connection state is dropped and the
+    /// requester starts over with GET_VERSION.
+    RequestResynch,
+}
+
+/// Random bytes padded to a secured message before it is encrypted, to hide
+/// the size of the SPDM message from an observer of the transport.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpdmRandomPaddingPolicy {
+    /// 1 to the maximum random count, picked for each message.
+    Random,
+    /// Always the maximum random count, all messages of the same size are
+    /// padded alike.
+    Max,
+    /// No padding.
+    Zero,
+}
+
+/// `SpdmConfigInfo::new()` and `SpdmProvisionInfo::new()` are const, a
+/// device can keep both in a `static` in ROM and clone them into the
+/// context.
+#[derive(Debug, Clone)]
 pub struct SpdmConfigInfo {
     pub spdm_version: [SpdmVersion; config::MAX_SPDM_VERSION_COUNT],
     pub req_capabilities: SpdmRequestCapabilityFlags,
     pub rsp_capabilities: SpdmResponseCapabilityFlags,
     pub req_ct_exponent: u8,
     pub rsp_ct_exponent: u8,
+    // SPDM 1.2 only
+    pub data_transfer_size: u32,
+    pub max_spdm_msg_size: u32,
+    pub opaque_support: SpdmOpaqueSupport,
     pub measurement_specification: SpdmMeasurementSpecification,
     pub measurement_hash_algo: SpdmMeasurementHashAlgo,
     pub base_hash_algo: SpdmBaseHashAlgo,
@@ -394,6 +1686,172 @@ pub struct SpdmConfigInfo {
     pub aead_algo: SpdmAeadAlgo,
     pub req_asym_algo: SpdmReqAsymAlgo,
     pub key_schedule_algo: SpdmKeyScheduleAlgo,
+    // responder only, the algorithms selected in ALGORITHMS in order of
+    // preference. Algorithms left out of a list are never selected, an
+    // empty list selects in the built-in order.
+    pub base_asym_algo_priority: &'static [SpdmBaseAsymAlgo],
+    pub base_hash_algo_priority: &'static [SpdmBaseHashAlgo],
+    pub dhe_algo_priority: &'static [SpdmDheAlgo],
+    pub aead_algo_priority: &'static [SpdmAeadAlgo],
+    pub req_asym_algo_priority: &'static [SpdmReqAsymAlgo],
+    pub key_schedule_algo_priority: &'static [SpdmKeyScheduleAlgo],
+    // extended algorithms offered in NEGOTIATE_ALGORITHMS, selected by a
+    // responder in this order if no base algorithm is in common. Only those
+    // the SpdmExtAlgo callbacks implement are used.
+    pub ext_asym_algo: &'static [SpdmExtAlgStruct],
+    pub ext_hash_algo: &'static [SpdmExtAlgStruct],
+    // requested in KEY_EXCHANGE, SPDM 1.2 only
+    pub session_policy: SpdmKeyExchangeSessionPolicy,
+    // responder only, in seconds, returned in KEY_EXCHANGE_RSP and
+    // PSK_EXCHANGE_RSP. 0 disables the heartbeat.
+    pub heartbeat_period: u8,
+    // responder only, in seconds, sessions are terminated this long after
+    // they are set up. 0 disables the limit.
+    pub max_session_lifetime: u32,
+    // responder only, requests answered per second, the others get
+    // ERROR(Busy). 0 disables the limit.
+    pub request_rate_limit: u32,
+    // heap backed transcripts, not bound by MAX_SPDM_MESSAGE_BUFFER_SIZE
+    #[cfg(feature = "alloc")]
+    pub use_heap_buffer: bool,
+    // sizes of the heap scratch buffers of the send and receive paths, 0
+    // uses MAX_SPDM_TRANSPORT_SIZE and MAX_SPDM_MESSAGE_BUFFER_SIZE
+    #[cfg(feature = "alloc")]
+    pub transport_buffer_size: usize,
+    #[cfg(feature = "alloc")]
+    pub message_buffer_size: usize,
+    // 0 blocks in SpdmDeviceIo::receive()
+    pub receive_timeout_us: usize,
+    // requester only, times the request is sent again if no response is
+    // received within receive_timeout_us
+    pub receive_retry_count: u8,
+    // requester only, round-trip time of the transport (RTT). Non-zero
+    // waits RTT + ST1 for a response instead of receive_timeout_us, or
+    // RTT + CT for requests with cryptographic processing, CT being
+    // 2^CTExponent us of the responder.
+    pub transport_rtt_us: usize,
+    // requester only, times a request answered with ERROR(Busy) is sent
+    // again, busy_retry_interval_us apart
+    pub busy_retry_count: u8,
+    pub busy_retry_interval_us: usize,
+    // requester only, the PSK of PSK_EXCHANGE and the hint the responder
+    // identifies it by. Responders look the PSK up with the SpdmPsk callback.
+    pub psk: SpdmDheFinalKeyStruct,
+    pub psk_hint: SpdmPskHintStruct,
+    // secured messages accepted out of order, see SpdmSession::set_replay_window_size()
+    pub replay_window_size: u8,
+    // random padding of the secured messages sent, up to max_random_count
+    // bytes. None uses SpdmTransportEncap::get_max_random_count().
+    pub max_random_count: Option<u16>,
+    pub random_padding_policy: SpdmRandomPaddingPolicy,
+    // feed the TH digests as the messages are appended to message_k and
+    // message_f instead of hashing the whole transcript each time, see
+    // SpdmHash::hash_ctx_init_cb. Sessions fail if no digest can be started.
+    pub running_transcript_hash: bool,
+    // verify the peer cert chain of a session again before each KEY_UPDATE,
+    // the session is terminated if it expired or was revoked since
+    pub reverify_peer_cert_on_key_update: bool,
+    // checks of the peer cert chains besides the signatures. Without std
+    // there is no clock to check their validity with unless an SpdmTime or
+    // the get_unix_time_cb of the config is provided.
+    pub cert_chain_config: SpdmCertChainConfig,
+    // responder only, checks of the own cert chains installed with
+    // provision_complete(), the peer ones may follow another policy
+    pub my_cert_chain_config: SpdmCertChainConfig,
+    // responder only, the opaque data returned in CHALLENGE_AUTH
+    pub challenge_opaque_data: SpdmOpaqueStruct,
+    // responder only
+    pub crypto_failure_policy: SpdmCryptoFailurePolicy,
+}
+
+impl SpdmConfigInfo {
+    /// Nothing supported, fill in the rest with `..SpdmConfigInfo::new()`.
+    pub const fn new() -> SpdmConfigInfo {
+        SpdmConfigInfo {
+            spdm_version: [SpdmVersion::Unknown(0); config::MAX_SPDM_VERSION_COUNT],
+            req_capabilities: SpdmRequestCapabilityFlags::empty(),
+            rsp_capabilities: SpdmResponseCapabilityFlags::empty(),
+            req_ct_exponent: 0,
+            rsp_ct_exponent: 0,
+            data_transfer_size: 0,
+            max_spdm_msg_size: 0,
+            opaque_support: SpdmOpaqueSupport::empty(),
+            measurement_specification: SpdmMeasurementSpecification::empty(),
+            measurement_hash_algo: SpdmMeasurementHashAlgo::empty(),
+            base_hash_algo: SpdmBaseHashAlgo::empty(),
+            base_asym_algo: SpdmBaseAsymAlgo::empty(),
+            dhe_algo: SpdmDheAlgo::empty(),
+            aead_algo: SpdmAeadAlgo::empty(),
+            req_asym_algo: SpdmReqAsymAlgo::empty(),
+            key_schedule_algo: SpdmKeyScheduleAlgo::empty(),
+            base_asym_algo_priority: &[],
+            base_hash_algo_priority: &[],
+            dhe_algo_priority: &[],
+            aead_algo_priority: &[],
+            req_asym_algo_priority: &[],
+            key_schedule_algo_priority: &[],
+            ext_asym_algo: &[],
+            ext_hash_algo: &[],
+            session_policy: SpdmKeyExchangeSessionPolicy::empty(),
+            heartbeat_period: 0,
+            max_session_lifetime: 0,
+            request_rate_limit: 0,
+            #[cfg(feature = "alloc")]
+            use_heap_buffer: false,
+            #[cfg(feature = "alloc")]
+            transport_buffer_size: 0,
+            #[cfg(feature = "alloc")]
+            message_buffer_size: 0,
+            receive_timeout_us: 0,
+            receive_retry_count: 0,
+            transport_rtt_us: 0,
+            busy_retry_count: 0,
+            busy_retry_interval_us: 0,
+            psk: SpdmDheFinalKeyStruct {
+                data_size: 0,
+                data: [0u8; SPDM_MAX_DHE_KEY_SIZE],
+            },
+            psk_hint: SpdmPskHintStruct {
+                data_size: 0,
+                data: [0u8; config::MAX_SPDM_PSK_HINT_SIZE],
+            },
+            replay_window_size: 0,
+            max_random_count: None,
+            random_padding_policy: SpdmRandomPaddingPolicy::Random,
+            running_transcript_hash: false,
+            reverify_peer_cert_on_key_update: false,
+            cert_chain_config: SpdmCertChainConfig::new(),
+            my_cert_chain_config: SpdmCertChainConfig::new(),
+            challenge_opaque_data: SpdmOpaqueStruct {
+                data_size: 0,
+                data: [0u8; config::MAX_SPDM_OPAQUE_SIZE],
+            },
+            crypto_failure_policy: SpdmCryptoFailurePolicy::Unspecified,
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl SpdmConfigInfo {
+    pub fn get_transport_buffer_size(&self) -> usize {
+        match self.transport_buffer_size {
+            0 => config::MAX_SPDM_TRANSPORT_SIZE,
+            size => size,
+        }
+    }
+
+    pub fn get_message_buffer_size(&self) -> usize {
+        match self.message_buffer_size {
+            0 => config::MAX_SPDM_MESSAGE_BUFFER_SIZE,
+            size => size,
+        }
+    }
+}
+
+impl Default for SpdmConfigInfo {
+    fn default() -> SpdmConfigInfo {
+        SpdmConfigInfo::new()
+    }
 }
 
 #[derive(Debug, Default)]
@@ -403,6 +1861,11 @@ pub struct SpdmNegotiateInfo {
     pub rsp_capabilities_sel: SpdmResponseCapabilityFlags,
     pub req_ct_exponent_sel: u8,
     pub rsp_ct_exponent_sel: u8,
+    pub req_data_transfer_size_sel: u32,
+    pub rsp_data_transfer_size_sel: u32,
+    pub req_max_spdm_msg_size_sel: u32,
+    pub rsp_max_spdm_msg_size_sel: u32,
+    pub opaque_support_sel: SpdmOpaqueSupport,
     pub measurement_specification_sel: SpdmMeasurementSpecification,
     pub measurement_hash_sel: SpdmMeasurementHashAlgo,
     pub base_hash_sel: SpdmBaseHashAlgo,
@@ -411,38 +1874,310 @@ pub struct SpdmNegotiateInfo {
     pub aead_sel: SpdmAeadAlgo,
     pub req_asym_sel: SpdmReqAsymAlgo,
     pub key_schedule_sel: SpdmKeyScheduleAlgo,
+    // extended algorithm selected instead of a base one, if any
+    pub ext_asym_sel: Option<SpdmExtAlgStruct>,
+    pub ext_hash_sel: Option<SpdmExtAlgStruct>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpdmChunkStatus {
+    Idle,
+    // receiving a large request in CHUNK_SEND
+    LargeRequest,
+    // holding a large response that is not consumed yet
+    LargeResponse,
+}
+
+/// Large message transferred with CHUNK_SEND / CHUNK_GET.
+#[derive(Debug, Clone)]
+pub struct SpdmChunkContext {
+    pub status: SpdmChunkStatus,
+    pub handle: u8,
+    pub chunk_seq_no: u16,
+    // bytes of the large message transferred so far
+    pub transferred_size: usize,
+    pub large_message_size: usize,
+    pub large_message: [u8; config::MAX_SPDM_MESSAGE_BUFFER_SIZE],
+}
+
+impl Default for SpdmChunkContext {
+    fn default() -> SpdmChunkContext {
+        SpdmChunkContext {
+            status: SpdmChunkStatus::Idle,
+            handle: 0,
+            chunk_seq_no: 0,
+            transferred_size: 0,
+            large_message_size: 0,
+            large_message: [0u8; config::MAX_SPDM_MESSAGE_BUFFER_SIZE],
+        }
+    }
+}
+
+impl SpdmChunkContext {
+    /// Start a new transfer of `large_message_size` bytes.
+    /// The handle changes with every transfer.
+    pub fn start(&mut self, status: SpdmChunkStatus, large_message_size: usize) -> SpdmResult {
+        if large_message_size > config::MAX_SPDM_MESSAGE_BUFFER_SIZE {
+            return spdm_result_err!(ENOMEM, "large message too big");
+        }
+        self.status = status;
+        self.new_handle();
+        self.chunk_seq_no = 0;
+        self.transferred_size = 0;
+        self.large_message_size = large_message_size;
+        Ok(())
+    }
+
+    pub fn new_handle(&mut self) -> u8 {
+        self.handle = self.handle.wrapping_add(1);
+        self.handle
+    }
+
+    pub fn reset(&mut self) {
+        self.status = SpdmChunkStatus::Idle;
+        self.chunk_seq_no = 0;
+        self.transferred_size = 0;
+        self.large_message_size = 0;
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.transferred_size == self.large_message_size
+    }
+
+    pub fn get_large_message(&self) -> &[u8] {
+        &self.large_message[..self.large_message_size]
+    }
 }
 
 // TBD ManagedSmallBuffer
-#[derive(Debug, Copy, Clone)]
-pub struct ManagedBuffer(usize, [u8; config::MAX_SPDM_MESSAGE_BUFFER_SIZE]);
+#[derive(Debug, Clone)]
+#[allow(clippy::large_enum_variant)]
+pub enum ManagedBuffer {
+    Static(usize, [u8; config::MAX_SPDM_MESSAGE_BUFFER_SIZE]),
+    // grows as needed, not bound by MAX_SPDM_MESSAGE_BUFFER_SIZE
+    #[cfg(feature = "alloc")]
+    Heap(Vec<u8>),
+}
 
 impl ManagedBuffer {
+    #[cfg(feature = "alloc")]
+    pub fn new_heap() -> Self {
+        ManagedBuffer::Heap(Vec::new())
+    }
+
     pub fn append_message(&mut self, bytes: &[u8]) -> Option<usize> {
-        let used = self.0;
-        let mut writer = Writer::init(&mut self.1[used..]);
-        let write_len = writer.extend_from_slice(bytes)?;
-        self.0 = used + write_len;
-        Some(writer.used())
+        match self {
+            ManagedBuffer::Static(used, data) => {
+                let mut writer = Writer::init(&mut data[*used..]);
+                let write_len = writer.extend_from_slice(bytes)?;
+                *used += write_len;
+                Some(writer.used())
+            }
+            #[cfg(feature = "alloc")]
+            ManagedBuffer::Heap(data) => {
+                // grown here, as a reallocation by the Vec leaves the old
+                // transcript unwiped
+                if data.capacity() - data.len() < bytes.len() {
+                    let needed = data.len().checked_add(bytes.len())?;
+                    let mut grown = Vec::with_capacity(core::cmp::max(2 * data.capacity(), needed));
+                    grown.extend_from_slice(data);
+                    zeroize(data.as_mut_slice());
+                    *data = grown;
+                }
+                data.extend_from_slice(bytes);
+                Some(bytes.len())
+            }
+        }
     }
     pub fn reset_message(&mut self) {
-        self.0 = 0;
+        match self {
+            ManagedBuffer::Static(used, data) => {
+                zeroize(&mut data[..*used]);
+                *used = 0;
+            }
+            #[cfg(feature = "alloc")]
+            ManagedBuffer::Heap(data) => {
+                zeroize(data.as_mut_slice());
+                data.clear();
+            }
+        }
+    }
+}
+
+// The session transcripts hold the FINISH verify data.
+impl Drop for ManagedBuffer {
+    fn drop(&mut self) {
+        self.reset_message();
+    }
+}
+
+/// Overwrite the secret `data` with zeros. With the zeroize feature the
+/// writes can't be optimized away, otherwise the wipe is best effort.
+pub fn zeroize(data: &mut [u8]) {
+    #[cfg(feature = "zeroize")]
+    ::zeroize::Zeroize::zeroize(data);
+
+    #[cfg(not(feature = "zeroize"))]
+    {
+        for byte in data.iter_mut() {
+            *byte = 0;
+        }
+        core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+/// Whether `a` and `b` hold the same bytes, in a time that only depends on
+/// their length, for comparing MACs and digests of secrets. Slices of a
+/// different length are never equal.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
     }
+    a.iter()
+        .zip(b.iter())
+        .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+        == 0
+}
+
+#[test]
+fn test_constant_time_eq() {
+    assert!(constant_time_eq(&[], &[]));
+    assert!(constant_time_eq(&[0x5a; 48], &[0x5a; 48]));
+    let mut other = [0x5a; 48];
+    other[47] = 0x5b;
+    assert!(!constant_time_eq(&[0x5a; 48], &other));
+    other[47] = 0x5a;
+    other[0] = 0;
+    assert!(!constant_time_eq(&[0x5a; 48], &other));
+
+    // a truncated or extended MAC
+    assert!(!constant_time_eq(&[0x5a; 48], &[0x5a; 32]));
+    assert!(!constant_time_eq(&[0x5a; 32], &[0x5a; 48]));
+    assert!(!constant_time_eq(&[0x5a; 48], &[]));
 }
 
 impl AsRef<[u8]> for ManagedBuffer {
     fn as_ref(&self) -> &[u8] {
-        &self.1[0..(self.0 as usize)]
+        match self {
+            ManagedBuffer::Static(used, data) => &data[0..*used],
+            #[cfg(feature = "alloc")]
+            ManagedBuffer::Heap(data) => data.as_slice(),
+        }
     }
 }
 
 impl Default for ManagedBuffer {
     fn default() -> Self {
-        ManagedBuffer(0usize, [0u8; config::MAX_SPDM_MESSAGE_BUFFER_SIZE])
+        ManagedBuffer::Static(0usize, [0u8; config::MAX_SPDM_MESSAGE_BUFFER_SIZE])
+    }
+}
+
+/// Digest in progress of a TH transcript, see
+/// `SpdmConfigInfo::running_transcript_hash`.
+pub struct SpdmRunningHash {
+    crypto_provider: SpdmCryptoProvider,
+    base_hash_algo: SpdmBaseHashAlgo,
+    ctx: SpdmHashCtx,
+}
+
+impl SpdmRunningHash {
+    fn new(
+        crypto_provider: SpdmCryptoProvider,
+        base_hash_algo: SpdmBaseHashAlgo,
+    ) -> SpdmResult<Self> {
+        let ctx = crypto_provider
+            .hash_ctx_init(base_hash_algo)
+            .ok_or_else(|| spdm_err!(EFAULT, "no incremental hash"))?;
+        Ok(SpdmRunningHash {
+            crypto_provider,
+            base_hash_algo,
+            ctx,
+        })
+    }
+
+    fn update(&mut self, data: &[u8]) -> SpdmResult {
+        self.crypto_provider.hash_ctx_update(&mut self.ctx, data)
+    }
+
+    fn try_clone(&self) -> SpdmResult<Self> {
+        let ctx = self
+            .crypto_provider
+            .hash_ctx_dup(&self.ctx)
+            .ok_or_else(|| spdm_err!(EFAULT))?;
+        Ok(SpdmRunningHash {
+            crypto_provider: self.crypto_provider,
+            base_hash_algo: self.base_hash_algo,
+            ctx,
+        })
+    }
+
+    // the digest of the data so far, this one can still be fed
+    fn digest(&self) -> SpdmResult<SpdmDigestStruct> {
+        let ctx = self.try_clone()?.ctx;
+        self.crypto_provider
+            .hash_ctx_finalize(self.base_hash_algo, ctx)
+            .ok_or_else(|| spdm_err!(EFAULT))
+    }
+}
+
+impl core::fmt::Debug for SpdmRunningHash {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("SpdmRunningHash")
+            .field("base_hash_algo", &self.base_hash_algo)
+            .finish()
+    }
+}
+
+/// message_k or message_f of a session, see `SpdmContext::new_message_k()`.
+/// The messages are kept for the HMAC and signature data even when their
+/// TH digest is fed as they are appended.
+#[derive(Debug, Default)]
+pub struct SpdmTranscript {
+    message: ManagedBuffer,
+    running_hash: Option<SpdmRunningHash>,
+}
+
+impl SpdmTranscript {
+    pub fn append_message(&mut self, bytes: &[u8]) -> Option<usize> {
+        if let Some(running_hash) = &mut self.running_hash {
+            running_hash.update(bytes).ok()?;
+        }
+        self.message.append_message(bytes)
+    }
+
+    pub fn try_clone(&self) -> SpdmResult<Self> {
+        let running_hash = match &self.running_hash {
+            Some(running_hash) => Some(running_hash.try_clone()?),
+            None => None,
+        };
+        Ok(SpdmTranscript {
+            message: self.message.clone(),
+            running_hash,
+        })
+    }
+}
+
+impl AsRef<[u8]> for SpdmTranscript {
+    fn as_ref(&self) -> &[u8] {
+        self.message.as_ref()
+    }
+}
+
+bitflags! {
+    /// Exchanges recorded in the M1/M2 transcript that the requester caches
+    /// data from.
+    #[derive(Default)]
+    pub struct SpdmTranscriptCoverage: u8 {
+        /// GET_VERSION, GET_CAPABILITIES and NEGOTIATE_ALGORITHMS in message_a.
+        const VCA = 0b0000_0001;
+        /// GET_DIGESTS in message_b.
+        const DIGESTS = 0b0000_0010;
+        /// The complete peer cert chain retrieved with GET_CERTIFICATE in message_b.
+        const CERTIFICATE = 0b0000_0100;
     }
 }
 
-#[derive(Debug, Copy, Clone, Default)]
+#[derive(Debug, Clone, Default)]
 pub struct SpdmRuntimeInfo {
     pub need_measurement_summary_hash: bool,
     pub need_measurement_signature: bool,
@@ -450,18 +2185,224 @@ pub struct SpdmRuntimeInfo {
     pub message_b: ManagedBuffer,
     pub message_c: ManagedBuffer,
     pub message_m: ManagedBuffer,
+    // exchanges recorded in message_a and message_b
+    pub transcript_coverage: SpdmTranscriptCoverage,
+    // exchanges covered by the last verified CHALLENGE_AUTH signature
+    pub verified_coverage: SpdmTranscriptCoverage,
+    // provisioned slot whose cert chain and key are used for the signatures
+    pub my_cert_chain_slot_id: u8,
+    // requester nonces of the last signed CHALLENGE_AUTH and MEASUREMENTS, a
+    // repeated nonce would have the same transcript signed twice
+    pub last_challenge_nonce: Option<SpdmNonceStruct>,
+    pub last_measurement_nonce: Option<SpdmNonceStruct>,
+    // my_cert_chain_data with the SPDM cert chain header and the root hash
+    // of the negotiated algorithm, kept out of the provision info so it can
+    // be in ROM
+    pub my_cert_chain: [Option<SpdmCertChainData>; SPDM_MAX_SLOT_NUMBER],
+    // digests of the last DIGESTS, kept by reset_runtime_info()
+    pub peer_digests: Option<SpdmPeerDigests>,
 }
 
-#[derive(Default)]
+/// Digests of the peer cert chains reported in DIGESTS, kept across
+/// connections to notice a changed cert chain.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SpdmPeerDigests {
+    pub base_hash_algo: SpdmBaseHashAlgo,
+    /// Slots holding a cert chain.
+    pub slot_mask: u8,
+    /// Indexed by slot, only the ones in `slot_mask` are set.
+    pub digests: [SpdmDigestStruct; SPDM_MAX_SLOT_NUMBER],
+}
+
+impl SpdmPeerDigests {
+    pub fn get_digest(&self, slot_id: u8) -> Option<&SpdmDigestStruct> {
+        if slot_id as usize >= SPDM_MAX_SLOT_NUMBER || self.slot_mask & (1 << slot_id) == 0 {
+            return None;
+        }
+        Some(&self.digests[slot_id as usize])
+    }
+}
+
+/// Application checks on a peer cert chain that passed the verification,
+/// e.g. pinning the root or checking the EKU of the leaf certificate.
+#[derive(Clone, Copy)]
+pub struct SpdmCertVerificationPolicy {
+    /// `cert_chain` holds the DER certificates, without the SPDM cert chain header.
+    pub verify_cb: fn(slot_id: u8, cert_chain: &[u8], leaf_cert: &[u8]) -> SpdmResult,
+}
+
+/// How far the connection is set up. Requests are
+/// only sent and answered in the state the spec allows them in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SpdmConnectionState {
+    NotStarted,
+    AfterVersion,
+    AfterCapabilities,
+    Negotiated,
+    AfterDigests,
+    AfterCertificate,
+    Authenticated,
+}
+
+/// What a responder signature is made for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpdmSigningPurpose {
+    Challenge,
+    Measurement,
+    KeyExchange,
+}
+
+/// CoRIM style identifier of a measurement block, the environment and
+/// measurement key reference values from supply-chain manifests are
+/// matched on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SpdmMeasurementIdentifier<'a> {
+    /// Class of the measured component, e.g. a vendor and model UUID.
+    pub class_id: Option<&'a [u8]>,
+    /// Instance of the measured component, e.g. a UEID or serial number.
+    pub instance_id: Option<&'a [u8]>,
+    /// Key of the measurement within the environment. `None` if the block
+    /// index is the key.
+    pub measurement_key: Option<&'a [u8]>,
+}
+
+/// The slot whose key signs for each purpose, for devices that e.g. sign
+/// measurements with a different key than the identity cert. `None` signs
+/// with whichever provisioned slot the requester asks for.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SpdmSigningSlotIds {
+    pub challenge: Option<u8>,
+    pub measurement: Option<u8>,
+    pub key_exchange: Option<u8>,
+}
+
+impl SpdmSigningSlotIds {
+    pub fn get(&self, purpose: SpdmSigningPurpose) -> Option<u8> {
+        match purpose {
+            SpdmSigningPurpose::Challenge => self.challenge,
+            SpdmSigningPurpose::Measurement => self.measurement,
+            SpdmSigningPurpose::KeyExchange => self.key_exchange,
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct SpdmProvisionInfo {
-    pub my_cert_chain_data: Option<SpdmCertChainData>,
-    pub my_cert_chain: Option<SpdmCertChainData>, // use SpdmCertChainData instead of SpdmCertChain for easy command sending.
+    // one cert chain per slot, slots without a chain are None
+    pub my_cert_chain_data: [Option<SpdmCertChainData>; SPDM_MAX_SLOT_NUMBER],
+    // responder only, DER root certificate the cert chains installed with
+    // provision_complete() have to start with
+    pub my_root_cert: Option<&'static [u8]>,
     // TBD: union peer. But it is still option.
     pub peer_cert_chain_data: Option<SpdmCertChainData>,
     pub peer_cert_chain_root_hash: Option<SpdmDigestStruct>,
+    // DER root certificates the peer cert chain has to start with one of
+    pub peer_root_cert_store: Option<&'static [&'static [u8]]>,
+    pub cert_verification_policy: Option<SpdmCertVerificationPolicy>,
+    // responder only, requests naming another slot are rejected
+    pub signing_slot_ids: SpdmSigningSlotIds,
+}
+
+impl SpdmProvisionInfo {
+    /// Nothing provisioned, fill in the rest with `..SpdmProvisionInfo::new()`.
+    pub const fn new() -> SpdmProvisionInfo {
+        SpdmProvisionInfo {
+            my_cert_chain_data: [None; SPDM_MAX_SLOT_NUMBER],
+            my_root_cert: None,
+            peer_cert_chain_data: None,
+            peer_cert_chain_root_hash: None,
+            peer_root_cert_store: None,
+            cert_verification_policy: None,
+            signing_slot_ids: SpdmSigningSlotIds {
+                challenge: None,
+                measurement: None,
+                key_exchange: None,
+            },
+        }
+    }
+}
+
+impl Default for SpdmProvisionInfo {
+    fn default() -> SpdmProvisionInfo {
+        SpdmProvisionInfo::new()
+    }
 }
 
 #[derive(Default)]
 pub struct SpdmPeerInfo {
     pub peer_cert_chain: SpdmCertChain,
+    pub peer_cert_chain_slot_id: u8,
+    // verified cert chains retrieved with GET_CERTIFICATE, one per slot.
+    pub peer_cert_chains: [Option<SpdmCertChainData>; SPDM_MAX_SLOT_NUMBER],
+    // cert chain hash reported by the responder in CHALLENGE_AUTH, after it is verified.
+    pub peer_cert_chain_hash: [Option<SpdmDigestStruct>; SPDM_MAX_SLOT_NUMBER],
+    // latest measurement block received in MEASUREMENTS, one per index.
+    pub peer_measurements: SpdmMeasurementRecordStructure,
+    // whether a verified MEASUREMENTS signature covers the block in peer_measurements.
+    pub peer_measurements_signed: [bool; config::MAX_SPDM_MEASUREMENT_BLOCK_COUNT],
+    // measurement generation reported in the latest MEASUREMENTS, blocks of
+    // an older generation are dropped from peer_measurements.
+    pub peer_measurement_generation: Option<u32>,
+    // PSK derived from the last KEY_EXCHANGE session with the peer, for
+    // resuming sessions with PSK_EXCHANGE.
+    pub resumption_psk: Option<SpdmResumptionPsk>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::SpdmErrorNum;
+    use crate::testlib::{NullDeviceIo, PlainTransportEncap};
+
+    #[test]
+    fn test_signing_context() {
+        let mut device_io = NullDeviceIo;
+        let mut transport_encap = PlainTransportEncap;
+        let mut context = SpdmContext::new(
+            &mut device_io,
+            &mut transport_encap,
+            SpdmConfigInfo::default(),
+            SpdmProvisionInfo::default(),
+        );
+        context.negotiate_info.base_hash_sel = SpdmBaseHashAlgo::TPM_ALG_SHA_384;
+        let mut message = ManagedBuffer::default();
+        message.append_message(&[0x11, 0x03, 0x00, 0x00]).unwrap();
+
+        // SPDM 1.1 signs the messages themselves
+        context.negotiate_info.spdm_version_sel = SpdmVersion::SpdmVersion11;
+        let signing_data = context
+            .create_signing_data(SPDM_CHALLENGE_AUTH_SIGN_CONTEXT, message.clone())
+            .unwrap();
+        assert_eq!(signing_data.as_ref(), message.as_ref());
+
+        // SPDM 1.2 signs the prefix, the zero padded context and the message hash
+        context.negotiate_info.spdm_version_sel = SpdmVersion::SpdmVersion12;
+        let signing_data = context
+            .create_signing_data(SPDM_CHALLENGE_AUTH_SIGN_CONTEXT, message.clone())
+            .unwrap();
+        let signing_data = signing_data.as_ref();
+        assert_eq!(signing_data.len(), 100 + 48);
+        for prefix in signing_data[..64].chunks(16) {
+            assert_eq!(prefix, b"dmtf-spdm-v1.2.*");
+        }
+        let context_start = 100 - SPDM_CHALLENGE_AUTH_SIGN_CONTEXT.len();
+        assert!(signing_data[64..context_start].iter().all(|b| *b == 0));
+        assert_eq!(
+            &signing_data[context_start..100],
+            SPDM_CHALLENGE_AUTH_SIGN_CONTEXT
+        );
+        let message_hash =
+            crate::crypto::hash::hash_all(SpdmBaseHashAlgo::TPM_ALG_SHA_384, message.as_ref())
+                .unwrap();
+        assert_eq!(&signing_data[100..], message_hash.as_ref());
+
+        // a context longer than the 36 bytes after the prefix
+        assert_eq!(
+            context
+                .create_signing_data(&[0x5a; 37], message)
+                .unwrap_err()
+                .num(),
+            SpdmErrorNum::EINVAL
+        );
+    }
 }
@@ -6,15 +6,40 @@ use crate::error::SpdmResult;
 
 extern crate alloc;
 use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::any::Any;
 
 use crate::msgs::{
     SpdmAeadAlgo, SpdmBaseAsymAlgo, SpdmBaseHashAlgo, SpdmDheAlgo, SpdmDheExchangeStruct,
-    SpdmDheFinalKeyStruct, SpdmDigestStruct, SpdmSignatureStruct,
+    SpdmDheFinalKeyStruct, SpdmDigestStruct, SpdmExtAlgStruct, SpdmPskContextStruct,
+    SpdmSignatureStruct, SpdmVersion,
 };
 
+/// State of a digest computed over data passed in pieces, owned by the
+/// `SpdmHash` callbacks that created it.
+pub type SpdmHashCtx = Box<dyn Any>;
+
 #[derive(Clone, Copy)]
 pub struct SpdmHash {
     pub hash_all_cb: fn(base_hash_algo: SpdmBaseHashAlgo, data: &[u8]) -> Option<SpdmDigestStruct>,
+
+    /// Start a digest that is fed with `hash_ctx_update_cb` and completed
+    /// with `hash_ctx_finalize_cb`, so the data never has to be buffered.
+    pub hash_ctx_init_cb: fn(base_hash_algo: SpdmBaseHashAlgo) -> Option<SpdmHashCtx>,
+
+    pub hash_ctx_update_cb: fn(ctx: &mut SpdmHashCtx, data: &[u8]) -> SpdmResult,
+
+    /// Copy of a digest in progress, for the digest of the data so far
+    /// while the original one is fed on.
+    pub hash_ctx_dup_cb: fn(ctx: &SpdmHashCtx) -> Option<SpdmHashCtx>,
+
+    pub hash_ctx_finalize_cb: fn(ctx: SpdmHashCtx) -> Option<SpdmDigestStruct>,
+}
+
+#[derive(Clone, Copy)]
+pub struct SpdmRandom {
+    /// Fill `data` with random bytes, returns the number of bytes written.
+    pub get_random_cb: fn(data: &mut [u8]) -> SpdmResult<usize>,
 }
 
 #[derive(Clone, Copy)]
@@ -52,6 +77,10 @@ type DecryptCb = fn(
 
 #[derive(Clone, Copy)]
 pub struct SpdmAead {
+    /// Algorithms the callbacks can encrypt with, no other AEAD is
+    /// negotiated.
+    pub supported_algo: SpdmAeadAlgo,
+
     pub encrypt_cb: EncryptCb,
 
     pub decrypt_cb: DecryptCb,
@@ -59,7 +88,34 @@ pub struct SpdmAead {
 
 #[derive(Clone, Copy)]
 pub struct SpdmAsymSign {
+    /// Sign with the private key of the cert chain provisioned in `slot_id`.
     pub sign_cb: fn(
+        slot_id: u8,
+        base_hash_algo: SpdmBaseHashAlgo,
+        base_asym_algo: SpdmBaseAsymAlgo,
+        data: &[u8],
+    ) -> Option<SpdmSignatureStruct>,
+}
+
+/// Private key the built-in `SpdmAsymSign` signs with for a slot.
+#[derive(Debug, Clone, Copy)]
+pub enum SpdmSigningKey {
+    /// Key in memory, PKCS#8 for ECDSA and a DER RSAPrivateKey for RSA.
+    Der(&'static [u8]),
+    /// Key that never leaves an HSM or a TEE, e.g. the number of its
+    /// hardware key slot, signed with by `sign_external_cb`.
+    External(u64),
+}
+
+/// Where the built-in `SpdmAsymSign` finds the signing keys.
+#[derive(Clone, Copy)]
+pub struct SpdmSigningKeyStore {
+    /// The key of the cert chain provisioned in `slot_id`.
+    pub get_signing_key_cb: fn(slot_id: u8) -> Option<SpdmSigningKey>,
+
+    /// Sign with the `SpdmSigningKey::External` key `handle`.
+    pub sign_external_cb: fn(
+        handle: u64,
         base_hash_algo: SpdmBaseHashAlgo,
         base_asym_algo: SpdmBaseAsymAlgo,
         data: &[u8],
@@ -89,11 +145,119 @@ pub struct SpdmHkdf {
 
 type GetCertFromCertChainCb = fn(cert_chain: &[u8], index: isize) -> SpdmResult<(usize, usize)>;
 
+/// Answer of a CRL, OCSP or denylist lookup.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpdmRevocationStatus {
+    Good,
+    Revoked,
+    /// No answer, e.g. the OCSP responder can't be reached. Fails the
+    /// verification only if `SpdmCertChainConfig::revocation_hard_fail`.
+    Unknown,
+}
+
+type CheckRevocationCb = fn(cert: &[u8], issuer: &[u8]) -> SpdmRevocationStatus;
+
+/// Fields of a DER certificate for policy checks, as slices of the cert.
+/// OIDs are the DER value of the OBJECT IDENTIFIER, without tag and length.
+#[derive(Debug, Clone)]
+pub struct SpdmCertInfo<'a> {
+    /// Value of the serialNumber INTEGER.
+    pub serial_number: &'a [u8],
+    /// DER Name, including its SEQUENCE tag and length.
+    pub issuer: &'a [u8],
+    /// DER Name, including its SEQUENCE tag and length.
+    pub subject: &'a [u8],
+    /// Algorithm of the subjectPublicKeyInfo, e.g. id-ecPublicKey.
+    pub public_key_algorithm: &'a [u8],
+    /// DER parameters of the public key algorithm, e.g. the OBJECT
+    /// IDENTIFIER of the named curve. Empty if absent.
+    pub public_key_parameters: &'a [u8],
+    pub extensions: Vec<SpdmCertExtension<'a>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpdmCertExtension<'a> {
+    pub oid: &'a [u8],
+    pub critical: bool,
+    /// Content of the extnValue OCTET STRING, the DER of the extension.
+    pub value: &'a [u8],
+}
+
+impl<'a> SpdmCertInfo<'a> {
+    pub fn get_extension(&self, oid: &[u8]) -> Option<&SpdmCertExtension<'a>> {
+        self.extensions
+            .iter()
+            .find(|extension| extension.oid == oid)
+    }
+}
+
+type VerifyCertChainCb = fn(cert_chain: &[u8], config: &SpdmCertChainConfig) -> SpdmResult;
+
+type ParseCertInfoCb = fn(cert: &[u8]) -> SpdmResult<SpdmCertInfo<'_>>;
+
 #[derive(Clone, Copy)]
 pub struct SpdmCertOperation {
     pub get_cert_from_cert_chain_cb: GetCertFromCertChainCb,
 
-    pub verify_cert_chain_cb: fn(cert_chain: &[u8]) -> SpdmResult,
+    pub verify_cert_chain_cb: VerifyCertChainCb,
+
+    /// Parse one DER certificate, e.g. the leaf of a verified chain.
+    pub parse_cert_info_cb: ParseCertInfoCb,
+
+    /// Revocation status of each cert but the root after the chain is
+    /// verified. `issuer` is the cert before `cert` in the chain.
+    pub check_revocation_cb: Option<CheckRevocationCb>,
+}
+
+/// What `verify_cert_chain_cb` checks besides the signatures, the CA basic
+/// constraints of the root and intermediates and the validity periods, set
+/// per context in `SpdmConfigInfo::cert_chain_config`.
+///
+/// The validity is checked against `get_unix_time_cb`, or
+/// `time::get_unix_time()` without it. Without std there is no clock by
+/// default, so the verification fails with "No clock" until one of them is
+/// provided.
+#[derive(Debug, Clone, Copy)]
+pub struct SpdmCertChainConfig {
+    /// DER value of the extended key usage OID the leaf needs, if it has
+    /// the extension.
+    pub leaf_eku: &'static [u8],
+    /// The leaf key usage has to include digitalSignature. A leaf without
+    /// the extension may be used for anything, as in RFC 5280.
+    pub leaf_digital_signature: bool,
+    /// Maximum number of certificates, root and leaf included.
+    pub max_chain_depth: usize,
+    /// Reject certs whose revocation status is unknown.
+    pub revocation_hard_fail: bool,
+    /// Wall clock in seconds since the UNIX epoch, overriding the one of
+    /// the registered `SpdmTime`.
+    pub get_unix_time_cb: Option<fn() -> Option<u64>>,
+}
+
+impl SpdmCertChainConfig {
+    pub const fn new() -> SpdmCertChainConfig {
+        SpdmCertChainConfig {
+            // id-kp-serverAuth
+            leaf_eku: &[40 + 3, 6, 1, 5, 5, 7, 3, 1],
+            leaf_digital_signature: true,
+            max_chain_depth: 8,
+            revocation_hard_fail: false,
+            get_unix_time_cb: None,
+        }
+    }
+
+    pub fn get_unix_time(&self) -> Option<u64> {
+        match self.get_unix_time_cb {
+            Some(get_unix_time_cb) => get_unix_time_cb(),
+            None => crate::time::get_unix_time(),
+        }
+    }
+}
+
+impl Default for SpdmCertChainConfig {
+    fn default() -> SpdmCertChainConfig {
+        SpdmCertChainConfig::new()
+    }
 }
 
 type GenerateKeyPairCb =
@@ -101,6 +265,9 @@ type GenerateKeyPairCb =
 
 #[derive(Clone, Copy)]
 pub struct SpdmDhe {
+    /// Groups `generate_key_pair_cb` implements, the others aren't
+    /// negotiated.
+    pub supported_algo: SpdmDheAlgo,
     pub generate_key_pair_cb: GenerateKeyPairCb,
 }
 
@@ -110,3 +277,139 @@ pub trait SpdmDheKeyExchange {
         peer_pub_key: &SpdmDheExchangeStruct,
     ) -> Option<SpdmDheFinalKeyStruct>;
 }
+
+/// Opaque handle of the secrets of one session retained by an
+/// `SpdmExternalKeySchedule`.
+pub type SpdmKeyHandle = u64;
+
+/// Session secret an `SpdmExternalKeySchedule` operation uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpdmSessionKeyId {
+    RequestHandshake,
+    ResponseHandshake,
+    RequestFinished,
+    ResponseFinished,
+    RequestData,
+    ResponseData,
+    /// Request data key replaced by a key update that is not verified yet.
+    PreviousRequestData,
+    /// Response data key replaced by a key update that is not verified yet.
+    PreviousResponseData,
+}
+
+type ImportSharedSecretCb = fn(
+    spdm_version: SpdmVersion,
+    base_hash_algo: SpdmBaseHashAlgo,
+    aead_algo: SpdmAeadAlgo,
+    shared_secret: &[u8],
+) -> Option<SpdmKeyHandle>;
+
+type KeyEncryptCb = fn(
+    handle: SpdmKeyHandle,
+    key_id: SpdmSessionKeyId,
+    sequence_number: u64,
+    aad: &[u8],
+    plain_text: &[u8],
+    tag: &mut [u8],
+    cipher_text: &mut [u8],
+) -> SpdmResult<(usize, usize)>;
+
+type KeyDecryptCb = fn(
+    handle: SpdmKeyHandle,
+    key_id: SpdmSessionKeyId,
+    sequence_number: u64,
+    aad: &[u8],
+    cipher_text: &[u8],
+    tag: &[u8],
+    plain_text: &mut [u8],
+) -> SpdmResult<usize>;
+
+/// Key schedule backend that derives and retains the session secrets
+/// outside of the library, e.g. in an HSM. The library only holds a handle
+/// and never sees the handshake secrets, finished keys or AEAD keys.
+#[derive(Clone, Copy)]
+pub struct SpdmExternalKeySchedule {
+    /// Take the DHE secret or the PSK and derive the handshake and master
+    /// secret from it, as the SPDM key schedule of `spdm_version` does.
+    pub import_shared_secret_cb: ImportSharedSecretCb,
+
+    /// Derive the handshake secrets, the finished keys and the handshake
+    /// AEAD keys and IVs from TH1.
+    pub derive_handshake_keys_cb: fn(handle: SpdmKeyHandle, th1: &[u8]) -> SpdmResult,
+
+    /// Derive the data secrets and the data AEAD keys and IVs from TH2.
+    pub derive_data_keys_cb: fn(handle: SpdmKeyHandle, th2: &[u8]) -> SpdmResult,
+
+    /// Update `RequestData` or `ResponseData`, retaining the current key
+    /// as `PreviousRequestData` or `PreviousResponseData`.
+    pub update_data_key_cb: fn(handle: SpdmKeyHandle, key_id: SpdmSessionKeyId) -> SpdmResult,
+
+    /// Complete the update of `RequestData` or `ResponseData`: drop the
+    /// previous key, after restoring it if `use_new_key` is false.
+    pub activate_data_key_update_cb:
+        fn(handle: SpdmKeyHandle, key_id: SpdmSessionKeyId, use_new_key: bool) -> SpdmResult,
+
+    /// HMAC with `RequestFinished` or `ResponseFinished`.
+    pub hmac_cb: fn(
+        handle: SpdmKeyHandle,
+        key_id: SpdmSessionKeyId,
+        data: &[u8],
+    ) -> Option<SpdmDigestStruct>,
+
+    /// AEAD with a handshake or data key, the IV is the salt of that key
+    /// XORed with `sequence_number`.
+    pub encrypt_cb: KeyEncryptCb,
+
+    pub decrypt_cb: KeyDecryptCb,
+
+    /// Wipe all secrets of the session.
+    pub release_cb: fn(handle: SpdmKeyHandle),
+}
+
+type PskLookupCb =
+    fn(psk_hint: &[u8]) -> Option<(SpdmDheFinalKeyStruct, Option<SpdmPskContextStruct>)>;
+
+/// PSK storage of a responder, e.g. a secure element.
+#[derive(Clone, Copy)]
+pub struct SpdmPsk {
+    /// The PSK identified by `psk_hint`, and the responder context to send in
+    /// PSK_EXCHANGE_RSP if the storage provides one. None if the hint is unknown.
+    pub psk_lookup_cb: PskLookupCb,
+}
+
+/// Crypto of the extended algorithms of NEGOTIATE_ALGORITHMS (ExtAsym and
+/// ExtHash), e.g. SM2 and SM3, which have no bit in the base algorithm
+/// fields.
+#[derive(Clone, Copy)]
+pub struct SpdmExtAlgo {
+    /// Whether the callbacks implement `ext_alg`, no other extended
+    /// algorithm is negotiated.
+    pub is_supported_cb: fn(ext_alg: &SpdmExtAlgStruct) -> bool,
+
+    pub hash_all_cb: fn(ext_hash: &SpdmExtAlgStruct, data: &[u8]) -> Option<SpdmDigestStruct>,
+
+    /// Sign with the private key of the cert chain provisioned in `slot_id`.
+    pub sign_cb: fn(
+        slot_id: u8,
+        ext_hash: &SpdmExtAlgStruct,
+        ext_asym: &SpdmExtAlgStruct,
+        data: &[u8],
+    ) -> Option<SpdmSignatureStruct>,
+
+    pub verify_cb: fn(
+        ext_hash: &SpdmExtAlgStruct,
+        ext_asym: &SpdmExtAlgStruct,
+        public_cert_der: &[u8],
+        data: &[u8],
+        signature: &SpdmSignatureStruct,
+    ) -> SpdmResult,
+}
+
+/// Known answer tests of the crypto backends, e.g. the self-tests FIPS 140-3
+/// requires before an approved algorithm is first used.
+#[derive(Clone, Copy)]
+pub struct SpdmCryptoSelfTest {
+    /// Run the KATs of every registered backend, an error leaves the crypto
+    /// unusable.
+    pub run_kat_cb: fn() -> SpdmResult,
+}
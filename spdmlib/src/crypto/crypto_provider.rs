@@ -0,0 +1,360 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+use crate::error::SpdmResult;
+
+extern crate alloc;
+use alloc::boxed::Box;
+
+use super::{
+    aead, asym_sign, asym_verify, cert_operation, dhe, ext_algo, external_key_schedule, hash, hkdf,
+    hmac, psk, rand, self_test, SpdmAead, SpdmAsymSign, SpdmAsymVerify, SpdmCertChainConfig,
+    SpdmCertOperation, SpdmDhe, SpdmDheKeyExchange, SpdmExtAlgo, SpdmExternalKeySchedule, SpdmHash,
+    SpdmHashCtx, SpdmHkdf, SpdmHmac, SpdmPsk, SpdmRandom,
+};
+use crate::msgs::{
+    SpdmAeadAlgo, SpdmBaseAsymAlgo, SpdmBaseHashAlgo, SpdmDheAlgo, SpdmDheExchangeStruct,
+    SpdmDheFinalKeyStruct, SpdmDigestStruct, SpdmExtAlgStruct, SpdmPskContextStruct,
+    SpdmSignatureStruct,
+};
+use core::fmt::Debug;
+
+/// Per-context crypto callbacks.
+///
+/// Each callback set that is `Some` overrides the globally registered one
+/// for the owning context only. Unset entries fall back to the global
+/// registry, so a default provider behaves exactly like the global functions.
+/// The crypto operations are gated by the global `self_test` KATs.
+#[derive(Clone, Copy, Default)]
+pub struct SpdmCryptoProvider {
+    pub hash: Option<SpdmHash>,
+    pub hmac: Option<SpdmHmac>,
+    pub aead: Option<SpdmAead>,
+    pub asym_sign: Option<SpdmAsymSign>,
+    pub asym_verify: Option<SpdmAsymVerify>,
+    pub dhe: Option<SpdmDhe>,
+    pub cert_operation: Option<SpdmCertOperation>,
+    pub hkdf: Option<SpdmHkdf>,
+    pub external_key_schedule: Option<SpdmExternalKeySchedule>,
+    pub psk: Option<SpdmPsk>,
+    pub rand: Option<SpdmRandom>,
+    pub ext_algo: Option<SpdmExtAlgo>,
+}
+
+impl Debug for SpdmCryptoProvider {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("SpdmCryptoProvider")
+            .field("hash", &self.hash.is_some())
+            .field("hmac", &self.hmac.is_some())
+            .field("aead", &self.aead.is_some())
+            .field("asym_sign", &self.asym_sign.is_some())
+            .field("asym_verify", &self.asym_verify.is_some())
+            .field("dhe", &self.dhe.is_some())
+            .field("cert_operation", &self.cert_operation.is_some())
+            .field("hkdf", &self.hkdf.is_some())
+            .field(
+                "external_key_schedule",
+                &self.external_key_schedule.is_some(),
+            )
+            .field("psk", &self.psk.is_some())
+            .field("rand", &self.rand.is_some())
+            .field("ext_algo", &self.ext_algo.is_some())
+            .finish()
+    }
+}
+
+// The digests produced and verified are checked against the size of the hash
+// algorithm, so a callback can't hand out a digest_size that later copies
+// trust.
+impl SpdmCryptoProvider {
+    pub fn hash_all(
+        &self,
+        base_hash_algo: SpdmBaseHashAlgo,
+        data: &[u8],
+    ) -> Option<SpdmDigestStruct> {
+        self_test::check().ok()?;
+        let digest = match self.hash {
+            Some(cb) => (cb.hash_all_cb)(base_hash_algo, data),
+            None => hash::hash_all(base_hash_algo, data),
+        }?;
+        digest.check_size(base_hash_algo).ok()?;
+        Some(digest)
+    }
+
+    /// Start a digest fed with hash_ctx_update(). The same provider has to
+    /// update and finalize it, as only the callbacks creating it know it.
+    pub fn hash_ctx_init(&self, base_hash_algo: SpdmBaseHashAlgo) -> Option<SpdmHashCtx> {
+        self_test::check().ok()?;
+        match self.hash {
+            Some(cb) => (cb.hash_ctx_init_cb)(base_hash_algo),
+            None => hash::hash_ctx_init(base_hash_algo),
+        }
+    }
+
+    pub fn hash_ctx_update(&self, ctx: &mut SpdmHashCtx, data: &[u8]) -> SpdmResult {
+        self_test::check()?;
+        match self.hash {
+            Some(cb) => (cb.hash_ctx_update_cb)(ctx, data),
+            None => hash::hash_ctx_update(ctx, data),
+        }
+    }
+
+    pub fn hash_ctx_dup(&self, ctx: &SpdmHashCtx) -> Option<SpdmHashCtx> {
+        self_test::check().ok()?;
+        match self.hash {
+            Some(cb) => (cb.hash_ctx_dup_cb)(ctx),
+            None => hash::hash_ctx_dup(ctx),
+        }
+    }
+
+    /// `base_hash_algo` is the one the digest was started with.
+    pub fn hash_ctx_finalize(
+        &self,
+        base_hash_algo: SpdmBaseHashAlgo,
+        ctx: SpdmHashCtx,
+    ) -> Option<SpdmDigestStruct> {
+        self_test::check().ok()?;
+        let digest = match self.hash {
+            Some(cb) => (cb.hash_ctx_finalize_cb)(ctx),
+            None => hash::hash_ctx_finalize(ctx),
+        }?;
+        digest.check_size(base_hash_algo).ok()?;
+        Some(digest)
+    }
+
+    pub fn hmac(
+        &self,
+        base_hash_algo: SpdmBaseHashAlgo,
+        key: &[u8],
+        data: &[u8],
+    ) -> Option<SpdmDigestStruct> {
+        self_test::check().ok()?;
+        let hmac = match self.hmac {
+            Some(cb) => (cb.hmac_cb)(base_hash_algo, key, data),
+            None => hmac::hmac(base_hash_algo, key, data),
+        }?;
+        hmac.check_size(base_hash_algo).ok()?;
+        Some(hmac)
+    }
+
+    pub fn hmac_verify(
+        &self,
+        base_hash_algo: SpdmBaseHashAlgo,
+        key: &[u8],
+        data: &[u8],
+        hmac: &SpdmDigestStruct,
+    ) -> SpdmResult {
+        self_test::check()?;
+        hmac.check_size(base_hash_algo)?;
+        match self.hmac {
+            Some(cb) => (cb.hmac_verify_cb)(base_hash_algo, key, data, hmac),
+            None => hmac::hmac_verify(base_hash_algo, key, data, hmac),
+        }
+    }
+
+    pub fn aead_supported_algo(&self) -> SpdmAeadAlgo {
+        match self.aead {
+            Some(cb) => cb.supported_algo,
+            None => aead::supported_algo(),
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn aead_encrypt(
+        &self,
+        aead_algo: SpdmAeadAlgo,
+        key: &[u8],
+        iv: &[u8],
+        aad: &[u8],
+        plain_text: &[u8],
+        tag: &mut [u8],
+        cipher_text: &mut [u8],
+    ) -> SpdmResult<(usize, usize)> {
+        self_test::check()?;
+        match self.aead {
+            Some(cb) => (cb.encrypt_cb)(aead_algo, key, iv, aad, plain_text, tag, cipher_text),
+            None => aead::encrypt(aead_algo, key, iv, aad, plain_text, tag, cipher_text),
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn aead_decrypt(
+        &self,
+        aead_algo: SpdmAeadAlgo,
+        key: &[u8],
+        iv: &[u8],
+        aad: &[u8],
+        cipher_text: &[u8],
+        tag: &[u8],
+        plain_text: &mut [u8],
+    ) -> SpdmResult<usize> {
+        self_test::check()?;
+        match self.aead {
+            Some(cb) => (cb.decrypt_cb)(aead_algo, key, iv, aad, cipher_text, tag, plain_text),
+            None => aead::decrypt(aead_algo, key, iv, aad, cipher_text, tag, plain_text),
+        }
+    }
+
+    pub fn asym_sign(
+        &self,
+        slot_id: u8,
+        base_hash_algo: SpdmBaseHashAlgo,
+        base_asym_algo: SpdmBaseAsymAlgo,
+        data: &[u8],
+    ) -> Option<SpdmSignatureStruct> {
+        self_test::check().ok()?;
+        match self.asym_sign {
+            Some(cb) => (cb.sign_cb)(slot_id, base_hash_algo, base_asym_algo, data),
+            None => asym_sign::sign(slot_id, base_hash_algo, base_asym_algo, data),
+        }
+    }
+
+    pub fn asym_verify(
+        &self,
+        base_hash_algo: SpdmBaseHashAlgo,
+        base_asym_algo: SpdmBaseAsymAlgo,
+        public_cert_der: &[u8],
+        data: &[u8],
+        signature: &SpdmSignatureStruct,
+    ) -> SpdmResult {
+        self_test::check()?;
+        match self.asym_verify {
+            Some(cb) => (cb.verify_cb)(
+                base_hash_algo,
+                base_asym_algo,
+                public_cert_der,
+                data,
+                signature,
+            ),
+            None => asym_verify::verify(
+                base_hash_algo,
+                base_asym_algo,
+                public_cert_der,
+                data,
+                signature,
+            ),
+        }
+    }
+
+    pub fn dhe_supported_algo(&self) -> SpdmDheAlgo {
+        match self.dhe {
+            Some(cb) => cb.supported_algo,
+            None => dhe::supported_algo(),
+        }
+    }
+
+    pub fn dhe_generate_key_pair(
+        &self,
+        dhe_algo: SpdmDheAlgo,
+    ) -> Option<(SpdmDheExchangeStruct, Box<dyn SpdmDheKeyExchange>)> {
+        self_test::check().ok()?;
+        match self.dhe {
+            Some(cb) => (cb.generate_key_pair_cb)(dhe_algo),
+            None => dhe::generate_key_pair(dhe_algo),
+        }
+    }
+
+    pub fn get_cert_from_cert_chain(
+        &self,
+        cert_chain: &[u8],
+        index: isize,
+    ) -> SpdmResult<(usize, usize)> {
+        self_test::check()?;
+        match self.cert_operation {
+            Some(cb) => (cb.get_cert_from_cert_chain_cb)(cert_chain, index),
+            None => cert_operation::get_cert_from_cert_chain(cert_chain, index),
+        }
+    }
+
+    pub fn verify_cert_chain(&self, cert_chain: &[u8], config: &SpdmCertChainConfig) -> SpdmResult {
+        self_test::check()?;
+        match self.cert_operation {
+            Some(cb) => {
+                (cb.verify_cert_chain_cb)(cert_chain, config)?;
+                cert_operation::check_revocation(&cb, cert_chain, config)
+            }
+            None => cert_operation::verify_cert_chain(cert_chain, config),
+        }
+    }
+
+    pub fn hkdf_expand(
+        &self,
+        hash_algo: SpdmBaseHashAlgo,
+        pk: &[u8],
+        info: &[u8],
+        out_size: u16,
+    ) -> Option<SpdmDigestStruct> {
+        self_test::check().ok()?;
+        match self.hkdf {
+            Some(cb) => (cb.hkdf_expand_cb)(hash_algo, pk, info, out_size),
+            None => hkdf::hkdf_expand(hash_algo, pk, info, out_size),
+        }
+    }
+
+    pub fn get_random(&self, data: &mut [u8]) -> SpdmResult<usize> {
+        self_test::check()?;
+        match self.rand {
+            Some(cb) => (cb.get_random_cb)(data),
+            None => rand::get_random(data),
+        }
+    }
+
+    /// `None` if the session secrets are derived in memory.
+    pub fn get_external_key_schedule(&self) -> Option<SpdmExternalKeySchedule> {
+        self.external_key_schedule
+            .or_else(external_key_schedule::get)
+    }
+
+    pub fn psk_lookup(
+        &self,
+        psk_hint: &[u8],
+    ) -> Option<(SpdmDheFinalKeyStruct, Option<SpdmPskContextStruct>)> {
+        let cb = self.psk.or_else(psk::get)?;
+        (cb.psk_lookup_cb)(psk_hint)
+    }
+
+    pub fn is_ext_alg_supported(&self, ext_alg: &SpdmExtAlgStruct) -> bool {
+        match self.ext_algo.or_else(ext_algo::get) {
+            Some(cb) => (cb.is_supported_cb)(ext_alg),
+            None => false,
+        }
+    }
+
+    pub fn ext_hash_all(
+        &self,
+        ext_hash: &SpdmExtAlgStruct,
+        data: &[u8],
+    ) -> Option<SpdmDigestStruct> {
+        self_test::check().ok()?;
+        let cb = self.ext_algo.or_else(ext_algo::get)?;
+        (cb.hash_all_cb)(ext_hash, data)
+    }
+
+    pub fn ext_asym_sign(
+        &self,
+        slot_id: u8,
+        ext_hash: &SpdmExtAlgStruct,
+        ext_asym: &SpdmExtAlgStruct,
+        data: &[u8],
+    ) -> Option<SpdmSignatureStruct> {
+        self_test::check().ok()?;
+        let cb = self.ext_algo.or_else(ext_algo::get)?;
+        (cb.sign_cb)(slot_id, ext_hash, ext_asym, data)
+    }
+
+    pub fn ext_asym_verify(
+        &self,
+        ext_hash: &SpdmExtAlgStruct,
+        ext_asym: &SpdmExtAlgStruct,
+        public_cert_der: &[u8],
+        data: &[u8],
+        signature: &SpdmSignatureStruct,
+    ) -> SpdmResult {
+        self_test::check()?;
+        match self.ext_algo.or_else(ext_algo::get) {
+            Some(cb) => (cb.verify_cb)(ext_hash, ext_asym, public_cert_der, data, signature),
+            None => spdm_result_err!(ENOSYS, "no extended algorithm callbacks"),
+        }
+    }
+}
@@ -3,14 +3,24 @@
 // SPDX-License-Identifier: BSD-2-Clause-Patent
 
 mod crypto_callbacks;
+mod crypto_provider;
 
 #[cfg(feature = "spdm-ring")]
 mod spdm_ring;
 
+#[cfg(feature = "spdm-gm")]
+mod spdm_gm;
+
+#[cfg(all(feature = "fips", feature = "spdm-gm"))]
+compile_error!("the SM algorithms of spdm-gm aren't FIPS approved");
+
 pub use crypto_callbacks::{
-    SpdmAead, SpdmAsymSign, SpdmAsymVerify, SpdmCertOperation, SpdmDhe, SpdmDheKeyExchange,
-    SpdmHash, SpdmHkdf, SpdmHmac,
+    SpdmAead, SpdmAsymSign, SpdmAsymVerify, SpdmCertChainConfig, SpdmCertExtension, SpdmCertInfo, SpdmCertOperation,
+    SpdmCryptoSelfTest, SpdmDhe, SpdmDheKeyExchange, SpdmExtAlgo, SpdmExternalKeySchedule, SpdmHash, SpdmHashCtx, SpdmHkdf, SpdmHmac,
+    SpdmKeyHandle, SpdmPsk, SpdmRandom, SpdmRevocationStatus, SpdmSessionKeyId, SpdmSigningKey,
+    SpdmSigningKeyStore,
 };
+pub use crypto_provider::SpdmCryptoProvider;
 
 use conquer_once::spin::OnceCell;
 
@@ -18,26 +28,44 @@ static CRYPTO_HASH: OnceCell<SpdmHash> = OnceCell::uninit();
 static CRYPTO_HMAC: OnceCell<SpdmHmac> = OnceCell::uninit();
 static CRYPTO_AEAD: OnceCell<SpdmAead> = OnceCell::uninit();
 static CRYPTO_ASYM_SIGN: OnceCell<SpdmAsymSign> = OnceCell::uninit();
+static CRYPTO_SIGNING_KEY_STORE: OnceCell<SpdmSigningKeyStore> = OnceCell::uninit();
 static CRYPTO_ASYM_VERIFY: OnceCell<SpdmAsymVerify> = OnceCell::uninit();
 static CRYPTO_DHE: OnceCell<SpdmDhe> = OnceCell::uninit();
 static CRYPTO_CERT_OPERATION: OnceCell<SpdmCertOperation> = OnceCell::uninit();
 static CRYPTO_HKDF: OnceCell<SpdmHkdf> = OnceCell::uninit();
+static CRYPTO_EXTERNAL_KEY_SCHEDULE: OnceCell<SpdmExternalKeySchedule> = OnceCell::uninit();
+static CRYPTO_PSK: OnceCell<SpdmPsk> = OnceCell::uninit();
+static CRYPTO_RAND: OnceCell<SpdmRandom> = OnceCell::uninit();
+static CRYPTO_EXT_ALGO: OnceCell<SpdmExtAlgo> = OnceCell::uninit();
+static CRYPTO_SELF_TEST: OnceCell<SpdmCryptoSelfTest> = OnceCell::uninit();
 
 pub mod hash {
     use super::CRYPTO_HASH;
-    use crate::crypto::SpdmHash;
+    use crate::crypto::{SpdmHash, SpdmHashCtx};
+    use crate::error::SpdmResult;
     use crate::msgs::{SpdmBaseHashAlgo, SpdmDigestStruct};
 
-    #[cfg(not(any(feature = "spdm-ring")))]
+    #[cfg(not(any(feature = "spdm-ring", feature = "spdm-gm")))]
     static DEFAULT: SpdmHash = SpdmHash {
         hash_all_cb: |_base_hash_algo: SpdmBaseHashAlgo,
                       _data: &[u8]|
          -> Option<SpdmDigestStruct> { unimplemented!() },
+        hash_ctx_init_cb: |_base_hash_algo: SpdmBaseHashAlgo| -> Option<SpdmHashCtx> {
+            unimplemented!()
+        },
+        hash_ctx_update_cb: |_ctx: &mut SpdmHashCtx, _data: &[u8]| -> SpdmResult {
+            unimplemented!()
+        },
+        hash_ctx_dup_cb: |_ctx: &SpdmHashCtx| -> Option<SpdmHashCtx> { unimplemented!() },
+        hash_ctx_finalize_cb: |_ctx: SpdmHashCtx| -> Option<SpdmDigestStruct> { unimplemented!() },
     };
 
-    #[cfg(feature = "spdm-ring")]
+    #[cfg(all(feature = "spdm-ring", not(feature = "spdm-gm")))]
     use super::spdm_ring::hash_impl::DEFAULT;
 
+    #[cfg(feature = "spdm-gm")]
+    use super::spdm_gm::hash_impl::DEFAULT;
+
     pub fn register(context: SpdmHash) -> bool {
         CRYPTO_HASH.try_init_once(|| context).is_ok()
     }
@@ -45,6 +73,34 @@ pub mod hash {
     pub fn hash_all(base_hash_algo: SpdmBaseHashAlgo, data: &[u8]) -> Option<SpdmDigestStruct> {
         (CRYPTO_HASH.try_get_or_init(|| DEFAULT).ok()?.hash_all_cb)(base_hash_algo, data)
     }
+
+    pub fn hash_ctx_init(base_hash_algo: SpdmBaseHashAlgo) -> Option<SpdmHashCtx> {
+        (CRYPTO_HASH
+            .try_get_or_init(|| DEFAULT)
+            .ok()?
+            .hash_ctx_init_cb)(base_hash_algo)
+    }
+
+    pub fn hash_ctx_update(ctx: &mut SpdmHashCtx, data: &[u8]) -> SpdmResult {
+        (CRYPTO_HASH
+            .try_get_or_init(|| DEFAULT)
+            .map_err(|_| spdm_err!(EFAULT))?
+            .hash_ctx_update_cb)(ctx, data)
+    }
+
+    pub fn hash_ctx_dup(ctx: &SpdmHashCtx) -> Option<SpdmHashCtx> {
+        (CRYPTO_HASH
+            .try_get_or_init(|| DEFAULT)
+            .ok()?
+            .hash_ctx_dup_cb)(ctx)
+    }
+
+    pub fn hash_ctx_finalize(ctx: SpdmHashCtx) -> Option<SpdmDigestStruct> {
+        (CRYPTO_HASH
+            .try_get_or_init(|| DEFAULT)
+            .ok()?
+            .hash_ctx_finalize_cb)(ctx)
+    }
 }
 
 pub mod hmac {
@@ -53,7 +109,7 @@ pub mod hmac {
     use crate::error::SpdmResult;
     use crate::msgs::{SpdmBaseHashAlgo, SpdmDigestStruct};
 
-    #[cfg(not(any(feature = "spdm-ring")))]
+    #[cfg(not(any(feature = "spdm-ring", feature = "spdm-gm")))]
     static DEFAULT: SpdmHmac = SpdmHmac {
         hmac_cb: |_base_hash_algo: SpdmBaseHashAlgo,
                   _key: &[u8],
@@ -66,9 +122,12 @@ pub mod hmac {
          -> SpdmResult { unimplemented!() },
     };
 
-    #[cfg(feature = "spdm-ring")]
+    #[cfg(all(feature = "spdm-ring", not(feature = "spdm-gm")))]
     use super::spdm_ring::hmac_impl::DEFAULT;
 
+    #[cfg(feature = "spdm-gm")]
+    use super::spdm_gm::hmac_impl::DEFAULT;
+
     pub fn register(context: SpdmHmac) -> bool {
         CRYPTO_HMAC.try_init_once(|| context).is_ok()
     }
@@ -95,27 +154,67 @@ pub mod hmac {
 }
 
 pub mod asym_sign {
-    use super::CRYPTO_ASYM_SIGN;
-    use crate::crypto::SpdmAsymSign;
+    use super::{CRYPTO_ASYM_SIGN, CRYPTO_SIGNING_KEY_STORE};
+    use crate::crypto::{SpdmAsymSign, SpdmSigningKey, SpdmSigningKeyStore};
     use crate::msgs::{SpdmBaseAsymAlgo, SpdmBaseHashAlgo, SpdmSignatureStruct};
 
     pub fn register(context: SpdmAsymSign) -> bool {
         CRYPTO_ASYM_SIGN.try_init_once(|| context).is_ok()
     }
 
+    /// Keys of the built-in `SpdmAsymSign`, unused once another one is
+    /// registered.
+    pub fn register_key_store(key_store: SpdmSigningKeyStore) -> bool {
+        CRYPTO_SIGNING_KEY_STORE.try_init_once(|| key_store).is_ok()
+    }
+
+    pub fn get_signing_key(slot_id: u8) -> Option<SpdmSigningKey> {
+        (CRYPTO_SIGNING_KEY_STORE.get()?.get_signing_key_cb)(slot_id)
+    }
+
+    pub fn sign_external(
+        handle: u64,
+        base_hash_algo: SpdmBaseHashAlgo,
+        base_asym_algo: SpdmBaseAsymAlgo,
+        data: &[u8],
+    ) -> Option<SpdmSignatureStruct> {
+        (CRYPTO_SIGNING_KEY_STORE.get()?.sign_external_cb)(
+            handle,
+            base_hash_algo,
+            base_asym_algo,
+            data,
+        )
+    }
+
+    #[cfg(not(any(feature = "spdm-ring", feature = "spdm-gm")))]
     static DEFAULT: SpdmAsymSign = SpdmAsymSign {
-        sign_cb: |_base_hash_algo: SpdmBaseHashAlgo,
+        sign_cb: |_slot_id: u8,
+                  _base_hash_algo: SpdmBaseHashAlgo,
                   _base_asym_algo: SpdmBaseAsymAlgo,
                   _data: &[u8]|
          -> Option<SpdmSignatureStruct> { unimplemented!() },
     };
 
+    #[cfg(all(feature = "spdm-ring", not(feature = "spdm-gm")))]
+    use super::spdm_ring::asym_sign_impl::DEFAULT;
+
+    #[cfg(feature = "spdm-gm")]
+    use super::spdm_gm::asym_sign_impl::DEFAULT;
+
+    #[cfg(all(feature = "spdm-ring", not(feature = "spdm-gm")))]
+    pub use super::spdm_ring::asym_sign_impl::sign_with_key;
+
+    #[cfg(feature = "spdm-gm")]
+    pub use super::spdm_gm::asym_sign_impl::sign_with_key;
+
     pub fn sign(
+        slot_id: u8,
         base_hash_algo: SpdmBaseHashAlgo,
         base_asym_algo: SpdmBaseAsymAlgo,
         data: &[u8],
     ) -> Option<SpdmSignatureStruct> {
         (CRYPTO_ASYM_SIGN.try_get_or_init(|| DEFAULT).ok()?.sign_cb)(
+            slot_id,
             base_hash_algo,
             base_asym_algo,
             data,
@@ -129,7 +228,7 @@ pub mod asym_verify {
     use crate::error::SpdmResult;
     use crate::msgs::{SpdmBaseAsymAlgo, SpdmBaseHashAlgo, SpdmSignatureStruct};
 
-    #[cfg(not(any(feature = "spdm-ring")))]
+    #[cfg(not(any(feature = "spdm-ring", feature = "spdm-gm")))]
     static DEFAULT: SpdmAsymVerify = SpdmAsymVerify {
         verify_cb: |_base_hash_algo: SpdmBaseHashAlgo,
                     _base_asym_algo: SpdmBaseAsymAlgo,
@@ -139,9 +238,12 @@ pub mod asym_verify {
          -> SpdmResult { unimplemented!() },
     };
 
-    #[cfg(feature = "spdm-ring")]
+    #[cfg(all(feature = "spdm-ring", not(feature = "spdm-gm")))]
     use super::spdm_ring::asym_verify_impl::DEFAULT;
 
+    #[cfg(feature = "spdm-gm")]
+    use super::spdm_gm::asym_verify_impl::DEFAULT;
+
     pub fn register(context: SpdmAsymVerify) -> bool {
         CRYPTO_ASYM_VERIFY.try_get_or_init(|| context).is_ok()
     }
@@ -174,21 +276,32 @@ pub mod dhe {
     use crate::crypto::{SpdmDhe, SpdmDheKeyExchange};
     use crate::msgs::{SpdmDheAlgo, SpdmDheExchangeStruct};
 
-    #[cfg(not(any(feature = "spdm-ring")))]
+    #[cfg(not(any(feature = "spdm-ring", feature = "spdm-gm")))]
     static DEFAULT: SpdmDhe =
         SpdmDhe {
+            supported_algo: SpdmDheAlgo::empty(),
             generate_key_pair_cb: |_dhe_algo: SpdmDheAlgo| -> Option<(
                 SpdmDheExchangeStruct,
                 Box<dyn SpdmDheKeyExchange>,
             )> { unimplemented!() },
         };
-    #[cfg(feature = "spdm-ring")]
+    #[cfg(all(feature = "spdm-ring", not(feature = "spdm-gm")))]
     use super::spdm_ring::dhe_impl::DEFAULT;
 
+    #[cfg(feature = "spdm-gm")]
+    use super::spdm_gm::dhe_impl::DEFAULT;
+
     pub fn register(context: SpdmDhe) -> bool {
         CRYPTO_DHE.try_init_once(|| context).is_ok()
     }
 
+    pub fn supported_algo() -> SpdmDheAlgo {
+        CRYPTO_DHE
+            .try_get_or_init(|| DEFAULT)
+            .map(|dhe| dhe.supported_algo)
+            .unwrap_or_else(|_| SpdmDheAlgo::empty())
+    }
+
     pub fn generate_key_pair(
         dhe_algo: SpdmDheAlgo,
     ) -> Option<(SpdmDheExchangeStruct, Box<dyn SpdmDheKeyExchange>)> {
@@ -201,7 +314,9 @@ pub mod dhe {
 
 pub mod cert_operation {
     use super::CRYPTO_CERT_OPERATION;
-    use crate::crypto::SpdmCertOperation;
+    use crate::crypto::{
+        SpdmCertChainConfig, SpdmCertInfo, SpdmCertOperation, SpdmRevocationStatus,
+    };
     use crate::error::SpdmResult;
 
     #[cfg(not(any(feature = "spdm-ring")))]
@@ -209,7 +324,11 @@ pub mod cert_operation {
         get_cert_from_cert_chain_cb: |_cert_chain: &[u8],
                                       _index: isize|
          -> SpdmResult<(usize, usize)> { unimplemented!() },
-        verify_cert_chain_cb: |_cert_chain: &[u8]| -> SpdmResult { unimplemented!() },
+        verify_cert_chain_cb: |_cert_chain: &[u8], _config: &SpdmCertChainConfig| -> SpdmResult {
+            unimplemented!()
+        },
+        parse_cert_info_cb: |_cert: &[u8]| -> SpdmResult<SpdmCertInfo<'_>> { unimplemented!() },
+        check_revocation_cb: None,
     };
 
     #[cfg(feature = "spdm-ring")]
@@ -226,11 +345,65 @@ pub mod cert_operation {
             .get_cert_from_cert_chain_cb)(cert_chain, index)
     }
 
-    pub fn verify_cert_chain(cert_chain: &[u8]) -> SpdmResult {
+    pub fn parse_cert_info(cert: &[u8]) -> SpdmResult<SpdmCertInfo<'_>> {
         (CRYPTO_CERT_OPERATION
             .try_get_or_init(|| DEFAULT)
             .map_err(|_| spdm_err!(EFAULT))?
-            .verify_cert_chain_cb)(cert_chain)
+            .parse_cert_info_cb)(cert)
+    }
+
+    pub fn verify_cert_chain(cert_chain: &[u8], config: &SpdmCertChainConfig) -> SpdmResult {
+        let cert_operation = CRYPTO_CERT_OPERATION
+            .try_get_or_init(|| DEFAULT)
+            .map_err(|_| spdm_err!(EFAULT))?;
+        (cert_operation.verify_cert_chain_cb)(cert_chain, config)?;
+        check_revocation(cert_operation, cert_chain, config)
+    }
+
+    /// Ask the `check_revocation_cb` of `cert_operation`, if any, about the
+    /// certs of the verified `cert_chain`.
+    pub fn check_revocation(
+        cert_operation: &SpdmCertOperation,
+        cert_chain: &[u8],
+        config: &SpdmCertChainConfig,
+    ) -> SpdmResult {
+        let check_revocation_cb = match cert_operation.check_revocation_cb {
+            Some(check_revocation_cb) => check_revocation_cb,
+            None => return Ok(()),
+        };
+        let revocation_hard_fail = config.revocation_hard_fail;
+
+        let (mut issuer_begin, mut issuer_end) =
+            (cert_operation.get_cert_from_cert_chain_cb)(cert_chain, 0)?;
+        while issuer_end < cert_chain.len() {
+            let (begin, end) =
+                (cert_operation.get_cert_from_cert_chain_cb)(&cert_chain[issuer_end..], 0)?;
+            let (begin, end) = (issuer_end + begin, issuer_end + end);
+            if end > cert_chain.len() {
+                return spdm_result_err!(EINVAL, "truncated cert chain");
+            }
+            let status = check_revocation_cb(
+                &cert_chain[begin..end],
+                &cert_chain[issuer_begin..issuer_end],
+            );
+            match status {
+                SpdmRevocationStatus::Good => {}
+                SpdmRevocationStatus::Unknown if !revocation_hard_fail => {
+                    info!("cert revocation status unknown\n");
+                }
+                SpdmRevocationStatus::Unknown => {
+                    error!("!!! cert revocation status unknown !!!\n");
+                    return spdm_result_err!(EFAULT, "cert revocation status unknown");
+                }
+                SpdmRevocationStatus::Revoked => {
+                    error!("!!! cert revoked !!!\n");
+                    return spdm_result_err!(EFAULT, "cert revoked");
+                }
+            }
+            issuer_begin = begin;
+            issuer_end = end;
+        }
+        Ok(())
     }
 }
 
@@ -239,7 +412,7 @@ pub mod hkdf {
     use crate::crypto::SpdmHkdf;
     use crate::msgs::{SpdmBaseHashAlgo, SpdmDigestStruct};
 
-    #[cfg(not(any(feature = "spdm-ring")))]
+    #[cfg(not(any(feature = "spdm-ring", feature = "spdm-gm")))]
     static DEFAULT: SpdmHkdf = SpdmHkdf {
         hkdf_expand_cb: |_hash_algo: SpdmBaseHashAlgo,
                          _pk: &[u8],
@@ -248,9 +421,12 @@ pub mod hkdf {
          -> Option<SpdmDigestStruct> { unimplemented!() },
     };
 
-    #[cfg(feature = "spdm-ring")]
+    #[cfg(all(feature = "spdm-ring", not(feature = "spdm-gm")))]
     use super::spdm_ring::hkdf_impl::DEFAULT;
 
+    #[cfg(feature = "spdm-gm")]
+    use super::spdm_gm::hkdf_impl::DEFAULT;
+
     pub fn register(context: SpdmHkdf) -> bool {
         CRYPTO_HKDF.try_init_once(|| context).is_ok()
     }
@@ -267,14 +443,40 @@ pub mod hkdf {
     }
 }
 
+pub mod rand {
+    use super::CRYPTO_RAND;
+    use crate::crypto::SpdmRandom;
+    use crate::error::SpdmResult;
+
+    #[cfg(not(any(feature = "spdm-ring")))]
+    static DEFAULT: SpdmRandom = SpdmRandom {
+        get_random_cb: |_data: &mut [u8]| -> SpdmResult<usize> { unimplemented!() },
+    };
+
+    #[cfg(feature = "spdm-ring")]
+    use super::spdm_ring::rand_impl::DEFAULT;
+
+    pub fn register(context: SpdmRandom) -> bool {
+        CRYPTO_RAND.try_init_once(|| context).is_ok()
+    }
+
+    pub fn get_random(data: &mut [u8]) -> SpdmResult<usize> {
+        (CRYPTO_RAND
+            .try_get_or_init(|| DEFAULT)
+            .map_err(|_| spdm_err!(EFAULT))?
+            .get_random_cb)(data)
+    }
+}
+
 pub mod aead {
     use super::CRYPTO_AEAD;
     use crate::crypto::SpdmAead;
     use crate::error::SpdmResult;
     use crate::msgs::SpdmAeadAlgo;
 
-    #[cfg(not(any(feature = "spdm-ring")))]
+    #[cfg(not(any(feature = "spdm-ring", feature = "spdm-gm")))]
     static DEFAULT: SpdmAead = SpdmAead {
+        supported_algo: SpdmAeadAlgo::empty(),
         encrypt_cb: |_aead_algo: SpdmAeadAlgo,
                      _key: &[u8],
                      _iv: &[u8],
@@ -293,13 +495,23 @@ pub mod aead {
          -> SpdmResult<usize> { unimplemented!() },
     };
 
-    #[cfg(feature = "spdm-ring")]
+    #[cfg(all(feature = "spdm-ring", not(feature = "spdm-gm")))]
     use super::spdm_ring::aead_impl::DEFAULT;
 
+    #[cfg(feature = "spdm-gm")]
+    use super::spdm_gm::aead_impl::DEFAULT;
+
     pub fn register(context: SpdmAead) -> bool {
         CRYPTO_AEAD.try_init_once(|| context).is_ok()
     }
 
+    pub fn supported_algo() -> SpdmAeadAlgo {
+        CRYPTO_AEAD
+            .try_get_or_init(|| DEFAULT)
+            .map(|aead| aead.supported_algo)
+            .unwrap_or_else(|_| SpdmAeadAlgo::empty())
+    }
+
     pub fn encrypt(
         aead_algo: SpdmAeadAlgo,
         key: &[u8],
@@ -330,3 +542,217 @@ pub mod aead {
             .decrypt_cb)(aead_algo, key, iv, aad, cipher_text, tag, plain_text)
     }
 }
+
+/// Without a registered external key schedule the session secrets are
+/// derived in memory with the hkdf and hmac callbacks.
+pub mod external_key_schedule {
+    use super::CRYPTO_EXTERNAL_KEY_SCHEDULE;
+    use crate::crypto::SpdmExternalKeySchedule;
+
+    pub fn register(context: SpdmExternalKeySchedule) -> bool {
+        CRYPTO_EXTERNAL_KEY_SCHEDULE
+            .try_init_once(|| context)
+            .is_ok()
+    }
+
+    pub fn get() -> Option<SpdmExternalKeySchedule> {
+        CRYPTO_EXTERNAL_KEY_SCHEDULE.get().copied()
+    }
+}
+
+/// Without a registered PSK storage the responder knows no PSK and
+/// PSK_EXCHANGE is answered with ERROR(InvalidRequest).
+pub mod psk {
+    use super::CRYPTO_PSK;
+    use crate::crypto::SpdmPsk;
+
+    pub fn register(context: SpdmPsk) -> bool {
+        CRYPTO_PSK.try_init_once(|| context).is_ok()
+    }
+
+    pub fn get() -> Option<SpdmPsk> {
+        CRYPTO_PSK.get().copied()
+    }
+}
+
+/// Without registered extended algorithm callbacks only the base
+/// algorithms are negotiated.
+pub mod ext_algo {
+    use super::CRYPTO_EXT_ALGO;
+    use crate::crypto::SpdmExtAlgo;
+
+    pub fn register(context: SpdmExtAlgo) -> bool {
+        CRYPTO_EXT_ALGO.try_init_once(|| context).is_ok()
+    }
+
+    pub fn get() -> Option<SpdmExtAlgo> {
+        CRYPTO_EXT_ALGO.get().copied()
+    }
+}
+
+/// Without a registered self-test no KAT is run, unless the `fips` feature
+/// is enabled, which makes them mandatory. Otherwise the KATs run before the
+/// first crypto operation of an `SpdmCryptoProvider`, unless the integrator
+/// runs them earlier with `run_kat()`. The crypto operations are only
+/// available once the KATs have passed, except to the KATs themselves.
+pub mod self_test {
+    use super::CRYPTO_SELF_TEST;
+    use crate::crypto::SpdmCryptoSelfTest;
+    use crate::error::SpdmResult;
+    use core::sync::atomic::{AtomicU8, Ordering};
+
+    const NOT_RUN: u8 = 0;
+    const RUNNING: u8 = 1;
+    const PASSED: u8 = 2;
+    const FAILED: u8 = 3;
+
+    struct SelfTestState(AtomicU8);
+
+    impl SelfTestState {
+        const fn new() -> Self {
+            SelfTestState(AtomicU8::new(NOT_RUN))
+        }
+
+        // A failure is final, running the KATs again doesn't clear it.
+        fn run_kat(&self, self_test: &SpdmCryptoSelfTest) -> SpdmResult {
+            let state = self.0.load(Ordering::SeqCst);
+            if state == FAILED {
+                return spdm_result_err!(EFAULT, "crypto self-test failed");
+            }
+            if self
+                .0
+                .compare_exchange(state, RUNNING, Ordering::SeqCst, Ordering::SeqCst)
+                .is_err()
+            {
+                return spdm_result_err!(EBUSY, "crypto self-test running");
+            }
+
+            let result = kat::run(self_test);
+            match result {
+                Ok(()) => self.0.store(PASSED, Ordering::SeqCst),
+                Err(_) => {
+                    error!("!!! crypto self-test failed !!!\n");
+                    self.0.store(FAILED, Ordering::SeqCst);
+                }
+            }
+            result
+        }
+
+        // The crypto operations the KATs go through themselves aren't
+        // blocked while they run, those of other threads are.
+        fn check(&self, self_test: Option<&SpdmCryptoSelfTest>) -> SpdmResult {
+            if let Some(self_test) = self_test {
+                if self.0.load(Ordering::SeqCst) == NOT_RUN {
+                    let _ = self.run_kat(self_test);
+                }
+            }
+            match self.0.load(Ordering::SeqCst) {
+                PASSED => Ok(()),
+                NOT_RUN if self_test.is_none() && !cfg!(feature = "fips") => Ok(()),
+                NOT_RUN => spdm_result_err!(EFAULT, "no crypto self-test registered"),
+                RUNNING if kat::running_here() => Ok(()),
+                RUNNING => spdm_result_err!(EBUSY, "crypto self-test running"),
+                _ => spdm_result_err!(EFAULT, "crypto self-test failed"),
+            }
+        }
+    }
+
+    // Which thread runs the KATs, without std there is only one.
+    #[cfg(feature = "std")]
+    mod kat {
+        use crate::crypto::SpdmCryptoSelfTest;
+        use crate::error::SpdmResult;
+        use core::cell::Cell;
+
+        std::thread_local! {
+            static RUNNING_HERE: Cell<bool> = Cell::default();
+        }
+
+        pub fn run(self_test: &SpdmCryptoSelfTest) -> SpdmResult {
+            RUNNING_HERE.with(|running| running.set(true));
+            let result = (self_test.run_kat_cb)();
+            RUNNING_HERE.with(|running| running.set(false));
+            result
+        }
+
+        pub fn running_here() -> bool {
+            RUNNING_HERE.with(|running| running.get())
+        }
+    }
+
+    #[cfg(not(feature = "std"))]
+    mod kat {
+        use crate::crypto::SpdmCryptoSelfTest;
+        use crate::error::SpdmResult;
+
+        pub fn run(self_test: &SpdmCryptoSelfTest) -> SpdmResult {
+            (self_test.run_kat_cb)()
+        }
+
+        pub fn running_here() -> bool {
+            true
+        }
+    }
+
+    static STATE: SelfTestState = SelfTestState::new();
+
+    pub fn register(context: SpdmCryptoSelfTest) -> bool {
+        CRYPTO_SELF_TEST.try_init_once(|| context).is_ok()
+    }
+
+    /// Run the registered KATs now, e.g. at power-up or on demand.
+    pub fn run_kat() -> SpdmResult {
+        match CRYPTO_SELF_TEST.get() {
+            Some(self_test) => STATE.run_kat(self_test),
+            None => Ok(()),
+        }
+    }
+
+    /// Ok if the crypto operations may be used, the KATs are run first if
+    /// they haven't been yet.
+    pub fn check() -> SpdmResult {
+        STATE.check(CRYPTO_SELF_TEST.get())
+    }
+
+    #[test]
+    fn test_self_test_state() {
+        let passing = SpdmCryptoSelfTest {
+            run_kat_cb: || Ok(()),
+        };
+        let failing = SpdmCryptoSelfTest {
+            run_kat_cb: || spdm_result_err!(EFAULT),
+        };
+
+        let state = SelfTestState::new();
+        assert_eq!(state.check(None).is_ok(), !cfg!(feature = "fips"));
+        assert_eq!(state.0.load(Ordering::SeqCst), NOT_RUN);
+        assert!(state.check(Some(&passing)).is_ok());
+        assert_eq!(state.0.load(Ordering::SeqCst), PASSED);
+        assert!(state.run_kat(&failing).is_err());
+        assert!(state.check(Some(&passing)).is_err());
+        assert!(state.run_kat(&passing).is_err());
+
+        let state = SelfTestState::new();
+        assert!(state.check(Some(&failing)).is_err());
+        assert!(state.check(None).is_err());
+    }
+
+    #[test]
+    fn test_self_test_running() {
+        static STATE: SelfTestState = SelfTestState::new();
+        let checking = SpdmCryptoSelfTest {
+            run_kat_cb: || STATE.check(None),
+        };
+
+        // the KATs use the crypto
+        assert!(STATE.run_kat(&checking).is_ok());
+
+        // others wait for them
+        let state = SelfTestState::new();
+        state.0.store(RUNNING, Ordering::SeqCst);
+        let result = std::thread::spawn(move || state.check(None))
+            .join()
+            .unwrap();
+        assert_eq!(result.unwrap_err().num(), crate::error::SpdmErrorNum::EBUSY);
+    }
+}
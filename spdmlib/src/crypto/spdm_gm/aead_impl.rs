@@ -0,0 +1,97 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+use super::sm4::{Sm4Gcm, SM4_GCM_IV_SIZE, SM4_GCM_TAG_SIZE, SM4_KEY_SIZE};
+use crate::crypto::SpdmAead;
+use crate::error::SpdmResult;
+use crate::msgs::SpdmAeadAlgo;
+use core::convert::TryFrom;
+
+#[cfg(feature = "spdm-ring")]
+use crate::crypto::spdm_ring::aead_impl::DEFAULT as FALLBACK;
+
+#[cfg(feature = "spdm-ring")]
+pub static DEFAULT: SpdmAead = SpdmAead {
+    supported_algo: SpdmAeadAlgo::from_bits_truncate(
+        SpdmAeadAlgo::AES_128_GCM.bits()
+            | SpdmAeadAlgo::AES_256_GCM.bits()
+            | SpdmAeadAlgo::CHACHA20_POLY1305.bits()
+            | SpdmAeadAlgo::SM4_GCM.bits(),
+    ),
+    encrypt_cb: encrypt,
+    decrypt_cb: decrypt,
+};
+
+#[cfg(not(feature = "spdm-ring"))]
+pub static DEFAULT: SpdmAead = SpdmAead {
+    supported_algo: SpdmAeadAlgo::SM4_GCM,
+    encrypt_cb: encrypt,
+    decrypt_cb: decrypt,
+};
+
+fn get_key_iv<'a>(
+    key: &'a [u8],
+    iv: &'a [u8],
+) -> SpdmResult<(&'a [u8; SM4_KEY_SIZE], &'a [u8; SM4_GCM_IV_SIZE])> {
+    let key =
+        <&[u8; SM4_KEY_SIZE]>::try_from(key).map_err(|_| spdm_err!(EINVAL, "key len invalid"))?;
+    let iv =
+        <&[u8; SM4_GCM_IV_SIZE]>::try_from(iv).map_err(|_| spdm_err!(EINVAL, "iv len invalid"))?;
+    Ok((key, iv))
+}
+
+fn encrypt(
+    aead_algo: SpdmAeadAlgo,
+    key: &[u8],
+    iv: &[u8],
+    aad: &[u8],
+    plain_text: &[u8],
+    tag: &mut [u8],
+    cipher_text: &mut [u8],
+) -> SpdmResult<(usize, usize)> {
+    if aead_algo != SpdmAeadAlgo::SM4_GCM {
+        #[cfg(feature = "spdm-ring")]
+        return (FALLBACK.encrypt_cb)(aead_algo, key, iv, aad, plain_text, tag, cipher_text);
+        #[cfg(not(feature = "spdm-ring"))]
+        return spdm_result_err!(EINVAL, "unsupported aead algorithm");
+    }
+
+    let (key, iv) = get_key_iv(key, iv)?;
+    if tag.len() != SM4_GCM_TAG_SIZE || cipher_text.len() != plain_text.len() {
+        return spdm_result_err!(EINVAL, "tag or cipher_text len invalid");
+    }
+    cipher_text.copy_from_slice(plain_text);
+    tag.copy_from_slice(&Sm4Gcm::new(key).seal(iv, aad, cipher_text));
+    Ok((cipher_text.len(), tag.len()))
+}
+
+fn decrypt(
+    aead_algo: SpdmAeadAlgo,
+    key: &[u8],
+    iv: &[u8],
+    aad: &[u8],
+    cipher_text: &[u8],
+    tag: &[u8],
+    plain_text: &mut [u8],
+) -> SpdmResult<usize> {
+    if aead_algo != SpdmAeadAlgo::SM4_GCM {
+        #[cfg(feature = "spdm-ring")]
+        return (FALLBACK.decrypt_cb)(aead_algo, key, iv, aad, cipher_text, tag, plain_text);
+        #[cfg(not(feature = "spdm-ring"))]
+        return spdm_result_err!(EINVAL, "unsupported aead algorithm");
+    }
+
+    let (key, iv) = get_key_iv(key, iv)?;
+    if tag.len() != SM4_GCM_TAG_SIZE || plain_text.len() != cipher_text.len() {
+        return spdm_result_err!(EINVAL, "tag or plain_text len invalid");
+    }
+    plain_text.copy_from_slice(cipher_text);
+    if let Err(e) = Sm4Gcm::new(key).open(iv, aad, plain_text, tag) {
+        for b in plain_text.iter_mut() {
+            *b = 0;
+        }
+        return Err(e);
+    }
+    Ok(plain_text.len())
+}
@@ -0,0 +1,69 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+use super::der;
+use super::sm2::{Sm2PrivateKey, SM2_DEFAULT_ID};
+use crate::crypto::{asym_sign, SpdmAsymSign, SpdmSigningKey};
+use crate::msgs::{
+    SpdmBaseAsymAlgo, SpdmBaseHashAlgo, SpdmSignatureStruct, SPDM_MAX_ASYM_KEY_SIZE,
+};
+
+#[cfg(feature = "spdm-ring")]
+use crate::crypto::spdm_ring::asym_sign_impl::{self as fallback, DEFAULT as FALLBACK};
+
+pub static DEFAULT: SpdmAsymSign = SpdmAsymSign { sign_cb: asym_sign };
+
+fn asym_sign(
+    slot_id: u8,
+    base_hash_algo: SpdmBaseHashAlgo,
+    base_asym_algo: SpdmBaseAsymAlgo,
+    data: &[u8],
+) -> Option<SpdmSignatureStruct> {
+    match (base_hash_algo, base_asym_algo) {
+        (SpdmBaseHashAlgo::TPM_ALG_SM3_256, SpdmBaseAsymAlgo::TPM_ALG_SM2_ECC_SM2_P256) => {
+            match asym_sign::get_signing_key(slot_id)? {
+                SpdmSigningKey::Der(key) => sign_sm2(key, data),
+                SpdmSigningKey::External(handle) => {
+                    asym_sign::sign_external(handle, base_hash_algo, base_asym_algo, data)
+                }
+            }
+        }
+        #[cfg(feature = "spdm-ring")]
+        _ => (FALLBACK.sign_cb)(slot_id, base_hash_algo, base_asym_algo, data),
+        #[cfg(not(feature = "spdm-ring"))]
+        _ => None,
+    }
+}
+
+/// Sign with the `SpdmSigningKey::Der` key `key`, a PKCS#8 key for SM2,
+/// for software key stores holding the keys in memory.
+pub fn sign_with_key(
+    key: &[u8],
+    base_hash_algo: SpdmBaseHashAlgo,
+    base_asym_algo: SpdmBaseAsymAlgo,
+    data: &[u8],
+) -> Option<SpdmSignatureStruct> {
+    match (base_hash_algo, base_asym_algo) {
+        (SpdmBaseHashAlgo::TPM_ALG_SM3_256, SpdmBaseAsymAlgo::TPM_ALG_SM2_ECC_SM2_P256) => {
+            sign_sm2(key, data)
+        }
+        #[cfg(feature = "spdm-ring")]
+        _ => fallback::sign_with_key(key, base_hash_algo, base_asym_algo, data),
+        #[cfg(not(feature = "spdm-ring"))]
+        _ => None,
+    }
+}
+
+fn sign_sm2(key: &[u8], data: &[u8]) -> Option<SpdmSignatureStruct> {
+    let private_key = Sm2PrivateKey::from_bytes(der::sm2_private_key_from_pkcs8(key)?)?;
+    let public_key = private_key.public_key()?;
+    let signature = private_key.sign(SM2_DEFAULT_ID, &public_key, data)?;
+
+    let mut full_signature = [0u8; SPDM_MAX_ASYM_KEY_SIZE];
+    full_signature[..signature.len()].copy_from_slice(&signature);
+    Some(SpdmSignatureStruct {
+        data_size: signature.len() as u16,
+        data: full_signature,
+    })
+}
@@ -0,0 +1,48 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+use super::der;
+use super::sm2::{sm2_verify, SM2_DEFAULT_ID};
+use crate::crypto::{self, SpdmAsymVerify};
+use crate::error::SpdmResult;
+use crate::msgs::{SpdmBaseAsymAlgo, SpdmBaseHashAlgo, SpdmSignatureStruct};
+
+#[cfg(feature = "spdm-ring")]
+use crate::crypto::spdm_ring::asym_verify_impl::DEFAULT as FALLBACK;
+
+pub static DEFAULT: SpdmAsymVerify = SpdmAsymVerify {
+    verify_cb: asym_verify,
+};
+
+fn asym_verify(
+    base_hash_algo: SpdmBaseHashAlgo,
+    base_asym_algo: SpdmBaseAsymAlgo,
+    public_cert_der: &[u8],
+    data: &[u8],
+    signature: &SpdmSignatureStruct,
+) -> SpdmResult {
+    match (base_hash_algo, base_asym_algo) {
+        (SpdmBaseHashAlgo::TPM_ALG_SM3_256, SpdmBaseAsymAlgo::TPM_ALG_SM2_ECC_SM2_P256) => {
+            let (leaf_begin, leaf_end) =
+                crypto::cert_operation::get_cert_from_cert_chain(public_cert_der, -1)?;
+            let public_key = der::sm2_public_key_from_cert(&public_cert_der[leaf_begin..leaf_end])
+                .ok_or(spdm_err!(EINVAL, "no SM2 public key in the leaf cert"))?;
+            if sm2_verify(SM2_DEFAULT_ID, public_key, data, signature.as_ref()) {
+                Ok(())
+            } else {
+                spdm_result_err!(EFAULT)
+            }
+        }
+        #[cfg(feature = "spdm-ring")]
+        _ => (FALLBACK.verify_cb)(
+            base_hash_algo,
+            base_asym_algo,
+            public_cert_der,
+            data,
+            signature,
+        ),
+        #[cfg(not(feature = "spdm-ring"))]
+        _ => spdm_result_err!(EINVAL, "unsupported signature algorithm"),
+    }
+}
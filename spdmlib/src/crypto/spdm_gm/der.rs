@@ -0,0 +1,95 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+// Just enough DER to find the SM2 key in a PKCS#8 private key or in the
+// SubjectPublicKeyInfo of a certificate.
+
+pub const TAG_INTEGER: u8 = 0x02;
+pub const TAG_BIT_STRING: u8 = 0x03;
+pub const TAG_OCTET_STRING: u8 = 0x04;
+pub const TAG_OID: u8 = 0x06;
+pub const TAG_SEQUENCE: u8 = 0x30;
+pub const TAG_CONTEXT_0: u8 = 0xA0;
+
+// id-ecPublicKey, 1.2.840.10045.2.1
+pub const OID_EC_PUBLIC_KEY: &[u8] = &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x02, 0x01];
+// sm2p256v1, 1.2.156.10197.1.301
+pub const OID_SM2: &[u8] = &[0x2A, 0x81, 0x1C, 0xCF, 0x55, 0x01, 0x82, 0x2D];
+
+/// The tag and the contents of the element at the start of `data`, and the
+/// data after it.
+pub fn read(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let tag = *data.get(0)?;
+    let first = *data.get(1)? as usize;
+    let (size, header_size) = match first {
+        0..=0x7F => (first, 2usize),
+        0x81 => (*data.get(2)? as usize, 3),
+        0x82 => (((*data.get(2)? as usize) << 8) | *data.get(3)? as usize, 4),
+        _ => return None,
+    };
+    let end = header_size.checked_add(size)?;
+    if end > data.len() {
+        return None;
+    }
+    Some((tag, &data[header_size..end], &data[end..]))
+}
+
+/// The contents of the element at the start of `data` if it has `tag`, and
+/// the data after it.
+pub fn expect(data: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+    match read(data)? {
+        (t, contents, rest) if t == tag => Some((contents, rest)),
+        _ => None,
+    }
+}
+
+// AlgorithmIdentifier { id-ecPublicKey, sm2p256v1 }
+fn is_sm2_algorithm(algorithm: &[u8]) -> bool {
+    let check = || {
+        let (oid, rest) = expect(algorithm, TAG_OID)?;
+        let (curve, _) = expect(rest, TAG_OID)?;
+        Some(oid == OID_EC_PUBLIC_KEY && curve == OID_SM2)
+    };
+    check().unwrap_or(false)
+}
+
+/// The private key of a PKCS#8 SM2 key, RFC 5208 and RFC 5915.
+pub fn sm2_private_key_from_pkcs8(key: &[u8]) -> Option<&[u8]> {
+    let (private_key_info, _) = expect(key, TAG_SEQUENCE)?;
+    let (_version, rest) = expect(private_key_info, TAG_INTEGER)?;
+    let (algorithm, rest) = expect(rest, TAG_SEQUENCE)?;
+    if !is_sm2_algorithm(algorithm) {
+        return None;
+    }
+    let (private_key, _) = expect(rest, TAG_OCTET_STRING)?;
+    let (ec_private_key, _) = expect(private_key, TAG_SEQUENCE)?;
+    let (_version, rest) = expect(ec_private_key, TAG_INTEGER)?;
+    let (d, _) = expect(rest, TAG_OCTET_STRING)?;
+    Some(d)
+}
+
+/// The x || y public key of a certificate with an SM2 key.
+pub fn sm2_public_key_from_cert(cert: &[u8]) -> Option<&[u8]> {
+    let (certificate, _) = expect(cert, TAG_SEQUENCE)?;
+    let (tbs_certificate, _) = expect(certificate, TAG_SEQUENCE)?;
+    let mut rest = tbs_certificate;
+    if let Some((_version, after)) = expect(rest, TAG_CONTEXT_0) {
+        rest = after;
+    }
+    let (_serial_number, rest) = expect(rest, TAG_INTEGER)?;
+    let (_signature, rest) = expect(rest, TAG_SEQUENCE)?;
+    let (_issuer, rest) = expect(rest, TAG_SEQUENCE)?;
+    let (_validity, rest) = expect(rest, TAG_SEQUENCE)?;
+    let (_subject, rest) = expect(rest, TAG_SEQUENCE)?;
+    let (subject_public_key_info, _) = expect(rest, TAG_SEQUENCE)?;
+    let (algorithm, rest) = expect(subject_public_key_info, TAG_SEQUENCE)?;
+    if !is_sm2_algorithm(algorithm) {
+        return None;
+    }
+    // no unused bits, uncompressed point
+    match expect(rest, TAG_BIT_STRING)?.0 {
+        [0x00, 0x04, point @ ..] => Some(point),
+        _ => None,
+    }
+}
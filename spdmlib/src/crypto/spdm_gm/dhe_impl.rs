@@ -0,0 +1,71 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+extern crate alloc;
+use alloc::boxed::Box;
+
+use super::sm2::Sm2PrivateKey;
+use crate::crypto::{SpdmDhe, SpdmDheKeyExchange};
+use crate::msgs::{SpdmDheAlgo, SpdmDheExchangeStruct, SpdmDheFinalKeyStruct};
+use bytes::BytesMut;
+
+#[cfg(feature = "spdm-ring")]
+use crate::crypto::spdm_ring::dhe_impl::DEFAULT as FALLBACK;
+
+#[cfg(feature = "spdm-ring")]
+pub static DEFAULT: SpdmDhe = SpdmDhe {
+    supported_algo: SpdmDheAlgo::from_bits_truncate(
+        SpdmDheAlgo::SECP_256_R1.bits()
+            | SpdmDheAlgo::SECP_384_R1.bits()
+            | SpdmDheAlgo::FFDHE_2048.bits()
+            | SpdmDheAlgo::FFDHE_3072.bits()
+            | SpdmDheAlgo::FFDHE_4096.bits()
+            | SpdmDheAlgo::SM2_P256.bits(),
+    ),
+    generate_key_pair_cb: generate_key_pair,
+};
+
+#[cfg(not(feature = "spdm-ring"))]
+pub static DEFAULT: SpdmDhe = SpdmDhe {
+    supported_algo: SpdmDheAlgo::SM2_P256,
+    generate_key_pair_cb: generate_key_pair,
+};
+
+fn generate_key_pair(
+    dhe_algo: SpdmDheAlgo,
+) -> Option<(SpdmDheExchangeStruct, Box<dyn SpdmDheKeyExchange>)> {
+    match dhe_algo {
+        SpdmDheAlgo::SM2_P256 => SpdmDheKeyExchangeSm2::generate_key_pair(),
+        #[cfg(feature = "spdm-ring")]
+        _ => (FALLBACK.generate_key_pair_cb)(dhe_algo),
+        #[cfg(not(feature = "spdm-ring"))]
+        _ => None,
+    }
+}
+
+// The x coordinate of the ECDH product on the SM2 curve is the shared
+// secret, the DHE callbacks don't know which side initiated the exchange
+// as the SM2 key exchange protocol of GB/T 32918.3 would need.
+struct SpdmDheKeyExchangeSm2(Sm2PrivateKey);
+
+impl SpdmDheKeyExchange for SpdmDheKeyExchangeSm2 {
+    fn compute_final_key(
+        self: Box<Self>,
+        peer_pub_key: &SpdmDheExchangeStruct,
+    ) -> Option<SpdmDheFinalKeyStruct> {
+        let final_key = self.0.agree(peer_pub_key.as_ref())?;
+        Some(SpdmDheFinalKeyStruct::from(BytesMut::from(&final_key[..])))
+    }
+}
+
+impl SpdmDheKeyExchangeSm2 {
+    fn generate_key_pair() -> Option<(SpdmDheExchangeStruct, Box<dyn SpdmDheKeyExchange>)> {
+        let private_key = Sm2PrivateKey::generate()?;
+        let public_key = BytesMut::from(&private_key.public_key()?[..]);
+
+        let res: Box<dyn SpdmDheKeyExchange> = Box::new(Self(private_key));
+
+        Some((SpdmDheExchangeStruct::from(public_key), res))
+    }
+}
@@ -0,0 +1,78 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+extern crate alloc;
+use alloc::boxed::Box;
+
+use super::sm3::Sm3;
+use crate::crypto::{SpdmHash, SpdmHashCtx};
+use crate::error::SpdmResult;
+use crate::msgs::{SpdmBaseHashAlgo, SpdmDigestStruct};
+use core::convert::TryFrom;
+
+#[cfg(feature = "spdm-ring")]
+use crate::crypto::spdm_ring::hash_impl::DEFAULT as FALLBACK;
+
+pub static DEFAULT: SpdmHash = SpdmHash {
+    hash_all_cb: hash_all,
+    hash_ctx_init_cb: hash_ctx_init,
+    hash_ctx_update_cb: hash_ctx_update,
+    hash_ctx_dup_cb: hash_ctx_dup,
+    hash_ctx_finalize_cb: hash_ctx_finalize,
+};
+
+fn hash_all(base_hash_algo: SpdmBaseHashAlgo, data: &[u8]) -> Option<SpdmDigestStruct> {
+    match base_hash_algo {
+        SpdmBaseHashAlgo::TPM_ALG_SM3_256 => {
+            SpdmDigestStruct::try_from(&Sm3::digest(data)[..]).ok()
+        }
+        #[cfg(feature = "spdm-ring")]
+        _ => (FALLBACK.hash_all_cb)(base_hash_algo, data),
+        #[cfg(not(feature = "spdm-ring"))]
+        _ => None,
+    }
+}
+
+fn hash_ctx_init(base_hash_algo: SpdmBaseHashAlgo) -> Option<SpdmHashCtx> {
+    match base_hash_algo {
+        SpdmBaseHashAlgo::TPM_ALG_SM3_256 => Some(Box::new(Sm3::default())),
+        #[cfg(feature = "spdm-ring")]
+        _ => (FALLBACK.hash_ctx_init_cb)(base_hash_algo),
+        #[cfg(not(feature = "spdm-ring"))]
+        _ => None,
+    }
+}
+
+fn hash_ctx_update(ctx: &mut SpdmHashCtx, data: &[u8]) -> SpdmResult {
+    match ctx.downcast_mut::<Sm3>() {
+        Some(ctx) => {
+            ctx.update(data);
+            Ok(())
+        }
+        #[cfg(feature = "spdm-ring")]
+        None => (FALLBACK.hash_ctx_update_cb)(ctx, data),
+        #[cfg(not(feature = "spdm-ring"))]
+        None => spdm_result_err!(EINVAL, "not an SM3 hash context"),
+    }
+}
+
+fn hash_ctx_dup(ctx: &SpdmHashCtx) -> Option<SpdmHashCtx> {
+    match ctx.downcast_ref::<Sm3>() {
+        Some(ctx) => Some(Box::new(ctx.clone())),
+        #[cfg(feature = "spdm-ring")]
+        None => (FALLBACK.hash_ctx_dup_cb)(ctx),
+        #[cfg(not(feature = "spdm-ring"))]
+        None => None,
+    }
+}
+
+fn hash_ctx_finalize(ctx: SpdmHashCtx) -> Option<SpdmDigestStruct> {
+    match ctx.downcast::<Sm3>() {
+        Ok(ctx) => SpdmDigestStruct::try_from(&ctx.finish()[..]).ok(),
+        #[cfg(feature = "spdm-ring")]
+        Err(ctx) => (FALLBACK.hash_ctx_finalize_cb)(ctx),
+        #[cfg(not(feature = "spdm-ring"))]
+        Err(_) => None,
+    }
+}
@@ -0,0 +1,56 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+use super::sm3::{HmacSm3, SM3_DIGEST_SIZE};
+use crate::crypto::SpdmHkdf;
+use crate::msgs::{SpdmBaseHashAlgo, SpdmDigestStruct, SPDM_MAX_HASH_SIZE};
+
+#[cfg(feature = "spdm-ring")]
+use crate::crypto::spdm_ring::hkdf_impl::DEFAULT as FALLBACK;
+
+pub static DEFAULT: SpdmHkdf = SpdmHkdf {
+    hkdf_expand_cb: hkdf_expand,
+};
+
+fn hkdf_expand(
+    hash_algo: SpdmBaseHashAlgo,
+    pk: &[u8],
+    info: &[u8],
+    out_size: u16,
+) -> Option<SpdmDigestStruct> {
+    match hash_algo {
+        SpdmBaseHashAlgo::TPM_ALG_SM3_256 => hkdf_expand_sm3(pk, info, out_size),
+        #[cfg(feature = "spdm-ring")]
+        _ => (FALLBACK.hkdf_expand_cb)(hash_algo, pk, info, out_size),
+        #[cfg(not(feature = "spdm-ring"))]
+        _ => None,
+    }
+}
+
+// HKDF-Expand of RFC 5869 with HMAC-SM3
+fn hkdf_expand_sm3(pk: &[u8], info: &[u8], out_size: u16) -> Option<SpdmDigestStruct> {
+    if out_size as usize > SPDM_MAX_HASH_SIZE {
+        return None;
+    }
+    let mut ret = SpdmDigestStruct {
+        data_size: out_size,
+        ..Default::default()
+    };
+    let mut t = [0u8; SM3_DIGEST_SIZE];
+    for (i, chunk) in ret.data[..out_size as usize]
+        .chunks_mut(SM3_DIGEST_SIZE)
+        .enumerate()
+    {
+        // T(i) = HMAC(PRK, T(i - 1) || info || i)
+        let mut ctx = HmacSm3::new(pk);
+        if i != 0 {
+            ctx.update(&t);
+        }
+        ctx.update(info);
+        ctx.update(&[i as u8 + 1]);
+        t = ctx.finish();
+        chunk.copy_from_slice(&t[..chunk.len()]);
+    }
+    Some(ret)
+}
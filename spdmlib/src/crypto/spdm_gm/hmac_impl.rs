@@ -0,0 +1,52 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+use super::sm3::hmac_sm3;
+use crate::common::constant_time_eq;
+use crate::crypto::SpdmHmac;
+use crate::error::SpdmResult;
+use crate::msgs::{SpdmBaseHashAlgo, SpdmDigestStruct};
+use core::convert::TryFrom;
+
+#[cfg(feature = "spdm-ring")]
+use crate::crypto::spdm_ring::hmac_impl::DEFAULT as FALLBACK;
+
+pub static DEFAULT: SpdmHmac = SpdmHmac {
+    hmac_cb: hmac,
+    hmac_verify_cb: hmac_verify,
+};
+
+fn hmac(base_hash_algo: SpdmBaseHashAlgo, key: &[u8], data: &[u8]) -> Option<SpdmDigestStruct> {
+    match base_hash_algo {
+        SpdmBaseHashAlgo::TPM_ALG_SM3_256 => {
+            SpdmDigestStruct::try_from(&hmac_sm3(key, data)[..]).ok()
+        }
+        #[cfg(feature = "spdm-ring")]
+        _ => (FALLBACK.hmac_cb)(base_hash_algo, key, data),
+        #[cfg(not(feature = "spdm-ring"))]
+        _ => None,
+    }
+}
+
+fn hmac_verify(
+    base_hash_algo: SpdmBaseHashAlgo,
+    key: &[u8],
+    data: &[u8],
+    hmac: &SpdmDigestStruct,
+) -> SpdmResult {
+    match base_hash_algo {
+        SpdmBaseHashAlgo::TPM_ALG_SM3_256 => {
+            hmac.check_size(base_hash_algo)?;
+            if constant_time_eq(&hmac_sm3(key, data), hmac.as_slice()) {
+                Ok(())
+            } else {
+                spdm_result_err!(EFAULT)
+            }
+        }
+        #[cfg(feature = "spdm-ring")]
+        _ => (FALLBACK.hmac_verify_cb)(base_hash_algo, key, data, hmac),
+        #[cfg(not(feature = "spdm-ring"))]
+        _ => spdm_result_err!(EINVAL, "unsupported hash algorithm"),
+    }
+}
@@ -0,0 +1,19 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+// SM2, SM3 and SM4 of the Chinese commercial cryptography standards. Every
+// other algorithm is passed on to the ring backend if it is enabled.
+
+pub mod aead_impl;
+pub mod asym_sign_impl;
+pub mod asym_verify_impl;
+pub mod dhe_impl;
+pub mod hash_impl;
+pub mod hkdf_impl;
+pub mod hmac_impl;
+
+mod der;
+mod sm2;
+mod sm3;
+mod sm4;
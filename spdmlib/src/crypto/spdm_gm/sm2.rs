@@ -0,0 +1,660 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+// SM2 signature and key agreement over the curve recommended in
+// GB/T 32918.5-2017. Scalars are multiplied with a Montgomery ladder, but
+// neither the point nor the field operations are constant time.
+
+use super::sm3::Sm3;
+use crate::crypto;
+
+pub const SM2_SCALAR_SIZE: usize = 32;
+pub const SM2_POINT_SIZE: usize = 64;
+pub const SM2_SIGNATURE_SIZE: usize = 64;
+
+/// ID signed with when no other one is agreed on, GB/T 35276-2017.
+pub const SM2_DEFAULT_ID: &[u8] = b"1234567812345678";
+
+// 256 bit integer, least significant limb first
+type U256 = [u64; 4];
+
+const P: U256 = [
+    0xFFFF_FFFF_FFFF_FFFF,
+    0xFFFF_FFFF_0000_0000,
+    0xFFFF_FFFF_FFFF_FFFF,
+    0xFFFF_FFFE_FFFF_FFFF,
+];
+const N: U256 = [
+    0x53BB_F409_39D5_4123,
+    0x7203_DF6B_21C6_052B,
+    0xFFFF_FFFF_FFFF_FFFF,
+    0xFFFF_FFFE_FFFF_FFFF,
+];
+const A: U256 = [
+    0xFFFF_FFFF_FFFF_FFFC,
+    0xFFFF_FFFF_0000_0000,
+    0xFFFF_FFFF_FFFF_FFFF,
+    0xFFFF_FFFE_FFFF_FFFF,
+];
+const B: U256 = [
+    0xDDBC_BD41_4D94_0E93,
+    0xF397_89F5_15AB_8F92,
+    0x4D5A_9E4B_CF65_09A7,
+    0x28E9_FA9E_9D9F_5E34,
+];
+const GX: U256 = [
+    0x715A_4589_334C_74C7,
+    0x8FE3_0BBF_F266_0BE1,
+    0x5F99_0446_6A39_C994,
+    0x32C4_AE2C_1F19_8119,
+];
+const GY: U256 = [
+    0x02DF_32E5_2139_F0A0,
+    0xD0A9_877C_C62A_4740,
+    0x59BD_CEE3_6B69_2153,
+    0xBC37_36A2_F4F6_779C,
+];
+
+const ZERO: U256 = [0; 4];
+const ONE: U256 = [1, 0, 0, 0];
+
+fn from_bytes(bytes: &[u8]) -> U256 {
+    let mut v = ZERO;
+    for (i, chunk) in bytes.chunks(8).enumerate() {
+        let mut limb = [0u8; 8];
+        limb.copy_from_slice(chunk);
+        v[3 - i] = u64::from_be_bytes(limb);
+    }
+    v
+}
+
+fn to_bytes(v: &U256, bytes: &mut [u8]) {
+    for (i, chunk) in bytes.chunks_mut(8).enumerate() {
+        chunk.copy_from_slice(&v[3 - i].to_be_bytes());
+    }
+}
+
+fn is_zero(a: &U256) -> bool {
+    a.iter().all(|limb| *limb == 0)
+}
+
+fn less_than(a: &U256, b: &U256) -> bool {
+    for i in (0..4).rev() {
+        if a[i] != b[i] {
+            return a[i] < b[i];
+        }
+    }
+    false
+}
+
+fn add(a: &U256, b: &U256) -> (U256, bool) {
+    let mut r = ZERO;
+    let mut carry = 0u128;
+    for i in 0..4 {
+        let s = a[i] as u128 + b[i] as u128 + carry;
+        r[i] = s as u64;
+        carry = s >> 64;
+    }
+    (r, carry != 0)
+}
+
+fn sub(a: &U256, b: &U256) -> (U256, bool) {
+    let mut r = ZERO;
+    let mut borrow = false;
+    for i in 0..4 {
+        let (d, b1) = a[i].overflowing_sub(b[i]);
+        let (d, b2) = d.overflowing_sub(borrow as u64);
+        r[i] = d;
+        borrow = b1 || b2;
+    }
+    (r, borrow)
+}
+
+/// Arithmetic modulo the odd `m`, with the operands of `mul` in the
+/// Montgomery domain.
+struct Modulus {
+    m: U256,
+    // -m^-1 mod 2^64
+    m_inv: u64,
+    // 2^512 mod m
+    r2: U256,
+}
+
+impl Modulus {
+    fn new(m: U256) -> Self {
+        let mut inv = 1u64;
+        for _ in 0..6 {
+            inv = inv.wrapping_mul(2u64.wrapping_sub(m[0].wrapping_mul(inv)));
+        }
+        let mut modulus = Modulus {
+            m,
+            m_inv: inv.wrapping_neg(),
+            r2: ZERO,
+        };
+        // 2^256 mod m, doubled 256 times
+        let mut r2 = sub(&ZERO, &m).0;
+        for _ in 0..256 {
+            r2 = modulus.add(&r2, &r2);
+        }
+        modulus.r2 = r2;
+        modulus
+    }
+
+    // a mod m for a < 2^256, m > 2^255
+    fn reduce(&self, a: &U256) -> U256 {
+        if less_than(a, &self.m) {
+            *a
+        } else {
+            sub(a, &self.m).0
+        }
+    }
+
+    fn add(&self, a: &U256, b: &U256) -> U256 {
+        let (r, carry) = add(a, b);
+        if carry || !less_than(&r, &self.m) {
+            sub(&r, &self.m).0
+        } else {
+            r
+        }
+    }
+
+    fn sub(&self, a: &U256, b: &U256) -> U256 {
+        let (r, borrow) = sub(a, b);
+        if borrow {
+            add(&r, &self.m).0
+        } else {
+            r
+        }
+    }
+
+    fn mul(&self, a: &U256, b: &U256) -> U256 {
+        let mut t = [0u64; 6];
+        for i in 0..4 {
+            let mut carry = 0u128;
+            for j in 0..4 {
+                let s = t[j] as u128 + (a[j] as u128) * (b[i] as u128) + carry;
+                t[j] = s as u64;
+                carry = s >> 64;
+            }
+            let s = t[4] as u128 + carry;
+            t[4] = s as u64;
+            t[5] = (s >> 64) as u64;
+
+            let q = t[0].wrapping_mul(self.m_inv);
+            let mut carry = (t[0] as u128 + (q as u128) * (self.m[0] as u128)) >> 64;
+            for j in 1..4 {
+                let s = t[j] as u128 + (q as u128) * (self.m[j] as u128) + carry;
+                t[j - 1] = s as u64;
+                carry = s >> 64;
+            }
+            let s = t[4] as u128 + carry;
+            t[3] = s as u64;
+            t[4] = t[5] + (s >> 64) as u64;
+            t[5] = 0;
+        }
+        let r = [t[0], t[1], t[2], t[3]];
+        if t[4] != 0 || !less_than(&r, &self.m) {
+            sub(&r, &self.m).0
+        } else {
+            r
+        }
+    }
+
+    fn to_mont(&self, a: &U256) -> U256 {
+        self.mul(a, &self.r2)
+    }
+
+    fn from_mont(&self, a: &U256) -> U256 {
+        self.mul(a, &ONE)
+    }
+
+    // a^-1 in the Montgomery domain, a^(m-2) as m is prime
+    fn inv(&self, a: &U256) -> U256 {
+        let e = sub(&self.m, &[2, 0, 0, 0]).0;
+        let mut r = self.to_mont(&ONE);
+        for i in (0..256).rev() {
+            r = self.mul(&r, &r);
+            if (e[i / 64] >> (i % 64)) & 1 == 1 {
+                r = self.mul(&r, a);
+            }
+        }
+        r
+    }
+}
+
+// Jacobian coordinates in the Montgomery domain, Z = 0 is the point at
+// infinity.
+#[derive(Clone, Copy)]
+struct Point {
+    x: U256,
+    y: U256,
+    z: U256,
+}
+
+struct Curve {
+    p: Modulus,
+    n: Modulus,
+}
+
+impl Curve {
+    fn new() -> Self {
+        Curve {
+            p: Modulus::new(P),
+            n: Modulus::new(N),
+        }
+    }
+
+    fn infinity(&self) -> Point {
+        Point {
+            x: ZERO,
+            y: ZERO,
+            z: ZERO,
+        }
+    }
+
+    fn generator(&self) -> Point {
+        Point {
+            x: self.p.to_mont(&GX),
+            y: self.p.to_mont(&GY),
+            z: self.p.to_mont(&ONE),
+        }
+    }
+
+    // the point of the coordinates `x` || `y` if it is on the curve
+    fn point_from_bytes(&self, bytes: &[u8]) -> Option<Point> {
+        if bytes.len() != SM2_POINT_SIZE {
+            return None;
+        }
+        let x = from_bytes(&bytes[..SM2_SCALAR_SIZE]);
+        let y = from_bytes(&bytes[SM2_SCALAR_SIZE..]);
+        if !less_than(&x, &P) || !less_than(&y, &P) {
+            return None;
+        }
+        let fp = &self.p;
+        let (x, y) = (fp.to_mont(&x), fp.to_mont(&y));
+        // y^2 = x^3 + ax + b
+        let rhs = fp.add(
+            &fp.mul(&fp.add(&fp.mul(&x, &x), &fp.to_mont(&A)), &x),
+            &fp.to_mont(&B),
+        );
+        if fp.mul(&y, &y) != rhs {
+            return None;
+        }
+        Some(Point {
+            x,
+            y,
+            z: fp.to_mont(&ONE),
+        })
+    }
+
+    // affine x || y, None for the point at infinity
+    fn point_to_bytes(&self, point: &Point) -> Option<[u8; SM2_POINT_SIZE]> {
+        if is_zero(&point.z) {
+            return None;
+        }
+        let fp = &self.p;
+        let z_inv = fp.inv(&point.z);
+        let z_inv2 = fp.mul(&z_inv, &z_inv);
+        let x = fp.from_mont(&fp.mul(&point.x, &z_inv2));
+        let y = fp.from_mont(&fp.mul(&point.y, &fp.mul(&z_inv2, &z_inv)));
+        let mut bytes = [0u8; SM2_POINT_SIZE];
+        to_bytes(&x, &mut bytes[..SM2_SCALAR_SIZE]);
+        to_bytes(&y, &mut bytes[SM2_SCALAR_SIZE..]);
+        Some(bytes)
+    }
+
+    // dbl-2001-b, a = -3
+    fn double(&self, p1: &Point) -> Point {
+        let fp = &self.p;
+        if is_zero(&p1.z) || is_zero(&p1.y) {
+            return self.infinity();
+        }
+        let delta = fp.mul(&p1.z, &p1.z);
+        let gamma = fp.mul(&p1.y, &p1.y);
+        let beta = fp.mul(&p1.x, &gamma);
+        let t = fp.mul(&fp.sub(&p1.x, &delta), &fp.add(&p1.x, &delta));
+        let alpha = fp.add(&fp.add(&t, &t), &t);
+        let beta4 = fp.add(&fp.add(&beta, &beta), &fp.add(&beta, &beta));
+        let x = fp.sub(&fp.mul(&alpha, &alpha), &fp.add(&beta4, &beta4));
+        let yz = fp.add(&p1.y, &p1.z);
+        let z = fp.sub(&fp.sub(&fp.mul(&yz, &yz), &gamma), &delta);
+        let gamma2 = fp.mul(&gamma, &gamma);
+        let gamma8 = fp.add(
+            &fp.add(&fp.add(&gamma2, &gamma2), &fp.add(&gamma2, &gamma2)),
+            &fp.add(&fp.add(&gamma2, &gamma2), &fp.add(&gamma2, &gamma2)),
+        );
+        let y = fp.sub(&fp.mul(&alpha, &fp.sub(&beta4, &x)), &gamma8);
+        Point { x, y, z }
+    }
+
+    fn add(&self, p1: &Point, p2: &Point) -> Point {
+        let fp = &self.p;
+        if is_zero(&p1.z) {
+            return *p2;
+        }
+        if is_zero(&p2.z) {
+            return *p1;
+        }
+        let z1z1 = fp.mul(&p1.z, &p1.z);
+        let z2z2 = fp.mul(&p2.z, &p2.z);
+        let u1 = fp.mul(&p1.x, &z2z2);
+        let u2 = fp.mul(&p2.x, &z1z1);
+        let s1 = fp.mul(&p1.y, &fp.mul(&z2z2, &p2.z));
+        let s2 = fp.mul(&p2.y, &fp.mul(&z1z1, &p1.z));
+        let h = fp.sub(&u2, &u1);
+        let r = fp.sub(&s2, &s1);
+        if is_zero(&h) {
+            return if is_zero(&r) {
+                self.double(p1)
+            } else {
+                self.infinity()
+            };
+        }
+        let hh = fp.mul(&h, &h);
+        let hhh = fp.mul(&hh, &h);
+        let v = fp.mul(&u1, &hh);
+        let x = fp.sub(&fp.sub(&fp.mul(&r, &r), &hhh), &fp.add(&v, &v));
+        let y = fp.sub(&fp.mul(&r, &fp.sub(&v, &x)), &fp.mul(&s1, &hhh));
+        let z = fp.mul(&fp.mul(&p1.z, &p2.z), &h);
+        Point { x, y, z }
+    }
+
+    fn mul(&self, k: &U256, point: &Point) -> Point {
+        let mut r0 = self.infinity();
+        let mut r1 = *point;
+        for i in (0..256).rev() {
+            let bit = (k[i / 64] >> (i % 64)) & 1;
+            cswap(&mut r0, &mut r1, bit);
+            r1 = self.add(&r0, &r1);
+            r0 = self.double(&r0);
+            cswap(&mut r0, &mut r1, bit);
+        }
+        r0
+    }
+
+    // a random scalar in [1, n - 1]
+    fn random_scalar(&self) -> Option<U256> {
+        let mut bytes = [0u8; SM2_SCALAR_SIZE];
+        loop {
+            crypto::rand::get_random(&mut bytes).ok()?;
+            let k = from_bytes(&bytes);
+            if !is_zero(&k) && less_than(&k, &N) {
+                return Some(k);
+            }
+        }
+    }
+}
+
+fn cswap_limbs(u: &mut U256, v: &mut U256, mask: u64) {
+    for (a, b) in u.iter_mut().zip(v.iter_mut()) {
+        let t = mask & (*a ^ *b);
+        *a ^= t;
+        *b ^= t;
+    }
+}
+
+// swap `a` and `b` if `bit` is 1, without branching on it
+fn cswap(a: &mut Point, b: &mut Point, bit: u64) {
+    let mask = bit.wrapping_neg();
+    cswap_limbs(&mut a.x, &mut b.x, mask);
+    cswap_limbs(&mut a.y, &mut b.y, mask);
+    cswap_limbs(&mut a.z, &mut b.z, mask);
+}
+
+/// The private key `d` of a key pair, in [1, n - 2].
+pub struct Sm2PrivateKey {
+    d: U256,
+}
+
+impl Sm2PrivateKey {
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != SM2_SCALAR_SIZE {
+            return None;
+        }
+        let d = from_bytes(bytes);
+        if is_zero(&d) || !less_than(&add(&d, &ONE).0, &N) {
+            return None;
+        }
+        Some(Sm2PrivateKey { d })
+    }
+
+    pub fn generate() -> Option<Self> {
+        let curve = Curve::new();
+        loop {
+            let d = curve.random_scalar()?;
+            if less_than(&add(&d, &ONE).0, &N) {
+                return Some(Sm2PrivateKey { d });
+            }
+        }
+    }
+
+    /// Public key, x || y.
+    pub fn public_key(&self) -> Option<[u8; SM2_POINT_SIZE]> {
+        let curve = Curve::new();
+        curve.point_to_bytes(&curve.mul(&self.d, &curve.generator()))
+    }
+
+    /// The x coordinate of the product of the key and `peer_public_key`.
+    pub fn agree(&self, peer_public_key: &[u8]) -> Option<[u8; SM2_SCALAR_SIZE]> {
+        let curve = Curve::new();
+        let peer = curve.point_from_bytes(peer_public_key)?;
+        let shared = curve.point_to_bytes(&curve.mul(&self.d, &peer))?;
+        let mut x = [0u8; SM2_SCALAR_SIZE];
+        x.copy_from_slice(&shared[..SM2_SCALAR_SIZE]);
+        Some(x)
+    }
+
+    /// Sign `data` as `id` with the public key `public_key`, r || s.
+    pub fn sign(
+        &self,
+        id: &[u8],
+        public_key: &[u8],
+        data: &[u8],
+    ) -> Option<[u8; SM2_SIGNATURE_SIZE]> {
+        let curve = Curve::new();
+        let e = curve
+            .n
+            .reduce(&from_bytes(&message_digest(id, public_key, data)?));
+        loop {
+            let k = curve.random_scalar()?;
+            if let Some(signature) = self.sign_digest(&curve, &e, &k) {
+                return Some(signature);
+            }
+        }
+    }
+
+    // r || s of the digest `e` with the nonce `k`, None if `k` has to be
+    // replaced
+    fn sign_digest(&self, curve: &Curve, e: &U256, k: &U256) -> Option<[u8; SM2_SIGNATURE_SIZE]> {
+        let fnn = &curve.n;
+        let kg = curve.point_to_bytes(&curve.mul(k, &curve.generator()))?;
+        let x1 = fnn.reduce(&from_bytes(&kg[..SM2_SCALAR_SIZE]));
+        let r = fnn.add(e, &x1);
+        if is_zero(&r) || is_zero(&fnn.add(&r, k)) {
+            return None;
+        }
+        // s = (1 + d)^-1 * (k - r * d)
+        let d = fnn.to_mont(&self.d);
+        let rd = fnn.mul(&fnn.to_mont(&r), &d);
+        let k_rd = fnn.sub(&fnn.to_mont(k), &rd);
+        let d1_inv = fnn.inv(&fnn.add(&d, &fnn.to_mont(&ONE)));
+        let s = fnn.from_mont(&fnn.mul(&d1_inv, &k_rd));
+        if is_zero(&s) {
+            return None;
+        }
+        let mut signature = [0u8; SM2_SIGNATURE_SIZE];
+        to_bytes(&r, &mut signature[..SM2_SCALAR_SIZE]);
+        to_bytes(&s, &mut signature[SM2_SCALAR_SIZE..]);
+        Some(signature)
+    }
+}
+
+/// Verify the r || s `signature` of `data` signed as `id` with the key of
+/// `public_key`.
+pub fn sm2_verify(id: &[u8], public_key: &[u8], data: &[u8], signature: &[u8]) -> bool {
+    if signature.len() != SM2_SIGNATURE_SIZE {
+        return false;
+    }
+    let curve = Curve::new();
+    let public_point = match curve.point_from_bytes(public_key) {
+        Some(point) => point,
+        None => return false,
+    };
+    let fnn = &curve.n;
+    let r = from_bytes(&signature[..SM2_SCALAR_SIZE]);
+    let s = from_bytes(&signature[SM2_SCALAR_SIZE..]);
+    if is_zero(&r) || !less_than(&r, &N) || is_zero(&s) || !less_than(&s, &N) {
+        return false;
+    }
+    let t = fnn.add(&r, &s);
+    if is_zero(&t) {
+        return false;
+    }
+    let e = match message_digest(id, public_key, data) {
+        Some(e) => fnn.reduce(&from_bytes(&e)),
+        None => return false,
+    };
+    let point = curve.add(
+        &curve.mul(&s, &curve.generator()),
+        &curve.mul(&t, &public_point),
+    );
+    match curve.point_to_bytes(&point) {
+        Some(bytes) => {
+            let x1 = fnn.reduce(&from_bytes(&bytes[..SM2_SCALAR_SIZE]));
+            fnn.add(&e, &x1) == r
+        }
+        None => false,
+    }
+}
+
+// SM3(Z || data), Z = SM3(ENTL || ID || a || b || xG || yG || xA || yA)
+fn message_digest(id: &[u8], public_key: &[u8], data: &[u8]) -> Option<[u8; 32]> {
+    if id.len() > 0x1FFF || public_key.len() != SM2_POINT_SIZE {
+        return None;
+    }
+    let mut ctx = Sm3::default();
+    ctx.update(&((id.len() * 8) as u16).to_be_bytes());
+    ctx.update(id);
+    let mut bytes = [0u8; SM2_SCALAR_SIZE];
+    for v in [A, B, GX, GY].iter() {
+        to_bytes(v, &mut bytes);
+        ctx.update(&bytes);
+    }
+    ctx.update(public_key);
+    let z = ctx.finish();
+
+    let mut ctx = Sm3::default();
+    ctx.update(&z);
+    ctx.update(data);
+    Some(ctx.finish())
+}
+
+#[test]
+fn test_sm2_curve() {
+    let curve = Curve::new();
+    let g = curve.generator();
+    // n * G is the point at infinity, (n - 1) * G is -G
+    assert!(curve.point_to_bytes(&curve.mul(&N, &g)).is_none());
+    let minus_g = curve
+        .point_to_bytes(&curve.mul(&sub(&N, &ONE).0, &g))
+        .unwrap();
+    let mut expected = [0u8; SM2_POINT_SIZE];
+    to_bytes(&GX, &mut expected[..SM2_SCALAR_SIZE]);
+    to_bytes(&sub(&P, &GY).0, &mut expected[SM2_SCALAR_SIZE..]);
+    assert_eq!(minus_g[..], expected[..]);
+    // 3G, added and multiplied
+    assert_eq!(
+        curve
+            .point_to_bytes(&curve.add(&curve.double(&g), &g))
+            .unwrap()[..],
+        curve.point_to_bytes(&curve.mul(&[3, 0, 0, 0], &g)).unwrap()[..]
+    );
+    assert!(curve
+        .point_from_bytes(&curve.point_to_bytes(&g).unwrap())
+        .is_some());
+    assert!(curve.point_from_bytes(&[1u8; SM2_POINT_SIZE]).is_none());
+}
+
+#[test]
+fn test_sm2_sign_verify() {
+    let key = Sm2PrivateKey::generate().unwrap();
+    let public_key = key.public_key().unwrap();
+    let signature = key.sign(SM2_DEFAULT_ID, &public_key, b"message").unwrap();
+    assert!(sm2_verify(
+        SM2_DEFAULT_ID,
+        &public_key,
+        b"message",
+        &signature
+    ));
+    assert!(!sm2_verify(
+        SM2_DEFAULT_ID,
+        &public_key,
+        b"messagf",
+        &signature
+    ));
+    assert!(!sm2_verify(b"ALICE", &public_key, b"message", &signature));
+    let other_public_key = Sm2PrivateKey::generate().unwrap().public_key().unwrap();
+    assert!(!sm2_verify(
+        SM2_DEFAULT_ID,
+        &other_public_key,
+        b"message",
+        &signature
+    ));
+}
+
+#[test]
+fn test_sm2_agree() {
+    let key1 = Sm2PrivateKey::generate().unwrap();
+    let key2 = Sm2PrivateKey::generate().unwrap();
+    assert_eq!(
+        key1.agree(&key2.public_key().unwrap()).unwrap(),
+        key2.agree(&key1.public_key().unwrap()).unwrap()
+    );
+    assert!(key1.agree(&[0u8; SM2_POINT_SIZE]).is_none());
+}
+
+#[test]
+fn test_sm2_example() {
+    // GB/T 32918.5-2017 appendix A
+    let key = Sm2PrivateKey::from_bytes(&[
+        0x39, 0x45, 0x20, 0x8f, 0x7b, 0x21, 0x44, 0xb1, 0x3f, 0x36, 0xe3, 0x8a, 0xc6, 0xd3, 0x9f,
+        0x95, 0x88, 0x93, 0x93, 0x69, 0x28, 0x60, 0xb5, 0x1a, 0x42, 0xfb, 0x81, 0xef, 0x4d, 0xf7,
+        0xc5, 0xb8,
+    ])
+    .unwrap();
+    let public_key = key.public_key().unwrap();
+    assert_eq!(
+        public_key[..],
+        [
+            0x09, 0xf9, 0xdf, 0x31, 0x1e, 0x54, 0x21, 0xa1, 0x50, 0xdd, 0x7d, 0x16, 0x1e, 0x4b,
+            0xc5, 0xc6, 0x72, 0x17, 0x9f, 0xad, 0x18, 0x33, 0xfc, 0x07, 0x6b, 0xb0, 0x8f, 0xf3,
+            0x56, 0xf3, 0x50, 0x20, 0xcc, 0xea, 0x49, 0x0c, 0xe2, 0x67, 0x75, 0xa5, 0x2d, 0xc6,
+            0xea, 0x71, 0x8c, 0xc1, 0xaa, 0x60, 0x0a, 0xed, 0x05, 0xfb, 0xf3, 0x5e, 0x08, 0x4a,
+            0x66, 0x32, 0xf6, 0x07, 0x2d, 0xa9, 0xad, 0x13
+        ][..]
+    );
+
+    let id = SM2_DEFAULT_ID;
+    let curve = Curve::new();
+    let e = curve.n.reduce(&from_bytes(
+        &message_digest(id, &public_key, b"message digest").unwrap(),
+    ));
+    let k = from_bytes(&[
+        0x59, 0x27, 0x6e, 0x27, 0xd5, 0x06, 0x86, 0x1a, 0x16, 0x68, 0x0f, 0x3a, 0xd9, 0xc0, 0x2d,
+        0xcc, 0xef, 0x3c, 0xc1, 0xfa, 0x3c, 0xdb, 0xe4, 0xce, 0x6d, 0x54, 0xb8, 0x0d, 0xea, 0xc1,
+        0xbc, 0x21,
+    ]);
+    let signature = key.sign_digest(&curve, &e, &k).unwrap();
+    assert_eq!(
+        signature[..],
+        [
+            0xf5, 0xa0, 0x3b, 0x06, 0x48, 0xd2, 0xc4, 0x63, 0x0e, 0xea, 0xc5, 0x13, 0xe1, 0xbb,
+            0x81, 0xa1, 0x59, 0x44, 0xda, 0x38, 0x27, 0xd5, 0xb7, 0x41, 0x43, 0xac, 0x7e, 0xac,
+            0xee, 0xe7, 0x20, 0xb3, 0xb1, 0xb6, 0xaa, 0x29, 0xdf, 0x21, 0x2f, 0xd8, 0x76, 0x31,
+            0x82, 0xbc, 0x0d, 0x42, 0x1c, 0xa1, 0xbb, 0x90, 0x38, 0xfd, 0x1f, 0x7f, 0x42, 0xd4,
+            0x84, 0x0b, 0x69, 0xc4, 0x85, 0xbb, 0xc1, 0xaa
+        ][..]
+    );
+    assert!(sm2_verify(id, &public_key, b"message digest", &signature));
+}
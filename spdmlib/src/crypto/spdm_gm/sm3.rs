@@ -0,0 +1,215 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+// SM3 hash, GB/T 32905-2016.
+
+pub const SM3_DIGEST_SIZE: usize = 32;
+pub const SM3_BLOCK_SIZE: usize = 64;
+
+const IV: [u32; 8] = [
+    0x7380_166F,
+    0x4914_B2B9,
+    0x1724_42D7,
+    0xDA8A_0600,
+    0xA96F_30BC,
+    0x1631_38AA,
+    0xE38D_EE4D,
+    0xB0FB_0E4E,
+];
+
+fn p0(x: u32) -> u32 {
+    x ^ x.rotate_left(9) ^ x.rotate_left(17)
+}
+
+fn p1(x: u32) -> u32 {
+    x ^ x.rotate_left(15) ^ x.rotate_left(23)
+}
+
+#[derive(Clone)]
+pub struct Sm3 {
+    state: [u32; 8],
+    block: [u8; SM3_BLOCK_SIZE],
+    block_used: usize,
+    total_size: u64,
+}
+
+impl Default for Sm3 {
+    fn default() -> Self {
+        Sm3 {
+            state: IV,
+            block: [0u8; SM3_BLOCK_SIZE],
+            block_used: 0,
+            total_size: 0,
+        }
+    }
+}
+
+impl Sm3 {
+    pub fn digest(data: &[u8]) -> [u8; SM3_DIGEST_SIZE] {
+        let mut ctx = Sm3::default();
+        ctx.update(data);
+        ctx.finish()
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.total_size += data.len() as u64;
+        if self.block_used != 0 {
+            let size = (SM3_BLOCK_SIZE - self.block_used).min(data.len());
+            self.block[self.block_used..self.block_used + size].copy_from_slice(&data[..size]);
+            self.block_used += size;
+            data = &data[size..];
+            if self.block_used < SM3_BLOCK_SIZE {
+                return;
+            }
+            let block = self.block;
+            self.compress(&block);
+            self.block_used = 0;
+        }
+        while data.len() >= SM3_BLOCK_SIZE {
+            let (block, rest) = data.split_at(SM3_BLOCK_SIZE);
+            self.compress(block);
+            data = rest;
+        }
+        self.block[..data.len()].copy_from_slice(data);
+        self.block_used = data.len();
+    }
+
+    pub fn finish(mut self) -> [u8; SM3_DIGEST_SIZE] {
+        let bit_size = self.total_size.wrapping_mul(8);
+        let mut padding = [0u8; SM3_BLOCK_SIZE + 8];
+        padding[0] = 0x80;
+        let padding_size = if self.block_used < SM3_BLOCK_SIZE - 8 {
+            SM3_BLOCK_SIZE - 8 - self.block_used
+        } else {
+            2 * SM3_BLOCK_SIZE - 8 - self.block_used
+        };
+        padding[padding_size..padding_size + 8].copy_from_slice(&bit_size.to_be_bytes());
+        let total_size = self.total_size;
+        self.update(&padding[..padding_size + 8]);
+        self.total_size = total_size;
+
+        let mut digest = [0u8; SM3_DIGEST_SIZE];
+        for (chunk, word) in digest.chunks_mut(4).zip(self.state.iter()) {
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+        digest
+    }
+
+    fn compress(&mut self, block: &[u8]) {
+        let mut w = [0u32; 68];
+        for (i, chunk) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        }
+        for j in 16..68 {
+            w[j] = p1(w[j - 16] ^ w[j - 9] ^ w[j - 3].rotate_left(15))
+                ^ w[j - 13].rotate_left(7)
+                ^ w[j - 6];
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.state;
+        for j in 0..64 {
+            let t: u32 = if j < 16 { 0x79CC_4519 } else { 0x7A87_9D8A };
+            let ss1 = a
+                .rotate_left(12)
+                .wrapping_add(e)
+                .wrapping_add(t.rotate_left(j as u32 % 32))
+                .rotate_left(7);
+            let ss2 = ss1 ^ a.rotate_left(12);
+            let (ff, gg) = if j < 16 {
+                (a ^ b ^ c, e ^ f ^ g)
+            } else {
+                ((a & b) | (a & c) | (b & c), (e & f) | (!e & g))
+            };
+            let tt1 = ff
+                .wrapping_add(d)
+                .wrapping_add(ss2)
+                .wrapping_add(w[j] ^ w[j + 4]);
+            let tt2 = gg.wrapping_add(h).wrapping_add(ss1).wrapping_add(w[j]);
+            d = c;
+            c = b.rotate_left(9);
+            b = a;
+            a = tt1;
+            h = g;
+            g = f.rotate_left(19);
+            f = e;
+            e = p0(tt2);
+        }
+
+        for (state, v) in self.state.iter_mut().zip([a, b, c, d, e, f, g, h].iter()) {
+            *state ^= v;
+        }
+    }
+}
+
+/// HMAC-SM3, RFC 2104, over data passed in pieces.
+pub struct HmacSm3 {
+    inner: Sm3,
+    outer: Sm3,
+}
+
+impl HmacSm3 {
+    pub fn new(key: &[u8]) -> Self {
+        let mut block_key = [0u8; SM3_BLOCK_SIZE];
+        if key.len() > SM3_BLOCK_SIZE {
+            block_key[..SM3_DIGEST_SIZE].copy_from_slice(&Sm3::digest(key));
+        } else {
+            block_key[..key.len()].copy_from_slice(key);
+        }
+
+        let mut pad = [0u8; SM3_BLOCK_SIZE];
+        for (p, k) in pad.iter_mut().zip(block_key.iter()) {
+            *p = k ^ 0x36;
+        }
+        let mut inner = Sm3::default();
+        inner.update(&pad);
+        for (p, k) in pad.iter_mut().zip(block_key.iter()) {
+            *p = k ^ 0x5c;
+        }
+        let mut outer = Sm3::default();
+        outer.update(&pad);
+        HmacSm3 { inner, outer }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.inner.update(data);
+    }
+
+    pub fn finish(mut self) -> [u8; SM3_DIGEST_SIZE] {
+        self.outer.update(&self.inner.finish());
+        self.outer.finish()
+    }
+}
+
+pub fn hmac_sm3(key: &[u8], data: &[u8]) -> [u8; SM3_DIGEST_SIZE] {
+    let mut ctx = HmacSm3::new(key);
+    ctx.update(data);
+    ctx.finish()
+}
+
+#[test]
+fn test_sm3() {
+    // GB/T 32905-2016 examples
+    assert_eq!(
+        Sm3::digest(b"abc"),
+        [
+            0x66, 0xc7, 0xf0, 0xf4, 0x62, 0xee, 0xed, 0xd9, 0xd1, 0xf2, 0xd4, 0x6b, 0xdc, 0x10,
+            0xe4, 0xe2, 0x41, 0x67, 0xc4, 0x87, 0x5c, 0xf2, 0xf7, 0xa2, 0x29, 0x7d, 0xa0, 0x2b,
+            0x8f, 0x4b, 0xa8, 0xe0
+        ]
+    );
+    let data = [b'a', b'b', b'c', b'd'].repeat(16);
+    let expected = [
+        0xde, 0xbe, 0x9f, 0xf9, 0x22, 0x75, 0xb8, 0xa1, 0x38, 0x60, 0x48, 0x89, 0xc1, 0x8e, 0x5a,
+        0x4d, 0x6f, 0xdb, 0x70, 0xe5, 0x38, 0x7e, 0x57, 0x65, 0x29, 0x3d, 0xcb, 0xa3, 0x9c, 0x0c,
+        0x57, 0x32,
+    ];
+    assert_eq!(Sm3::digest(&data), expected);
+
+    // the same data in pieces
+    let mut ctx = Sm3::default();
+    for chunk in data.chunks(7) {
+        ctx.update(chunk);
+    }
+    assert_eq!(ctx.finish(), expected);
+}
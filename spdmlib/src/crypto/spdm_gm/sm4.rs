@@ -0,0 +1,262 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+// SM4 block cipher, GB/T 32907-2016, and SM4-GCM, RFC 8998.
+
+use crate::common::constant_time_eq;
+use crate::error::SpdmResult;
+
+pub const SM4_KEY_SIZE: usize = 16;
+pub const SM4_BLOCK_SIZE: usize = 16;
+pub const SM4_GCM_IV_SIZE: usize = 12;
+pub const SM4_GCM_TAG_SIZE: usize = 16;
+
+const SBOX: [u8; 256] = [
+    0xd6, 0x90, 0xe9, 0xfe, 0xcc, 0xe1, 0x3d, 0xb7, 0x16, 0xb6, 0x14, 0xc2, 0x28, 0xfb, 0x2c, 0x05,
+    0x2b, 0x67, 0x9a, 0x76, 0x2a, 0xbe, 0x04, 0xc3, 0xaa, 0x44, 0x13, 0x26, 0x49, 0x86, 0x06, 0x99,
+    0x9c, 0x42, 0x50, 0xf4, 0x91, 0xef, 0x98, 0x7a, 0x33, 0x54, 0x0b, 0x43, 0xed, 0xcf, 0xac, 0x62,
+    0xe4, 0xb3, 0x1c, 0xa9, 0xc9, 0x08, 0xe8, 0x95, 0x80, 0xdf, 0x94, 0xfa, 0x75, 0x8f, 0x3f, 0xa6,
+    0x47, 0x07, 0xa7, 0xfc, 0xf3, 0x73, 0x17, 0xba, 0x83, 0x59, 0x3c, 0x19, 0xe6, 0x85, 0x4f, 0xa8,
+    0x68, 0x6b, 0x81, 0xb2, 0x71, 0x64, 0xda, 0x8b, 0xf8, 0xeb, 0x0f, 0x4b, 0x70, 0x56, 0x9d, 0x35,
+    0x1e, 0x24, 0x0e, 0x5e, 0x63, 0x58, 0xd1, 0xa2, 0x25, 0x22, 0x7c, 0x3b, 0x01, 0x21, 0x78, 0x87,
+    0xd4, 0x00, 0x46, 0x57, 0x9f, 0xd3, 0x27, 0x52, 0x4c, 0x36, 0x02, 0xe7, 0xa0, 0xc4, 0xc8, 0x9e,
+    0xea, 0xbf, 0x8a, 0xd2, 0x40, 0xc7, 0x38, 0xb5, 0xa3, 0xf7, 0xf2, 0xce, 0xf9, 0x61, 0x15, 0xa1,
+    0xe0, 0xae, 0x5d, 0xa4, 0x9b, 0x34, 0x1a, 0x55, 0xad, 0x93, 0x32, 0x30, 0xf5, 0x8c, 0xb1, 0xe3,
+    0x1d, 0xf6, 0xe2, 0x2e, 0x82, 0x66, 0xca, 0x60, 0xc0, 0x29, 0x23, 0xab, 0x0d, 0x53, 0x4e, 0x6f,
+    0xd5, 0xdb, 0x37, 0x45, 0xde, 0xfd, 0x8e, 0x2f, 0x03, 0xff, 0x6a, 0x72, 0x6d, 0x6c, 0x5b, 0x51,
+    0x8d, 0x1b, 0xaf, 0x92, 0xbb, 0xdd, 0xbc, 0x7f, 0x11, 0xd9, 0x5c, 0x41, 0x1f, 0x10, 0x5a, 0xd8,
+    0x0a, 0xc1, 0x31, 0x88, 0xa5, 0xcd, 0x7b, 0xbd, 0x2d, 0x74, 0xd0, 0x12, 0xb8, 0xe5, 0xb4, 0xb0,
+    0x89, 0x69, 0x97, 0x4a, 0x0c, 0x96, 0x77, 0x7e, 0x65, 0xb9, 0xf1, 0x09, 0xc5, 0x6e, 0xc6, 0x84,
+    0x18, 0xf0, 0x7d, 0xec, 0x3a, 0xdc, 0x4d, 0x20, 0x79, 0xee, 0x5f, 0x3e, 0xd7, 0xcb, 0x39, 0x48,
+];
+
+const FK: [u32; 4] = [0xa3b1_bac6, 0x56aa_3350, 0x677d_9197, 0xb270_22dc];
+
+fn tau(a: u32) -> u32 {
+    let b = a.to_be_bytes();
+    u32::from_be_bytes([
+        SBOX[b[0] as usize],
+        SBOX[b[1] as usize],
+        SBOX[b[2] as usize],
+        SBOX[b[3] as usize],
+    ])
+}
+
+pub struct Sm4 {
+    round_keys: [u32; 32],
+}
+
+impl Sm4 {
+    pub fn new(key: &[u8; SM4_KEY_SIZE]) -> Self {
+        let mut k = [0u32; 4];
+        for (i, chunk) in key.chunks(4).enumerate() {
+            k[i] = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]) ^ FK[i];
+        }
+        let mut round_keys = [0u32; 32];
+        for (i, round_key) in round_keys.iter_mut().enumerate() {
+            let ck = u32::from_be_bytes([
+                ((4 * i) * 7) as u8,
+                ((4 * i + 1) * 7) as u8,
+                ((4 * i + 2) * 7) as u8,
+                ((4 * i + 3) * 7) as u8,
+            ]);
+            let b = tau(k[(i + 1) % 4] ^ k[(i + 2) % 4] ^ k[(i + 3) % 4] ^ ck);
+            k[i % 4] ^= b ^ b.rotate_left(13) ^ b.rotate_left(23);
+            *round_key = k[i % 4];
+        }
+        Sm4 { round_keys }
+    }
+
+    pub fn encrypt_block(&self, block: &mut [u8; SM4_BLOCK_SIZE]) {
+        let mut x = [0u32; 4];
+        for (i, chunk) in block.chunks(4).enumerate() {
+            x[i] = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        }
+        for (i, round_key) in self.round_keys.iter().enumerate() {
+            let b = tau(x[(i + 1) % 4] ^ x[(i + 2) % 4] ^ x[(i + 3) % 4] ^ round_key);
+            x[i % 4] ^=
+                b ^ b.rotate_left(2) ^ b.rotate_left(10) ^ b.rotate_left(18) ^ b.rotate_left(24);
+        }
+        for (chunk, word) in block.chunks_mut(4).zip(x.iter().rev()) {
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+    }
+}
+
+// multiplication in GF(2^128) of GCM, NIST SP 800-38D algorithm 1
+fn gf_mul(x: u128, y: u128) -> u128 {
+    let mut z = 0u128;
+    let mut v = y;
+    for i in 0..128 {
+        if (x >> (127 - i)) & 1 == 1 {
+            z ^= v;
+        }
+        v = if v & 1 == 1 {
+            (v >> 1) ^ (0xe1u128 << 120)
+        } else {
+            v >> 1
+        };
+    }
+    z
+}
+
+struct Ghash {
+    h: u128,
+    y: u128,
+}
+
+impl Ghash {
+    // `data` zero padded to a multiple of the block size
+    fn update(&mut self, data: &[u8]) {
+        for chunk in data.chunks(SM4_BLOCK_SIZE) {
+            let mut block = [0u8; SM4_BLOCK_SIZE];
+            block[..chunk.len()].copy_from_slice(chunk);
+            self.y = gf_mul(self.y ^ u128::from_be_bytes(block), self.h);
+        }
+    }
+}
+
+pub struct Sm4Gcm {
+    cipher: Sm4,
+}
+
+impl Sm4Gcm {
+    pub fn new(key: &[u8; SM4_KEY_SIZE]) -> Self {
+        Sm4Gcm {
+            cipher: Sm4::new(key),
+        }
+    }
+
+    // XOR `data` with the key stream starting at counter block `j0 + 1`
+    fn ctr(&self, j0: &[u8; SM4_BLOCK_SIZE], data: &mut [u8]) {
+        let mut counter = u32::from_be_bytes([j0[12], j0[13], j0[14], j0[15]]);
+        for chunk in data.chunks_mut(SM4_BLOCK_SIZE) {
+            counter = counter.wrapping_add(1);
+            let mut key_stream = *j0;
+            key_stream[12..].copy_from_slice(&counter.to_be_bytes());
+            self.cipher.encrypt_block(&mut key_stream);
+            for (d, k) in chunk.iter_mut().zip(key_stream.iter()) {
+                *d ^= k;
+            }
+        }
+    }
+
+    fn tag(&self, j0: &[u8; SM4_BLOCK_SIZE], aad: &[u8], cipher_text: &[u8]) -> [u8; 16] {
+        let mut h = [0u8; SM4_BLOCK_SIZE];
+        self.cipher.encrypt_block(&mut h);
+        let mut ghash = Ghash {
+            h: u128::from_be_bytes(h),
+            y: 0,
+        };
+        ghash.update(aad);
+        ghash.update(cipher_text);
+        let mut lengths = [0u8; SM4_BLOCK_SIZE];
+        lengths[..8].copy_from_slice(&(aad.len() as u64 * 8).to_be_bytes());
+        lengths[8..].copy_from_slice(&(cipher_text.len() as u64 * 8).to_be_bytes());
+        ghash.update(&lengths);
+
+        let mut tag = *j0;
+        self.cipher.encrypt_block(&mut tag);
+        (u128::from_be_bytes(tag) ^ ghash.y).to_be_bytes()
+    }
+
+    fn j0(iv: &[u8; SM4_GCM_IV_SIZE]) -> [u8; SM4_BLOCK_SIZE] {
+        let mut j0 = [0u8; SM4_BLOCK_SIZE];
+        j0[..SM4_GCM_IV_SIZE].copy_from_slice(iv);
+        j0[SM4_BLOCK_SIZE - 1] = 1;
+        j0
+    }
+
+    /// Encrypt `data` in place, returns the tag.
+    pub fn seal(
+        &self,
+        iv: &[u8; SM4_GCM_IV_SIZE],
+        aad: &[u8],
+        data: &mut [u8],
+    ) -> [u8; SM4_GCM_TAG_SIZE] {
+        let j0 = Self::j0(iv);
+        self.ctr(&j0, data);
+        self.tag(&j0, aad, data)
+    }
+
+    /// Decrypt `data` in place if `tag` matches, it is left untouched
+    /// otherwise.
+    pub fn open(
+        &self,
+        iv: &[u8; SM4_GCM_IV_SIZE],
+        aad: &[u8],
+        data: &mut [u8],
+        tag: &[u8],
+    ) -> SpdmResult {
+        let j0 = Self::j0(iv);
+        let expected = self.tag(&j0, aad, data);
+        if !constant_time_eq(tag, &expected) {
+            return spdm_result_err!(EFAULT);
+        }
+        self.ctr(&j0, data);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_sm4() {
+    // GB/T 32907-2016 example 1
+    let key = [
+        0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef, 0xfe, 0xdc, 0xba, 0x98, 0x76, 0x54, 0x32,
+        0x10,
+    ];
+    let mut block = key;
+    Sm4::new(&key).encrypt_block(&mut block);
+    assert_eq!(
+        block,
+        [
+            0x68, 0x1e, 0xdf, 0x34, 0xd2, 0x06, 0x96, 0x5e, 0x86, 0xb3, 0xe9, 0x4f, 0x53, 0x6e,
+            0x42, 0x46
+        ]
+    );
+}
+
+#[test]
+fn test_sm4_gcm() {
+    // RFC 8998 appendix A.1
+    let key = [
+        0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef, 0xfe, 0xdc, 0xba, 0x98, 0x76, 0x54, 0x32,
+        0x10,
+    ];
+    let iv = [
+        0x00, 0x00, 0x12, 0x34, 0x56, 0x78, 0x00, 0x00, 0x00, 0x00, 0xab, 0xcd,
+    ];
+    let aad = [
+        0xfe, 0xed, 0xfa, 0xce, 0xde, 0xad, 0xbe, 0xef, 0xfe, 0xed, 0xfa, 0xce, 0xde, 0xad, 0xbe,
+        0xef, 0xab, 0xad, 0xda, 0xd2,
+    ];
+    let mut plain_text = [0u8; 64];
+    for (i, chunk) in plain_text.chunks_mut(8).enumerate() {
+        let v = [0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff, 0xee, 0xaa][i];
+        for b in chunk.iter_mut() {
+            *b = v;
+        }
+    }
+    let cipher_text = [
+        0x17, 0xf3, 0x99, 0xf0, 0x8c, 0x67, 0xd5, 0xee, 0x19, 0xd0, 0xdc, 0x99, 0x69, 0xc4, 0xbb,
+        0x7d, 0x5f, 0xd4, 0x6f, 0xd3, 0x75, 0x64, 0x89, 0x06, 0x91, 0x57, 0xb2, 0x82, 0xbb, 0x20,
+        0x07, 0x35, 0xd8, 0x27, 0x10, 0xca, 0x5c, 0x22, 0xf0, 0xcc, 0xfa, 0x7c, 0xbf, 0x93, 0xd4,
+        0x96, 0xac, 0x15, 0xa5, 0x68, 0x34, 0xcb, 0xcf, 0x98, 0xc3, 0x97, 0xb4, 0x02, 0x4a, 0x26,
+        0x91, 0x23, 0x3b, 0x8d,
+    ];
+    let tag = [
+        0x83, 0xde, 0x35, 0x41, 0xe4, 0xc2, 0xb5, 0x81, 0x77, 0xe0, 0x65, 0xa9, 0xbf, 0x7b, 0x62,
+        0xec,
+    ];
+
+    let gcm = Sm4Gcm::new(&key);
+    let mut data = plain_text;
+    assert_eq!(gcm.seal(&iv, &aad, &mut data), tag);
+    assert_eq!(data[..], cipher_text[..]);
+
+    assert!(gcm.open(&iv, &aad, &mut data, &tag[..15]).is_err());
+    assert!(gcm.open(&iv, &aad[1..], &mut data, &tag).is_err());
+    assert!(gcm.open(&iv, &aad, &mut data, &tag).is_ok());
+    assert_eq!(data[..], plain_text[..]);
+}
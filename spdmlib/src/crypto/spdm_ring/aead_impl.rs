@@ -8,7 +8,23 @@ use bytes::BytesMut;
 
 use crate::msgs::SpdmAeadAlgo;
 
+#[cfg(not(feature = "fips"))]
 pub static DEFAULT: SpdmAead = SpdmAead {
+    supported_algo: SpdmAeadAlgo::from_bits_truncate(
+        SpdmAeadAlgo::AES_128_GCM.bits()
+            | SpdmAeadAlgo::AES_256_GCM.bits()
+            | SpdmAeadAlgo::CHACHA20_POLY1305.bits(),
+    ),
+    encrypt_cb: encrypt,
+    decrypt_cb: decrypt,
+};
+
+// ChaCha20-Poly1305 isn't FIPS approved.
+#[cfg(feature = "fips")]
+pub static DEFAULT: SpdmAead = SpdmAead {
+    supported_algo: SpdmAeadAlgo::from_bits_truncate(
+        SpdmAeadAlgo::AES_128_GCM.bits() | SpdmAeadAlgo::AES_256_GCM.bits(),
+    ),
     encrypt_cb: encrypt,
     decrypt_cb: decrypt,
 };
@@ -26,25 +42,23 @@ fn encrypt(
         SpdmAeadAlgo::AES_128_GCM => &ring::aead::AES_128_GCM,
         SpdmAeadAlgo::AES_256_GCM => &ring::aead::AES_256_GCM,
         SpdmAeadAlgo::CHACHA20_POLY1305 => &ring::aead::CHACHA20_POLY1305,
-        _ => {
-            panic!();
-        }
+        _ => return spdm_result_err!(EINVAL, "unsupported aead algorithm"),
     };
 
     if key.len() != aead_algo.get_key_size() as usize {
-        panic!("key len invalid");
+        return spdm_result_err!(EINVAL, "key len invalid");
     }
     if iv.len() != aead_algo.get_iv_size() as usize {
-        panic!("iv len invalid");
+        return spdm_result_err!(EINVAL, "iv len invalid");
     }
     let tag_size = tag.len();
     if tag_size != aead_algo.get_tag_size() as usize {
-        panic!("tag len invalid");
+        return spdm_result_err!(EINVAL, "tag len invalid");
     }
     let plain_text_size = plain_text.len();
 
     if cipher_text.len() != plain_text_size as usize {
-        panic!("cipher_text len invalid");
+        return spdm_result_err!(EINVAL, "cipher_text len invalid");
     }
 
     //debug!("encryption:\n");
@@ -60,7 +74,7 @@ fn encrypt(
     let mut in_out = BytesMut::new();
     in_out.extend_from_slice(&plain_text);
 
-    let mut s_key: ring::aead::SealingKey<OneNonceSequence> = make_key(algorithm, key, nonce);
+    let mut s_key: ring::aead::SealingKey<OneNonceSequence> = make_key(algorithm, key, nonce)?;
     match s_key.seal_in_place_append_tag(ring::aead::Aad::from(aad), &mut in_out) {
         Ok(()) => {
             cipher_text.copy_from_slice(&in_out[..plain_text_size]);
@@ -86,25 +100,23 @@ fn decrypt(
         SpdmAeadAlgo::AES_128_GCM => &ring::aead::AES_128_GCM,
         SpdmAeadAlgo::AES_256_GCM => &ring::aead::AES_256_GCM,
         SpdmAeadAlgo::CHACHA20_POLY1305 => &ring::aead::CHACHA20_POLY1305,
-        _ => {
-            panic!();
-        }
+        _ => return spdm_result_err!(EINVAL, "unsupported aead algorithm"),
     };
 
     if key.len() != aead_algo.get_key_size() as usize {
-        panic!("key len invalid");
+        return spdm_result_err!(EINVAL, "key len invalid");
     }
     if iv.len() != aead_algo.get_iv_size() as usize {
-        panic!("iv len invalid");
+        return spdm_result_err!(EINVAL, "iv len invalid");
     }
     let tag_size = tag.len();
     if tag_size != aead_algo.get_tag_size() as usize {
-        panic!("tag len invalid");
+        return spdm_result_err!(EINVAL, "tag len invalid");
     }
     let cipher_text_size = cipher_text.len();
 
     if plain_text.len() != cipher_text_size as usize {
-        panic!("plain_text len invalid");
+        return spdm_result_err!(EINVAL, "plain_text len invalid");
     }
 
     //debug!("decryption:\n");
@@ -122,7 +134,7 @@ fn decrypt(
     in_out.extend_from_slice(&cipher_text);
     in_out.extend_from_slice(&tag);
 
-    let mut o_key: ring::aead::OpeningKey<OneNonceSequence> = make_key(algorithm, key, nonce);
+    let mut o_key: ring::aead::OpeningKey<OneNonceSequence> = make_key(algorithm, key, nonce)?;
     match o_key.open_in_place(ring::aead::Aad::from(aad), &mut in_out) {
         Ok(in_out_result) => {
             plain_text.copy_from_slice(&in_out_result[..cipher_text_size]);
@@ -153,8 +165,8 @@ fn make_key<K: ring::aead::BoundKey<OneNonceSequence>>(
     algorithm: &'static ring::aead::Algorithm,
     key: &[u8],
     nonce: ring::aead::Nonce,
-) -> K {
-    let key = ring::aead::UnboundKey::new(algorithm, key).unwrap();
+) -> SpdmResult<K> {
+    let key = ring::aead::UnboundKey::new(algorithm, key).map_err(|_| spdm_err!(EINVAL))?;
     let nonce_sequence = OneNonceSequence::new(nonce);
-    K::new(key, nonce_sequence)
+    Ok(K::new(key, nonce_sequence))
 }
@@ -0,0 +1,125 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+use crate::crypto::{asym_sign, SpdmAsymSign, SpdmSigningKey};
+use crate::msgs::{
+    SpdmBaseAsymAlgo, SpdmBaseHashAlgo, SpdmSignatureStruct, SPDM_MAX_ASYM_KEY_SIZE,
+};
+
+pub static DEFAULT: SpdmAsymSign = SpdmAsymSign { sign_cb: asym_sign };
+
+// The key of `slot_id` comes from the registered `SpdmSigningKeyStore`, an
+// external one is only referred to by its handle.
+fn asym_sign(
+    slot_id: u8,
+    base_hash_algo: SpdmBaseHashAlgo,
+    base_asym_algo: SpdmBaseAsymAlgo,
+    data: &[u8],
+) -> Option<SpdmSignatureStruct> {
+    match asym_sign::get_signing_key(slot_id)? {
+        SpdmSigningKey::Der(key) => sign_with_key(key, base_hash_algo, base_asym_algo, data),
+        SpdmSigningKey::External(handle) => {
+            asym_sign::sign_external(handle, base_hash_algo, base_asym_algo, data)
+        }
+    }
+}
+
+/// Sign with the `SpdmSigningKey::Der` key `key`, for software key stores
+/// holding the keys in memory.
+pub fn sign_with_key(
+    key: &[u8],
+    base_hash_algo: SpdmBaseHashAlgo,
+    base_asym_algo: SpdmBaseAsymAlgo,
+    data: &[u8],
+) -> Option<SpdmSignatureStruct> {
+    match (base_hash_algo, base_asym_algo) {
+        (SpdmBaseHashAlgo::TPM_ALG_SHA_256, SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P256) => {
+            sign_ecdsa(key, &ring::signature::ECDSA_P256_SHA256_FIXED_SIGNING, data)
+        }
+        (SpdmBaseHashAlgo::TPM_ALG_SHA_384, SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P384) => {
+            sign_ecdsa(key, &ring::signature::ECDSA_P384_SHA384_FIXED_SIGNING, data)
+        }
+        (SpdmBaseHashAlgo::TPM_ALG_SHA_256, SpdmBaseAsymAlgo::TPM_ALG_RSASSA_2048)
+        | (SpdmBaseHashAlgo::TPM_ALG_SHA_256, SpdmBaseAsymAlgo::TPM_ALG_RSASSA_3072)
+        | (SpdmBaseHashAlgo::TPM_ALG_SHA_256, SpdmBaseAsymAlgo::TPM_ALG_RSASSA_4096) => sign_rsa(
+            key,
+            &ring::signature::RSA_PKCS1_SHA256,
+            base_asym_algo,
+            data,
+        ),
+        (SpdmBaseHashAlgo::TPM_ALG_SHA_256, SpdmBaseAsymAlgo::TPM_ALG_RSAPSS_2048)
+        | (SpdmBaseHashAlgo::TPM_ALG_SHA_256, SpdmBaseAsymAlgo::TPM_ALG_RSAPSS_3072)
+        | (SpdmBaseHashAlgo::TPM_ALG_SHA_256, SpdmBaseAsymAlgo::TPM_ALG_RSAPSS_4096) => {
+            sign_rsa(key, &ring::signature::RSA_PSS_SHA256, base_asym_algo, data)
+        }
+        (SpdmBaseHashAlgo::TPM_ALG_SHA_384, SpdmBaseAsymAlgo::TPM_ALG_RSASSA_2048)
+        | (SpdmBaseHashAlgo::TPM_ALG_SHA_384, SpdmBaseAsymAlgo::TPM_ALG_RSASSA_3072)
+        | (SpdmBaseHashAlgo::TPM_ALG_SHA_384, SpdmBaseAsymAlgo::TPM_ALG_RSASSA_4096) => sign_rsa(
+            key,
+            &ring::signature::RSA_PKCS1_SHA384,
+            base_asym_algo,
+            data,
+        ),
+        (SpdmBaseHashAlgo::TPM_ALG_SHA_384, SpdmBaseAsymAlgo::TPM_ALG_RSAPSS_2048)
+        | (SpdmBaseHashAlgo::TPM_ALG_SHA_384, SpdmBaseAsymAlgo::TPM_ALG_RSAPSS_3072)
+        | (SpdmBaseHashAlgo::TPM_ALG_SHA_384, SpdmBaseAsymAlgo::TPM_ALG_RSAPSS_4096) => {
+            sign_rsa(key, &ring::signature::RSA_PSS_SHA384, base_asym_algo, data)
+        }
+        (SpdmBaseHashAlgo::TPM_ALG_SHA_512, SpdmBaseAsymAlgo::TPM_ALG_RSASSA_2048)
+        | (SpdmBaseHashAlgo::TPM_ALG_SHA_512, SpdmBaseAsymAlgo::TPM_ALG_RSASSA_3072)
+        | (SpdmBaseHashAlgo::TPM_ALG_SHA_512, SpdmBaseAsymAlgo::TPM_ALG_RSASSA_4096) => sign_rsa(
+            key,
+            &ring::signature::RSA_PKCS1_SHA512,
+            base_asym_algo,
+            data,
+        ),
+        (SpdmBaseHashAlgo::TPM_ALG_SHA_512, SpdmBaseAsymAlgo::TPM_ALG_RSAPSS_2048)
+        | (SpdmBaseHashAlgo::TPM_ALG_SHA_512, SpdmBaseAsymAlgo::TPM_ALG_RSAPSS_3072)
+        | (SpdmBaseHashAlgo::TPM_ALG_SHA_512, SpdmBaseAsymAlgo::TPM_ALG_RSAPSS_4096) => {
+            sign_rsa(key, &ring::signature::RSA_PSS_SHA512, base_asym_algo, data)
+        }
+        _ => None,
+    }
+}
+
+fn sign_ecdsa(
+    key: &[u8],
+    algorithm: &'static ring::signature::EcdsaSigningAlgorithm,
+    data: &[u8],
+) -> Option<SpdmSignatureStruct> {
+    let key_pair = ring::signature::EcdsaKeyPair::from_pkcs8(algorithm, key).ok()?;
+    let rng = ring::rand::SystemRandom::new();
+    let signature = key_pair.sign(&rng, data).ok()?;
+    let signature = signature.as_ref();
+
+    let mut full_signature = [0u8; SPDM_MAX_ASYM_KEY_SIZE];
+    full_signature[..signature.len()].copy_from_slice(signature);
+    Some(SpdmSignatureStruct {
+        data_size: signature.len() as u16,
+        data: full_signature,
+    })
+}
+
+fn sign_rsa(
+    key: &[u8],
+    padding_alg: &'static dyn ring::signature::RsaEncoding,
+    base_asym_algo: SpdmBaseAsymAlgo,
+    data: &[u8],
+) -> Option<SpdmSignatureStruct> {
+    let key_pair = ring::signature::RsaKeyPair::from_der(key).ok()?;
+    let key_len = base_asym_algo.get_size() as usize;
+    if key_len != key_pair.public_modulus_len() {
+        return None;
+    }
+    let rng = ring::rand::SystemRandom::new();
+
+    let mut full_signature = [0u8; SPDM_MAX_ASYM_KEY_SIZE];
+    key_pair
+        .sign(padding_alg, &rng, data, &mut full_signature[..key_len])
+        .ok()?;
+    Some(SpdmSignatureStruct {
+        data_size: key_len as u16,
+        data: full_signature,
+    })
+}
@@ -57,9 +57,7 @@ fn asym_verify(
         | (SpdmBaseHashAlgo::TPM_ALG_SHA_512, SpdmBaseAsymAlgo::TPM_ALG_RSAPSS_4096) => {
             &webpki::RSA_PSS_2048_8192_SHA512_LEGACY_KEY
         }
-        _ => {
-            panic!();
-        }
+        _ => return spdm_result_err!(EINVAL, "unsupported algorithm"),
     };
 
     //
@@ -84,7 +82,7 @@ fn asym_verify(
                 | SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P384 => {
                     let mut der_signature = [0u8; 66 * 2 + 8];
                     let der_sign_size =
-                        ecc_signature_bin_to_der(signature.as_ref(), &mut der_signature);
+                        ecc_signature_bin_to_der(signature.as_ref(), &mut der_signature)?;
 
                     //debug!("der signature len - 0x{:x?}\n", der_sign_size);
                     //debug!("der signature - {:x?}\n", der_signature);
@@ -109,7 +107,7 @@ fn asym_verify(
 }
 
 // add ASN.1 for the ECDSA binary signature
-fn ecc_signature_bin_to_der(signature: &[u8], der_signature: &mut [u8]) -> usize {
+fn ecc_signature_bin_to_der(signature: &[u8], der_signature: &mut [u8]) -> SpdmResult<usize> {
     let sign_size = signature.len();
     let half_size = sign_size / 2;
 
@@ -134,7 +132,7 @@ fn ecc_signature_bin_to_der(signature: &[u8], der_signature: &mut [u8]) -> usize
     let s = &signature[half_size + s_index..sign_size];
 
     if r_size == 0 || s_size == 0 {
-        return 0;
+        return spdm_result_err!(EINVAL, "empty signature");
     }
 
     let der_r_size = if r[0] < 0x80 { r_size } else { r_size + 1 };
@@ -142,7 +140,7 @@ fn ecc_signature_bin_to_der(signature: &[u8], der_signature: &mut [u8]) -> usize
     let der_sign_size = der_r_size + der_s_size + 6;
 
     if der_signature.len() < der_sign_size {
-        panic!("der_signature too small");
+        return spdm_result_err!(EINVAL, "der_signature too small");
     }
 
     der_signature[0] = 0x30u8;
@@ -165,5 +163,5 @@ fn ecc_signature_bin_to_der(signature: &[u8], der_signature: &mut [u8]) -> usize
         der_signature[(7 + der_r_size)..(7 + der_r_size + s_size)].copy_from_slice(s);
     }
 
-    der_sign_size
+    Ok(der_sign_size)
 }
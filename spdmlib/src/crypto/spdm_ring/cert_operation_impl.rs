@@ -5,12 +5,14 @@
 extern crate alloc;
 use alloc::vec::Vec;
 
-use crate::crypto::SpdmCertOperation;
+use crate::crypto::{SpdmCertChainConfig, SpdmCertExtension, SpdmCertInfo, SpdmCertOperation};
 use crate::error::SpdmResult;
 
 pub static DEFAULT: SpdmCertOperation = SpdmCertOperation {
     get_cert_from_cert_chain_cb: get_cert_from_cert_chain,
     verify_cert_chain_cb: verify_cert_chain,
+    parse_cert_info_cb: parse_cert_info,
+    check_revocation_cb: None,
 };
 
 fn get_cert_from_cert_chain(cert_chain: &[u8], index: isize) -> SpdmResult<(usize, usize)> {
@@ -39,12 +41,7 @@ fn get_cert_from_cert_chain(cert_chain: &[u8], index: isize) -> SpdmResult<(usiz
     }
 }
 
-fn verify_cert_chain(cert_chain: &[u8]) -> SpdmResult {
-    static EKU_SPDM_RESPONDER_AUTH: webpki::verify_cert::KeyPurposeId =
-        webpki::verify_cert::KeyPurposeId {
-            oid_value: untrusted::Input::from(&[40 + 3, 6, 1, 5, 5, 7, 3, 1]), // TBD
-        };
-
+fn verify_cert_chain(cert_chain: &[u8], config: &SpdmCertChainConfig) -> SpdmResult {
     static ALL_SIGALGS: &[&webpki::SignatureAlgorithm] = &[
         &webpki::RSA_PKCS1_2048_8192_SHA256,
         &webpki::RSA_PKCS1_2048_8192_SHA384,
@@ -55,41 +52,60 @@ fn verify_cert_chain(cert_chain: &[u8]) -> SpdmResult {
         &webpki::ECDSA_P384_SHA384,
     ];
 
-    let (ca_begin, ca_end) = get_cert_from_cert_chain(cert_chain, 0)?;
-    let ca = &cert_chain[ca_begin..ca_end];
-    // TBD: assume only one inter cert here.
-    let (inter_begin, inter_end) = get_cert_from_cert_chain(cert_chain, 1)?;
-    let inter = &cert_chain[inter_begin..inter_end];
-    let (ee_begin, ee_end) = get_cert_from_cert_chain(cert_chain, -1)?;
-    let ee = &cert_chain[ee_begin..ee_end];
-
-    let mut anchors = Vec::new();
-    anchors.push(webpki::TrustAnchor::from_cert_der(ca).unwrap());
+    let mut certs = Vec::new();
+    let mut offset = 0usize;
+    while offset < cert_chain.len() {
+        let (_, cert_end) = get_cert_from_cert_chain(&cert_chain[offset..], 0)?;
+        if offset + cert_end > cert_chain.len() {
+            return spdm_result_err!(EINVAL, "truncated cert chain");
+        }
+        certs.push(&cert_chain[offset..offset + cert_end]);
+        offset += cert_end;
+    }
+    if certs.len() < 2 {
+        return spdm_result_err!(EINVAL, "cert chain without leaf");
+    }
+    if certs.len() > config.max_chain_depth {
+        error!("Cert chain of {} certs is too deep\n", certs.len());
+        return spdm_result_err!(EFAULT);
+    }
+    let ca = certs[0];
+    let ee = certs[certs.len() - 1];
+    let intermediates = &certs[1..certs.len() - 1];
 
+    let unix_time = config.get_unix_time();
     #[cfg(target_os = "uefi")]
-    let time = webpki::Time::from_seconds_since_unix_epoch(uefi_time::get_rtc_time() as u64);
-    #[cfg(feature = "std")]
-    use std::convert::TryFrom;
-    #[cfg(feature = "std")]
-    let time = webpki::Time::try_from(std::time::SystemTime::now()).unwrap();
+    let unix_time = unix_time.or_else(|| Some(uefi_time::get_rtc_time() as u64));
+    let time = match unix_time {
+        Some(seconds) => webpki::Time::from_seconds_since_unix_epoch(seconds),
+        None => {
+            error!("No clock to check the cert validity\n");
+            return spdm_result_err!(EFAULT);
+        }
+    };
+
+    let mut anchors = Vec::new();
+    anchors.push(webpki::TrustAnchor::from_cert_der(ca).map_err(|_| spdm_err!(EFAULT))?);
 
     let cert = webpki::cert::parse_cert(
         untrusted::Input::from(ee),
         webpki::cert::EndEntityOrCA::EndEntity,
     )
-    .unwrap();
+    .map_err(|_| spdm_err!(EFAULT))?;
+
+    if config.leaf_digital_signature && allows_digital_signature(ee) != Some(true) {
+        error!("Cert key usage without digitalSignature\n");
+        return spdm_result_err!(EFAULT);
+    }
+
+    let eku = webpki::verify_cert::KeyPurposeId {
+        oid_value: untrusted::Input::from(config.leaf_eku),
+    };
 
     // we cannot call verify_is_valid_tls_server_cert because it will check verify_cert::EKU_SERVER_AUTH.
-    if webpki::verify_cert::build_chain(
-        EKU_SPDM_RESPONDER_AUTH,
-        ALL_SIGALGS,
-        &anchors,
-        &[inter],
-        &cert,
-        time,
-        0,
-    )
-    .is_ok()
+    // build_chain checks the validity and the CA basic constraints.
+    if webpki::verify_cert::build_chain(eku, ALL_SIGALGS, &anchors, intermediates, &cert, time, 0)
+        .is_ok()
     {
         info!("Cert verification Pass\n");
         Ok(())
@@ -98,3 +114,180 @@ fn verify_cert_chain(cert_chain: &[u8]) -> SpdmResult {
         spdm_result_err!(EFAULT)
     }
 }
+
+// id-ce-keyUsage
+const OID_KEY_USAGE: &[u8] = &[0x55, 0x1d, 0x0f];
+
+// Tag and value of the next DER element, with the length forms certificates
+// use.
+fn read_der<'a>(reader: &mut untrusted::Reader<'a>) -> Option<(u8, untrusted::Input<'a>)> {
+    let tag = reader.read_byte().ok()?;
+    let length = match reader.read_byte().ok()? {
+        length if length < 0x80 => length as usize,
+        0x81 => reader.read_byte().ok()? as usize,
+        0x82 => {
+            let high = reader.read_byte().ok()? as usize;
+            (high << 8) | reader.read_byte().ok()? as usize
+        }
+        _ => return None,
+    };
+    let value = reader.read_bytes(length).ok()?;
+    Some((tag, value))
+}
+
+// Like read_der(), but returns the whole element.
+fn read_der_element<'a>(reader: &mut untrusted::Reader<'a>) -> Option<&'a [u8]> {
+    let mark = reader.mark();
+    read_der(reader)?;
+    let element = reader.get_input_between_marks(mark, reader.mark()).ok()?;
+    Some(element.as_slice_less_safe())
+}
+
+fn parse_cert_info(cert: &[u8]) -> SpdmResult<SpdmCertInfo<'_>> {
+    match parse_tbs_certificate(cert) {
+        Some(cert_info) => Ok(cert_info),
+        None => spdm_result_err!(EINVAL, "malformed cert"),
+    }
+}
+
+// The fields of the TBSCertificate of `cert` (RFC 5280 4.1).
+fn parse_tbs_certificate(cert: &[u8]) -> Option<SpdmCertInfo<'_>> {
+    let (_, certificate) = read_der(&mut untrusted::Reader::new(untrusted::Input::from(cert)))?;
+    let (_, tbs) = read_der(&mut untrusted::Reader::new(certificate))?;
+    let mut tbs = untrusted::Reader::new(tbs);
+
+    // version [0] EXPLICIT, absent in v1 certs
+    if tbs.peek(0xa0) {
+        read_der(&mut tbs)?;
+    }
+    let (_, serial_number) = read_der(&mut tbs)?;
+    let _signature = read_der(&mut tbs)?;
+    let issuer = read_der_element(&mut tbs)?;
+    let _validity = read_der(&mut tbs)?;
+    let subject = read_der_element(&mut tbs)?;
+
+    let (_, public_key_info) = read_der(&mut tbs)?;
+    let (_, algorithm) = read_der(&mut untrusted::Reader::new(public_key_info))?;
+    let mut algorithm = untrusted::Reader::new(algorithm);
+    let (_, public_key_algorithm) = read_der(&mut algorithm)?;
+    let public_key_parameters = if algorithm.at_end() {
+        &[][..]
+    } else {
+        read_der_element(&mut algorithm)?
+    };
+
+    let mut extensions = Vec::new();
+    while !tbs.at_end() {
+        let (tag, value) = read_der(&mut tbs)?;
+        // extensions [3] EXPLICIT SEQUENCE OF Extension, after the unique IDs
+        if tag != 0xa3 {
+            continue;
+        }
+        let (_, value) = read_der(&mut untrusted::Reader::new(value))?;
+        let mut value = untrusted::Reader::new(value);
+        while !value.at_end() {
+            let (_, extension) = read_der(&mut value)?;
+            let mut extension = untrusted::Reader::new(extension);
+            let (_, oid) = read_der(&mut extension)?;
+            let (mut tag, mut value) = read_der(&mut extension)?;
+            let mut critical = false;
+            if tag == 0x01 {
+                critical = value.as_slice_less_safe() != [0x00];
+                let next = read_der(&mut extension)?;
+                tag = next.0;
+                value = next.1;
+            }
+            if tag != 0x04 {
+                return None;
+            }
+            extensions.push(SpdmCertExtension {
+                oid: oid.as_slice_less_safe(),
+                critical,
+                value: value.as_slice_less_safe(),
+            });
+        }
+    }
+
+    Some(SpdmCertInfo {
+        serial_number: serial_number.as_slice_less_safe(),
+        issuer,
+        subject,
+        public_key_algorithm: public_key_algorithm.as_slice_less_safe(),
+        public_key_parameters,
+        extensions,
+    })
+}
+
+// Whether the key usage of `cert` includes digitalSignature. A cert without
+// the extension may be used for anything (RFC 5280 4.2.1.3). webpki doesn't
+// look at the key usage.
+fn allows_digital_signature(cert: &[u8]) -> Option<bool> {
+    let cert_info = parse_tbs_certificate(cert)?;
+    let key_usage = match cert_info.get_extension(OID_KEY_USAGE) {
+        Some(key_usage) => key_usage,
+        None => return Some(true),
+    };
+    // BIT STRING, its first byte is the number of unused bits and
+    // digitalSignature is bit 0.
+    let (_, bits) = read_der(&mut untrusted::Reader::new(untrusted::Input::from(
+        key_usage.value,
+    )))?;
+    let bits = bits.as_slice_less_safe();
+    Some(bits.len() > 1 && bits[1] & 0x80 != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::msgs::SpdmBaseAsymAlgo;
+    use crate::testlib;
+
+    #[test]
+    fn test_parse_cert_info() {
+        for (base_hash_algo, base_asym_algo) in testlib::CERT_CHAIN_ALGOS.iter() {
+            let test_cert_chain = testlib::generate_cert_chain(*base_hash_algo, *base_asym_algo);
+            let cert = test_cert_chain.leaf_cert.as_slice();
+            let cert_info = parse_cert_info(cert).unwrap();
+
+            assert_eq!(cert_info.serial_number, &[0x03]);
+            // SEQUENCE of the RDN with the CN
+            assert_eq!(cert_info.issuer[0], 0x30);
+            assert!(cert_info.issuer.ends_with(b"spdm test intermediate cert"));
+            assert!(cert_info.subject.ends_with(b"spdm test responder cert"));
+            if *base_asym_algo == SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P256
+                || *base_asym_algo == SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P384
+            {
+                // id-ecPublicKey, prime256v1 or secp384r1
+                assert_eq!(
+                    cert_info.public_key_algorithm,
+                    &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01]
+                );
+                assert_eq!(cert_info.public_key_parameters[0], 0x06);
+            } else {
+                // rsaEncryption, NULL
+                assert_eq!(
+                    cert_info.public_key_algorithm,
+                    &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01]
+                );
+                assert_eq!(cert_info.public_key_parameters, &[0x05, 0x00]);
+            }
+
+            // basicConstraints, keyUsage, subjectAltName, extKeyUsage
+            assert_eq!(cert_info.extensions.len(), 4);
+            // basicConstraints, CA:FALSE
+            let basic_constraints = cert_info.get_extension(&[0x55, 0x1d, 0x13]).unwrap();
+            assert!(basic_constraints.critical);
+            assert_eq!(basic_constraints.value, &[0x30, 0x00]);
+            assert!(!cert_info.get_extension(OID_KEY_USAGE).unwrap().critical);
+            assert_eq!(allows_digital_signature(cert), Some(true));
+
+            // CA:TRUE
+            let cert_info = parse_cert_info(&test_cert_chain.root_cert).unwrap();
+            assert_eq!(cert_info.serial_number, &[0x01]);
+            let basic_constraints = cert_info.get_extension(&[0x55, 0x1d, 0x13]).unwrap();
+            assert_eq!(basic_constraints.value, &[0x30, 0x03, 0x01, 0x01, 0xff]);
+
+            assert!(parse_cert_info(&cert[..cert.len() / 2]).is_err());
+        }
+    }
+}
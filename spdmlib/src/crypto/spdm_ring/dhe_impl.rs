@@ -4,12 +4,23 @@
 
 extern crate alloc;
 use alloc::boxed::Box;
+use alloc::vec::Vec;
 
+use super::ffdhe::{self, FfdheGroup};
+use crate::common::zeroize;
 use crate::crypto::{SpdmDhe, SpdmDheKeyExchange};
 use crate::msgs::{SpdmDheAlgo, SpdmDheExchangeStruct, SpdmDheFinalKeyStruct};
 use bytes::{BufMut, BytesMut};
+use ring::rand::SecureRandom;
 
 pub static DEFAULT: SpdmDhe = SpdmDhe {
+    supported_algo: SpdmDheAlgo::from_bits_truncate(
+        SpdmDheAlgo::SECP_256_R1.bits()
+            | SpdmDheAlgo::SECP_384_R1.bits()
+            | SpdmDheAlgo::FFDHE_2048.bits()
+            | SpdmDheAlgo::FFDHE_3072.bits()
+            | SpdmDheAlgo::FFDHE_4096.bits(),
+    ),
     generate_key_pair_cb: generate_key_pair,
 };
 
@@ -20,9 +31,9 @@ fn generate_key_pair(
         SpdmDheAlgo::SECP_256_R1 => SpdmDheKeyExchangeP256::generate_key_pair(),
         SpdmDheAlgo::SECP_384_R1 => SpdmDheKeyExchangeP384::generate_key_pair(),
         SpdmDheAlgo::SECP_521_R1 => None,
-        SpdmDheAlgo::FFDHE_2048 => None,
-        SpdmDheAlgo::FFDHE_3072 => None,
-        SpdmDheAlgo::FFDHE_4096 => None,
+        SpdmDheAlgo::FFDHE_2048 => SpdmDheKeyExchangeFfdhe::generate_key_pair(&ffdhe::FFDHE_2048),
+        SpdmDheAlgo::FFDHE_3072 => SpdmDheKeyExchangeFfdhe::generate_key_pair(&ffdhe::FFDHE_3072),
+        SpdmDheAlgo::FFDHE_4096 => SpdmDheKeyExchangeFfdhe::generate_key_pair(&ffdhe::FFDHE_4096),
         _ => None,
     }
 }
@@ -115,9 +126,60 @@ impl SpdmDheKeyExchangeP384 {
     }
 }
 
+struct SpdmDheKeyExchangeFfdhe {
+    group: &'static FfdheGroup,
+    private_key: Vec<u8>,
+}
+
+impl SpdmDheKeyExchange for SpdmDheKeyExchangeFfdhe {
+    fn compute_final_key(
+        self: Box<Self>,
+        peer_pub_key: &SpdmDheExchangeStruct,
+    ) -> Option<SpdmDheFinalKeyStruct> {
+        let mut final_key = self
+            .group
+            .compute_shared_secret(peer_pub_key.as_ref(), &self.private_key)?;
+        let res = SpdmDheFinalKeyStruct::from(BytesMut::from(final_key.as_slice()));
+        zeroize(&mut final_key);
+        Some(res)
+    }
+}
+
+impl SpdmDheKeyExchangeFfdhe {
+    fn generate_key_pair(
+        group: &'static FfdheGroup,
+    ) -> Option<(SpdmDheExchangeStruct, Box<dyn SpdmDheKeyExchange>)> {
+        let rng = ring::rand::SystemRandom::new();
+        let mut private_key = alloc::vec![0u8; group.get_private_key_size()];
+        rng.fill(&mut private_key).ok()?;
+        let public_key = group.compute_public_key(&private_key);
+
+        let res: Box<dyn SpdmDheKeyExchange> = Box::new(Self { group, private_key });
+
+        Some((
+            SpdmDheExchangeStruct::from(BytesMut::from(public_key.as_slice())),
+            res,
+        ))
+    }
+}
+
+impl Drop for SpdmDheKeyExchangeFfdhe {
+    fn drop(&mut self) {
+        zeroize(&mut self.private_key);
+    }
+}
+
 #[test]
 fn test_dhe() {
-    for dhe_algo in [SpdmDheAlgo::SECP_256_R1, SpdmDheAlgo::SECP_384_R1].iter() {
+    for dhe_algo in [
+        SpdmDheAlgo::SECP_256_R1,
+        SpdmDheAlgo::SECP_384_R1,
+        SpdmDheAlgo::FFDHE_2048,
+        SpdmDheAlgo::FFDHE_3072,
+        SpdmDheAlgo::FFDHE_4096,
+    ]
+    .iter()
+    {
         let (exchange1, private1) = generate_key_pair(*dhe_algo).unwrap();
         let (exchange2, private2) = generate_key_pair(*dhe_algo).unwrap();
 
@@ -127,3 +189,32 @@ fn test_dhe() {
         assert_eq!(peer1.as_ref(), peer2.as_ref());
     }
 }
+
+#[test]
+fn test_ffdhe() {
+    // 2^2048 mod p = 2^2048 - p, whose complement is p - 1
+    let group = &ffdhe::FFDHE_2048;
+    let mut prime = group.compute_public_key(&[0x08, 0x00]);
+    for byte in prime.iter_mut() {
+        *byte = !*byte;
+    }
+    assert_eq!(
+        &prime[..9],
+        &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xad]
+    );
+    assert_eq!(prime[255], 0xfe);
+
+    // the peer public keys 1, p - 1 and p are refused
+    let private_key = [0x5a; 32];
+    assert!(group.compute_shared_secret(&prime, &private_key).is_none());
+    prime[255] = 0xff;
+    assert!(group.compute_shared_secret(&prime, &private_key).is_none());
+    let mut peer = [0u8; 256];
+    peer[255] = 1;
+    assert!(group.compute_shared_secret(&peer, &private_key).is_none());
+    peer[255] = 2;
+    assert_eq!(
+        group.compute_shared_secret(&peer, &private_key),
+        Some(group.compute_public_key(&private_key))
+    );
+}
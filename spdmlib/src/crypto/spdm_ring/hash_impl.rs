@@ -2,20 +2,98 @@
 //
 // SPDX-License-Identifier: BSD-2-Clause-Patent
 
-use crate::crypto::SpdmHash;
+extern crate alloc;
+use alloc::boxed::Box;
+
+use crate::crypto::{SpdmHash, SpdmHashCtx};
+use crate::error::SpdmResult;
 use crate::msgs::{SpdmBaseHashAlgo, SpdmDigestStruct};
+use core::convert::TryFrom;
 
 pub static DEFAULT: SpdmHash = SpdmHash {
     hash_all_cb: hash_all,
+    hash_ctx_init_cb: hash_ctx_init,
+    hash_ctx_update_cb: hash_ctx_update,
+    hash_ctx_dup_cb: hash_ctx_dup,
+    hash_ctx_finalize_cb: hash_ctx_finalize,
 };
 
+fn get_algorithm(base_hash_algo: SpdmBaseHashAlgo) -> Option<&'static ring::digest::Algorithm> {
+    match base_hash_algo {
+        SpdmBaseHashAlgo::TPM_ALG_SHA_256 => Some(&ring::digest::SHA256),
+        SpdmBaseHashAlgo::TPM_ALG_SHA_384 => Some(&ring::digest::SHA384),
+        SpdmBaseHashAlgo::TPM_ALG_SHA_512 => Some(&ring::digest::SHA512),
+        _ => None,
+    }
+}
+
 fn hash_all(base_hash_algo: SpdmBaseHashAlgo, data: &[u8]) -> Option<SpdmDigestStruct> {
-    let algorithm = match base_hash_algo {
-        SpdmBaseHashAlgo::TPM_ALG_SHA_256 => &ring::digest::SHA256,
-        SpdmBaseHashAlgo::TPM_ALG_SHA_384 => &ring::digest::SHA384,
-        SpdmBaseHashAlgo::TPM_ALG_SHA_512 => &ring::digest::SHA512,
-        _ => return None,
-    };
+    let algorithm = get_algorithm(base_hash_algo)?;
     let digest_value = ring::digest::digest(algorithm, data);
-    Some(SpdmDigestStruct::from(digest_value.as_ref()))
+    SpdmDigestStruct::try_from(digest_value.as_ref()).ok()
+}
+
+fn hash_ctx_init(base_hash_algo: SpdmBaseHashAlgo) -> Option<SpdmHashCtx> {
+    let algorithm = get_algorithm(base_hash_algo)?;
+    Some(Box::new(ring::digest::Context::new(algorithm)))
+}
+
+fn hash_ctx_update(ctx: &mut SpdmHashCtx, data: &[u8]) -> SpdmResult {
+    let ctx = ctx
+        .downcast_mut::<ring::digest::Context>()
+        .ok_or(spdm_err!(EINVAL, "not a ring hash context"))?;
+    ctx.update(data);
+    Ok(())
+}
+
+fn hash_ctx_dup(ctx: &SpdmHashCtx) -> Option<SpdmHashCtx> {
+    let ctx = ctx.downcast_ref::<ring::digest::Context>()?;
+    Some(Box::new(ctx.clone()))
+}
+
+fn hash_ctx_finalize(ctx: SpdmHashCtx) -> Option<SpdmDigestStruct> {
+    let ctx = ctx.downcast::<ring::digest::Context>().ok()?;
+    let digest_value = ctx.finish();
+    SpdmDigestStruct::try_from(digest_value.as_ref()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_ctx() {
+        let data = [0x5au8; 1000];
+        for base_hash_algo in [
+            SpdmBaseHashAlgo::TPM_ALG_SHA_256,
+            SpdmBaseHashAlgo::TPM_ALG_SHA_384,
+            SpdmBaseHashAlgo::TPM_ALG_SHA_512,
+        ]
+        .iter()
+        {
+            let mut ctx = hash_ctx_init(*base_hash_algo).unwrap();
+            for chunk in data.chunks(333) {
+                hash_ctx_update(&mut ctx, chunk).unwrap();
+            }
+            // the copy goes on where the original stopped
+            let dup = hash_ctx_dup(&ctx).unwrap();
+            assert_eq!(
+                hash_ctx_finalize(dup).unwrap().as_ref(),
+                hash_all(*base_hash_algo, &data).unwrap().as_ref()
+            );
+            let digest = hash_ctx_finalize(ctx).unwrap();
+            assert_eq!(
+                digest.as_ref(),
+                hash_all(*base_hash_algo, &data).unwrap().as_ref()
+            );
+        }
+
+        assert!(hash_ctx_init(SpdmBaseHashAlgo::empty()).is_none());
+
+        // a context some other hash callback set up
+        let mut ctx: SpdmHashCtx = Box::new(0u32);
+        assert!(hash_ctx_update(&mut ctx, &data).is_err());
+        assert!(hash_ctx_dup(&ctx).is_none());
+        assert!(hash_ctx_finalize(ctx).is_none());
+    }
 }
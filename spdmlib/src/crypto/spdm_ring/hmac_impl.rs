@@ -2,9 +2,11 @@
 //
 // SPDX-License-Identifier: BSD-2-Clause-Patent
 
+use crate::common::constant_time_eq;
 use crate::crypto::SpdmHmac;
 use crate::error::SpdmResult;
 use crate::msgs::{SpdmBaseHashAlgo, SpdmDigestStruct};
+use core::convert::TryFrom;
 
 pub static DEFAULT: SpdmHmac = SpdmHmac {
     hmac_cb: hmac,
@@ -16,15 +18,12 @@ fn hmac(base_hash_algo: SpdmBaseHashAlgo, key: &[u8], data: &[u8]) -> Option<Spd
         SpdmBaseHashAlgo::TPM_ALG_SHA_256 => ring::hmac::HMAC_SHA256,
         SpdmBaseHashAlgo::TPM_ALG_SHA_384 => ring::hmac::HMAC_SHA384,
         SpdmBaseHashAlgo::TPM_ALG_SHA_512 => ring::hmac::HMAC_SHA512,
-        _ => {
-            panic!();
-        }
+        _ => return None,
     };
 
     let s_key = ring::hmac::Key::new(algorithm, key);
     let tag = ring::hmac::sign(&s_key, data);
-    let tag = tag.as_ref();
-    Some(SpdmDigestStruct::from(tag))
+    SpdmDigestStruct::try_from(tag.as_ref()).ok()
 }
 
 fn hmac_verify(
@@ -37,14 +36,48 @@ fn hmac_verify(
         SpdmBaseHashAlgo::TPM_ALG_SHA_256 => ring::hmac::HMAC_SHA256,
         SpdmBaseHashAlgo::TPM_ALG_SHA_384 => ring::hmac::HMAC_SHA384,
         SpdmBaseHashAlgo::TPM_ALG_SHA_512 => ring::hmac::HMAC_SHA512,
-        _ => {
-            panic!();
-        }
+        _ => return spdm_result_err!(EINVAL, "unsupported hash algorithm"),
     };
 
+    hmac.check_size(base_hash_algo)?;
     let v_key = ring::hmac::Key::new(algorithm, key);
-    match ring::hmac::verify(&v_key, data, &hmac.data[..(hmac.data_size as usize)]) {
-        Ok(()) => Ok(()),
-        Err(_) => spdm_result_err!(EFAULT),
+    let tag = ring::hmac::sign(&v_key, data);
+    if constant_time_eq(tag.as_ref(), hmac.as_slice()) {
+        Ok(())
+    } else {
+        spdm_result_err!(EFAULT)
     }
 }
+
+#[test]
+fn test_hmac_verify() {
+    let key = [0x5au8; 48];
+    let data = b"finished";
+    for base_hash_algo in [
+        SpdmBaseHashAlgo::TPM_ALG_SHA_256,
+        SpdmBaseHashAlgo::TPM_ALG_SHA_384,
+        SpdmBaseHashAlgo::TPM_ALG_SHA_512,
+    ]
+    .iter()
+    {
+        let mut mac = hmac(*base_hash_algo, &key, data).unwrap();
+        assert!(hmac_verify(*base_hash_algo, &key, data, &mac).is_ok());
+        assert!(hmac_verify(*base_hash_algo, &key, b"finishe", &mac).is_err());
+
+        // a truncated, an extended and an oversized MAC are rejected
+        let size = mac.data_size;
+        mac.data_size = size - 1;
+        assert!(hmac_verify(*base_hash_algo, &key, data, &mac).is_err());
+        mac.data_size = size + 1;
+        assert!(hmac_verify(*base_hash_algo, &key, data, &mac).is_err());
+        mac.data_size = u16::MAX;
+        assert!(hmac_verify(*base_hash_algo, &key, data, &mac).is_err());
+        mac.data_size = 0;
+        assert!(hmac_verify(*base_hash_algo, &key, data, &mac).is_err());
+    }
+
+    // no supported hash algorithm negotiated
+    let mac = hmac(SpdmBaseHashAlgo::TPM_ALG_SHA_384, &key, data).unwrap();
+    assert!(hmac(SpdmBaseHashAlgo::empty(), &key, data).is_none());
+    assert!(hmac_verify(SpdmBaseHashAlgo::empty(), &key, data, &mac).is_err());
+}
@@ -3,9 +3,12 @@
 // SPDX-License-Identifier: BSD-2-Clause-Patent
 
 pub mod aead_impl;
+pub mod asym_sign_impl;
 pub mod asym_verify_impl;
 pub mod cert_operation_impl;
 pub mod dhe_impl;
+pub mod ffdhe;
 pub mod hash_impl;
 pub mod hkdf_impl;
 pub mod hmac_impl;
+pub mod rand_impl;
@@ -0,0 +1,18 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+use crate::crypto::SpdmRandom;
+use crate::error::SpdmResult;
+
+use ring::rand::SecureRandom;
+
+pub static DEFAULT: SpdmRandom = SpdmRandom {
+    get_random_cb: get_random,
+};
+
+fn get_random(data: &mut [u8]) -> SpdmResult<usize> {
+    let rng = ring::rand::SystemRandom::new();
+    rng.fill(data).map_err(|_| spdm_err!(EFAULT))?;
+    Ok(data.len())
+}
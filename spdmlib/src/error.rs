@@ -24,6 +24,10 @@ pub enum SpdmErrorNum {
     EINVAL = 22,
     ERANGE = 34,
     ENOSYS = 38,
+    EBADMSG = 74,
+    ETIMEDOUT = 110,
+    EALREADY = 114,
+    EKEYREJECTED = 129,
 }
 
 pub struct SpdmError {
@@ -53,6 +57,10 @@ impl SpdmErrorNum {
             EINVAL => "Invalid argument",
             ERANGE => "Math result not representable",
             ENOSYS => "Function not implemented",
+            EBADMSG => "Bad message",
+            ETIMEDOUT => "Connection timed out",
+            EALREADY => "Operation already in progress",
+            EKEYREJECTED => "Key was rejected by service",
         }
     }
 }
@@ -77,6 +85,10 @@ impl SpdmError {
     pub fn code(&self) -> i32 {
         -(self.num as u32 as i32)
     }
+
+    pub fn num(&self) -> SpdmErrorNum {
+        self.num
+    }
 }
 
 impl Debug for SpdmError {
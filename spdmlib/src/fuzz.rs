@@ -0,0 +1,245 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+#![forbid(unsafe_code)]
+
+//! Entry points for fuzz targets of the message codecs.
+//!
+//! The codecs need an `SpdmContext` for the negotiated version and
+//! algorithms. The functions here decode with a context that looks
+//! negotiated with fixed algorithms, so the same input always takes the
+//! same path. They return None for input that doesn't decode, any panic is
+//! a bug in the codecs.
+
+use crate::common::{SpdmConfigInfo, SpdmContext, SpdmDeviceIo, SpdmProvisionInfo};
+use crate::common::{SpdmConnectionState, SpdmTransportEncap};
+use crate::config;
+use crate::error::SpdmResult;
+use crate::msgs::*;
+use crate::session::{SpdmSession, SpdmSessionState};
+use codec::{Codec, Reader, Writer};
+use core::convert::TryFrom;
+
+struct FuzzDeviceIo;
+
+impl SpdmDeviceIo for FuzzDeviceIo {
+    fn send(&mut self, _buffer: &[u8]) -> SpdmResult {
+        Ok(())
+    }
+
+    fn receive(&mut self, _buffer: &mut [u8]) -> Result<usize, usize> {
+        Err(0)
+    }
+
+    fn flush_all(&mut self) -> SpdmResult {
+        Ok(())
+    }
+}
+
+struct FuzzTransportEncap;
+
+impl SpdmTransportEncap for FuzzTransportEncap {
+    fn encap(
+        &mut self,
+        _spdm_buffer: &[u8],
+        _transport_buffer: &mut [u8],
+        _secured_message: bool,
+    ) -> SpdmResult<usize> {
+        spdm_result_err!(ENOSYS)
+    }
+
+    fn decap(
+        &mut self,
+        _transport_buffer: &[u8],
+        _spdm_buffer: &mut [u8],
+    ) -> SpdmResult<(usize, bool)> {
+        spdm_result_err!(ENOSYS)
+    }
+
+    fn encap_app(&mut self, _spdm_buffer: &[u8], _app_buffer: &mut [u8]) -> SpdmResult<usize> {
+        spdm_result_err!(ENOSYS)
+    }
+
+    fn decap_app(&mut self, _app_buffer: &[u8], _spdm_buffer: &mut [u8]) -> SpdmResult<usize> {
+        spdm_result_err!(ENOSYS)
+    }
+
+    fn get_sequence_number_count(&mut self) -> u8 {
+        0
+    }
+
+    fn get_max_random_count(&mut self) -> u16 {
+        0
+    }
+}
+
+/// Run `f` with a context negotiated at `spdm_version` with SHA-384,
+/// ECDSA P-384, SECP384R1 and AES-256-GCM.
+pub fn with_fuzz_context<T>(spdm_version: SpdmVersion, f: impl FnOnce(&mut SpdmContext) -> T) -> T {
+    let mut device_io = FuzzDeviceIo;
+    let mut transport_encap = FuzzTransportEncap;
+    let mut context = SpdmContext::new(
+        &mut device_io,
+        &mut transport_encap,
+        SpdmConfigInfo::default(),
+        SpdmProvisionInfo::default(),
+    );
+
+    let negotiate_info = &mut context.negotiate_info;
+    negotiate_info.spdm_version_sel = spdm_version;
+    negotiate_info.req_capabilities_sel = SpdmRequestCapabilityFlags::CERT_CAP
+        | SpdmRequestCapabilityFlags::CHAL_CAP
+        | SpdmRequestCapabilityFlags::ENCRYPT_CAP
+        | SpdmRequestCapabilityFlags::MAC_CAP
+        | SpdmRequestCapabilityFlags::MUT_AUTH_CAP
+        | SpdmRequestCapabilityFlags::KEY_EX_CAP
+        | SpdmRequestCapabilityFlags::PSK_CAP
+        | SpdmRequestCapabilityFlags::ENCAP_CAP
+        | SpdmRequestCapabilityFlags::HBEAT_CAP
+        | SpdmRequestCapabilityFlags::KEY_UPD_CAP
+        | SpdmRequestCapabilityFlags::CHUNK_CAP;
+    negotiate_info.rsp_capabilities_sel = SpdmResponseCapabilityFlags::CERT_CAP
+        | SpdmResponseCapabilityFlags::CHAL_CAP
+        | SpdmResponseCapabilityFlags::MEAS_CAP_SIG
+        | SpdmResponseCapabilityFlags::MEAS_FRESH_CAP
+        | SpdmResponseCapabilityFlags::ENCRYPT_CAP
+        | SpdmResponseCapabilityFlags::MAC_CAP
+        | SpdmResponseCapabilityFlags::MUT_AUTH_CAP
+        | SpdmResponseCapabilityFlags::KEY_EX_CAP
+        | SpdmResponseCapabilityFlags::PSK_CAP_WITH_CONTEXT
+        | SpdmResponseCapabilityFlags::ENCAP_CAP
+        | SpdmResponseCapabilityFlags::HBEAT_CAP
+        | SpdmResponseCapabilityFlags::KEY_UPD_CAP
+        | SpdmResponseCapabilityFlags::CHUNK_CAP;
+    negotiate_info.req_data_transfer_size_sel = config::MAX_SPDM_TRANSPORT_SIZE as u32;
+    negotiate_info.rsp_data_transfer_size_sel = config::MAX_SPDM_TRANSPORT_SIZE as u32;
+    negotiate_info.req_max_spdm_msg_size_sel = config::MAX_SPDM_MESSAGE_BUFFER_SIZE as u32;
+    negotiate_info.rsp_max_spdm_msg_size_sel = config::MAX_SPDM_MESSAGE_BUFFER_SIZE as u32;
+    negotiate_info.opaque_support_sel = SpdmOpaqueSupport::OPAQUE_DATA_FMT1;
+    negotiate_info.measurement_specification_sel = SpdmMeasurementSpecification::DMTF;
+    negotiate_info.measurement_hash_sel = SpdmMeasurementHashAlgo::TPM_ALG_SHA_384;
+    negotiate_info.base_hash_sel = SpdmBaseHashAlgo::TPM_ALG_SHA_384;
+    negotiate_info.base_asym_sel = SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P384;
+    negotiate_info.dhe_sel = SpdmDheAlgo::SECP_384_R1;
+    negotiate_info.aead_sel = SpdmAeadAlgo::AES_256_GCM;
+    negotiate_info.req_asym_sel = SpdmReqAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P384;
+    negotiate_info.key_schedule_sel = SpdmKeyScheduleAlgo::SPDM_KEY_SCHEDULE;
+    context.connection_state = SpdmConnectionState::Negotiated;
+
+    f(&mut context)
+}
+
+/// Decode `bytes` as the payload `T` of an `spdm_version` message.
+pub fn spdm_read_bytes<T: SpdmCodec>(spdm_version: SpdmVersion, bytes: &[u8]) -> Option<T> {
+    with_fuzz_context(spdm_version, |context| {
+        T::spdm_read(context, &mut Reader::init(bytes))
+    })
+}
+
+/// Decode `bytes` as an SPDM message of the version in its header, and
+/// encode it again to run the encoder on what was decoded.
+pub fn parse_spdm_message(bytes: &[u8]) -> Option<SpdmMessage> {
+    let spdm_version = SpdmVersion::read(&mut Reader::init(bytes))?;
+    with_fuzz_context(spdm_version, |context| {
+        let message = SpdmMessage::spdm_read(context, &mut Reader::init(bytes))?;
+        let mut buffer = [0u8; config::MAX_SPDM_MESSAGE_BUFFER_SIZE];
+        let _ = message.spdm_encode(context, &mut Writer::init(&mut buffer));
+        Some(message)
+    })
+}
+
+/// Decode `bytes` as a secured message of the session whose ID it starts
+/// with, in the handshake and the application phase and from either side.
+/// The session keys are fixed, the result is the size of the application
+/// data of the last decode that succeeded.
+pub fn decode_spdm_secured_message(bytes: &[u8]) -> Option<usize> {
+    let session_id = u32::read(&mut Reader::init(bytes))?;
+    let th = SpdmDigestStruct::try_from(&[0x5au8; 48][..]).ok()?;
+    let mut app_buffer = [0u8; config::MAX_SPDM_MESSAGE_BUFFER_SIZE];
+    let mut used = None;
+    for session_state in [
+        SpdmSessionState::SpdmSessionHandshaking,
+        SpdmSessionState::SpdmSessionEstablished,
+    ]
+    .iter()
+    {
+        for is_requester in [true, false].iter() {
+            let mut session = SpdmSession::new();
+            session.setup(session_id).ok()?;
+            session.set_crypto_param(
+                SpdmBaseHashAlgo::TPM_ALG_SHA_384,
+                SpdmDheAlgo::SECP_384_R1,
+                SpdmAeadAlgo::AES_256_GCM,
+                SpdmKeyScheduleAlgo::SPDM_KEY_SCHEDULE,
+            );
+            session.set_transport_param(2, 0);
+            let mut dhe_secret = SpdmDheFinalKeyStruct::try_from(&[0x5au8; 48][..]).ok()?;
+            session.set_dhe_secret(&mut dhe_secret).ok()?;
+            session.generate_handshake_secret(&th).ok()?;
+            session.generate_data_secret(&th).ok()?;
+            session.set_session_state(*session_state);
+            if let Ok(size) =
+                session.decode_spdm_secured_message(bytes, &mut app_buffer, *is_requester)
+            {
+                used = Some(size);
+            }
+        }
+    }
+    used
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_spdm_message() {
+        // GET_VERSION, VERSION and GET_MEASUREMENTS with a nonce
+        let get_version = [0x10, 0x84, 0x00, 0x00];
+        let version = [0x10, 0x04, 0x00, 0x00, 0x00, 0x01, 0x00, 0x12];
+        let mut get_measurements = [0u8; 37];
+        get_measurements[..4].copy_from_slice(&[0x12, 0xE0, 0x01, 0xFF]);
+        assert!(parse_spdm_message(&get_version).is_some());
+        assert!(parse_spdm_message(&version).is_some());
+        assert!(parse_spdm_message(&get_measurements).is_some());
+        assert!(parse_spdm_message(&[]).is_none());
+
+        // truncated and corrupted messages, all response codes
+        let mut seed = 1u32;
+        let mut bytes = [0u8; 256];
+        for code in 0..=255u8 {
+            for size in [0usize, 2, 4, 8, 37, 100, 256].iter() {
+                for b in bytes.iter_mut() {
+                    seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                    *b = (seed >> 16) as u8;
+                }
+                bytes[0] = 0x12;
+                bytes[1] = code;
+                let _ = parse_spdm_message(&bytes[..*size]);
+            }
+        }
+
+        let payload: Option<SpdmGetVersionRequestPayload> =
+            spdm_read_bytes(SpdmVersion::SpdmVersion10, &[0x00, 0x00]);
+        assert!(payload.is_some());
+    }
+
+    #[test]
+    fn test_decode_spdm_secured_message() {
+        let mut seed = 1u32;
+        let mut bytes = [0u8; 256];
+        for size in [0usize, 4, 7, 8, 9, 24, 25, 100, 256].iter() {
+            for length in [0u16, 1, 15, 16, 17, 18, 100, 248, 0x1000, 0xFFFF].iter() {
+                for b in bytes.iter_mut() {
+                    seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                    *b = (seed >> 16) as u8;
+                }
+                // session ID, the first sequence number, then the length
+                bytes[4..6].copy_from_slice(&[0, 0]);
+                bytes[6..8].copy_from_slice(&length.to_le_bytes());
+                assert!(decode_spdm_secured_message(&bytes[..*size]).is_none());
+            }
+        }
+    }
+}
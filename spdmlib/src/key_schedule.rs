@@ -2,8 +2,10 @@
 //
 // SPDX-License-Identifier: BSD-2-Clause-Patent
 
+use core::convert::TryFrom;
+
 use crate::config::MAX_SPDM_MESSAGE_BUFFER_SIZE;
-use crate::crypto;
+use crate::crypto::SpdmCryptoProvider;
 use crate::msgs::*;
 use codec::{Codec, Writer};
 
@@ -19,10 +21,17 @@ const BIN_STR6_LABEL: &[u8] = b"iv";
 const BIN_STR7_LABEL: &[u8] = b"finished";
 const BIN_STR8_LABEL: &[u8] = b"exp master";
 const BIN_STR9_LABEL: &[u8] = b"traffic upd";
-const SPDM_VERSION_VALUE: &[u8; 8] = b"spdm1.1 ";
+// not defined by the spec, only used between peers of this library
+const BIN_STR10_LABEL: &[u8] = b"res master";
+const BIN_STR11_LABEL: &[u8] = b"res hint";
+const SPDM_VERSION_1_1_VALUE: &[u8; 8] = b"spdm1.1 ";
+const SPDM_VERSION_1_2_VALUE: &[u8; 8] = b"spdm1.2 ";
 
 #[derive(Copy, Clone, Debug)]
-pub struct SpdmKeySchedule;
+pub struct SpdmKeySchedule {
+    spdm_version: SpdmVersion,
+    crypto_provider: SpdmCryptoProvider,
+}
 
 impl Default for SpdmKeySchedule {
     fn default() -> Self {
@@ -32,7 +41,27 @@ impl Default for SpdmKeySchedule {
 
 impl SpdmKeySchedule {
     pub fn new() -> Self {
-        SpdmKeySchedule {}
+        SpdmKeySchedule {
+            spdm_version: SpdmVersion::SpdmVersion11,
+            crypto_provider: SpdmCryptoProvider::default(),
+        }
+    }
+
+    /// The negotiated version selects the version label of the bin_str.
+    pub fn set_spdm_version(&mut self, spdm_version: SpdmVersion) {
+        self.spdm_version = spdm_version;
+    }
+
+    fn get_version_value(&self) -> &'static [u8; 8] {
+        if self.spdm_version.get_u8() >= SpdmVersion::SpdmVersion12.get_u8() {
+            SPDM_VERSION_1_2_VALUE
+        } else {
+            SPDM_VERSION_1_1_VALUE
+        }
+    }
+
+    pub fn set_crypto_provider(&mut self, crypto_provider: SpdmCryptoProvider) {
+        self.crypto_provider = crypto_provider;
     }
 
     pub fn derive_handshake_secret(
@@ -40,7 +69,8 @@ impl SpdmKeySchedule {
         hash_algo: SpdmBaseHashAlgo,
         key: &[u8],
     ) -> Option<SpdmDigestStruct> {
-        crypto::hmac::hmac(hash_algo, key, &SALT_0[0..hash_algo.get_size() as usize])
+        self.crypto_provider
+            .hmac(hash_algo, key, &SALT_0[0..hash_algo.get_size() as usize])
     }
 
     pub fn derive_master_secret(
@@ -51,16 +81,16 @@ impl SpdmKeySchedule {
         let buffer = &mut [0; MAX_SPDM_MESSAGE_BUFFER_SIZE];
         let bin_str0 = self.binconcat(
             hash_algo.get_size(),
-            SPDM_VERSION_VALUE,
+            self.get_version_value(),
             BIN_STR0_LABEL,
             None,
             buffer,
         )?;
-        let salt_1 = crypto::hkdf::hkdf_expand(hash_algo, key, bin_str0, hash_algo.get_size())?;
-
-        debug!("salt_1 - {:02x?}", salt_1.as_ref());
+        let salt_1 =
+            self.crypto_provider
+                .hkdf_expand(hash_algo, key, bin_str0, hash_algo.get_size())?;
 
-        crypto::hmac::hmac(
+        self.crypto_provider.hmac(
             hash_algo,
             salt_1.as_ref(),
             &ZERO_FILLED[0..hash_algo.get_size() as usize],
@@ -76,12 +106,13 @@ impl SpdmKeySchedule {
         let buffer = &mut [0; MAX_SPDM_MESSAGE_BUFFER_SIZE];
         let bin_str1 = self.binconcat(
             hash_algo.get_size(),
-            SPDM_VERSION_VALUE,
+            self.get_version_value(),
             BIN_STR1_LABEL,
             Some(th1),
             buffer,
         )?;
-        crypto::hkdf::hkdf_expand(hash_algo, key, bin_str1, hash_algo.get_size())
+        self.crypto_provider
+            .hkdf_expand(hash_algo, key, bin_str1, hash_algo.get_size())
     }
 
     pub fn derive_response_handshake_secret(
@@ -93,12 +124,13 @@ impl SpdmKeySchedule {
         let buffer = &mut [0; MAX_SPDM_MESSAGE_BUFFER_SIZE];
         let bin_str2 = self.binconcat(
             hash_algo.get_size(),
-            SPDM_VERSION_VALUE,
+            self.get_version_value(),
             BIN_STR2_LABEL,
             Some(th1),
             buffer,
         )?;
-        crypto::hkdf::hkdf_expand(hash_algo, key, bin_str2, hash_algo.get_size())
+        self.crypto_provider
+            .hkdf_expand(hash_algo, key, bin_str2, hash_algo.get_size())
     }
 
     pub fn derive_finished_key(
@@ -109,12 +141,13 @@ impl SpdmKeySchedule {
         let buffer = &mut [0; MAX_SPDM_MESSAGE_BUFFER_SIZE];
         let bin_str7 = self.binconcat(
             hash_algo.get_size(),
-            SPDM_VERSION_VALUE,
+            self.get_version_value(),
             BIN_STR7_LABEL,
             None,
             buffer,
         )?;
-        crypto::hkdf::hkdf_expand(hash_algo, key, bin_str7, hash_algo.get_size())
+        self.crypto_provider
+            .hkdf_expand(hash_algo, key, bin_str7, hash_algo.get_size())
     }
 
     pub fn derive_aead_key_iv(
@@ -126,39 +159,33 @@ impl SpdmKeySchedule {
         let buffer = &mut [0; MAX_SPDM_MESSAGE_BUFFER_SIZE];
         let bin_str5 = self.binconcat(
             aead_algo.get_key_size(),
-            SPDM_VERSION_VALUE,
+            self.get_version_value(),
             BIN_STR5_LABEL,
             None,
             buffer,
         )?;
-        let res =
-            crypto::hkdf::hkdf_expand(hash_algo, key, bin_str5, SPDM_MAX_AEAD_KEY_SIZE as u16)?;
-        let encrypt_key = SpdmAeadKeyStruct {
-            data_size: res.data_size,
-            data: {
-                let mut k = [0u8; SPDM_MAX_AEAD_KEY_SIZE];
-                k[0..res.data_size as usize].copy_from_slice(&res.data[0..res.data_size as usize]);
-                k
-            },
-        };
+        let res = self.crypto_provider.hkdf_expand(
+            hash_algo,
+            key,
+            bin_str5,
+            SPDM_MAX_AEAD_KEY_SIZE as u16,
+        )?;
+        let encrypt_key = SpdmAeadKeyStruct::try_from(res.as_slice()).ok()?;
 
         let bin_str6 = self.binconcat(
             aead_algo.get_iv_size(),
-            SPDM_VERSION_VALUE,
+            self.get_version_value(),
             BIN_STR6_LABEL,
             None,
             buffer,
         )?;
-        let res =
-            crypto::hkdf::hkdf_expand(hash_algo, key, bin_str6, SPDM_MAX_AEAD_IV_SIZE as u16)?;
-        let iv = SpdmAeadIvStruct {
-            data_size: res.data_size,
-            data: {
-                let mut k = [0u8; SPDM_MAX_AEAD_IV_SIZE];
-                k[0..res.data_size as usize].copy_from_slice(&res.data[0..res.data_size as usize]);
-                k
-            },
-        };
+        let res = self.crypto_provider.hkdf_expand(
+            hash_algo,
+            key,
+            bin_str6,
+            SPDM_MAX_AEAD_IV_SIZE as u16,
+        )?;
+        let iv = SpdmAeadIvStruct::try_from(res.as_slice()).ok()?;
         Some((encrypt_key, iv))
     }
 
@@ -171,12 +198,13 @@ impl SpdmKeySchedule {
         let buffer = &mut [0; MAX_SPDM_MESSAGE_BUFFER_SIZE];
         let bin_str3 = self.binconcat(
             hash_algo.get_size(),
-            SPDM_VERSION_VALUE,
+            self.get_version_value(),
             BIN_STR3_LABEL,
             Some(th2),
             buffer,
         )?;
-        crypto::hkdf::hkdf_expand(hash_algo, key, bin_str3, hash_algo.get_size())
+        self.crypto_provider
+            .hkdf_expand(hash_algo, key, bin_str3, hash_algo.get_size())
     }
 
     pub fn derive_response_data_secret(
@@ -188,12 +216,13 @@ impl SpdmKeySchedule {
         let buffer = &mut [0; MAX_SPDM_MESSAGE_BUFFER_SIZE];
         let bin_str4 = self.binconcat(
             hash_algo.get_size(),
-            SPDM_VERSION_VALUE,
+            self.get_version_value(),
             BIN_STR4_LABEL,
             Some(th2),
             buffer,
         )?;
-        crypto::hkdf::hkdf_expand(hash_algo, key, bin_str4, hash_algo.get_size())
+        self.crypto_provider
+            .hkdf_expand(hash_algo, key, bin_str4, hash_algo.get_size())
     }
 
     pub fn derive_export_master_secret(
@@ -204,12 +233,13 @@ impl SpdmKeySchedule {
         let buffer = &mut [0; MAX_SPDM_MESSAGE_BUFFER_SIZE];
         let bin_str8 = self.binconcat(
             hash_algo.get_size(),
-            SPDM_VERSION_VALUE,
+            self.get_version_value(),
             BIN_STR8_LABEL,
             None,
             buffer,
         )?;
-        crypto::hkdf::hkdf_expand(hash_algo, key, bin_str8, hash_algo.get_size())
+        self.crypto_provider
+            .hkdf_expand(hash_algo, key, bin_str8, hash_algo.get_size())
     }
 
     pub fn derive_update_secret(
@@ -220,12 +250,67 @@ impl SpdmKeySchedule {
         let buffer = &mut [0; MAX_SPDM_MESSAGE_BUFFER_SIZE];
         let bin_str9 = self.binconcat(
             hash_algo.get_size(),
-            SPDM_VERSION_VALUE,
+            self.get_version_value(),
             BIN_STR9_LABEL,
             None,
             buffer,
         )?;
-        crypto::hkdf::hkdf_expand(hash_algo, key, bin_str9, hash_algo.get_size())
+        self.crypto_provider
+            .hkdf_expand(hash_algo, key, bin_str9, hash_algo.get_size())
+    }
+
+    /// PSK for resuming a session, derived from its master secret.
+    pub fn derive_resumption_psk(
+        &self,
+        hash_algo: SpdmBaseHashAlgo,
+        key: &[u8],
+    ) -> Option<SpdmDigestStruct> {
+        let buffer = &mut [0; MAX_SPDM_MESSAGE_BUFFER_SIZE];
+        let bin_str10 = self.binconcat(
+            hash_algo.get_size(),
+            self.get_version_value(),
+            BIN_STR10_LABEL,
+            None,
+            buffer,
+        )?;
+        self.crypto_provider
+            .hkdf_expand(hash_algo, key, bin_str10, hash_algo.get_size())
+    }
+
+    /// PSK hint of the resumption PSK, `size` bytes derived from the master
+    /// secret so both peers agree on it without exchanging it.
+    pub fn derive_resumption_psk_hint(
+        &self,
+        hash_algo: SpdmBaseHashAlgo,
+        key: &[u8],
+        size: u16,
+    ) -> Option<SpdmDigestStruct> {
+        let buffer = &mut [0; MAX_SPDM_MESSAGE_BUFFER_SIZE];
+        let bin_str11 = self.binconcat(
+            size,
+            self.get_version_value(),
+            BIN_STR11_LABEL,
+            None,
+            buffer,
+        )?;
+        self.crypto_provider
+            .hkdf_expand(hash_algo, key, bin_str11, size)
+    }
+
+    /// HKDF-Expand of `key` with the bin_str of `label` and `context`, the
+    /// way every secret of the key schedule is derived.
+    pub fn hkdf_expand_label(
+        &self,
+        hash_algo: SpdmBaseHashAlgo,
+        key: &[u8],
+        label: &[u8],
+        context: Option<&[u8]>,
+        size: u16,
+    ) -> Option<SpdmDigestStruct> {
+        let buffer = &mut [0; MAX_SPDM_MESSAGE_BUFFER_SIZE];
+        let bin_str = self.binconcat(size, self.get_version_value(), label, context, buffer)?;
+        self.crypto_provider
+            .hkdf_expand(hash_algo, key, bin_str, size)
     }
 
     fn binconcat<'a>(
@@ -6,10 +6,10 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
 #[macro_use]
-extern crate log;
+extern crate bitflags;
 
 #[macro_use]
-extern crate bitflags;
+pub mod trace;
 
 #[macro_use]
 pub mod error;
@@ -20,11 +20,25 @@ pub mod config;
 
 pub mod cmds;
 pub mod common;
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub mod loopback;
+pub mod mailbox;
 pub mod msgs;
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub mod pcap;
+pub mod provision;
 pub mod requester;
 pub mod responder;
+#[cfg(feature = "std")]
+pub mod socket;
 
 pub mod session;
 
 pub mod crypto;
+#[cfg(feature = "fuzzing")]
+pub mod fuzz;
 pub mod key_schedule;
+pub mod time;
+
+#[cfg(test)]
+mod testlib;
@@ -0,0 +1,369 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+#![forbid(unsafe_code)]
+
+//! In-memory `SpdmDeviceIo` connecting a requester and a responder on the
+//! same thread, for tests that run both sides.
+//!
+//! `SpdmDeviceIoLoopback::pair()` returns two linked endpoints, each
+//! receiving what the other one sends. The requester endpoint is given the
+//! responder with `attach_responder()` and has it process every request as
+//! soon as it is sent, so the response is queued by the time the requester
+//! receives.
+
+extern crate alloc;
+use alloc::collections::VecDeque;
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+use crate::common::SpdmDeviceIo;
+use crate::error::SpdmResult;
+use crate::responder::ResponderContext;
+
+type MessageQueue = Rc<RefCell<VecDeque<Vec<u8>>>>;
+
+pub struct SpdmDeviceIoLoopback<'a> {
+    rx: MessageQueue,
+    tx: MessageQueue,
+    responder: Option<Rc<RefCell<ResponderContext<'a>>>>,
+}
+
+impl<'a> SpdmDeviceIoLoopback<'a> {
+    /// Two endpoints, the first one for the requester.
+    pub fn pair<'b>() -> (Self, SpdmDeviceIoLoopback<'b>) {
+        let requests = MessageQueue::default();
+        let responses = MessageQueue::default();
+        (
+            SpdmDeviceIoLoopback {
+                rx: responses.clone(),
+                tx: requests.clone(),
+                responder: None,
+            },
+            SpdmDeviceIoLoopback {
+                rx: requests,
+                tx: responses,
+                responder: None,
+            },
+        )
+    }
+
+    /// Have `responder`, which uses the other endpoint, process the
+    /// messages sent on this one.
+    pub fn attach_responder(&mut self, responder: Rc<RefCell<ResponderContext<'a>>>) {
+        self.responder = Some(responder);
+    }
+
+    /// Messages sent to this endpoint and not received yet.
+    pub fn pending(&self) -> usize {
+        self.rx.borrow().len()
+    }
+}
+
+impl SpdmDeviceIo for SpdmDeviceIoLoopback<'_> {
+    fn send(&mut self, buffer: &[u8]) -> SpdmResult {
+        self.tx.borrow_mut().push_back(buffer.to_vec());
+        if let Some(responder) = &self.responder {
+            // a message the responder can't decode is left unanswered
+            let mut responder = responder.borrow_mut();
+            while !self.tx.borrow().is_empty() {
+                let _ = responder.process_message();
+            }
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self, buffer: &mut [u8]) -> Result<usize, usize> {
+        let message = self.rx.borrow_mut().pop_front().ok_or(0usize)?;
+        if message.len() > buffer.len() {
+            return Err(0);
+        }
+        buffer[..message.len()].copy_from_slice(&message);
+        Ok(message.len())
+    }
+
+    fn flush_all(&mut self) -> SpdmResult {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{SpdmConfigInfo, SpdmConnectionState, SpdmProvisionInfo};
+    use crate::config;
+    use crate::msgs::*;
+    use crate::pcap::{SpdmPcapRecorder, LINKTYPE_USER0};
+    use crate::requester::RequesterContext;
+    use crate::testlib::PlainTransportEncap;
+    use crate::trace::{self, SpdmLogLevel, SpdmMessageDirection, SpdmTrace};
+
+    #[test]
+    fn test_loopback_init_connection() {
+        let config_info = SpdmConfigInfo {
+            spdm_version: [
+                SpdmVersion::SpdmVersion10,
+                SpdmVersion::SpdmVersion11,
+                SpdmVersion::SpdmVersion12,
+            ],
+            req_capabilities: SpdmRequestCapabilityFlags::CERT_CAP
+                | SpdmRequestCapabilityFlags::CHAL_CAP,
+            rsp_capabilities: SpdmResponseCapabilityFlags::CERT_CAP
+                | SpdmResponseCapabilityFlags::CHAL_CAP,
+            measurement_specification: SpdmMeasurementSpecification::DMTF,
+            measurement_hash_algo: SpdmMeasurementHashAlgo::TPM_ALG_SHA_384,
+            base_asym_algo: SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P384,
+            base_hash_algo: SpdmBaseHashAlgo::TPM_ALG_SHA_384,
+            ..SpdmConfigInfo::default()
+        };
+
+        let (mut requester_io, mut responder_io) = SpdmDeviceIoLoopback::pair();
+        let mut requester_encap = PlainTransportEncap;
+        let mut responder_encap = PlainTransportEncap;
+        let responder = Rc::new(RefCell::new(ResponderContext::new(
+            &mut responder_io,
+            &mut responder_encap,
+            config_info.clone(),
+            SpdmProvisionInfo::default(),
+        )));
+        requester_io.attach_responder(responder.clone());
+        let mut requester = RequesterContext::new(
+            &mut requester_io,
+            &mut requester_encap,
+            config_info,
+            SpdmProvisionInfo::default(),
+        );
+
+        requester.init_connection().unwrap();
+        assert_eq!(
+            requester.common.get_connection_state(),
+            SpdmConnectionState::Negotiated
+        );
+        assert_eq!(
+            responder.borrow().common.get_connection_state(),
+            SpdmConnectionState::Negotiated
+        );
+        assert_eq!(
+            responder.borrow().common.negotiate_info.base_hash_sel,
+            SpdmBaseHashAlgo::TPM_ALG_SHA_384
+        );
+    }
+
+    fn negotiate_version(
+        requester_versions: [SpdmVersion; config::MAX_SPDM_VERSION_COUNT],
+        responder_versions: [SpdmVersion; config::MAX_SPDM_VERSION_COUNT],
+    ) -> Option<(SpdmVersion, SpdmVersion)> {
+        let config_info = SpdmConfigInfo {
+            base_hash_algo: SpdmBaseHashAlgo::TPM_ALG_SHA_384,
+            base_asym_algo: SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P384,
+            ..SpdmConfigInfo::default()
+        };
+
+        let (mut requester_io, mut responder_io) = SpdmDeviceIoLoopback::pair();
+        let mut requester_encap = PlainTransportEncap;
+        let mut responder_encap = PlainTransportEncap;
+        let responder = Rc::new(RefCell::new(ResponderContext::new(
+            &mut responder_io,
+            &mut responder_encap,
+            SpdmConfigInfo {
+                spdm_version: responder_versions,
+                ..config_info.clone()
+            },
+            SpdmProvisionInfo::default(),
+        )));
+        requester_io.attach_responder(responder.clone());
+        let mut requester = RequesterContext::new(
+            &mut requester_io,
+            &mut requester_encap,
+            SpdmConfigInfo {
+                spdm_version: requester_versions,
+                ..config_info
+            },
+            SpdmProvisionInfo::default(),
+        );
+
+        requester.send_receive_spdm_version().ok()?;
+        requester.send_receive_spdm_capability().ok()?;
+        let responder_version = responder.borrow().common.negotiate_info.spdm_version_sel;
+        Some((
+            requester.common.negotiate_info.spdm_version_sel,
+            responder_version,
+        ))
+    }
+
+    #[test]
+    fn test_loopback_version_selection() {
+        // the highest version both sides advertise, in any order
+        assert_eq!(
+            negotiate_version(
+                [
+                    SpdmVersion::SpdmVersion10,
+                    SpdmVersion::SpdmVersion11,
+                    SpdmVersion::SpdmVersion12,
+                ],
+                [
+                    SpdmVersion::SpdmVersion11,
+                    SpdmVersion::SpdmVersion10,
+                    SpdmVersion::Unknown(0),
+                ],
+            ),
+            Some((SpdmVersion::SpdmVersion11, SpdmVersion::SpdmVersion11))
+        );
+        assert_eq!(
+            negotiate_version(
+                [
+                    SpdmVersion::SpdmVersion12,
+                    SpdmVersion::SpdmVersion11,
+                    SpdmVersion::Unknown(0),
+                ],
+                [
+                    SpdmVersion::SpdmVersion12,
+                    SpdmVersion::SpdmVersion10,
+                    SpdmVersion::SpdmVersion11,
+                ],
+            ),
+            Some((SpdmVersion::SpdmVersion12, SpdmVersion::SpdmVersion12))
+        );
+
+        // nothing in common
+        assert_eq!(
+            negotiate_version(
+                [
+                    SpdmVersion::SpdmVersion12,
+                    SpdmVersion::Unknown(0),
+                    SpdmVersion::Unknown(0),
+                ],
+                [
+                    SpdmVersion::SpdmVersion10,
+                    SpdmVersion::SpdmVersion11,
+                    SpdmVersion::Unknown(0),
+                ],
+            ),
+            None
+        );
+    }
+
+    std::thread_local! {
+        static MESSAGES: RefCell<Vec<(SpdmMessageDirection, Option<u32>, u8)>> =
+            RefCell::new(Vec::with_capacity(16));
+        static LOGS: RefCell<Vec<(SpdmLogLevel, std::string::String)>> =
+            RefCell::new(Vec::with_capacity(16));
+    }
+
+    #[test]
+    fn test_loopback_trace() {
+        // the other tests log to it too, each thread keeps its own
+        assert!(trace::register(SpdmTrace {
+            log_cb: |level, args| {
+                LOGS.with(|logs| logs.borrow_mut().push((level, std::format!("{}", args))))
+            },
+            on_message_cb: Some(|direction, session_id, bytes| {
+                MESSAGES.with(|messages| {
+                    messages.borrow_mut().push((
+                        direction,
+                        session_id,
+                        bytes.get(1).copied().unwrap_or(0),
+                    ))
+                })
+            }),
+        }));
+
+        negotiate_version(
+            [
+                SpdmVersion::SpdmVersion10,
+                SpdmVersion::Unknown(0),
+                SpdmVersion::Unknown(0),
+            ],
+            [
+                SpdmVersion::SpdmVersion10,
+                SpdmVersion::Unknown(0),
+                SpdmVersion::Unknown(0),
+            ],
+        )
+        .unwrap();
+
+        // each message is seen by the requester and the responder
+        let messages = MESSAGES.with(|messages| messages.borrow().clone());
+        assert_eq!(
+            messages,
+            [
+                (SpdmMessageDirection::Send, None, 0x84),
+                (SpdmMessageDirection::Receive, None, 0x84),
+                (SpdmMessageDirection::Send, None, 0x04),
+                (SpdmMessageDirection::Receive, None, 0x04),
+                (SpdmMessageDirection::Send, None, 0xE1),
+                (SpdmMessageDirection::Receive, None, 0xE1),
+                (SpdmMessageDirection::Send, None, 0x61),
+                (SpdmMessageDirection::Receive, None, 0x61),
+            ]
+        );
+        LOGS.with(|logs| {
+            assert!(logs
+                .borrow()
+                .iter()
+                .any(|(level, log)| *level == SpdmLogLevel::Debug
+                    && log.starts_with("send GET_VERSION")));
+        });
+    }
+
+    #[test]
+    fn test_loopback_pcap() {
+        let config_info = SpdmConfigInfo {
+            spdm_version: [
+                SpdmVersion::SpdmVersion10,
+                SpdmVersion::SpdmVersion11,
+                SpdmVersion::SpdmVersion12,
+            ],
+            base_hash_algo: SpdmBaseHashAlgo::TPM_ALG_SHA_384,
+            base_asym_algo: SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P384,
+            ..SpdmConfigInfo::default()
+        };
+
+        let mut recorder = SpdmPcapRecorder::new(Vec::new(), LINKTYPE_USER0);
+        let (mut requester_io, mut responder_io) = SpdmDeviceIoLoopback::pair();
+        let mut requester_encap = PlainTransportEncap;
+        let mut responder_encap = PlainTransportEncap;
+        {
+            let responder = Rc::new(RefCell::new(ResponderContext::new(
+                &mut responder_io,
+                &mut responder_encap,
+                config_info.clone(),
+                SpdmProvisionInfo::default(),
+            )));
+            requester_io.attach_responder(responder);
+            let mut requester = RequesterContext::new(
+                &mut requester_io,
+                &mut requester_encap,
+                config_info,
+                SpdmProvisionInfo::default(),
+            );
+            requester.common.attach_trace_recorder(&mut recorder);
+            requester.send_receive_spdm_version().unwrap();
+        }
+        assert_eq!(recorder.get_dropped_count(), 0);
+        let capture = recorder.into_inner();
+
+        // section and interface headers, then GET_VERSION and VERSION
+        let mut packets = Vec::new();
+        let mut offset = 48;
+        while offset < capture.len() {
+            let u32_at = |offset: usize| {
+                let mut bytes = [0u8; 4];
+                bytes.copy_from_slice(&capture[offset..offset + 4]);
+                u32::from_le_bytes(bytes)
+            };
+            let block_size = u32_at(offset + 4) as usize;
+            let packet_size = u32_at(offset + 20) as usize;
+            let flags = u32_at(offset + block_size - 12);
+            packets.push((flags, capture[offset + 28 + 1], packet_size));
+            offset += block_size;
+        }
+        assert_eq!(offset, capture.len());
+        assert_eq!(packets.len(), 2);
+        assert_eq!((packets[0].0, packets[0].1), (2, 0x84));
+        assert_eq!((packets[1].0, packets[1].1), (1, 0x04));
+        assert_eq!(packets[0].2, 4);
+    }
+}
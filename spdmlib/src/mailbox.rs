@@ -0,0 +1,324 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+#![forbid(unsafe_code)]
+
+//! `SpdmDeviceIo` over doorbell / mailbox hardware.
+//!
+//! The two sides share one buffer, owned by one of them at a time. The
+//! owner writes a message into it and rings the doorbell, which hands the
+//! buffer to the other side. That side reads the message and owns the
+//! buffer until it rings the doorbell with its answer. The device only
+//! implements the register accesses in `SpdmMailboxHw`.
+
+use crate::common::SpdmDeviceIo;
+use crate::error::SpdmResult;
+use crate::time;
+
+/// Mailbox status as read from the hardware.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpdmMailboxStatus {
+    /// Owned by this side, nothing to read.
+    Idle,
+    /// Owned by the peer.
+    Busy,
+    /// The peer rang the doorbell, the buffer holds its message and is
+    /// owned by this side.
+    MessageReady,
+    /// The hardware needs a reset.
+    Error,
+}
+
+/// Register accesses of one mailbox.
+pub trait SpdmMailboxHw {
+    /// Size of the shared buffer.
+    fn get_buffer_size(&mut self) -> usize;
+
+    /// Copy `message` to the shared buffer. Only called while this side
+    /// owns it.
+    fn write_buffer(&mut self, message: &[u8]) -> SpdmResult;
+
+    /// Copy the message of the peer from the shared buffer to `message`,
+    /// returns its size. Only called in `MessageReady`.
+    fn read_buffer(&mut self, message: &mut [u8]) -> SpdmResult<usize>;
+
+    /// Hand the buffer holding a message of `size` bytes to the peer.
+    fn ring_doorbell(&mut self, size: usize) -> SpdmResult;
+
+    fn poll_status(&mut self) -> SpdmMailboxStatus;
+
+    /// Reset the mailbox after an error or a timeout, e.g. by taking the
+    /// buffer back from the peer.
+    fn reset(&mut self) -> SpdmResult;
+}
+
+/// Whose turn it is, as far as the adapter knows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpdmMailboxState {
+    /// This side may write the next message.
+    Owned,
+    /// The doorbell was rung, waiting for the message of the peer.
+    PeerOwned,
+    /// A timeout or an error, the mailbox is reset before it is used again.
+    Failed,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SpdmMailboxConfig {
+    /// Time between two reads of the status.
+    pub poll_interval_us: usize,
+    /// How long `send()` waits for the peer to give the buffer back.
+    pub send_timeout_us: usize,
+    /// How long `receive()` waits for a message, 0 waits forever.
+    /// `receive_with_timeout()` uses the timeout it is given.
+    pub receive_timeout_us: usize,
+}
+
+impl Default for SpdmMailboxConfig {
+    fn default() -> SpdmMailboxConfig {
+        SpdmMailboxConfig {
+            poll_interval_us: 100,
+            send_timeout_us: 1_000_000,
+            receive_timeout_us: 0,
+        }
+    }
+}
+
+/// `SpdmDeviceIo` driving the mailbox `hw`.
+///
+/// A receive that fails on a hardware error returns `Err(0)` like a
+/// timeout, so a requester with a receive timeout sends the request again.
+/// The mailbox is reset before that send.
+pub struct SpdmMailboxDeviceIo<T: SpdmMailboxHw> {
+    hw: T,
+    config: SpdmMailboxConfig,
+    state: SpdmMailboxState,
+}
+
+impl<T: SpdmMailboxHw> SpdmMailboxDeviceIo<T> {
+    /// `owned` is whether this side starts out owning the buffer, usually
+    /// the requester.
+    pub fn new(hw: T, config: SpdmMailboxConfig, owned: bool) -> Self {
+        SpdmMailboxDeviceIo {
+            hw,
+            config,
+            state: if owned {
+                SpdmMailboxState::Owned
+            } else {
+                SpdmMailboxState::PeerOwned
+            },
+        }
+    }
+
+    pub fn get_state(&self) -> SpdmMailboxState {
+        self.state
+    }
+
+    pub fn get_hw(&mut self) -> &mut T {
+        &mut self.hw
+    }
+
+    // Reset the hardware and pick up the ownership it reports.
+    fn recover(&mut self) -> SpdmResult {
+        info!("mailbox reset\n");
+        self.hw.reset()?;
+        self.state = match self.hw.poll_status() {
+            SpdmMailboxStatus::Idle | SpdmMailboxStatus::MessageReady => SpdmMailboxState::Owned,
+            SpdmMailboxStatus::Busy => SpdmMailboxState::PeerOwned,
+            SpdmMailboxStatus::Error => {
+                self.state = SpdmMailboxState::Failed;
+                return spdm_result_err!(EIO, "mailbox reset failed");
+            }
+        };
+        Ok(())
+    }
+
+    // Poll until the status is one of `expected`, for `timeout_us` at most,
+    // or forever if `timeout_us` is None. An error status fails the mailbox.
+    fn wait_for(
+        &mut self,
+        expected: &[SpdmMailboxStatus],
+        timeout_us: Option<usize>,
+    ) -> SpdmResult<SpdmMailboxStatus> {
+        let mut waited_us = 0usize;
+        loop {
+            let status = self.hw.poll_status();
+            if expected.contains(&status) {
+                return Ok(status);
+            }
+            if status == SpdmMailboxStatus::Error {
+                self.state = SpdmMailboxState::Failed;
+                return spdm_result_err!(EIO, "mailbox error");
+            }
+            if let Some(timeout_us) = timeout_us {
+                if waited_us >= timeout_us {
+                    return spdm_result_err!(ETIMEDOUT);
+                }
+            }
+            time::sleep(self.config.poll_interval_us);
+            waited_us = waited_us.saturating_add(self.config.poll_interval_us);
+        }
+    }
+
+    fn receive_within(
+        &mut self,
+        buffer: &mut [u8],
+        timeout_us: Option<usize>,
+    ) -> SpdmResult<usize> {
+        if self.state == SpdmMailboxState::Failed {
+            self.recover()?;
+        }
+        self.wait_for(&[SpdmMailboxStatus::MessageReady], timeout_us)?;
+        self.state = SpdmMailboxState::Owned;
+        let result = self.hw.read_buffer(buffer);
+        if result.is_err() {
+            self.state = SpdmMailboxState::Failed;
+        }
+        result
+    }
+}
+
+impl<T: SpdmMailboxHw> SpdmDeviceIo for SpdmMailboxDeviceIo<T> {
+    /// Fails with E2BIG if `buffer` doesn't fit in the mailbox and with
+    /// ETIMEDOUT if the peer doesn't give the buffer back in time.
+    fn send(&mut self, buffer: &[u8]) -> SpdmResult {
+        if buffer.len() > self.hw.get_buffer_size() {
+            return spdm_result_err!(E2BIG, "message larger than the mailbox");
+        }
+        if self.state == SpdmMailboxState::Failed {
+            self.recover()?;
+        }
+        if self.state == SpdmMailboxState::PeerOwned {
+            // sending twice in a row, e.g. a retried request
+            let timeout_us = self.config.send_timeout_us;
+            if let Err(e) = self.wait_for(
+                &[SpdmMailboxStatus::Idle, SpdmMailboxStatus::MessageReady],
+                Some(timeout_us),
+            ) {
+                error!("!!! mailbox : peer owns the buffer !!!\n");
+                // take the buffer back on the next send
+                self.state = SpdmMailboxState::Failed;
+                return Err(e);
+            }
+        }
+
+        let result = self
+            .hw
+            .write_buffer(buffer)
+            .and_then(|_| self.hw.ring_doorbell(buffer.len()));
+        self.state = match result {
+            Ok(()) => SpdmMailboxState::PeerOwned,
+            Err(_) => SpdmMailboxState::Failed,
+        };
+        result
+    }
+
+    fn receive(&mut self, buffer: &mut [u8]) -> Result<usize, usize> {
+        let timeout_us = match self.config.receive_timeout_us {
+            0 => None,
+            timeout_us => Some(timeout_us),
+        };
+        self.receive_within(buffer, timeout_us).map_err(|_| 0usize)
+    }
+
+    fn receive_with_timeout(
+        &mut self,
+        buffer: &mut [u8],
+        timeout_us: usize,
+    ) -> Result<usize, usize> {
+        self.receive_within(buffer, Some(timeout_us))
+            .map_err(|_| 0usize)
+    }
+
+    fn flush_all(&mut self) -> SpdmResult {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The peer answers each message with the message reversed.
+    struct LoopbackMailbox {
+        buffer: [u8; 16],
+        size: usize,
+        status: SpdmMailboxStatus,
+        resets: usize,
+    }
+
+    impl SpdmMailboxHw for LoopbackMailbox {
+        fn get_buffer_size(&mut self) -> usize {
+            self.buffer.len()
+        }
+
+        fn write_buffer(&mut self, message: &[u8]) -> SpdmResult {
+            self.buffer[..message.len()].copy_from_slice(message);
+            Ok(())
+        }
+
+        fn read_buffer(&mut self, message: &mut [u8]) -> SpdmResult<usize> {
+            message[..self.size].copy_from_slice(&self.buffer[..self.size]);
+            self.status = SpdmMailboxStatus::Idle;
+            Ok(self.size)
+        }
+
+        fn ring_doorbell(&mut self, size: usize) -> SpdmResult {
+            if self.status == SpdmMailboxStatus::Error {
+                return Ok(());
+            }
+            self.buffer[..size].reverse();
+            self.size = size;
+            self.status = SpdmMailboxStatus::MessageReady;
+            Ok(())
+        }
+
+        fn poll_status(&mut self) -> SpdmMailboxStatus {
+            self.status
+        }
+
+        fn reset(&mut self) -> SpdmResult {
+            self.resets += 1;
+            self.status = SpdmMailboxStatus::Idle;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_mailbox_device_io() {
+        let hw = LoopbackMailbox {
+            buffer: [0u8; 16],
+            size: 0,
+            status: SpdmMailboxStatus::Idle,
+            resets: 0,
+        };
+        let config = SpdmMailboxConfig {
+            poll_interval_us: 0,
+            ..Default::default()
+        };
+        let mut device_io = SpdmMailboxDeviceIo::new(hw, config, true);
+        let mut buffer = [0u8; 16];
+
+        device_io.send(&[1, 2, 3]).unwrap();
+        assert_eq!(device_io.get_state(), SpdmMailboxState::PeerOwned);
+        assert_eq!(device_io.receive(&mut buffer), Ok(3));
+        assert_eq!(&buffer[..3], &[3, 2, 1]);
+        assert_eq!(device_io.get_state(), SpdmMailboxState::Owned);
+
+        assert!(device_io.send(&[0u8; 17]).is_err());
+
+        // nothing to receive while the buffer is ours
+        assert_eq!(device_io.receive_with_timeout(&mut buffer, 0), Err(0));
+
+        device_io.get_hw().status = SpdmMailboxStatus::Error;
+        device_io.send(&[4]).unwrap();
+        assert_eq!(device_io.receive_with_timeout(&mut buffer, 0), Err(0));
+        assert_eq!(device_io.get_state(), SpdmMailboxState::Failed);
+
+        device_io.send(&[5, 6]).unwrap();
+        assert_eq!(device_io.get_hw().resets, 1);
+        assert_eq!(device_io.receive(&mut buffer), Ok(2));
+        assert_eq!(&buffer[..2], &[6, 5]);
+    }
+}
@@ -4,7 +4,9 @@
 
 #![forbid(unsafe_code)]
 
+use crate::common;
 use crate::config;
+use crate::error::SpdmResult;
 use bytes::BytesMut;
 use codec::{enum_builder, Codec, Reader, Writer};
 use core::convert::From;
@@ -15,6 +17,7 @@ pub const SHA512_DIGEST_SIZE: usize = 64;
 pub const SHA3_256_DIGEST_SIZE: usize = 32;
 pub const SHA3_384_DIGEST_SIZE: usize = 48;
 pub const SHA3_512_DIGEST_SIZE: usize = 64;
+pub const SM3_256_DIGEST_SIZE: usize = 32;
 
 pub const RSASSA_2048_KEY_SIZE: usize = 256;
 pub const RSASSA_3072_KEY_SIZE: usize = 384;
@@ -26,6 +29,7 @@ pub const RSAPSS_4096_KEY_SIZE: usize = 512;
 pub const ECDSA_ECC_NIST_P256_KEY_SIZE: usize = 32 * 2;
 pub const ECDSA_ECC_NIST_P384_KEY_SIZE: usize = 48 * 2;
 pub const ECDSA_ECC_NIST_P521_KEY_SIZE: usize = 66 * 2;
+pub const SM2_ECC_SM2_P256_KEY_SIZE: usize = 32 * 2;
 
 pub const FFDHE_2048_KEY_SIZE: usize = 256;
 pub const FFDHE_3072_KEY_SIZE: usize = 384;
@@ -34,22 +38,27 @@ pub const FFDHE_4096_KEY_SIZE: usize = 512;
 pub const SECP_256_R1_KEY_SIZE: usize = 32 * 2;
 pub const SECP_384_R1_KEY_SIZE: usize = 48 * 2;
 pub const SECP_521_R1_KEY_SIZE: usize = 66 * 2;
+pub const SM2_P256_KEY_SIZE: usize = 32 * 2;
 
 pub const AEAD_AES_128_GCM_KEY_SIZE: usize = 16;
 pub const AEAD_AES_256_GCM_KEY_SIZE: usize = 32;
 pub const AEAD_CHACHA20_POLY1305_KEY_SIZE: usize = 32;
+pub const AEAD_SM4_GCM_KEY_SIZE: usize = 16;
 
 pub const AEAD_AES_128_GCM_BLOCK_SIZE: usize = 16;
 pub const AEAD_AES_256_GCM_BLOCK_SIZE: usize = 16;
 pub const AEAD_CHACHA20_POLY1305_BLOCK_SIZE: usize = 16;
+pub const AEAD_SM4_GCM_BLOCK_SIZE: usize = 16;
 
 pub const AEAD_AES_128_GCM_IV_SIZE: usize = 12;
 pub const AEAD_AES_256_GCM_IV_SIZE: usize = 12;
 pub const AEAD_CHACHA20_POLY1305_IV_SIZE: usize = 12;
+pub const AEAD_SM4_GCM_IV_SIZE: usize = 12;
 
 pub const AEAD_AES_128_GCM_TAG_SIZE: usize = 16;
 pub const AEAD_AES_256_GCM_TAG_SIZE: usize = 16;
 pub const AEAD_CHACHA20_POLY1305_TAG_SIZE: usize = 16;
+pub const AEAD_SM4_GCM_TAG_SIZE: usize = 16;
 
 pub const SPDM_NONCE_SIZE: usize = 32;
 pub const SPDM_RANDOM_SIZE: usize = 32;
@@ -58,6 +67,7 @@ pub const SPDM_MAX_ASYM_KEY_SIZE: usize = 512;
 pub const SPDM_MAX_DHE_KEY_SIZE: usize = 512;
 pub const SPDM_MAX_AEAD_KEY_SIZE: usize = 32;
 pub const SPDM_MAX_AEAD_IV_SIZE: usize = 12;
+pub const SPDM_MAX_AEAD_TAG_SIZE: usize = 16;
 
 #[derive(Debug, Copy, Clone)]
 pub struct SpdmDigestStruct {
@@ -73,24 +83,26 @@ impl Default for SpdmDigestStruct {
     }
 }
 
-impl AsRef<[u8]> for SpdmDigestStruct {
-    fn as_ref(&self) -> &[u8] {
-        &self.data[0..self.data_size as usize]
+impl SpdmDigestStruct {
+    /// Fails with EINVAL unless the digest has the size of `base_hash_algo`.
+    pub fn check_size(&self, base_hash_algo: SpdmBaseHashAlgo) -> SpdmResult {
+        if self.data_size != base_hash_algo.get_size() {
+            error!(
+                "!!! digest size {} for {:?} !!!\n",
+                self.data_size, base_hash_algo
+            );
+            return spdm_result_err!(EINVAL, "digest size mismatch");
+        }
+        Ok(())
     }
 }
 
 impl From<BytesMut> for SpdmDigestStruct {
     fn from(value: BytesMut) -> Self {
-        SpdmDigestStruct::from(value.as_ref())
-    }
-}
-
-impl From<&[u8]> for SpdmDigestStruct {
-    fn from(value: &[u8]) -> Self {
-        assert!(value.len() <= SPDM_MAX_HASH_SIZE);
-        let data_size = value.len() as u16;
+        assert!(value.as_ref().len() <= SPDM_MAX_HASH_SIZE);
+        let data_size = value.as_ref().len() as u16;
         let mut data = [0u8; SPDM_MAX_HASH_SIZE];
-        data[0..value.len()].copy_from_slice(value.as_ref());
+        data[0..value.as_ref().len()].copy_from_slice(value.as_ref());
         Self { data_size, data }
     }
 }
@@ -99,6 +111,15 @@ bitflags! {
     #[derive(Default)]
     pub struct SpdmMeasurementSpecification: u8 {
         const DMTF = 0b0000_0001;
+        // Not assigned by SPDM. Kept so that other measurement
+        // specifications can be negotiated and passed through.
+        const SPEC_1 = 0b0000_0010;
+        const SPEC_2 = 0b0000_0100;
+        const SPEC_3 = 0b0000_1000;
+        const SPEC_4 = 0b0001_0000;
+        const SPEC_5 = 0b0010_0000;
+        const SPEC_6 = 0b0100_0000;
+        const SPEC_7 = 0b1000_0000;
     }
 }
 
@@ -121,9 +142,12 @@ impl SpdmMeasurementSpecification {
         for v in prio_table.iter() {
             if self.bits() & v.bits() != 0 {
                 *self = *v;
-                break;
+                return;
             }
         }
+        // no preferred one in common, select the lowest common bit
+        let bits = self.bits();
+        *self = SpdmMeasurementSpecification::from_bits_truncate(bits & bits.wrapping_neg());
     }
 }
 
@@ -137,6 +161,7 @@ bitflags! {
         const TPM_ALG_SHA3_256 = 0b0001_0000;
         const TPM_ALG_SHA3_384 = 0b0010_0000;
         const TPM_ALG_SHA3_512 = 0b0100_0000;
+        const TPM_ALG_SM3_256 = 0b1000_0000;
     }
 }
 
@@ -149,6 +174,7 @@ impl SpdmMeasurementHashAlgo {
             SpdmMeasurementHashAlgo::TPM_ALG_SHA3_384 => SHA3_384_DIGEST_SIZE as u16,
             SpdmMeasurementHashAlgo::TPM_ALG_SHA_512 => SHA512_DIGEST_SIZE as u16,
             SpdmMeasurementHashAlgo::TPM_ALG_SHA3_512 => SHA3_512_DIGEST_SIZE as u16,
+            SpdmMeasurementHashAlgo::TPM_ALG_SM3_256 => SM3_256_DIGEST_SIZE as u16,
             SpdmMeasurementHashAlgo::RAW_BIT_STREAM => 0u16,
             _ => {
                 assert!(false);
@@ -181,6 +207,7 @@ bitflags! {
         const TPM_ALG_RSAPSS_4096 = 0b0100_0000;
         const TPM_ALG_ECDSA_ECC_NIST_P384 = 0b1000_0000;
         const TPM_ALG_ECDSA_ECC_NIST_P521 = 0b0000_0001_0000_0000;
+        const TPM_ALG_SM2_ECC_SM2_P256 = 0b0000_0010_0000_0000;
     }
 }
 
@@ -195,6 +222,7 @@ impl SpdmBaseAsymAlgo {
             SpdmBaseAsymAlgo::TPM_ALG_RSASSA_4096,
             SpdmBaseAsymAlgo::TPM_ALG_RSASSA_3072,
             SpdmBaseAsymAlgo::TPM_ALG_RSASSA_2048,
+            SpdmBaseAsymAlgo::TPM_ALG_SM2_ECC_SM2_P256,
         ];
 
         *self &= peer;
@@ -216,6 +244,7 @@ impl SpdmBaseAsymAlgo {
             SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P256 => ECDSA_ECC_NIST_P256_KEY_SIZE as u16,
             SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P384 => ECDSA_ECC_NIST_P384_KEY_SIZE as u16,
             SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P521 => ECDSA_ECC_NIST_P521_KEY_SIZE as u16,
+            SpdmBaseAsymAlgo::TPM_ALG_SM2_ECC_SM2_P256 => SM2_ECC_SM2_P256_KEY_SIZE as u16,
             _ => {
                 assert!(false);
                 0
@@ -245,6 +274,7 @@ bitflags! {
         const TPM_ALG_SHA3_256 = 0b0000_1000;
         const TPM_ALG_SHA3_384 = 0b0001_0000;
         const TPM_ALG_SHA3_512 = 0b0010_0000;
+        const TPM_ALG_SM3_256 = 0b0100_0000;
     }
 }
 
@@ -254,6 +284,7 @@ impl SpdmBaseHashAlgo {
             SpdmBaseHashAlgo::TPM_ALG_SHA_512,
             SpdmBaseHashAlgo::TPM_ALG_SHA_384,
             SpdmBaseHashAlgo::TPM_ALG_SHA_256,
+            SpdmBaseHashAlgo::TPM_ALG_SM3_256,
         ];
 
         *self &= peer;
@@ -272,6 +303,7 @@ impl SpdmBaseHashAlgo {
             SpdmBaseHashAlgo::TPM_ALG_SHA3_384 => SHA3_384_DIGEST_SIZE as u16,
             SpdmBaseHashAlgo::TPM_ALG_SHA_512 => SHA512_DIGEST_SIZE as u16,
             SpdmBaseHashAlgo::TPM_ALG_SHA3_512 => SHA3_512_DIGEST_SIZE as u16,
+            SpdmBaseHashAlgo::TPM_ALG_SM3_256 => SM3_256_DIGEST_SIZE as u16,
             _ => {
                 assert!(false);
                 0
@@ -308,7 +340,10 @@ enum_builder! {
     }
 }
 
-#[derive(Debug, Copy, Clone, Default)]
+/// An algorithm outside the ones of the fixed fields, identified by the
+/// registry defining it and its ID there, e.g. a vendor defined algorithm.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmExtAlgStruct {
     pub registry_id: SpdmStandardId,
     pub reserved: u8,
@@ -344,6 +379,7 @@ bitflags! {
         const SECP_256_R1 = 0b0000_1000;
         const SECP_384_R1 = 0b0001_0000;
         const SECP_521_R1 = 0b0010_0000;
+        const SM2_P256 = 0b0100_0000;
     }
 }
 
@@ -355,6 +391,7 @@ impl SpdmDheAlgo {
             SpdmDheAlgo::FFDHE_4096,
             SpdmDheAlgo::FFDHE_3072,
             SpdmDheAlgo::FFDHE_2048,
+            SpdmDheAlgo::SM2_P256,
         ];
 
         *self &= peer;
@@ -373,6 +410,7 @@ impl SpdmDheAlgo {
             SpdmDheAlgo::SECP_256_R1 => SECP_256_R1_KEY_SIZE as u16,
             SpdmDheAlgo::SECP_384_R1 => SECP_384_R1_KEY_SIZE as u16,
             SpdmDheAlgo::SECP_521_R1 => SECP_521_R1_KEY_SIZE as u16,
+            SpdmDheAlgo::SM2_P256 => SM2_P256_KEY_SIZE as u16,
             _ => {
                 assert!(false);
                 0
@@ -399,6 +437,7 @@ bitflags! {
         const AES_128_GCM = 0b0000_0001;
         const AES_256_GCM = 0b0000_0010;
         const CHACHA20_POLY1305 = 0b0000_0100;
+        const SM4_GCM = 0b0000_1000;
     }
 }
 
@@ -408,6 +447,7 @@ impl SpdmAeadAlgo {
             SpdmAeadAlgo::AES_256_GCM,
             SpdmAeadAlgo::AES_128_GCM,
             SpdmAeadAlgo::CHACHA20_POLY1305,
+            SpdmAeadAlgo::SM4_GCM,
         ];
 
         *self &= peer;
@@ -423,6 +463,7 @@ impl SpdmAeadAlgo {
             SpdmAeadAlgo::AES_128_GCM => AEAD_AES_128_GCM_KEY_SIZE as u16,
             SpdmAeadAlgo::AES_256_GCM => AEAD_AES_256_GCM_KEY_SIZE as u16,
             SpdmAeadAlgo::CHACHA20_POLY1305 => AEAD_CHACHA20_POLY1305_KEY_SIZE as u16,
+            SpdmAeadAlgo::SM4_GCM => AEAD_SM4_GCM_KEY_SIZE as u16,
             _ => {
                 assert!(false);
                 0
@@ -434,6 +475,7 @@ impl SpdmAeadAlgo {
             SpdmAeadAlgo::AES_128_GCM => AEAD_AES_128_GCM_IV_SIZE as u16,
             SpdmAeadAlgo::AES_256_GCM => AEAD_AES_256_GCM_IV_SIZE as u16,
             SpdmAeadAlgo::CHACHA20_POLY1305 => AEAD_CHACHA20_POLY1305_IV_SIZE as u16,
+            SpdmAeadAlgo::SM4_GCM => AEAD_SM4_GCM_IV_SIZE as u16,
             _ => {
                 assert!(false);
                 0
@@ -445,6 +487,7 @@ impl SpdmAeadAlgo {
             SpdmAeadAlgo::AES_128_GCM => AEAD_AES_128_GCM_TAG_SIZE as u16,
             SpdmAeadAlgo::AES_256_GCM => AEAD_AES_256_GCM_TAG_SIZE as u16,
             SpdmAeadAlgo::CHACHA20_POLY1305 => AEAD_CHACHA20_POLY1305_TAG_SIZE as u16,
+            SpdmAeadAlgo::SM4_GCM => AEAD_SM4_GCM_TAG_SIZE as u16,
             _ => {
                 assert!(false);
                 0
@@ -477,6 +520,7 @@ bitflags! {
         const TPM_ALG_RSAPSS_4096 = 0b0100_0000;
         const TPM_ALG_ECDSA_ECC_NIST_P384 = 0b1000_0000;
         const TPM_ALG_ECDSA_ECC_NIST_P521 = 0b0000_0001_0000_0000;
+        const TPM_ALG_SM2_ECC_SM2_P256 = 0b0000_0010_0000_0000;
     }
 }
 
@@ -491,6 +535,7 @@ impl SpdmReqAsymAlgo {
             SpdmReqAsymAlgo::TPM_ALG_RSASSA_2048,
             SpdmReqAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P384,
             SpdmReqAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P256,
+            SpdmReqAsymAlgo::TPM_ALG_SM2_ECC_SM2_P256,
         ];
 
         *self &= peer;
@@ -512,6 +557,7 @@ impl SpdmReqAsymAlgo {
             SpdmReqAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P256 => ECDSA_ECC_NIST_P256_KEY_SIZE as u16,
             SpdmReqAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P384 => ECDSA_ECC_NIST_P384_KEY_SIZE as u16,
             SpdmReqAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P521 => ECDSA_ECC_NIST_P521_KEY_SIZE as u16,
+            SpdmReqAsymAlgo::TPM_ALG_SM2_ECC_SM2_P256 => SM2_ECC_SM2_P256_KEY_SIZE as u16,
             _ => {
                 assert!(false);
                 0
@@ -520,6 +566,13 @@ impl SpdmReqAsymAlgo {
     }
 }
 
+// ReqBaseAsymAlg uses the same bits as BaseAsymAlgo.
+impl From<SpdmReqAsymAlgo> for SpdmBaseAsymAlgo {
+    fn from(value: SpdmReqAsymAlgo) -> Self {
+        SpdmBaseAsymAlgo::from_bits_truncate(value.bits() as u32)
+    }
+}
+
 impl Codec for SpdmReqAsymAlgo {
     fn encode(&self, bytes: &mut Writer) {
         self.bits().encode(bytes);
@@ -565,7 +618,78 @@ impl Codec for SpdmKeyScheduleAlgo {
     }
 }
 
+macro_rules! impl_prioritize_in_order {
+    ($($algo: ident),* $(,)?) => {
+        $(
+            impl $algo {
+                /// Select the first algorithm of `priority` both sides support,
+                /// none if there is no such one. An empty `priority` selects
+                /// in the built-in order of prioritize().
+                pub fn prioritize_in_order(&mut self, peer: $algo, priority: &[$algo]) {
+                    if priority.is_empty() {
+                        return self.prioritize(peer);
+                    }
+                    let common = *self & peer;
+                    *self = priority
+                        .iter()
+                        .copied()
+                        .find(|v| !v.is_empty() && common.contains(*v))
+                        .unwrap_or_else($algo::empty);
+                }
+            }
+        )*
+    };
+}
+
+impl_prioritize_in_order!(
+    SpdmBaseAsymAlgo,
+    SpdmBaseHashAlgo,
+    SpdmDheAlgo,
+    SpdmAeadAlgo,
+    SpdmReqAsymAlgo,
+    SpdmKeyScheduleAlgo,
+);
+
+// OtherParamsSupport / OtherParamsSelection of NEGOTIATE_ALGORITHMS / ALGORITHMS, SPDM 1.2 only
+bitflags! {
+    #[derive(Default)]
+    pub struct SpdmOpaqueSupport: u8 {
+        const OPAQUE_DATA_FMT0 = 0b0000_0001;
+        const OPAQUE_DATA_FMT1 = 0b0000_0010;
+    }
+}
+
+impl SpdmOpaqueSupport {
+    pub fn prioritize(&mut self, peer: SpdmOpaqueSupport) {
+        let prio_table = [
+            SpdmOpaqueSupport::OPAQUE_DATA_FMT1,
+            SpdmOpaqueSupport::OPAQUE_DATA_FMT0,
+        ];
+
+        *self &= peer;
+        for v in prio_table.iter() {
+            if self.bits() & v.bits() != 0 {
+                *self = *v;
+                break;
+            }
+        }
+    }
+}
+
+impl Codec for SpdmOpaqueSupport {
+    fn encode(&self, bytes: &mut Writer) {
+        self.bits().encode(bytes);
+    }
+
+    fn read(r: &mut Reader) -> Option<SpdmOpaqueSupport> {
+        let bits = u8::read(r)?;
+
+        SpdmOpaqueSupport::from_bits(bits)
+    }
+}
+
 #[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmUnknownAlgo {}
 impl Codec for SpdmUnknownAlgo {
     fn encode(&self, _bytes: &mut Writer) {}
@@ -587,6 +711,7 @@ enum_builder! {
 }
 
 #[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub enum SpdmAlg {
     SpdmAlgoDhe(SpdmDheAlgo),
     SpdmAlgoAead(SpdmAeadAlgo),
@@ -602,6 +727,7 @@ impl Default for SpdmAlg {
 }
 
 #[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmAlgStruct {
     pub alg_type: SpdmAlgType,
     pub alg_fixed_count: u8,
@@ -683,6 +809,7 @@ enum_builder! {
 }
 
 #[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmNonceStruct {
     pub data: [u8; SPDM_NONCE_SIZE],
 }
@@ -703,6 +830,7 @@ impl Codec for SpdmNonceStruct {
 }
 
 #[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmRandomStruct {
     pub data: [u8; SPDM_RANDOM_SIZE],
 }
@@ -736,12 +864,6 @@ impl Default for SpdmSignatureStruct {
     }
 }
 
-impl AsRef<[u8]> for SpdmSignatureStruct {
-    fn as_ref(&self) -> &[u8] {
-        &self.data[0..(self.data_size as usize)]
-    }
-}
-
 impl From<BytesMut> for SpdmSignatureStruct {
     fn from(value: BytesMut) -> Self {
         assert!(value.as_ref().len() <= SPDM_MAX_ASYM_KEY_SIZE);
@@ -765,12 +887,6 @@ impl Default for SpdmCertChainData {
         }
     }
 }
-impl AsRef<[u8]> for SpdmCertChainData {
-    fn as_ref(&self) -> &[u8] {
-        &self.data[0..(self.data_size as usize)]
-    }
-}
-
 #[derive(Debug, Copy, Clone, Default)]
 pub struct SpdmCertChain {
     pub root_hash: SpdmDigestStruct,
@@ -816,15 +932,31 @@ impl Default for SpdmDmtfMeasurementStructure {
     }
 }
 
+// encoded size: block header, DMTF measurement header and value
+pub const SPDM_MAX_MEASUREMENT_BLOCK_SIZE: usize = 7 + config::MAX_SPDM_MEASUREMENT_VALUE_LEN;
+
 #[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmMeasurementBlockStructure {
     pub index: u8,
     pub measurement_specification: SpdmMeasurementSpecification,
     pub measurement_size: u16,
     pub measurement: SpdmDmtfMeasurementStructure,
 }
+impl SpdmMeasurementBlockStructure {
+    /// Blocks of other measurement specifications are not parsed, their raw
+    /// bytes are kept in `measurement.value`.
+    pub fn is_measurement_size_valid(&self) -> bool {
+        if self.measurement_specification == SpdmMeasurementSpecification::DMTF {
+            self.measurement_size == self.measurement.value_size + 3
+        } else {
+            self.measurement_size == self.measurement.value_size
+        }
+    }
+}
 
 #[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmMeasurementRecordStructure {
     pub number_of_blocks: u8,
     pub record: [SpdmMeasurementBlockStructure; config::MAX_SPDM_MEASUREMENT_BLOCK_COUNT],
@@ -853,12 +985,6 @@ impl Default for SpdmDheExchangeStruct {
     }
 }
 
-impl AsRef<[u8]> for SpdmDheExchangeStruct {
-    fn as_ref(&self) -> &[u8] {
-        &self.data[0..(self.data_size as usize)]
-    }
-}
-
 impl From<BytesMut> for SpdmDheExchangeStruct {
     fn from(value: BytesMut) -> Self {
         assert!(value.as_ref().len() <= SPDM_MAX_DHE_KEY_SIZE);
@@ -869,7 +995,8 @@ impl From<BytesMut> for SpdmDheExchangeStruct {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+// Not Copy, the copies of a secret are explicit.
+#[derive(Debug, Clone)]
 pub struct SpdmDheFinalKeyStruct {
     pub data_size: u16,
     pub data: [u8; SPDM_MAX_DHE_KEY_SIZE],
@@ -883,18 +1010,13 @@ impl Default for SpdmDheFinalKeyStruct {
     }
 }
 
-impl AsRef<[u8]> for SpdmDheFinalKeyStruct {
-    fn as_ref(&self) -> &[u8] {
-        &self.data[0..(self.data_size as usize)]
-    }
-}
-
 impl From<BytesMut> for SpdmDheFinalKeyStruct {
-    fn from(value: BytesMut) -> Self {
+    fn from(mut value: BytesMut) -> Self {
         assert!(value.as_ref().len() <= SPDM_MAX_DHE_KEY_SIZE);
         let data_size = value.as_ref().len() as u16;
         let mut data = [0u8; SPDM_MAX_DHE_KEY_SIZE];
         data[0..value.as_ref().len()].copy_from_slice(value.as_ref());
+        common::zeroize(value.as_mut());
         Self { data_size, data }
     }
 }
@@ -913,12 +1035,6 @@ impl Default for SpdmPskContextStruct {
     }
 }
 
-impl AsRef<[u8]> for SpdmPskContextStruct {
-    fn as_ref(&self) -> &[u8] {
-        &self.data[0..(self.data_size as usize)]
-    }
-}
-
 #[derive(Debug, Copy, Clone)]
 pub struct SpdmPskHintStruct {
     pub data_size: u16,
@@ -933,12 +1049,6 @@ impl Default for SpdmPskHintStruct {
     }
 }
 
-impl AsRef<[u8]> for SpdmPskHintStruct {
-    fn as_ref(&self) -> &[u8] {
-        &self.data[0..(self.data_size as usize)]
-    }
-}
-
 #[derive(Debug, Copy, Clone)]
 pub struct SpdmAeadKeyStruct {
     pub data_size: u16,
@@ -953,12 +1063,6 @@ impl Default for SpdmAeadKeyStruct {
     }
 }
 
-impl AsRef<[u8]> for SpdmAeadKeyStruct {
-    fn as_ref(&self) -> &[u8] {
-        &self.data[0..self.data_size as usize]
-    }
-}
-
 impl From<BytesMut> for SpdmAeadKeyStruct {
     fn from(value: BytesMut) -> Self {
         assert!(value.as_ref().len() <= SPDM_MAX_AEAD_KEY_SIZE);
@@ -983,12 +1087,6 @@ impl Default for SpdmAeadIvStruct {
     }
 }
 
-impl AsRef<[u8]> for SpdmAeadIvStruct {
-    fn as_ref(&self) -> &[u8] {
-        &self.data[0..self.data_size as usize]
-    }
-}
-
 impl From<BytesMut> for SpdmAeadIvStruct {
     fn from(value: BytesMut) -> Self {
         assert!(value.as_ref().len() <= SPDM_MAX_AEAD_IV_SIZE);
@@ -998,3 +1096,15 @@ impl From<BytesMut> for SpdmAeadIvStruct {
         Self { data_size, data }
     }
 }
+
+impl_spdm_buffer!(
+    SpdmDigestStruct: SPDM_MAX_HASH_SIZE,
+    SpdmSignatureStruct: SPDM_MAX_ASYM_KEY_SIZE,
+    SpdmCertChainData: config::MAX_SPDM_CERT_CHAIN_DATA_SIZE,
+    SpdmDheExchangeStruct: SPDM_MAX_DHE_KEY_SIZE,
+    SpdmDheFinalKeyStruct: SPDM_MAX_DHE_KEY_SIZE,
+    SpdmPskContextStruct: config::MAX_SPDM_PSK_CONTEXT_SIZE,
+    SpdmPskHintStruct: config::MAX_SPDM_PSK_HINT_SIZE,
+    SpdmAeadKeyStruct: SPDM_MAX_AEAD_KEY_SIZE,
+    SpdmAeadIvStruct: SPDM_MAX_AEAD_IV_SIZE
+);
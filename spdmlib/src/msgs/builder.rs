@@ -0,0 +1,177 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+#![forbid(unsafe_code)]
+
+// Builders for the messages handlers used to fill in by hand. The header
+// version and the field sizes are taken from the negotiated state. The
+// signatures and HMACs computed over the encoded message are zeroed
+// placeholders of the negotiated size, patched in after encoding.
+
+use crate::common;
+use crate::config;
+use crate::error::SpdmResult;
+use crate::msgs::*;
+
+impl SpdmSignatureStruct {
+    /// A zeroed signature of `size` bytes to patch after encoding.
+    pub fn placeholder(size: u16) -> SpdmSignatureStruct {
+        SpdmSignatureStruct {
+            data_size: size.min(SPDM_MAX_ASYM_KEY_SIZE as u16),
+            data: [0u8; SPDM_MAX_ASYM_KEY_SIZE],
+        }
+    }
+}
+
+impl SpdmDigestStruct {
+    /// A zeroed digest of the `base_hash_algo` size to patch after encoding.
+    pub fn placeholder(base_hash_algo: SpdmBaseHashAlgo) -> SpdmDigestStruct {
+        SpdmDigestStruct {
+            data_size: base_hash_algo.get_size(),
+            data: [0u8; SPDM_MAX_HASH_SIZE],
+        }
+    }
+}
+
+fn negotiated_version(context: &common::SpdmContext) -> SpdmResult<SpdmVersion> {
+    match context.negotiate_info.spdm_version_sel {
+        SpdmVersion::Unknown(_) => spdm_result_err!(EINVAL, "no version negotiated"),
+        version => Ok(version),
+    }
+}
+
+impl SpdmMessage {
+    /// ERROR in the negotiated version, or in 1.0, the GET_VERSION version,
+    /// before one is negotiated.
+    pub fn new_error_response(
+        context: &common::SpdmContext,
+        error_code: SpdmErrorCode,
+        error_data: u8,
+        extended_data: SpdmErrorResponseExtData,
+    ) -> SpdmMessage {
+        let version = match context.negotiate_info.spdm_version_sel {
+            SpdmVersion::Unknown(_) => SpdmVersion::SpdmVersion10,
+            version => version,
+        };
+        SpdmMessage {
+            header: SpdmMessageHeader {
+                version,
+                request_response_code: SpdmResponseResponseCode::SpdmResponseError,
+            },
+            payload: SpdmMessagePayload::SpdmErrorResponse(SpdmErrorResponsePayload {
+                error_code,
+                error_data,
+                extended_data,
+            }),
+        }
+    }
+
+    /// VERSION in 1.0 listing `versions`, unknown ones are skipped. Fails
+    /// with EINVAL for more than MAX_SPDM_VERSION_COUNT versions.
+    pub fn new_version_response(versions: &[SpdmVersion]) -> SpdmResult<SpdmMessage> {
+        let mut entries = [SpdmVersionStruct::default(); config::MAX_SPDM_VERSION_COUNT];
+        let mut count = 0;
+        for version in versions.iter() {
+            if let SpdmVersion::Unknown(_) = version {
+                continue;
+            }
+            if count == config::MAX_SPDM_VERSION_COUNT {
+                return spdm_result_err!(EINVAL, "too many versions");
+            }
+            entries[count] = SpdmVersionStruct {
+                update: 0,
+                version: *version,
+            };
+            count += 1;
+        }
+        Ok(SpdmMessage {
+            header: SpdmMessageHeader {
+                version: SpdmVersion::SpdmVersion10,
+                request_response_code: SpdmResponseResponseCode::SpdmResponseVersion,
+            },
+            payload: SpdmMessagePayload::SpdmVersionResponse(SpdmVersionResponsePayload {
+                version_number_entry_count: count as u8,
+                versions: entries,
+            }),
+        })
+    }
+}
+
+/// Builds KEY_EXCHANGE_RSP. The signature and verify_data are placeholders
+/// of the negotiated sizes, they come last in the encoded message.
+#[derive(Debug, Copy, Clone)]
+pub struct SpdmKeyExchangeResponseBuilder {
+    payload: SpdmKeyExchangeResponsePayload,
+}
+
+impl SpdmKeyExchangeResponseBuilder {
+    pub fn new(
+        rsp_session_id: u16,
+        random: SpdmRandomStruct,
+        exchange: SpdmDheExchangeStruct,
+    ) -> SpdmKeyExchangeResponseBuilder {
+        SpdmKeyExchangeResponseBuilder {
+            payload: SpdmKeyExchangeResponsePayload {
+                rsp_session_id,
+                random,
+                exchange,
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn heartbeat_period(mut self, heartbeat_period: u8) -> Self {
+        self.payload.heartbeat_period = heartbeat_period;
+        self
+    }
+
+    pub fn mut_auth_req(
+        mut self,
+        mut_auth_req: SpdmKeyExchangeMutAuthAttributes,
+        req_slot_id: u8,
+    ) -> Self {
+        self.payload.mut_auth_req = mut_auth_req;
+        self.payload.req_slot_id = req_slot_id;
+        self
+    }
+
+    /// Only encoded if the KEY_EXCHANGE asked for it.
+    pub fn measurement_summary_hash(mut self, measurement_summary_hash: SpdmDigestStruct) -> Self {
+        self.payload.measurement_summary_hash = measurement_summary_hash;
+        self
+    }
+
+    pub fn opaque(mut self, opaque: SpdmOpaqueStruct) -> Self {
+        self.payload.opaque = opaque;
+        self
+    }
+
+    /// Fails with EINVAL before the version is negotiated or if the
+    /// exchange data or measurement summary hash doesn't have the
+    /// negotiated size.
+    pub fn build(self, context: &common::SpdmContext) -> SpdmResult<SpdmMessage> {
+        let version = negotiated_version(context)?;
+        let negotiate_info = &context.negotiate_info;
+        if self.payload.exchange.data_size != negotiate_info.dhe_sel.get_size() {
+            return spdm_result_err!(EINVAL, "exchange data size mismatch");
+        }
+        if context.runtime_info.need_measurement_summary_hash {
+            self.payload
+                .measurement_summary_hash
+                .check_size(negotiate_info.base_hash_sel)?;
+        }
+
+        let mut payload = self.payload;
+        payload.signature =
+            SpdmSignatureStruct::placeholder(negotiate_info.base_asym_sel.get_size());
+        payload.verify_data = SpdmDigestStruct::placeholder(negotiate_info.base_hash_sel);
+        Ok(SpdmMessage {
+            header: SpdmMessageHeader {
+                version,
+                request_response_code: SpdmResponseResponseCode::SpdmResponseKeyExchangeRsp,
+            },
+            payload: SpdmMessagePayload::SpdmKeyExchangeResponse(payload),
+        })
+    }
+}
@@ -0,0 +1,225 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+#![forbid(unsafe_code)]
+
+// Typed request/response pairs for custom flows. A request payload can only
+// be answered with its response payload: encode_request() hands out the
+// SpdmPendingResponse reading it, read_request() the SpdmPendingRequest
+// encoding it. Neither updates the transcripts.
+
+use core::marker::PhantomData;
+
+use crate::common;
+use crate::config;
+use crate::error::SpdmResult;
+use crate::msgs::*;
+use codec::{Codec, Reader, Writer};
+
+/// A request payload and the response payload it is answered with.
+pub trait SpdmRequest: SpdmCodec {
+    const REQUEST_CODE: SpdmResponseResponseCode;
+    type Response: SpdmResponse;
+}
+
+pub trait SpdmResponse: SpdmCodec {
+    const RESPONSE_CODE: SpdmResponseResponseCode;
+}
+
+macro_rules! impl_spdm_exchange {
+    ($($request: ident: $request_code: ident => $response: ident: $response_code: ident),* $(,)?) => {
+        $(
+            impl SpdmRequest for $request {
+                const REQUEST_CODE: SpdmResponseResponseCode =
+                    SpdmResponseResponseCode::$request_code;
+                type Response = $response;
+            }
+
+            impl SpdmResponse for $response {
+                const RESPONSE_CODE: SpdmResponseResponseCode =
+                    SpdmResponseResponseCode::$response_code;
+            }
+        )*
+    };
+}
+
+impl_spdm_exchange! {
+    SpdmGetVersionRequestPayload: SpdmRequestGetVersion
+        => SpdmVersionResponsePayload: SpdmResponseVersion,
+    SpdmGetCapabilitiesRequestPayload: SpdmRequestGetCapabilities
+        => SpdmCapabilitiesResponsePayload: SpdmResponseCapabilities,
+    SpdmNegotiateAlgorithmsRequestPayload: SpdmRequestNegotiateAlgorithms
+        => SpdmAlgorithmsResponsePayload: SpdmResponseAlgorithms,
+    SpdmGetDigestsRequestPayload: SpdmRequestGetDigests
+        => SpdmDigestsResponsePayload: SpdmResponseDigests,
+    SpdmGetCertificateRequestPayload: SpdmRequestGetCertificate
+        => SpdmCertificateResponsePayload: SpdmResponseCertificate,
+    SpdmChallengeRequestPayload: SpdmRequestChallenge
+        => SpdmChallengeAuthResponsePayload: SpdmResponseChallengeAuth,
+    SpdmGetMeasurementsRequestPayload: SpdmRequestGetMeasurements
+        => SpdmMeasurementsResponsePayload: SpdmResponseMeasurements,
+    SpdmKeyExchangeRequestPayload: SpdmRequestKeyExchange
+        => SpdmKeyExchangeResponsePayload: SpdmResponseKeyExchangeRsp,
+    SpdmFinishRequestPayload: SpdmRequestFinish
+        => SpdmFinishResponsePayload: SpdmResponseFinishRsp,
+    SpdmPskExchangeRequestPayload: SpdmRequestPskExchange
+        => SpdmPskExchangeResponsePayload: SpdmResponsePskExchangeRsp,
+    SpdmPskFinishRequestPayload: SpdmRequestPskFinish
+        => SpdmPskFinishResponsePayload: SpdmResponsePskFinishRsp,
+    SpdmHeartbeatRequestPayload: SpdmRequestHeartbeat
+        => SpdmHeartbeatResponsePayload: SpdmResponseHeartbeatAck,
+    SpdmKeyUpdateRequestPayload: SpdmRequestKeyUpdate
+        => SpdmKeyUpdateResponsePayload: SpdmResponseKeyUpdateAck,
+    SpdmEndSessionRequestPayload: SpdmRequestEndSession
+        => SpdmEndSessionResponsePayload: SpdmResponseEndSessionAck,
+    SpdmGetEncapsulatedRequestPayload: SpdmRequestGetEncapsulatedRequest
+        => SpdmEncapsulatedRequestPayload: SpdmResponseEncapsulatedRequest,
+    SpdmDeliverEncapsulatedResponsePayload: SpdmRequestDeliverEncapsulatedResponse
+        => SpdmEncapsulatedResponseAckPayload: SpdmResponseEncapsulatedResponseAck,
+    SpdmChunkSendRequestPayload: SpdmRequestChunkSend
+        => SpdmChunkSendAckResponsePayload: SpdmResponseChunkSendAck,
+    SpdmChunkGetRequestPayload: SpdmRequestChunkGet
+        => SpdmChunkResponsePayload: SpdmResponseChunkResponse,
+    SpdmVendorDefinedRequestPayload: SpdmRequestVendorDefinedRequest
+        => SpdmVendorDefinedResponsePayload: SpdmResponseVendorDefinedResponse,
+}
+
+/// What a request is answered with.
+#[derive(Debug)]
+pub enum SpdmExchangeResponse<T> {
+    Response(T),
+    Error(SpdmErrorResponsePayload),
+}
+
+/// Reads the response to a request sent with encode_request().
+#[derive(Debug)]
+pub struct SpdmPendingResponse<Q: SpdmRequest> {
+    version: SpdmVersion,
+    request: PhantomData<Q>,
+}
+
+/// Encodes the response to a request read with read_request().
+#[derive(Debug)]
+pub struct SpdmPendingRequest<Q: SpdmRequest> {
+    version: SpdmVersion,
+    request: PhantomData<Q>,
+}
+
+fn encode_message<T: SpdmCodec>(
+    context: &mut common::SpdmContext,
+    header: SpdmMessageHeader,
+    payload: &T,
+    buffer: &mut [u8],
+) -> SpdmResult<usize> {
+    let mut encode_buffer = [0u8; config::MAX_SPDM_MESSAGE_BUFFER_SIZE];
+    let mut writer = Writer::init(&mut encode_buffer);
+    header.encode(&mut writer);
+    payload.spdm_encode(context, &mut writer)?;
+    let used = writer.used();
+    if used > buffer.len() {
+        return spdm_result_err!(ENOMEM);
+    }
+    buffer[..used].copy_from_slice(&encode_buffer[..used]);
+    Ok(used)
+}
+
+/// Encode `request` for `version` into `buffer`. Returns the size and the
+/// SpdmPendingResponse to read the response with.
+pub fn encode_request<Q: SpdmRequest>(
+    context: &mut common::SpdmContext,
+    version: SpdmVersion,
+    request: &Q,
+    buffer: &mut [u8],
+) -> SpdmResult<(usize, SpdmPendingResponse<Q>)> {
+    let header = SpdmMessageHeader {
+        version,
+        request_response_code: Q::REQUEST_CODE,
+    };
+    let used = encode_message(context, header, request, buffer)?;
+    Ok((
+        used,
+        SpdmPendingResponse {
+            version,
+            request: PhantomData,
+        },
+    ))
+}
+
+impl<Q: SpdmRequest> SpdmPendingResponse<Q> {
+    /// Decode `bytes` as the response to the request or as ERROR. Fails
+    /// with EINVAL for any other response or another SPDM version.
+    pub fn read_response(
+        self,
+        context: &mut common::SpdmContext,
+        bytes: &[u8],
+    ) -> SpdmResult<SpdmExchangeResponse<Q::Response>> {
+        let mut reader = Reader::init(bytes);
+        let header = SpdmMessageHeader::read(&mut reader).ok_or(spdm_err!(EIO))?;
+        if header.version != self.version {
+            return spdm_result_err!(EINVAL, "response version mismatch");
+        }
+        if header.request_response_code == SpdmResponseResponseCode::SpdmResponseError {
+            let error = SpdmErrorResponsePayload::spdm_read(context, &mut reader)
+                .ok_or(spdm_err!(EFAULT))?;
+            return Ok(SpdmExchangeResponse::Error(error));
+        }
+        if header.request_response_code != Q::Response::RESPONSE_CODE {
+            return spdm_result_err!(EINVAL, "unexpected response");
+        }
+        let response = Q::Response::spdm_read(context, &mut reader).ok_or(spdm_err!(EFAULT))?;
+        Ok(SpdmExchangeResponse::Response(response))
+    }
+}
+
+/// Decode `bytes` as a request of type `Q`. Returns it and the
+/// SpdmPendingRequest to encode the response with. Fails with EINVAL for
+/// any other request.
+pub fn read_request<Q: SpdmRequest>(
+    context: &mut common::SpdmContext,
+    bytes: &[u8],
+) -> SpdmResult<(Q, SpdmPendingRequest<Q>)> {
+    let mut reader = Reader::init(bytes);
+    let header = SpdmMessageHeader::read(&mut reader).ok_or(spdm_err!(EIO))?;
+    if header.request_response_code != Q::REQUEST_CODE {
+        return spdm_result_err!(EINVAL, "unexpected request");
+    }
+    let request = Q::spdm_read(context, &mut reader).ok_or(spdm_err!(EFAULT))?;
+    Ok((
+        request,
+        SpdmPendingRequest {
+            version: header.version,
+            request: PhantomData,
+        },
+    ))
+}
+
+impl<Q: SpdmRequest> SpdmPendingRequest<Q> {
+    /// Encode `response` to the request into `buffer`.
+    pub fn encode_response(
+        self,
+        context: &mut common::SpdmContext,
+        response: &Q::Response,
+        buffer: &mut [u8],
+    ) -> SpdmResult<usize> {
+        let header = SpdmMessageHeader {
+            version: self.version,
+            request_response_code: Q::Response::RESPONSE_CODE,
+        };
+        encode_message(context, header, response, buffer)
+    }
+
+    /// Encode ERROR as the answer to the request into `buffer`.
+    pub fn encode_error(
+        self,
+        context: &mut common::SpdmContext,
+        error: &SpdmErrorResponsePayload,
+        buffer: &mut [u8],
+    ) -> SpdmResult<usize> {
+        let header = SpdmMessageHeader {
+            version: self.version,
+            request_response_code: SpdmResponseResponseCode::SpdmResponseError,
+        };
+        encode_message(context, header, error, buffer)
+    }
+}
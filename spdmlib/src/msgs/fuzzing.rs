@@ -0,0 +1,291 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+#![forbid(unsafe_code)]
+
+// arbitrary::Arbitrary for the types that cannot derive it:
+// enum_builder enums, bitflags, and structs with large fixed size buffers.
+//
+// The size fields are left unconstrained on purpose, so that fuzzers can
+// produce inconsistent sizes and absurd counts.
+
+use crate::config;
+use crate::msgs::*;
+use arbitrary::{Arbitrary, Result, Unstructured};
+use codec::{Codec, Reader};
+
+macro_rules! impl_arbitrary_for_enum {
+    ($($enum_name: ident),*) => {
+        $(
+            impl Arbitrary for $enum_name {
+                fn arbitrary(u: &mut Unstructured<'_>) -> Result<Self> {
+                    let value = u8::arbitrary(u)?;
+                    Ok($enum_name::read(&mut Reader::init(&[value])).unwrap())
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_arbitrary_for_bitflags {
+    ($($flags_name: ident: $bits_type: ty),*) => {
+        $(
+            impl Arbitrary for $flags_name {
+                fn arbitrary(u: &mut Unstructured<'_>) -> Result<Self> {
+                    Ok($flags_name::from_bits_truncate(<$bits_type>::arbitrary(u)?))
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_arbitrary_for_buffer {
+    ($($struct_name: ident: $data_size: expr),*) => {
+        $(
+            impl Arbitrary for $struct_name {
+                fn arbitrary(u: &mut Unstructured<'_>) -> Result<Self> {
+                    let data_size = u16::arbitrary(u)?;
+                    let mut data = [0u8; $data_size];
+                    u.fill_buffer(&mut data)?;
+                    Ok($struct_name { data_size, data })
+                }
+            }
+        )*
+    };
+}
+
+impl_arbitrary_for_enum!(
+    SpdmVersion,
+    SpdmResponseResponseCode,
+    SpdmAlgType,
+    SpdmMeasurementSummaryHashType,
+    SpdmDmtfMeasurementType,
+    SpdmDmtfMeasurementRepresentation,
+    SpdmMeasurementOperation,
+    SpdmKeyUpdateOperation,
+    SpdmEncapsulatedResponseAckPayloadType,
+    SpdmErrorCode,
+    SpdmStandardId
+);
+
+impl_arbitrary_for_bitflags!(
+    SpdmMeasurementSpecification: u8,
+    SpdmMeasurementHashAlgo: u32,
+    SpdmBaseAsymAlgo: u32,
+    SpdmBaseHashAlgo: u32,
+    SpdmDheAlgo: u16,
+    SpdmAeadAlgo: u16,
+    SpdmReqAsymAlgo: u16,
+    SpdmKeyScheduleAlgo: u16,
+    SpdmOpaqueSupport: u8,
+    SpdmRequestCapabilityFlags: u32,
+    SpdmResponseCapabilityFlags: u32,
+    SpdmChallengeAuthAttribute: u8,
+    SpdmMeasurementeAttributes: u8,
+    SpdmKeyExchangeSessionPolicy: u8,
+    SpdmKeyExchangeMutAuthAttributes: u8,
+    SpdmFinishRequestAttributes: u8,
+    SpdmEndSessionRequestAttributes: u8,
+    SpdmChunkAttributes: u8,
+    SpdmChunkSendAckAttributes: u8
+);
+
+impl_arbitrary_for_buffer!(
+    SpdmDigestStruct: SPDM_MAX_HASH_SIZE,
+    SpdmSignatureStruct: SPDM_MAX_ASYM_KEY_SIZE,
+    SpdmDheExchangeStruct: SPDM_MAX_DHE_KEY_SIZE,
+    SpdmPskContextStruct: config::MAX_SPDM_PSK_CONTEXT_SIZE,
+    SpdmPskHintStruct: config::MAX_SPDM_PSK_HINT_SIZE,
+    SpdmOpaqueStruct: config::MAX_SPDM_OPAQUE_SIZE,
+    SpdmVendorIdStruct: SPDM_MAX_VENDOR_ID_SIZE,
+    SpdmVendorDefinedPayloadStruct: config::MAX_SPDM_TRANSPORT_SIZE
+);
+
+impl Arbitrary for SpdmDmtfMeasurementStructure {
+    fn arbitrary(u: &mut Unstructured<'_>) -> Result<Self> {
+        let r#type = SpdmDmtfMeasurementType::arbitrary(u)?;
+        let representation = SpdmDmtfMeasurementRepresentation::arbitrary(u)?;
+        let value_size = u16::arbitrary(u)?;
+        let mut value = [0u8; config::MAX_SPDM_MEASUREMENT_VALUE_LEN];
+        u.fill_buffer(&mut value)?;
+        Ok(SpdmDmtfMeasurementStructure {
+            r#type,
+            representation,
+            value_size,
+            value,
+        })
+    }
+}
+
+impl Arbitrary for SpdmCertificateResponsePayload {
+    fn arbitrary(u: &mut Unstructured<'_>) -> Result<Self> {
+        let slot_id = u8::arbitrary(u)?;
+        let portion_length = u16::arbitrary(u)?;
+        let remainder_length = u16::arbitrary(u)?;
+        let mut cert_chain = [0u8; config::MAX_SPDM_CERT_PORTION_LEN];
+        u.fill_buffer(&mut cert_chain)?;
+        Ok(SpdmCertificateResponsePayload {
+            slot_id,
+            portion_length,
+            remainder_length,
+            cert_chain,
+        })
+    }
+}
+
+impl Arbitrary for SpdmChunkSendRequestPayload {
+    fn arbitrary(u: &mut Unstructured<'_>) -> Result<Self> {
+        let attributes = SpdmChunkAttributes::arbitrary(u)?;
+        let handle = u8::arbitrary(u)?;
+        let chunk_seq_no = u16::arbitrary(u)?;
+        let large_message_size = u32::arbitrary(u)?;
+        let chunk_size = u32::arbitrary(u)?;
+        let mut chunk = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        u.fill_buffer(&mut chunk)?;
+        Ok(SpdmChunkSendRequestPayload {
+            attributes,
+            handle,
+            chunk_seq_no,
+            large_message_size,
+            chunk_size,
+            chunk,
+        })
+    }
+}
+
+impl Arbitrary for SpdmChunkSendAckResponsePayload {
+    fn arbitrary(u: &mut Unstructured<'_>) -> Result<Self> {
+        let attributes = SpdmChunkSendAckAttributes::arbitrary(u)?;
+        let handle = u8::arbitrary(u)?;
+        let chunk_seq_no = u16::arbitrary(u)?;
+        let response_size = u32::arbitrary(u)?;
+        let mut response = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        u.fill_buffer(&mut response)?;
+        Ok(SpdmChunkSendAckResponsePayload {
+            attributes,
+            handle,
+            chunk_seq_no,
+            response_size,
+            response,
+        })
+    }
+}
+
+impl Arbitrary for SpdmChunkResponsePayload {
+    fn arbitrary(u: &mut Unstructured<'_>) -> Result<Self> {
+        let attributes = SpdmChunkAttributes::arbitrary(u)?;
+        let handle = u8::arbitrary(u)?;
+        let chunk_seq_no = u16::arbitrary(u)?;
+        let large_message_size = u32::arbitrary(u)?;
+        let chunk_size = u32::arbitrary(u)?;
+        let mut chunk = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        u.fill_buffer(&mut chunk)?;
+        Ok(SpdmChunkResponsePayload {
+            attributes,
+            handle,
+            chunk_seq_no,
+            large_message_size,
+            chunk_size,
+            chunk,
+        })
+    }
+}
+
+impl Arbitrary for SpdmEncapsulatedRequestPayload {
+    fn arbitrary(u: &mut Unstructured<'_>) -> Result<Self> {
+        let request_id = u8::arbitrary(u)?;
+        let request_size = u32::arbitrary(u)?;
+        let mut request = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        u.fill_buffer(&mut request)?;
+        Ok(SpdmEncapsulatedRequestPayload {
+            request_id,
+            request_size,
+            request,
+        })
+    }
+}
+
+impl Arbitrary for SpdmDeliverEncapsulatedResponsePayload {
+    fn arbitrary(u: &mut Unstructured<'_>) -> Result<Self> {
+        let request_id = u8::arbitrary(u)?;
+        let response_size = u32::arbitrary(u)?;
+        let mut response = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        u.fill_buffer(&mut response)?;
+        Ok(SpdmDeliverEncapsulatedResponsePayload {
+            request_id,
+            response_size,
+            response,
+        })
+    }
+}
+
+impl Arbitrary for SpdmEncapsulatedResponseAckPayload {
+    fn arbitrary(u: &mut Unstructured<'_>) -> Result<Self> {
+        let request_id = u8::arbitrary(u)?;
+        let payload_type = SpdmEncapsulatedResponseAckPayloadType::arbitrary(u)?;
+        let ack_request_id = u8::arbitrary(u)?;
+        let request_size = u32::arbitrary(u)?;
+        let mut request = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        u.fill_buffer(&mut request)?;
+        Ok(SpdmEncapsulatedResponseAckPayload {
+            request_id,
+            payload_type,
+            ack_request_id,
+            request_size,
+            request,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fuzz;
+    use codec::Writer;
+
+    #[test]
+    fn test_arbitrary_fields() {
+        let mut u = Unstructured::new(&[0x12, 0x42]);
+        assert_eq!(
+            SpdmVersion::arbitrary(&mut u).unwrap(),
+            SpdmVersion::SpdmVersion12
+        );
+        assert_eq!(
+            SpdmVersion::arbitrary(&mut u).unwrap(),
+            SpdmVersion::Unknown(0x42)
+        );
+
+        // undefined bits are dropped
+        let mut u = Unstructured::new(&[0xff; 4]);
+        assert_eq!(
+            SpdmBaseHashAlgo::arbitrary(&mut u).unwrap(),
+            SpdmBaseHashAlgo::all()
+        );
+
+        // the size isn't bound to the buffer
+        let mut u = Unstructured::new(&[0xff; 2 + SPDM_MAX_HASH_SIZE]);
+        let digest = SpdmDigestStruct::arbitrary(&mut u).unwrap();
+        assert_eq!(digest.data_size, 0xffff);
+        assert_eq!(digest.data, [0xff; SPDM_MAX_HASH_SIZE]);
+    }
+
+    #[test]
+    fn test_arbitrary_payload() {
+        let bytes = [0x01, 0x34, 0x12, 0x00, 0x04];
+        let payload =
+            SpdmGetCertificateRequestPayload::arbitrary(&mut Unstructured::new(&bytes)).unwrap();
+
+        let mut buffer = [0u8; 8];
+        let mut writer = Writer::init(&mut buffer);
+        fuzz::with_fuzz_context(SpdmVersion::SpdmVersion11, |context| {
+            payload.spdm_encode(context, &mut writer).unwrap()
+        });
+        let used = writer.used();
+        let decoded: SpdmGetCertificateRequestPayload =
+            fuzz::spdm_read_bytes(SpdmVersion::SpdmVersion11, &buffer[..used]).unwrap();
+        assert_eq!(decoded.slot_id, payload.slot_id);
+        assert_eq!(decoded.offset, payload.offset);
+        assert_eq!(decoded.length, payload.length);
+    }
+}
@@ -6,13 +6,15 @@
 
 use codec::enum_builder;
 use codec::{Codec, Reader, Writer};
+use core::fmt;
 
 enum_builder! {
     @U8
     EnumName: SpdmVersion;
     EnumVal{
         SpdmVersion10 => 0x10,
-        SpdmVersion11 => 0x11
+        SpdmVersion11 => 0x11,
+        SpdmVersion12 => 0x12
     }
 }
 
@@ -28,7 +30,7 @@ enum_builder! {
         SpdmResponseMeasurements => 0x60,
         SpdmResponseCapabilities => 0x61,
         SpdmResponseAlgorithms => 0x63,
-//        SpdmResponseVendorDefinedResponse => 0x7E,
+        SpdmResponseVendorDefinedResponse => 0x7E,
         SpdmResponseError => 0x7F,
         // 1.1 response
         SpdmResponseKeyExchangeRsp => 0x64,
@@ -37,9 +39,12 @@ enum_builder! {
         SpdmResponsePskFinishRsp => 0x67,
         SpdmResponseHeartbeatAck => 0x68,
         SpdmResponseKeyUpdateAck => 0x69,
-//        SpdmResponseEncapsulatedRequest => 0x6A,
-//        SpdmResponseEncapsulatedResponseAck => 0x6B,
+        SpdmResponseEncapsulatedRequest => 0x6A,
+        SpdmResponseEncapsulatedResponseAck => 0x6B,
         SpdmResponseEndSessionAck => 0x6C,
+        // 1.2 response
+        SpdmResponseChunkSendAck => 0x05,
+        SpdmResponseChunkResponse => 0x06,
 
         // 1.0 rerquest
         SpdmRequestGetDigests => 0x81,
@@ -49,8 +54,8 @@ enum_builder! {
         SpdmRequestGetMeasurements => 0xE0,
         SpdmRequestGetCapabilities => 0xE1,
         SpdmRequestNegotiateAlgorithms => 0xE3,
-//        SpdmRequestVendorDefinedRequest => 0xFE,
-//        SpdmRequestResponseIfReady => 0xFF,
+        SpdmRequestVendorDefinedRequest => 0xFE,
+        SpdmRequestResponseIfReady => 0xFF,
         // 1.1 request
         SpdmRequestKeyExchange => 0xE4,
         SpdmRequestFinish => 0xE5,
@@ -58,13 +63,104 @@ enum_builder! {
         SpdmRequestPskFinish => 0xE7,
         SpdmRequestHeartbeat => 0xE8,
         SpdmRequestKeyUpdate => 0xE9,
-//        SpdmRequestGetEncapsulatedRequest => 0xEA,
-//        SpdmRequestDeliverEncapsulatedResponse => 0xEB,
-        SpdmRequestEndSession => 0xEC
+        SpdmRequestGetEncapsulatedRequest => 0xEA,
+        SpdmRequestDeliverEncapsulatedResponse => 0xEB,
+        SpdmRequestEndSession => 0xEC,
+        // 1.2 request
+        SpdmRequestChunkSend => 0x85,
+        SpdmRequestChunkGet => 0x86
+    }
+}
+
+impl SpdmVersion {
+    /// Version as written in the specification, e.g. "1.1".
+    pub fn name(&self) -> &'static str {
+        match self {
+            SpdmVersion::SpdmVersion10 => "1.0",
+            SpdmVersion::SpdmVersion11 => "1.1",
+            SpdmVersion::SpdmVersion12 => "1.2",
+            SpdmVersion::Unknown(_) => "unknown",
+        }
+    }
+}
+
+impl fmt::Display for SpdmVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SpdmVersion::Unknown(v) => write!(f, "unknown({:#04x})", v),
+            _ => f.write_str(self.name()),
+        }
+    }
+}
+
+impl SpdmResponseResponseCode {
+    /// Message name as written in the specification, e.g. "GET_VERSION".
+    pub fn name(&self) -> &'static str {
+        match self {
+            SpdmResponseResponseCode::SpdmResponseDigests => "DIGESTS",
+            SpdmResponseResponseCode::SpdmResponseCertificate => "CERTIFICATE",
+            SpdmResponseResponseCode::SpdmResponseChallengeAuth => "CHALLENGE_AUTH",
+            SpdmResponseResponseCode::SpdmResponseVersion => "VERSION",
+            SpdmResponseResponseCode::SpdmResponseMeasurements => "MEASUREMENTS",
+            SpdmResponseResponseCode::SpdmResponseCapabilities => "CAPABILITIES",
+            SpdmResponseResponseCode::SpdmResponseAlgorithms => "ALGORITHMS",
+            SpdmResponseResponseCode::SpdmResponseVendorDefinedResponse => {
+                "VENDOR_DEFINED_RESPONSE"
+            }
+            SpdmResponseResponseCode::SpdmResponseError => "ERROR",
+            SpdmResponseResponseCode::SpdmResponseKeyExchangeRsp => "KEY_EXCHANGE_RSP",
+            SpdmResponseResponseCode::SpdmResponseFinishRsp => "FINISH_RSP",
+            SpdmResponseResponseCode::SpdmResponsePskExchangeRsp => "PSK_EXCHANGE_RSP",
+            SpdmResponseResponseCode::SpdmResponsePskFinishRsp => "PSK_FINISH_RSP",
+            SpdmResponseResponseCode::SpdmResponseHeartbeatAck => "HEARTBEAT_ACK",
+            SpdmResponseResponseCode::SpdmResponseKeyUpdateAck => "KEY_UPDATE_ACK",
+            SpdmResponseResponseCode::SpdmResponseEncapsulatedRequest => "ENCAPSULATED_REQUEST",
+            SpdmResponseResponseCode::SpdmResponseEncapsulatedResponseAck => {
+                "ENCAPSULATED_RESPONSE_ACK"
+            }
+            SpdmResponseResponseCode::SpdmResponseEndSessionAck => "END_SESSION_ACK",
+            SpdmResponseResponseCode::SpdmResponseChunkSendAck => "CHUNK_SEND_ACK",
+            SpdmResponseResponseCode::SpdmResponseChunkResponse => "CHUNK_RESPONSE",
+            SpdmResponseResponseCode::SpdmRequestGetDigests => "GET_DIGESTS",
+            SpdmResponseResponseCode::SpdmRequestGetCertificate => "GET_CERTIFICATE",
+            SpdmResponseResponseCode::SpdmRequestChallenge => "CHALLENGE",
+            SpdmResponseResponseCode::SpdmRequestGetVersion => "GET_VERSION",
+            SpdmResponseResponseCode::SpdmRequestGetMeasurements => "GET_MEASUREMENTS",
+            SpdmResponseResponseCode::SpdmRequestGetCapabilities => "GET_CAPABILITIES",
+            SpdmResponseResponseCode::SpdmRequestNegotiateAlgorithms => "NEGOTIATE_ALGORITHMS",
+            SpdmResponseResponseCode::SpdmRequestVendorDefinedRequest => "VENDOR_DEFINED_REQUEST",
+            SpdmResponseResponseCode::SpdmRequestResponseIfReady => "RESPOND_IF_READY",
+            SpdmResponseResponseCode::SpdmRequestKeyExchange => "KEY_EXCHANGE",
+            SpdmResponseResponseCode::SpdmRequestFinish => "FINISH",
+            SpdmResponseResponseCode::SpdmRequestPskExchange => "PSK_EXCHANGE",
+            SpdmResponseResponseCode::SpdmRequestPskFinish => "PSK_FINISH",
+            SpdmResponseResponseCode::SpdmRequestHeartbeat => "HEARTBEAT",
+            SpdmResponseResponseCode::SpdmRequestKeyUpdate => "KEY_UPDATE",
+            SpdmResponseResponseCode::SpdmRequestGetEncapsulatedRequest => {
+                "GET_ENCAPSULATED_REQUEST"
+            }
+            SpdmResponseResponseCode::SpdmRequestDeliverEncapsulatedResponse => {
+                "DELIVER_ENCAPSULATED_RESPONSE"
+            }
+            SpdmResponseResponseCode::SpdmRequestEndSession => "END_SESSION",
+            SpdmResponseResponseCode::SpdmRequestChunkSend => "CHUNK_SEND",
+            SpdmResponseResponseCode::SpdmRequestChunkGet => "CHUNK_GET",
+            SpdmResponseResponseCode::Unknown(_) => "UNKNOWN",
+        }
+    }
+}
+
+impl fmt::Display for SpdmResponseResponseCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SpdmResponseResponseCode::Unknown(c) => write!(f, "UNKNOWN({:#04x})", c),
+            _ => f.write_str(self.name()),
+        }
     }
 }
 
 #[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmMessageHeader {
     pub version: SpdmVersion,
     pub request_response_code: SpdmResponseResponseCode,
@@ -85,3 +181,24 @@ impl Codec for SpdmMessageHeader {
         })
     }
 }
+
+/// Formats as e.g. "GET_VERSION (SPDM 1.0)".
+impl fmt::Display for SpdmMessageHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (SPDM {})", self.request_response_code, self.version)
+    }
+}
+
+/// Formats the header of a captured SPDM message and its size, e.g.
+/// "GET_VERSION (SPDM 1.0), 4 bytes", for logs and message dumps.
+#[derive(Clone, Copy)]
+pub struct SpdmMessageSummary<'a>(pub &'a [u8]);
+
+impl fmt::Display for SpdmMessageSummary<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match SpdmMessageHeader::read_bytes(self.0) {
+            Some(header) => write!(f, "{}, {} bytes", header, self.0.len()),
+            None => write!(f, "truncated message, {} bytes", self.0.len()),
+        }
+    }
+}
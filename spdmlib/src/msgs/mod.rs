@@ -4,27 +4,83 @@
 
 #![forbid(unsafe_code)]
 
+// Accessors for the structs holding `data_size` valid bytes in a fixed size
+// `data` array. Use them instead of the raw fields, which stay public for
+// the codecs and existing users.
+macro_rules! impl_spdm_buffer {
+    ($($struct_name: ident: $max_size: expr),*) => {
+        $(
+            impl $struct_name {
+                pub const MAX_SIZE: usize = $max_size;
+
+                /// The valid bytes, prefer it to reading `data` directly.
+                /// A `data_size` beyond the array is truncated instead of
+                /// causing a panic.
+                pub fn as_slice(&self) -> &[u8] {
+                    &self.data[..(self.data_size as usize).min($max_size)]
+                }
+
+                pub fn len(&self) -> usize {
+                    self.as_slice().len()
+                }
+
+                pub fn is_empty(&self) -> bool {
+                    self.len() == 0
+                }
+            }
+
+            impl AsRef<[u8]> for $struct_name {
+                fn as_ref(&self) -> &[u8] {
+                    self.as_slice()
+                }
+            }
+
+            impl core::convert::TryFrom<&[u8]> for $struct_name {
+                type Error = crate::error::SpdmError;
+
+                fn try_from(value: &[u8]) -> crate::error::SpdmResult<Self> {
+                    if value.len() > $max_size {
+                        return spdm_result_err!(EINVAL);
+                    }
+                    let mut buffer = $struct_name::default();
+                    buffer.data[..value.len()].copy_from_slice(value);
+                    buffer.data_size = value.len() as u16;
+                    Ok(buffer)
+                }
+            }
+        )*
+    };
+}
+
 mod algo;
+mod builder;
+pub mod exchange;
+#[cfg(feature = "fuzzing")]
+mod fuzzing;
 mod header;
 mod opaque;
 mod spdm_codec;
 pub use algo::*;
+pub use builder::*;
 
 use crate::cmds::digest as cmd_digest;
 use crate::cmds::key_exchange as cmd_key_exchange;
 use crate::cmds::*;
 use crate::common;
+use crate::error::SpdmResult;
 use codec::{Codec, Reader, Writer};
 pub use header::*;
 pub use opaque::*;
-pub use spdm_codec::SpdmCodec;
+pub use spdm_codec::{spdm_encoded_size, SpdmCodec};
 
 pub use algorithm::*;
 pub use capability::*;
 pub use certificate::*;
 pub use challenge::*;
+pub use chunk::*;
 pub use cmd_digest::*;
 pub use cmd_key_exchange::*;
+pub use encapsulated::*;
 pub use end_session::*;
 pub use error::*;
 pub use finish::*;
@@ -33,10 +89,18 @@ pub use key_update::*;
 pub use measurement::*;
 pub use psk_exchange::*;
 pub use psk_finish::*;
+pub use respond_if_ready::*;
+pub use vendor::*;
 pub use version::*;
 // Add new SPDM command here.
 
+impl_spdm_buffer!(
+    SpdmVendorIdStruct: SPDM_MAX_VENDOR_ID_SIZE,
+    SpdmVendorDefinedPayloadStruct: crate::config::MAX_SPDM_TRANSPORT_SIZE
+);
+
 #[derive(Debug)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub struct SpdmMessage {
     pub header: SpdmMessageHeader,
     pub payload: SpdmMessagePayload,
@@ -48,6 +112,7 @@ pub struct SpdmMessage {
 //
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug)]
+#[cfg_attr(feature = "fuzzing", derive(arbitrary::Arbitrary))]
 pub enum SpdmMessagePayload {
     SpdmGetVersionRequest(SpdmGetVersionRequestPayload),
     SpdmVersionResponse(SpdmVersionResponsePayload),
@@ -91,6 +156,23 @@ pub enum SpdmMessagePayload {
     SpdmEndSessionRequest(SpdmEndSessionRequestPayload),
     SpdmEndSessionResponse(SpdmEndSessionResponsePayload),
 
+    SpdmGetEncapsulatedRequest(SpdmGetEncapsulatedRequestPayload),
+    SpdmEncapsulatedRequest(SpdmEncapsulatedRequestPayload),
+
+    SpdmDeliverEncapsulatedResponse(SpdmDeliverEncapsulatedResponsePayload),
+    SpdmEncapsulatedResponseAck(SpdmEncapsulatedResponseAckPayload),
+
+    SpdmChunkSendRequest(SpdmChunkSendRequestPayload),
+    SpdmChunkSendAckResponse(SpdmChunkSendAckResponsePayload),
+
+    SpdmChunkGetRequest(SpdmChunkGetRequestPayload),
+    SpdmChunkResponse(SpdmChunkResponsePayload),
+
+    SpdmRespondIfReadyRequest(SpdmRespondIfReadyRequestPayload),
+
+    SpdmVendorDefinedRequest(SpdmVendorDefinedRequestPayload),
+    SpdmVendorDefinedResponse(SpdmVendorDefinedResponsePayload),
+
     // Add new SPDM command here.
     SpdmErrorResponse(SpdmErrorResponsePayload),
 }
@@ -257,6 +339,67 @@ impl SpdmMessage {
                 ))
             }
 
+            SpdmResponseResponseCode::SpdmRequestGetEncapsulatedRequest => {
+                Some(SpdmMessagePayload::SpdmGetEncapsulatedRequest(
+                    SpdmGetEncapsulatedRequestPayload::spdm_read(context, r)?,
+                ))
+            }
+            SpdmResponseResponseCode::SpdmResponseEncapsulatedRequest => {
+                Some(SpdmMessagePayload::SpdmEncapsulatedRequest(
+                    SpdmEncapsulatedRequestPayload::spdm_read(context, r)?,
+                ))
+            }
+
+            SpdmResponseResponseCode::SpdmRequestDeliverEncapsulatedResponse => {
+                Some(SpdmMessagePayload::SpdmDeliverEncapsulatedResponse(
+                    SpdmDeliverEncapsulatedResponsePayload::spdm_read(context, r)?,
+                ))
+            }
+            SpdmResponseResponseCode::SpdmResponseEncapsulatedResponseAck => {
+                Some(SpdmMessagePayload::SpdmEncapsulatedResponseAck(
+                    SpdmEncapsulatedResponseAckPayload::spdm_read(context, r)?,
+                ))
+            }
+
+            SpdmResponseResponseCode::SpdmResponseChunkSendAck => {
+                Some(SpdmMessagePayload::SpdmChunkSendAckResponse(
+                    SpdmChunkSendAckResponsePayload::spdm_read(context, r)?,
+                ))
+            }
+            SpdmResponseResponseCode::SpdmRequestChunkSend => {
+                Some(SpdmMessagePayload::SpdmChunkSendRequest(
+                    SpdmChunkSendRequestPayload::spdm_read(context, r)?,
+                ))
+            }
+
+            SpdmResponseResponseCode::SpdmResponseChunkResponse => {
+                Some(SpdmMessagePayload::SpdmChunkResponse(
+                    SpdmChunkResponsePayload::spdm_read(context, r)?,
+                ))
+            }
+            SpdmResponseResponseCode::SpdmRequestChunkGet => {
+                Some(SpdmMessagePayload::SpdmChunkGetRequest(
+                    SpdmChunkGetRequestPayload::spdm_read(context, r)?,
+                ))
+            }
+
+            SpdmResponseResponseCode::SpdmRequestResponseIfReady => {
+                Some(SpdmMessagePayload::SpdmRespondIfReadyRequest(
+                    SpdmRespondIfReadyRequestPayload::spdm_read(context, r)?,
+                ))
+            }
+
+            SpdmResponseResponseCode::SpdmRequestVendorDefinedRequest => {
+                Some(SpdmMessagePayload::SpdmVendorDefinedRequest(
+                    SpdmVendorDefinedRequestPayload::spdm_read(context, r)?,
+                ))
+            }
+            SpdmResponseResponseCode::SpdmResponseVendorDefinedResponse => {
+                Some(SpdmMessagePayload::SpdmVendorDefinedResponse(
+                    SpdmVendorDefinedResponsePayload::spdm_read(context, r)?,
+                ))
+            }
+
             // Add new SPDM command here.
             SpdmResponseResponseCode::SpdmResponseError => {
                 Some(SpdmMessagePayload::SpdmErrorResponse(
@@ -272,112 +415,157 @@ impl SpdmMessage {
 }
 
 impl SpdmCodec for SpdmMessage {
-    fn spdm_encode(&self, context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         self.header.encode(bytes);
         match &self.payload {
             SpdmMessagePayload::SpdmGetVersionRequest(payload) => {
-                payload.spdm_encode(context, bytes);
+                payload.spdm_encode(context, bytes)?;
             }
             SpdmMessagePayload::SpdmVersionResponse(payload) => {
-                payload.spdm_encode(context, bytes);
+                payload.spdm_encode(context, bytes)?;
             }
 
             SpdmMessagePayload::SpdmGetCapabilitiesRequest(payload) => {
-                payload.spdm_encode(context, bytes);
+                payload.spdm_encode(context, bytes)?;
             }
             SpdmMessagePayload::SpdmCapabilitiesResponse(payload) => {
-                payload.spdm_encode(context, bytes);
+                payload.spdm_encode(context, bytes)?;
             }
 
             SpdmMessagePayload::SpdmNegotiateAlgorithmsRequest(payload) => {
-                payload.spdm_encode(context, bytes);
+                payload.spdm_encode(context, bytes)?;
             }
             SpdmMessagePayload::SpdmAlgorithmsResponse(payload) => {
-                payload.spdm_encode(context, bytes);
+                payload.spdm_encode(context, bytes)?;
             }
 
             SpdmMessagePayload::SpdmGetDigestsRequest(payload) => {
-                payload.spdm_encode(context, bytes);
+                payload.spdm_encode(context, bytes)?;
             }
             SpdmMessagePayload::SpdmDigestsResponse(payload) => {
-                payload.spdm_encode(context, bytes);
+                payload.spdm_encode(context, bytes)?;
             }
 
             SpdmMessagePayload::SpdmGetCertificateRequest(payload) => {
-                payload.spdm_encode(context, bytes);
+                payload.spdm_encode(context, bytes)?;
             }
             SpdmMessagePayload::SpdmCertificateResponse(payload) => {
-                payload.spdm_encode(context, bytes);
+                payload.spdm_encode(context, bytes)?;
             }
 
             SpdmMessagePayload::SpdmChallengeRequest(payload) => {
-                payload.spdm_encode(context, bytes);
+                payload.spdm_encode(context, bytes)?;
             }
             SpdmMessagePayload::SpdmChallengeAuthResponse(payload) => {
-                payload.spdm_encode(context, bytes);
+                payload.spdm_encode(context, bytes)?;
             }
 
             SpdmMessagePayload::SpdmGetMeasurementsRequest(payload) => {
-                payload.spdm_encode(context, bytes);
+                payload.spdm_encode(context, bytes)?;
             }
             SpdmMessagePayload::SpdmMeasurementsResponse(payload) => {
-                payload.spdm_encode(context, bytes);
+                payload.spdm_encode(context, bytes)?;
             }
 
             SpdmMessagePayload::SpdmKeyExchangeRequest(payload) => {
-                payload.spdm_encode(context, bytes);
+                payload.spdm_encode(context, bytes)?;
             }
             SpdmMessagePayload::SpdmKeyExchangeResponse(payload) => {
-                payload.spdm_encode(context, bytes);
+                payload.spdm_encode(context, bytes)?;
             }
 
             SpdmMessagePayload::SpdmFinishRequest(payload) => {
-                payload.spdm_encode(context, bytes);
+                payload.spdm_encode(context, bytes)?;
             }
             SpdmMessagePayload::SpdmFinishResponse(payload) => {
-                payload.spdm_encode(context, bytes);
+                payload.spdm_encode(context, bytes)?;
             }
 
             SpdmMessagePayload::SpdmPskExchangeRequest(payload) => {
-                payload.spdm_encode(context, bytes);
+                payload.spdm_encode(context, bytes)?;
             }
             SpdmMessagePayload::SpdmPskExchangeResponse(payload) => {
-                payload.spdm_encode(context, bytes);
+                payload.spdm_encode(context, bytes)?;
             }
 
             SpdmMessagePayload::SpdmPskFinishRequest(payload) => {
-                payload.spdm_encode(context, bytes);
+                payload.spdm_encode(context, bytes)?;
             }
             SpdmMessagePayload::SpdmPskFinishResponse(payload) => {
-                payload.spdm_encode(context, bytes);
+                payload.spdm_encode(context, bytes)?;
             }
 
             SpdmMessagePayload::SpdmEndSessionRequest(payload) => {
-                payload.spdm_encode(context, bytes);
+                payload.spdm_encode(context, bytes)?;
             }
             SpdmMessagePayload::SpdmEndSessionResponse(payload) => {
-                payload.spdm_encode(context, bytes);
+                payload.spdm_encode(context, bytes)?;
+            }
+
+            SpdmMessagePayload::SpdmGetEncapsulatedRequest(payload) => {
+                payload.spdm_encode(context, bytes)?;
+            }
+            SpdmMessagePayload::SpdmEncapsulatedRequest(payload) => {
+                payload.spdm_encode(context, bytes)?;
+            }
+
+            SpdmMessagePayload::SpdmDeliverEncapsulatedResponse(payload) => {
+                payload.spdm_encode(context, bytes)?;
+            }
+            SpdmMessagePayload::SpdmEncapsulatedResponseAck(payload) => {
+                payload.spdm_encode(context, bytes)?;
             }
 
             SpdmMessagePayload::SpdmHeartbeatRequest(payload) => {
-                payload.spdm_encode(context, bytes);
+                payload.spdm_encode(context, bytes)?;
             }
             SpdmMessagePayload::SpdmHeartbeatResponse(payload) => {
-                payload.spdm_encode(context, bytes);
+                payload.spdm_encode(context, bytes)?;
             }
 
             SpdmMessagePayload::SpdmKeyUpdateRequest(payload) => {
-                payload.spdm_encode(context, bytes);
+                payload.spdm_encode(context, bytes)?;
             }
             SpdmMessagePayload::SpdmKeyUpdateResponse(payload) => {
-                payload.spdm_encode(context, bytes);
+                payload.spdm_encode(context, bytes)?;
+            }
+
+            SpdmMessagePayload::SpdmChunkSendRequest(payload) => {
+                payload.spdm_encode(context, bytes)?;
+            }
+            SpdmMessagePayload::SpdmChunkSendAckResponse(payload) => {
+                payload.spdm_encode(context, bytes)?;
+            }
+
+            SpdmMessagePayload::SpdmChunkGetRequest(payload) => {
+                payload.spdm_encode(context, bytes)?;
+            }
+            SpdmMessagePayload::SpdmChunkResponse(payload) => {
+                payload.spdm_encode(context, bytes)?;
+            }
+
+            SpdmMessagePayload::SpdmRespondIfReadyRequest(payload) => {
+                payload.spdm_encode(context, bytes)?;
+            }
+
+            SpdmMessagePayload::SpdmVendorDefinedRequest(payload) => {
+                payload.spdm_encode(context, bytes)?;
+            }
+            SpdmMessagePayload::SpdmVendorDefinedResponse(payload) => {
+                payload.spdm_encode(context, bytes)?;
             }
 
             // Add new SPDM command here.
             SpdmMessagePayload::SpdmErrorResponse(payload) => {
-                payload.spdm_encode(context, bytes);
+                payload.spdm_encode(context, bytes)?;
             }
         }
+        spdm_encoded_size(bytes, start)
     }
 
     fn spdm_read(context: &mut common::SpdmContext, r: &mut Reader) -> Option<SpdmMessage> {
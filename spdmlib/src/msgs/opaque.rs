@@ -6,7 +6,8 @@
 
 use crate::common;
 use crate::config;
-use crate::msgs::SpdmCodec;
+use crate::error::SpdmResult;
+use crate::msgs::{spdm_encoded_size, SpdmCodec};
 use codec::{Codec, Reader, Writer};
 
 //pub const SPDM_MAX_OPAQUE_SIZE : usize = 1024;
@@ -25,12 +26,20 @@ impl Default for SpdmOpaqueStruct {
     }
 }
 
+impl_spdm_buffer!(SpdmOpaqueStruct: config::MAX_SPDM_OPAQUE_SIZE);
+
 impl SpdmCodec for SpdmOpaqueStruct {
-    fn spdm_encode(&self, _context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        _context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         self.data_size.encode(bytes);
         for d in self.data.iter().take(self.data_size as usize) {
             d.encode(bytes);
         }
+        spdm_encoded_size(bytes, start)
     }
     fn spdm_read(_context: &mut common::SpdmContext, r: &mut Reader) -> Option<SpdmOpaqueStruct> {
         let data_size = u16::read(r)?;
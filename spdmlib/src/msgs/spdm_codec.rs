@@ -6,15 +6,20 @@
 
 use crate::common;
 use crate::config;
+use crate::error::SpdmResult;
 use crate::msgs::*;
 use codec::{u24, Codec, Reader, Writer};
 use core::fmt::Debug;
 
 pub trait SpdmCodec: Debug + Sized {
-    /// Encode yourself by appending onto `bytes`.
-    /// TBD: Encode may fail if the caller encodes too many data that exceeds the max size of preallocated slice.
-    /// Should we assert() here? or return to caller to let the caller handle it?
-    fn spdm_encode(&self, _context: &mut common::SpdmContext, _bytes: &mut Writer);
+    /// Encode yourself by appending onto `bytes`, returns the encoded size.
+    /// Fails with ENOMEM if `bytes` is too small and with EINVAL if the
+    /// fields are inconsistent, `bytes` holds a partial encoding then.
+    fn spdm_encode(
+        &self,
+        _context: &mut common::SpdmContext,
+        _bytes: &mut Writer,
+    ) -> SpdmResult<usize>;
 
     /// Decode yourself by fiddling with the `Reader`.
     /// Return Some if it worked, None if not.
@@ -37,11 +42,26 @@ pub trait SpdmCodec: Debug + Sized {
     }
 }
 
+/// Size encoded onto `bytes` since `start` was its `used()`, fails with
+/// ENOMEM if any of it didn't fit.
+pub fn spdm_encoded_size(bytes: &Writer, start: usize) -> SpdmResult<usize> {
+    if bytes.is_overflowed() {
+        return spdm_result_err!(ENOMEM, "encode buffer too small");
+    }
+    Ok(bytes.used() - start)
+}
+
 impl SpdmCodec for SpdmDigestStruct {
-    fn spdm_encode(&self, _context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        _context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         for d in self.data.iter().take(self.data_size as usize) {
             d.encode(bytes);
         }
+        spdm_encoded_size(bytes, start)
     }
     fn spdm_read(context: &mut common::SpdmContext, r: &mut Reader) -> Option<SpdmDigestStruct> {
         let data_size = context.get_hash_size();
@@ -54,10 +74,16 @@ impl SpdmCodec for SpdmDigestStruct {
 }
 
 impl SpdmCodec for SpdmSignatureStruct {
-    fn spdm_encode(&self, _context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        _context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         for d in self.data.iter().take(self.data_size as usize) {
             d.encode(bytes);
         }
+        spdm_encoded_size(bytes, start)
     }
     fn spdm_read(context: &mut common::SpdmContext, r: &mut Reader) -> Option<SpdmSignatureStruct> {
         let data_size = context.get_asym_key_size();
@@ -69,12 +95,17 @@ impl SpdmCodec for SpdmSignatureStruct {
     }
 }
 impl SpdmCodec for SpdmCertChain {
-    fn spdm_encode(&self, context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         let length = self.cert_chain.data_size as u16 + self.root_hash.data_size as u16 + 4_u16;
         length.encode(bytes);
         0u16.encode(bytes);
 
-        self.root_hash.spdm_encode(context, bytes);
+        self.root_hash.spdm_encode(context, bytes)?;
 
         for d in self
             .cert_chain
@@ -84,6 +115,7 @@ impl SpdmCodec for SpdmCertChain {
         {
             d.encode(bytes);
         }
+        spdm_encoded_size(bytes, start)
     }
     fn spdm_read(context: &mut common::SpdmContext, r: &mut Reader) -> Option<SpdmCertChain> {
         let length = u16::read(r)?;
@@ -105,13 +137,18 @@ impl SpdmCodec for SpdmCertChain {
 }
 
 impl SpdmCodec for SpdmMeasurementRecordStructure {
-    fn spdm_encode(&self, context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         self.number_of_blocks.encode(bytes);
 
         let mut calc_length = 0u32;
         for d in self.record.iter().take(self.number_of_blocks as usize) {
-            if d.measurement_size != d.measurement.value_size + 3 {
-                panic!();
+            if !d.is_measurement_size_valid() {
+                return spdm_result_err!(EINVAL);
             }
             calc_length += d.measurement_size as u32 + 4;
         }
@@ -119,8 +156,9 @@ impl SpdmCodec for SpdmMeasurementRecordStructure {
         record_length.encode(bytes);
 
         for d in self.record.iter().take(self.number_of_blocks as usize) {
-            d.spdm_encode(context, bytes);
+            d.spdm_encode(context, bytes)?;
         }
+        spdm_encoded_size(bytes, start)
     }
     fn spdm_read(
         context: &mut common::SpdmContext,
@@ -137,7 +175,7 @@ impl SpdmCodec for SpdmMeasurementRecordStructure {
 
         let mut calc_length = 0u32;
         for d in record.iter().take(number_of_blocks as usize) {
-            if d.measurement_size != d.measurement.value_size + 3 {
+            if !d.is_measurement_size_valid() {
                 return None;
             }
             calc_length += d.measurement_size as u32 + 4;
@@ -154,10 +192,16 @@ impl SpdmCodec for SpdmMeasurementRecordStructure {
 }
 
 impl SpdmCodec for SpdmDheExchangeStruct {
-    fn spdm_encode(&self, _context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        _context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         for d in self.data.iter().take(self.data_size as usize) {
             d.encode(bytes);
         }
+        spdm_encoded_size(bytes, start)
     }
     fn spdm_read(
         context: &mut common::SpdmContext,
@@ -173,10 +217,16 @@ impl SpdmCodec for SpdmDheExchangeStruct {
 }
 
 impl SpdmCodec for SpdmPskContextStruct {
-    fn spdm_encode(&self, _context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        _context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         for d in self.data.iter().take(self.data_size as usize) {
             d.encode(bytes);
         }
+        spdm_encoded_size(bytes, start)
     }
     fn spdm_read(
         _context: &mut common::SpdmContext,
@@ -192,10 +242,16 @@ impl SpdmCodec for SpdmPskContextStruct {
 }
 
 impl SpdmCodec for SpdmPskHintStruct {
-    fn spdm_encode(&self, _context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        _context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         for d in self.data.iter().take(self.data_size as usize) {
             d.encode(bytes);
         }
+        spdm_encoded_size(bytes, start)
     }
     fn spdm_read(_context: &mut common::SpdmContext, r: &mut Reader) -> Option<SpdmPskHintStruct> {
         let data_size = u16::read(r)?;
@@ -208,7 +264,12 @@ impl SpdmCodec for SpdmPskHintStruct {
 }
 
 impl SpdmCodec for SpdmDmtfMeasurementStructure {
-    fn spdm_encode(&self, _context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        _context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         let type_value = self.r#type.get_u8();
         let representation_value = self.r#type.get_u8();
         let final_value = type_value + representation_value;
@@ -220,6 +281,7 @@ impl SpdmCodec for SpdmDmtfMeasurementStructure {
         for v in self.value.iter().take(self.value_size as usize) {
             v.encode(bytes);
         }
+        spdm_encoded_size(bytes, start)
     }
     fn spdm_read(
         _context: &mut common::SpdmContext,
@@ -259,11 +321,29 @@ impl SpdmCodec for SpdmDmtfMeasurementStructure {
 }
 
 impl SpdmCodec for SpdmMeasurementBlockStructure {
-    fn spdm_encode(&self, context: &mut common::SpdmContext, bytes: &mut Writer) {
+    fn spdm_encode(
+        &self,
+        context: &mut common::SpdmContext,
+        bytes: &mut Writer,
+    ) -> SpdmResult<usize> {
+        let start = bytes.used();
         self.index.encode(bytes);
         self.measurement_specification.encode(bytes);
         self.measurement_size.encode(bytes);
-        self.measurement.spdm_encode(context, bytes);
+        if self.measurement_specification == SpdmMeasurementSpecification::DMTF {
+            self.measurement.spdm_encode(context, bytes)?;
+        } else {
+            // other specifications are passed through as is
+            for v in self
+                .measurement
+                .value
+                .iter()
+                .take(self.measurement_size as usize)
+            {
+                v.encode(bytes);
+            }
+        }
+        spdm_encoded_size(bytes, start)
     }
     fn spdm_read(
         context: &mut common::SpdmContext,
@@ -272,7 +352,22 @@ impl SpdmCodec for SpdmMeasurementBlockStructure {
         let index = u8::read(r)?;
         let measurement_specification = SpdmMeasurementSpecification::read(r)?;
         let measurement_size = u16::read(r)?;
-        let measurement = SpdmDmtfMeasurementStructure::spdm_read(context, r)?;
+        let measurement = if measurement_specification == SpdmMeasurementSpecification::DMTF {
+            SpdmDmtfMeasurementStructure::spdm_read(context, r)?
+        } else {
+            if measurement_size as usize > config::MAX_SPDM_MEASUREMENT_VALUE_LEN {
+                return None;
+            }
+            let mut value = [0u8; config::MAX_SPDM_MEASUREMENT_VALUE_LEN];
+            for v in value.iter_mut().take(measurement_size as usize) {
+                *v = u8::read(r)?;
+            }
+            SpdmDmtfMeasurementStructure {
+                value_size: measurement_size,
+                value,
+                ..Default::default()
+            }
+        };
         Some(SpdmMeasurementBlockStructure {
             index,
             measurement_specification,
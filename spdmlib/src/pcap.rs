@@ -0,0 +1,253 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+#![forbid(unsafe_code)]
+
+//! Trace recorder writing the transport messages of a context to a pcapng
+//! capture, e.g. to analyze an interoperability failure offline with the
+//! DMTF Wireshark dissector.
+//!
+//! ```ignore
+//! let mut recorder = SpdmPcapRecorder::new(File::create("spdm.pcapng")?, LINKTYPE_MCTP);
+//! recorder.set_link_header(&[0x01, 0x00, 0x00, 0xC8])?;
+//! context.common.attach_trace_recorder(&mut recorder);
+//! ```
+
+use crate::error::SpdmResult;
+use crate::time;
+use crate::trace::{SpdmMessageDirection, SpdmTraceRecorder};
+
+#[cfg(not(feature = "std"))]
+extern crate alloc;
+
+/// DMTF MCTP, the packets begin with the MCTP transport header.
+pub const LINKTYPE_MCTP: u16 = 291;
+/// Reserved for private use, e.g. for the PCI DOE framing.
+pub const LINKTYPE_USER0: u16 = 147;
+
+pub const MAX_LINK_HEADER_SIZE: usize = 16;
+
+const BLOCK_TYPE_SECTION_HEADER: u32 = 0x0A0D_0D0A;
+const BLOCK_TYPE_INTERFACE_DESCRIPTION: u32 = 0x0000_0001;
+const BLOCK_TYPE_ENHANCED_PACKET: u32 = 0x0000_0006;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+const OPTION_END_OF_OPT: u16 = 0;
+const OPTION_EPB_FLAGS: u16 = 2;
+const EPB_FLAGS_INBOUND: u32 = 0b01;
+const EPB_FLAGS_OUTBOUND: u32 = 0b10;
+
+/// Where the capture is written: anything implementing `std::io::Write`, or
+/// a `Vec<u8>` without std.
+pub trait SpdmPcapWrite {
+    fn write_all(&mut self, data: &[u8]) -> SpdmResult;
+}
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write> SpdmPcapWrite for W {
+    fn write_all(&mut self, data: &[u8]) -> SpdmResult {
+        std::io::Write::write_all(self, data).map_err(|_| spdm_err!(EIO))
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl SpdmPcapWrite for alloc::vec::Vec<u8> {
+    fn write_all(&mut self, data: &[u8]) -> SpdmResult {
+        self.extend_from_slice(data);
+        Ok(())
+    }
+}
+
+pub struct SpdmPcapRecorder<W: SpdmPcapWrite> {
+    writer: W,
+    link_type: u16,
+    link_header: [u8; MAX_LINK_HEADER_SIZE],
+    link_header_size: usize,
+    header_written: bool,
+    dropped_count: usize,
+    // wall clock minus the monotonic one at the first packet, in us
+    clock_offset_us: Option<u64>,
+}
+
+impl<W: SpdmPcapWrite> SpdmPcapRecorder<W> {
+    /// The section and interface headers are written with the first packet.
+    pub fn new(writer: W, link_type: u16) -> Self {
+        SpdmPcapRecorder {
+            writer,
+            link_type,
+            link_header: [0u8; MAX_LINK_HEADER_SIZE],
+            link_header_size: 0,
+            header_written: false,
+            dropped_count: 0,
+            clock_offset_us: None,
+        }
+    }
+
+    /// Bytes written before every transport message, for the framing the
+    /// transport encap leaves to the device, e.g. the MCTP transport header.
+    pub fn set_link_header(&mut self, link_header: &[u8]) -> SpdmResult {
+        if link_header.len() > MAX_LINK_HEADER_SIZE {
+            return spdm_result_err!(EINVAL, "link header too large");
+        }
+        self.link_header[..link_header.len()].copy_from_slice(link_header);
+        self.link_header_size = link_header.len();
+        Ok(())
+    }
+
+    /// Packets that could not be written, the recorder can't fail the
+    /// exchange it records.
+    pub fn get_dropped_count(&self) -> usize {
+        self.dropped_count
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// `timestamp_us` in microseconds since the UNIX epoch.
+    pub fn write_packet(
+        &mut self,
+        direction: SpdmMessageDirection,
+        timestamp_us: u64,
+        transport_message: &[u8],
+    ) -> SpdmResult {
+        if !self.header_written {
+            self.write_header()?;
+            self.header_written = true;
+        }
+
+        let packet_size = self.link_header_size + transport_message.len();
+        let padding_size = (4 - packet_size % 4) % 4;
+        let block_size = 28 + packet_size + padding_size + 12 + 4;
+        let flags = match direction {
+            SpdmMessageDirection::Send => EPB_FLAGS_OUTBOUND,
+            SpdmMessageDirection::Receive => EPB_FLAGS_INBOUND,
+        };
+
+        self.write_u32(BLOCK_TYPE_ENHANCED_PACKET)?;
+        self.write_u32(block_size as u32)?;
+        // interface id
+        self.write_u32(0)?;
+        self.write_u32((timestamp_us >> 32) as u32)?;
+        self.write_u32(timestamp_us as u32)?;
+        // captured and original packet length
+        self.write_u32(packet_size as u32)?;
+        self.write_u32(packet_size as u32)?;
+        self.writer
+            .write_all(&self.link_header[..self.link_header_size])?;
+        self.writer.write_all(transport_message)?;
+        self.writer.write_all(&[0u8; 3][..padding_size])?;
+        self.write_u16(OPTION_EPB_FLAGS)?;
+        self.write_u16(4)?;
+        self.write_u32(flags)?;
+        self.write_u16(OPTION_END_OF_OPT)?;
+        self.write_u16(0)?;
+        self.write_u32(block_size as u32)
+    }
+
+    fn write_header(&mut self) -> SpdmResult {
+        self.write_u32(BLOCK_TYPE_SECTION_HEADER)?;
+        self.write_u32(28)?;
+        self.write_u32(BYTE_ORDER_MAGIC)?;
+        // version 1.0
+        self.write_u16(1)?;
+        self.write_u16(0)?;
+        // section length not specified
+        self.writer.write_all(&[0xFFu8; 8])?;
+        self.write_u32(28)?;
+
+        // microsecond timestamps, no snapshot length limit
+        self.write_u32(BLOCK_TYPE_INTERFACE_DESCRIPTION)?;
+        self.write_u32(20)?;
+        self.write_u16(self.link_type)?;
+        self.write_u16(0)?;
+        self.write_u32(0)?;
+        self.write_u32(20)
+    }
+
+    fn write_u16(&mut self, value: u16) -> SpdmResult {
+        self.writer.write_all(&value.to_le_bytes())
+    }
+
+    fn write_u32(&mut self, value: u32) -> SpdmResult {
+        self.writer.write_all(&value.to_le_bytes())
+    }
+}
+
+impl<W: SpdmPcapWrite> SpdmTraceRecorder for SpdmPcapRecorder<W> {
+    // The packets are timed with the monotonic clock, anchored to the wall
+    // clock at the first packet.
+    fn record(&mut self, direction: SpdmMessageDirection, transport_message: &[u8]) {
+        let now = time::get_time();
+        let clock_offset_us = *self.clock_offset_us.get_or_insert_with(|| {
+            time::get_unix_time()
+                .unwrap_or(0)
+                .saturating_mul(1_000_000)
+                .saturating_sub(now)
+        });
+        if self
+            .write_packet(
+                direction,
+                clock_offset_us.saturating_add(now),
+                transport_message,
+            )
+            .is_err()
+        {
+            error!("!!! pcap : write fail !!!\n");
+            self.dropped_count += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_packet() {
+        let mut recorder = SpdmPcapRecorder::new(Vec::new(), LINKTYPE_MCTP);
+        recorder.set_link_header(&[0x01, 0x00, 0x00, 0xC8]).unwrap();
+        recorder
+            .write_packet(
+                SpdmMessageDirection::Send,
+                0x1_0000_0002,
+                &[0x05, 0x10, 0x84, 0x00, 0x00],
+            )
+            .unwrap();
+        recorder
+            .write_packet(SpdmMessageDirection::Receive, 3, &[0x05, 0x10, 0x04])
+            .unwrap();
+        assert!(recorder.set_link_header(&[0u8; 17]).is_err());
+        let capture = recorder.into_inner();
+
+        let u32_at = |offset: usize| {
+            let mut bytes = [0u8; 4];
+            bytes.copy_from_slice(&capture[offset..offset + 4]);
+            u32::from_le_bytes(bytes)
+        };
+        // section header and interface description
+        assert_eq!(u32_at(0), BLOCK_TYPE_SECTION_HEADER);
+        assert_eq!(u32_at(8), BYTE_ORDER_MAGIC);
+        assert_eq!(u32_at(28), BLOCK_TYPE_INTERFACE_DESCRIPTION);
+        assert_eq!(&capture[36..38], &LINKTYPE_MCTP.to_le_bytes());
+
+        // 9 bytes padded to 12
+        let packet = 48;
+        assert_eq!(u32_at(packet), BLOCK_TYPE_ENHANCED_PACKET);
+        assert_eq!(u32_at(packet + 4), 56);
+        assert_eq!(u32_at(packet + 12), 1);
+        assert_eq!(u32_at(packet + 16), 2);
+        assert_eq!(u32_at(packet + 20), 9);
+        assert_eq!(
+            &capture[packet + 28..packet + 40],
+            &[0x01, 0x00, 0x00, 0xC8, 0x05, 0x10, 0x84, 0x00, 0x00, 0x00, 0x00, 0x00]
+        );
+        assert_eq!(u32_at(packet + 44), EPB_FLAGS_OUTBOUND);
+        assert_eq!(u32_at(packet + 52), 56);
+
+        let packet = packet + 56;
+        assert_eq!(u32_at(packet + 20), 7);
+        assert_eq!(u32_at(packet + 40), EPB_FLAGS_INBOUND);
+        assert_eq!(capture.len(), packet + 52);
+    }
+}
@@ -0,0 +1,302 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+#![forbid(unsafe_code)]
+
+//! Provisioning blob, generated offline by the firmware build and loaded
+//! into an `SpdmProvisionInfo` when the context is created.
+//!
+//! All integers are little-endian, there is no padding:
+//!
+//! ```text
+//! magic        8 bytes  "SPDMPROV"
+//! version      u16      1
+//! entry_count  u16
+//! length       u32      size of the entries
+//! entries      length bytes, each:
+//!     type     u8
+//!     id       u8       meaning depends on the type
+//!     size     u16
+//!     value    size bytes
+//! digest       32 bytes SHA-256 of everything before it
+//! ```
+//!
+//! Entry types:
+//!
+//! * `0x01` cert chain: `id` is the slot, the value the SPDM cert chain
+//!   data (DER certificates) loaded into `my_cert_chain_data`.
+//! * `0x02` key: `id` is the slot, the value the private key or the handle
+//!   the asym_sign callback signs with. See `SpdmProvisionBlob::get_key()`.
+//! * `0x03` PSK: `id` is 0, the value a u8 hint size, the hint and the PSK.
+//!   See `SpdmProvisionBlob::get_psk()`.
+//! * `0x04` peer root hash: `id` is 0, the value the hash of the root
+//!   certificate the peer cert chain has to start with.
+//! * `0x05` signing slot: `id` is the purpose (0 CHALLENGE, 1 MEASUREMENTS,
+//!   2 KEY_EXCHANGE), the value the one byte slot signing for it.
+//!
+//! The keys and PSKs are not part of the `SpdmProvisionInfo`, the crypto
+//! callbacks look them up in the blob.
+
+use core::convert::TryFrom;
+
+use crate::common::{constant_time_eq, SpdmProvisionInfo};
+use crate::crypto;
+use crate::error::SpdmResult;
+use crate::msgs::*;
+use codec::enum_builder;
+use codec::{Codec, Reader, Writer};
+
+pub const SPDM_PROVISION_BLOB_MAGIC: [u8; 8] = *b"SPDMPROV";
+pub const SPDM_PROVISION_BLOB_VERSION: u16 = 1;
+
+const HEADER_SIZE: usize = 16;
+const DIGEST_SIZE: usize = 32;
+
+enum_builder! {
+    @U8
+    EnumName: SpdmProvisionEntryType;
+    EnumVal{
+        CertChain => 0x1,
+        Key => 0x2,
+        Psk => 0x3,
+        PeerRootHash => 0x4,
+        SigningSlot => 0x5
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SpdmProvisionEntry<'a> {
+    pub entry_type: SpdmProvisionEntryType,
+    pub id: u8,
+    pub value: &'a [u8],
+}
+
+impl<'a> SpdmProvisionEntry<'a> {
+    // The entry at the start of `bytes` and the bytes after it.
+    fn read(bytes: &'a [u8]) -> Option<(SpdmProvisionEntry<'a>, &'a [u8])> {
+        let mut reader = Reader::init(bytes);
+        let entry_type = SpdmProvisionEntryType::read(&mut reader)?;
+        let id = u8::read(&mut reader)?;
+        let size = u16::read(&mut reader)? as usize;
+        let start = reader.used();
+        let value = bytes.get(start..start + size)?;
+        Some((
+            SpdmProvisionEntry {
+                entry_type,
+                id,
+                value,
+            },
+            &bytes[start + size..],
+        ))
+    }
+}
+
+/// A provisioning blob whose header and digest are checked.
+#[derive(Debug, Clone, Copy)]
+pub struct SpdmProvisionBlob<'a> {
+    entry_count: u16,
+    entries: &'a [u8],
+}
+
+impl<'a> SpdmProvisionBlob<'a> {
+    /// Fails with EINVAL if `blob` is malformed and with EBADMSG if the
+    /// digest doesn't match.
+    pub fn parse(blob: &'a [u8]) -> SpdmResult<SpdmProvisionBlob<'a>> {
+        let mut reader = Reader::init(blob);
+        let magic = reader.take(SPDM_PROVISION_BLOB_MAGIC.len());
+        if magic != Some(&SPDM_PROVISION_BLOB_MAGIC[..]) {
+            return spdm_result_err!(EINVAL, "not a provisioning blob");
+        }
+        let version = u16::read(&mut reader).ok_or(spdm_err!(EINVAL))?;
+        if version != SPDM_PROVISION_BLOB_VERSION {
+            return spdm_result_err!(EINVAL, "unsupported provisioning blob version");
+        }
+        let entry_count = u16::read(&mut reader).ok_or(spdm_err!(EINVAL))?;
+        let length = u32::read(&mut reader).ok_or(spdm_err!(EINVAL))? as usize;
+        if reader.left() != length.checked_add(DIGEST_SIZE).ok_or(spdm_err!(EINVAL))? {
+            return spdm_result_err!(EINVAL, "provisioning blob size mismatch");
+        }
+
+        let digest = crypto::hash::hash_all(
+            SpdmBaseHashAlgo::TPM_ALG_SHA_256,
+            &blob[..HEADER_SIZE + length],
+        )
+        .ok_or(spdm_err!(EFAULT))?;
+        if !constant_time_eq(digest.as_ref(), &blob[HEADER_SIZE + length..]) {
+            return spdm_result_err!(EBADMSG, "provisioning blob digest mismatch");
+        }
+
+        let provision_blob = SpdmProvisionBlob {
+            entry_count,
+            entries: &blob[HEADER_SIZE..HEADER_SIZE + length],
+        };
+        let mut rest = provision_blob.entries;
+        for _ in 0..entry_count {
+            rest = SpdmProvisionEntry::read(rest).ok_or(spdm_err!(EINVAL))?.1;
+        }
+        if !rest.is_empty() {
+            return spdm_result_err!(EINVAL, "provisioning blob entry count mismatch");
+        }
+        Ok(provision_blob)
+    }
+
+    pub fn entries(&self) -> SpdmProvisionEntries<'a> {
+        SpdmProvisionEntries {
+            remaining: self.entry_count,
+            rest: self.entries,
+        }
+    }
+
+    /// The private key or key handle of `slot_id`.
+    pub fn get_key(&self, slot_id: u8) -> Option<&'a [u8]> {
+        self.entries()
+            .find(|entry| entry.entry_type == SpdmProvisionEntryType::Key && entry.id == slot_id)
+            .map(|entry| entry.value)
+    }
+
+    /// The PSK identified by `psk_hint`.
+    pub fn get_psk(&self, psk_hint: &[u8]) -> Option<&'a [u8]> {
+        self.entries()
+            .filter(|entry| entry.entry_type == SpdmProvisionEntryType::Psk)
+            .find_map(|entry| {
+                let hint_end = 1 + *entry.value.first()? as usize;
+                if entry.value.get(1..hint_end)? != psk_hint {
+                    return None;
+                }
+                Some(&entry.value[hint_end..])
+            })
+    }
+
+    /// The cert chains, the peer root hash and the signing slots of the
+    /// blob. Fails with EINVAL for an invalid or repeated entry.
+    pub fn to_provision_info(&self) -> SpdmResult<SpdmProvisionInfo> {
+        let mut provision_info = SpdmProvisionInfo::default();
+        for entry in self.entries() {
+            match entry.entry_type {
+                SpdmProvisionEntryType::CertChain => {
+                    let slot = provision_info
+                        .my_cert_chain_data
+                        .get_mut(entry.id as usize)
+                        .ok_or(spdm_err!(EINVAL, "invalid slot"))?;
+                    if slot.is_some() {
+                        return spdm_result_err!(EINVAL, "cert chain provisioned twice");
+                    }
+                    *slot = Some(SpdmCertChainData::try_from(entry.value)?);
+                }
+                SpdmProvisionEntryType::PeerRootHash => {
+                    if provision_info.peer_cert_chain_root_hash.is_some() {
+                        return spdm_result_err!(EINVAL, "peer root hash provisioned twice");
+                    }
+                    provision_info.peer_cert_chain_root_hash =
+                        Some(SpdmDigestStruct::try_from(entry.value)?);
+                }
+                SpdmProvisionEntryType::SigningSlot => {
+                    let slot_id = match entry.value {
+                        [slot_id] if (*slot_id as usize) < SPDM_MAX_SLOT_NUMBER => *slot_id,
+                        _ => return spdm_result_err!(EINVAL, "invalid signing slot"),
+                    };
+                    let signing_slot_ids = &mut provision_info.signing_slot_ids;
+                    let signing_slot_id = match entry.id {
+                        0 => &mut signing_slot_ids.challenge,
+                        1 => &mut signing_slot_ids.measurement,
+                        2 => &mut signing_slot_ids.key_exchange,
+                        _ => return spdm_result_err!(EINVAL, "invalid signing purpose"),
+                    };
+                    *signing_slot_id = Some(slot_id);
+                }
+                SpdmProvisionEntryType::Key | SpdmProvisionEntryType::Psk => {}
+                SpdmProvisionEntryType::Unknown(_) => {
+                    return spdm_result_err!(EINVAL, "unknown provisioning entry");
+                }
+            }
+        }
+        Ok(provision_info)
+    }
+}
+
+pub struct SpdmProvisionEntries<'a> {
+    remaining: u16,
+    rest: &'a [u8],
+}
+
+impl<'a> Iterator for SpdmProvisionEntries<'a> {
+    type Item = SpdmProvisionEntry<'a>;
+
+    fn next(&mut self) -> Option<SpdmProvisionEntry<'a>> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let (entry, rest) = SpdmProvisionEntry::read(self.rest)?;
+        self.rest = rest;
+        Some(entry)
+    }
+}
+
+impl SpdmProvisionInfo {
+    /// Load the provisioning blob `blob`, see the `provision` module.
+    pub fn from_blob(blob: &[u8]) -> SpdmResult<SpdmProvisionInfo> {
+        SpdmProvisionBlob::parse(blob)?.to_provision_info()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_blob(entries: &[(u8, u8, &[u8])], blob: &mut [u8]) -> usize {
+        let mut writer = Writer::init(blob);
+        writer.extend_from_slice(&SPDM_PROVISION_BLOB_MAGIC);
+        SPDM_PROVISION_BLOB_VERSION.encode(&mut writer);
+        (entries.len() as u16).encode(&mut writer);
+        let length: usize = entries.iter().map(|(_, _, value)| 4 + value.len()).sum();
+        (length as u32).encode(&mut writer);
+        for (entry_type, id, value) in entries.iter() {
+            entry_type.encode(&mut writer);
+            id.encode(&mut writer);
+            (value.len() as u16).encode(&mut writer);
+            writer.extend_from_slice(value);
+        }
+        let used = writer.used();
+        let digest =
+            crypto::hash::hash_all(SpdmBaseHashAlgo::TPM_ALG_SHA_256, &blob[..used]).unwrap();
+        blob[used..used + DIGEST_SIZE].copy_from_slice(digest.as_ref());
+        used + DIGEST_SIZE
+    }
+
+    #[test]
+    fn test_provision_blob() {
+        let mut blob = [0u8; 256];
+        let used = build_blob(
+            &[
+                (0x1, 1, &[0x30, 0x82, 0x01]),
+                (0x2, 1, &[0xAA; 4]),
+                (0x3, 0, &[2, b'h', b'i', 0x55, 0x55]),
+                (0x5, 1, &[1]),
+            ],
+            &mut blob,
+        );
+
+        let provision_blob = SpdmProvisionBlob::parse(&blob[..used]).unwrap();
+        assert_eq!(provision_blob.get_key(1), Some(&[0xAA; 4][..]));
+        assert_eq!(provision_blob.get_key(0), None);
+        assert_eq!(provision_blob.get_psk(b"hi"), Some(&[0x55, 0x55][..]));
+        assert_eq!(provision_blob.get_psk(b"ho"), None);
+
+        let provision_info = SpdmProvisionInfo::from_blob(&blob[..used]).unwrap();
+        assert!(provision_info.my_cert_chain_data[0].is_none());
+        assert_eq!(
+            provision_info.my_cert_chain_data[1].unwrap().as_ref(),
+            &[0x30, 0x82, 0x01]
+        );
+        assert_eq!(provision_info.signing_slot_ids.measurement, Some(1));
+
+        blob[HEADER_SIZE] ^= 1;
+        assert!(SpdmProvisionBlob::parse(&blob[..used]).is_err());
+        assert!(SpdmProvisionBlob::parse(&blob[..used - 1]).is_err());
+
+        let used = build_blob(&[(0x1, SPDM_MAX_SLOT_NUMBER as u8, &[0x30])], &mut blob);
+        assert!(SpdmProvisionInfo::from_blob(&blob[..used]).is_err());
+    }
+}
@@ -0,0 +1,43 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+#![forbid(unsafe_code)]
+
+use crate::error::SpdmResult;
+use crate::requester::*;
+
+impl<'a> RequesterContext<'a> {
+    /// Send the application message `app_req` in the session `session_id`
+    /// and receive the application message answering it into `app_rsp_buf`.
+    /// The messages carry the transport header of the application, e.g.
+    /// the MCTP message type. Fails with ENOSYS if the transport only
+    /// carries SPDM in sessions and with EINVAL if an SPDM message is
+    /// received instead.
+    pub fn send_receive_app_message(
+        &mut self,
+        session_id: u32,
+        app_req: &[u8],
+        app_rsp_buf: &mut [u8],
+    ) -> SpdmResult<usize> {
+        let mut app_buffer = [0u8; config::MAX_SPDM_MESSAGE_BUFFER_SIZE];
+        let used = self
+            .common
+            .transport_encap
+            .encap_app_message(app_req, &mut app_buffer)?;
+        self.send_secured_app_buffer(session_id, &app_buffer[..used])?;
+
+        let used = self.receive_secured_app_buffer(session_id, &mut app_buffer)?;
+        match self
+            .common
+            .transport_encap
+            .decap_app_message(&app_buffer[..used], app_rsp_buf)?
+        {
+            Some(used) => Ok(used),
+            None => {
+                error!("!!! app_message : SPDM message received !!!\n");
+                spdm_result_err!(EINVAL, "no application message received")
+            }
+        }
+    }
+}
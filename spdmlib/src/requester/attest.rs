@@ -0,0 +1,198 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+#![forbid(unsafe_code)]
+
+use crate::error::SpdmResult;
+use crate::requester::*;
+
+#[cfg(feature = "std")]
+use std::time::Duration;
+
+/// What attest_device() learned about the responder.
+#[derive(Debug, Clone, Copy)]
+pub struct SpdmAttestationEvidence {
+    pub slot_id: u8,
+    /// The verified cert chain of the slot.
+    pub cert_chain: SpdmCertChainData,
+    /// Exchanges covered by the verified CHALLENGE_AUTH signature, empty
+    /// for responders without CHAL_CAP.
+    pub verified_coverage: SpdmTranscriptCoverage,
+    /// Measurement blocks, empty for responders without MEAS_CAP.
+    pub measurements: SpdmMeasurementRecordStructure,
+    /// Whether a verified MEASUREMENTS signature covers the block in
+    /// `measurements`.
+    pub measurements_signed: [bool; config::MAX_SPDM_MEASUREMENT_BLOCK_COUNT],
+    pub measurement_generation: Option<u32>,
+}
+
+impl<'a> RequesterContext<'a> {
+    /// VCA, GET_DIGESTS, GET_CERTIFICATE of `slot_id`, CHALLENGE and all
+    /// measurements, as far as the responder supports them.
+    pub fn attest_device(&mut self, slot_id: u8) -> SpdmResult<SpdmAttestationEvidence> {
+        self.init_connection()?;
+        self.send_receive_spdm_digest()?;
+        self.send_receive_spdm_certificate(slot_id)?;
+
+        let rsp_capabilities = self.common.negotiate_info.rsp_capabilities_sel;
+        if rsp_capabilities.contains(SpdmResponseCapabilityFlags::CHAL_CAP) {
+            self.send_receive_spdm_challenge(
+                slot_id,
+                SpdmMeasurementSummaryHashType::SpdmMeasurementSummaryHashTypeNone,
+            )?;
+        }
+        if rsp_capabilities.intersects(
+            SpdmResponseCapabilityFlags::MEAS_CAP_SIG
+                | SpdmResponseCapabilityFlags::MEAS_CAP_NO_SIG,
+        ) {
+            self.send_receive_spdm_measurement(
+                SpdmMeasurementOperation::SpdmMeasurementRequestAll,
+                slot_id,
+            )?;
+        }
+
+        let peer_info = &self.common.peer_info;
+        Ok(SpdmAttestationEvidence {
+            slot_id,
+            cert_chain: peer_info.peer_cert_chains[slot_id as usize].ok_or(spdm_err!(EFAULT))?,
+            verified_coverage: self.common.runtime_info.verified_coverage,
+            measurements: peer_info.peer_measurements,
+            measurements_signed: peer_info.peer_measurements_signed,
+            measurement_generation: peer_info.peer_measurement_generation,
+        })
+    }
+}
+
+/// How attest_devices() runs.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy)]
+pub struct SpdmParallelAttestConfig {
+    /// Devices attested at the same time.
+    pub max_parallel: usize,
+    /// A device not attested within this time fails with ETIMEDOUT.
+    pub device_timeout: Duration,
+}
+
+#[cfg(feature = "std")]
+impl Default for SpdmParallelAttestConfig {
+    fn default() -> SpdmParallelAttestConfig {
+        SpdmParallelAttestConfig {
+            max_parallel: 8,
+            device_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Result of one device of attest_devices().
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct SpdmDeviceAttestation<T> {
+    /// Index of the device in the list given to attest_devices().
+    pub device: usize,
+    pub result: SpdmResult<T>,
+    pub elapsed: Duration,
+}
+
+/// Run `attest` for every device of `devices` on a pool of threads,
+/// returns the results in the order of `devices`.
+///
+/// `attest` sets up the transport and the requester context of the device
+/// it is given, typically to call attest_device(). A device that times out
+/// is reported as failed while its thread keeps running until `attest`
+/// returns, so the device io should have a receive timeout as well.
+#[cfg(feature = "std")]
+pub fn attest_devices<D, T, F>(
+    devices: Vec<D>,
+    config: SpdmParallelAttestConfig,
+    attest: F,
+) -> Vec<SpdmDeviceAttestation<T>>
+where
+    D: Send + 'static,
+    T: Send + 'static,
+    F: Fn(D) -> SpdmResult<T> + Send + Sync + 'static,
+{
+    use std::sync::mpsc::{self, RecvTimeoutError};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Instant;
+
+    let worker_count = config.max_parallel.max(1).min(devices.len());
+    let queue = Arc::new(Mutex::new(devices.into_iter().enumerate()));
+    let attest = Arc::new(attest);
+    let (result_sender, result_receiver) = mpsc::channel();
+
+    let workers: Vec<_> = (0..worker_count)
+        .map(|_| {
+            let queue = queue.clone();
+            let attest = attest.clone();
+            let result_sender = result_sender.clone();
+            thread::spawn(move || loop {
+                let next = queue.lock().unwrap().next();
+                let (device, device_handle) = match next {
+                    Some(next) => next,
+                    None => break,
+                };
+
+                let start = Instant::now();
+                let (sender, receiver) = mpsc::channel();
+                let attest = attest.clone();
+                thread::spawn(move || {
+                    let _ = sender.send(attest(device_handle));
+                });
+                let result = match receiver.recv_timeout(config.device_timeout) {
+                    Ok(result) => result,
+                    Err(RecvTimeoutError::Timeout) => {
+                        error!("!!! attest_devices : device {} timeout !!!\n", device);
+                        spdm_result_err!(ETIMEDOUT)
+                    }
+                    Err(RecvTimeoutError::Disconnected) => {
+                        spdm_result_err!(EIO, "attestation thread panicked")
+                    }
+                };
+                let _ = result_sender.send(SpdmDeviceAttestation {
+                    device,
+                    result,
+                    elapsed: start.elapsed(),
+                });
+            })
+        })
+        .collect();
+    drop(result_sender);
+
+    for worker in workers {
+        let _ = worker.join();
+    }
+    let mut results: Vec<_> = result_receiver.iter().collect();
+    results.sort_by_key(|attestation| attestation.device);
+    results
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attest_devices() {
+        let config = SpdmParallelAttestConfig {
+            max_parallel: 2,
+            device_timeout: Duration::from_millis(200),
+        };
+        let results = attest_devices(vec![0u64, 1000, 0, 1], config, |delay_ms| {
+            std::thread::sleep(Duration::from_millis(delay_ms));
+            if delay_ms == 1 {
+                spdm_result_err!(EIO)
+            } else {
+                Ok(delay_ms)
+            }
+        });
+
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0].device, 0);
+        assert_eq!(results[0].result.as_ref().unwrap(), &0);
+        assert!(results[1].result.is_err());
+        assert!(results[1].elapsed < Duration::from_millis(1000));
+        assert!(results[2].result.is_ok());
+        assert!(results[3].result.is_err());
+    }
+}
@@ -8,22 +8,19 @@ use crate::error::SpdmResult;
 use crate::requester::*;
 
 impl<'a> RequesterContext<'a> {
-    pub fn send_receive_spdm_challenge(
+    /// Encode CHALLENGE into `buffer` without sending it.
+    pub fn encode_spdm_challenge(
         &mut self,
         slot_id: u8,
         measurement_summary_hash_type: SpdmMeasurementSummaryHashType,
-    ) -> SpdmResult {
-        info!("send spdm challenge\n");
-        let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
-        let mut writer = Writer::init(&mut send_buffer);
-
-        let nonce = [0xafu8; SPDM_NONCE_SIZE];
-        //let spdm_random = SpdmCryptoRandom {}; // TBD
-        //spdm_random.get_random (&mut nonce);
+        buffer: &mut [u8],
+    ) -> SpdmResult<usize> {
+        let mut nonce = [0u8; SPDM_NONCE_SIZE];
+        self.common.crypto_provider.get_random(&mut nonce)?;
 
         let request = SpdmMessage {
             header: SpdmMessageHeader {
-                version: SpdmVersion::SpdmVersion11,
+                version: self.common.negotiate_info.spdm_version_sel,
                 request_response_code: SpdmResponseResponseCode::SpdmRequestChallenge,
             },
             payload: SpdmMessagePayload::SpdmChallengeRequest(SpdmChallengeRequestPayload {
@@ -32,8 +29,39 @@ impl<'a> RequesterContext<'a> {
                 nonce: SpdmNonceStruct { data: nonce },
             }),
         };
-        request.spdm_encode(&mut self.common, &mut writer);
-        let used = writer.used();
+        self.encode_request(&request, buffer)
+    }
+
+    pub fn send_receive_spdm_challenge(
+        &mut self,
+        slot_id: u8,
+        measurement_summary_hash_type: SpdmMeasurementSummaryHashType,
+    ) -> SpdmResult {
+        self.common
+            .check_connection_state(SpdmConnectionState::Negotiated)?;
+        if slot_id as usize >= SPDM_MAX_SLOT_NUMBER {
+            return spdm_result_err!(EINVAL);
+        }
+        self.load_peer_cert_chain(slot_id);
+
+        let result = self.exchange_spdm_challenge(slot_id, measurement_summary_hash_type);
+        if result.is_err() {
+            // a retried CHALLENGE doesn't sign the failed exchange
+            self.common.runtime_info.message_c.reset_message();
+        }
+        result
+    }
+
+    // message_c is left to the caller to reset on error
+    fn exchange_spdm_challenge(
+        &mut self,
+        slot_id: u8,
+        measurement_summary_hash_type: SpdmMeasurementSummaryHashType,
+    ) -> SpdmResult {
+        info!("send spdm challenge\n");
+        let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        let used =
+            self.encode_spdm_challenge(slot_id, measurement_summary_hash_type, &mut send_buffer)?;
 
         self.send_message(&send_buffer[..used])?;
 
@@ -59,7 +87,8 @@ impl<'a> RequesterContext<'a> {
         }
 
         // Receive
-        let mut receive_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        // may be a large response retrieved in chunks
+        let mut receive_buffer = [0u8; config::MAX_SPDM_MESSAGE_BUFFER_SIZE];
         let used = self.receive_message(&mut receive_buffer)?;
 
         let mut reader = Reader::init(&receive_buffer[..used]);
@@ -72,6 +101,20 @@ impl<'a> RequesterContext<'a> {
                     if let Some(challenge_auth) = challenge_auth {
                         debug!("!!! challenge_auth : {:02x?}\n", challenge_auth);
 
+                        if challenge_auth.slot_id != slot_id {
+                            error!("!!! challenge_auth : slot_id mismatch !!!\n");
+                            return spdm_result_err!(EINVAL);
+                        }
+
+                        // verify cert chain hash
+                        if let Err(e) = self
+                            .common
+                            .verify_peer_cert_chain_hash(slot_id, &challenge_auth.cert_chain_hash)
+                        {
+                            error!("verify_peer_cert_chain_hash fail");
+                            return Err(e);
+                        }
+
                         // verify signature
                         let base_asym_size =
                             self.common.negotiate_info.base_asym_sel.get_size() as usize;
@@ -95,6 +138,16 @@ impl<'a> RequesterContext<'a> {
                         } else {
                             info!("verify_challenge_auth_signature pass");
                         }
+                        // only the hash the responder signed is cached
+                        self.common.peer_info.peer_cert_chain_hash[slot_id as usize] =
+                            Some(challenge_auth.cert_chain_hash);
+                        let runtime_info = &mut self.common.runtime_info;
+                        runtime_info.verified_coverage = runtime_info.transcript_coverage;
+                        // the next CHALLENGE signs the exchanges from here on
+                        runtime_info.message_b.reset_message();
+                        runtime_info.message_c.reset_message();
+                        self.common
+                            .advance_connection_state(SpdmConnectionState::Authenticated);
 
                         Ok(())
                     } else {
@@ -102,9 +155,144 @@ impl<'a> RequesterContext<'a> {
                         spdm_result_err!(EFAULT)
                     }
                 }
+                SpdmResponseResponseCode::SpdmResponseError => {
+                    self.read_spdm_peer_error(&mut reader)
+                }
                 _ => spdm_result_err!(EINVAL),
             },
             None => spdm_result_err!(EIO),
         }
     }
+
+    /// Check that the last verified CHALLENGE_AUTH signature covers the
+    /// exchanges in `required`, i.e. the data cached from them can be relied
+    /// upon. Exchanges that are repeated after CHALLENGE are not covered.
+    pub fn verify_transcript_coverage(&self, required: SpdmTranscriptCoverage) -> SpdmResult {
+        let verified_coverage = self.common.runtime_info.verified_coverage;
+        if !verified_coverage.contains(required) {
+            error!(
+                "!!! transcript coverage : {:?} not verified !!!\n",
+                required - verified_coverage
+            );
+            return spdm_result_err!(EFAULT);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{SpdmConfigInfo, SpdmProvisionInfo};
+    use crate::crypto::{SpdmAsymVerify, SpdmCryptoProvider};
+    use crate::error::SpdmErrorNum;
+    use crate::testlib::{self, ScriptedDeviceIo};
+    use core::convert::TryFrom;
+
+    // CHALLENGE_AUTH for slot 0 of an SPDM 1.1 SHA-384 and ECDSA P-384
+    // responder, with an empty opaque data and a signature starting with
+    // `signature`.
+    fn challenge_auth(cert_chain_hash: &[u8], signature: u8) -> [u8; 4 + 48 + 32 + 2 + 96] {
+        let mut response = [0u8; 4 + 48 + 32 + 2 + 96];
+        response[..4].copy_from_slice(&[0x11, 0x03, 0x00, 0x01]);
+        response[4..52].copy_from_slice(cert_chain_hash);
+        response[86] = signature;
+        response
+    }
+
+    // the result, the cached cert chain hash and the size of message_c after
+    // CHALLENGE is answered with `response`
+    fn challenge(response: &[u8]) -> (SpdmResult, Option<SpdmDigestStruct>, usize) {
+        let responses = [response];
+        let mut device_io = ScriptedDeviceIo {
+            responses: &responses,
+            sent: 0,
+        };
+        let mut context = testlib::new_requester(
+            &mut device_io,
+            SpdmConfigInfo::default(),
+            SpdmProvisionInfo::default(),
+        );
+        // accepts the signatures starting with 1
+        context.common.set_crypto_provider(SpdmCryptoProvider {
+            asym_verify: Some(SpdmAsymVerify {
+                verify_cb: |_, _, _, _, signature| {
+                    if signature.data[0] == 1 {
+                        Ok(())
+                    } else {
+                        spdm_result_err!(EFAULT)
+                    }
+                },
+            }),
+            ..Default::default()
+        });
+        context.common.connection_state = SpdmConnectionState::Negotiated;
+        let negotiate_info = &mut context.common.negotiate_info;
+        negotiate_info.spdm_version_sel = SpdmVersion::SpdmVersion11;
+        negotiate_info.base_hash_sel = SpdmBaseHashAlgo::TPM_ALG_SHA_384;
+        negotiate_info.base_asym_sel = SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P384;
+        let peer_info = &mut context.common.peer_info;
+        peer_info.peer_cert_chain_slot_id = 0;
+        peer_info.peer_cert_chain.cert_chain.data[..4 + 48 + 16]
+            .copy_from_slice(&[0x5a; 4 + 48 + 16]);
+        peer_info.peer_cert_chain.cert_chain.data_size = 4 + 48 + 16;
+
+        let result = context.send_receive_spdm_challenge(
+            0,
+            SpdmMeasurementSummaryHashType::SpdmMeasurementSummaryHashTypeNone,
+        );
+        (
+            result,
+            context.common.peer_info.peer_cert_chain_hash[0],
+            context.common.runtime_info.message_c.as_ref().len(),
+        )
+    }
+
+    #[test]
+    fn test_challenge_cert_chain_hash() {
+        let cert_chain_hash =
+            crate::crypto::hash::hash_all(SpdmBaseHashAlgo::TPM_ALG_SHA_384, &[0x5a; 4 + 48 + 16])
+                .unwrap();
+
+        let (result, cached_hash, _) = challenge(&challenge_auth(cert_chain_hash.as_ref(), 1));
+        assert!(result.is_ok());
+        assert_eq!(cached_hash.unwrap().as_ref(), cert_chain_hash.as_ref());
+
+        // not signed by the responder
+        let (result, cached_hash, _) = challenge(&challenge_auth(cert_chain_hash.as_ref(), 0));
+        assert_eq!(result.unwrap_err().num(), SpdmErrorNum::EFAULT);
+        assert!(cached_hash.is_none());
+
+        let mut other_hash = SpdmDigestStruct::try_from(cert_chain_hash.as_ref()).unwrap();
+        other_hash.data[0] ^= 1;
+        let (result, cached_hash, _) = challenge(&challenge_auth(other_hash.as_ref(), 1));
+        assert_eq!(result.unwrap_err().num(), SpdmErrorNum::EKEYREJECTED);
+        assert!(cached_hash.is_none());
+    }
+
+    #[test]
+    fn test_challenge_failure_resets_message_c() {
+        let cert_chain_hash =
+            crate::crypto::hash::hash_all(SpdmBaseHashAlgo::TPM_ALG_SHA_384, &[0x5a; 4 + 48 + 16])
+                .unwrap();
+        let mut other_hash = SpdmDigestStruct::try_from(cert_chain_hash.as_ref()).unwrap();
+        other_hash.data[0] ^= 1;
+
+        for response in [
+            // not signed by the responder
+            challenge_auth(cert_chain_hash.as_ref(), 0),
+            // another cert chain
+            challenge_auth(other_hash.as_ref(), 1),
+        ]
+        .iter()
+        {
+            let (result, _, message_c_size) = challenge(response);
+            assert!(result.is_err());
+            assert_eq!(message_c_size, 0);
+        }
+        // ERROR(InvalidRequest)
+        let (result, _, message_c_size) = challenge(&[0x11, 0x7F, 0x01, 0x00]);
+        assert!(result.is_err());
+        assert_eq!(message_c_size, 0);
+    }
 }
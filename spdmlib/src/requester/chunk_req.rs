@@ -0,0 +1,202 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+#![forbid(unsafe_code)]
+
+use crate::common::SpdmChunkStatus;
+use crate::error::SpdmResult;
+use crate::requester::*;
+
+// SPDM header, ChunkSeqNo, Reserved, ChunkSize
+const CHUNK_HEADER_SIZE: usize = 12;
+// LargeMessageSize, first chunk only
+const LARGE_MESSAGE_SIZE_FIELD_SIZE: usize = 4;
+
+impl<'a> RequesterContext<'a> {
+    // Largest message the responder accepts in one piece.
+    pub(crate) fn get_max_request_size(&self) -> usize {
+        let data_transfer_size = self.common.negotiate_info.rsp_data_transfer_size_sel as usize;
+        if data_transfer_size > config::MAX_SPDM_TRANSPORT_SIZE {
+            config::MAX_SPDM_TRANSPORT_SIZE
+        } else {
+            data_transfer_size
+        }
+    }
+
+    // The handle of the held response if `response` is ERROR(LargeResponse).
+    pub(crate) fn get_large_response_handle(&mut self, response: &[u8]) -> Option<u8> {
+        let mut reader = Reader::init(response);
+        let message_header = SpdmMessageHeader::read(&mut reader)?;
+        if message_header.request_response_code != SpdmResponseResponseCode::SpdmResponseError {
+            return None;
+        }
+        let error = SpdmErrorResponsePayload::spdm_read(&mut self.common, &mut reader)?;
+        match error.extended_data {
+            SpdmErrorResponseExtData::SpdmErrorExtDataLargeResponse(extended_data) => {
+                Some(extended_data.handle)
+            }
+            _ => None,
+        }
+    }
+
+    pub(crate) fn send_large_request(&mut self, large_request: &[u8]) -> SpdmResult {
+        let max_request_size = self.get_max_request_size();
+        if max_request_size <= CHUNK_HEADER_SIZE + LARGE_MESSAGE_SIZE_FIELD_SIZE {
+            return spdm_result_err!(EINVAL, "data transfer size too small for chunks");
+        }
+        let handle = self.common.chunk_context.new_handle();
+
+        let mut chunk_seq_no = 0u16;
+        let mut offset = 0usize;
+        loop {
+            let mut header_size = CHUNK_HEADER_SIZE;
+            if chunk_seq_no == 0 {
+                header_size += LARGE_MESSAGE_SIZE_FIELD_SIZE;
+            }
+            let mut chunk_size = large_request.len() - offset;
+            if chunk_size > max_request_size - header_size {
+                chunk_size = max_request_size - header_size;
+            }
+            let last_chunk = offset + chunk_size == large_request.len();
+
+            info!("send spdm chunk send\n");
+            let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+            let mut writer = Writer::init(&mut send_buffer);
+            let mut payload = SpdmChunkSendRequestPayload {
+                attributes: if last_chunk {
+                    SpdmChunkAttributes::LAST_CHUNK
+                } else {
+                    SpdmChunkAttributes::empty()
+                },
+                handle,
+                chunk_seq_no,
+                large_message_size: large_request.len() as u32,
+                chunk_size: chunk_size as u32,
+                ..Default::default()
+            };
+            payload.chunk[..chunk_size]
+                .copy_from_slice(&large_request[offset..(offset + chunk_size)]);
+            let request = SpdmMessage {
+                header: SpdmMessageHeader {
+                    version: self.common.negotiate_info.spdm_version_sel,
+                    request_response_code: SpdmResponseResponseCode::SpdmRequestChunkSend,
+                },
+                payload: SpdmMessagePayload::SpdmChunkSendRequest(payload),
+            };
+            let used = request.spdm_encode(&mut self.common, &mut writer)?;
+            self.send_message_unchunked(&send_buffer[..used])?;
+
+            let mut receive_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+            let used = self.receive_message_unchunked(&mut receive_buffer)?;
+            let mut reader = Reader::init(&receive_buffer[..used]);
+            let message_header = SpdmMessageHeader::read(&mut reader).ok_or(spdm_err!(EIO))?;
+            if message_header.request_response_code
+                != SpdmResponseResponseCode::SpdmResponseChunkSendAck
+            {
+                error!("!!! chunk_send_ack : fail !!!\n");
+                return spdm_result_err!(EFAULT);
+            }
+            let chunk_send_ack =
+                SpdmChunkSendAckResponsePayload::spdm_read(&mut self.common, &mut reader)
+                    .ok_or(spdm_err!(EIO))?;
+            if chunk_send_ack.handle != handle || chunk_send_ack.chunk_seq_no != chunk_seq_no {
+                error!("!!! chunk_send_ack : unexpected chunk !!!\n");
+                return spdm_result_err!(EFAULT);
+            }
+
+            let early_error = chunk_send_ack
+                .attributes
+                .contains(SpdmChunkSendAckAttributes::EARLY_ERROR_DETECTED);
+            if early_error || last_chunk {
+                // keep the response to the large request for receive_message()
+                let response_size = chunk_send_ack.response_size as usize;
+                if response_size == 0 {
+                    error!("!!! chunk_send_ack : no response !!!\n");
+                    return spdm_result_err!(EFAULT);
+                }
+                let chunk_context = &mut self.common.chunk_context;
+                chunk_context.start(SpdmChunkStatus::LargeResponse, response_size)?;
+                chunk_context.large_message[..response_size]
+                    .copy_from_slice(&chunk_send_ack.response[..response_size]);
+                chunk_context.transferred_size = response_size;
+                return Ok(());
+            }
+
+            offset += chunk_size;
+            chunk_seq_no = chunk_seq_no.checked_add(1).ok_or(spdm_err!(E2BIG))?;
+        }
+    }
+
+    pub(crate) fn receive_large_response(
+        &mut self,
+        handle: u8,
+        receive_buffer: &mut [u8],
+    ) -> SpdmResult<usize> {
+        let mut chunk_seq_no = 0u16;
+        let mut large_response_size = 0usize;
+        let mut offset = 0usize;
+        loop {
+            info!("send spdm chunk get\n");
+            let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+            let mut writer = Writer::init(&mut send_buffer);
+            let request = SpdmMessage {
+                header: SpdmMessageHeader {
+                    version: self.common.negotiate_info.spdm_version_sel,
+                    request_response_code: SpdmResponseResponseCode::SpdmRequestChunkGet,
+                },
+                payload: SpdmMessagePayload::SpdmChunkGetRequest(SpdmChunkGetRequestPayload {
+                    handle,
+                    chunk_seq_no,
+                }),
+            };
+            let used = request.spdm_encode(&mut self.common, &mut writer)?;
+            self.send_message_unchunked(&send_buffer[..used])?;
+
+            let mut chunk_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+            let used = self.receive_message_unchunked(&mut chunk_buffer)?;
+            let mut reader = Reader::init(&chunk_buffer[..used]);
+            let message_header = SpdmMessageHeader::read(&mut reader).ok_or(spdm_err!(EIO))?;
+            if message_header.request_response_code
+                != SpdmResponseResponseCode::SpdmResponseChunkResponse
+            {
+                error!("!!! chunk_response : fail !!!\n");
+                return spdm_result_err!(EFAULT);
+            }
+            let chunk_response = SpdmChunkResponsePayload::spdm_read(&mut self.common, &mut reader)
+                .ok_or(spdm_err!(EIO))?;
+            if chunk_response.handle != handle || chunk_response.chunk_seq_no != chunk_seq_no {
+                error!("!!! chunk_response : unexpected chunk !!!\n");
+                return spdm_result_err!(EFAULT);
+            }
+
+            if chunk_seq_no == 0 {
+                large_response_size = chunk_response.large_message_size as usize;
+                if large_response_size > receive_buffer.len() {
+                    return spdm_result_err!(ENOMEM);
+                }
+            }
+            let chunk_size = chunk_response.chunk_size as usize;
+            if offset + chunk_size > large_response_size {
+                return spdm_result_err!(EFAULT);
+            }
+            receive_buffer[offset..(offset + chunk_size)]
+                .copy_from_slice(&chunk_response.chunk[..chunk_size]);
+            offset += chunk_size;
+
+            if chunk_response
+                .attributes
+                .contains(SpdmChunkAttributes::LAST_CHUNK)
+            {
+                break;
+            }
+            chunk_seq_no = chunk_seq_no.checked_add(1).ok_or(spdm_err!(E2BIG))?;
+        }
+
+        if offset != large_response_size {
+            error!("!!! chunk_response : large response size mismatch !!!\n");
+            return spdm_result_err!(EFAULT);
+        }
+        Ok(offset)
+    }
+}
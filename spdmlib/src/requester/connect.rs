@@ -0,0 +1,121 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+#![forbid(unsafe_code)]
+
+use crate::error::{SpdmErrorNum, SpdmResult};
+use crate::requester::*;
+
+/// How connect() establishes the session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpdmConnectPolicy {
+    /// KEY_EXCHANGE with the cert chain of the slot only.
+    Cert,
+    /// PSK_EXCHANGE with the configured PSK only.
+    Psk,
+    /// KEY_EXCHANGE, or PSK_EXCHANGE if the responder does not support it or
+    /// the cert based exchanges fail for a reason other than a failed verification.
+    CertWithPskFallback,
+}
+
+/// Session mode connect() ended up with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpdmConnectMode {
+    Cert { slot_id: u8 },
+    Psk,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SpdmConnectInfo {
+    pub session_id: u32,
+    pub mode: SpdmConnectMode,
+    /// Exchanges covered by the verified CHALLENGE_AUTH signature. Empty for
+    /// PSK sessions and responders without CHAL_CAP.
+    pub verified_coverage: SpdmTranscriptCoverage,
+}
+
+impl<'a> RequesterContext<'a> {
+    fn is_cert_session_supported(&self) -> bool {
+        self.common
+            .negotiate_info
+            .req_capabilities_sel
+            .contains(SpdmRequestCapabilityFlags::CERT_CAP | SpdmRequestCapabilityFlags::KEY_EX_CAP)
+            && self.common.negotiate_info.rsp_capabilities_sel.contains(
+                SpdmResponseCapabilityFlags::CERT_CAP | SpdmResponseCapabilityFlags::KEY_EX_CAP,
+            )
+    }
+
+    fn is_psk_session_supported(&self) -> bool {
+        self.common
+            .negotiate_info
+            .req_capabilities_sel
+            .contains(SpdmRequestCapabilityFlags::PSK_CAP)
+            && self.common.negotiate_info.rsp_capabilities_sel.intersects(
+                SpdmResponseCapabilityFlags::PSK_CAP
+                    | SpdmResponseCapabilityFlags::PSK_CAP_WITH_CONTEXT,
+            )
+    }
+
+    // GET_DIGESTS, GET_CERTIFICATE and, if supported, CHALLENGE before KEY_EXCHANGE.
+    fn connect_with_cert(
+        &mut self,
+        slot_id: u8,
+        measurement_summary_hash_type: SpdmMeasurementSummaryHashType,
+    ) -> SpdmResult<SpdmConnectInfo> {
+        self.send_receive_spdm_digest()?;
+        self.send_receive_spdm_certificate(slot_id)?;
+        if self
+            .common
+            .negotiate_info
+            .rsp_capabilities_sel
+            .contains(SpdmResponseCapabilityFlags::CHAL_CAP)
+        {
+            self.send_receive_spdm_challenge(slot_id, measurement_summary_hash_type)?;
+        }
+        let verified_coverage = self.common.runtime_info.verified_coverage;
+        let session_id = self.start_session(false, slot_id, measurement_summary_hash_type)?;
+        Ok(SpdmConnectInfo {
+            session_id,
+            mode: SpdmConnectMode::Cert { slot_id },
+            verified_coverage,
+        })
+    }
+
+    /// VCA followed by a session with the responder, established as `policy`
+    /// allows. A failed verification is never retried with a PSK, so a
+    /// tampered responder can't downgrade the session.
+    pub fn connect(
+        &mut self,
+        policy: SpdmConnectPolicy,
+        slot_id: u8,
+        measurement_summary_hash_type: SpdmMeasurementSummaryHashType,
+    ) -> SpdmResult<SpdmConnectInfo> {
+        self.init_connection()?;
+
+        if policy != SpdmConnectPolicy::Psk {
+            let result = if self.is_cert_session_supported() {
+                self.connect_with_cert(slot_id, measurement_summary_hash_type)
+            } else {
+                spdm_result_err!(ENOSYS, "cert based session not supported")
+            };
+            match result {
+                Ok(info) => return Ok(info),
+                Err(e) if policy == SpdmConnectPolicy::Cert || e.num() == SpdmErrorNum::EFAULT => {
+                    return Err(e)
+                }
+                Err(e) => info!("!!! connect : cert session fail {:?}, try psk !!!\n", e),
+            }
+        }
+
+        if !self.is_psk_session_supported() {
+            return spdm_result_err!(ENOSYS, "psk session not supported");
+        }
+        let session_id = self.start_session(true, 0, measurement_summary_hash_type)?;
+        Ok(SpdmConnectInfo {
+            session_id,
+            mode: SpdmConnectMode::Psk,
+            verified_coverage: SpdmTranscriptCoverage::empty(),
+        })
+    }
+}
@@ -4,13 +4,29 @@
 
 #![forbid(unsafe_code)]
 
-use crate::common::{self, SpdmDeviceIo, SpdmTransportEncap};
+use crate::common::{
+    self, SpdmChunkStatus, SpdmDeviceIo, SpdmMessageBuffer, SpdmTransportBuffer, SpdmTransportEncap,
+};
 use crate::config;
-use crate::error::SpdmResult;
+use crate::error::{SpdmErrorNum, SpdmResult};
 use crate::msgs::*;
+use crate::requester::SpdmPeerError;
+use crate::trace::{self, SpdmMessageDirection};
+use codec::{Codec, Writer};
 
 pub struct RequesterContext<'a> {
     pub common: common::SpdmContext<'a>,
+    // sent again if its response times out
+    last_request: SpdmTransportBuffer,
+    last_request_size: usize,
+    // how long the response to the last request is waited for, 0 blocks
+    response_timeout_us: usize,
+    // sent again if it is answered with ERROR(Busy)
+    pub(crate) last_message: SpdmMessageBuffer,
+    pub(crate) last_message_size: usize,
+    pub(crate) last_message_session_id: Option<u32>,
+    pub(crate) last_request_code: SpdmResponseResponseCode,
+    pub(crate) last_peer_error: Option<SpdmPeerError>,
 }
 
 impl<'a> RequesterContext<'a> {
@@ -20,13 +36,21 @@ impl<'a> RequesterContext<'a> {
         config_info: common::SpdmConfigInfo,
         provision_info: common::SpdmProvisionInfo,
     ) -> Self {
+        let mut common =
+            common::SpdmContext::new(device_io, transport_encap, config_info, provision_info);
+        let last_request = common.take_transport_buffer();
+        let last_message = common.take_message_buffer();
+        let response_timeout_us = common.config_info.receive_timeout_us;
         RequesterContext {
-            common: common::SpdmContext::new(
-                device_io,
-                transport_encap,
-                config_info,
-                provision_info,
-            ),
+            common,
+            last_request,
+            last_request_size: 0,
+            response_timeout_us,
+            last_message,
+            last_message_size: 0,
+            last_message_session_id: None,
+            last_request_code: SpdmResponseResponseCode::default(),
+            last_peer_error: None,
         }
     }
 
@@ -42,6 +66,17 @@ impl<'a> RequesterContext<'a> {
         self.send_receive_spdm_algorithm()
     }
 
+    /// Start the connection over with GET_VERSION, GET_CAPABILITIES and
+    /// NEGOTIATE_ALGORITHMS. The sessions and transcripts are dropped on
+    /// both sides, the peer's certificate chains and digests are kept.
+    pub fn reset_connection(&mut self) -> SpdmResult {
+        self.init_connection()
+    }
+
+    /// Set up a session with KEY_EXCHANGE and FINISH, or with PSK_EXCHANGE
+    /// and PSK_FINISH if `use_psk`. A PSK session resumes the last
+    /// KEY_EXCHANGE session with its resumption PSK if there is one, and
+    /// falls back to the configured PSK if the responder doesn't know it.
     pub fn start_session(
         &mut self,
         use_psk: bool,
@@ -49,31 +84,60 @@ impl<'a> RequesterContext<'a> {
         measurement_summary_hash_type: SpdmMeasurementSummaryHashType,
     ) -> SpdmResult<u32> {
         if !use_psk {
-            let result =
-                self.send_receive_spdm_key_exchange(slot_id, measurement_summary_hash_type);
-            if let Ok(session_id) = result {
-                let result = self.send_receive_spdm_finish(session_id);
-                if result.is_ok() {
-                    Ok(session_id)
-                } else {
-                    spdm_result_err!(EIO)
-                }
-            } else {
-                spdm_result_err!(EIO)
-            }
-        } else {
-            let result = self.send_receive_spdm_psk_exchange(measurement_summary_hash_type);
-            if let Ok(session_id) = result {
-                let result = self.send_receive_spdm_psk_finish(session_id);
-                if result.is_ok() {
-                    Ok(session_id)
-                } else {
-                    spdm_result_err!(EIO)
+            // the errors are passed on, a failed verification is EFAULT
+            let session_id =
+                self.send_receive_spdm_key_exchange(slot_id, measurement_summary_hash_type)?;
+            self.send_receive_spdm_encapsulated_request(session_id)?;
+            self.send_receive_spdm_finish(session_id)?;
+            return Ok(session_id);
+        }
+
+        let resumption_psk = self
+            .common
+            .peer_info
+            .resumption_psk
+            .as_ref()
+            .and_then(|resumption_psk| {
+                self.common
+                    .get_resumption_psk(resumption_psk.psk_hint.as_ref())
+            });
+        if let Some(resumption_psk) = resumption_psk {
+            match self.start_psk_session(
+                measurement_summary_hash_type,
+                resumption_psk.psk_hint.as_ref(),
+            ) {
+                // e.g. the responder was reset
+                Err(e)
+                    if e.num() == SpdmErrorNum::ENOENT
+                        && self.common.config_info.psk.data_size != 0 =>
+                {
+                    info!("resumption psk unknown to the responder\n");
+                    self.common.peer_info.resumption_psk = None;
                 }
-            } else {
-                spdm_result_err!(EIO)
+                result => return result,
             }
         }
+        let psk_hint = self.common.config_info.psk_hint;
+        self.start_psk_session(measurement_summary_hash_type, psk_hint.as_ref())
+    }
+
+    fn start_psk_session(
+        &mut self,
+        measurement_summary_hash_type: SpdmMeasurementSummaryHashType,
+        psk_hint: &[u8],
+    ) -> SpdmResult<u32> {
+        let mut psk_context = [0u8; config::MAX_SPDM_PSK_CONTEXT_SIZE];
+        let psk_context_size = self.common.negotiate_info.base_hash_sel.get_size() as usize;
+        self.common
+            .crypto_provider
+            .get_random(&mut psk_context[..psk_context_size])?;
+        let session_id = self.send_receive_spdm_psk_exchange(
+            measurement_summary_hash_type,
+            psk_hint,
+            &psk_context[..psk_context_size],
+        )?;
+        self.send_receive_spdm_psk_finish(session_id)?;
+        Ok(session_id)
     }
 
     pub fn end_session(&mut self, session_id: u32) -> SpdmResult {
@@ -81,57 +145,253 @@ impl<'a> RequesterContext<'a> {
         Ok(())
     }
 
+    /// Encode `request` into `buffer`. Nothing is sent and the transcript
+    /// is not updated. Returns ENOMEM if `buffer` is too small.
+    pub(crate) fn encode_request(
+        &mut self,
+        request: &SpdmMessage,
+        buffer: &mut [u8],
+    ) -> SpdmResult<usize> {
+        let mut encode_buffer = self.common.take_transport_buffer();
+        let mut writer = Writer::init(&mut encode_buffer);
+        let result = request
+            .spdm_encode(&mut self.common, &mut writer)
+            .and_then(|used| {
+                if used > buffer.len() {
+                    spdm_result_err!(ENOMEM)
+                } else {
+                    buffer[..used].copy_from_slice(&encode_buffer[..used]);
+                    Ok(used)
+                }
+            });
+        self.common.put_transport_buffer(encode_buffer);
+        result
+    }
+
+    /// Send a request. Requests larger than the responder's DataTransferSize
+    /// are sent with CHUNK_SEND if both sides support chunking.
     pub fn send_message(&mut self, send_buffer: &[u8]) -> SpdmResult {
-        let mut transport_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
-        let used =
-            self.common
-                .transport_encap
-                .encap(&send_buffer[..], &mut transport_buffer, false)?;
-        self.common.device_io.send(&transport_buffer[..used])
+        debug!("send {}\n", SpdmMessageSummary(send_buffer));
+        trace::on_message(SpdmMessageDirection::Send, None, send_buffer);
+        self.record_request(None, send_buffer);
+        if self.common.is_chunk_cap_negotiated() && send_buffer.len() > self.get_max_request_size()
+        {
+            // not sent again as a whole
+            self.last_message_size = 0;
+            return self.send_large_request(send_buffer);
+        }
+        self.send_message_unchunked(send_buffer)
+    }
+
+    // Remember the request to send it again if it is answered with
+    // ERROR(Busy), and to tell which request an ERROR was returned for.
+    fn record_request(&mut self, session_id: Option<u32>, send_buffer: &[u8]) {
+        self.last_request_code = SpdmMessageHeader::read_bytes(send_buffer)
+            .map(|header| header.request_response_code)
+            .unwrap_or_default();
+        self.last_message_session_id = session_id;
+        self.response_timeout_us = self.get_response_timeout_us(send_buffer);
+        self.last_message_size = 0;
+        if self.common.config_info.busy_retry_count != 0
+            && send_buffer.len() <= self.last_message.len()
+        {
+            self.last_message[..send_buffer.len()].copy_from_slice(send_buffer);
+            self.last_message_size = send_buffer.len();
+        }
+    }
+
+    /// How long the response to `send_buffer` is waited for: RTT + CT for
+    /// the requests the responder signs or derives keys for, RTT + ST1 for
+    /// the others, or receive_timeout_us if transport_rtt_us is 0.
+    /// RESPOND_IF_READY keeps the timeout of the request it retrieves.
+    fn get_response_timeout_us(&self, send_buffer: &[u8]) -> usize {
+        let rtt = self.common.config_info.transport_rtt_us;
+        if rtt == 0 {
+            return self.common.config_info.receive_timeout_us;
+        }
+        let is_cryptographic = match self.last_request_code {
+            SpdmResponseResponseCode::SpdmRequestResponseIfReady => {
+                return self.response_timeout_us
+            }
+            SpdmResponseResponseCode::SpdmRequestChallenge
+            | SpdmResponseResponseCode::SpdmRequestKeyExchange
+            | SpdmResponseResponseCode::SpdmRequestFinish
+            | SpdmResponseResponseCode::SpdmRequestPskExchange
+            | SpdmResponseResponseCode::SpdmRequestPskFinish => true,
+            SpdmResponseResponseCode::SpdmRequestGetMeasurements => {
+                // Param1 of GET_MEASUREMENTS
+                matches!(send_buffer.get(2), Some(attributes)
+                    if SpdmMeasurementeAttributes::from_bits_truncate(*attributes)
+                        .contains(SpdmMeasurementeAttributes::INCLUDE_SIGNATURE))
+            }
+            _ => false,
+        };
+        if !is_cryptographic {
+            return rtt.saturating_add(common::SPDM_ST1_US);
+        }
+        // CT is 2^CTExponent us
+        let ct = 1usize
+            .checked_shl(self.common.negotiate_info.rsp_ct_exponent_sel as u32)
+            .unwrap_or(usize::MAX);
+        rtt.saturating_add(ct)
+    }
+
+    pub(crate) fn send_message_unchunked(&mut self, send_buffer: &[u8]) -> SpdmResult {
+        let mut transport_buffer = self.common.take_transport_buffer();
+        let result = self
+            .common
+            .transport_encap
+            .encap(send_buffer, &mut transport_buffer, false)
+            .and_then(|used| self.send_transport_message(&transport_buffer[..used]));
+        self.common.put_transport_buffer(transport_buffer);
+        result
     }
 
     pub fn send_secured_message(&mut self, session_id: u32, send_buffer: &[u8]) -> SpdmResult {
-        let mut app_buffer = [0u8; config::MAX_SPDM_MESSAGE_BUFFER_SIZE];
-        let used = self
+        debug!(
+            "send {} in session {:08x}\n",
+            SpdmMessageSummary(send_buffer),
+            session_id
+        );
+        trace::on_message(SpdmMessageDirection::Send, Some(session_id), send_buffer);
+        self.record_request(Some(session_id), send_buffer);
+        let mut app_buffer = self.common.take_message_buffer();
+        let result = self
             .common
             .transport_encap
-            .encap_app(send_buffer, &mut app_buffer)?;
+            .encap_app(send_buffer, &mut app_buffer)
+            .and_then(|used| self.send_secured_app_buffer(session_id, &app_buffer[..used]));
+        self.common.put_message_buffer(app_buffer);
+        result
+    }
+
+    // Send the encoded application data `app_buffer` in the session.
+    pub(crate) fn send_secured_app_buffer(
+        &mut self,
+        session_id: u32,
+        app_buffer: &[u8],
+    ) -> SpdmResult {
+        let mut encoded_send_buffer = self.common.take_message_buffer();
+        let mut transport_buffer = self.common.take_transport_buffer();
+        let result = self.send_secured_app_buffer_with(
+            session_id,
+            app_buffer,
+            &mut encoded_send_buffer,
+            &mut transport_buffer,
+        );
+        self.common.put_transport_buffer(transport_buffer);
+        self.common.put_message_buffer(encoded_send_buffer);
+        result
+    }
 
+    fn send_secured_app_buffer_with(
+        &mut self,
+        session_id: u32,
+        app_buffer: &[u8],
+        encoded_send_buffer: &mut [u8],
+        transport_buffer: &mut [u8],
+    ) -> SpdmResult {
         let spdm_session = self
             .common
             .get_session_via_id(session_id)
             .ok_or(spdm_err!(EINVAL))?;
 
-        let mut encoded_send_buffer = [0u8; config::MAX_SPDM_MESSAGE_BUFFER_SIZE];
-        let encode_size = spdm_session.encode_spdm_secured_message(
-            &app_buffer[0..used],
-            &mut encoded_send_buffer,
-            true,
-        )?;
+        let encode_size =
+            spdm_session.encode_spdm_secured_message(app_buffer, encoded_send_buffer, true)?;
 
-        let mut transport_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
         let used = self.common.transport_encap.encap(
             &encoded_send_buffer[..encode_size],
-            &mut transport_buffer,
+            transport_buffer,
             true,
         )?;
-        self.common.device_io.send(&transport_buffer[..used])
+        self.send_transport_message(&transport_buffer[..used])
+    }
+
+    fn send_transport_message(&mut self, transport_buffer: &[u8]) -> SpdmResult {
+        if self.common.config_info.receive_retry_count != 0 {
+            let size = transport_buffer.len().min(self.last_request.len());
+            self.last_request[..size].copy_from_slice(&transport_buffer[..size]);
+            self.last_request_size = size;
+        }
+        self.common.send_transport_message(transport_buffer)
+    }
+
+    /// Receive a transport message. If nothing is received within the
+    /// response timeout, the last request is sent again, receive_retry_count
+    /// times at most, before it fails with ETIMEDOUT.
+    fn receive_transport_message(&mut self, transport_buffer: &mut [u8]) -> SpdmResult<usize> {
+        let mut retry_count = 0u8;
+        loop {
+            match self
+                .common
+                .receive_transport_message_timeout(transport_buffer, self.response_timeout_us)
+            {
+                Ok(used) => return Ok(used),
+                Err(0) if self.response_timeout_us != 0 => {
+                    if retry_count >= self.common.config_info.receive_retry_count {
+                        error!("!!! receive_message : timeout !!!\n");
+                        return spdm_result_err!(ETIMEDOUT);
+                    }
+                    retry_count += 1;
+                    info!("resend request after timeout\n");
+                    self.common
+                        .send_transport_message(&self.last_request[..self.last_request_size])?;
+                }
+                Err(_) => return spdm_result_err!(EIO),
+            }
+        }
     }
 
+    /// Receive a response. A response that the responder holds back with
+    /// ERROR(LargeResponse) is retrieved with CHUNK_GET, and one that is not
+    /// ready yet with RESPOND_IF_READY.
     pub fn receive_message(&mut self, receive_buffer: &mut [u8]) -> SpdmResult<usize> {
+        let used = if self.common.chunk_context.status == SpdmChunkStatus::LargeResponse {
+            // returned in the last CHUNK_SEND_ACK
+            let large_response = self.common.chunk_context.get_large_message();
+            if large_response.len() > receive_buffer.len() {
+                self.common.chunk_context.reset();
+                return spdm_result_err!(ENOMEM);
+            }
+            receive_buffer[..large_response.len()].copy_from_slice(large_response);
+            let used = large_response.len();
+            self.common.chunk_context.reset();
+            used
+        } else {
+            let used = self.receive_message_unchunked(receive_buffer)?;
+            let used = self.handle_response_not_ready(None, receive_buffer, used)?;
+            self.handle_response_busy(None, receive_buffer, used)?
+        };
+
+        debug!("receive {}\n", SpdmMessageSummary(&receive_buffer[..used]));
+        trace::on_message(SpdmMessageDirection::Receive, None, &receive_buffer[..used]);
+
+        if !self.common.is_chunk_cap_negotiated() {
+            return Ok(used);
+        }
+        match self.get_large_response_handle(&receive_buffer[..used]) {
+            Some(handle) => self.receive_large_response(handle, receive_buffer),
+            None => Ok(used),
+        }
+    }
+
+    pub(crate) fn receive_message_unchunked(
+        &mut self,
+        receive_buffer: &mut [u8],
+    ) -> SpdmResult<usize> {
         info!("receive_message!\n");
 
-        let mut transport_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
-        let used = self
-            .common
-            .device_io
-            .receive(&mut transport_buffer)
-            .map_err(|_| spdm_err!(EIO))?;
-        let (used, secured_message) = self
-            .common
-            .transport_encap
-            .decap(&transport_buffer[..used], receive_buffer)?;
+        let mut transport_buffer = self.common.take_transport_buffer();
+        let result = self
+            .receive_transport_message(&mut transport_buffer)
+            .and_then(|used| {
+                self.common
+                    .transport_encap
+                    .decap(&transport_buffer[..used], receive_buffer)
+            });
+        self.common.put_transport_buffer(transport_buffer);
 
+        let (used, secured_message) = result?;
         if secured_message {
             return spdm_result_err!(EFAULT);
         }
@@ -139,25 +399,79 @@ impl<'a> RequesterContext<'a> {
         Ok(used)
     }
 
+    /// Receive a response in the session, retried with RESPOND_IF_READY
+    /// while it is not ready.
     pub fn receive_secured_message(
         &mut self,
         session_id: u32,
         receive_buffer: &mut [u8],
+    ) -> SpdmResult<usize> {
+        let used = self.receive_secured_message_once(session_id, receive_buffer)?;
+        let used = self.handle_response_not_ready(Some(session_id), receive_buffer, used)?;
+        let used = self.handle_response_busy(Some(session_id), receive_buffer, used)?;
+        debug!(
+            "receive {} in session {:08x}\n",
+            SpdmMessageSummary(&receive_buffer[..used]),
+            session_id
+        );
+        trace::on_message(
+            SpdmMessageDirection::Receive,
+            Some(session_id),
+            &receive_buffer[..used],
+        );
+        Ok(used)
+    }
+
+    pub(crate) fn receive_secured_message_once(
+        &mut self,
+        session_id: u32,
+        receive_buffer: &mut [u8],
+    ) -> SpdmResult<usize> {
+        let mut app_buffer = self.common.take_message_buffer();
+        let result = self
+            .receive_secured_app_buffer(session_id, &mut app_buffer)
+            .and_then(|decode_size| {
+                self.common
+                    .transport_encap
+                    .decap_app(&app_buffer[0..decode_size], receive_buffer)
+            });
+        self.common.put_message_buffer(app_buffer);
+        result
+    }
+
+    // Receive the application data of a secured message in the session.
+    pub(crate) fn receive_secured_app_buffer(
+        &mut self,
+        session_id: u32,
+        app_buffer: &mut [u8],
     ) -> SpdmResult<usize> {
         info!("receive_secured_message!\n");
 
-        let mut transport_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
-        let mut encoded_receive_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        let mut transport_buffer = self.common.take_transport_buffer();
+        let mut encoded_receive_buffer = self.common.take_transport_buffer();
+        let result = self.receive_secured_app_buffer_with(
+            session_id,
+            app_buffer,
+            &mut transport_buffer,
+            &mut encoded_receive_buffer,
+        );
+        self.common.put_transport_buffer(encoded_receive_buffer);
+        self.common.put_transport_buffer(transport_buffer);
+        result
+    }
 
-        let used = self
-            .common
-            .device_io
-            .receive(&mut transport_buffer)
-            .map_err(|_| spdm_err!(EIO))?;
+    fn receive_secured_app_buffer_with(
+        &mut self,
+        session_id: u32,
+        app_buffer: &mut [u8],
+        transport_buffer: &mut [u8],
+        encoded_receive_buffer: &mut [u8],
+    ) -> SpdmResult<usize> {
+        let used = self.receive_transport_message(transport_buffer)?;
         let (used, secured_message) = self
             .common
             .transport_encap
-            .decap(&transport_buffer[..used], &mut encoded_receive_buffer)?;
+            .decap(&transport_buffer[..used], encoded_receive_buffer)?;
 
         if !secured_message {
             return spdm_result_err!(EFAULT);
@@ -168,18 +482,105 @@ impl<'a> RequesterContext<'a> {
             .get_session_via_id(session_id)
             .ok_or(spdm_err!(EINVAL))?;
 
-        let mut app_buffer = [0u8; config::MAX_SPDM_MESSAGE_BUFFER_SIZE];
-        let decode_size = spdm_session.decode_spdm_secured_message(
-            &encoded_receive_buffer[..used],
-            &mut app_buffer,
-            false,
-        )?;
+        spdm_session.decode_spdm_secured_message(&encoded_receive_buffer[..used], app_buffer, false)
+    }
+}
 
-        let used = self
-            .common
-            .transport_encap
-            .decap_app(&app_buffer[0..decode_size], receive_buffer)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{SpdmConfigInfo, SpdmProvisionInfo};
+    use crate::error::SpdmErrorNum;
+    use crate::testlib;
 
-        Ok(used)
+    // Never answers, records the timeouts it is asked to wait for.
+    #[derive(Default)]
+    struct SilentDeviceIo {
+        timeouts: Vec<usize>,
+    }
+
+    impl SpdmDeviceIo for SilentDeviceIo {
+        fn send(&mut self, _buffer: &[u8]) -> SpdmResult {
+            Ok(())
+        }
+
+        fn receive(&mut self, _buffer: &mut [u8]) -> Result<usize, usize> {
+            Err(0)
+        }
+
+        fn receive_with_timeout(
+            &mut self,
+            _buffer: &mut [u8],
+            timeout_us: usize,
+        ) -> Result<usize, usize> {
+            self.timeouts.push(timeout_us);
+            Err(0)
+        }
+
+        fn flush_all(&mut self) -> SpdmResult {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_response_timeout() {
+        let rtt = 1000;
+        let mut device_io = SilentDeviceIo::default();
+        let mut config_info = SpdmConfigInfo {
+            transport_rtt_us: rtt,
+            receive_retry_count: 1,
+            ..SpdmConfigInfo::default()
+        };
+        config_info.spdm_version[0] = SpdmVersion::SpdmVersion10;
+        let mut context =
+            testlib::new_requester(&mut device_io, config_info, SpdmProvisionInfo::default());
+
+        let result = context.send_receive_spdm_version();
+        assert_eq!(result.unwrap_err().num(), SpdmErrorNum::ETIMEDOUT);
+
+        context.common.negotiate_info.rsp_ct_exponent_sel = 20;
+        let ct = 1 << 20;
+        context.record_request(None, &[0x12, 0x83, 0x00, 0x00]);
+        assert_eq!(context.response_timeout_us, rtt + ct);
+        // RESPOND_IF_READY waits as long as the request it retrieves
+        context.record_request(None, &[0x12, 0xFF, 0x83, 0x01]);
+        assert_eq!(context.response_timeout_us, rtt + ct);
+        // GET_MEASUREMENTS with and without a signature
+        context.record_request(None, &[0x12, 0xE0, 0x01, 0xFF]);
+        assert_eq!(context.response_timeout_us, rtt + ct);
+        context.record_request(None, &[0x12, 0xE0, 0x00, 0xFF]);
+        assert_eq!(context.response_timeout_us, rtt + common::SPDM_ST1_US);
+
+        // the first receive and the one after the request is sent again
+        assert_eq!(device_io.timeouts, [rtt + common::SPDM_ST1_US; 2]);
+    }
+
+    #[test]
+    fn test_unknown_session() {
+        let mut device_io = SilentDeviceIo::default();
+        let mut context = testlib::new_requester(
+            &mut device_io,
+            SpdmConfigInfo::default(),
+            SpdmProvisionInfo::default(),
+        );
+        context.common.negotiate_info.base_hash_sel = SpdmBaseHashAlgo::TPM_ALG_SHA_384;
+
+        assert_eq!(
+            context
+                .send_receive_spdm_finish(0xFFFE_FFFE)
+                .unwrap_err()
+                .num(),
+            SpdmErrorNum::EINVAL
+        );
+        assert_eq!(
+            context
+                .send_receive_spdm_psk_finish(0xFFFE_FFFE)
+                .unwrap_err()
+                .num(),
+            SpdmErrorNum::EINVAL
+        );
+        assert!(context
+            .send_receive_spdm_key_update(0xFFFE_FFFE, SpdmKeyUpdateOperation::SpdmUpdateAllKeys)
+            .is_err());
     }
 }
@@ -0,0 +1,307 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+#![forbid(unsafe_code)]
+
+use crate::error::SpdmResult;
+use crate::requester::*;
+
+impl<'a> RequesterContext<'a> {
+    // Answer the encapsulated request with the requester cert chain in slot 0.
+    fn encode_encap_response(&mut self, request: &[u8], buffer: &mut [u8]) -> SpdmResult<usize> {
+        let mut reader = Reader::init(request);
+        let request_code = SpdmMessageHeader::read(&mut reader).map(|h| h.request_response_code);
+        let my_cert_chain = self.common.runtime_info.my_cert_chain[0];
+
+        let payload = match (request_code, my_cert_chain) {
+            (Some(SpdmResponseResponseCode::SpdmRequestGetDigests), Some(my_cert_chain)) => {
+                debug!("!!! encap get_digests !!!\n");
+                let base_hash_sel = self.common.negotiate_info.base_hash_sel;
+                match self
+                    .common
+                    .crypto_provider
+                    .hash_all(base_hash_sel, my_cert_chain.as_ref())
+                {
+                    Some(cert_chain_hash) => {
+                        let mut digests = [SpdmDigestStruct::default(); SPDM_MAX_SLOT_NUMBER];
+                        digests[0] = cert_chain_hash;
+                        Some((
+                            SpdmResponseResponseCode::SpdmResponseDigests,
+                            SpdmMessagePayload::SpdmDigestsResponse(SpdmDigestsResponsePayload {
+                                slot_mask: 0x1,
+                                slot_count: 1u8,
+                                digests,
+                            }),
+                        ))
+                    }
+                    None => None,
+                }
+            }
+            (Some(SpdmResponseResponseCode::SpdmRequestGetCertificate), Some(my_cert_chain)) => {
+                match SpdmGetCertificateRequestPayload::spdm_read(&mut self.common, &mut reader) {
+                    Some(get_certificate)
+                        if get_certificate.slot_id == 0
+                            && get_certificate.offset < my_cert_chain.data_size =>
+                    {
+                        debug!("!!! encap get_certificate : {:02x?}\n", get_certificate);
+                        let offset = get_certificate.offset;
+                        let mut length = get_certificate.length;
+                        if length > config::MAX_SPDM_CERT_PORTION_LEN as u16 {
+                            length = config::MAX_SPDM_CERT_PORTION_LEN as u16;
+                        }
+                        if length > my_cert_chain.data_size - offset {
+                            length = my_cert_chain.data_size - offset;
+                        }
+
+                        let mut cert_chain = [0u8; config::MAX_SPDM_CERT_PORTION_LEN];
+                        cert_chain[..(length as usize)].copy_from_slice(
+                            &my_cert_chain.data
+                                [(offset as usize)..(offset as usize + length as usize)],
+                        );
+                        Some((
+                            SpdmResponseResponseCode::SpdmResponseCertificate,
+                            SpdmMessagePayload::SpdmCertificateResponse(
+                                SpdmCertificateResponsePayload {
+                                    slot_id: 0,
+                                    portion_length: length,
+                                    remainder_length: my_cert_chain.data_size - (offset + length),
+                                    cert_chain,
+                                },
+                            ),
+                        ))
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+
+        let (response_code, payload) = match payload {
+            Some(payload) => payload,
+            None => {
+                error!(
+                    "!!! encap request : {:02x?} unsupported !!!\n",
+                    request_code
+                );
+                let error_code = match (request_code, my_cert_chain) {
+                    (Some(SpdmResponseResponseCode::SpdmRequestGetDigests), None)
+                    | (Some(SpdmResponseResponseCode::SpdmRequestGetCertificate), None) => {
+                        SpdmErrorCode::SpdmErrorUnspecified
+                    }
+                    (Some(SpdmResponseResponseCode::SpdmRequestGetCertificate), Some(_)) => {
+                        SpdmErrorCode::SpdmErrorInvalidRequest
+                    }
+                    _ => SpdmErrorCode::SpdmErrorUnsupportedRequest,
+                };
+                (
+                    SpdmResponseResponseCode::SpdmResponseError,
+                    SpdmMessagePayload::SpdmErrorResponse(SpdmErrorResponsePayload {
+                        error_code,
+                        error_data: 0,
+                        extended_data: SpdmErrorResponseExtData::SpdmErrorExtDataNone(
+                            SpdmErrorResponseNoneExtData {},
+                        ),
+                    }),
+                )
+            }
+        };
+
+        let mut writer = Writer::init(buffer);
+        let response = SpdmMessage {
+            header: SpdmMessageHeader {
+                version: self.common.negotiate_info.spdm_version_sel,
+                request_response_code: response_code,
+            },
+            payload,
+        };
+        response.spdm_encode(&mut self.common, &mut writer)
+    }
+
+    fn send_receive_spdm_get_encapsulated_request(
+        &mut self,
+        session_id: u32,
+    ) -> SpdmResult<SpdmEncapsulatedRequestPayload> {
+        info!("send spdm get encapsulated request\n");
+        let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        let mut writer = Writer::init(&mut send_buffer);
+        let request = SpdmMessage {
+            header: SpdmMessageHeader {
+                version: self.common.negotiate_info.spdm_version_sel,
+                request_response_code: SpdmResponseResponseCode::SpdmRequestGetEncapsulatedRequest,
+            },
+            payload: SpdmMessagePayload::SpdmGetEncapsulatedRequest(
+                SpdmGetEncapsulatedRequestPayload {},
+            ),
+        };
+        let used = request.spdm_encode(&mut self.common, &mut writer)?;
+        self.send_secured_message(session_id, &send_buffer[..used])?;
+
+        let mut receive_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        let used = self.receive_secured_message(session_id, &mut receive_buffer)?;
+        let mut reader = Reader::init(&receive_buffer[..used]);
+        match SpdmMessageHeader::read(&mut reader) {
+            Some(message_header) => match message_header.request_response_code {
+                SpdmResponseResponseCode::SpdmResponseEncapsulatedRequest => {
+                    let encap_request =
+                        SpdmEncapsulatedRequestPayload::spdm_read(&mut self.common, &mut reader);
+                    if let Some(encap_request) = encap_request {
+                        debug!("!!! encap_request : {:02x?}\n", encap_request.request_id);
+                        Ok(encap_request)
+                    } else {
+                        error!("!!! encap_request : fail !!!\n");
+                        spdm_result_err!(EFAULT)
+                    }
+                }
+                SpdmResponseResponseCode::SpdmResponseError => {
+                    self.read_spdm_peer_error(&mut reader)
+                }
+                _ => spdm_result_err!(EINVAL),
+            },
+            None => spdm_result_err!(EIO),
+        }
+    }
+
+    // Deliver the response to the encapsulated request `request_id`.
+    fn send_receive_spdm_deliver_encapsulated_response(
+        &mut self,
+        session_id: u32,
+        request_id: u8,
+        response: &[u8],
+    ) -> SpdmResult<SpdmEncapsulatedResponseAckPayload> {
+        info!("send spdm deliver encapsulated response\n");
+        let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        let mut writer = Writer::init(&mut send_buffer);
+        let mut payload = SpdmDeliverEncapsulatedResponsePayload {
+            request_id,
+            response_size: response.len() as u32,
+            ..Default::default()
+        };
+        payload.response[..response.len()].copy_from_slice(response);
+        let request = SpdmMessage {
+            header: SpdmMessageHeader {
+                version: self.common.negotiate_info.spdm_version_sel,
+                request_response_code:
+                    SpdmResponseResponseCode::SpdmRequestDeliverEncapsulatedResponse,
+            },
+            payload: SpdmMessagePayload::SpdmDeliverEncapsulatedResponse(payload),
+        };
+        let used = request.spdm_encode(&mut self.common, &mut writer)?;
+        self.send_secured_message(session_id, &send_buffer[..used])?;
+
+        let mut receive_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        let used = self.receive_secured_message(session_id, &mut receive_buffer)?;
+        let mut reader = Reader::init(&receive_buffer[..used]);
+        match SpdmMessageHeader::read(&mut reader) {
+            Some(message_header) => match message_header.request_response_code {
+                SpdmResponseResponseCode::SpdmResponseEncapsulatedResponseAck => {
+                    let encap_ack = SpdmEncapsulatedResponseAckPayload::spdm_read(
+                        &mut self.common,
+                        &mut reader,
+                    );
+                    if let Some(encap_ack) = encap_ack {
+                        debug!(
+                            "!!! encap_response_ack : {:02x?} {:02x?}\n",
+                            encap_ack.request_id, encap_ack.payload_type
+                        );
+                        Ok(encap_ack)
+                    } else {
+                        error!("!!! encap_response_ack : fail !!!\n");
+                        spdm_result_err!(EFAULT)
+                    }
+                }
+                SpdmResponseResponseCode::SpdmResponseError => {
+                    self.read_spdm_peer_error(&mut reader)
+                }
+                _ => spdm_result_err!(EINVAL),
+            },
+            None => spdm_result_err!(EIO),
+        }
+    }
+
+    /// Answer the encapsulated requests of the responder, either after
+    /// KEY_EXCHANGE asked for mutual authentication or in an established session.
+    pub fn send_receive_spdm_encapsulated_request(&mut self, session_id: u32) -> SpdmResult {
+        let session = self
+            .common
+            .get_session_via_id(session_id)
+            .ok_or(spdm_err!(EINVAL))?;
+        let mut_auth_requested = session.get_mut_auth_requested();
+        let established =
+            session.get_session_state() == crate::session::SpdmSessionState::SpdmSessionEstablished;
+        if established && !self.common.is_mut_auth_cap_negotiated() {
+            return spdm_result_err!(EPERM, "encapsulated requests not negotiated");
+        }
+
+        let (mut request_id, mut request_size, mut request) = if established
+            || mut_auth_requested
+                .contains(SpdmKeyExchangeMutAuthAttributes::MUT_AUTH_REQ_WITH_ENCAP_REQUEST)
+        {
+            let encap_request = self.send_receive_spdm_get_encapsulated_request(session_id)?;
+            (
+                encap_request.request_id,
+                encap_request.request_size as usize,
+                encap_request.request,
+            )
+        } else if mut_auth_requested
+            .contains(SpdmKeyExchangeMutAuthAttributes::MUT_AUTH_REQ_WITH_GET_DIGESTS)
+        {
+            // GET_DIGESTS is implied, answered with request ID 0
+            let mut request = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+            let mut writer = Writer::init(&mut request);
+            let get_digests = SpdmMessage {
+                header: SpdmMessageHeader {
+                    version: self.common.negotiate_info.spdm_version_sel,
+                    request_response_code: SpdmResponseResponseCode::SpdmRequestGetDigests,
+                },
+                payload: SpdmMessagePayload::SpdmGetDigestsRequest(SpdmGetDigestsRequestPayload {}),
+            };
+            let request_size = get_digests.spdm_encode(&mut self.common, &mut writer)?;
+            (0u8, request_size, request)
+        } else {
+            // the requester cert chain is known to the responder
+            return Ok(());
+        };
+
+        loop {
+            let mut response = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+            let response_size =
+                self.encode_encap_response(&request[..request_size], &mut response)?;
+
+            let encap_ack = self.send_receive_spdm_deliver_encapsulated_response(
+                session_id,
+                request_id,
+                &response[..response_size],
+            )?;
+            if self.common.negotiate_info.spdm_version_sel.get_u8()
+                >= SpdmVersion::SpdmVersion12.get_u8()
+                && encap_ack.ack_request_id != request_id
+            {
+                error!("!!! encap_response_ack : unexpected request id !!!\n");
+                return spdm_result_err!(EFAULT);
+            }
+
+            match encap_ack.payload_type {
+                SpdmEncapsulatedResponseAckPayloadType::SpdmEncapsulatedPayloadAbsent => {
+                    return Ok(());
+                }
+                SpdmEncapsulatedResponseAckPayloadType::SpdmEncapsulatedPayloadReqSlotNumber => {
+                    // only slot 0 is provisioned
+                    if encap_ack.request_size == 0 || encap_ack.request[0] != 0 {
+                        error!("!!! encap_response_ack : unexpected slot !!!\n");
+                        return spdm_result_err!(EFAULT);
+                    }
+                    return Ok(());
+                }
+                SpdmEncapsulatedResponseAckPayloadType::SpdmEncapsulatedPayloadPresent => {
+                    request_id = encap_ack.request_id;
+                    request_size = encap_ack.request_size as usize;
+                    request = encap_ack.request;
+                }
+                SpdmEncapsulatedResponseAckPayloadType::Unknown(_) => {
+                    return spdm_result_err!(EFAULT);
+                }
+            }
+        }
+    }
+}
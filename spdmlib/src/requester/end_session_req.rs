@@ -8,22 +8,24 @@ use crate::error::SpdmResult;
 use crate::requester::*;
 
 impl<'a> RequesterContext<'a> {
-    pub fn send_receive_spdm_end_session(&mut self, session_id: u32) -> SpdmResult {
-        info!("send spdm end_session\n");
-        let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
-        let mut writer = Writer::init(&mut send_buffer);
-
+    /// Encode END_SESSION into `buffer` without sending it.
+    pub fn encode_spdm_end_session(&mut self, buffer: &mut [u8]) -> SpdmResult<usize> {
         let request = SpdmMessage {
             header: SpdmMessageHeader {
-                version: SpdmVersion::SpdmVersion11,
+                version: self.common.negotiate_info.spdm_version_sel,
                 request_response_code: SpdmResponseResponseCode::SpdmRequestEndSession,
             },
             payload: SpdmMessagePayload::SpdmEndSessionRequest(SpdmEndSessionRequestPayload {
                 end_session_request_attributes: SpdmEndSessionRequestAttributes::empty(),
             }),
         };
-        request.spdm_encode(&mut self.common, &mut writer);
-        let used = writer.used();
+        self.encode_request(&request, buffer)
+    }
+
+    pub fn send_receive_spdm_end_session(&mut self, session_id: u32) -> SpdmResult {
+        info!("send spdm end_session\n");
+        let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        let used = self.encode_spdm_end_session(&mut send_buffer)?;
 
         self.send_secured_message(session_id, &send_buffer[..used])?;
 
@@ -40,8 +42,9 @@ impl<'a> RequesterContext<'a> {
                     if let Some(end_session_rsp) = end_session_rsp {
                         debug!("!!! end_session rsp : {:02x?}\n", end_session_rsp);
 
-                        let session = self.common.get_session_via_id(session_id).unwrap();
-                        session.teardown(session_id)?;
+                        if let Some(session) = self.common.get_session_via_id(session_id) {
+                            session.teardown();
+                        }
 
                         Ok(())
                     } else {
@@ -49,6 +52,9 @@ impl<'a> RequesterContext<'a> {
                         spdm_result_err!(EFAULT)
                     }
                 }
+                SpdmResponseResponseCode::SpdmResponseError => {
+                    self.read_spdm_peer_error(&mut reader)
+                }
                 _ => spdm_result_err!(EINVAL),
             },
             None => spdm_result_err!(EIO),
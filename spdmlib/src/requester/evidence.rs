@@ -0,0 +1,37 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+#![forbid(unsafe_code)]
+
+use crate::requester::*;
+
+/// A received measurement block, identified for a CoRIM verifier.
+#[derive(Debug, Clone, Copy)]
+pub struct SpdmMeasurementEvidence<'a> {
+    pub identifier: SpdmMeasurementIdentifier<'static>,
+    pub block: &'a SpdmMeasurementBlockStructure,
+    /// A verified MEASUREMENTS signature covers the block.
+    pub signed: bool,
+}
+
+impl<'a> RequesterContext<'a> {
+    /// The measurement blocks received so far. `identify` returns the CoRIM
+    /// identifier of a block index, blocks it knows none for only have the
+    /// index as measurement key.
+    pub fn export_measurement_evidence(
+        &self,
+        identify: fn(index: u8) -> Option<SpdmMeasurementIdentifier<'static>>,
+    ) -> impl Iterator<Item = SpdmMeasurementEvidence<'_>> {
+        let peer_info = &self.common.peer_info;
+        let count = peer_info.peer_measurements.number_of_blocks as usize;
+        peer_info.peer_measurements.record[..count]
+            .iter()
+            .zip(peer_info.peer_measurements_signed.iter())
+            .map(move |(block, signed)| SpdmMeasurementEvidence {
+                identifier: identify(block.index).unwrap_or_default(),
+                block,
+                signed: *signed,
+            })
+    }
+}
@@ -7,48 +7,94 @@
 use crate::error::SpdmResult;
 use crate::requester::*;
 
-use crate::common::ManagedBuffer;
-
 impl<'a> RequesterContext<'a> {
-    pub fn send_receive_spdm_finish(&mut self, session_id: u32) -> SpdmResult {
-        info!("send spdm finish\n");
-        let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
-        let mut writer = Writer::init(&mut send_buffer);
+    /// Encode FINISH for the session `session_id` into `buffer` without
+    /// sending it. The signature, if mutual authentication is requested, and
+    /// the verify_data are placeholders of the negotiated size.
+    pub fn encode_spdm_finish(&mut self, session_id: u32, buffer: &mut [u8]) -> SpdmResult<usize> {
+        let session = self
+            .common
+            .get_session_via_id(session_id)
+            .ok_or(spdm_err!(EINVAL))?;
+        let mut_auth = !session.get_mut_auth_requested().is_empty();
+        let signature_size = if mut_auth {
+            self.common.get_req_asym_key_size() as usize
+        } else {
+            0
+        };
 
         let request = SpdmMessage {
             header: SpdmMessageHeader {
-                version: SpdmVersion::SpdmVersion11,
+                version: self.common.negotiate_info.spdm_version_sel,
                 request_response_code: SpdmResponseResponseCode::SpdmRequestFinish,
             },
             payload: SpdmMessagePayload::SpdmFinishRequest(SpdmFinishRequestPayload {
-                finish_request_attributes: SpdmFinishRequestAttributes::empty(),
-                req_slot_id: 0,
-                signature: SpdmSignatureStruct::default(),
-                verify_data: SpdmDigestStruct {
-                    data_size: self.common.negotiate_info.base_hash_sel.get_size(),
-                    data: [0xcc; SPDM_MAX_HASH_SIZE],
+                finish_request_attributes: if mut_auth {
+                    SpdmFinishRequestAttributes::SIGNATURE_INCLUDED
+                } else {
+                    SpdmFinishRequestAttributes::empty()
                 },
+                // only slot 0 is provisioned
+                req_slot_id: 0,
+                signature: SpdmSignatureStruct::placeholder(signature_size as u16),
+                verify_data: SpdmDigestStruct::placeholder(
+                    self.common.negotiate_info.base_hash_sel,
+                ),
             }),
         };
-        request.spdm_encode(&mut self.common, &mut writer);
-        let send_used = writer.used();
+        self.encode_request(&request, buffer)
+    }
+
+    pub fn send_receive_spdm_finish(&mut self, session_id: u32) -> SpdmResult {
+        let session = self
+            .common
+            .get_session_via_id(session_id)
+            .ok_or(spdm_err!(EINVAL))?;
+        let mut_auth = !session.get_mut_auth_requested().is_empty();
+        let signature_size = if mut_auth {
+            self.common.get_req_asym_key_size() as usize
+        } else {
+            0
+        };
+
+        info!("send spdm finish\n");
+        let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        let send_used = self.encode_spdm_finish(session_id, &mut send_buffer)?;
 
-        // generate HMAC with finished_key
         let base_hash_size = self.common.negotiate_info.base_hash_sel.get_size() as usize;
-        let temp_used = send_used - base_hash_size;
+        let temp_used = send_used - base_hash_size - signature_size;
 
-        let mut message_f = ManagedBuffer::default();
+        let session = self
+            .common
+            .get_session_via_id(session_id)
+            .ok_or(spdm_err!(EINVAL))?;
+        let message_k = session.runtime_info.message_k.try_clone()?;
+        let mut message_f = self.common.new_message_f(true, mut_auth, &message_k)?;
         message_f
             .append_message(&send_buffer[..temp_used])
             .ok_or(spdm_err!(ENOMEM))?;
 
-        let session = self.common.get_session_via_id(session_id).unwrap();
-        let message_k = session.runtime_info.message_k;
+        if mut_auth {
+            // sign with the requester key
+            let signature = self
+                .common
+                .generate_finish_req_signature(&message_k, &message_f)?;
+            // patch the message before send
+            send_buffer[temp_used..(temp_used + signature_size)]
+                .copy_from_slice(signature.as_ref());
+            message_f
+                .append_message(signature.as_ref())
+                .ok_or(spdm_err!(ENOMEM))?;
+        }
 
+        // generate HMAC with finished_key
         let transcript_data =
             self.common
-                .calc_req_transcript_data(false, &message_k, Some(&message_f))?;
-        let session = self.common.get_session_via_id(session_id).unwrap();
+                .calc_req_transcript_data(false, mut_auth, &message_k, Some(&message_f))?;
+        let session = self
+            .common
+            .get_session_via_id(session_id)
+            .ok_or(spdm_err!(EINVAL))?;
         let hmac = session.generate_hmac_with_request_finished_key(transcript_data.as_ref())?;
         message_f
             .append_message(hmac.as_ref())
@@ -93,10 +139,14 @@ impl<'a> RequesterContext<'a> {
 
                             let transcript_data = self.common.calc_req_transcript_data(
                                 false,
+                                mut_auth,
                                 &message_k,
                                 Some(&message_f),
                             )?;
-                            let session = self.common.get_session_via_id(session_id).unwrap();
+                            let session = self
+                                .common
+                                .get_session_via_id(session_id)
+                                .ok_or(spdm_err!(EINVAL))?;
                             if session
                                 .verify_hmac_with_response_finished_key(
                                     transcript_data.as_ref(),
@@ -105,7 +155,7 @@ impl<'a> RequesterContext<'a> {
                                 .is_err()
                             {
                                 error!("verify_hmac_with_response_finished_key fail");
-                                let _ = session.teardown(session_id);
+                                session.teardown();
                                 return spdm_result_err!(EFAULT);
                             } else {
                                 info!("verify_hmac_with_response_finished_key pass");
@@ -113,27 +163,33 @@ impl<'a> RequesterContext<'a> {
                             message_f
                                 .append_message(finish_rsp.verify_data.as_ref())
                                 .ok_or(spdm_err!(ENOMEM))?;
-                            session.runtime_info.message_f = message_f;
                         } else {
-                            let session = self.common.get_session_via_id(session_id).unwrap();
                             message_f
                                 .append_message(&receive_buffer[..receive_used])
                                 .ok_or(spdm_err!(ENOMEM))?;
-                            session.runtime_info.message_f = message_f;
                         }
 
                         // generate the data secret
                         let th2 = self.common.calc_req_transcript_hash(
                             false,
+                            mut_auth,
                             &message_k,
                             Some(&message_f),
                         )?;
                         debug!("!!! th2 : {:02x?}\n", th2.as_ref());
-                        let session = self.common.get_session_via_id(session_id).unwrap();
-                        session.generate_data_secret(&th2).unwrap();
+                        let session = self
+                            .common
+                            .get_session_via_id(session_id)
+                            .ok_or(spdm_err!(EINVAL))?;
+                        session.runtime_info.message_f = message_f;
+                        if session.generate_data_secret(&th2).is_err() {
+                            session.teardown();
+                            return spdm_result_err!(EFAULT);
+                        }
                         session.set_session_state(
                             crate::session::SpdmSessionState::SpdmSessionEstablished,
                         );
+                        self.common.save_resumption_psk(session_id);
 
                         Ok(())
                     } else {
@@ -141,6 +197,9 @@ impl<'a> RequesterContext<'a> {
                         spdm_result_err!(EFAULT)
                     }
                 }
+                SpdmResponseResponseCode::SpdmResponseError => {
+                    self.read_spdm_peer_error(&mut reader)
+                }
                 _ => spdm_result_err!(EINVAL),
             },
             None => spdm_result_err!(EIO),
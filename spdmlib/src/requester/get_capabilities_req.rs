@@ -8,23 +8,33 @@ use crate::error::SpdmResult;
 use crate::requester::*;
 
 impl<'a> RequesterContext<'a> {
-    pub fn send_receive_spdm_capability(&mut self) -> SpdmResult {
-        let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
-        let mut writer = Writer::init(&mut send_buffer);
+    /// Encode GET_CAPABILITIES with the configured capabilities into
+    /// `buffer` without sending it.
+    pub fn encode_spdm_capability(&mut self, buffer: &mut [u8]) -> SpdmResult<usize> {
         let request = SpdmMessage {
             header: SpdmMessageHeader {
-                version: SpdmVersion::SpdmVersion11,
+                version: self.common.negotiate_info.spdm_version_sel,
                 request_response_code: SpdmResponseResponseCode::SpdmRequestGetCapabilities,
             },
             payload: SpdmMessagePayload::SpdmGetCapabilitiesRequest(
                 SpdmGetCapabilitiesRequestPayload {
                     ct_exponent: self.common.config_info.req_ct_exponent,
                     flags: self.common.config_info.req_capabilities,
+                    data_transfer_size: self.common.config_info.data_transfer_size,
+                    max_spdm_msg_size: self.common.config_info.max_spdm_msg_size,
                 },
             ),
         };
-        request.spdm_encode(&mut self.common, &mut writer);
-        let used = writer.used();
+        self.encode_request(&request, buffer)
+    }
+
+    pub fn send_receive_spdm_capability(&mut self) -> SpdmResult {
+        if self.common.get_connection_state() != SpdmConnectionState::AfterVersion {
+            return spdm_result_err!(EPERM, "GET_CAPABILITIES only follows GET_VERSION");
+        }
+
+        let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        let used = self.encode_spdm_capability(&mut send_buffer)?;
 
         self.send_message(&send_buffer[..used])?;
 
@@ -57,6 +67,14 @@ impl<'a> RequesterContext<'a> {
                             self.common.config_info.req_capabilities;
                         self.common.negotiate_info.rsp_ct_exponent_sel = capabilities.ct_exponent;
                         self.common.negotiate_info.rsp_capabilities_sel = capabilities.flags;
+                        self.common.negotiate_info.req_data_transfer_size_sel =
+                            self.common.config_info.data_transfer_size;
+                        self.common.negotiate_info.req_max_spdm_msg_size_sel =
+                            self.common.config_info.max_spdm_msg_size;
+                        self.common.negotiate_info.rsp_data_transfer_size_sel =
+                            capabilities.data_transfer_size;
+                        self.common.negotiate_info.rsp_max_spdm_msg_size_sel =
+                            capabilities.max_spdm_msg_size;
 
                         if self
                             .common
@@ -68,12 +86,17 @@ impl<'a> RequesterContext<'a> {
                             return spdm_result_err!(ENOMEM);
                         }
 
+                        self.common
+                            .advance_connection_state(SpdmConnectionState::AfterCapabilities);
                         Ok(())
                     } else {
                         error!("!!! capabilities : fail !!!\n");
                         spdm_result_err!(EFAULT)
                     }
                 }
+                SpdmResponseResponseCode::SpdmResponseError => {
+                    self.read_spdm_peer_error(&mut reader)
+                }
                 _ => spdm_result_err!(EINVAL),
             },
             None => spdm_result_err!(EIO),
@@ -4,23 +4,22 @@
 
 #![forbid(unsafe_code)]
 
-use crate::crypto;
 use crate::error::SpdmResult;
 use crate::requester::*;
 
 impl<'a> RequesterContext<'a> {
-    fn send_receive_spdm_certificate_partial(
+    /// Encode GET_CERTIFICATE for `length` bytes of the slot `slot_id`
+    /// cert chain from `offset` into `buffer` without sending it.
+    pub fn encode_spdm_certificate(
         &mut self,
         slot_id: u8,
         offset: u16,
         length: u16,
-    ) -> SpdmResult<(u16, u16)> {
-        info!("send spdm certificate\n");
-        let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
-        let mut writer = Writer::init(&mut send_buffer);
+        buffer: &mut [u8],
+    ) -> SpdmResult<usize> {
         let request = SpdmMessage {
             header: SpdmMessageHeader {
-                version: SpdmVersion::SpdmVersion11,
+                version: self.common.negotiate_info.spdm_version_sel,
                 request_response_code: SpdmResponseResponseCode::SpdmRequestGetCertificate,
             },
             payload: SpdmMessagePayload::SpdmGetCertificateRequest(
@@ -31,8 +30,50 @@ impl<'a> RequesterContext<'a> {
                 },
             ),
         };
-        request.spdm_encode(&mut self.common, &mut writer);
-        let used = writer.used();
+        self.encode_request(&request, buffer)
+    }
+
+    /// Retrieve `length` bytes of the slot `slot_id` cert chain from
+    /// `offset` with one GET_CERTIFICATE, for a requester that streams the
+    /// cert chain to external storage instead of holding it in RAM. The
+    /// portion and the remainder length are returned as received, the
+    /// caller assembles and verifies the cert chain.
+    pub fn send_receive_spdm_certificate_partial(
+        &mut self,
+        slot_id: u8,
+        offset: u16,
+        length: u16,
+    ) -> SpdmResult<SpdmCertificateResponsePayload> {
+        self.common
+            .check_connection_state(SpdmConnectionState::Negotiated)?;
+        if slot_id as usize >= SPDM_MAX_SLOT_NUMBER
+            || length == 0
+            || length as usize > config::MAX_SPDM_CERT_PORTION_LEN
+        {
+            return spdm_result_err!(EINVAL);
+        }
+
+        // not a cert chain the library verified
+        let runtime_info = &mut self.common.runtime_info;
+        runtime_info
+            .transcript_coverage
+            .remove(SpdmTranscriptCoverage::CERTIFICATE);
+        runtime_info
+            .verified_coverage
+            .remove(SpdmTranscriptCoverage::CERTIFICATE);
+
+        self.get_certificate_portion(slot_id, offset, length)
+    }
+
+    fn get_certificate_portion(
+        &mut self,
+        slot_id: u8,
+        offset: u16,
+        length: u16,
+    ) -> SpdmResult<SpdmCertificateResponsePayload> {
+        info!("send spdm certificate\n");
+        let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        let used = self.encode_spdm_certificate(slot_id, offset, length, &mut send_buffer)?;
 
         self.send_message(&send_buffer[..used])?;
 
@@ -48,7 +89,8 @@ impl<'a> RequesterContext<'a> {
         }
 
         // Receive
-        let mut receive_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        // may be a large response retrieved in chunks
+        let mut receive_buffer = [0u8; config::MAX_SPDM_MESSAGE_BUFFER_SIZE];
         let used = self.receive_message(&mut receive_buffer)?;
 
         let mut reader = Reader::init(&receive_buffer[..used]);
@@ -61,14 +103,16 @@ impl<'a> RequesterContext<'a> {
                     if let Some(certificate) = certificate {
                         debug!("!!! certificate : {:02x?}\n", certificate);
 
-                        self.common.peer_info.peer_cert_chain.cert_chain.data[(offset as usize)
-                            ..(offset as usize + certificate.portion_length as usize)]
-                            .copy_from_slice(
-                                &certificate.cert_chain[0..(certificate.portion_length as usize)],
-                            );
-
-                        self.common.peer_info.peer_cert_chain.cert_chain.data_size =
-                            offset + certificate.portion_length;
+                        if certificate.slot_id != slot_id
+                            || certificate.portion_length > length
+                            || offset as usize + certificate.portion_length as usize
+                                > u16::MAX as usize
+                            || (certificate.portion_length == 0
+                                && certificate.remainder_length != 0)
+                        {
+                            error!("!!! certificate : invalid portion !!!\n");
+                            return spdm_result_err!(EINVAL);
+                        }
 
                         if self
                             .common
@@ -80,12 +124,15 @@ impl<'a> RequesterContext<'a> {
                             return spdm_result_err!(ENOMEM);
                         }
 
-                        Ok((certificate.portion_length, certificate.remainder_length))
+                        Ok(certificate)
                     } else {
                         error!("!!! certificate : fail !!!\n");
                         spdm_result_err!(EFAULT)
                     }
                 }
+                SpdmResponseResponseCode::SpdmResponseError => {
+                    self.read_spdm_peer_error(&mut reader)
+                }
                 _ => spdm_result_err!(EINVAL),
             },
             None => spdm_result_err!(EIO),
@@ -93,87 +140,107 @@ impl<'a> RequesterContext<'a> {
     }
 
     pub fn send_receive_spdm_certificate(&mut self, slot_id: u8) -> SpdmResult {
-        let mut offset = 0u16;
-        let mut length = config::MAX_SPDM_CERT_PORTION_LEN as u16;
+        self.send_receive_spdm_certificate_with_portion_length(
+            slot_id,
+            config::MAX_SPDM_CERT_PORTION_LEN as u16,
+        )
+    }
 
-        while length != 0 {
-            let result = self.send_receive_spdm_certificate_partial(slot_id, offset, length);
-            match result {
-                Ok((portion_length, remainder_length)) => {
-                    offset += portion_length;
-                    length = remainder_length;
-                    if length > config::MAX_SPDM_CERT_PORTION_LEN as u16 {
-                        length = config::MAX_SPDM_CERT_PORTION_LEN as u16;
-                    }
-                }
-                Err(_) => return spdm_result_err!(EIO),
-            }
+    /// Retrieve and verify the cert chain in `slot_id`, requesting at most
+    /// `portion_length` bytes at a time. The verified chain is cached per
+    /// slot and used by CHALLENGE, KEY_EXCHANGE and GET_MEASUREMENTS with
+    /// this slot.
+    pub fn send_receive_spdm_certificate_with_portion_length(
+        &mut self,
+        slot_id: u8,
+        portion_length: u16,
+    ) -> SpdmResult {
+        self.common
+            .check_connection_state(SpdmConnectionState::Negotiated)?;
+        if slot_id as usize >= SPDM_MAX_SLOT_NUMBER
+            || portion_length == 0
+            || portion_length as usize > config::MAX_SPDM_CERT_PORTION_LEN
+        {
+            return spdm_result_err!(EINVAL);
         }
 
-        // verify
-        if let Some(peer_cert_chain_data) = self.common.provision_info.peer_cert_chain_data {
-            //
-            // TBD: Verify cert chain
-            //
-            let data_size = self.common.peer_info.peer_cert_chain.cert_chain.data_size
-                - 4
-                - self.common.negotiate_info.base_hash_sel.get_size();
-            let mut data = [0u8; config::MAX_SPDM_CERT_CHAIN_DATA_SIZE];
-            data[0..(data_size as usize)].copy_from_slice(
-                &self.common.peer_info.peer_cert_chain.cert_chain.data[(4usize
-                    + self.common.negotiate_info.base_hash_sel.get_size() as usize)
-                    ..(self.common.peer_info.peer_cert_chain.cert_chain.data_size as usize)],
-            );
-            let runtime_peer_cert_chain_data = SpdmCertChainData { data_size, data };
-
-            let (root_cert_begin, root_cert_end) =
-                crypto::cert_operation::get_cert_from_cert_chain(
-                    &runtime_peer_cert_chain_data.data
-                        [..(runtime_peer_cert_chain_data.data_size as usize)],
-                    0,
-                )
-                .unwrap();
-            let root_cert = &runtime_peer_cert_chain_data.data[root_cert_begin..root_cert_end];
-            let root_hash =
-                crypto::hash::hash_all(self.common.negotiate_info.base_hash_sel, root_cert)
-                    .unwrap();
-            if root_hash.data[..(root_hash.data_size as usize)]
-                != self.common.peer_info.peer_cert_chain.cert_chain.data[4usize
-                    ..(4usize + self.common.negotiate_info.base_hash_sel.get_size() as usize)]
-            {
-                error!("root_hash - fail!\n");
-                return spdm_result_err!(EINVAL);
-            }
+        // the peer cert chain is replaced, so a verified signature doesn't cover it
+        let runtime_info = &mut self.common.runtime_info;
+        runtime_info
+            .transcript_coverage
+            .remove(SpdmTranscriptCoverage::CERTIFICATE);
+        runtime_info
+            .verified_coverage
+            .remove(SpdmTranscriptCoverage::CERTIFICATE);
+        self.common.peer_info.peer_cert_chains[slot_id as usize] = None;
 
-            if runtime_peer_cert_chain_data.data_size != peer_cert_chain_data.data_size {
-                error!("cert_chain size - fail!\n");
-                debug!(
-                    "provision cert_chain data size - {:?}\n",
-                    peer_cert_chain_data.data_size
-                );
-                debug!(
-                    "runtime cert_chain data size - {:?}\n",
-                    runtime_peer_cert_chain_data.data_size
-                );
-                return spdm_result_err!(EINVAL);
-            }
-            if runtime_peer_cert_chain_data.data != peer_cert_chain_data.data {
-                error!("cert_chain data - fail!\n");
+        let mut offset = 0u16;
+        let mut length = portion_length;
+
+        while length != 0 {
+            let certificate = match self.get_certificate_portion(slot_id, offset, length) {
+                Ok(certificate) => certificate,
+                Err(_) => return spdm_result_err!(EIO),
+            };
+            let portion_end = offset as usize + certificate.portion_length as usize;
+            if portion_end > config::MAX_SPDM_CERT_CHAIN_DATA_SIZE {
+                error!("!!! certificate : cert chain too big !!!\n");
                 return spdm_result_err!(EINVAL);
             }
+            let cert_chain = &mut self.common.peer_info.peer_cert_chain.cert_chain;
+            cert_chain.data[(offset as usize)..portion_end]
+                .copy_from_slice(&certificate.cert_chain[..(certificate.portion_length as usize)]);
+            cert_chain.data_size = portion_end as u16;
 
-            if crypto::cert_operation::verify_cert_chain(
-                &runtime_peer_cert_chain_data.data
-                    [..(runtime_peer_cert_chain_data.data_size as usize)],
-            )
-            .is_err()
-            {
-                error!("cert_chain verification - fail! - TBD later\n");
-                return spdm_result_err!(EFAULT);
-            }
-            info!("cert_chain verification - pass!\n");
+            offset = portion_end as u16;
+            length = certificate.remainder_length.min(portion_length);
         }
+        self.common.peer_info.peer_cert_chain_slot_id = slot_id;
+        self.common.peer_info.peer_cert_chain_hash[slot_id as usize] = None;
 
+        self.common.verify_peer_cert_chain()?;
+        self.common.peer_info.peer_cert_chains[slot_id as usize] =
+            Some(self.common.peer_info.peer_cert_chain.cert_chain);
+        self.common
+            .runtime_info
+            .transcript_coverage
+            .insert(SpdmTranscriptCoverage::CERTIFICATE);
+        self.common
+            .advance_connection_state(SpdmConnectionState::AfterCertificate);
         Ok(())
     }
+
+    /// Make the cached cert chain of `slot_id` the one signatures are
+    /// verified with.
+    pub(crate) fn load_peer_cert_chain(&mut self, slot_id: u8) {
+        let peer_info = &mut self.common.peer_info;
+        if slot_id == peer_info.peer_cert_chain_slot_id {
+            return;
+        }
+        if let Some(Some(cert_chain)) = peer_info.peer_cert_chains.get(slot_id as usize) {
+            peer_info.peer_cert_chain.cert_chain = *cert_chain;
+            peer_info.peer_cert_chain_slot_id = slot_id;
+        }
+    }
+
+    /// The DER leaf certificate of the verified cert chain in `slot_id`.
+    pub fn get_peer_leaf_cert(&self, slot_id: u8) -> SpdmResult<&[u8]> {
+        let cert_chain = self
+            .common
+            .peer_info
+            .peer_cert_chains
+            .get(slot_id as usize)
+            .and_then(|cert_chain| cert_chain.as_ref())
+            .ok_or(spdm_err!(ENOENT, "no cert chain retrieved for slot"))?;
+        let hash_size = self.common.negotiate_info.base_hash_sel.get_size() as usize;
+        let cert_chain = cert_chain
+            .as_slice()
+            .get((4 + hash_size)..)
+            .ok_or(spdm_err!(EINVAL))?;
+        let (leaf_cert_begin, leaf_cert_end) = self
+            .common
+            .crypto_provider
+            .get_cert_from_cert_chain(cert_chain, -1)?;
+        Ok(&cert_chain[leaf_cert_begin..leaf_cert_end])
+    }
 }
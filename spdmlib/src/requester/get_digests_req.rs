@@ -4,26 +4,102 @@
 
 #![forbid(unsafe_code)]
 
+use crate::common::constant_time_eq;
 use crate::error::SpdmResult;
 use crate::requester::*;
 
+/// How the digests of DIGESTS compare with the ones of the previous
+/// GET_DIGESTS, possibly of an earlier connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpdmDigestsStatus {
+    /// Nothing to compare with, or digests of another hash algorithm.
+    New,
+    Unchanged,
+    /// Slots whose cert chain is new, changed or removed. Their cached cert
+    /// chains are dropped, so GET_CERTIFICATE has to verify them again.
+    Changed {
+        slot_mask: u8,
+    },
+}
+
+fn compare_digests(
+    cached: Option<&SpdmPeerDigests>,
+    peer_digests: &SpdmPeerDigests,
+) -> SpdmDigestsStatus {
+    let cached = match cached {
+        Some(cached) if cached.base_hash_algo == peer_digests.base_hash_algo => cached,
+        _ => return SpdmDigestsStatus::New,
+    };
+    let mut slot_mask = cached.slot_mask ^ peer_digests.slot_mask;
+    for slot_id in 0..SPDM_MAX_SLOT_NUMBER as u8 {
+        if let (Some(cached_digest), Some(digest)) =
+            (cached.get_digest(slot_id), peer_digests.get_digest(slot_id))
+        {
+            if !constant_time_eq(cached_digest.as_ref(), digest.as_ref()) {
+                slot_mask |= 1 << slot_id;
+            }
+        }
+    }
+    if slot_mask == 0 {
+        SpdmDigestsStatus::Unchanged
+    } else {
+        SpdmDigestsStatus::Changed { slot_mask }
+    }
+}
+
 impl<'a> RequesterContext<'a> {
-    pub fn send_receive_spdm_digest(&mut self) -> SpdmResult {
-        info!("send spdm digest\n");
-        let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
-        let mut writer = Writer::init(&mut send_buffer);
+    /// Digests of the last DIGESTS, kept across connections.
+    pub fn get_peer_digests(&self) -> Option<&SpdmPeerDigests> {
+        self.common.runtime_info.peer_digests.as_ref()
+    }
+
+    /// Forget the digests, e.g. when another device is connected. The next
+    /// DIGESTS is reported as `SpdmDigestsStatus::New`.
+    pub fn clear_peer_digests(&mut self) {
+        self.common.runtime_info.peer_digests = None;
+    }
+
+    fn forget_peer_cert_chains(&mut self, slot_mask: u8) {
+        let peer_info = &mut self.common.peer_info;
+        for slot_id in 0..SPDM_MAX_SLOT_NUMBER {
+            if slot_mask & (1 << slot_id) == 0 {
+                continue;
+            }
+            peer_info.peer_cert_chains[slot_id] = None;
+            peer_info.peer_cert_chain_hash[slot_id] = None;
+            if peer_info.peer_cert_chain_slot_id as usize == slot_id {
+                peer_info.peer_cert_chain = SpdmCertChain::default();
+            }
+        }
+    }
+
+    /// Encode GET_DIGESTS into `buffer` without sending it.
+    pub fn encode_spdm_digest(&mut self, buffer: &mut [u8]) -> SpdmResult<usize> {
         let request = SpdmMessage {
             header: SpdmMessageHeader {
-                version: SpdmVersion::SpdmVersion11,
+                version: self.common.negotiate_info.spdm_version_sel,
                 request_response_code: SpdmResponseResponseCode::SpdmRequestGetDigests,
             },
             payload: SpdmMessagePayload::SpdmGetDigestsRequest(SpdmGetDigestsRequestPayload {}),
         };
-        request.spdm_encode(&mut self.common, &mut writer);
-        let used = writer.used();
+        self.encode_request(&request, buffer)
+    }
+
+    pub fn send_receive_spdm_digest(&mut self) -> SpdmResult<SpdmDigestsStatus> {
+        self.common
+            .check_connection_state(SpdmConnectionState::Negotiated)?;
+        info!("send spdm digest\n");
+        let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        let used = self.encode_spdm_digest(&mut send_buffer)?;
 
         self.send_message(&send_buffer[..used])?;
 
+        // a verified signature doesn't cover the new digests
+        self.common
+            .runtime_info
+            .verified_coverage
+            .remove(SpdmTranscriptCoverage::DIGESTS);
+
         // append message_b
         if self
             .common
@@ -36,7 +112,8 @@ impl<'a> RequesterContext<'a> {
         }
 
         // Receive
-        let mut receive_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        // may be a large response retrieved in chunks
+        let mut receive_buffer = [0u8; config::MAX_SPDM_MESSAGE_BUFFER_SIZE];
         let used = self.receive_message(&mut receive_buffer)?;
 
         let mut reader = Reader::init(&receive_buffer[..used]);
@@ -58,16 +135,105 @@ impl<'a> RequesterContext<'a> {
                         {
                             return spdm_result_err!(ENOMEM);
                         }
+                        self.common
+                            .runtime_info
+                            .transcript_coverage
+                            .insert(SpdmTranscriptCoverage::DIGESTS);
+                        self.common
+                            .advance_connection_state(SpdmConnectionState::AfterDigests);
+
+                        let mut peer_digests = SpdmPeerDigests {
+                            base_hash_algo: self.common.negotiate_info.base_hash_sel,
+                            slot_mask: digests.slot_mask,
+                            ..Default::default()
+                        };
+                        let mut received = digests.digests.iter();
+                        for slot_id in 0..SPDM_MAX_SLOT_NUMBER {
+                            if digests.slot_mask & (1 << slot_id) != 0 {
+                                if let Some(digest) = received.next() {
+                                    peer_digests.digests[slot_id] = *digest;
+                                }
+                            }
+                        }
+                        let status = compare_digests(
+                            self.common.runtime_info.peer_digests.as_ref(),
+                            &peer_digests,
+                        );
+                        if let SpdmDigestsStatus::Changed { slot_mask } = status {
+                            info!("peer cert chains changed, slot mask {:02x}\n", slot_mask);
+                            self.forget_peer_cert_chains(slot_mask);
+                        }
+                        self.common.runtime_info.peer_digests = Some(peer_digests);
 
-                        Ok(())
+                        Ok(status)
                     } else {
                         error!("!!! digests : fail !!!\n");
                         spdm_result_err!(EFAULT)
                     }
                 }
+                SpdmResponseResponseCode::SpdmResponseError => {
+                    self.read_spdm_peer_error(&mut reader)
+                }
                 _ => spdm_result_err!(EINVAL),
             },
             None => spdm_result_err!(EIO),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::convert::TryFrom;
+
+    fn peer_digests(slot_mask: u8, digest: u8) -> SpdmPeerDigests {
+        let mut peer_digests = SpdmPeerDigests {
+            base_hash_algo: SpdmBaseHashAlgo::TPM_ALG_SHA_384,
+            slot_mask,
+            ..Default::default()
+        };
+        for (slot_id, peer_digest) in peer_digests.digests.iter_mut().enumerate() {
+            if slot_mask & (1 << slot_id) != 0 {
+                *peer_digest = SpdmDigestStruct::try_from(&[digest; 48][..]).unwrap();
+            }
+        }
+        peer_digests
+    }
+
+    #[test]
+    fn test_compare_digests() {
+        let cached = peer_digests(0b0000_0011, 0x5a);
+        assert_eq!(compare_digests(None, &cached), SpdmDigestsStatus::New);
+        assert_eq!(
+            compare_digests(Some(&cached), &cached),
+            SpdmDigestsStatus::Unchanged
+        );
+
+        // slot 2 provisioned, slot 1 removed
+        assert_eq!(
+            compare_digests(Some(&cached), &peer_digests(0b0000_0101, 0x5a)),
+            SpdmDigestsStatus::Changed {
+                slot_mask: 0b0000_0110
+            }
+        );
+        // both cert chains replaced
+        assert_eq!(
+            compare_digests(Some(&cached), &peer_digests(0b0000_0011, 0xa5)),
+            SpdmDigestsStatus::Changed {
+                slot_mask: 0b0000_0011
+            }
+        );
+
+        // digests of another hash algorithm are not compared
+        let mut other_hash_algo = peer_digests(0b0000_0011, 0xa5);
+        other_hash_algo.base_hash_algo = SpdmBaseHashAlgo::TPM_ALG_SHA_256;
+        assert_eq!(
+            compare_digests(Some(&cached), &other_hash_algo),
+            SpdmDigestsStatus::New
+        );
+
+        assert_eq!(cached.get_digest(1).unwrap().as_ref(), &[0x5a; 48][..]);
+        assert!(cached.get_digest(2).is_none());
+        assert!(cached.get_digest(8).is_none());
+    }
+}
@@ -8,23 +8,68 @@ use crate::error::SpdmResult;
 use crate::requester::*;
 
 impl<'a> RequesterContext<'a> {
-    fn send_receive_spdm_measurement_record(
+    // Keep the latest block per index for verify_measurements(). Blocks of
+    // an older measurement generation than the one in `opaque` are dropped.
+    fn save_peer_measurements(
+        &mut self,
+        record: &SpdmMeasurementRecordStructure,
+        opaque: &SpdmOpaqueStruct,
+    ) -> SpdmResult {
+        let generation = self.common.decode_measurement_generation(opaque);
+        let peer_info = &mut self.common.peer_info;
+        if generation.is_some() && generation != peer_info.peer_measurement_generation {
+            if peer_info.peer_measurement_generation.is_some() {
+                info!("measurement generation changed, drop the saved blocks\n");
+            }
+            peer_info.peer_measurements = SpdmMeasurementRecordStructure::default();
+            peer_info.peer_measurements_signed = [false; config::MAX_SPDM_MEASUREMENT_BLOCK_COUNT];
+            peer_info.peer_measurement_generation = generation;
+        }
+
+        for block in record.record.iter().take(record.number_of_blocks as usize) {
+            let mut block_buffer = [0u8; SPDM_MAX_MEASUREMENT_BLOCK_SIZE];
+            let mut writer = Writer::init(&mut block_buffer);
+            let used = block.spdm_encode(&mut self.common, &mut writer)?;
+            self.common.emit_transcript(
+                SpdmTranscriptSegment::MeasurementBlock,
+                &block_buffer[..used],
+            );
+        }
+
+        let peer_info = &mut self.common.peer_info;
+        for block in record.record.iter().take(record.number_of_blocks as usize) {
+            let count = peer_info.peer_measurements.number_of_blocks as usize;
+            let i = match peer_info.peer_measurements.record[..count]
+                .iter()
+                .position(|b| b.index == block.index)
+            {
+                Some(i) => i,
+                None if count < config::MAX_SPDM_MEASUREMENT_BLOCK_COUNT => {
+                    peer_info.peer_measurements.number_of_blocks += 1;
+                    count
+                }
+                None => return spdm_result_err!(ENOMEM),
+            };
+            peer_info.peer_measurements.record[i] = *block;
+            peer_info.peer_measurements_signed[i] = false;
+        }
+        Ok(())
+    }
+
+    /// Encode GET_MEASUREMENTS into `buffer` without sending it.
+    pub fn encode_spdm_measurement(
         &mut self,
         measurement_attributes: SpdmMeasurementeAttributes,
         measurement_operation: SpdmMeasurementOperation,
         slot_id: u8,
-    ) -> SpdmResult<u8> {
-        info!("send spdm measurement\n");
-        let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
-        let mut writer = Writer::init(&mut send_buffer);
-
-        let nonce = [0xafu8; SPDM_NONCE_SIZE];
-        //let spdm_random = SpdmCryptoRandom {}; // TBD
-        //spdm_random.get_random (&mut nonce);
+        buffer: &mut [u8],
+    ) -> SpdmResult<usize> {
+        let mut nonce = [0u8; SPDM_NONCE_SIZE];
+        self.common.crypto_provider.get_random(&mut nonce)?;
 
         let request = SpdmMessage {
             header: SpdmMessageHeader {
-                version: SpdmVersion::SpdmVersion11,
+                version: self.common.negotiate_info.spdm_version_sel,
                 request_response_code: SpdmResponseResponseCode::SpdmRequestGetMeasurements,
             },
             payload: SpdmMessagePayload::SpdmGetMeasurementsRequest(
@@ -36,10 +81,51 @@ impl<'a> RequesterContext<'a> {
                 },
             ),
         };
-        request.spdm_encode(&mut self.common, &mut writer);
-        let used = writer.used();
+        self.encode_request(&request, buffer)
+    }
 
-        self.send_message(&send_buffer[..used])?;
+    // The unsigned exchanges accumulate in message_m (L1/L2) until a signed
+    // one is verified. A failed exchange ends the transcript as well, so the
+    // next signature only covers the exchanges after it.
+    fn send_receive_spdm_measurement_record(
+        &mut self,
+        session_id: Option<u32>,
+        measurement_attributes: SpdmMeasurementeAttributes,
+        measurement_operation: SpdmMeasurementOperation,
+        slot_id: u8,
+    ) -> SpdmResult<SpdmMeasurementsResponsePayload> {
+        let result = self.exchange_spdm_measurement_record(
+            session_id,
+            measurement_attributes,
+            measurement_operation,
+            slot_id,
+        );
+        if result.is_err() {
+            self.common.runtime_info.message_m.reset_message();
+        }
+        result
+    }
+
+    fn exchange_spdm_measurement_record(
+        &mut self,
+        session_id: Option<u32>,
+        measurement_attributes: SpdmMeasurementeAttributes,
+        measurement_operation: SpdmMeasurementOperation,
+        slot_id: u8,
+    ) -> SpdmResult<SpdmMeasurementsResponsePayload> {
+        info!("send spdm measurement\n");
+        let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        let used = self.encode_spdm_measurement(
+            measurement_attributes,
+            measurement_operation,
+            slot_id,
+            &mut send_buffer,
+        )?;
+
+        match session_id {
+            Some(session_id) => self.send_secured_message(session_id, &send_buffer[..used])?,
+            None => self.send_message(&send_buffer[..used])?,
+        }
 
         // append message_m
         if self
@@ -59,8 +145,12 @@ impl<'a> RequesterContext<'a> {
         }
 
         // Receive
-        let mut receive_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
-        let used = self.receive_message(&mut receive_buffer)?;
+        // may be a large response retrieved in chunks
+        let mut receive_buffer = [0u8; config::MAX_SPDM_MESSAGE_BUFFER_SIZE];
+        let used = match session_id {
+            Some(session_id) => self.receive_secured_message(session_id, &mut receive_buffer)?,
+            None => self.receive_message(&mut receive_buffer)?,
+        };
 
         let mut reader = Reader::init(&receive_buffer[..used]);
         match SpdmMessageHeader::read(&mut reader) {
@@ -99,6 +189,20 @@ impl<'a> RequesterContext<'a> {
                                 info!("verify_measurement_signature pass");
                             }
                             self.common.runtime_info.message_m.reset_message();
+
+                            // the signature covers the blocks received since the last one
+                            self.save_peer_measurements(
+                                &measurements.measurement_record,
+                                &measurements.opaque,
+                            )?;
+                            let peer_info = &mut self.common.peer_info;
+                            for signed in peer_info
+                                .peer_measurements_signed
+                                .iter_mut()
+                                .take(peer_info.peer_measurements.number_of_blocks as usize)
+                            {
+                                *signed = true;
+                            }
                         } else if self
                             .common
                             .runtime_info
@@ -107,50 +211,118 @@ impl<'a> RequesterContext<'a> {
                             .is_none()
                         {
                             return spdm_result_err!(ENOMEM);
+                        } else {
+                            self.save_peer_measurements(
+                                &measurements.measurement_record,
+                                &measurements.opaque,
+                            )?;
                         }
 
-                        match measurement_operation {
-                            SpdmMeasurementOperation::SpdmMeasurementQueryTotalNumber => {
-                                Ok(measurements.number_of_measurement)
-                            }
-                            SpdmMeasurementOperation::SpdmMeasurementRequestAll => {
-                                Ok(measurements.measurement_record.number_of_blocks)
-                            }
-                            _ => Ok(measurements.measurement_record.number_of_blocks),
-                        }
+                        Ok(measurements)
                     } else {
                         error!("!!! measurements : fail !!!\n");
                         spdm_result_err!(EFAULT)
                     }
                 }
+                SpdmResponseResponseCode::SpdmResponseError => {
+                    self.read_spdm_peer_error(&mut reader)
+                }
                 _ => spdm_result_err!(EINVAL),
             },
             None => spdm_result_err!(EIO),
         }
     }
 
+    /// GET_MEASUREMENTS of all blocks with a signature, returns the blocks
+    /// once the signature over L1/L2 is verified with the leaf cert of
+    /// `slot_id`.
+    pub fn send_receive_spdm_all_measurements(
+        &mut self,
+        slot_id: u8,
+    ) -> SpdmResult<SpdmMeasurementRecordStructure> {
+        self.send_receive_spdm_all_measurements_via_session(None, slot_id)
+    }
+
+    /// send_receive_spdm_all_measurements() in the established session
+    /// `session_id`.
+    pub fn send_receive_spdm_all_measurements_in_session(
+        &mut self,
+        session_id: u32,
+        slot_id: u8,
+    ) -> SpdmResult<SpdmMeasurementRecordStructure> {
+        self.send_receive_spdm_all_measurements_via_session(Some(session_id), slot_id)
+    }
+
+    fn send_receive_spdm_all_measurements_via_session(
+        &mut self,
+        session_id: Option<u32>,
+        slot_id: u8,
+    ) -> SpdmResult<SpdmMeasurementRecordStructure> {
+        self.common
+            .check_connection_state(SpdmConnectionState::Negotiated)?;
+        self.load_peer_cert_chain(slot_id);
+        let measurements = self.send_receive_spdm_measurement_record(
+            session_id,
+            SpdmMeasurementeAttributes::INCLUDE_SIGNATURE,
+            SpdmMeasurementOperation::SpdmMeasurementRequestAll,
+            slot_id,
+        )?;
+        Ok(measurements.measurement_record)
+    }
+
     pub fn send_receive_spdm_measurement(
         &mut self,
         measurement_operation: SpdmMeasurementOperation,
         slot_id: u8,
     ) -> SpdmResult {
+        self.send_receive_spdm_measurement_via_session(None, measurement_operation, slot_id)
+    }
+
+    /// GET_MEASUREMENTS in the established session `session_id`, for
+    /// responders that only reveal their measurements in a session.
+    pub fn send_receive_spdm_measurement_in_session(
+        &mut self,
+        session_id: u32,
+        measurement_operation: SpdmMeasurementOperation,
+        slot_id: u8,
+    ) -> SpdmResult {
+        self.send_receive_spdm_measurement_via_session(
+            Some(session_id),
+            measurement_operation,
+            slot_id,
+        )
+    }
+
+    fn send_receive_spdm_measurement_via_session(
+        &mut self,
+        session_id: Option<u32>,
+        measurement_operation: SpdmMeasurementOperation,
+        slot_id: u8,
+    ) -> SpdmResult {
+        self.common
+            .check_connection_state(SpdmConnectionState::Negotiated)?;
+        self.load_peer_cert_chain(slot_id);
         match measurement_operation {
             SpdmMeasurementOperation::SpdmMeasurementRequestAll => self
                 .send_receive_spdm_measurement_record(
+                    session_id,
                     SpdmMeasurementeAttributes::INCLUDE_SIGNATURE,
                     SpdmMeasurementOperation::SpdmMeasurementRequestAll,
                     slot_id,
                 )
                 .and(Ok(())),
             SpdmMeasurementOperation::SpdmMeasurementQueryTotalNumber => {
-                if let Ok(total_number) = self.send_receive_spdm_measurement_record(
+                if let Ok(measurements) = self.send_receive_spdm_measurement_record(
+                    session_id,
                     SpdmMeasurementeAttributes::empty(),
                     SpdmMeasurementOperation::SpdmMeasurementQueryTotalNumber,
                     slot_id,
                 ) {
+                    let total_number = measurements.number_of_measurement;
                     for block_i in 1..(total_number + 1) {
                         if self
                             .send_receive_spdm_measurement_record(
+                                session_id,
                                 if block_i == total_number {
                                     SpdmMeasurementeAttributes::INCLUDE_SIGNATURE
                                 } else {
@@ -171,6 +343,7 @@ impl<'a> RequesterContext<'a> {
             }
             SpdmMeasurementOperation::Unknown(index) => self
                 .send_receive_spdm_measurement_record(
+                    session_id,
                     SpdmMeasurementeAttributes::INCLUDE_SIGNATURE,
                     SpdmMeasurementOperation::Unknown(index as u8),
                     slot_id,
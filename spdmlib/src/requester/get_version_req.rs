@@ -8,9 +8,8 @@ use crate::error::SpdmResult;
 use crate::requester::*;
 
 impl<'a> RequesterContext<'a> {
-    pub fn send_receive_spdm_version(&mut self) -> SpdmResult {
-        let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
-        let mut writer = Writer::init(&mut send_buffer);
+    /// Encode GET_VERSION into `buffer` without sending it.
+    pub fn encode_spdm_version(&mut self, buffer: &mut [u8]) -> SpdmResult<usize> {
         let request = SpdmMessage {
             header: SpdmMessageHeader {
                 version: SpdmVersion::SpdmVersion10,
@@ -18,13 +17,17 @@ impl<'a> RequesterContext<'a> {
             },
             payload: SpdmMessagePayload::SpdmGetVersionRequest(SpdmGetVersionRequestPayload {}),
         };
-        request.spdm_encode(&mut self.common, &mut writer);
-        let used = writer.used();
+        self.encode_request(&request, buffer)
+    }
+
+    pub fn send_receive_spdm_version(&mut self) -> SpdmResult {
+        let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        let used = self.encode_spdm_version(&mut send_buffer)?;
 
         self.send_message(&send_buffer[..used])?;
 
-        // clear cache data
-        self.common.reset_runtime_info();
+        // the responder ends the sessions on GET_VERSION
+        self.common.reset_connection();
 
         // append message_a
         if self
@@ -61,12 +64,47 @@ impl<'a> RequesterContext<'a> {
                             return spdm_result_err!(ENOMEM);
                         }
 
+                        // select the highest version both sides support
+                        let mut spdm_version_sel: Option<SpdmVersion> = None;
+                        for peer_version in version
+                            .versions
+                            .iter()
+                            .take(version.version_number_entry_count as usize)
+                        {
+                            if let SpdmVersion::Unknown(_) = peer_version.version {
+                                continue;
+                            }
+                            if !self
+                                .common
+                                .config_info
+                                .spdm_version
+                                .contains(&peer_version.version)
+                            {
+                                continue;
+                            }
+                            match spdm_version_sel {
+                                Some(v) if v.get_u8() >= peer_version.version.get_u8() => {}
+                                _ => spdm_version_sel = Some(peer_version.version),
+                            }
+                        }
+                        match spdm_version_sel {
+                            Some(spdm_version_sel) => {
+                                self.common.negotiate_info.spdm_version_sel = spdm_version_sel
+                            }
+                            None => return spdm_result_err!(EINVAL, "no common SPDM version"),
+                        }
+
+                        self.common
+                            .advance_connection_state(SpdmConnectionState::AfterVersion);
                         Ok(())
                     } else {
                         error!("!!! version : fail !!!\n");
                         spdm_result_err!(EFAULT)
                     }
                 }
+                SpdmResponseResponseCode::SpdmResponseError => {
+                    self.read_spdm_peer_error(&mut reader)
+                }
                 _ => spdm_result_err!(EINVAL),
             },
             None => spdm_result_err!(EIO),
@@ -5,23 +5,51 @@
 #![forbid(unsafe_code)]
 
 use crate::error::SpdmResult;
+use crate::msgs::exchange;
 use crate::requester::*;
 
 impl<'a> RequesterContext<'a> {
-    pub fn send_receive_spdm_heartbeat(&mut self, session_id: u32) -> SpdmResult {
-        info!("send spdm heartbeat\n");
-        let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
-        let mut writer = Writer::init(&mut send_buffer);
-
+    /// Encode HEARTBEAT into `buffer` without sending it.
+    pub fn encode_spdm_heartbeat(&mut self, buffer: &mut [u8]) -> SpdmResult<usize> {
         let request = SpdmMessage {
             header: SpdmMessageHeader {
-                version: SpdmVersion::SpdmVersion11,
+                version: self.common.negotiate_info.spdm_version_sel,
                 request_response_code: SpdmResponseResponseCode::SpdmRequestHeartbeat,
             },
             payload: SpdmMessagePayload::SpdmHeartbeatRequest(SpdmHeartbeatRequestPayload {}),
         };
-        request.spdm_encode(&mut self.common, &mut writer);
-        let used = writer.used();
+        self.encode_request(&request, buffer)
+    }
+
+    /// Send HEARTBEAT if a heartbeat period has passed since the last
+    /// response in the session, which otherwise expires after twice the
+    /// period.
+    pub fn send_receive_spdm_heartbeat_if_due(&mut self, session_id: u32) -> SpdmResult {
+        let session = self
+            .common
+            .get_session_via_id(session_id)
+            .ok_or(spdm_err!(EINVAL))?;
+        if session.is_heartbeat_expired() {
+            error!("!!! session {:x} : heartbeat expired !!!\n", session_id);
+            session.teardown();
+            return spdm_result_err!(ETIMEDOUT, "heartbeat expired");
+        }
+        if !session.is_heartbeat_due() {
+            return Ok(());
+        }
+        self.send_receive_spdm_heartbeat(session_id)
+    }
+
+    pub fn send_receive_spdm_heartbeat(&mut self, session_id: u32) -> SpdmResult {
+        info!("send spdm heartbeat\n");
+        let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        let version = self.common.negotiate_info.spdm_version_sel;
+        let (used, pending_response) = exchange::encode_request(
+            &mut self.common,
+            version,
+            &SpdmHeartbeatRequestPayload {},
+            &mut send_buffer,
+        )?;
 
         self.send_secured_message(session_id, &send_buffer[..used])?;
 
@@ -29,23 +57,19 @@ impl<'a> RequesterContext<'a> {
         let mut receive_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
         let used = self.receive_secured_message(session_id, &mut receive_buffer)?;
 
-        let mut reader = Reader::init(&receive_buffer[..used]);
-        match SpdmMessageHeader::read(&mut reader) {
-            Some(message_header) => match message_header.request_response_code {
-                SpdmResponseResponseCode::SpdmResponseHeartbeatAck => {
-                    let heartbeat_rsp =
-                        SpdmHeartbeatResponsePayload::spdm_read(&mut self.common, &mut reader);
-                    if let Some(heartbeat_rsp) = heartbeat_rsp {
-                        debug!("!!! heartbeat rsp : {:02x?}\n", heartbeat_rsp);
-                        Ok(())
-                    } else {
-                        error!("!!! heartbeat : fail !!!\n");
-                        spdm_result_err!(EFAULT)
-                    }
-                }
-                _ => spdm_result_err!(EINVAL),
-            },
-            None => spdm_result_err!(EIO),
+        match pending_response.read_response(&mut self.common, &receive_buffer[..used]) {
+            Ok(exchange::SpdmExchangeResponse::Response(heartbeat_rsp)) => {
+                debug!("!!! heartbeat rsp : {:02x?}\n", heartbeat_rsp);
+                self.common.record_heartbeat(session_id);
+                Ok(())
+            }
+            Ok(exchange::SpdmExchangeResponse::Error(error)) => {
+                Err(self.record_spdm_peer_error(error))
+            }
+            Err(e) => {
+                error!("!!! heartbeat : fail !!!\n");
+                Err(e)
+            }
         }
     }
 }
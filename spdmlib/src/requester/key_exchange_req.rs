@@ -4,57 +4,89 @@
 
 #![forbid(unsafe_code)]
 
+use core::convert::TryFrom;
+
 use crate::error::SpdmResult;
 use crate::requester::*;
 
-use crate::common::ManagedBuffer;
-
-use crate::crypto;
+const KEY_EXCHANGE_REQ_SESSION_ID: u16 = 0xFFFE;
 
 impl<'a> RequesterContext<'a> {
-    pub fn send_receive_spdm_key_exchange(
+    // Our half of the ID of the next session. Stays the same until a session
+    // is set up, so the one sent in KEY_EXCHANGE is used for its response.
+    fn get_key_exchange_req_session_id(&self) -> SpdmResult<u16> {
+        self.common
+            .get_free_half_session_id(KEY_EXCHANGE_REQ_SESSION_ID, true)
+            .ok_or_else(|| spdm_err!(EBUSY, "too many sessions"))
+    }
+
+    // the session policy is only sent since SPDM 1.2
+    fn get_key_exchange_session_policy(&self) -> SpdmKeyExchangeSessionPolicy {
+        if self.common.negotiate_info.spdm_version_sel.get_u8()
+            >= SpdmVersion::SpdmVersion12.get_u8()
+        {
+            self.common.config_info.session_policy
+        } else {
+            SpdmKeyExchangeSessionPolicy::empty()
+        }
+    }
+
+    /// Encode KEY_EXCHANGE with the DHE public key `exchange` into `buffer`
+    /// without sending it.
+    pub fn encode_spdm_key_exchange(
         &mut self,
         slot_id: u8,
         measurement_summary_hash_type: SpdmMeasurementSummaryHashType,
-    ) -> SpdmResult<u32> {
-        info!("send spdm key exchange\n");
-
-        let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
-        let mut writer = Writer::init(&mut send_buffer);
-
-        let req_session_id = 0xFFFE;
-
-        let random = [0xafu8; SPDM_RANDOM_SIZE];
-        //let spdm_random = SpdmCryptoRandom {}; // TBD
-        //spdm_random.get_random (&mut nonce);
-
-        let (exchange, key_exchange_context) =
-            crypto::dhe::generate_key_pair(self.common.negotiate_info.dhe_sel)
-                .ok_or(spdm_err!(EFAULT))?;
-
-        debug!("!!! exchange data : {:02x?}\n", exchange);
-        let mut opaque = SpdmOpaqueStruct {
-            data_size: crate::common::OPAQUE_DATA_SUPPORT_VERSION.len() as u16,
-            ..Default::default()
-        };
-        opaque.data[..(opaque.data_size as usize)]
-            .copy_from_slice(crate::common::OPAQUE_DATA_SUPPORT_VERSION.as_ref());
+        exchange: &SpdmDheExchangeStruct,
+        buffer: &mut [u8],
+    ) -> SpdmResult<usize> {
+        let req_session_id = self.get_key_exchange_req_session_id()?;
+        let mut random = [0u8; SPDM_RANDOM_SIZE];
+        self.common.crypto_provider.get_random(&mut random)?;
+
+        let opaque = SpdmOpaqueStruct::try_from(self.common.get_opaque_data_support_version())?;
         let request = SpdmMessage {
             header: SpdmMessageHeader {
-                version: SpdmVersion::SpdmVersion11,
+                version: self.common.negotiate_info.spdm_version_sel,
                 request_response_code: SpdmResponseResponseCode::SpdmRequestKeyExchange,
             },
             payload: SpdmMessagePayload::SpdmKeyExchangeRequest(SpdmKeyExchangeRequestPayload {
                 slot_id,
                 measurement_summary_hash_type,
                 req_session_id,
+                session_policy: self.get_key_exchange_session_policy(),
                 random: SpdmRandomStruct { data: random },
-                exchange,
+                exchange: *exchange,
                 opaque,
             }),
         };
-        request.spdm_encode(&mut self.common, &mut writer);
-        let send_used = writer.used();
+        self.encode_request(&request, buffer)
+    }
+
+    pub fn send_receive_spdm_key_exchange(
+        &mut self,
+        slot_id: u8,
+        measurement_summary_hash_type: SpdmMeasurementSummaryHashType,
+    ) -> SpdmResult<u32> {
+        self.common
+            .check_connection_state(SpdmConnectionState::Negotiated)?;
+        info!("send spdm key exchange\n");
+        self.load_peer_cert_chain(slot_id);
+
+        let (exchange, key_exchange_context) = self
+            .common
+            .crypto_provider
+            .dhe_generate_key_pair(self.common.negotiate_info.dhe_sel)
+            .ok_or(spdm_err!(EFAULT))?;
+        debug!("!!! exchange data : {:02x?}\n", exchange);
+
+        let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        let send_used = self.encode_spdm_key_exchange(
+            slot_id,
+            measurement_summary_hash_type,
+            &exchange,
+            &mut send_buffer,
+        )?;
 
         self.send_message(&send_buffer[..send_used])?;
 
@@ -69,7 +101,8 @@ impl<'a> RequesterContext<'a> {
         }
 
         // Receive
-        let mut receive_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        // may be a large response retrieved in chunks
+        let mut receive_buffer = [0u8; config::MAX_SPDM_MESSAGE_BUFFER_SIZE];
         let receive_used = self.receive_message(&mut receive_buffer)?;
 
         let mut reader = Reader::init(&receive_buffer[..receive_used]);
@@ -86,19 +119,17 @@ impl<'a> RequesterContext<'a> {
                             &key_exchange_rsp.exchange
                         );
 
-                        let final_key = key_exchange_context
+                        let mut final_key = key_exchange_context
                             .compute_final_key(&key_exchange_rsp.exchange)
                             .ok_or(spdm_err!(EFAULT))?;
 
-                        debug!("!!! final_key : {:02x?}\n", final_key.as_ref());
-
                         // verify signature
                         let base_asym_size =
                             self.common.negotiate_info.base_asym_sel.get_size() as usize;
                         let base_hash_size =
                             self.common.negotiate_info.base_hash_sel.get_size() as usize;
 
-                        let mut message_k = ManagedBuffer::default();
+                        let mut message_k = self.common.new_message_k(true, false)?;
                         message_k
                             .append_message(&send_buffer[..send_used])
                             .ok_or(spdm_err!(ENOMEM))?;
@@ -127,25 +158,31 @@ impl<'a> RequesterContext<'a> {
                         // create session - generate the handshake secret (including finished_key)
                         let th1 = self
                             .common
-                            .calc_req_transcript_hash(false, &message_k, None)?;
+                            .calc_req_transcript_hash(false, false, &message_k, None)?;
                         debug!("!!! th1 : {:02x?}\n", th1.as_ref());
                         let base_hash_algo = self.common.negotiate_info.base_hash_sel;
                         let dhe_algo = self.common.negotiate_info.dhe_sel;
                         let aead_algo = self.common.negotiate_info.aead_sel;
                         let key_schedule_algo = self.common.negotiate_info.key_schedule_sel;
+                        let spdm_version = self.common.negotiate_info.spdm_version_sel;
                         let sequence_number_count =
                             self.common.transport_encap.get_sequence_number_count();
-                        let max_random_count = self.common.transport_encap.get_max_random_count();
+                        let max_random_count = self.common.get_max_random_count();
+                        let replay_window_size = self.common.config_info.replay_window_size;
+                        let random_padding_policy = self.common.config_info.random_padding_policy;
+                        let session_policy = self.get_key_exchange_session_policy();
 
+                        let req_session_id = self.get_key_exchange_req_session_id()?;
                         let session_id = ((req_session_id as u32) << 16)
                             + key_exchange_rsp.rsp_session_id as u32;
-                        let session = self
-                            .common
-                            .get_next_avaiable_session()
-                            .ok_or(spdm_err!(EINVAL))?;
-
-                        session.setup(session_id).unwrap();
+                        let mut session_guard = self.common.setup_session(session_id)?;
+                        let session = session_guard.session();
                         session.set_use_psk(false);
+                        session.set_slot_id(slot_id);
+                        session.set_mut_auth_requested(key_exchange_rsp.mut_auth_req);
+                        session.set_heartbeat_period(key_exchange_rsp.heartbeat_period);
+                        session.set_session_policy(session_policy);
+                        session.set_peer_opaque_data(&key_exchange_rsp.opaque);
 
                         session.set_crypto_param(
                             base_hash_algo,
@@ -153,18 +190,18 @@ impl<'a> RequesterContext<'a> {
                             aead_algo,
                             key_schedule_algo,
                         );
+                        session.set_spdm_version(spdm_version);
                         session.set_transport_param(sequence_number_count, max_random_count);
-                        session.set_dhe_secret(&final_key);
-                        session.generate_handshake_secret(&th1).unwrap();
+                        session.set_replay_window_size(replay_window_size);
+                        session.set_random_padding_policy(random_padding_policy);
+                        session.set_dhe_secret(&mut final_key)?;
+                        session.generate_handshake_secret(&th1)?;
 
                         // verify HMAC with finished_key
-                        let transcript_data = self
-                            .common
-                            .calc_req_transcript_data(false, &message_k, None)?;
-                        let session = self
-                            .common
-                            .get_session_via_id(session_id)
-                            .ok_or(spdm_err!(EINVAL))?;
+                        let transcript_data = session_guard
+                            .context()
+                            .calc_req_transcript_data(false, false, &message_k, None)?;
+                        let session = session_guard.session();
                         if session
                             .verify_hmac_with_response_finished_key(
                                 transcript_data.as_ref(),
@@ -173,7 +210,6 @@ impl<'a> RequesterContext<'a> {
                             .is_err()
                         {
                             error!("verify_hmac_with_response_finished_key fail");
-                            let _ = session.teardown(session_id);
                             return spdm_result_err!(EFAULT);
                         } else {
                             info!("verify_hmac_with_response_finished_key pass");
@@ -187,12 +223,15 @@ impl<'a> RequesterContext<'a> {
                             crate::session::SpdmSessionState::SpdmSessionHandshaking,
                         );
 
-                        Ok(session_id)
+                        Ok(session_guard.commit())
                     } else {
                         error!("!!! key_exchange : fail !!!\n");
                         spdm_result_err!(EFAULT)
                     }
                 }
+                SpdmResponseResponseCode::SpdmResponseError => {
+                    self.read_spdm_peer_error(&mut reader)
+                }
                 _ => spdm_result_err!(EINVAL),
             },
             None => spdm_result_err!(EIO),
@@ -8,19 +8,17 @@ use crate::error::SpdmResult;
 use crate::requester::*;
 
 impl<'a> RequesterContext<'a> {
-    fn send_receive_spdm_key_update_op(
+    /// Encode KEY_UPDATE into `buffer` without sending it. The session
+    /// keys are not updated.
+    pub fn encode_spdm_key_update(
         &mut self,
-        session_id: u32,
         key_update_operation: SpdmKeyUpdateOperation,
         tag: u8,
-    ) -> SpdmResult {
-        info!("send spdm key_update\n");
-        let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
-        let mut writer = Writer::init(&mut send_buffer);
-
+        buffer: &mut [u8],
+    ) -> SpdmResult<usize> {
         let request = SpdmMessage {
             header: SpdmMessageHeader {
-                version: SpdmVersion::SpdmVersion11,
+                version: self.common.negotiate_info.spdm_version_sel,
                 request_response_code: SpdmResponseResponseCode::SpdmRequestKeyUpdate,
             },
             payload: SpdmMessagePayload::SpdmKeyUpdateRequest(SpdmKeyUpdateRequestPayload {
@@ -28,68 +26,114 @@ impl<'a> RequesterContext<'a> {
                 tag,
             }),
         };
-        request.spdm_encode(&mut self.common, &mut writer);
-        let used = writer.used();
-
-        self.send_secured_message(session_id, &send_buffer[..used])?;
+        self.encode_request(&request, buffer)
+    }
 
-        // update key
-        let session = self.common.get_session_via_id(session_id).unwrap();
+    fn send_receive_spdm_key_update_op(
+        &mut self,
+        session_id: u32,
+        key_update_operation: SpdmKeyUpdateOperation,
+        tag: u8,
+    ) -> SpdmResult {
+        info!("send spdm key_update\n");
         let update_requester = key_update_operation == SpdmKeyUpdateOperation::SpdmUpdateSingleKey
             || key_update_operation == SpdmKeyUpdateOperation::SpdmUpdateAllKeys;
         let update_responder = key_update_operation == SpdmKeyUpdateOperation::SpdmUpdateAllKeys;
+
+        if update_requester && self.common.config_info.reverify_peer_cert_on_key_update {
+            if let Err(e) = self
+                .common
+                .reverify_session_peer_cert_chain(session_id, true)
+            {
+                if let Some(session) = self.common.get_session_via_id(session_id) {
+                    session.teardown();
+                }
+                return Err(e);
+            }
+        }
+
+        let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        let used = self.encode_spdm_key_update(key_update_operation, tag, &mut send_buffer)?;
+
+        self.send_secured_message(session_id, &send_buffer[..used])?;
+
+        // update key
+        let session = self
+            .common
+            .get_session_via_id(session_id)
+            .ok_or(spdm_err!(EINVAL))?;
         session.create_data_secret_update(update_requester, update_responder)?;
 
         // Receive
         let mut receive_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
-        let used = self.receive_secured_message(session_id, &mut receive_buffer)?;
+        let used = match self.receive_secured_message(session_id, &mut receive_buffer) {
+            Ok(used) => used,
+            Err(e) => {
+                let session = self
+                    .common
+                    .get_session_via_id(session_id)
+                    .ok_or(spdm_err!(EINVAL))?;
+                session.activate_data_secret_update(update_requester, update_responder, false)?;
+                return Err(e);
+            }
+        };
 
         let mut reader = Reader::init(&receive_buffer[..used]);
-        match SpdmMessageHeader::read(&mut reader) {
-            Some(message_header) => match message_header.request_response_code {
-                SpdmResponseResponseCode::SpdmResponseKeyUpdateAck => {
-                    let key_update_rsp =
-                        SpdmKeyUpdateResponsePayload::spdm_read(&mut self.common, &mut reader);
-                    let session = self.common.get_session_via_id(session_id).unwrap();
-                    if let Some(key_update_rsp) = key_update_rsp {
-                        debug!("!!! key_update rsp : {:02x?}\n", key_update_rsp);
-                        session.activate_data_secret_update(
-                            update_requester,
-                            update_responder,
-                            true,
-                        )?;
-                        Ok(())
-                    } else {
-                        error!("!!! key_update : fail !!!\n");
-                        session.activate_data_secret_update(
-                            update_requester,
-                            update_responder,
-                            false,
-                        )?;
-                        spdm_result_err!(EFAULT)
-                    }
+        let mut peer_error = None;
+        let key_update_rsp = match SpdmMessageHeader::read(&mut reader) {
+            Some(message_header)
+                if message_header.request_response_code
+                    == SpdmResponseResponseCode::SpdmResponseKeyUpdateAck =>
+            {
+                SpdmKeyUpdateResponsePayload::spdm_read(&mut self.common, &mut reader)
+            }
+            Some(message_header)
+                if message_header.request_response_code
+                    == SpdmResponseResponseCode::SpdmResponseError =>
+            {
+                peer_error = SpdmErrorResponsePayload::spdm_read(&mut self.common, &mut reader);
+                None
+            }
+            _ => None,
+        };
+        let session = self
+            .common
+            .get_session_via_id(session_id)
+            .ok_or(spdm_err!(EINVAL))?;
+        match key_update_rsp {
+            Some(key_update_rsp)
+                if key_update_rsp.key_update_operation == key_update_operation
+                    && key_update_rsp.tag == tag =>
+            {
+                debug!("!!! key_update rsp : {:02x?}\n", key_update_rsp);
+                session.activate_data_secret_update(update_requester, update_responder, true)
+            }
+            _ => {
+                error!("!!! key_update : fail !!!\n");
+                // keep the previous keys
+                session.activate_data_secret_update(update_requester, update_responder, false)?;
+                match peer_error {
+                    Some(error) => Err(self.record_spdm_peer_error(error)),
+                    None => spdm_result_err!(EFAULT),
                 }
-                _ => spdm_result_err!(EINVAL),
-            },
-            None => spdm_result_err!(EIO),
+            }
         }
     }
 
+    /// Send KEY_UPDATE with `key_update_operation`. The new keys are used
+    /// once KEY_UPDATE_ACK is received, and should then be verified with
+    /// `SpdmVerifyNewKey`, which lets the responder drop the previous key.
     pub fn send_receive_spdm_key_update(
         &mut self,
         session_id: u32,
         key_update_operation: SpdmKeyUpdateOperation,
     ) -> SpdmResult {
-        if key_update_operation != SpdmKeyUpdateOperation::SpdmUpdateAllKeys
-            && key_update_operation != SpdmKeyUpdateOperation::SpdmUpdateSingleKey
-        {
-            return spdm_result_err!(EINVAL);
-        }
-        self.send_receive_spdm_key_update_op(session_id, key_update_operation, 1)?;
-        self.send_receive_spdm_key_update_op(
-            session_id,
-            SpdmKeyUpdateOperation::SpdmVerifyNewKey,
-            2,
-        )
+        let tag = match key_update_operation {
+            SpdmKeyUpdateOperation::SpdmUpdateSingleKey
+            | SpdmKeyUpdateOperation::SpdmUpdateAllKeys => 1,
+            SpdmKeyUpdateOperation::SpdmVerifyNewKey => 2,
+            _ => return spdm_result_err!(EINVAL),
+        };
+        self.send_receive_spdm_key_update_op(session_id, key_update_operation, tag)
     }
 }
@@ -0,0 +1,176 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+#![forbid(unsafe_code)]
+
+use crate::error::SpdmResult;
+use crate::requester::*;
+
+// measurement block index 0 is reserved and 0xFF requests all blocks.
+const MAX_SPDM_MEASUREMENT_INDEX_COUNT: usize = 0xFE;
+
+/// Expected value of a measurement block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpdmMeasurementExpectation<'a> {
+    /// Any value, the block only has to be received.
+    Any,
+    /// DMTF measurement with digest representation and this digest.
+    Digest(&'a [u8]),
+    /// DMTF measurement with raw bit stream representation and this value.
+    RawBitStream(&'a [u8]),
+}
+
+/// Reference value of one measurement block.
+#[derive(Debug, Clone, Copy)]
+pub struct SpdmMeasurementReference<'a> {
+    /// Measurement block index, 1 to 0xFE.
+    pub index: u8,
+    pub expected: SpdmMeasurementExpectation<'a>,
+    /// The block has to be covered by a verified MEASUREMENTS signature.
+    pub must_be_signed: bool,
+}
+
+/// Reference manifest the measurements of the responder are verified against.
+#[derive(Debug, Clone, Copy)]
+pub struct SpdmMeasurementManifest<'a> {
+    pub references: &'a [SpdmMeasurementReference<'a>],
+    /// Received blocks without a reference do not fail the verification.
+    pub allow_unlisted: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpdmMeasurementComplianceStatus {
+    /// The block matches its reference.
+    Match,
+    /// The block has a different value or representation.
+    Mismatch,
+    /// The block has not been received.
+    Missing,
+    /// The block matches but no verified signature covers it.
+    Unsigned,
+    /// The block has been received but the manifest has no reference for it.
+    Unlisted,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SpdmMeasurementComplianceEntry {
+    pub index: u8,
+    pub status: SpdmMeasurementComplianceStatus,
+}
+
+/// Result of verifying the measurements against a manifest: one entry per
+/// reference in manifest order, followed by the unlisted blocks.
+#[derive(Debug, Clone, Copy)]
+pub struct SpdmMeasurementComplianceReport {
+    pub compliant: bool,
+    pub entry_count: usize,
+    pub entries: [SpdmMeasurementComplianceEntry; MAX_SPDM_MEASUREMENT_INDEX_COUNT],
+}
+
+impl SpdmMeasurementComplianceReport {
+    pub fn entries(&self) -> &[SpdmMeasurementComplianceEntry] {
+        &self.entries[..self.entry_count]
+    }
+}
+
+impl<'a> SpdmMeasurementExpectation<'a> {
+    fn is_matched(&self, block: &SpdmMeasurementBlockStructure) -> bool {
+        let (representation, value) = match self {
+            SpdmMeasurementExpectation::Any => return true,
+            SpdmMeasurementExpectation::Digest(value) => (
+                SpdmDmtfMeasurementRepresentation::SpdmDmtfMeasurementDigest,
+                value,
+            ),
+            SpdmMeasurementExpectation::RawBitStream(value) => (
+                SpdmDmtfMeasurementRepresentation::SpdmDmtfMeasurementRawBit,
+                value,
+            ),
+        };
+        block.measurement_specification == SpdmMeasurementSpecification::DMTF
+            && block.measurement.representation == representation
+            && &block.measurement.value[..(block.measurement.value_size as usize)] == *value
+    }
+}
+
+impl<'a> SpdmMeasurementManifest<'a> {
+    /// Verify `record`, where `signed[i]` tells whether a verified signature
+    /// covers `record.record[i]`.
+    pub fn verify(
+        &self,
+        record: &SpdmMeasurementRecordStructure,
+        signed: &[bool],
+    ) -> SpdmResult<SpdmMeasurementComplianceReport> {
+        let mut report = SpdmMeasurementComplianceReport {
+            compliant: true,
+            entry_count: 0,
+            entries: [SpdmMeasurementComplianceEntry {
+                index: 0,
+                status: SpdmMeasurementComplianceStatus::Missing,
+            }; MAX_SPDM_MEASUREMENT_INDEX_COUNT],
+        };
+        let blocks = &record.record[..(record.number_of_blocks as usize)];
+
+        for (i, reference) in self.references.iter().enumerate() {
+            if reference.index == 0
+                || reference.index == 0xFF
+                || self.references[..i]
+                    .iter()
+                    .any(|r| r.index == reference.index)
+            {
+                return spdm_result_err!(EINVAL, "invalid measurement manifest");
+            }
+
+            let status = match blocks.iter().position(|b| b.index == reference.index) {
+                None => SpdmMeasurementComplianceStatus::Missing,
+                Some(i) if !reference.expected.is_matched(&blocks[i]) => {
+                    SpdmMeasurementComplianceStatus::Mismatch
+                }
+                Some(i) if reference.must_be_signed && !signed.get(i).copied().unwrap_or(false) => {
+                    SpdmMeasurementComplianceStatus::Unsigned
+                }
+                Some(_) => SpdmMeasurementComplianceStatus::Match,
+            };
+            if status != SpdmMeasurementComplianceStatus::Match {
+                report.compliant = false;
+            }
+            report.entries[report.entry_count] = SpdmMeasurementComplianceEntry {
+                index: reference.index,
+                status,
+            };
+            report.entry_count += 1;
+        }
+
+        for block in blocks {
+            if self.references.iter().any(|r| r.index == block.index) {
+                continue;
+            }
+            if report.entry_count == MAX_SPDM_MEASUREMENT_INDEX_COUNT {
+                return spdm_result_err!(EINVAL, "invalid measurement record");
+            }
+            if !self.allow_unlisted {
+                report.compliant = false;
+            }
+            report.entries[report.entry_count] = SpdmMeasurementComplianceEntry {
+                index: block.index,
+                status: SpdmMeasurementComplianceStatus::Unlisted,
+            };
+            report.entry_count += 1;
+        }
+
+        Ok(report)
+    }
+}
+
+impl<'a> RequesterContext<'a> {
+    /// Verify the measurement blocks received so far against `manifest`.
+    pub fn verify_measurements(
+        &self,
+        manifest: &SpdmMeasurementManifest,
+    ) -> SpdmResult<SpdmMeasurementComplianceReport> {
+        manifest.verify(
+            &self.common.peer_info.peer_measurements,
+            &self.common.peer_info.peer_measurements_signed,
+        )
+    }
+}
@@ -6,8 +6,14 @@
 
 mod context;
 
+mod app_message_req;
+mod attest;
 mod challenge_req;
+mod chunk_req;
+mod connect;
+mod encapsulated_req;
 mod end_session_req;
+mod evidence;
 mod finish_req;
 mod get_capabilities_req;
 mod get_certificate_req;
@@ -17,12 +23,32 @@ mod get_version_req;
 mod heartbeat_req;
 mod key_exchange_req;
 mod key_update_req;
+mod measurement_policy;
 mod negotiate_algorithms_req;
+mod peer_error;
 mod psk_exchange_req;
 mod psk_finish_req;
+mod respond_if_ready_req;
+mod vendor_req;
 
+#[cfg(feature = "std")]
+pub use attest::{attest_devices, SpdmDeviceAttestation, SpdmParallelAttestConfig};
+pub use attest::SpdmAttestationEvidence;
+pub use connect::{SpdmConnectInfo, SpdmConnectMode, SpdmConnectPolicy};
 pub use context::RequesterContext;
+pub use get_digests_req::SpdmDigestsStatus;
+pub use evidence::SpdmMeasurementEvidence;
+pub use measurement_policy::{
+    SpdmMeasurementComplianceEntry, SpdmMeasurementComplianceReport,
+    SpdmMeasurementComplianceStatus, SpdmMeasurementExpectation, SpdmMeasurementManifest,
+    SpdmMeasurementReference,
+};
+pub use peer_error::SpdmPeerError;
 
+use crate::common::{
+    SpdmConnectionState, SpdmMeasurementIdentifier, SpdmPeerDigests, SpdmTranscriptCoverage,
+    SpdmTranscriptSegment,
+};
 use crate::config;
 use crate::msgs::*;
 use codec::{Codec, Reader, Writer};
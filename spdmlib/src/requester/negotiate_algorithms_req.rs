@@ -7,32 +7,84 @@
 use crate::error::SpdmResult;
 use crate::requester::*;
 
+// Copy `ext_algs` into `buffer`, returns how many fit.
+fn collect_ext_algs(
+    ext_algs: impl Iterator<Item = SpdmExtAlgStruct>,
+    buffer: &mut [SpdmExtAlgStruct],
+) -> u8 {
+    let mut count = 0;
+    for (entry, ext_alg) in buffer.iter_mut().zip(ext_algs) {
+        *entry = ext_alg;
+        count += 1;
+    }
+    count
+}
+
 impl<'a> RequesterContext<'a> {
-    pub fn send_receive_spdm_algorithm(&mut self) -> SpdmResult {
-        let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
-        let mut writer = Writer::init(&mut send_buffer);
+    // The extended algorithm selected in ALGORITHMS. At most one, only one
+    // we offered, and only if no base algorithm is selected.
+    fn get_ext_alg_sel(
+        &self,
+        offered: &'static [SpdmExtAlgStruct],
+        selected: &[SpdmExtAlgStruct],
+        base_selected: bool,
+    ) -> SpdmResult<Option<SpdmExtAlgStruct>> {
+        match selected {
+            [] => Ok(None),
+            [ext_alg]
+                if !base_selected && self.common.get_ext_algs(offered).any(|v| v == *ext_alg) =>
+            {
+                Ok(Some(*ext_alg))
+            }
+            _ => {
+                error!("!!! algorithms : invalid extended algorithm !!!\n");
+                spdm_result_err!(EFAULT)
+            }
+        }
+    }
+
+    /// Encode NEGOTIATE_ALGORITHMS with the configured algorithms into
+    /// `buffer` without sending it.
+    pub fn encode_spdm_algorithm(&mut self, buffer: &mut [u8]) -> SpdmResult<usize> {
+        let mut ext_asym = [SpdmExtAlgStruct::default(); config::MAX_SPDM_EXTEND_ASYM_ALGO_COUNT];
+        let ext_asym_count = collect_ext_algs(
+            self.common
+                .get_ext_algs(self.common.config_info.ext_asym_algo),
+            &mut ext_asym,
+        );
+        let mut ext_hash = [SpdmExtAlgStruct::default(); config::MAX_SPDM_EXTEND_HASH_ALGO_COUNT];
+        let ext_hash_count = collect_ext_algs(
+            self.common
+                .get_ext_algs(self.common.config_info.ext_hash_algo),
+            &mut ext_hash,
+        );
         let request = SpdmMessage {
             header: SpdmMessageHeader {
-                version: SpdmVersion::SpdmVersion11,
+                version: self.common.negotiate_info.spdm_version_sel,
                 request_response_code: SpdmResponseResponseCode::SpdmRequestNegotiateAlgorithms,
             },
             payload: SpdmMessagePayload::SpdmNegotiateAlgorithmsRequest(
                 SpdmNegotiateAlgorithmsRequestPayload {
                     measurement_specification: self.common.config_info.measurement_specification,
+                    other_params_support: self.common.config_info.opaque_support,
                     base_asym_algo: self.common.config_info.base_asym_algo,
                     base_hash_algo: self.common.config_info.base_hash_algo,
+                    ext_asym_count,
+                    ext_asym,
+                    ext_hash_count,
+                    ext_hash,
                     alg_struct_count: 4,
                     alg_struct: [
                         SpdmAlgStruct {
                             alg_type: SpdmAlgType::SpdmAlgTypeDHE,
                             alg_fixed_count: 2,
-                            alg_supported: SpdmAlg::SpdmAlgoDhe(self.common.config_info.dhe_algo),
+                            alg_supported: SpdmAlg::SpdmAlgoDhe(self.common.get_dhe_algo()),
                             alg_ext_count: 0,
                         },
                         SpdmAlgStruct {
                             alg_type: SpdmAlgType::SpdmAlgTypeAEAD,
                             alg_fixed_count: 2,
-                            alg_supported: SpdmAlg::SpdmAlgoAead(self.common.config_info.aead_algo),
+                            alg_supported: SpdmAlg::SpdmAlgoAead(self.common.get_aead_algo()),
                             alg_ext_count: 0,
                         },
                         SpdmAlgStruct {
@@ -55,8 +107,16 @@ impl<'a> RequesterContext<'a> {
                 },
             ),
         };
-        request.spdm_encode(&mut self.common, &mut writer);
-        let used = writer.used();
+        self.encode_request(&request, buffer)
+    }
+
+    pub fn send_receive_spdm_algorithm(&mut self) -> SpdmResult {
+        if self.common.get_connection_state() != SpdmConnectionState::AfterCapabilities {
+            return spdm_result_err!(EPERM, "NEGOTIATE_ALGORITHMS only follows GET_CAPABILITIES");
+        }
+
+        let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        let used = self.encode_spdm_algorithm(&mut send_buffer)?;
 
         self.send_message(&send_buffer[..used])?;
 
@@ -82,20 +142,74 @@ impl<'a> RequesterContext<'a> {
                     let used = reader.used();
                     if let Some(algorithms) = algorithms {
                         debug!("!!! algorithms : {:02x?}\n", algorithms);
-                        self.common.negotiate_info.measurement_specification_sel =
+                        // at most one specification, and only one we offered
+                        let measurement_specification_sel =
                             algorithms.measurement_specification_sel;
+                        if measurement_specification_sel.bits().count_ones() > 1
+                            || !self
+                                .common
+                                .config_info
+                                .measurement_specification
+                                .contains(measurement_specification_sel)
+                        {
+                            error!("!!! algorithms : invalid measurement specification !!!\n");
+                            return spdm_result_err!(EFAULT);
+                        }
+                        self.common.negotiate_info.measurement_specification_sel =
+                            measurement_specification_sel;
+                        // at most one opaque data format, and only one we offered
+                        let opaque_support_sel = algorithms.other_params_selection;
+                        if opaque_support_sel.bits().count_ones() > 1
+                            || !self
+                                .common
+                                .config_info
+                                .opaque_support
+                                .contains(opaque_support_sel)
+                        {
+                            error!("!!! algorithms : invalid opaque data format !!!\n");
+                            return spdm_result_err!(EFAULT);
+                        }
+                        self.common.negotiate_info.opaque_support_sel = opaque_support_sel;
                         self.common.negotiate_info.measurement_hash_sel =
                             algorithms.measurement_hash_algo;
                         self.common.negotiate_info.base_hash_sel = algorithms.base_hash_sel;
                         self.common.negotiate_info.base_asym_sel = algorithms.base_asym_sel;
+                        self.common.negotiate_info.ext_asym_sel = self.get_ext_alg_sel(
+                            self.common.config_info.ext_asym_algo,
+                            &algorithms.ext_asym_sel[..algorithms.ext_asym_sel_count as usize],
+                            !algorithms.base_asym_sel.is_empty(),
+                        )?;
+                        self.common.negotiate_info.ext_hash_sel = self.get_ext_alg_sel(
+                            self.common.config_info.ext_hash_algo,
+                            &algorithms.ext_hash_sel[..algorithms.ext_hash_sel_count as usize],
+                            !algorithms.base_hash_sel.is_empty(),
+                        )?;
                         for alg in algorithms
                             .alg_struct
                             .iter()
                             .take(algorithms.alg_struct_count as usize)
                         {
                             match alg.alg_supported {
-                                SpdmAlg::SpdmAlgoDhe(v) => self.common.negotiate_info.dhe_sel = v,
-                                SpdmAlg::SpdmAlgoAead(v) => self.common.negotiate_info.aead_sel = v,
+                                SpdmAlg::SpdmAlgoDhe(v) => {
+                                    // at most one group, and only one we implement
+                                    if v.bits().count_ones() > 1
+                                        || !self.common.get_dhe_algo().contains(v)
+                                    {
+                                        error!("!!! algorithms : invalid dhe !!!\n");
+                                        return spdm_result_err!(EFAULT);
+                                    }
+                                    self.common.negotiate_info.dhe_sel = v
+                                }
+                                SpdmAlg::SpdmAlgoAead(v) => {
+                                    // at most one AEAD, and only one we can encrypt with
+                                    if v.bits().count_ones() > 1
+                                        || !self.common.get_aead_algo().contains(v)
+                                    {
+                                        error!("!!! algorithms : invalid aead !!!\n");
+                                        return spdm_result_err!(EFAULT);
+                                    }
+                                    self.common.negotiate_info.aead_sel = v
+                                }
                                 SpdmAlg::SpdmAlgoReqAsym(v) => {
                                     self.common.negotiate_info.req_asym_sel = v
                                 }
@@ -105,6 +219,7 @@ impl<'a> RequesterContext<'a> {
                                 SpdmAlg::SpdmAlgoUnknown(_v) => {}
                             }
                         }
+                        self.common.update_my_cert_chain();
                         if self
                             .common
                             .runtime_info
@@ -112,12 +227,25 @@ impl<'a> RequesterContext<'a> {
                             .append_message(&receive_buffer[..used])
                             .is_some()
                         {
+                            self.common
+                                .runtime_info
+                                .transcript_coverage
+                                .insert(SpdmTranscriptCoverage::VCA);
+                            self.common.emit_transcript(
+                                SpdmTranscriptSegment::Vca,
+                                self.common.runtime_info.message_a.as_ref(),
+                            );
+                            self.common
+                                .advance_connection_state(SpdmConnectionState::Negotiated);
                             return Ok(());
                         };
                     }
                     error!("!!! algorithms : fail !!!\n");
                     spdm_result_err!(EFAULT)
                 }
+                SpdmResponseResponseCode::SpdmResponseError => {
+                    self.read_spdm_peer_error(&mut reader)
+                }
                 _ => spdm_result_err!(EINVAL),
             },
             None => spdm_result_err!(EIO),
@@ -0,0 +1,220 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+#![forbid(unsafe_code)]
+
+use crate::error::{SpdmError, SpdmResult};
+use crate::requester::*;
+
+/// The last ERROR a request was answered with.
+#[derive(Debug, Clone, Copy)]
+pub struct SpdmPeerError {
+    pub request_code: SpdmResponseResponseCode,
+    pub error_code: SpdmErrorCode,
+    pub error_data: u8,
+    /// Extended error data of ERROR(VendorDefined).
+    pub vendor_data: Option<SpdmErrorResponseVendorExtData>,
+}
+
+fn peer_error_to_spdm_error(error_code: SpdmErrorCode) -> SpdmError {
+    match error_code {
+        SpdmErrorCode::SpdmErrorInvalidRequest => spdm_err!(EINVAL, "peer error: invalid request"),
+        SpdmErrorCode::SpdmErrorInvalidSession => spdm_err!(ENOENT, "peer error: invalid session"),
+        SpdmErrorCode::SpdmErrorBusy | SpdmErrorCode::SpdmErrorResponseNotReady => {
+            spdm_err!(EBUSY, "peer error: busy")
+        }
+        SpdmErrorCode::SpdmErrorUnexpectedRequest => {
+            spdm_err!(EPERM, "peer error: unexpected request")
+        }
+        SpdmErrorCode::SpdmErrorDecryptError => spdm_err!(EBADMSG, "peer error: decrypt error"),
+        SpdmErrorCode::SpdmErrorUnsupportedRequest => {
+            spdm_err!(ENOSYS, "peer error: unsupported request")
+        }
+        SpdmErrorCode::SpdmErrorRequestInFlight => {
+            spdm_err!(EALREADY, "peer error: request in flight")
+        }
+        SpdmErrorCode::SpdmErrorInvalidResponseCode => {
+            spdm_err!(EINVAL, "peer error: invalid response code")
+        }
+        SpdmErrorCode::SpdmErrorSessionLimitExceeded => {
+            spdm_err!(ENOMEM, "peer error: session limit exceeded")
+        }
+        SpdmErrorCode::SpdmErrorSessionRequired => spdm_err!(EPERM, "peer error: session required"),
+        SpdmErrorCode::SpdmErrorRequestTooLarge => {
+            spdm_err!(E2BIG, "peer error: request too large")
+        }
+        SpdmErrorCode::SpdmErrorMajorVersionMismatch => {
+            spdm_err!(ERANGE, "peer error: major version mismatch")
+        }
+        SpdmErrorCode::SpdmErrorRequestResynch => spdm_err!(EIO, "peer error: request resynch"),
+        SpdmErrorCode::SpdmErrorVendorDefined => spdm_err!(EIO, "peer error: vendor defined"),
+        _ => spdm_err!(EIO, "peer error: unspecified"),
+    }
+}
+
+impl<'a> RequesterContext<'a> {
+    /// The last ERROR received, `None` if no request has been answered
+    /// with one yet.
+    pub fn get_last_peer_error(&self) -> Option<SpdmPeerError> {
+        self.last_peer_error
+    }
+
+    /// Record the ERROR `error` and return the error it maps to. After
+    /// ERROR(RequestResynch) the connection has to be set up again.
+    pub(crate) fn record_spdm_peer_error(&mut self, error: SpdmErrorResponsePayload) -> SpdmError {
+        error!(
+            "!!! peer error {:02x?} to {} !!!\n",
+            error.error_code, self.last_request_code
+        );
+        let vendor_data = match error.extended_data {
+            SpdmErrorResponseExtData::SpdmErrorExtDataVendorDefined(vendor_data) => {
+                Some(vendor_data)
+            }
+            _ => None,
+        };
+        self.last_peer_error = Some(SpdmPeerError {
+            request_code: self.last_request_code,
+            error_code: error.error_code,
+            error_data: error.error_data,
+            vendor_data,
+        });
+        if error.error_code == SpdmErrorCode::SpdmErrorRequestResynch {
+            self.common.reset_runtime_info();
+        }
+        peer_error_to_spdm_error(error.error_code)
+    }
+
+    /// Record the ERROR read from `reader`, right after the message header,
+    /// and fail with the error it maps to.
+    pub(crate) fn read_spdm_peer_error<T>(&mut self, reader: &mut Reader) -> SpdmResult<T> {
+        match SpdmErrorResponsePayload::spdm_read(&mut self.common, reader) {
+            Some(error) => Err(self.record_spdm_peer_error(error)),
+            None => spdm_result_err!(EIO),
+        }
+    }
+
+    fn is_response_busy(&self, response: &[u8]) -> bool {
+        let mut reader = Reader::init(response);
+        match SpdmMessageHeader::read(&mut reader) {
+            Some(message_header)
+                if message_header.request_response_code
+                    == SpdmResponseResponseCode::SpdmResponseError => {}
+            _ => return false,
+        }
+        matches!(
+            SpdmErrorCode::read(&mut reader),
+            Some(SpdmErrorCode::SpdmErrorBusy)
+        )
+    }
+
+    /// Replace an ERROR(Busy) in `receive_buffer[..used]` by the response
+    /// to the last request sent again, busy_retry_count times at most,
+    /// busy_retry_interval_us apart.
+    pub(crate) fn handle_response_busy(
+        &mut self,
+        session_id: Option<u32>,
+        receive_buffer: &mut [u8],
+        used: usize,
+    ) -> SpdmResult<usize> {
+        let mut used = used;
+        let mut retry_count = 0u8;
+        while self.is_response_busy(&receive_buffer[..used])
+            && retry_count < self.common.config_info.busy_retry_count
+            && self.last_message_size != 0
+            && self.last_message_session_id == session_id
+        {
+            retry_count += 1;
+            crate::time::sleep(self.common.config_info.busy_retry_interval_us);
+
+            info!("resend request after busy\n");
+            let mut send_buffer = self.common.take_message_buffer();
+            let size = self.last_message_size;
+            send_buffer[..size].copy_from_slice(&self.last_message[..size]);
+            let result = match session_id {
+                Some(session_id) => self
+                    .send_secured_message(session_id, &send_buffer[..size])
+                    .and_then(|_| self.receive_secured_message_once(session_id, receive_buffer)),
+                None => self
+                    .send_message_unchunked(&send_buffer[..size])
+                    .and_then(|_| self.receive_message_unchunked(receive_buffer)),
+            };
+            self.common.put_message_buffer(send_buffer);
+            used = self.handle_response_not_ready(session_id, receive_buffer, result?)?;
+        }
+        Ok(used)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{SpdmConfigInfo, SpdmProvisionInfo};
+    use crate::error::SpdmErrorNum;
+    use crate::testlib::{self, ScriptedDeviceIo};
+
+    const GET_VERSION_BUSY: [u8; 4] = [0x10, 0x7F, 0x03, 0x00];
+    const VERSION_1_0: [u8; 8] = [0x10, 0x04, 0x00, 0x00, 0x00, 0x01, 0x00, 0x10];
+
+    fn get_version(responses: &[&[u8]], busy_retry_count: u8) -> (SpdmResult, usize) {
+        let mut device_io = ScriptedDeviceIo { responses, sent: 0 };
+        let mut config_info = SpdmConfigInfo {
+            busy_retry_count,
+            ..SpdmConfigInfo::default()
+        };
+        config_info.spdm_version[0] = SpdmVersion::SpdmVersion10;
+        let mut context =
+            testlib::new_requester(&mut device_io, config_info, SpdmProvisionInfo::default());
+        let result = context.send_receive_spdm_version();
+        if result.is_err() {
+            let peer_error = context.get_last_peer_error().unwrap();
+            assert_eq!(
+                peer_error.request_code,
+                SpdmResponseResponseCode::SpdmRequestGetVersion
+            );
+        }
+        (result, device_io.sent)
+    }
+
+    #[test]
+    fn test_busy_retry() {
+        let (result, sent) = get_version(&[&GET_VERSION_BUSY, &VERSION_1_0], 2);
+        assert!(result.is_ok());
+        assert_eq!(sent, 2);
+
+        let (result, sent) = get_version(&[&GET_VERSION_BUSY, &GET_VERSION_BUSY], 1);
+        assert_eq!(result.unwrap_err().num(), SpdmErrorNum::EBUSY);
+        assert_eq!(sent, 2);
+
+        // no retries configured
+        let (result, sent) = get_version(&[&GET_VERSION_BUSY, &VERSION_1_0], 0);
+        assert_eq!(result.unwrap_err().num(), SpdmErrorNum::EBUSY);
+        assert_eq!(sent, 1);
+    }
+
+    #[test]
+    fn test_peer_error() {
+        let unsupported = [0x10, 0x7F, 0x07, 0x84];
+        let (result, _) = get_version(&[&unsupported], 0);
+        assert_eq!(result.unwrap_err().num(), SpdmErrorNum::ENOSYS);
+
+        let vendor_defined = [0x10, 0x7F, 0xFF, 0x00, 0xAA, 0xBB];
+        let mut device_io = ScriptedDeviceIo {
+            responses: &[&vendor_defined],
+            sent: 0,
+        };
+        let mut context = testlib::new_requester(
+            &mut device_io,
+            SpdmConfigInfo::default(),
+            SpdmProvisionInfo::default(),
+        );
+        assert!(context.send_receive_spdm_version().is_err());
+        let peer_error = context.get_last_peer_error().unwrap();
+        assert_eq!(peer_error.error_code, SpdmErrorCode::SpdmErrorVendorDefined);
+        let vendor_data = peer_error.vendor_data.unwrap();
+        assert_eq!(
+            &vendor_data.data[..vendor_data.data_size as usize],
+            &[0xAA, 0xBB]
+        );
+    }
+}
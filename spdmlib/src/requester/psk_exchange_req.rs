@@ -4,52 +4,97 @@
 
 #![forbid(unsafe_code)]
 
-use config::MAX_SPDM_PSK_CONTEXT_SIZE;
+use core::convert::TryFrom;
 
 use crate::error::SpdmResult;
 use crate::requester::*;
 
-use crate::common::ManagedBuffer;
+const PSK_EXCHANGE_REQ_SESSION_ID: u16 = 0xFFFD;
 
 impl<'a> RequesterContext<'a> {
-    pub fn send_receive_spdm_psk_exchange(
-        &mut self,
-        measurement_summary_hash_type: SpdmMeasurementSummaryHashType,
-    ) -> SpdmResult<u32> {
-        info!("send spdm psk exchange\n");
-
-        let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
-        let mut writer = Writer::init(&mut send_buffer);
+    // An ERROR in reply to PSK_EXCHANGE. InvalidRequest is returned by the
+    // responder if it has no PSK for the hint.
+    fn get_psk_exchange_error(&mut self, reader: &mut Reader) -> SpdmResult<u32> {
+        let error =
+            SpdmErrorResponsePayload::spdm_read(&mut self.common, reader).ok_or(spdm_err!(EIO))?;
+        let peer_error = self.record_spdm_peer_error(error);
+        if error.error_code == SpdmErrorCode::SpdmErrorInvalidRequest {
+            spdm_result_err!(ENOENT, "unknown psk hint")
+        } else {
+            Err(peer_error)
+        }
+    }
 
-        let req_session_id = 0xFFFD;
+    // Our half of the ID of the next session. Stays the same until a session
+    // is set up, so the one sent in PSK_EXCHANGE is used for its response.
+    fn get_psk_exchange_req_session_id(&self) -> SpdmResult<u16> {
+        self.common
+            .get_free_half_session_id(PSK_EXCHANGE_REQ_SESSION_ID, true)
+            .ok_or_else(|| spdm_err!(EBUSY, "too many sessions"))
+    }
 
-        let psk_context = [0xaa; MAX_SPDM_PSK_CONTEXT_SIZE];
-        //let spdm_random = SpdmCryptoRandom {}; // TBD
-        //spdm_random.get_random (&mut nonce);
-        let mut opaque = SpdmOpaqueStruct {
-            data_size: crate::common::OPAQUE_DATA_SUPPORT_VERSION.len() as u16,
-            ..Default::default()
-        };
-        opaque.data[..(opaque.data_size as usize)]
-            .copy_from_slice(crate::common::OPAQUE_DATA_SUPPORT_VERSION.as_ref());
+    /// Encode PSK_EXCHANGE into `buffer` without sending it.
+    pub fn encode_spdm_psk_exchange(
+        &mut self,
+        measurement_summary_hash_type: SpdmMeasurementSummaryHashType,
+        psk_hint: &[u8],
+        psk_context: &[u8],
+        buffer: &mut [u8],
+    ) -> SpdmResult<usize> {
+        let req_session_id = self.get_psk_exchange_req_session_id()?;
+        let hint = SpdmPskHintStruct::try_from(psk_hint)?;
+        let context = SpdmPskContextStruct::try_from(psk_context)?;
+
+        let opaque = SpdmOpaqueStruct::try_from(self.common.get_opaque_data_support_version())?;
         let request = SpdmMessage {
             header: SpdmMessageHeader {
-                version: SpdmVersion::SpdmVersion11,
+                version: self.common.negotiate_info.spdm_version_sel,
                 request_response_code: SpdmResponseResponseCode::SpdmRequestPskExchange,
             },
             payload: SpdmMessagePayload::SpdmPskExchangeRequest(SpdmPskExchangeRequestPayload {
                 measurement_summary_hash_type,
                 req_session_id,
-                psk_hint: SpdmPskHintStruct::default(),
-                psk_context: SpdmPskContextStruct {
-                    data_size: self.common.negotiate_info.base_hash_sel.get_size(),
-                    data: psk_context,
-                },
+                psk_hint: hint,
+                psk_context: context,
                 opaque,
             }),
         };
-        request.spdm_encode(&mut self.common, &mut writer);
-        let send_used = writer.used();
+        self.encode_request(&request, buffer)
+    }
+
+    /// Start a PSK session with the configured PSK, identified by `psk_hint`,
+    /// or resume one with the resumption PSK if `psk_hint` is its hint.
+    /// `psk_context` is the requester context, a random value of at least
+    /// the negotiated hash size.
+    ///
+    /// Returns ENOENT if the responder does not know the hint and EFAULT if
+    /// the responder verify_data does not match.
+    pub fn send_receive_spdm_psk_exchange(
+        &mut self,
+        measurement_summary_hash_type: SpdmMeasurementSummaryHashType,
+        psk_hint: &[u8],
+        psk_context: &[u8],
+    ) -> SpdmResult<u32> {
+        self.common
+            .check_connection_state(SpdmConnectionState::Negotiated)?;
+        info!("send spdm psk exchange\n");
+
+        let base_hash_size = self.common.negotiate_info.base_hash_sel.get_size() as usize;
+        if psk_context.len() < base_hash_size {
+            return spdm_result_err!(EINVAL);
+        }
+        let resumption_psk = self.common.get_resumption_psk(psk_hint);
+        if resumption_psk.is_none() && self.common.config_info.psk.data_size == 0 {
+            return spdm_result_err!(EINVAL, "no psk configured");
+        }
+
+        let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        let send_used = self.encode_spdm_psk_exchange(
+            measurement_summary_hash_type,
+            psk_hint,
+            psk_context,
+            &mut send_buffer,
+        )?;
 
         self.send_message(&send_buffer[..send_used])?;
 
@@ -64,7 +109,8 @@ impl<'a> RequesterContext<'a> {
         }
 
         // Receive
-        let mut receive_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        // may be a large response retrieved in chunks
+        let mut receive_buffer = [0u8; config::MAX_SPDM_MESSAGE_BUFFER_SIZE];
         let receive_used = self.receive_message(&mut receive_buffer)?;
 
         let mut reader = Reader::init(&receive_buffer[..receive_used]);
@@ -77,10 +123,7 @@ impl<'a> RequesterContext<'a> {
                     if let Some(psk_exchange_rsp) = psk_exchange_rsp {
                         debug!("!!! psk_exchange rsp : {:02x?}\n", psk_exchange_rsp);
 
-                        let base_hash_size =
-                            self.common.negotiate_info.base_hash_sel.get_size() as usize;
-
-                        let mut message_k = ManagedBuffer::default();
+                        let mut message_k = self.common.new_message_k(true, true)?;
                         message_k
                             .append_message(&send_buffer[..send_used])
                             .ok_or(spdm_err!(ENOMEM))?;
@@ -92,49 +135,55 @@ impl<'a> RequesterContext<'a> {
                         // create session - generate the handshake secret (including finished_key)
                         let th1 = self
                             .common
-                            .calc_req_transcript_hash(true, &message_k, None)?;
+                            .calc_req_transcript_hash(true, false, &message_k, None)?;
                         debug!("!!! th1 : {:02x?}\n", th1.as_ref());
                         let base_hash_algo = self.common.negotiate_info.base_hash_sel;
                         let dhe_algo = self.common.negotiate_info.dhe_sel;
                         let aead_algo = self.common.negotiate_info.aead_sel;
                         let key_schedule_algo = self.common.negotiate_info.key_schedule_sel;
+                        let spdm_version = self.common.negotiate_info.spdm_version_sel;
                         let sequence_number_count =
                             self.common.transport_encap.get_sequence_number_count();
-                        let max_random_count = self.common.transport_encap.get_max_random_count();
+                        let max_random_count = self.common.get_max_random_count();
+                        let replay_window_size = self.common.config_info.replay_window_size;
+                        let random_padding_policy = self.common.config_info.random_padding_policy;
+                        let (mut psk_key, session_policy) = match resumption_psk {
+                            Some(resumption_psk) => {
+                                (resumption_psk.psk.clone(), resumption_psk.session_policy)
+                            }
+                            None => (
+                                self.common.config_info.psk.clone(),
+                                SpdmKeyExchangeSessionPolicy::empty(),
+                            ),
+                        };
 
+                        let req_session_id = self.get_psk_exchange_req_session_id()?;
                         let session_id = ((req_session_id as u32) << 16)
                             + psk_exchange_rsp.rsp_session_id as u32;
-                        let session = self
-                            .common
-                            .get_next_avaiable_session()
-                            .ok_or(spdm_err!(EINVAL))?;
-
-                        session.setup(session_id).unwrap();
+                        let mut session_guard = self.common.setup_session(session_id)?;
+                        let session = session_guard.session();
                         session.set_use_psk(true);
-                        let mut psk_key = SpdmDheFinalKeyStruct {
-                            data_size: b"TestPskData\0".len() as u16,
-                            ..Default::default()
-                        };
-                        psk_key.data[0..(psk_key.data_size as usize)]
-                            .copy_from_slice(b"TestPskData\0");
+                        session.set_session_policy(session_policy);
+                        session.set_heartbeat_period(psk_exchange_rsp.heartbeat_period);
+                        session.set_peer_opaque_data(&psk_exchange_rsp.opaque);
                         session.set_crypto_param(
                             base_hash_algo,
                             dhe_algo,
                             aead_algo,
                             key_schedule_algo,
                         );
+                        session.set_spdm_version(spdm_version);
                         session.set_transport_param(sequence_number_count, max_random_count);
-                        session.set_dhe_secret(&psk_key); // TBD
-                        session.generate_handshake_secret(&th1).unwrap();
+                        session.set_replay_window_size(replay_window_size);
+                        session.set_random_padding_policy(random_padding_policy);
+                        session.set_dhe_secret(&mut psk_key)?;
+                        session.generate_handshake_secret(&th1)?;
 
                         // verify HMAC with finished_key
-                        let transcript_data = self
-                            .common
-                            .calc_req_transcript_data(true, &message_k, None)?;
-                        let session = self
-                            .common
-                            .get_session_via_id(session_id)
-                            .ok_or(spdm_err!(EINVAL))?;
+                        let transcript_data = session_guard
+                            .context()
+                            .calc_req_transcript_data(true, false, &message_k, None)?;
+                        let session = session_guard.session();
                         if session
                             .verify_hmac_with_response_finished_key(
                                 transcript_data.as_ref(),
@@ -143,7 +192,6 @@ impl<'a> RequesterContext<'a> {
                             .is_err()
                         {
                             error!("verify_hmac_with_response_finished_key fail");
-                            let _ = session.teardown(session_id);
                             return spdm_result_err!(EFAULT);
                         } else {
                             info!("verify_hmac_with_response_finished_key pass");
@@ -157,12 +205,15 @@ impl<'a> RequesterContext<'a> {
                             crate::session::SpdmSessionState::SpdmSessionHandshaking,
                         );
 
-                        Ok(session_id)
+                        Ok(session_guard.commit())
                     } else {
                         error!("!!! psk_exchange : fail !!!\n");
                         spdm_result_err!(EFAULT)
                     }
                 }
+                SpdmResponseResponseCode::SpdmResponseError => {
+                    self.get_psk_exchange_error(&mut reader)
+                }
                 _ => spdm_result_err!(EINVAL),
             },
             None => spdm_result_err!(EIO),
@@ -7,45 +7,50 @@
 use crate::error::SpdmResult;
 use crate::requester::*;
 
-use crate::common::ManagedBuffer;
-
 impl<'a> RequesterContext<'a> {
-    pub fn send_receive_spdm_psk_finish(&mut self, session_id: u32) -> SpdmResult {
-        info!("send spdm psk_finish\n");
-        let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
-        let mut writer = Writer::init(&mut send_buffer);
-
+    /// Encode PSK_FINISH into `buffer` without sending it. The
+    /// verify_data is a placeholder of the negotiated hash size.
+    pub fn encode_spdm_psk_finish(&mut self, buffer: &mut [u8]) -> SpdmResult<usize> {
         let request = SpdmMessage {
             header: SpdmMessageHeader {
-                version: SpdmVersion::SpdmVersion11,
+                version: self.common.negotiate_info.spdm_version_sel,
                 request_response_code: SpdmResponseResponseCode::SpdmRequestPskFinish,
             },
             payload: SpdmMessagePayload::SpdmPskFinishRequest(SpdmPskFinishRequestPayload {
-                verify_data: SpdmDigestStruct {
-                    data_size: self.common.negotiate_info.base_hash_sel.get_size(),
-                    data: [0xcc; SPDM_MAX_HASH_SIZE],
-                },
+                verify_data: SpdmDigestStruct::placeholder(
+                    self.common.negotiate_info.base_hash_sel,
+                ),
             }),
         };
-        request.spdm_encode(&mut self.common, &mut writer);
-        let send_used = writer.used();
+        self.encode_request(&request, buffer)
+    }
+
+    pub fn send_receive_spdm_psk_finish(&mut self, session_id: u32) -> SpdmResult {
+        info!("send spdm psk_finish\n");
+        let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        let send_used = self.encode_spdm_psk_finish(&mut send_buffer)?;
 
         // generate HMAC with finished_key
         let base_hash_size = self.common.negotiate_info.base_hash_sel.get_size() as usize;
         let temp_used = send_used - base_hash_size;
 
-        let mut message_f = ManagedBuffer::default();
+        let session = self
+            .common
+            .get_session_via_id(session_id)
+            .ok_or(spdm_err!(EINVAL))?;
+        let message_k = session.runtime_info.message_k.try_clone()?;
+        let mut message_f = self.common.new_message_f(true, false, &message_k)?;
         message_f
             .append_message(&send_buffer[..temp_used])
             .ok_or(spdm_err!(ENOMEM))?;
 
-        let session = self.common.get_session_via_id(session_id).unwrap();
-        let message_k = session.runtime_info.message_k;
-
         let transcript_data =
             self.common
-                .calc_req_transcript_data(true, &message_k, Some(&message_f))?;
-        let session = self.common.get_session_via_id(session_id).unwrap();
+                .calc_req_transcript_data(true, false, &message_k, Some(&message_f))?;
+        let session = self
+            .common
+            .get_session_via_id(session_id)
+            .ok_or(spdm_err!(EINVAL))?;
         let hmac = session.generate_hmac_with_request_finished_key(transcript_data.as_ref())?;
         message_f
             .append_message(hmac.as_ref())
@@ -69,21 +74,27 @@ impl<'a> RequesterContext<'a> {
                     let receive_used = reader.used();
                     if let Some(psk_finish_rsp) = psk_finish_rsp {
                         debug!("!!! psk_finish rsp : {:02x?}\n", psk_finish_rsp);
-                        let session = self.common.get_session_via_id(session_id).unwrap();
                         message_f
                             .append_message(&receive_buffer[..receive_used])
                             .ok_or(spdm_err!(ENOMEM))?;
-                        session.runtime_info.message_f = message_f;
 
                         // generate the data secret
                         let th2 = self.common.calc_req_transcript_hash(
                             true,
+                            false,
                             &message_k,
                             Some(&message_f),
                         )?;
                         debug!("!!! th2 : {:02x?}\n", th2.as_ref());
-                        let session = self.common.get_session_via_id(session_id).unwrap();
-                        session.generate_data_secret(&th2).unwrap();
+                        let session = self
+                            .common
+                            .get_session_via_id(session_id)
+                            .ok_or(spdm_err!(EINVAL))?;
+                        session.runtime_info.message_f = message_f;
+                        if session.generate_data_secret(&th2).is_err() {
+                            session.teardown();
+                            return spdm_result_err!(EFAULT);
+                        }
                         session.set_session_state(
                             crate::session::SpdmSessionState::SpdmSessionEstablished,
                         );
@@ -94,6 +105,9 @@ impl<'a> RequesterContext<'a> {
                         spdm_result_err!(EFAULT)
                     }
                 }
+                SpdmResponseResponseCode::SpdmResponseError => {
+                    self.read_spdm_peer_error(&mut reader)
+                }
                 _ => spdm_result_err!(EINVAL),
             },
             None => spdm_result_err!(EIO),
@@ -0,0 +1,90 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+#![forbid(unsafe_code)]
+
+use crate::error::SpdmResult;
+use crate::requester::*;
+
+impl<'a> RequesterContext<'a> {
+    fn get_response_not_ready(
+        &mut self,
+        response: &[u8],
+    ) -> Option<SpdmErrorResponseNotReadyExtData> {
+        let mut reader = Reader::init(response);
+        let message_header = SpdmMessageHeader::read(&mut reader)?;
+        if message_header.request_response_code != SpdmResponseResponseCode::SpdmResponseError {
+            return None;
+        }
+        let error = SpdmErrorResponsePayload::spdm_read(&mut self.common, &mut reader)?;
+        match error.extended_data {
+            SpdmErrorResponseExtData::SpdmErrorExtDataNotReady(not_ready)
+                if error.error_code == SpdmErrorCode::SpdmErrorResponseNotReady =>
+            {
+                Some(not_ready)
+            }
+            _ => None,
+        }
+    }
+
+    /// Replace an ERROR(ResponseNotReady) in `receive_buffer[..used]` by the
+    /// response to RESPOND_IF_READY. It is retried every RDT, RDTM times at most.
+    pub(crate) fn handle_response_not_ready(
+        &mut self,
+        session_id: Option<u32>,
+        receive_buffer: &mut [u8],
+        used: usize,
+    ) -> SpdmResult<usize> {
+        let mut used = used;
+        let mut retry_count = 0u8;
+        while let Some(not_ready) = self.get_response_not_ready(&receive_buffer[..used]) {
+            debug!("!!! response not ready : {:02x?}\n", not_ready);
+            if retry_count >= not_ready.tdtm.max(1) {
+                error!("!!! respond_if_ready : responder not ready !!!\n");
+                return spdm_result_err!(EBUSY);
+            }
+            retry_count += 1;
+
+            // RDT is 2^RDTExponent us
+            let rdt = 1usize
+                .checked_shl(not_ready.rdt_exponent as u32)
+                .unwrap_or(usize::MAX);
+            crate::time::sleep(rdt);
+
+            info!("send spdm respond_if_ready\n");
+            let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+            let mut writer = Writer::init(&mut send_buffer);
+            // before the version is negotiated, retry with the GET_VERSION version
+            let version = match self.common.negotiate_info.spdm_version_sel {
+                SpdmVersion::Unknown(_) => SpdmVersion::SpdmVersion10,
+                version => version,
+            };
+            let request = SpdmMessage {
+                header: SpdmMessageHeader {
+                    version,
+                    request_response_code: SpdmResponseResponseCode::SpdmRequestResponseIfReady,
+                },
+                payload: SpdmMessagePayload::SpdmRespondIfReadyRequest(
+                    SpdmRespondIfReadyRequestPayload {
+                        request_code: not_ready.request_code,
+                        token: not_ready.token,
+                    },
+                ),
+            };
+            let send_used = request.spdm_encode(&mut self.common, &mut writer)?;
+
+            used = match session_id {
+                Some(session_id) => {
+                    self.send_secured_message(session_id, &send_buffer[..send_used])?;
+                    self.receive_secured_message_once(session_id, receive_buffer)?
+                }
+                None => {
+                    self.send_message_unchunked(&send_buffer[..send_used])?;
+                    self.receive_message_unchunked(receive_buffer)?
+                }
+            };
+        }
+        Ok(used)
+    }
+}
@@ -0,0 +1,71 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+#![forbid(unsafe_code)]
+
+use core::convert::TryFrom;
+
+use crate::error::SpdmResult;
+use crate::msgs::exchange;
+use crate::requester::*;
+
+impl<'a> RequesterContext<'a> {
+    /// Send VENDOR_DEFINED_REQUEST with `req_payload` to the vendor
+    /// `vendor_id` of the `standard_id` body, in the session `session_id`
+    /// or in the clear if `None`. Returns the VENDOR_DEFINED_RESPONSE
+    /// payload. Fails with ENOSYS if the responder doesn't serve the vendor.
+    pub fn send_receive_vendor_defined_request(
+        &mut self,
+        session_id: Option<u32>,
+        standard_id: SpdmStandardId,
+        vendor_id: &[u8],
+        req_payload: &[u8],
+    ) -> SpdmResult<SpdmVendorDefinedPayloadStruct> {
+        self.common
+            .check_connection_state(SpdmConnectionState::Negotiated)?;
+        let request = SpdmVendorDefinedRequestPayload {
+            standard_id,
+            vendor_id: SpdmVendorIdStruct::try_from(vendor_id)?,
+            req_payload: SpdmVendorDefinedPayloadStruct::try_from(req_payload)?,
+        };
+
+        info!("send spdm vendor_defined_request\n");
+        let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        let version = self.common.negotiate_info.spdm_version_sel;
+        let (used, pending_response) =
+            exchange::encode_request(&mut self.common, version, &request, &mut send_buffer)?;
+
+        match session_id {
+            Some(session_id) => self.send_secured_message(session_id, &send_buffer[..used])?,
+            None => self.send_message(&send_buffer[..used])?,
+        }
+
+        // Receive
+        let mut receive_buffer = [0u8; config::MAX_SPDM_MESSAGE_BUFFER_SIZE];
+        let used = match session_id {
+            Some(session_id) => self.receive_secured_message(session_id, &mut receive_buffer)?,
+            None => self.receive_message(&mut receive_buffer)?,
+        };
+
+        match pending_response.read_response(&mut self.common, &receive_buffer[..used]) {
+            Ok(exchange::SpdmExchangeResponse::Response(vendor_defined_rsp)) => {
+                if vendor_defined_rsp.standard_id != standard_id
+                    || vendor_defined_rsp.vendor_id.as_slice() != vendor_id
+                {
+                    error!("!!! vendor_defined_response : vendor mismatch !!!\n");
+                    return spdm_result_err!(EINVAL, "response of another vendor");
+                }
+                Ok(vendor_defined_rsp.rsp_payload)
+            }
+            // ENOSYS if the responder doesn't support the request
+            Ok(exchange::SpdmExchangeResponse::Error(error)) => {
+                Err(self.record_spdm_peer_error(error))
+            }
+            Err(e) => {
+                error!("!!! vendor_defined_request : fail !!!\n");
+                Err(e)
+            }
+        }
+    }
+}
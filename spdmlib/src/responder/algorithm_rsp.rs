@@ -4,7 +4,6 @@
 
 #![forbid(unsafe_code)]
 
-use crate::crypto;
 use crate::responder::*;
 
 impl<'a> ResponderContext<'a> {
@@ -14,10 +13,12 @@ impl<'a> ResponderContext<'a> {
 
         let negotiate_algorithms =
             SpdmNegotiateAlgorithmsRequestPayload::spdm_read(&mut self.common, &mut reader);
-        if let Some(negotiate_algorithms) = negotiate_algorithms {
+        if let Some(negotiate_algorithms) = &negotiate_algorithms {
             debug!("!!! negotiate_algorithms : {:02x?}\n", negotiate_algorithms);
             self.common.negotiate_info.measurement_specification_sel =
                 negotiate_algorithms.measurement_specification;
+            self.common.negotiate_info.opaque_support_sel =
+                negotiate_algorithms.other_params_support;
             self.common.negotiate_info.base_hash_sel = negotiate_algorithms.base_hash_algo;
             self.common.negotiate_info.base_asym_sel = negotiate_algorithms.base_asym_algo;
             for alg in negotiate_algorithms
@@ -52,72 +53,100 @@ impl<'a> ResponderContext<'a> {
             return;
         }
 
+        let measurement_specification = self.get_measurement_specification();
         self.common
             .negotiate_info
             .measurement_specification_sel
-            .prioritize(self.common.config_info.measurement_specification);
+            .prioritize(measurement_specification);
         self.common.negotiate_info.measurement_hash_sel =
             self.common.config_info.measurement_hash_algo;
         self.common
             .negotiate_info
-            .base_hash_sel
-            .prioritize(self.common.config_info.base_hash_algo);
-        self.common
-            .negotiate_info
-            .base_asym_sel
-            .prioritize(self.common.config_info.base_asym_algo);
-        self.common
-            .negotiate_info
-            .dhe_sel
-            .prioritize(self.common.config_info.dhe_algo);
+            .opaque_support_sel
+            .prioritize(self.common.config_info.opaque_support);
         self.common
             .negotiate_info
-            .aead_sel
-            .prioritize(self.common.config_info.aead_algo);
+            .base_hash_sel
+            .prioritize_in_order(
+                self.common.config_info.base_hash_algo,
+                self.common.config_info.base_hash_algo_priority,
+            );
         self.common
             .negotiate_info
-            .req_asym_sel
-            .prioritize(self.common.config_info.req_asym_algo);
+            .base_asym_sel
+            .prioritize_in_order(
+                self.common.config_info.base_asym_algo,
+                self.common.config_info.base_asym_algo_priority,
+            );
+        self.common.negotiate_info.dhe_sel.prioritize_in_order(
+            self.common.get_dhe_algo(),
+            self.common.config_info.dhe_algo_priority,
+        );
+        self.common.negotiate_info.aead_sel.prioritize_in_order(
+            self.common.get_aead_algo(),
+            self.common.config_info.aead_algo_priority,
+        );
+        self.common.negotiate_info.req_asym_sel.prioritize_in_order(
+            self.common.config_info.req_asym_algo,
+            self.common.config_info.req_asym_algo_priority,
+        );
         self.common
             .negotiate_info
             .key_schedule_sel
-            .prioritize(self.common.config_info.key_schedule_algo);
+            .prioritize_in_order(
+                self.common.config_info.key_schedule_algo,
+                self.common.config_info.key_schedule_algo_priority,
+            );
 
-        //
-        // update cert chain - append root cert hash
-        //
-        if self.common.provision_info.my_cert_chain.is_none()
-            && self.common.provision_info.my_cert_chain_data.is_some()
-        {
-            let cert_chain = self.common.provision_info.my_cert_chain_data.unwrap();
-            let (root_cert_begin, root_cert_end) =
-                crypto::cert_operation::get_cert_from_cert_chain(
-                    &cert_chain.data[..(cert_chain.data_size as usize)],
-                    0,
-                )
-                .unwrap();
-            let root_cert = &cert_chain.data[root_cert_begin..root_cert_end];
-            let root_hash =
-                crypto::hash::hash_all(self.common.negotiate_info.base_hash_sel, root_cert)
-                    .unwrap();
-            let data_size = 4 + root_hash.data_size + cert_chain.data_size;
-            let mut data = [0u8; config::MAX_SPDM_CERT_CHAIN_DATA_SIZE];
-            data[0] = (data_size & 0xFF) as u8;
-            data[1] = (data_size >> 8) as u8;
-            data[4..(4 + root_hash.data_size as usize)]
-                .copy_from_slice(&root_hash.data[..(root_hash.data_size as usize)]);
-            data[(4 + root_hash.data_size as usize)..(data_size as usize)]
-                .copy_from_slice(&cert_chain.data[..(cert_chain.data_size as usize)]);
-            self.common.provision_info.my_cert_chain = Some(SpdmCertChainData { data_size, data });
-            debug!("my_cert_chain - {:02x?}\n", &data[..(data_size as usize)]);
-        }
+        // an extended algorithm is only selected when no base one is in common
+        let negotiate_algorithms = negotiate_algorithms.unwrap();
+        self.common.negotiate_info.ext_asym_sel =
+            if self.common.negotiate_info.base_asym_sel.is_empty() {
+                let requested =
+                    &negotiate_algorithms.ext_asym[..negotiate_algorithms.ext_asym_count as usize];
+                self.common
+                    .get_ext_algs(self.common.config_info.ext_asym_algo)
+                    .find(|ext_alg| requested.contains(ext_alg))
+            } else {
+                None
+            };
+        self.common.negotiate_info.ext_hash_sel =
+            if self.common.negotiate_info.base_hash_sel.is_empty() {
+                let requested =
+                    &negotiate_algorithms.ext_hash[..negotiate_algorithms.ext_hash_count as usize];
+                self.common
+                    .get_ext_algs(self.common.config_info.ext_hash_algo)
+                    .find(|ext_alg| requested.contains(ext_alg))
+            } else {
+                None
+            };
+
+        self.common.update_my_cert_chain();
 
         info!("send spdm algorithm\n");
+        let mut ext_asym_sel =
+            [SpdmExtAlgStruct::default(); config::MAX_SPDM_EXTEND_ASYM_ALGO_COUNT];
+        let ext_asym_sel_count = match self.common.negotiate_info.ext_asym_sel {
+            Some(ext_alg) => {
+                ext_asym_sel[0] = ext_alg;
+                1
+            }
+            None => 0,
+        };
+        let mut ext_hash_sel =
+            [SpdmExtAlgStruct::default(); config::MAX_SPDM_EXTEND_HASH_ALGO_COUNT];
+        let ext_hash_sel_count = match self.common.negotiate_info.ext_hash_sel {
+            Some(ext_alg) => {
+                ext_hash_sel[0] = ext_alg;
+                1
+            }
+            None => 0,
+        };
         let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
         let mut writer = Writer::init(&mut send_buffer);
         let response = SpdmMessage {
             header: SpdmMessageHeader {
-                version: SpdmVersion::SpdmVersion11,
+                version: self.common.negotiate_info.spdm_version_sel,
                 request_response_code: SpdmResponseResponseCode::SpdmResponseAlgorithms,
             },
             payload: SpdmMessagePayload::SpdmAlgorithmsResponse(SpdmAlgorithmsResponsePayload {
@@ -125,9 +154,14 @@ impl<'a> ResponderContext<'a> {
                     .common
                     .negotiate_info
                     .measurement_specification_sel,
+                other_params_selection: self.common.negotiate_info.opaque_support_sel,
                 measurement_hash_algo: self.common.negotiate_info.measurement_hash_sel,
                 base_asym_sel: self.common.negotiate_info.base_asym_sel,
                 base_hash_sel: self.common.negotiate_info.base_hash_sel,
+                ext_asym_sel_count,
+                ext_asym_sel,
+                ext_hash_sel_count,
+                ext_hash_sel,
                 alg_struct_count: 4,
                 alg_struct: [
                     SpdmAlgStruct {
@@ -161,13 +195,26 @@ impl<'a> ResponderContext<'a> {
                 ],
             }),
         };
-        response.spdm_encode(&mut self.common, &mut writer);
-        let used = writer.used();
+        let used = match response.spdm_encode(&mut self.common, &mut writer) {
+            Ok(used) => used,
+            Err(_) => {
+                self.send_spdm_error(SpdmErrorCode::SpdmErrorUnspecified, 0);
+                return;
+            }
+        };
         let _ = self.send_message(&send_buffer[0..used]);
 
-        self.common
+        if self
+            .common
             .runtime_info
             .message_a
-            .append_message(&send_buffer[..used]);
+            .append_message(&send_buffer[..used])
+            .is_some()
+        {
+            self.common.emit_transcript(
+                SpdmTranscriptSegment::Vca,
+                self.common.runtime_info.message_a.as_ref(),
+            );
+        }
     }
 }
@@ -0,0 +1,64 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+#![forbid(unsafe_code)]
+
+use crate::error::SpdmResult;
+use crate::responder::*;
+
+/// Answers the application messages other than SPDM received in sessions.
+#[derive(Clone, Copy)]
+pub struct SpdmAppMessageHandler {
+    /// Writes the application message answering `app_message`, received in
+    /// `session_id`, to `app_response` and returns its size, 0 to send
+    /// nothing. Both carry the transport header of the application, e.g.
+    /// the MCTP message type.
+    pub handle_app_message_cb:
+        fn(session_id: u32, app_message: &[u8], app_response: &mut [u8]) -> SpdmResult<usize>,
+}
+
+impl<'a> ResponderContext<'a> {
+    /// Hand the application messages received in sessions to `handler`.
+    /// Without one they are returned as not handled.
+    pub fn set_app_message_handler(&mut self, handler: SpdmAppMessageHandler) {
+        self.app_message_handler = Some(handler);
+    }
+
+    /// Send the application message `app_message` in the session
+    /// `session_id`. Fails with ENOSYS if the transport only carries SPDM
+    /// in sessions.
+    pub fn send_secured_app_message(&mut self, session_id: u32, app_message: &[u8]) -> SpdmResult {
+        let mut app_buffer = [0u8; config::MAX_SPDM_MESSAGE_BUFFER_SIZE];
+        let used = self
+            .common
+            .transport_encap
+            .encap_app_message(app_message, &mut app_buffer)?;
+        self.send_secured_app_buffer(session_id, &app_buffer[..used])
+    }
+
+    pub(crate) fn handle_app_message(&mut self, session_id: u32, app_message: &[u8]) -> bool {
+        self.last_error_code = None;
+        let handler = match self.app_message_handler {
+            Some(handler) => handler,
+            None => {
+                error!("!!! app_message : no handler !!!\n");
+                return false;
+            }
+        };
+
+        let mut app_response = [0u8; config::MAX_SPDM_MESSAGE_BUFFER_SIZE];
+        let used = match (handler.handle_app_message_cb)(session_id, app_message, &mut app_response)
+        {
+            Ok(used) => used,
+            Err(_) => {
+                error!("!!! app_message : handler fail !!!\n");
+                return false;
+            }
+        };
+        if used != 0 {
+            let _ = self.send_secured_app_message(session_id, &app_response[..used]);
+        }
+        true
+    }
+}
@@ -0,0 +1,84 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+#![forbid(unsafe_code)]
+
+use crate::responder::context::is_request;
+use crate::responder::*;
+use crate::time;
+
+/// Tells the responder it can't serve a request now, e.g. while the device
+/// is still computing an earlier response or refreshing its measurements.
+#[derive(Clone, Copy)]
+pub struct SpdmThrottle {
+    /// Return true to answer `request_code`, received in `session_id` or
+    /// outside of a session if None, with ERROR(Busy). The requester sends
+    /// it again later.
+    pub is_busy_cb: fn(request_code: SpdmResponseResponseCode, session_id: Option<u32>) -> bool,
+}
+
+// requests answered since `start_us`, counted against request_rate_limit
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct SpdmRequestRateWindow {
+    start_us: u64,
+    count: u32,
+}
+
+impl<'a> ResponderContext<'a> {
+    /// Have `throttle` decide whether a request is answered with
+    /// ERROR(Busy), in addition to the request_rate_limit of the config.
+    pub fn set_throttle(&mut self, throttle: SpdmThrottle) {
+        self.throttle = Some(throttle);
+    }
+
+    /// Whether to answer `request_code` with ERROR(Busy). GET_VERSION,
+    /// which restarts the connection, and the chunks of a request already
+    /// accepted are always served.
+    pub(crate) fn is_throttled(
+        &mut self,
+        request_code: SpdmResponseResponseCode,
+        session_id: Option<u32>,
+    ) -> bool {
+        if !is_request(request_code)
+            || matches!(
+                request_code,
+                SpdmResponseResponseCode::SpdmRequestGetVersion
+                    | SpdmResponseResponseCode::SpdmRequestChunkSend
+                    | SpdmResponseResponseCode::SpdmRequestChunkGet
+            )
+        {
+            return false;
+        }
+        if let Some(throttle) = self.throttle {
+            if (throttle.is_busy_cb)(request_code, session_id) {
+                info!("request {} throttled\n", request_code);
+                return true;
+            }
+        }
+
+        let request_rate_limit = self.common.config_info.request_rate_limit;
+        if request_rate_limit == 0 {
+            return false;
+        }
+        let now = time::get_time();
+        let window = &mut self.request_rate_window;
+        if now < window.start_us || now - window.start_us >= 1_000_000 {
+            window.start_us = now;
+            window.count = 0;
+        }
+        if window.count >= request_rate_limit {
+            info!("request {} over the rate limit\n", request_code);
+            return true;
+        }
+        window.count += 1;
+        false
+    }
+
+    /// ERROR(Busy) for a request received in `session_id`, in the session,
+    /// or outside of a session if None.
+    pub(crate) fn send_busy_error(&mut self, session_id: Option<u32>) -> bool {
+        self.send_spdm_error_to(session_id, SpdmErrorCode::SpdmErrorBusy, 0);
+        true
+    }
+}
@@ -9,7 +9,25 @@ use crate::responder::*;
 impl<'a> ResponderContext<'a> {
     pub fn handle_spdm_capability(&mut self, bytes: &[u8]) {
         let mut reader = Reader::init(bytes);
-        SpdmMessageHeader::read(&mut reader);
+        let message_header = SpdmMessageHeader::read(&mut reader);
+        // GET_CAPABILITIES carries the version selected by the requester
+        match message_header {
+            Some(message_header)
+                if !matches!(message_header.version, SpdmVersion::Unknown(_))
+                    && self
+                        .common
+                        .config_info
+                        .spdm_version
+                        .contains(&message_header.version) =>
+            {
+                self.common.negotiate_info.spdm_version_sel = message_header.version;
+            }
+            _ => {
+                error!("!!! get_capabilities : version mismatch !!!\n");
+                self.send_spdm_error(SpdmErrorCode::SpdmErrorMajorVersionMismatch, 0);
+                return;
+            }
+        }
 
         let get_capabilities =
             SpdmGetCapabilitiesRequestPayload::spdm_read(&mut self.common, &mut reader);
@@ -19,8 +37,15 @@ impl<'a> ResponderContext<'a> {
             self.common.negotiate_info.req_capabilities_sel = get_capabilities.flags;
             self.common.negotiate_info.rsp_ct_exponent_sel =
                 self.common.config_info.rsp_ct_exponent;
-            self.common.negotiate_info.rsp_capabilities_sel =
-                self.common.config_info.rsp_capabilities;
+            self.common.negotiate_info.rsp_capabilities_sel = self.get_rsp_capabilities();
+            self.common.negotiate_info.req_data_transfer_size_sel =
+                get_capabilities.data_transfer_size;
+            self.common.negotiate_info.req_max_spdm_msg_size_sel =
+                get_capabilities.max_spdm_msg_size;
+            self.common.negotiate_info.rsp_data_transfer_size_sel =
+                self.common.config_info.data_transfer_size;
+            self.common.negotiate_info.rsp_max_spdm_msg_size_sel =
+                self.common.config_info.max_spdm_msg_size;
         } else {
             error!("!!! get_capabilities : fail !!!\n");
             self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
@@ -43,18 +68,25 @@ impl<'a> ResponderContext<'a> {
         let mut writer = Writer::init(&mut send_buffer);
         let response = SpdmMessage {
             header: SpdmMessageHeader {
-                version: SpdmVersion::SpdmVersion11,
+                version: self.common.negotiate_info.spdm_version_sel,
                 request_response_code: SpdmResponseResponseCode::SpdmResponseCapabilities,
             },
             payload: SpdmMessagePayload::SpdmCapabilitiesResponse(
                 SpdmCapabilitiesResponsePayload {
                     ct_exponent: self.common.config_info.rsp_ct_exponent,
-                    flags: self.common.config_info.rsp_capabilities,
+                    flags: self.common.negotiate_info.rsp_capabilities_sel,
+                    data_transfer_size: self.common.config_info.data_transfer_size,
+                    max_spdm_msg_size: self.common.config_info.max_spdm_msg_size,
                 },
             ),
         };
-        response.spdm_encode(&mut self.common, &mut writer);
-        let used = writer.used();
+        let used = match response.spdm_encode(&mut self.common, &mut writer) {
+            Ok(used) => used,
+            Err(_) => {
+                self.send_spdm_error(SpdmErrorCode::SpdmErrorUnspecified, 0);
+                return;
+            }
+        };
         let _ = self.send_message(&send_buffer[0..used]);
 
         self.common
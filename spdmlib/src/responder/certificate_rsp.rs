@@ -6,6 +6,20 @@
 
 use crate::responder::*;
 
+// The portion of a `data_size` bytes cert chain sent for a request of
+// `length` bytes from `offset`, as (portion_length, remainder_length). The
+// portion is capped to what fits in CERTIFICATE. None if `offset` is not
+// within the chain or nothing is requested.
+fn get_cert_chain_portion(data_size: u16, offset: u16, length: u16) -> Option<(u16, u16)> {
+    if offset >= data_size || length == 0 {
+        return None;
+    }
+    let portion_length = length
+        .min(config::MAX_SPDM_CERT_PORTION_LEN as u16)
+        .min(data_size - offset);
+    Some((portion_length, data_size - offset - portion_length))
+}
+
 impl<'a> ResponderContext<'a> {
     pub fn handle_spdm_certificate(&mut self, bytes: &[u8]) {
         let mut reader = Reader::init(bytes);
@@ -21,42 +35,36 @@ impl<'a> ResponderContext<'a> {
             return;
         }
 
-        if self
-            .common
-            .runtime_info
-            .message_b
-            .append_message(&bytes[..reader.used()])
-            .is_none()
-        {
-            self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
-            return;
-        }
-
         let get_certificate = get_certificate.unwrap();
         let slot_id = get_certificate.slot_id;
 
-        let my_cert_chain = self.common.provision_info.my_cert_chain.unwrap();
-
-        let mut length = get_certificate.length;
-        if length > config::MAX_SPDM_CERT_PORTION_LEN as u16 {
-            length = config::MAX_SPDM_CERT_PORTION_LEN as u16;
-        }
+        let my_cert_chain = match self.common.runtime_info.my_cert_chain.get(slot_id as usize) {
+            Some(Some(my_cert_chain)) => *my_cert_chain,
+            _ => {
+                error!(
+                    "!!! get_certificate : slot {} not provisioned !!!\n",
+                    slot_id
+                );
+                self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+                return;
+            }
+        };
 
         let offset = get_certificate.offset;
-        if offset > my_cert_chain.data_size {
-            self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
-            return;
-        }
-
-        if length > my_cert_chain.data_size - offset {
-            length = my_cert_chain.data_size - offset;
-        }
-
-        let portion_length = length as u16;
-        let remainder_length = my_cert_chain.data_size - (length + offset);
-
+        let (portion_length, remainder_length) =
+            match get_cert_chain_portion(my_cert_chain.data_size, offset, get_certificate.length) {
+                Some(portion) => portion,
+                None => {
+                    error!(
+                        "!!! get_certificate : invalid offset {} length {} !!!\n",
+                        offset, get_certificate.length
+                    );
+                    self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+                    return;
+                }
+            };
         let cert_chain_data =
-            &my_cert_chain.data[(offset as usize)..(offset as usize + length as usize)];
+            &my_cert_chain.data[(offset as usize)..(offset as usize + portion_length as usize)];
 
         info!("send spdm certificate\n");
         let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
@@ -66,7 +74,7 @@ impl<'a> ResponderContext<'a> {
         cert_chain[..cert_chain_data.len()].copy_from_slice(cert_chain_data);
         let response = SpdmMessage {
             header: SpdmMessageHeader {
-                version: SpdmVersion::SpdmVersion11,
+                version: self.common.negotiate_info.spdm_version_sel,
                 request_response_code: SpdmResponseResponseCode::SpdmResponseCertificate,
             },
             payload: SpdmMessagePayload::SpdmCertificateResponse(SpdmCertificateResponsePayload {
@@ -76,8 +84,26 @@ impl<'a> ResponderContext<'a> {
                 cert_chain,
             }),
         };
-        response.spdm_encode(&mut self.common, &mut writer);
-        let used = writer.used();
+        let used = match response.spdm_encode(&mut self.common, &mut writer) {
+            Ok(used) => used,
+            Err(_) => {
+                self.send_spdm_error(SpdmErrorCode::SpdmErrorUnspecified, 0);
+                return;
+            }
+        };
+
+        // a rejected request is left out of the transcript
+        if self
+            .common
+            .runtime_info
+            .message_b
+            .append_message(&bytes[..reader.used()])
+            .is_none()
+        {
+            self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+            return;
+        }
+
         let _ = self.send_message(&send_buffer[0..used]);
 
         self.common
@@ -86,3 +112,35 @@ impl<'a> ResponderContext<'a> {
             .append_message(&send_buffer[..used]);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_cert_chain_portion() {
+        let max_portion_length = config::MAX_SPDM_CERT_PORTION_LEN as u16;
+        let data_size = max_portion_length + 10;
+
+        assert_eq!(
+            get_cert_chain_portion(data_size, 0, 8),
+            Some((8, data_size - 8))
+        );
+        assert_eq!(
+            get_cert_chain_portion(data_size, 0, u16::MAX),
+            Some((max_portion_length, 10))
+        );
+        assert_eq!(
+            get_cert_chain_portion(data_size, max_portion_length, u16::MAX),
+            Some((10, 0))
+        );
+        assert_eq!(
+            get_cert_chain_portion(data_size, data_size - 1, u16::MAX),
+            Some((1, 0))
+        );
+        assert_eq!(get_cert_chain_portion(data_size, data_size, 1), None);
+        assert_eq!(get_cert_chain_portion(data_size, u16::MAX, u16::MAX), None);
+        assert_eq!(get_cert_chain_portion(data_size, 0, 0), None);
+        assert_eq!(get_cert_chain_portion(0, 0, 1), None);
+    }
+}
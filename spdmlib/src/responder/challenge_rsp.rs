@@ -4,7 +4,6 @@
 
 #![forbid(unsafe_code)]
 
-use crate::crypto;
 use crate::responder::*;
 
 impl<'a> ResponderContext<'a> {
@@ -31,6 +30,37 @@ impl<'a> ResponderContext<'a> {
             return;
         }
 
+        let challenge = challenge.unwrap();
+        if challenge.measurement_summary_hash_type
+            != SpdmMeasurementSummaryHashType::SpdmMeasurementSummaryHashTypeNone
+            && !self.common.negotiate_info.rsp_capabilities_sel.intersects(
+                SpdmResponseCapabilityFlags::MEAS_CAP_SIG
+                    | SpdmResponseCapabilityFlags::MEAS_CAP_NO_SIG,
+            )
+        {
+            error!("!!! challenge : measurement summary hash without MEAS_CAP !!!\n");
+            self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+            return;
+        }
+        if let Some(last_nonce) = self.common.runtime_info.last_challenge_nonce {
+            if last_nonce.data == challenge.nonce.data {
+                error!("!!! challenge : nonce reused !!!\n");
+                self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+                return;
+            }
+        }
+
+        let slot_id = challenge.slot_id;
+        if self
+            .common
+            .set_my_signing_slot_id(SpdmSigningPurpose::Challenge, slot_id)
+            .is_err()
+        {
+            error!("!!! challenge : slot {} not usable !!!\n", slot_id);
+            self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+            return;
+        }
+
         if self
             .common
             .runtime_info
@@ -44,46 +74,71 @@ impl<'a> ResponderContext<'a> {
 
         info!("send spdm challenge_auth\n");
 
-        let my_cert_chain = self.common.provision_info.my_cert_chain.unwrap();
-        let cert_chain_hash = crypto::hash::hash_all(
+        let measurement_summary_hash =
+            match self.generate_measurement_summary_hash(challenge.measurement_summary_hash_type) {
+                Ok(measurement_summary_hash) => measurement_summary_hash,
+                Err(_) => {
+                    error!("!!! measurement_summary_hash : fail !!!\n");
+                    self.send_spdm_error(SpdmErrorCode::SpdmErrorUnspecified, 0);
+                    return;
+                }
+            };
+
+        let my_cert_chain = match self.common.runtime_info.my_cert_chain[slot_id as usize] {
+            Some(my_cert_chain) => my_cert_chain,
+            None => {
+                self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+                return;
+            }
+        };
+        let cert_chain_hash = match self.common.crypto_provider.hash_all(
             self.common.negotiate_info.base_hash_sel,
             my_cert_chain.as_ref(),
-        )
-        .unwrap();
+        ) {
+            Some(cert_chain_hash) => cert_chain_hash,
+            None => {
+                self.common.runtime_info.message_c.reset_message();
+                self.send_crypto_failure(SpdmCryptoOperation::Hash);
+                return;
+            }
+        };
+
+        let mut nonce = [0u8; SPDM_NONCE_SIZE];
+        if self.common.crypto_provider.get_random(&mut nonce).is_err() {
+            self.common.runtime_info.message_c.reset_message();
+            self.send_crypto_failure(SpdmCryptoOperation::Random);
+            return;
+        }
 
         let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
         let mut writer = Writer::init(&mut send_buffer);
         let response = SpdmMessage {
             header: SpdmMessageHeader {
-                version: SpdmVersion::SpdmVersion11,
+                version: self.common.negotiate_info.spdm_version_sel,
                 request_response_code: SpdmResponseResponseCode::SpdmResponseChallengeAuth,
             },
             payload: SpdmMessagePayload::SpdmChallengeAuthResponse(
                 SpdmChallengeAuthResponsePayload {
-                    slot_id: 0x0,
-                    slot_mask: 0x1,
+                    slot_id,
+                    slot_mask: self.common.get_my_slot_mask(),
                     challenge_auth_attribute: SpdmChallengeAuthAttribute::empty(),
                     cert_chain_hash,
-                    nonce: SpdmNonceStruct {
-                        data: [0x5bu8; SPDM_NONCE_SIZE],
-                    },
-                    measurement_summary_hash: SpdmDigestStruct {
-                        data_size: self.common.negotiate_info.base_hash_sel.get_size(),
-                        data: [0xaa; SPDM_MAX_HASH_SIZE],
-                    },
-                    opaque: SpdmOpaqueStruct {
-                        data_size: 0,
-                        data: [0u8; config::MAX_SPDM_OPAQUE_SIZE],
-                    },
-                    signature: SpdmSignatureStruct {
-                        data_size: self.common.negotiate_info.base_asym_sel.get_size(),
-                        data: [0xbb; SPDM_MAX_ASYM_KEY_SIZE],
-                    },
+                    nonce: SpdmNonceStruct { data: nonce },
+                    measurement_summary_hash,
+                    opaque: self.common.config_info.challenge_opaque_data,
+                    signature: SpdmSignatureStruct::placeholder(
+                        self.common.negotiate_info.base_asym_sel.get_size(),
+                    ),
                 },
             ),
         };
-        response.spdm_encode(&mut self.common, &mut writer);
-        let used = writer.used();
+        let used = match response.spdm_encode(&mut self.common, &mut writer) {
+            Ok(used) => used,
+            Err(_) => {
+                self.send_spdm_error(SpdmErrorCode::SpdmErrorUnspecified, 0);
+                return;
+            }
+        };
 
         // generat signature
         let base_asym_size = self.common.negotiate_info.base_asym_sel.get_size() as usize;
@@ -95,13 +150,19 @@ impl<'a> ResponderContext<'a> {
 
         let signature = self.common.generate_challenge_auth_signature();
         if signature.is_err() {
-            self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+            self.common.runtime_info.message_c.reset_message();
+            self.send_crypto_failure(SpdmCryptoOperation::Sign);
             return;
         }
         let signature = signature.unwrap();
         // patch the message before send
         send_buffer[(used - base_asym_size)..used].copy_from_slice(signature.as_ref());
+        self.common.runtime_info.last_challenge_nonce = Some(challenge.nonce);
 
         let _ = self.send_message(&send_buffer[0..used]);
+
+        // the next CHALLENGE signs the exchanges from here on
+        self.common.runtime_info.message_b.reset_message();
+        self.common.runtime_info.message_c.reset_message();
     }
 }
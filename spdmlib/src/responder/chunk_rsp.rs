@@ -0,0 +1,301 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+#![forbid(unsafe_code)]
+
+use crate::common::SpdmChunkStatus;
+use crate::error::SpdmResult;
+use crate::responder::*;
+
+// SPDM header, ChunkSeqNo, Reserved, ChunkSize
+#[cfg(feature = "responder-chunk")]
+const CHUNK_HEADER_SIZE: usize = 12;
+// LargeMessageSize, first chunk only
+#[cfg(feature = "responder-chunk")]
+const LARGE_MESSAGE_SIZE_FIELD_SIZE: usize = 4;
+// SPDM header, ChunkSeqNo
+const CHUNK_SEND_ACK_HEADER_SIZE: usize = 6;
+
+impl<'a> ResponderContext<'a> {
+    // Largest message the requester accepts in one piece.
+    pub(crate) fn get_max_response_size(&self) -> usize {
+        let data_transfer_size = self.common.negotiate_info.req_data_transfer_size_sel as usize;
+        if data_transfer_size > config::MAX_SPDM_TRANSPORT_SIZE {
+            config::MAX_SPDM_TRANSPORT_SIZE
+        } else {
+            data_transfer_size
+        }
+    }
+
+    // Keep `response` for CHUNK_GET and encode ERROR(LargeResponse) into
+    // `error_buffer` to be sent instead.
+    fn hold_large_response(
+        &mut self,
+        response: &[u8],
+        error_buffer: &mut [u8],
+    ) -> SpdmResult<usize> {
+        self.common
+            .chunk_context
+            .start(SpdmChunkStatus::LargeResponse, response.len())?;
+        self.common.chunk_context.large_message[..response.len()].copy_from_slice(response);
+        info!("hold large response {:x?} for CHUNK_GET\n", response.len());
+
+        let mut writer = Writer::init(error_buffer);
+        let error = SpdmMessage {
+            header: SpdmMessageHeader {
+                version: self.common.negotiate_info.spdm_version_sel,
+                request_response_code: SpdmResponseResponseCode::SpdmResponseError,
+            },
+            payload: SpdmMessagePayload::SpdmErrorResponse(SpdmErrorResponsePayload {
+                error_code: SpdmErrorCode::SpdmErrorLargeResponse,
+                error_data: 0,
+                extended_data: SpdmErrorResponseExtData::SpdmErrorExtDataLargeResponse(
+                    SpdmErrorResponseLargeResponseExtData {
+                        handle: self.common.chunk_context.handle,
+                    },
+                ),
+            }),
+        };
+        error.spdm_encode(&mut self.common, &mut writer)
+    }
+
+    pub(crate) fn send_large_response(&mut self, response: &[u8]) -> SpdmResult {
+        let mut error_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        let used = self.hold_large_response(response, &mut error_buffer)?;
+        self.send_message_unchunked(&error_buffer[..used])
+    }
+
+    // `response` is the response to the large request, empty for the
+    // chunks before the last one.
+    pub(crate) fn send_chunk_send_ack(
+        &mut self,
+        handle: u8,
+        chunk_seq_no: u16,
+        response: &[u8],
+    ) -> SpdmResult {
+        let mut error_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        let response = if response.len() + CHUNK_SEND_ACK_HEADER_SIZE > self.get_max_response_size()
+        {
+            let used = self.hold_large_response(response, &mut error_buffer)?;
+            &error_buffer[..used]
+        } else {
+            response
+        };
+
+        info!("send spdm chunk send ack\n");
+        let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        let mut writer = Writer::init(&mut send_buffer);
+        let mut payload = SpdmChunkSendAckResponsePayload {
+            handle,
+            chunk_seq_no,
+            response_size: response.len() as u32,
+            ..Default::default()
+        };
+        payload.response[..response.len()].copy_from_slice(response);
+        let ack = SpdmMessage {
+            header: SpdmMessageHeader {
+                version: self.common.negotiate_info.spdm_version_sel,
+                request_response_code: SpdmResponseResponseCode::SpdmResponseChunkSendAck,
+            },
+            payload: SpdmMessagePayload::SpdmChunkSendAckResponse(payload),
+        };
+        let used = ack.spdm_encode(&mut self.common, &mut writer)?;
+        self.send_message_unchunked(&send_buffer[..used])
+    }
+
+    #[cfg(feature = "responder-chunk")]
+    pub fn handle_spdm_chunk_send(&mut self, bytes: &[u8]) {
+        let mut reader = Reader::init(bytes);
+        SpdmMessageHeader::read(&mut reader);
+
+        let chunk_send = SpdmChunkSendRequestPayload::spdm_read(&mut self.common, &mut reader);
+        let chunk_send = match chunk_send {
+            Some(chunk_send) => chunk_send,
+            None => {
+                error!("!!! chunk_send : fail !!!\n");
+                self.common.chunk_context.reset();
+                self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+                return;
+            }
+        };
+        debug!(
+            "!!! chunk_send : {:02x?} {:02x?}\n",
+            chunk_send.handle, chunk_send.chunk_seq_no
+        );
+
+        if chunk_send.chunk_seq_no == 0 {
+            let large_message_size = chunk_send.large_message_size as usize;
+            if large_message_size < chunk_send.chunk_size as usize
+                || self
+                    .common
+                    .chunk_context
+                    .start(SpdmChunkStatus::LargeRequest, large_message_size)
+                    .is_err()
+            {
+                error!("!!! chunk_send : large request too big !!!\n");
+                self.common.chunk_context.reset();
+                self.send_spdm_error(SpdmErrorCode::SpdmErrorRequestTooLarge, 0);
+                return;
+            }
+            // the handle of a large request is picked by the requester
+            self.common.chunk_context.handle = chunk_send.handle;
+        } else if self.common.chunk_context.status != SpdmChunkStatus::LargeRequest {
+            error!("!!! chunk_send : no large request in progress !!!\n");
+            self.send_spdm_error(SpdmErrorCode::SpdmErrorUnexpectedRequest, 0);
+            return;
+        }
+
+        let chunk_context = &mut self.common.chunk_context;
+        let chunk_size = chunk_send.chunk_size as usize;
+        if chunk_send.handle != chunk_context.handle
+            || chunk_send.chunk_seq_no != chunk_context.chunk_seq_no
+            || chunk_context.transferred_size + chunk_size > chunk_context.large_message_size
+        {
+            error!("!!! chunk_send : unexpected chunk !!!\n");
+            chunk_context.reset();
+            self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+            return;
+        }
+        chunk_context.large_message
+            [chunk_context.transferred_size..(chunk_context.transferred_size + chunk_size)]
+            .copy_from_slice(&chunk_send.chunk[..chunk_size]);
+        chunk_context.transferred_size += chunk_size;
+        chunk_context.chunk_seq_no = chunk_context.chunk_seq_no.wrapping_add(1);
+
+        let last_chunk = chunk_send
+            .attributes
+            .contains(SpdmChunkAttributes::LAST_CHUNK);
+        if last_chunk != chunk_context.is_complete() {
+            error!("!!! chunk_send : large request size mismatch !!!\n");
+            chunk_context.reset();
+            self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+            return;
+        }
+
+        if !last_chunk {
+            let _ = self.send_chunk_send_ack(chunk_send.handle, chunk_send.chunk_seq_no, &[]);
+            return;
+        }
+
+        let mut large_request = [0u8; config::MAX_SPDM_MESSAGE_BUFFER_SIZE];
+        let large_request_size = chunk_context.large_message_size;
+        large_request[..large_request_size].copy_from_slice(chunk_context.get_large_message());
+        chunk_context.reset();
+
+        // the response to the large request goes into the last CHUNK_SEND_ACK
+        self.chunk_send_ack = Some((chunk_send.handle, chunk_send.chunk_seq_no));
+        let request_code =
+            SpdmMessageHeader::read(&mut Reader::init(&large_request[..large_request_size]))
+                .map(|h| h.request_response_code);
+        let status = match request_code {
+            Some(SpdmResponseResponseCode::SpdmRequestChunkSend)
+            | Some(SpdmResponseResponseCode::SpdmRequestChunkGet) => {
+                error!("!!! chunk_send : nested chunk request !!!\n");
+                self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+                ProcessStatus::ErrorSent(SpdmErrorCode::SpdmErrorInvalidRequest)
+            }
+            _ => {
+                self.dispatch_message(&large_request[..large_request_size])
+                    .status
+            }
+        };
+        match status {
+            ProcessStatus::ResponseSent => {}
+            ProcessStatus::ErrorSent(error_code) => self.last_error_code = Some(error_code),
+            ProcessStatus::NotHandled => {
+                self.send_spdm_error(SpdmErrorCode::SpdmErrorUnsupportedRequest, 0)
+            }
+        }
+        self.chunk_send_ack = None;
+    }
+
+    #[cfg(feature = "responder-chunk")]
+    pub fn handle_spdm_chunk_get(&mut self, bytes: &[u8]) {
+        let mut reader = Reader::init(bytes);
+        SpdmMessageHeader::read(&mut reader);
+
+        let chunk_get = SpdmChunkGetRequestPayload::spdm_read(&mut self.common, &mut reader);
+        let chunk_get = match chunk_get {
+            Some(chunk_get) => chunk_get,
+            None => {
+                error!("!!! chunk_get : fail !!!\n");
+                self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+                return;
+            }
+        };
+        debug!("!!! chunk_get : {:02x?}\n", chunk_get);
+
+        if self.common.chunk_context.status != SpdmChunkStatus::LargeResponse {
+            error!("!!! chunk_get : no large response !!!\n");
+            self.send_spdm_error(SpdmErrorCode::SpdmErrorUnexpectedRequest, 0);
+            return;
+        }
+        if chunk_get.handle != self.common.chunk_context.handle
+            || chunk_get.chunk_seq_no != self.common.chunk_context.chunk_seq_no
+        {
+            error!("!!! chunk_get : unexpected chunk !!!\n");
+            self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+            return;
+        }
+
+        let mut header_size = CHUNK_HEADER_SIZE;
+        if chunk_get.chunk_seq_no == 0 {
+            header_size += LARGE_MESSAGE_SIZE_FIELD_SIZE;
+        }
+        let max_response_size = self.get_max_response_size();
+        if max_response_size <= header_size {
+            self.send_spdm_error(SpdmErrorCode::SpdmErrorUnspecified, 0);
+            return;
+        }
+
+        let chunk_context = &self.common.chunk_context;
+        let offset = chunk_context.transferred_size;
+        let mut chunk_size = chunk_context.large_message_size - offset;
+        if chunk_size > max_response_size - header_size {
+            chunk_size = max_response_size - header_size;
+        }
+        let last_chunk = offset + chunk_size == chunk_context.large_message_size;
+        let mut payload = SpdmChunkResponsePayload {
+            attributes: if last_chunk {
+                SpdmChunkAttributes::LAST_CHUNK
+            } else {
+                SpdmChunkAttributes::empty()
+            },
+            handle: chunk_context.handle,
+            chunk_seq_no: chunk_context.chunk_seq_no,
+            large_message_size: chunk_context.large_message_size as u32,
+            chunk_size: chunk_size as u32,
+            ..Default::default()
+        };
+        payload.chunk[..chunk_size]
+            .copy_from_slice(&chunk_context.large_message[offset..(offset + chunk_size)]);
+
+        info!("send spdm chunk response\n");
+        let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        let mut writer = Writer::init(&mut send_buffer);
+        let response = SpdmMessage {
+            header: SpdmMessageHeader {
+                version: self.common.negotiate_info.spdm_version_sel,
+                request_response_code: SpdmResponseResponseCode::SpdmResponseChunkResponse,
+            },
+            payload: SpdmMessagePayload::SpdmChunkResponse(payload),
+        };
+        let used = match response.spdm_encode(&mut self.common, &mut writer) {
+            Ok(used) => used,
+            Err(_) => {
+                self.send_spdm_error(SpdmErrorCode::SpdmErrorUnspecified, 0);
+                return;
+            }
+        };
+        let _ = self.send_message_unchunked(&send_buffer[..used]);
+
+        let chunk_context = &mut self.common.chunk_context;
+        if last_chunk {
+            chunk_context.reset();
+        } else {
+            chunk_context.transferred_size += chunk_size;
+            chunk_context.chunk_seq_no = chunk_context.chunk_seq_no.wrapping_add(1);
+        }
+    }
+}
@@ -4,14 +4,90 @@
 
 #![forbid(unsafe_code)]
 
-use crate::common::{self, SpdmDeviceIo, SpdmTransportEncap};
+use crate::common::{
+    self, SpdmConnectionState, SpdmDeviceIo, SpdmMeasurementIdentifier, SpdmSigningPurpose,
+    SpdmTransportEncap,
+};
 use crate::config;
 use crate::error::SpdmResult;
 use crate::msgs::*;
+use crate::responder::busy_rsp::SpdmRequestRateWindow;
+#[cfg(any(
+    feature = "responder-challenge",
+    feature = "responder-key-exchange",
+    feature = "responder-psk-exchange"
+))]
+use crate::responder::measurement_rsp::SpdmMeasurementSummaryHashCache;
+use crate::responder::{
+    SpdmAppMessageHandler, SpdmCryptoFailureStats, SpdmMeasurementProvider, SpdmThrottle,
+    SpdmVendorDefinedHandler, MAX_SPDM_VENDOR_DEFINED_HANDLERS,
+};
+use crate::session::SpdmSessionState;
+use crate::trace::{self, SpdmMessageDirection};
 use codec::{Codec, Reader};
 
+bitflags! {
+    /// Requests the responder only answers inside an established session,
+    /// e.g. because the measurement values are confidential.
+    #[derive(Default)]
+    pub struct SpdmSessionRequiredRequests: u8 {
+        const GET_MEASUREMENTS = 0b0000_0001;
+        // CHALLENGE is never allowed in a session, so it is rejected altogether.
+        const CHALLENGE = 0b0000_0010;
+    }
+}
+
 pub struct ResponderContext<'a> {
     pub common: common::SpdmContext<'a>,
+    pub(crate) measurement_provider: Option<SpdmMeasurementProvider>,
+    #[cfg(any(
+        feature = "responder-challenge",
+        feature = "responder-key-exchange",
+        feature = "responder-psk-exchange"
+    ))]
+    pub(crate) measurement_summary_hash_cache: Option<SpdmMeasurementSummaryHashCache>,
+    // ERROR sent while handling the current request
+    pub(crate) last_error_code: Option<SpdmErrorCode>,
+    // (handle, chunk_seq_no) of the CHUNK_SEND whose large request is being handled
+    pub(crate) chunk_send_ack: Option<(u8, u16)>,
+    // (request_id, request code) of the encapsulated request sent for mutual authentication
+    pub(crate) encap_request: Option<(u8, SpdmResponseResponseCode)>,
+    pub(crate) session_required_requests: SpdmSessionRequiredRequests,
+    pub(crate) vendor_defined_handlers:
+        [Option<SpdmVendorDefinedHandler>; MAX_SPDM_VENDOR_DEFINED_HANDLERS],
+    pub(crate) app_message_handler: Option<SpdmAppMessageHandler>,
+    pub(crate) crypto_failure_stats: SpdmCryptoFailureStats,
+    pub(crate) throttle: Option<SpdmThrottle>,
+    pub(crate) request_rate_window: SpdmRequestRateWindow,
+    // the capabilities advertised changed since the requester got them
+    pub(crate) capabilities_changed: bool,
+}
+
+/// What `process_message()` did with a received message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessStatus {
+    /// The request was answered.
+    ResponseSent,
+    /// The request was answered with ERROR.
+    ErrorSent(SpdmErrorCode),
+    /// Not an SPDM request this responder serves (in this session state),
+    /// nothing was sent.
+    NotHandled,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProcessResult {
+    /// `None` if the message has no valid SPDM header.
+    pub request_code: Option<SpdmResponseResponseCode>,
+    /// Session the message was received in, `None` for plain messages.
+    pub session_id: Option<u32>,
+    pub status: ProcessStatus,
+}
+
+impl ProcessResult {
+    pub fn is_handled(&self) -> bool {
+        self.status != ProcessStatus::NotHandled
+    }
 }
 
 impl<'a> ResponderContext<'a> {
@@ -28,47 +104,555 @@ impl<'a> ResponderContext<'a> {
                 config_info,
                 provision_info,
             ),
+            measurement_provider: None,
+            #[cfg(any(
+                feature = "responder-challenge",
+                feature = "responder-key-exchange",
+                feature = "responder-psk-exchange"
+            ))]
+            measurement_summary_hash_cache: None,
+            last_error_code: None,
+            chunk_send_ack: None,
+            encap_request: None,
+            session_required_requests: SpdmSessionRequiredRequests::empty(),
+            vendor_defined_handlers: [None; MAX_SPDM_VENDOR_DEFINED_HANDLERS],
+            app_message_handler: None,
+            crypto_failure_stats: SpdmCryptoFailureStats::default(),
+            throttle: None,
+            request_rate_window: SpdmRequestRateWindow::default(),
+            capabilities_changed: false,
+        }
+    }
+
+    /// Serve MEASUREMENTS from `measurement_provider` instead of the built-in
+    /// DMTF blocks. Only specifications it supports are negotiated.
+    pub fn set_measurement_provider(&mut self, measurement_provider: SpdmMeasurementProvider) {
+        self.measurement_provider = Some(measurement_provider);
+        #[cfg(any(
+            feature = "responder-challenge",
+            feature = "responder-key-exchange",
+            feature = "responder-psk-exchange"
+        ))]
+        {
+            self.measurement_summary_hash_cache = None;
+        }
+    }
+
+    /// Only answer `session_required_requests` inside an established session.
+    /// Outside of it they get ERROR(SessionRequired), or ERROR(UnexpectedRequest)
+    /// before SPDM 1.2.
+    pub fn set_session_required_requests(
+        &mut self,
+        session_required_requests: SpdmSessionRequiredRequests,
+    ) {
+        self.session_required_requests = session_required_requests;
+    }
+
+    // GET_CAPABILITIES and NEGOTIATE_ALGORITHMS only follow the previous
+    // step of VCA, the other requests need the algorithms to be negotiated.
+    fn is_request_expected(&self, request_code: SpdmResponseResponseCode) -> bool {
+        match request_code {
+            SpdmResponseResponseCode::SpdmRequestGetVersion => true,
+            SpdmResponseResponseCode::SpdmRequestGetCapabilities => {
+                self.common.connection_state == SpdmConnectionState::AfterVersion
+            }
+            SpdmResponseResponseCode::SpdmRequestNegotiateAlgorithms => {
+                self.common.connection_state == SpdmConnectionState::AfterCapabilities
+            }
+            // the chunk size is known from CAPABILITIES
+            SpdmResponseResponseCode::SpdmRequestChunkSend
+            | SpdmResponseResponseCode::SpdmRequestChunkGet => {
+                self.common.connection_state >= SpdmConnectionState::AfterCapabilities
+            }
+            _ if is_request(request_code) => {
+                self.common.connection_state >= SpdmConnectionState::Negotiated
+            }
+            // not a request, left to the dispatcher
+            _ => true,
+        }
+    }
+
+    // after `request_code` is answered successfully
+    fn update_connection_state(&mut self, request_code: SpdmResponseResponseCode) {
+        let connection_state = match request_code {
+            SpdmResponseResponseCode::SpdmRequestGetVersion => SpdmConnectionState::AfterVersion,
+            SpdmResponseResponseCode::SpdmRequestGetCapabilities => {
+                SpdmConnectionState::AfterCapabilities
+            }
+            SpdmResponseResponseCode::SpdmRequestNegotiateAlgorithms => {
+                SpdmConnectionState::Negotiated
+            }
+            SpdmResponseResponseCode::SpdmRequestGetDigests => SpdmConnectionState::AfterDigests,
+            SpdmResponseResponseCode::SpdmRequestGetCertificate => {
+                SpdmConnectionState::AfterCertificate
+            }
+            SpdmResponseResponseCode::SpdmRequestChallenge => SpdmConnectionState::Authenticated,
+            _ => return,
+        };
+        self.common.advance_connection_state(connection_state);
+    }
+
+    // A known request this responder doesn't serve here, e.g. FINISH
+    // outside of a session.
+    fn send_unexpected_request_error(
+        &mut self,
+        session_id: Option<u32>,
+        request_code: SpdmResponseResponseCode,
+    ) -> bool {
+        error!("!!! unexpected request : {} !!!\n", request_code);
+        self.send_spdm_error_to(session_id, SpdmErrorCode::SpdmErrorUnexpectedRequest, 0);
+        true
+    }
+
+    // Request codes this responder doesn't implement. Anything else is not
+    // a request and left to the caller.
+    fn send_unsupported_request_error(
+        &mut self,
+        session_id: Option<u32>,
+        request_code: SpdmResponseResponseCode,
+    ) -> bool {
+        let code = request_code.get_u8();
+        if code & 0x80 == 0 {
+            return false;
+        }
+        error!("!!! unsupported request : {} !!!\n", request_code);
+        self.send_spdm_error_to(session_id, SpdmErrorCode::SpdmErrorUnsupportedRequest, code);
+        true
+    }
+
+    // SESSION_REQUIRED is new in SPDM 1.2.
+    fn send_session_required_error(&mut self, request_code: SpdmResponseResponseCode) -> bool {
+        error!("!!! session required : {} !!!\n", request_code);
+        if self.common.negotiate_info.spdm_version_sel.get_u8()
+            >= SpdmVersion::SpdmVersion12.get_u8()
+        {
+            self.send_spdm_error(SpdmErrorCode::SpdmErrorSessionRequired, 0);
+        } else {
+            self.send_spdm_error(SpdmErrorCode::SpdmErrorUnexpectedRequest, 0);
+        }
+        true
+    }
+
+    // FINISH and PSK_FINISH only complete the handshake of their own kind of
+    // session, the other requests need the handshake completed.
+    fn is_request_expected_in_session(
+        &mut self,
+        session_id: u32,
+        request_code: SpdmResponseResponseCode,
+    ) -> bool {
+        let session = match self.common.get_session_via_id(session_id) {
+            Some(session) => session,
+            None => return false,
+        };
+        let state = session.get_session_state();
+        match request_code {
+            // the encapsulated flow checks the session state itself
+            SpdmResponseResponseCode::SpdmRequestGetVersion
+            | SpdmResponseResponseCode::SpdmRequestGetEncapsulatedRequest
+            | SpdmResponseResponseCode::SpdmRequestDeliverEncapsulatedResponse => true,
+            SpdmResponseResponseCode::SpdmRequestFinish => {
+                state == SpdmSessionState::SpdmSessionHandshaking && !session.get_use_psk()
+            }
+            SpdmResponseResponseCode::SpdmRequestPskFinish => {
+                state == SpdmSessionState::SpdmSessionHandshaking && session.get_use_psk()
+            }
+            _ if is_request(request_code) => state == SpdmSessionState::SpdmSessionEstablished,
+            _ => true,
+        }
+    }
+
+    fn is_session_required(&self, request_code: SpdmResponseResponseCode) -> bool {
+        match request_code {
+            SpdmResponseResponseCode::SpdmRequestGetMeasurements => self
+                .session_required_requests
+                .contains(SpdmSessionRequiredRequests::GET_MEASUREMENTS),
+            SpdmResponseResponseCode::SpdmRequestChallenge => self
+                .session_required_requests
+                .contains(SpdmSessionRequiredRequests::CHALLENGE),
+            _ => false,
+        }
+    }
+
+    /// Tell the responder that the device measurements changed at runtime
+    /// (e.g. a firmware update was activated). Sessions whose requester set
+    /// the termination policy in KEY_EXCHANGE are terminated, and can't be
+    /// resumed.
+    pub fn notify_measurement_update(&mut self) {
+        #[cfg(any(
+            feature = "responder-challenge",
+            feature = "responder-key-exchange",
+            feature = "responder-psk-exchange"
+        ))]
+        {
+            self.measurement_summary_hash_cache = None;
+        }
+        if let Some(resumption_psk) = &self.common.peer_info.resumption_psk {
+            if resumption_psk
+                .session_policy
+                .contains(SpdmKeyExchangeSessionPolicy::TERMINATION_POLICY_RUNTIME_UPDATE)
+            {
+                self.common.peer_info.resumption_psk = None;
+            }
         }
+        for session in self.common.session.iter_mut() {
+            let session_id = session.get_session_id();
+            if session_id != 0
+                && session
+                    .get_session_policy()
+                    .contains(SpdmKeyExchangeSessionPolicy::TERMINATION_POLICY_RUNTIME_UPDATE)
+            {
+                info!(
+                    "terminate session {:08x} on measurement update\n",
+                    session_id
+                );
+                session.teardown();
+            }
+        }
+    }
+
+    /// IDs of the sessions that are set up, including those still in the
+    /// handshake.
+    pub fn get_active_session_ids(&self) -> impl Iterator<Item = u32> + '_ {
+        self.common.get_active_session_ids()
+    }
+
+    /// State of the session `session_id`, None if there is no such session.
+    pub fn get_session_state(&self, session_id: u32) -> Option<SpdmSessionState> {
+        self.common
+            .session
+            .iter()
+            .find(|session| session_id != 0 && session.get_session_id() == session_id)
+            .map(|session| session.get_session_state())
     }
 
+    /// Terminate the session `session_id` as if the requester had sent
+    /// END_SESSION, without sending anything. Its secrets are wiped and
+    /// further messages in it are not decoded.
+    pub fn terminate_session(&mut self, session_id: u32) -> SpdmResult {
+        match self.common.get_session_via_id(session_id) {
+            Some(session) => {
+                info!("terminate session {:08x}\n", session_id);
+                session.teardown();
+                Ok(())
+            }
+            None => spdm_result_err!(ENOENT, "no such session"),
+        }
+    }
+
+    // Terminate the sessions set up longer than max_session_lifetime ago.
+    fn expire_sessions(&mut self) {
+        let max_session_lifetime = self.common.config_info.max_session_lifetime;
+        if max_session_lifetime == 0 {
+            return;
+        }
+        for session in self.common.session.iter_mut() {
+            let session_id = session.get_session_id();
+            if session_id != 0
+                && session.get_time_since_setup() > max_session_lifetime as u64 * 1_000_000
+            {
+                info!("terminate session {:08x} on lifetime expiry\n", session_id);
+                session.teardown();
+            }
+        }
+    }
+
+    /// Measurement specifications offered in ALGORITHMS.
+    pub fn get_measurement_specification(&self) -> SpdmMeasurementSpecification {
+        let supported = match self.measurement_provider {
+            Some(provider) => provider.measurement_specification,
+            None => SpdmMeasurementSpecification::DMTF,
+        };
+        self.common.config_info.measurement_specification & supported
+    }
+
+    /// CoRIM identifier of the measurement block `index`, for evidence the
+    /// device reports about itself.
+    pub fn get_measurement_identifier(
+        &self,
+        index: u8,
+    ) -> Option<SpdmMeasurementIdentifier<'static>> {
+        (self.measurement_provider?.get_measurement_identifier_cb?)(index)
+    }
+
+    /// Install the cert chain of `slot_id` on a responder that was started without
+    /// it (e.g. the chain is only available after a secure element is unlocked).
+    /// Cert dependent requests are answered with ERROR(Busy) until a slot is provisioned.
+    /// If the capabilities the requester got change, its next request is answered with
+    /// ERROR(RequestResynch), so it gets them again. Nothing is installed on error,
+    /// including a chain that doesn't verify with `my_cert_chain_config`, doesn't start
+    /// with `my_root_cert` or whose leaf key isn't the one `slot_id` is signed with.
+    pub fn provision_complete(
+        &mut self,
+        slot_id: u8,
+        my_cert_chain_data: SpdmCertChainData,
+    ) -> SpdmResult {
+        if slot_id as usize >= SPDM_MAX_SLOT_NUMBER {
+            return spdm_result_err!(EINVAL);
+        }
+        // room for the header with the root hash of any algorithm
+        if my_cert_chain_data.data_size == 0
+            || my_cert_chain_data.data_size as usize + 4 + SPDM_MAX_HASH_SIZE
+                > config::MAX_SPDM_CERT_CHAIN_DATA_SIZE
+        {
+            return spdm_result_err!(EINVAL, "invalid cert chain");
+        }
+        self.check_my_cert_chain(slot_id, my_cert_chain_data.as_slice())?;
+        self.check_signing_slot_ids()?;
+
+        self.common.provision_info.my_cert_chain_data[slot_id as usize] = Some(my_cert_chain_data);
+        self.common.runtime_info.my_cert_chain[slot_id as usize] = None;
+        self.common.update_my_cert_chain();
+        if self.common.connection_state >= SpdmConnectionState::AfterCapabilities
+            && self.get_rsp_capabilities() != self.common.negotiate_info.rsp_capabilities_sel
+        {
+            self.capabilities_changed = true;
+        }
+        Ok(())
+    }
+
+    // The chain is verified with my_cert_chain_config, starts with the
+    // provisioned root cert if any, and its leaf key is the one `slot_id` is
+    // signed with: a signature of the configured algorithms verifies with it.
+    fn check_my_cert_chain(&self, slot_id: u8, cert_chain: &[u8]) -> SpdmResult {
+        let crypto_provider = &self.common.crypto_provider;
+        let (root_cert_begin, root_cert_end) = crypto_provider
+            .get_cert_from_cert_chain(cert_chain, 0)
+            .map_err(|_| spdm_err!(EINVAL, "no root cert"))?;
+        let root_cert = cert_chain
+            .get(root_cert_begin..root_cert_end)
+            .ok_or_else(|| spdm_err!(EINVAL, "no root cert"))?;
+        if let Some(my_root_cert) = self.common.provision_info.my_root_cert {
+            if root_cert != my_root_cert {
+                return spdm_result_err!(EINVAL, "not the provisioned root cert");
+            }
+        }
+        crypto_provider
+            .verify_cert_chain(cert_chain, &self.common.config_info.my_cert_chain_config)
+            .map_err(|_| spdm_err!(EINVAL, "cert chain verification failed"))?;
+
+        let (leaf_cert_begin, leaf_cert_end) = crypto_provider
+            .get_cert_from_cert_chain(cert_chain, -1)
+            .map_err(|_| spdm_err!(EINVAL, "no leaf cert"))?;
+        let leaf_cert = &cert_chain[leaf_cert_begin..leaf_cert_end];
+        let mut nonce = [0u8; SPDM_NONCE_SIZE];
+        crypto_provider.get_random(&mut nonce)?;
+        let base_hash_algo = self.common.config_info.base_hash_algo;
+        let base_asym_algo = self.common.config_info.base_asym_algo;
+        for hash_bit in 0..32 {
+            for asym_bit in 0..32 {
+                let hash_algo =
+                    base_hash_algo & SpdmBaseHashAlgo::from_bits_truncate(1 << hash_bit);
+                let asym_algo =
+                    base_asym_algo & SpdmBaseAsymAlgo::from_bits_truncate(1 << asym_bit);
+                if hash_algo.is_empty() || asym_algo.is_empty() {
+                    continue;
+                }
+                let signature =
+                    match crypto_provider.asym_sign(slot_id, hash_algo, asym_algo, &nonce) {
+                        Some(signature) => signature,
+                        None => continue,
+                    };
+                if crypto_provider
+                    .asym_verify(hash_algo, asym_algo, leaf_cert, &nonce, &signature)
+                    .is_ok()
+                {
+                    return Ok(());
+                }
+            }
+        }
+        spdm_result_err!(EINVAL, "leaf cert key isn't the signing key")
+    }
+
+    pub fn is_provisioned(&self) -> bool {
+        self.common.get_my_slot_mask() != 0
+    }
+
+    /// Check the slots dedicated to a signing purpose in `signing_slot_ids`
+    /// are valid slots and the capability signing with them is configured.
+    pub fn check_signing_slot_ids(&self) -> SpdmResult {
+        let rsp_capabilities = self.common.config_info.rsp_capabilities;
+        for (purpose, capability) in [
+            (
+                SpdmSigningPurpose::Challenge,
+                SpdmResponseCapabilityFlags::CHAL_CAP,
+            ),
+            (
+                SpdmSigningPurpose::Measurement,
+                SpdmResponseCapabilityFlags::MEAS_CAP_SIG,
+            ),
+            (
+                SpdmSigningPurpose::KeyExchange,
+                SpdmResponseCapabilityFlags::KEY_EX_CAP,
+            ),
+        ]
+        .iter()
+        {
+            if let Some(slot_id) = self.common.provision_info.signing_slot_ids.get(*purpose) {
+                if slot_id as usize >= SPDM_MAX_SLOT_NUMBER {
+                    return spdm_result_err!(EINVAL, "invalid signing slot");
+                }
+                if !rsp_capabilities.contains(*capability) {
+                    return spdm_result_err!(EINVAL, "signing slot set without the capability");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // a dedicated signing slot without a cert chain disables its capability
+    fn is_signing_slot_provisioned(&self, purpose: SpdmSigningPurpose) -> bool {
+        match self.common.provision_info.signing_slot_ids.get(purpose) {
+            Some(slot_id) => self.common.get_my_slot_mask() & (1 << slot_id) != 0,
+            None => true,
+        }
+    }
+
+    /// Capabilities advertised in CAPABILITIES. The cert dependent ones are hidden
+    /// until the cert chain is provisioned.
+    pub fn get_rsp_capabilities(&self) -> SpdmResponseCapabilityFlags {
+        let mut rsp_capabilities = self.common.config_info.rsp_capabilities;
+        if !self.is_provisioned() {
+            rsp_capabilities.remove(
+                SpdmResponseCapabilityFlags::CERT_CAP
+                    | SpdmResponseCapabilityFlags::CHAL_CAP
+                    | SpdmResponseCapabilityFlags::KEY_EX_CAP,
+            );
+            if rsp_capabilities.contains(SpdmResponseCapabilityFlags::MEAS_CAP_SIG) {
+                rsp_capabilities.remove(SpdmResponseCapabilityFlags::MEAS_CAP_SIG);
+                rsp_capabilities.insert(SpdmResponseCapabilityFlags::MEAS_CAP_NO_SIG);
+            }
+        }
+        if !self.is_signing_slot_provisioned(SpdmSigningPurpose::Challenge) {
+            rsp_capabilities.remove(SpdmResponseCapabilityFlags::CHAL_CAP);
+        }
+        if !self.is_signing_slot_provisioned(SpdmSigningPurpose::KeyExchange) {
+            rsp_capabilities.remove(SpdmResponseCapabilityFlags::KEY_EX_CAP);
+        }
+        if !self.is_signing_slot_provisioned(SpdmSigningPurpose::Measurement)
+            && rsp_capabilities.contains(SpdmResponseCapabilityFlags::MEAS_CAP_SIG)
+        {
+            rsp_capabilities.remove(SpdmResponseCapabilityFlags::MEAS_CAP_SIG);
+            rsp_capabilities.insert(SpdmResponseCapabilityFlags::MEAS_CAP_NO_SIG);
+        }
+        rsp_capabilities
+    }
+
+    /// Send a response. Responses larger than the requester's DataTransferSize
+    /// are held for CHUNK_GET if both sides support chunking.
     pub fn send_message(&mut self, send_buffer: &[u8]) -> SpdmResult {
-        let mut transport_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
-        let used =
-            self.common
-                .transport_encap
-                .encap(&send_buffer[..], &mut transport_buffer, false)?;
-        self.common.device_io.send(&transport_buffer[..used])
+        debug!("send {}\n", SpdmMessageSummary(send_buffer));
+        trace::on_message(SpdmMessageDirection::Send, None, send_buffer);
+        if let Some((handle, chunk_seq_no)) = self.chunk_send_ack.take() {
+            return self.send_chunk_send_ack(handle, chunk_seq_no, send_buffer);
+        }
+        if self.common.is_chunk_cap_negotiated() && send_buffer.len() > self.get_max_response_size()
+        {
+            return self.send_large_response(send_buffer);
+        }
+        self.send_message_unchunked(send_buffer)
+    }
+
+    pub(crate) fn send_message_unchunked(&mut self, send_buffer: &[u8]) -> SpdmResult {
+        let mut transport_buffer = self.common.take_transport_buffer();
+        let result = self
+            .common
+            .transport_encap
+            .encap(send_buffer, &mut transport_buffer, false)
+            .and_then(|used| {
+                self.common
+                    .send_transport_message(&transport_buffer[..used])
+            });
+        self.common.put_transport_buffer(transport_buffer);
+        result
     }
 
     pub fn send_secured_message(&mut self, session_id: u32, send_buffer: &[u8]) -> SpdmResult {
-        let mut app_buffer = [0u8; config::MAX_SPDM_MESSAGE_BUFFER_SIZE];
-        let used = self
+        debug!(
+            "send {} in session {:08x}\n",
+            SpdmMessageSummary(send_buffer),
+            session_id
+        );
+        trace::on_message(SpdmMessageDirection::Send, Some(session_id), send_buffer);
+        let mut app_buffer = self.common.take_message_buffer();
+        let result = self
             .common
             .transport_encap
-            .encap_app(send_buffer, &mut app_buffer)?;
+            .encap_app(send_buffer, &mut app_buffer)
+            .and_then(|used| self.send_secured_app_buffer(session_id, &app_buffer[..used]));
+        self.common.put_message_buffer(app_buffer);
+        result
+    }
 
+    // Send the encoded application data `app_buffer` in the session.
+    pub(crate) fn send_secured_app_buffer(
+        &mut self,
+        session_id: u32,
+        app_buffer: &[u8],
+    ) -> SpdmResult {
+        let mut encoded_send_buffer = self.common.take_message_buffer();
+        let mut transport_buffer = self.common.take_transport_buffer();
+        let result = self.send_secured_app_buffer_with(
+            session_id,
+            app_buffer,
+            &mut encoded_send_buffer,
+            &mut transport_buffer,
+        );
+        self.common.put_transport_buffer(transport_buffer);
+        self.common.put_message_buffer(encoded_send_buffer);
+        result
+    }
+
+    fn send_secured_app_buffer_with(
+        &mut self,
+        session_id: u32,
+        app_buffer: &[u8],
+        encoded_send_buffer: &mut [u8],
+        transport_buffer: &mut [u8],
+    ) -> SpdmResult {
         let spdm_session = self
             .common
             .get_session_via_id(session_id)
             .ok_or(spdm_err!(EINVAL))?;
 
-        let mut encoded_send_buffer = [0u8; config::MAX_SPDM_MESSAGE_BUFFER_SIZE];
-        let encode_size = spdm_session.encode_spdm_secured_message(
-            &app_buffer[0..used],
-            &mut encoded_send_buffer,
-            false,
-        )?;
+        let encode_size =
+            spdm_session.encode_spdm_secured_message(app_buffer, encoded_send_buffer, false)?;
 
-        let mut transport_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
         let used = self.common.transport_encap.encap(
             &encoded_send_buffer[..encode_size],
-            &mut transport_buffer,
+            transport_buffer,
             true,
         )?;
-        self.common.device_io.send(&transport_buffer[..used])
+        self.common
+            .send_transport_message(&transport_buffer[..used])
     }
 
-    pub fn process_message(&mut self) -> Result<bool, (usize, [u8; 1024])> {
+    fn process_result(
+        &mut self,
+        request_code: Option<SpdmResponseResponseCode>,
+        session_id: Option<u32>,
+        handled: bool,
+    ) -> ProcessResult {
+        let status = if !handled {
+            ProcessStatus::NotHandled
+        } else if let Some(error_code) = self.last_error_code.take() {
+            ProcessStatus::ErrorSent(error_code)
+        } else {
+            ProcessStatus::ResponseSent
+        };
+        ProcessResult {
+            request_code,
+            session_id,
+            status,
+        }
+    }
+
+    /// Receive one message and answer it.
+    ///
+    /// Messages that can't be received or decoded are handed back to the caller.
+    /// With a receive timeout in the config it returns Err with size 0 if
+    /// no message arrives in time, so the caller can poll it in its loop.
+    pub fn process_message(&mut self) -> Result<ProcessResult, (usize, [u8; 1024])> {
+        self.expire_sessions();
         let mut receive_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
         match self.receive_message(&mut receive_buffer[..]) {
             Ok((used, secured_message)) => {
@@ -76,34 +660,17 @@ impl<'a> ResponderContext<'a> {
                     let mut read = Reader::init(&receive_buffer[0..used]);
                     let session_id = u32::read(&mut read).ok_or((used, receive_buffer))?;
 
-                    let spdm_session = self
-                        .common
-                        .get_session_via_id(session_id)
-                        .ok_or((used, receive_buffer))?;
-
-                    let mut app_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
-
-                    let decode_size = spdm_session.decode_spdm_secured_message(
+                    let mut app_buffer = self.common.take_transport_buffer();
+                    let mut spdm_buffer = self.common.take_message_buffer();
+                    let result = self.process_secured_message(
+                        session_id,
                         &receive_buffer[..used],
                         &mut app_buffer,
-                        true,
+                        &mut spdm_buffer,
                     );
-                    if decode_size.is_err() {
-                        return Err((used, receive_buffer));
-                    }
-                    let decode_size = decode_size.unwrap();
-
-                    let mut spdm_buffer = [0u8; config::MAX_SPDM_MESSAGE_BUFFER_SIZE];
-                    let decode_size = self
-                        .common
-                        .transport_encap
-                        .decap_app(&app_buffer[0..decode_size], &mut spdm_buffer);
-                    if decode_size.is_err() {
-                        return Err((used, receive_buffer));
-                    }
-                    let decode_size = decode_size.unwrap();
-
-                    Ok(self.dispatch_secured_message(session_id, &spdm_buffer[0..decode_size]))
+                    self.common.put_message_buffer(spdm_buffer);
+                    self.common.put_transport_buffer(app_buffer);
+                    result.ok_or((used, receive_buffer))
                 } else {
                     Ok(self.dispatch_message(&receive_buffer[0..used]))
                 }
@@ -112,53 +679,142 @@ impl<'a> ResponderContext<'a> {
         }
     }
 
+    // Decrypt and answer the secured message `bytes`, None if it can't be
+    // decoded.
+    fn process_secured_message(
+        &mut self,
+        session_id: u32,
+        bytes: &[u8],
+        app_buffer: &mut [u8],
+        spdm_buffer: &mut [u8],
+    ) -> Option<ProcessResult> {
+        let spdm_session = self.common.get_session_via_id(session_id)?;
+        let decode_size = spdm_session
+            .decode_spdm_secured_message(bytes, app_buffer, true)
+            .ok()?;
+
+        match self
+            .common
+            .transport_encap
+            .decap_app_message(&app_buffer[0..decode_size], spdm_buffer)
+        {
+            Ok(Some(app_message_size)) => {
+                let handled = self.handle_app_message(session_id, &spdm_buffer[..app_message_size]);
+                return Some(self.process_result(None, Some(session_id), handled));
+            }
+            Ok(None) => {}
+            Err(_) => return None,
+        }
+
+        let decode_size = self
+            .common
+            .transport_encap
+            .decap_app(&app_buffer[0..decode_size], spdm_buffer)
+            .ok()?;
+
+        Some(self.dispatch_secured_message(session_id, &spdm_buffer[0..decode_size]))
+    }
+
     fn receive_message(&mut self, receive_buffer: &mut [u8]) -> Result<(usize, bool), usize> {
         info!("receive_message!\n");
 
-        let mut transport_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
-        let used = self.common.device_io.receive(receive_buffer)?;
+        let used = self.common.receive_transport_message(receive_buffer)?;
 
-        let (used, secured_message) = self
+        let mut transport_buffer = self.common.take_transport_buffer();
+        let result = self
             .common
             .transport_encap
-            .decap(&receive_buffer[..used], &mut transport_buffer)
-            .map_err(|_| used)?;
+            .decap(&receive_buffer[..used], &mut transport_buffer);
+        if let Ok((decap_size, _)) = result {
+            receive_buffer[..decap_size].copy_from_slice(&transport_buffer[..decap_size]);
+        }
+        self.common.put_transport_buffer(transport_buffer);
+        result.map_err(|_| used)
+    }
 
-        receive_buffer[..used].copy_from_slice(&transport_buffer[..used]);
-        Ok((used, secured_message))
+    // GET_VERSION is always 1.0 and GET_CAPABILITIES selects the version,
+    // any other request must use the negotiated one.
+    fn is_request_version_valid(
+        &self,
+        request_code: SpdmResponseResponseCode,
+        version: SpdmVersion,
+    ) -> bool {
+        match request_code {
+            SpdmResponseResponseCode::SpdmRequestGetVersion => {
+                version == SpdmVersion::SpdmVersion10
+            }
+            SpdmResponseResponseCode::SpdmRequestGetCapabilities => true,
+            _ if is_request(request_code) => version == self.common.negotiate_info.spdm_version_sel,
+            // not a request, left to the dispatcher
+            _ => true,
+        }
     }
 
-    fn dispatch_secured_message(&mut self, session_id: u32, bytes: &[u8]) -> bool {
+    fn dispatch_secured_message(&mut self, session_id: u32, bytes: &[u8]) -> ProcessResult {
+        self.last_error_code = None;
+        debug!(
+            "receive {} in session {:08x}\n",
+            SpdmMessageSummary(bytes),
+            session_id
+        );
+        trace::on_message(SpdmMessageDirection::Receive, Some(session_id), bytes);
         let mut reader = Reader::init(bytes);
-        match SpdmMessageHeader::read(&mut reader) {
-            Some(message_header) => match message_header.request_response_code {
-                SpdmResponseResponseCode::SpdmRequestGetVersion => false,
-                SpdmResponseResponseCode::SpdmRequestGetCapabilities => false,
-                SpdmResponseResponseCode::SpdmRequestNegotiateAlgorithms => false,
-                SpdmResponseResponseCode::SpdmRequestGetDigests => false,
-                SpdmResponseResponseCode::SpdmRequestGetCertificate => false,
-                SpdmResponseResponseCode::SpdmRequestChallenge => false,
-                SpdmResponseResponseCode::SpdmRequestGetMeasurements => false,
-
-                SpdmResponseResponseCode::SpdmRequestKeyExchange => false,
+        let message_header = SpdmMessageHeader::read(&mut reader);
+        let request_code = message_header.map(|h| h.request_response_code);
+        let version = message_header.map(|h| h.version).unwrap_or_default();
+        let handled = match request_code {
+            Some(request_code) if !self.is_request_version_valid(request_code, version) => {
+                error!("!!! version mismatch : {} !!!\n", version);
+                self.send_secured_spdm_error(
+                    session_id,
+                    SpdmErrorCode::SpdmErrorMajorVersionMismatch,
+                    0,
+                );
+                true
+            }
+            Some(request_code) if !is_request_size_valid(request_code, version, bytes) => {
+                error!("!!! invalid request size : {} !!!\n", request_code);
+                self.send_secured_spdm_error(session_id, SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+                true
+            }
+            Some(request_code)
+                if !self.is_request_expected_in_session(session_id, request_code) =>
+            {
+                self.send_unexpected_request_error(Some(session_id), request_code)
+            }
+            Some(request_code) if self.is_throttled(request_code, Some(session_id)) => {
+                self.send_busy_error(Some(session_id))
+            }
+            Some(request_code) => match request_code {
+                SpdmResponseResponseCode::SpdmRequestGetVersion => {
+                    self.handle_spdm_version_in_session(session_id);
+                    true
+                }
+                #[cfg(feature = "responder-measurements")]
+                SpdmResponseResponseCode::SpdmRequestGetMeasurements => {
+                    self.handle_spdm_measurement(Some(session_id), bytes);
+                    true
+                }
 
+                #[cfg(feature = "responder-key-exchange")]
                 SpdmResponseResponseCode::SpdmRequestFinish => {
                     self.handle_spdm_finish(session_id, bytes);
                     true
                 }
 
-                SpdmResponseResponseCode::SpdmRequestPskExchange => false,
-
+                #[cfg(feature = "responder-psk-exchange")]
                 SpdmResponseResponseCode::SpdmRequestPskFinish => {
                     self.handle_spdm_psk_finish(session_id, bytes);
                     true
                 }
 
+                #[cfg(feature = "responder-heartbeat")]
                 SpdmResponseResponseCode::SpdmRequestHeartbeat => {
                     self.handle_spdm_heartbeat(session_id, bytes);
                     true
                 }
 
+                #[cfg(feature = "responder-key-update")]
                 SpdmResponseResponseCode::SpdmRequestKeyUpdate => {
                     self.handle_spdm_key_update(session_id, bytes);
                     true
@@ -169,6 +825,37 @@ impl<'a> ResponderContext<'a> {
                     true
                 }
 
+                #[cfg(feature = "responder-encap")]
+                SpdmResponseResponseCode::SpdmRequestGetEncapsulatedRequest => {
+                    self.handle_spdm_get_encapsulated_request(session_id, bytes);
+                    true
+                }
+                #[cfg(feature = "responder-encap")]
+                SpdmResponseResponseCode::SpdmRequestDeliverEncapsulatedResponse => {
+                    self.handle_spdm_deliver_encapsulated_response(session_id, bytes);
+                    true
+                }
+
+                #[cfg(feature = "responder-vendor-defined")]
+                SpdmResponseResponseCode::SpdmRequestVendorDefinedRequest => {
+                    self.handle_spdm_vendor_defined_request(Some(session_id), bytes);
+                    true
+                }
+
+                // ERROR(ResponseNotReady) is never sent
+                SpdmResponseResponseCode::SpdmRequestGetCapabilities
+                | SpdmResponseResponseCode::SpdmRequestNegotiateAlgorithms
+                | SpdmResponseResponseCode::SpdmRequestGetDigests
+                | SpdmResponseResponseCode::SpdmRequestGetCertificate
+                | SpdmResponseResponseCode::SpdmRequestChallenge
+                | SpdmResponseResponseCode::SpdmRequestKeyExchange
+                | SpdmResponseResponseCode::SpdmRequestPskExchange
+                | SpdmResponseResponseCode::SpdmRequestChunkSend
+                | SpdmResponseResponseCode::SpdmRequestChunkGet
+                | SpdmResponseResponseCode::SpdmRequestResponseIfReady => {
+                    self.send_unexpected_request_error(Some(session_id), request_code)
+                }
+
                 SpdmResponseResponseCode::SpdmResponseDigests => false,
                 SpdmResponseResponseCode::SpdmResponseCertificate => false,
                 SpdmResponseResponseCode::SpdmResponseChallengeAuth => false,
@@ -183,18 +870,75 @@ impl<'a> ResponderContext<'a> {
                 SpdmResponseResponseCode::SpdmResponseHeartbeatAck => false,
                 SpdmResponseResponseCode::SpdmResponseKeyUpdateAck => false,
                 SpdmResponseResponseCode::SpdmResponseEndSessionAck => false,
+                SpdmResponseResponseCode::SpdmResponseChunkSendAck => false,
+                SpdmResponseResponseCode::SpdmResponseChunkResponse => false,
+                SpdmResponseResponseCode::SpdmResponseEncapsulatedRequest => false,
+                SpdmResponseResponseCode::SpdmResponseEncapsulatedResponseAck => false,
+                SpdmResponseResponseCode::SpdmResponseVendorDefinedResponse => false,
                 SpdmResponseResponseCode::SpdmResponseError => false,
-                SpdmResponseResponseCode::Unknown(_) => false,
+                // unknown, or compiled out, see the responder-* features
+                _ => self.send_unsupported_request_error(Some(session_id), request_code),
             },
             None => false,
-        }
+        };
+        self.process_result(request_code, Some(session_id), handled)
     }
 
-    pub fn dispatch_message(&mut self, bytes: &[u8]) -> bool {
+    pub fn dispatch_message(&mut self, bytes: &[u8]) -> ProcessResult {
+        self.last_error_code = None;
+        debug!("receive {}\n", SpdmMessageSummary(bytes));
+        trace::on_message(SpdmMessageDirection::Receive, None, bytes);
         let mut reader = Reader::init(bytes);
-        match SpdmMessageHeader::read(&mut reader) {
-            Some(message_header) => match message_header.request_response_code {
+        let message_header = SpdmMessageHeader::read(&mut reader);
+        let request_code = message_header.map(|h| h.request_response_code);
+        let version = message_header.map(|h| h.version).unwrap_or_default();
+        let handled = match request_code {
+            Some(request_code) if !self.is_request_expected(request_code) => {
+                self.send_unexpected_request_error(None, request_code)
+            }
+            Some(request_code) if !self.is_request_version_valid(request_code, version) => {
+                error!("!!! version mismatch : {} !!!\n", version);
+                self.send_spdm_error(SpdmErrorCode::SpdmErrorMajorVersionMismatch, 0);
+                true
+            }
+            Some(request_code) if !is_request_size_valid(request_code, version, bytes) => {
+                error!("!!! invalid request size : {} !!!\n", request_code);
+                self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+                true
+            }
+            Some(request_code)
+                if self.capabilities_changed
+                    && request_code != SpdmResponseResponseCode::SpdmRequestGetVersion =>
+            {
+                info!("!!! capabilities changed, resynch !!!\n");
+                self.capabilities_changed = false;
+                self.send_spdm_error(SpdmErrorCode::SpdmErrorRequestResynch, 0);
+                self.common.reset_runtime_info();
+                true
+            }
+            Some(request_code)
+                if !self.is_provisioned()
+                    && matches!(
+                        request_code,
+                        SpdmResponseResponseCode::SpdmRequestGetDigests
+                            | SpdmResponseResponseCode::SpdmRequestGetCertificate
+                            | SpdmResponseResponseCode::SpdmRequestChallenge
+                            | SpdmResponseResponseCode::SpdmRequestKeyExchange
+                    ) =>
+            {
+                error!("!!! cert chain not provisioned !!!\n");
+                self.send_spdm_error(SpdmErrorCode::SpdmErrorBusy, 0);
+                true
+            }
+            Some(request_code) if self.is_session_required(request_code) => {
+                self.send_session_required_error(request_code)
+            }
+            Some(request_code) if self.is_throttled(request_code, None) => {
+                self.send_busy_error(None)
+            }
+            Some(request_code) => match request_code {
                 SpdmResponseResponseCode::SpdmRequestGetVersion => {
+                    self.capabilities_changed = false;
                     self.handle_spdm_version(bytes);
                     true
                 }
@@ -214,34 +958,61 @@ impl<'a> ResponderContext<'a> {
                     self.handle_spdm_certificate(bytes);
                     true
                 }
+                #[cfg(feature = "responder-challenge")]
                 SpdmResponseResponseCode::SpdmRequestChallenge => {
                     self.handle_spdm_challenge(bytes);
                     true
                 }
+                #[cfg(feature = "responder-measurements")]
                 SpdmResponseResponseCode::SpdmRequestGetMeasurements => {
-                    self.handle_spdm_measurement(bytes);
+                    self.handle_spdm_measurement(None, bytes);
                     true
                 }
 
+                #[cfg(feature = "responder-key-exchange")]
                 SpdmResponseResponseCode::SpdmRequestKeyExchange => {
                     self.handle_spdm_key_exchange(bytes);
                     true
                 }
 
-                SpdmResponseResponseCode::SpdmRequestFinish => false,
-
+                #[cfg(feature = "responder-psk-exchange")]
                 SpdmResponseResponseCode::SpdmRequestPskExchange => {
                     self.handle_spdm_psk_exchange(bytes);
                     true
                 }
 
-                SpdmResponseResponseCode::SpdmRequestPskFinish => false,
+                #[cfg(feature = "responder-chunk")]
+                SpdmResponseResponseCode::SpdmRequestChunkSend => {
+                    self.handle_spdm_chunk_send(bytes);
+                    true
+                }
+                #[cfg(feature = "responder-chunk")]
+                SpdmResponseResponseCode::SpdmRequestChunkGet => {
+                    self.handle_spdm_chunk_get(bytes);
+                    true
+                }
 
-                SpdmResponseResponseCode::SpdmRequestHeartbeat => false,
+                #[cfg(feature = "responder-vendor-defined")]
+                SpdmResponseResponseCode::SpdmRequestVendorDefinedRequest => {
+                    self.handle_spdm_vendor_defined_request(None, bytes);
+                    true
+                }
 
-                SpdmResponseResponseCode::SpdmRequestKeyUpdate => false,
+                // only served in a session
+                SpdmResponseResponseCode::SpdmRequestFinish
+                | SpdmResponseResponseCode::SpdmRequestPskFinish
+                | SpdmResponseResponseCode::SpdmRequestHeartbeat
+                | SpdmResponseResponseCode::SpdmRequestKeyUpdate
+                | SpdmResponseResponseCode::SpdmRequestEndSession
+                | SpdmResponseResponseCode::SpdmRequestGetEncapsulatedRequest
+                | SpdmResponseResponseCode::SpdmRequestDeliverEncapsulatedResponse => {
+                    self.send_session_required_error(request_code)
+                }
 
-                SpdmResponseResponseCode::SpdmRequestEndSession => false,
+                // ERROR(ResponseNotReady) is never sent
+                SpdmResponseResponseCode::SpdmRequestResponseIfReady => {
+                    self.send_unexpected_request_error(None, request_code)
+                }
 
                 SpdmResponseResponseCode::SpdmResponseDigests => false,
                 SpdmResponseResponseCode::SpdmResponseCertificate => false,
@@ -257,10 +1028,1525 @@ impl<'a> ResponderContext<'a> {
                 SpdmResponseResponseCode::SpdmResponseHeartbeatAck => false,
                 SpdmResponseResponseCode::SpdmResponseKeyUpdateAck => false,
                 SpdmResponseResponseCode::SpdmResponseEndSessionAck => false,
+                SpdmResponseResponseCode::SpdmResponseChunkSendAck => false,
+                SpdmResponseResponseCode::SpdmResponseChunkResponse => false,
+                SpdmResponseResponseCode::SpdmResponseEncapsulatedRequest => false,
+                SpdmResponseResponseCode::SpdmResponseEncapsulatedResponseAck => false,
+                SpdmResponseResponseCode::SpdmResponseVendorDefinedResponse => false,
                 SpdmResponseResponseCode::SpdmResponseError => false,
-                SpdmResponseResponseCode::Unknown(_) => false,
+                // unknown, or compiled out, see the responder-* features
+                _ => self.send_unsupported_request_error(None, request_code),
             },
             None => false,
+        };
+        if let (Some(request_code), true, None) = (request_code, handled, self.last_error_code) {
+            self.update_connection_state(request_code);
         }
+        self.process_result(request_code, None, handled)
+    }
+}
+
+// Minimum request size of SPDM 1.0, 1.1 and 1.2, and maximum request size,
+// including the SPDM header, per request code. The spec only limits the length
+// of NEGOTIATE_ALGORITHMS. Other requests may be followed by transport alignment
+// padding, so they are bounded by the message buffer. A GET_MEASUREMENTS
+// requesting a signature is longer, see is_request_size_valid().
+const REQUEST_SIZE_TABLE: [(SpdmResponseResponseCode, [usize; 3], usize); 20] = [
+    (
+        SpdmResponseResponseCode::SpdmRequestGetVersion,
+        [4; 3],
+        MAX_REQUEST_SIZE,
+    ),
+    (
+        SpdmResponseResponseCode::SpdmRequestGetCapabilities,
+        [4, 12, 20],
+        MAX_REQUEST_SIZE,
+    ),
+    (
+        SpdmResponseResponseCode::SpdmRequestNegotiateAlgorithms,
+        [32; 3],
+        128,
+    ),
+    (
+        SpdmResponseResponseCode::SpdmRequestGetDigests,
+        [4; 3],
+        MAX_REQUEST_SIZE,
+    ),
+    (
+        SpdmResponseResponseCode::SpdmRequestGetCertificate,
+        [8; 3],
+        MAX_REQUEST_SIZE,
+    ),
+    (
+        SpdmResponseResponseCode::SpdmRequestChallenge,
+        [36; 3],
+        MAX_REQUEST_SIZE,
+    ),
+    (
+        SpdmResponseResponseCode::SpdmRequestGetMeasurements,
+        [4; 3],
+        MAX_REQUEST_SIZE,
+    ),
+    (
+        SpdmResponseResponseCode::SpdmRequestKeyExchange,
+        [42; 3],
+        MAX_REQUEST_SIZE,
+    ),
+    (
+        SpdmResponseResponseCode::SpdmRequestFinish,
+        [4; 3],
+        MAX_REQUEST_SIZE,
+    ),
+    (
+        SpdmResponseResponseCode::SpdmRequestPskExchange,
+        [12; 3],
+        MAX_REQUEST_SIZE,
+    ),
+    (
+        SpdmResponseResponseCode::SpdmRequestPskFinish,
+        [4; 3],
+        MAX_REQUEST_SIZE,
+    ),
+    (
+        SpdmResponseResponseCode::SpdmRequestHeartbeat,
+        [4; 3],
+        MAX_REQUEST_SIZE,
+    ),
+    (
+        SpdmResponseResponseCode::SpdmRequestKeyUpdate,
+        [4; 3],
+        MAX_REQUEST_SIZE,
+    ),
+    (
+        SpdmResponseResponseCode::SpdmRequestEndSession,
+        [4; 3],
+        MAX_REQUEST_SIZE,
+    ),
+    (
+        SpdmResponseResponseCode::SpdmRequestChunkSend,
+        [12; 3],
+        MAX_REQUEST_SIZE,
+    ),
+    (
+        SpdmResponseResponseCode::SpdmRequestChunkGet,
+        [6; 3],
+        MAX_REQUEST_SIZE,
+    ),
+    (
+        SpdmResponseResponseCode::SpdmRequestGetEncapsulatedRequest,
+        [4; 3],
+        MAX_REQUEST_SIZE,
+    ),
+    (
+        SpdmResponseResponseCode::SpdmRequestDeliverEncapsulatedResponse,
+        [4; 3],
+        MAX_REQUEST_SIZE,
+    ),
+    (
+        SpdmResponseResponseCode::SpdmRequestResponseIfReady,
+        [4; 3],
+        MAX_REQUEST_SIZE,
+    ),
+    (
+        SpdmResponseResponseCode::SpdmRequestVendorDefinedRequest,
+        [4; 3],
+        MAX_REQUEST_SIZE,
+    ),
+];
+const MAX_REQUEST_SIZE: usize = config::MAX_SPDM_MESSAGE_BUFFER_SIZE;
+
+pub(crate) fn is_request(request_code: SpdmResponseResponseCode) -> bool {
+    REQUEST_SIZE_TABLE
+        .iter()
+        .any(|(code, _, _)| *code == request_code)
+}
+
+// `version` is the one in the request header, it is checked first.
+fn is_request_size_valid(
+    request_code: SpdmResponseResponseCode,
+    version: SpdmVersion,
+    request: &[u8],
+) -> bool {
+    let version_index = match version {
+        SpdmVersion::SpdmVersion10 => 0,
+        SpdmVersion::SpdmVersion11 => 1,
+        _ => 2,
+    };
+    match REQUEST_SIZE_TABLE
+        .iter()
+        .find(|(code, _, _)| *code == request_code)
+    {
+        Some((_, min_size, max_size)) => {
+            let mut min_size = min_size[version_index];
+            // the nonce, and the SlotIDParam from 1.1 on
+            if request_code == SpdmResponseResponseCode::SpdmRequestGetMeasurements
+                && request.get(2).map_or(false, |attributes| {
+                    attributes & SpdmMeasurementeAttributes::INCLUDE_SIGNATURE.bits() != 0
+                })
+            {
+                min_size += SPDM_NONCE_SIZE + if version_index == 0 { 0 } else { 1 };
+            }
+            request.len() >= min_size && request.len() <= *max_size
+        }
+        // not a request, left to the dispatcher
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::SpdmCryptoFailurePolicy;
+    use crate::crypto::{
+        SpdmAsymSign, SpdmCertOperation, SpdmCryptoProvider, SpdmDhe, SpdmExtAlgo,
+        SpdmExternalKeySchedule, SpdmHash, SpdmRevocationStatus,
+    };
+    use crate::error::SpdmErrorNum;
+    use crate::responder::SpdmCryptoOperation;
+    use crate::session::SpdmSessionState;
+    use crate::testlib;
+    use core::convert::TryFrom;
+    use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+
+    #[test]
+    fn test_running_transcript_hash() {
+        let mut context = testlib::new_responder(
+            common::SpdmConfigInfo::default(),
+            common::SpdmProvisionInfo::default(),
+        );
+        context.common.negotiate_info.base_hash_sel = SpdmBaseHashAlgo::TPM_ALG_SHA_384;
+        context
+            .common
+            .runtime_info
+            .message_a
+            .append_message(&[0x10, 0x84, 0x00, 0x00])
+            .unwrap();
+        let peer_cert_chain = &mut context.common.peer_info.peer_cert_chain.cert_chain;
+        peer_cert_chain.data[..4 + 48 + 16].copy_from_slice(&[0x5a; 4 + 48 + 16]);
+        peer_cert_chain.data_size = 4 + 48 + 16;
+
+        for use_psk in [false, true].iter() {
+            let mut expected = None;
+            for running_transcript_hash in [false, true].iter() {
+                context.common.config_info.running_transcript_hash = *running_transcript_hash;
+                let mut message_k = context.common.new_message_k(true, *use_psk).unwrap();
+                message_k.append_message(&[0x12, 0xe4, 0x00, 0x00]).unwrap();
+                let th1 = context
+                    .common
+                    .calc_req_transcript_hash(*use_psk, false, &message_k, None)
+                    .unwrap();
+                // message_k goes on after TH1
+                message_k.append_message(&[0x5a; 48]).unwrap();
+                let mut message_f = context
+                    .common
+                    .new_message_f(true, false, &message_k)
+                    .unwrap();
+                message_f.append_message(&[0x12, 0xe5, 0x00, 0x00]).unwrap();
+                let th2 = context
+                    .common
+                    .calc_req_transcript_hash(*use_psk, false, &message_k, Some(&message_f))
+                    .unwrap();
+
+                let transcript_data = context
+                    .common
+                    .calc_req_transcript_data(*use_psk, false, &message_k, Some(&message_f))
+                    .unwrap();
+                let digest = context
+                    .common
+                    .crypto_provider
+                    .hash_all(SpdmBaseHashAlgo::TPM_ALG_SHA_384, transcript_data.as_ref())
+                    .unwrap();
+                assert_eq!(th2.as_ref(), digest.as_ref());
+                let expected = expected.get_or_insert((th1, th2));
+                assert_eq!(th1.as_ref(), expected.0.as_ref());
+                assert_eq!(th2.as_ref(), expected.1.as_ref());
+            }
+        }
+
+        // hash callbacks that can't start a digest fail the running hash
+        context.common.crypto_provider.hash = Some(SpdmHash {
+            hash_all_cb: crate::crypto::hash::hash_all,
+            hash_ctx_init_cb: |_base_hash_algo| None,
+            hash_ctx_update_cb: crate::crypto::hash::hash_ctx_update,
+            hash_ctx_dup_cb: crate::crypto::hash::hash_ctx_dup,
+            hash_ctx_finalize_cb: crate::crypto::hash::hash_ctx_finalize,
+        });
+        assert_eq!(
+            context.common.new_message_k(true, false).unwrap_err().num(),
+            SpdmErrorNum::EFAULT
+        );
+        context.common.config_info.running_transcript_hash = false;
+        let message_k = context.common.new_message_k(true, false).unwrap();
+        context.common.config_info.running_transcript_hash = true;
+        assert_eq!(
+            context
+                .common
+                .calc_req_transcript_hash(false, false, &message_k, None)
+                .unwrap_err()
+                .num(),
+            SpdmErrorNum::EINVAL
+        );
+    }
+
+    #[test]
+    fn test_empty_peer_cert_chain() {
+        let mut context = testlib::new_responder(
+            common::SpdmConfigInfo::default(),
+            common::SpdmProvisionInfo::default(),
+        );
+        context.common.negotiate_info.base_hash_sel = SpdmBaseHashAlgo::TPM_ALG_SHA_384;
+        let message_k = context.common.new_message_k(true, true).unwrap();
+
+        // the responder cert chain of a requester, the requester one with mut auth
+        for (is_requester, mut_auth) in [(true, false), (false, true)].iter() {
+            let transcript_hash = if *is_requester {
+                context
+                    .common
+                    .calc_req_transcript_hash(false, *mut_auth, &message_k, None)
+            } else {
+                context
+                    .common
+                    .calc_rsp_transcript_hash(true, *mut_auth, &message_k, None)
+            };
+            assert_eq!(transcript_hash.unwrap_err().num(), SpdmErrorNum::EINVAL);
+        }
+        context.common.config_info.running_transcript_hash = true;
+        assert_eq!(
+            context.common.new_message_k(true, false).unwrap_err().num(),
+            SpdmErrorNum::EINVAL
+        );
+    }
+
+    #[test]
+    fn test_verify_signature_without_peer_cert_chain() {
+        let mut context = testlib::new_responder(
+            common::SpdmConfigInfo::default(),
+            common::SpdmProvisionInfo::default(),
+        );
+        context.common.negotiate_info.base_hash_sel = SpdmBaseHashAlgo::TPM_ALG_SHA_384;
+        context.common.negotiate_info.base_asym_sel = SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P384;
+        let message_k = context.common.new_message_k(true, true).unwrap();
+        let message_f = context.common.new_message_k(true, true).unwrap();
+        let signature = SpdmSignatureStruct::default();
+
+        assert_eq!(
+            context
+                .common
+                .verify_challenge_auth_signature(&signature)
+                .unwrap_err()
+                .num(),
+            SpdmErrorNum::EINVAL
+        );
+        assert_eq!(
+            context
+                .common
+                .verify_measurement_signature(&signature)
+                .unwrap_err()
+                .num(),
+            SpdmErrorNum::EINVAL
+        );
+        assert_eq!(
+            context
+                .common
+                .verify_key_exchange_rsp_signature(&message_k, &signature)
+                .unwrap_err()
+                .num(),
+            SpdmErrorNum::EINVAL
+        );
+        assert_eq!(
+            context
+                .common
+                .verify_finish_req_signature(&message_k, &message_f, &signature)
+                .unwrap_err()
+                .num(),
+            SpdmErrorNum::EINVAL
+        );
+
+        // a header shorter than the root hash
+        context
+            .common
+            .peer_info
+            .peer_cert_chain
+            .cert_chain
+            .data_size = 4;
+        assert_eq!(
+            context
+                .common
+                .verify_measurement_signature(&signature)
+                .unwrap_err()
+                .num(),
+            SpdmErrorNum::EINVAL
+        );
+    }
+
+    #[test]
+    fn test_get_version_in_session() {
+        let (mut listener, mut context) = testlib::new_listened_responder(
+            common::SpdmConfigInfo::default(),
+            common::SpdmProvisionInfo::default(),
+        );
+        let session_id = 0xFFFE_FFFE;
+        let mut session = context.common.setup_session(session_id).unwrap();
+        testlib::establish_test_session(session.session());
+        session.commit();
+        let mut peer_session = testlib::new_peer_session(session_id);
+
+        let get_version = [0x10, 0x84, 0x00, 0x00];
+        let result = context.dispatch_secured_message(session_id, &get_version);
+
+        assert_eq!(
+            result.status,
+            ProcessStatus::ErrorSent(SpdmErrorCode::SpdmErrorUnexpectedRequest)
+        );
+        // the ERROR is the last message of the session
+        let response = testlib::receive_secured(&mut listener, &mut peer_session).unwrap();
+        assert_eq!(
+            &response[1..3],
+            &[0x7F, SpdmErrorCode::SpdmErrorUnexpectedRequest.get_u8()]
+        );
+        assert!(context.common.get_session_via_id(session_id).is_none());
+    }
+
+    #[test]
+    fn test_secured_errors() {
+        let (mut listener, mut context) = testlib::new_listened_responder(
+            common::SpdmConfigInfo::default(),
+            common::SpdmProvisionInfo::default(),
+        );
+        context.common.connection_state = SpdmConnectionState::Negotiated;
+        context.common.negotiate_info.spdm_version_sel = SpdmVersion::SpdmVersion12;
+        let session_id = 0xFFFE_FFFE;
+        let mut session = context.common.setup_session(session_id).unwrap();
+        testlib::establish_test_session(session.session());
+        session.commit();
+        let mut peer_session = testlib::new_peer_session(session_id);
+
+        for (request, error_code) in [
+            // HEARTBEAT of another version, truncated
+            (
+                &[0x11, 0xE8, 0x00, 0x00][..],
+                SpdmErrorCode::SpdmErrorMajorVersionMismatch,
+            ),
+            (
+                &[0x12, 0xE8, 0x00][..],
+                SpdmErrorCode::SpdmErrorInvalidRequest,
+            ),
+            // GET_DIGESTS, KEY_UPDATE with an unknown operation
+            (
+                &[0x12, 0x81, 0x00, 0x00][..],
+                SpdmErrorCode::SpdmErrorUnexpectedRequest,
+            ),
+            (
+                &[0x12, 0xE9, 0x07, 0x00][..],
+                SpdmErrorCode::SpdmErrorInvalidRequest,
+            ),
+            (
+                &[0x12, 0xF0, 0x00, 0x00][..],
+                SpdmErrorCode::SpdmErrorUnsupportedRequest,
+            ),
+        ]
+        .iter()
+        {
+            let result = context.dispatch_secured_message(session_id, request);
+            assert_eq!(result.status, ProcessStatus::ErrorSent(*error_code));
+            let response = testlib::receive_secured(&mut listener, &mut peer_session).unwrap();
+            assert_eq!(&response[1..3], &[0x7F, error_code.get_u8()]);
+        }
+    }
+
+    #[test]
+    fn test_get_version_resets_connection() {
+        let mut context = testlib::new_responder(
+            common::SpdmConfigInfo::default(),
+            common::SpdmProvisionInfo::default(),
+        );
+        context.common.connection_state = SpdmConnectionState::Authenticated;
+        context.common.negotiate_info.spdm_version_sel = SpdmVersion::SpdmVersion12;
+        context.common.setup_session(0xFFFE_FFFE).unwrap().commit();
+        context.common.setup_session(0xFFFD_FFFD).unwrap().commit();
+        context
+            .common
+            .runtime_info
+            .message_b
+            .append_message(&[0x12, 0x81]);
+        context
+            .common
+            .runtime_info
+            .message_m
+            .append_message(&[0x12, 0xE0]);
+
+        let get_version = [0x10, 0x84, 0x00, 0x00];
+        let result = context.dispatch_message(&get_version);
+
+        assert_eq!(result.status, ProcessStatus::ResponseSent);
+        assert_eq!(context.get_active_session_ids().count(), 0);
+        let runtime_info = &context.common.runtime_info;
+        assert!(runtime_info.message_b.as_ref().is_empty());
+        assert!(runtime_info.message_c.as_ref().is_empty());
+        assert!(runtime_info.message_m.as_ref().is_empty());
+        // GET_VERSION and VERSION
+        assert!(!runtime_info.message_a.as_ref().is_empty());
+        assert_eq!(
+            context.common.get_connection_state(),
+            SpdmConnectionState::AfterVersion
+        );
+    }
+
+    #[test]
+    fn test_session_only_requests() {
+        let mut context = testlib::new_responder(
+            common::SpdmConfigInfo::default(),
+            common::SpdmProvisionInfo::default(),
+        );
+        context.common.connection_state = SpdmConnectionState::Negotiated;
+        context.common.negotiate_info.spdm_version_sel = SpdmVersion::SpdmVersion12;
+
+        // HEARTBEAT outside of a session
+        let heartbeat = [0x12, 0xE8, 0x00, 0x00];
+        let result = context.dispatch_message(&heartbeat);
+        assert_eq!(
+            result.status,
+            ProcessStatus::ErrorSent(SpdmErrorCode::SpdmErrorSessionRequired)
+        );
+
+        // HEARTBEAT before the handshake is completed
+        let session_id = 0xFFFE_FFFE;
+        let mut session = context.common.setup_session(session_id).unwrap();
+        testlib::establish_test_session(session.session());
+        session
+            .session()
+            .set_session_state(SpdmSessionState::SpdmSessionHandshaking);
+        session.commit();
+        let result = context.dispatch_secured_message(session_id, &heartbeat);
+        assert_eq!(
+            result.status,
+            ProcessStatus::ErrorSent(SpdmErrorCode::SpdmErrorUnexpectedRequest)
+        );
+
+        // PSK_FINISH in a KEY_EXCHANGE session
+        let psk_finish = [0x12, 0xE7, 0x00, 0x00, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let result = context.dispatch_secured_message(session_id, &psk_finish);
+        assert_eq!(
+            result.status,
+            ProcessStatus::ErrorSent(SpdmErrorCode::SpdmErrorUnexpectedRequest)
+        );
+    }
+
+    #[test]
+    fn test_session_management() {
+        let config_info = common::SpdmConfigInfo {
+            max_session_lifetime: 1,
+            ..Default::default()
+        };
+        let mut context = testlib::new_responder(config_info, common::SpdmProvisionInfo::default());
+        context.common.setup_session(0xFFFE_FFFE).unwrap().commit();
+        context.common.setup_session(0xFFFD_FFFD).unwrap().commit();
+
+        let mut session_ids = [0u32; 2];
+        assert_eq!(context.get_active_session_ids().count(), 2);
+        for (id, session_id) in session_ids.iter_mut().zip(context.get_active_session_ids()) {
+            *id = session_id;
+        }
+        assert_eq!(session_ids, [0xFFFE_FFFE, 0xFFFD_FFFD]);
+        assert!(context.get_session_state(0xFFFE_FFFE).is_some());
+        assert_eq!(context.get_session_state(0x1234_5678), None);
+
+        context.terminate_session(0xFFFE_FFFE).unwrap();
+        assert_eq!(context.get_session_state(0xFFFE_FFFE), None);
+        assert_eq!(
+            context.terminate_session(0xFFFE_FFFE).unwrap_err().num(),
+            crate::error::SpdmErrorNum::ENOENT
+        );
+        assert_eq!(context.get_active_session_ids().count(), 1);
+
+        // expired before the next message is processed
+        std::thread::sleep(std::time::Duration::from_millis(1100));
+        assert!(context.process_message().is_err());
+        assert_eq!(context.get_active_session_ids().count(), 0);
+    }
+
+    #[test]
+    fn test_session_id_allocation() {
+        let mut context = testlib::new_responder(
+            common::SpdmConfigInfo::default(),
+            common::SpdmProvisionInfo::default(),
+        );
+
+        // every session gets its own responder half
+        for i in 0..config::MAX_SPDM_SESSION_COUNT {
+            let rsp_session_id = context.common.get_free_half_session_id(0xFFFE, false);
+            assert_eq!(rsp_session_id, Some(0xFFFE - i as u16));
+            let session_id = 0xFFFE_0000 + rsp_session_id.unwrap() as u32;
+            context.common.setup_session(session_id).unwrap().commit();
+        }
+        assert_eq!(context.common.get_free_half_session_id(0xFFFE, false), None);
+        assert_eq!(context.common.get_free_half_session_id(0xFFFE, true), None);
+
+        // a terminated session's half is reused
+        context.terminate_session(0xFFFE_FFFD).unwrap();
+        assert_eq!(
+            context.common.get_free_half_session_id(0xFFFE, false),
+            Some(0xFFFD)
+        );
+        // all sessions use the same requester half
+        assert_eq!(
+            context.common.get_free_half_session_id(0xFFFE, true),
+            Some(0xFFFD)
+        );
+    }
+
+    #[test]
+    fn test_unexpected_requests() {
+        let mut context = testlib::new_responder(
+            common::SpdmConfigInfo::default(),
+            common::SpdmProvisionInfo::default(),
+        );
+
+        // GET_CAPABILITIES before GET_VERSION
+        let get_capabilities = [0x11, 0xE1, 0x00, 0x00, 0, 0, 0, 0, 0, 0, 0, 0];
+        let result = context.dispatch_message(&get_capabilities);
+        assert_eq!(
+            result.status,
+            ProcessStatus::ErrorSent(SpdmErrorCode::SpdmErrorUnexpectedRequest)
+        );
+
+        context.common.connection_state = SpdmConnectionState::Negotiated;
+        context.common.negotiate_info.spdm_version_sel = SpdmVersion::SpdmVersion11;
+
+        // FINISH outside of a session
+        let finish = [0x11, 0xE5, 0x00, 0x00];
+        let result = context.dispatch_message(&finish);
+        assert_eq!(
+            result.status,
+            ProcessStatus::ErrorSent(SpdmErrorCode::SpdmErrorUnexpectedRequest)
+        );
+
+        // VENDOR_DEFINED_REQUEST
+        let vendor_defined = [0x11, 0xFE, 0x00, 0x00];
+        let result = context.dispatch_message(&vendor_defined);
+        assert_eq!(
+            result.status,
+            ProcessStatus::ErrorSent(SpdmErrorCode::SpdmErrorUnsupportedRequest)
+        );
+
+        // not a request
+        let digests = [0x11, 0x01, 0x00, 0x00];
+        let result = context.dispatch_message(&digests);
+        assert_eq!(result.status, ProcessStatus::NotHandled);
+        assert_eq!(
+            context.common.get_connection_state(),
+            SpdmConnectionState::Negotiated
+        );
+    }
+
+    #[test]
+    fn test_get_capabilities_size() {
+        let mut config_info = common::SpdmConfigInfo::default();
+        config_info.spdm_version[0] = SpdmVersion::SpdmVersion10;
+        config_info.spdm_version[1] = SpdmVersion::SpdmVersion11;
+        let mut context = testlib::new_responder(config_info, common::SpdmProvisionInfo::default());
+
+        // the header only in 1.0
+        context.common.connection_state = SpdmConnectionState::AfterVersion;
+        let get_capabilities = [0x10, 0xE1, 0x00, 0x00];
+        let result = context.dispatch_message(&get_capabilities);
+        assert_eq!(result.status, ProcessStatus::ResponseSent);
+        assert_eq!(
+            context.common.get_connection_state(),
+            SpdmConnectionState::AfterCapabilities
+        );
+
+        // but not in 1.1
+        context.common.connection_state = SpdmConnectionState::AfterVersion;
+        let get_capabilities = [0x11, 0xE1, 0x00, 0x00];
+        let result = context.dispatch_message(&get_capabilities);
+        assert_eq!(
+            result.status,
+            ProcessStatus::ErrorSent(SpdmErrorCode::SpdmErrorInvalidRequest)
+        );
+        let get_capabilities = [0x11, 0xE1, 0x00, 0x00, 0, 0, 0, 0, 0, 0, 0, 0];
+        let result = context.dispatch_message(&get_capabilities);
+        assert_eq!(result.status, ProcessStatus::ResponseSent);
+    }
+
+    #[test]
+    fn test_get_measurements_size() {
+        let request_code = SpdmResponseResponseCode::SpdmRequestGetMeasurements;
+        let mut get_measurements = [0u8; 37];
+        get_measurements[1] = 0xE0;
+        assert!(is_request_size_valid(
+            request_code,
+            SpdmVersion::SpdmVersion12,
+            &get_measurements[..4]
+        ));
+
+        // a signature requested with the header only, without the SlotIDParam
+        // from 1.1 on, or with everything
+        get_measurements[2] = SpdmMeasurementeAttributes::INCLUDE_SIGNATURE.bits();
+        for (version, min_size) in [
+            (SpdmVersion::SpdmVersion10, 36),
+            (SpdmVersion::SpdmVersion11, 37),
+            (SpdmVersion::SpdmVersion12, 37),
+        ]
+        .iter()
+        {
+            assert!(!is_request_size_valid(
+                request_code,
+                *version,
+                &get_measurements[..4]
+            ));
+            assert!(!is_request_size_valid(
+                request_code,
+                *version,
+                &get_measurements[..(min_size - 1)]
+            ));
+            assert!(is_request_size_valid(
+                request_code,
+                *version,
+                &get_measurements[..*min_size]
+            ));
+        }
+    }
+
+    std::thread_local! {
+        // PKCS#8 key of the leaf cert provisioned with provision_complete()
+        static SIGNING_KEY: core::cell::RefCell<std::vec::Vec<u8>> =
+            core::cell::RefCell::new(std::vec::Vec::new());
+    }
+
+    #[test]
+    fn test_provision_complete() {
+        let mut config_info = common::SpdmConfigInfo::default();
+        config_info.spdm_version[0] = SpdmVersion::SpdmVersion11;
+        config_info.rsp_capabilities =
+            SpdmResponseCapabilityFlags::CERT_CAP | SpdmResponseCapabilityFlags::CHAL_CAP;
+        config_info.base_hash_algo = SpdmBaseHashAlgo::TPM_ALG_SHA_384;
+        config_info.base_asym_algo = SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P384;
+        // id-kp-clientAuth, the policy of the peer cert chains only
+        config_info.cert_chain_config.leaf_eku = &[40 + 3, 6, 1, 5, 5, 7, 3, 2];
+        let mut provision_info = common::SpdmProvisionInfo::default();
+        // KEY_EX_CAP isn't configured
+        provision_info.signing_slot_ids.key_exchange = Some(0);
+        let (mut listener, mut context) =
+            testlib::new_listened_responder(config_info, provision_info);
+        context.common.crypto_provider.asym_sign = Some(SpdmAsymSign {
+            sign_cb: |_slot_id, base_hash_algo, base_asym_algo, data| {
+                SIGNING_KEY.with(|key| {
+                    crate::crypto::asym_sign::sign_with_key(
+                        &key.borrow(),
+                        base_hash_algo,
+                        base_asym_algo,
+                        data,
+                    )
+                })
+            },
+        });
+        let test_cert_chain = testlib::generate_cert_chain(
+            SpdmBaseHashAlgo::TPM_ALG_SHA_384,
+            SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P384,
+        );
+        SIGNING_KEY.with(|key| *key.borrow_mut() = test_cert_chain.leaf_key.clone());
+        let cert_chain_data = testlib::cert_chain_data(&test_cert_chain);
+
+        assert_eq!(
+            context
+                .provision_complete(0, cert_chain_data)
+                .unwrap_err()
+                .num(),
+            SpdmErrorNum::EINVAL
+        );
+        assert!(!context.is_provisioned());
+        context.common.provision_info.signing_slot_ids.key_exchange = None;
+
+        // empty, not a cert chain, with a bad leaf cert signature, or whose
+        // leaf key isn't the signing key
+        let garbage = SpdmCertChainData {
+            data_size: 16,
+            ..Default::default()
+        };
+        let mut tampered = cert_chain_data;
+        tampered.data[tampered.data_size as usize - 1] ^= 0x01;
+        let other_test_cert_chain = testlib::generate_cert_chain(
+            SpdmBaseHashAlgo::TPM_ALG_SHA_384,
+            SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P384,
+        );
+        let other_key = testlib::cert_chain_data(&other_test_cert_chain);
+        for invalid_cert_chain_data in
+            [SpdmCertChainData::default(), garbage, tampered, other_key].iter()
+        {
+            assert_eq!(
+                context
+                    .provision_complete(0, *invalid_cert_chain_data)
+                    .unwrap_err()
+                    .num(),
+                SpdmErrorNum::EINVAL
+            );
+            assert!(!context.is_provisioned());
+            assert_eq!(context.common.get_my_slot_mask(), 0);
+        }
+
+        // not the provisioned root
+        context.common.provision_info.my_root_cert = Some(Box::leak(
+            other_test_cert_chain.root_cert.into_boxed_slice(),
+        ));
+        assert_eq!(
+            context
+                .provision_complete(0, cert_chain_data)
+                .unwrap_err()
+                .num(),
+            SpdmErrorNum::EINVAL
+        );
+        assert!(!context.is_provisioned());
+        context.common.provision_info.my_root_cert =
+            Some(Box::leak(test_cert_chain.root_cert.into_boxed_slice()));
+
+        // the requester got the capabilities of the unprovisioned responder
+        context.common.connection_state = SpdmConnectionState::Negotiated;
+        context.common.negotiate_info.spdm_version_sel = SpdmVersion::SpdmVersion11;
+        context.common.negotiate_info.rsp_capabilities_sel = context.get_rsp_capabilities();
+        context.provision_complete(0, cert_chain_data).unwrap();
+        assert!(context.is_provisioned());
+
+        let get_digests = [0x11, 0x81, 0x00, 0x00];
+        let result = context.dispatch_message(&get_digests);
+        assert_eq!(
+            result.status,
+            ProcessStatus::ErrorSent(SpdmErrorCode::SpdmErrorRequestResynch)
+        );
+        assert_eq!(
+            context.common.get_connection_state(),
+            SpdmConnectionState::NotStarted
+        );
+
+        let get_version = [0x10, 0x84, 0x00, 0x00];
+        let result = context.dispatch_message(&get_version);
+        assert_eq!(result.status, ProcessStatus::ResponseSent);
+        let get_capabilities = [0x11, 0xE1, 0x00, 0x00, 0, 0, 0, 0, 0, 0, 0, 0];
+        let result = context.dispatch_message(&get_capabilities);
+        assert_eq!(result.status, ProcessStatus::ResponseSent);
+
+        let mut capabilities = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        while listener.pending() > 1 {
+            listener.receive(&mut capabilities).unwrap();
+        }
+        let used = listener.receive(&mut capabilities).unwrap();
+        assert_eq!(capabilities[1], 0x61);
+        let flags = SpdmResponseCapabilityFlags::from_bits_truncate(u32::from_le_bytes(
+            <[u8; 4]>::try_from(&capabilities[8..used]).unwrap(),
+        ));
+        assert!(flags.contains(SpdmResponseCapabilityFlags::CERT_CAP));
+        assert!(flags.contains(SpdmResponseCapabilityFlags::CHAL_CAP));
+    }
+
+    #[test]
+    fn test_busy() {
+        let config_info = common::SpdmConfigInfo {
+            request_rate_limit: 2,
+            ..Default::default()
+        };
+        let mut context = testlib::new_responder(config_info, common::SpdmProvisionInfo::default());
+        context.common.connection_state = SpdmConnectionState::Negotiated;
+        context.common.negotiate_info.spdm_version_sel = SpdmVersion::SpdmVersion11;
+
+        // busy in sessions only
+        context.set_throttle(crate::responder::SpdmThrottle {
+            is_busy_cb: |_request_code, session_id| session_id.is_some(),
+        });
+        let session_id = 0xFFFE_FFFE;
+        let mut session = context.common.setup_session(session_id).unwrap();
+        session.session().set_crypto_param(
+            SpdmBaseHashAlgo::TPM_ALG_SHA_384,
+            SpdmDheAlgo::SECP_384_R1,
+            SpdmAeadAlgo::AES_256_GCM,
+            SpdmKeyScheduleAlgo::SPDM_KEY_SCHEDULE,
+        );
+        session
+            .session()
+            .set_session_state(SpdmSessionState::SpdmSessionEstablished);
+        session.commit();
+        let heartbeat = [0x11, 0xE8, 0x00, 0x00];
+        let result = context.dispatch_secured_message(session_id, &heartbeat);
+        assert_eq!(
+            result.status,
+            ProcessStatus::ErrorSent(SpdmErrorCode::SpdmErrorBusy)
+        );
+        assert_eq!(result.session_id, Some(session_id));
+
+        // two requests a second
+        let vendor_defined = [0x11, 0xFE, 0x00, 0x00];
+        for _ in 0..2 {
+            let result = context.dispatch_message(&vendor_defined);
+            assert_eq!(
+                result.status,
+                ProcessStatus::ErrorSent(SpdmErrorCode::SpdmErrorUnsupportedRequest)
+            );
+        }
+        let result = context.dispatch_message(&vendor_defined);
+        assert_eq!(
+            result.status,
+            ProcessStatus::ErrorSent(SpdmErrorCode::SpdmErrorBusy)
+        );
+
+        // GET_VERSION is always answered
+        let get_version = [0x10, 0x84, 0x00, 0x00];
+        let result = context.dispatch_message(&get_version);
+        assert_eq!(result.status, ProcessStatus::ResponseSent);
+    }
+
+    #[test]
+    fn test_vendor_defined_request() {
+        let mut context = testlib::new_responder(
+            common::SpdmConfigInfo::default(),
+            common::SpdmProvisionInfo::default(),
+        );
+        context.common.connection_state = SpdmConnectionState::Negotiated;
+        context.common.negotiate_info.spdm_version_sel = SpdmVersion::SpdmVersion11;
+        context
+            .register_vendor_defined_handler(SpdmVendorDefinedHandler {
+                standard_id: SpdmStandardId::SpdmStandardIdPCISIG,
+                vendor_id: SpdmVendorIdStruct::try_from(&[0x86u8, 0x80][..]).unwrap(),
+                handle_request_cb: |_session_id, req_payload| {
+                    SpdmVendorDefinedPayloadStruct::try_from(req_payload)
+                },
+            })
+            .unwrap();
+
+        let vendor_defined = [
+            0x11, 0xFE, 0x00, 0x00, 0x03, 0x00, 0x02, 0x86, 0x80, 0x01, 0x00, 0xAA,
+        ];
+        let result = context.dispatch_message(&vendor_defined);
+        assert_eq!(result.status, ProcessStatus::ResponseSent);
+
+        // another vendor
+        let vendor_defined = [
+            0x11, 0xFE, 0x00, 0x00, 0x03, 0x00, 0x02, 0xDE, 0x10, 0x01, 0x00, 0xAA,
+        ];
+        let result = context.dispatch_message(&vendor_defined);
+        assert_eq!(
+            result.status,
+            ProcessStatus::ErrorSent(SpdmErrorCode::SpdmErrorUnsupportedRequest)
+        );
+
+        // ReqLength beyond the message
+        let vendor_defined = [
+            0x11, 0xFE, 0x00, 0x00, 0x03, 0x00, 0x02, 0x86, 0x80, 0x02, 0x00, 0xAA,
+        ];
+        let result = context.dispatch_message(&vendor_defined);
+        assert_eq!(
+            result.status,
+            ProcessStatus::ErrorSent(SpdmErrorCode::SpdmErrorInvalidRequest)
+        );
+    }
+
+    #[test]
+    fn test_challenge_nonce_reused() {
+        let mut context = testlib::new_responder(
+            common::SpdmConfigInfo::default(),
+            common::SpdmProvisionInfo::default(),
+        );
+        context.common.connection_state = SpdmConnectionState::Negotiated;
+        context.common.negotiate_info.spdm_version_sel = SpdmVersion::SpdmVersion11;
+        context.common.provision_info.my_cert_chain_data[0] = Some(SpdmCertChainData::default());
+        context.common.runtime_info.last_challenge_nonce = Some(SpdmNonceStruct {
+            data: [0x5A; SPDM_NONCE_SIZE],
+        });
+
+        let mut challenge = [0x5Au8; 4 + SPDM_NONCE_SIZE];
+        challenge[..4].copy_from_slice(&[0x11, 0x83, 0x00, 0x00]);
+        let result = context.dispatch_message(&challenge);
+        assert_eq!(
+            result.status,
+            ProcessStatus::ErrorSent(SpdmErrorCode::SpdmErrorInvalidRequest)
+        );
+        assert_eq!(
+            context.common.get_connection_state(),
+            SpdmConnectionState::Negotiated
+        );
+    }
+
+    static CONFIG_INFO: common::SpdmConfigInfo = common::SpdmConfigInfo {
+        base_hash_algo: SpdmBaseHashAlgo::TPM_ALG_SHA_384,
+        ..common::SpdmConfigInfo::new()
+    };
+    static PROVISION_INFO: common::SpdmProvisionInfo = common::SpdmProvisionInfo::new();
+
+    #[test]
+    fn test_static_config_info() {
+        let mut context = testlib::new_responder(CONFIG_INFO.clone(), PROVISION_INFO.clone());
+
+        let get_version = [0x10, 0x84, 0x00, 0x00];
+        let result = context.dispatch_message(&get_version);
+        assert_eq!(result.status, ProcessStatus::ResponseSent);
+        assert_eq!(
+            context.common.get_connection_state(),
+            SpdmConnectionState::AfterVersion
+        );
+        assert_eq!(
+            context.common.config_info.base_hash_algo,
+            SpdmBaseHashAlgo::TPM_ALG_SHA_384
+        );
+    }
+
+    static PRIORITY_CONFIG_INFO: common::SpdmConfigInfo = common::SpdmConfigInfo {
+        measurement_specification: SpdmMeasurementSpecification::DMTF,
+        base_asym_algo: SpdmBaseAsymAlgo::from_bits_truncate(
+            SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P256.bits()
+                | SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P384.bits(),
+        ),
+        base_hash_algo: SpdmBaseHashAlgo::from_bits_truncate(
+            SpdmBaseHashAlgo::TPM_ALG_SHA_256.bits() | SpdmBaseHashAlgo::TPM_ALG_SHA_384.bits(),
+        ),
+        dhe_algo: SpdmDheAlgo::from_bits_truncate(
+            SpdmDheAlgo::SECP_256_R1.bits() | SpdmDheAlgo::SECP_384_R1.bits(),
+        ),
+        aead_algo: SpdmAeadAlgo::from_bits_truncate(
+            SpdmAeadAlgo::AES_128_GCM.bits() | SpdmAeadAlgo::AES_256_GCM.bits(),
+        ),
+        req_asym_algo: SpdmReqAsymAlgo::from_bits_truncate(
+            SpdmReqAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P256.bits()
+                | SpdmReqAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P384.bits(),
+        ),
+        key_schedule_algo: SpdmKeyScheduleAlgo::SPDM_KEY_SCHEDULE,
+        base_asym_algo_priority: &[
+            SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P256,
+            SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P384,
+        ],
+        // SHA-256 is never selected
+        base_hash_algo_priority: &[SpdmBaseHashAlgo::TPM_ALG_SHA_384],
+        dhe_algo_priority: &[SpdmDheAlgo::SECP_256_R1, SpdmDheAlgo::SECP_384_R1],
+        aead_algo_priority: &[SpdmAeadAlgo::AES_128_GCM, SpdmAeadAlgo::AES_256_GCM],
+        ..common::SpdmConfigInfo::new()
+    };
+
+    fn negotiate_algorithms(base_hash_algo: u8) -> common::SpdmNegotiateInfo {
+        let mut context =
+            testlib::new_responder(PRIORITY_CONFIG_INFO.clone(), PROVISION_INFO.clone());
+        context.common.connection_state = SpdmConnectionState::AfterCapabilities;
+        context.common.negotiate_info.spdm_version_sel = SpdmVersion::SpdmVersion11;
+
+        // P-256, P-384 and the default order of the rest
+        let mut request = [0u8; 48];
+        request[..8].copy_from_slice(&[0x11, 0xE3, 0x04, 0x00, 48, 0x00, 0x01, 0x00]);
+        request[8..12].copy_from_slice(&0x90u32.to_le_bytes());
+        request[12..16].copy_from_slice(&(base_hash_algo as u32).to_le_bytes());
+        request[32..48].copy_from_slice(&[
+            0x02, 0x20, 0x18, 0x00, // DHE: secp256r1, secp384r1
+            0x03, 0x20, 0x03, 0x00, // AEAD: AES-128-GCM, AES-256-GCM
+            0x04, 0x20, 0x90, 0x00, // ReqBaseAsymAlg: P-256, P-384
+            0x05, 0x20, 0x01, 0x00, // KeySchedule
+        ]);
+        let result = context.dispatch_message(&request);
+        assert_eq!(result.status, ProcessStatus::ResponseSent);
+        context.common.negotiate_info
+    }
+
+    #[test]
+    fn test_algorithm_priority() {
+        let negotiate_info = negotiate_algorithms(0x03);
+        assert_eq!(
+            negotiate_info.base_asym_sel,
+            SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P256
+        );
+        assert_eq!(
+            negotiate_info.base_hash_sel,
+            SpdmBaseHashAlgo::TPM_ALG_SHA_384
+        );
+        assert_eq!(negotiate_info.dhe_sel, SpdmDheAlgo::SECP_256_R1);
+        assert_eq!(negotiate_info.aead_sel, SpdmAeadAlgo::AES_128_GCM);
+        // no priority list, the built-in order
+        assert_eq!(
+            negotiate_info.req_asym_sel,
+            SpdmReqAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P384
+        );
+
+        // only SHA-256 in common
+        assert!(negotiate_algorithms(0x01).base_hash_sel.is_empty());
+    }
+
+    const EXT_ASYM_SM2: SpdmExtAlgStruct = SpdmExtAlgStruct {
+        registry_id: SpdmStandardId::SpdmStandardIdTCG,
+        reserved: 0,
+        algorithm_id: 0x001B,
+    };
+    const EXT_ASYM_UNSUPPORTED: SpdmExtAlgStruct = SpdmExtAlgStruct {
+        registry_id: SpdmStandardId::SpdmStandardIdTCG,
+        reserved: 0,
+        algorithm_id: 0x0023,
+    };
+
+    fn negotiate_ext_algorithms(base_asym_algo: u32) -> common::SpdmNegotiateInfo {
+        let config_info = common::SpdmConfigInfo {
+            ext_asym_algo: &[EXT_ASYM_UNSUPPORTED, EXT_ASYM_SM2],
+            ..PRIORITY_CONFIG_INFO.clone()
+        };
+        let mut context = testlib::new_responder(config_info, PROVISION_INFO.clone());
+        context.common.set_crypto_provider(SpdmCryptoProvider {
+            ext_algo: Some(SpdmExtAlgo {
+                is_supported_cb: |ext_alg| *ext_alg == EXT_ASYM_SM2,
+                hash_all_cb: |_ext_hash, _data| None,
+                sign_cb: |_slot_id, _ext_hash, _ext_asym, _data| None,
+                verify_cb: |_ext_hash, _ext_asym, _public_cert_der, _data, _signature| {
+                    spdm_result_err!(EFAULT)
+                },
+            }),
+            ..Default::default()
+        });
+        context.common.connection_state = SpdmConnectionState::AfterCapabilities;
+        context.common.negotiate_info.spdm_version_sel = SpdmVersion::SpdmVersion11;
+
+        // SM2 and an algorithm the responder doesn't configure
+        let mut request = [0u8; 56];
+        request[..8].copy_from_slice(&[0x11, 0xE3, 0x04, 0x00, 56, 0x00, 0x01, 0x00]);
+        request[8..12].copy_from_slice(&base_asym_algo.to_le_bytes());
+        request[12..16].copy_from_slice(&0x02u32.to_le_bytes());
+        request[28] = 2; // ExtAsymCount
+        request[32..40].copy_from_slice(&[0x01, 0x00, 0x1B, 0x00, 0x01, 0x00, 0x24, 0x00]);
+        request[40..56].copy_from_slice(&[
+            0x02, 0x20, 0x10, 0x00, // DHE: secp384r1
+            0x03, 0x20, 0x02, 0x00, // AEAD: AES-256-GCM
+            0x04, 0x20, 0x80, 0x00, // ReqBaseAsymAlg: P-384
+            0x05, 0x20, 0x01, 0x00, // KeySchedule
+        ]);
+        let result = context.dispatch_message(&request);
+        assert_eq!(result.status, ProcessStatus::ResponseSent);
+        context.common.negotiate_info
+    }
+
+    #[test]
+    fn test_ext_algorithm() {
+        let negotiate_info = negotiate_ext_algorithms(0);
+        assert!(negotiate_info.base_asym_sel.is_empty());
+        assert_eq!(negotiate_info.ext_asym_sel, Some(EXT_ASYM_SM2));
+        assert_eq!(negotiate_info.ext_hash_sel, None);
+
+        // a base algorithm in common is preferred
+        let negotiate_info = negotiate_ext_algorithms(0x80);
+        assert_eq!(
+            negotiate_info.base_asym_sel,
+            SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P384
+        );
+        assert_eq!(negotiate_info.ext_asym_sel, None);
+    }
+
+    static MEASUREMENT_GENERATION: AtomicU32 = AtomicU32::new(1);
+    static MEASUREMENT_SUMMARY_HASH_COUNT: AtomicU32 = AtomicU32::new(0);
+
+    fn get_measurement_record(
+        _measurement_specification: SpdmMeasurementSpecification,
+        _measurement_hash_algo: SpdmMeasurementHashAlgo,
+        _measurement_operation: SpdmMeasurementOperation,
+    ) -> Option<SpdmMeasurementRecordStructure> {
+        None
+    }
+
+    fn get_measurement_summary_hash(
+        _measurement_summary_hash_type: SpdmMeasurementSummaryHashType,
+        _base_hash_algo: SpdmBaseHashAlgo,
+    ) -> Option<SpdmDigestStruct> {
+        MEASUREMENT_SUMMARY_HASH_COUNT.fetch_add(1, Ordering::SeqCst);
+        Some(SpdmDigestStruct::default())
+    }
+
+    fn get_measurement_generation() -> u32 {
+        MEASUREMENT_GENERATION.load(Ordering::SeqCst)
+    }
+
+    #[test]
+    fn test_measurement_generation() {
+        let mut context = testlib::new_responder(
+            common::SpdmConfigInfo::default(),
+            common::SpdmProvisionInfo::default(),
+        );
+        context.set_measurement_provider(SpdmMeasurementProvider {
+            measurement_specification: SpdmMeasurementSpecification::DMTF,
+            get_measurement_record_cb: get_measurement_record,
+            get_measurement_identifier_cb: None,
+            get_measurement_summary_hash_cb: Some(get_measurement_summary_hash),
+            get_generation_cb: Some(get_measurement_generation),
+        });
+
+        let all = SpdmMeasurementSummaryHashType::SpdmMeasurementSummaryHashTypeAll;
+        context.generate_measurement_summary_hash(all).unwrap();
+        context.generate_measurement_summary_hash(all).unwrap();
+        assert_eq!(MEASUREMENT_SUMMARY_HASH_COUNT.load(Ordering::SeqCst), 1);
+
+        MEASUREMENT_GENERATION.store(2, Ordering::SeqCst);
+        context.generate_measurement_summary_hash(all).unwrap();
+        assert_eq!(MEASUREMENT_SUMMARY_HASH_COUNT.load(Ordering::SeqCst), 2);
+
+        context.notify_measurement_update();
+        context.generate_measurement_summary_hash(all).unwrap();
+        assert_eq!(MEASUREMENT_SUMMARY_HASH_COUNT.load(Ordering::SeqCst), 3);
+
+        for opaque_support in [
+            SpdmOpaqueSupport::empty(),
+            SpdmOpaqueSupport::OPAQUE_DATA_FMT1,
+        ]
+        .iter()
+        {
+            context.common.negotiate_info.opaque_support_sel = *opaque_support;
+            let opaque = context.common.encode_measurement_generation(2);
+            assert_eq!(
+                context.common.decode_measurement_generation(&opaque),
+                Some(2)
+            );
+        }
+        assert_eq!(
+            context
+                .common
+                .decode_measurement_generation(&SpdmOpaqueStruct::default()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_encode_into_undersized_buffer() {
+        use crate::error::SpdmErrorNum;
+        use codec::Writer;
+
+        let mut context = testlib::new_responder(
+            common::SpdmConfigInfo::default(),
+            common::SpdmProvisionInfo::default(),
+        );
+        let mut versions = [SpdmVersionStruct::default(); config::MAX_SPDM_VERSION_COUNT];
+        versions[0].version = SpdmVersion::SpdmVersion10;
+        versions[1].version = SpdmVersion::SpdmVersion11;
+        let response = SpdmMessage {
+            header: SpdmMessageHeader {
+                version: SpdmVersion::SpdmVersion10,
+                request_response_code: SpdmResponseResponseCode::SpdmResponseVersion,
+            },
+            payload: SpdmMessagePayload::SpdmVersionResponse(SpdmVersionResponsePayload {
+                version_number_entry_count: 2,
+                versions,
+            }),
+        };
+
+        let mut buffer = [0u8; 64];
+        let size = response
+            .spdm_encode(&mut context.common, &mut Writer::init(&mut buffer))
+            .unwrap();
+        assert_eq!(
+            response
+                .spdm_encode(&mut context.common, &mut Writer::init(&mut buffer[..size]))
+                .unwrap(),
+            size
+        );
+        for short_size in [0, 1, size - 1].iter() {
+            let mut writer = Writer::init(&mut buffer[..*short_size]);
+            assert_eq!(
+                response
+                    .spdm_encode(&mut context.common, &mut writer)
+                    .unwrap_err()
+                    .num(),
+                SpdmErrorNum::ENOMEM
+            );
+        }
+
+        // nested payloads fail as a whole
+        let mut record = SpdmMeasurementRecordStructure {
+            number_of_blocks: 1,
+            ..Default::default()
+        };
+        record.record[0].measurement_specification = SpdmMeasurementSpecification::DMTF;
+        record.record[0].measurement.value_size = 16;
+        record.record[0].measurement_size = 16 + 3;
+        let mut writer = Writer::init(&mut buffer[..8]);
+        assert_eq!(
+            record
+                .spdm_encode(&mut context.common, &mut writer)
+                .unwrap_err()
+                .num(),
+            SpdmErrorNum::ENOMEM
+        );
+
+        // inconsistent sizes
+        record.record[0].measurement_size = 16;
+        let mut writer = Writer::init(&mut buffer);
+        assert_eq!(
+            record
+                .spdm_encode(&mut context.common, &mut writer)
+                .unwrap_err()
+                .num(),
+            SpdmErrorNum::EINVAL
+        );
+    }
+
+    static FAIL_CRYPTO: AtomicBool = AtomicBool::new(false);
+
+    fn failing_crypto_provider() -> SpdmCryptoProvider {
+        SpdmCryptoProvider {
+            hash: Some(SpdmHash {
+                hash_all_cb: |base_hash_algo, data| {
+                    if FAIL_CRYPTO.load(Ordering::SeqCst) {
+                        return None;
+                    }
+                    crate::crypto::hash::hash_all(base_hash_algo, data)
+                },
+                hash_ctx_init_cb: crate::crypto::hash::hash_ctx_init,
+                hash_ctx_update_cb: crate::crypto::hash::hash_ctx_update,
+                hash_ctx_dup_cb: crate::crypto::hash::hash_ctx_dup,
+                hash_ctx_finalize_cb: crate::crypto::hash::hash_ctx_finalize,
+            }),
+            dhe: Some(SpdmDhe {
+                supported_algo: crate::crypto::dhe::supported_algo(),
+                generate_key_pair_cb: |dhe_algo| {
+                    if FAIL_CRYPTO.load(Ordering::SeqCst) {
+                        return None;
+                    }
+                    crate::crypto::dhe::generate_key_pair(dhe_algo)
+                },
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_crypto_failure() {
+        let mut context = testlib::new_responder(
+            common::SpdmConfigInfo::default(),
+            common::SpdmProvisionInfo::default(),
+        );
+        context
+            .common
+            .set_crypto_provider(failing_crypto_provider());
+        context.common.connection_state = SpdmConnectionState::Negotiated;
+        context.common.negotiate_info.spdm_version_sel = SpdmVersion::SpdmVersion11;
+        context.common.negotiate_info.base_hash_sel = SpdmBaseHashAlgo::TPM_ALG_SHA_384;
+        context.common.negotiate_info.dhe_sel = SpdmDheAlgo::SECP_384_R1;
+        context.common.provision_info.my_cert_chain_data[0] = Some(SpdmCertChainData::default());
+        context.common.runtime_info.my_cert_chain[0] = Some(SpdmCertChainData::default());
+
+        FAIL_CRYPTO.store(true, Ordering::SeqCst);
+        let get_digests = [0x11, 0x81, 0x00, 0x00];
+        let result = context.dispatch_message(&get_digests);
+        assert_eq!(
+            result.status,
+            ProcessStatus::ErrorSent(SpdmErrorCode::SpdmErrorUnspecified)
+        );
+        assert_eq!(
+            context.get_crypto_failure_stats(),
+            SpdmCryptoFailureStats {
+                failures: 1,
+                last_operation: Some(SpdmCryptoOperation::Hash),
+            }
+        );
+
+        // the DHE key pair fails before a session is set up
+        let mut key_exchange = [0u8; 4 + 4 + SPDM_RANDOM_SIZE + 96 + 2];
+        key_exchange[..8].copy_from_slice(&[0x11, 0xE4, 0x00, 0x00, 0xFF, 0xFF, 0x00, 0x00]);
+        let result = context.dispatch_message(&key_exchange);
+        assert_eq!(
+            result.status,
+            ProcessStatus::ErrorSent(SpdmErrorCode::SpdmErrorUnspecified)
+        );
+        assert_eq!(context.get_crypto_failure_stats().failures, 2);
+        assert_eq!(
+            context.get_crypto_failure_stats().last_operation,
+            Some(SpdmCryptoOperation::DheKeyPair)
+        );
+        assert!(context.common.get_session_via_id(0xFFFF_FFFE).is_none());
+
+        context.common.config_info.crypto_failure_policy = SpdmCryptoFailurePolicy::RequestResynch;
+        let result = context.dispatch_message(&get_digests);
+        assert_eq!(
+            result.status,
+            ProcessStatus::ErrorSent(SpdmErrorCode::SpdmErrorRequestResynch)
+        );
+        assert_eq!(
+            context.common.get_connection_state(),
+            SpdmConnectionState::NotStarted
+        );
+
+        FAIL_CRYPTO.store(false, Ordering::SeqCst);
+        context.common.connection_state = SpdmConnectionState::Negotiated;
+        context.common.runtime_info.my_cert_chain[0] = Some(SpdmCertChainData::default());
+        let result = context.dispatch_message(&get_digests);
+        assert_eq!(result.status, ProcessStatus::ResponseSent);
+        assert_eq!(context.get_crypto_failure_stats().failures, 3);
+    }
+
+    #[test]
+    fn test_key_update_activation_failure() {
+        let (mut listener, mut context) = testlib::new_listened_responder(
+            common::SpdmConfigInfo::default(),
+            common::SpdmProvisionInfo::default(),
+        );
+        context.common.negotiate_info.spdm_version_sel = SpdmVersion::SpdmVersion11;
+        // keys that can't be activated, sent in the clear
+        context.common.set_crypto_provider(SpdmCryptoProvider {
+            external_key_schedule: Some(SpdmExternalKeySchedule {
+                import_shared_secret_cb: |_, _, _, _| Some(1),
+                derive_handshake_keys_cb: |_, _| Ok(()),
+                derive_data_keys_cb: |_, _| Ok(()),
+                update_data_key_cb: |_, _| Ok(()),
+                activate_data_key_update_cb: |_, _, _| spdm_result_err!(EFAULT),
+                hmac_cb: |_, _, _| None,
+                encrypt_cb: |_, _, _, _, plain_text, _tag, cipher_text| {
+                    cipher_text.copy_from_slice(plain_text);
+                    Ok((cipher_text.len(), 16))
+                },
+                decrypt_cb: |_, _, _, _, _, _, _| spdm_result_err!(EFAULT),
+                release_cb: |_| {},
+            }),
+            ..Default::default()
+        });
+        let session_id = 0xFFFE_FFFE;
+        let mut session_guard = context.common.setup_session(session_id).unwrap();
+        testlib::establish_test_session(session_guard.session());
+        session_guard
+            .session()
+            .create_data_secret_update(true, false)
+            .unwrap();
+        session_guard.commit();
+
+        let verify_new_key = [0x11, 0xE9, 0x03, 0x01];
+        let result = context.dispatch_secured_message(session_id, &verify_new_key);
+        assert_eq!(
+            result.status,
+            ProcessStatus::ErrorSent(SpdmErrorCode::SpdmErrorUnspecified)
+        );
+        assert_eq!(result.session_id, Some(session_id));
+        assert_eq!(
+            context.get_crypto_failure_stats().last_operation,
+            Some(SpdmCryptoOperation::KeySchedule)
+        );
+        let mut response = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        let used = listener.receive(&mut response).unwrap();
+        // ERROR(Unspecified) in a secured message of the session
+        assert_eq!(response[..4], session_id.to_le_bytes());
+        assert!(response[..used]
+            .windows(4)
+            .any(|message| message == [0x11, 0x7F, 0x05, 0x00]));
+        assert_eq!(listener.pending(), 0);
+    }
+
+    #[test]
+    fn test_reverify_peer_cert_on_key_update() {
+        for (base_hash_algo, base_asym_algo) in testlib::CERT_CHAIN_ALGOS.iter() {
+            check_reverify_peer_cert_on_key_update(*base_hash_algo, *base_asym_algo);
+        }
+    }
+
+    fn check_reverify_peer_cert_on_key_update(
+        base_hash_algo: SpdmBaseHashAlgo,
+        base_asym_algo: SpdmBaseAsymAlgo,
+    ) {
+        static INVALID_SESSION_ID: AtomicU32 = AtomicU32::new(0);
+        static UNIX_TIME: AtomicU64 = AtomicU64::new(0);
+        INVALID_SESSION_ID.store(0, Ordering::SeqCst);
+
+        let mut context = testlib::new_responder(
+            common::SpdmConfigInfo {
+                reverify_peer_cert_on_key_update: true,
+                ..common::SpdmConfigInfo::default()
+            },
+            common::SpdmProvisionInfo::default(),
+        );
+        context.common.negotiate_info.spdm_version_sel = SpdmVersion::SpdmVersion11;
+        context.common.negotiate_info.base_hash_sel = base_hash_algo;
+        context.common.negotiate_info.base_asym_sel = base_asym_algo;
+        context
+            .common
+            .set_peer_cert_callback(common::SpdmPeerCertCallback {
+                cert_invalid_cb: |session_id, _slot_id| {
+                    INVALID_SESSION_ID.store(session_id, Ordering::SeqCst)
+                },
+            });
+        let psk_session_id = 0xFFFD_FFFD;
+        let mut session_guard = context.common.setup_session(psk_session_id).unwrap();
+        session_guard.session().set_use_psk(true);
+        session_guard.commit();
+
+        // PSK sessions have no cert chain to verify
+        context
+            .common
+            .reverify_session_peer_cert_chain(psk_session_id, false)
+            .unwrap();
+        assert_eq!(INVALID_SESSION_ID.load(Ordering::SeqCst), 0);
+
+        // the requester cert chain, valid from 2020-01-01 to 2049-12-31
+        let cert_chain = testlib::generate_cert_chain(base_hash_algo, base_asym_algo).cert_chain();
+        let header_size = 4 + base_hash_algo.get_size() as usize;
+        let peer_cert_chain = &mut context.common.peer_info.peer_cert_chain.cert_chain;
+        peer_cert_chain.data[header_size..(header_size + cert_chain.len())]
+            .copy_from_slice(&cert_chain);
+        peer_cert_chain.data_size = (header_size + cert_chain.len()) as u16;
+        let revoked_cert_operation = SpdmCertOperation {
+            get_cert_from_cert_chain_cb: crate::crypto::cert_operation::get_cert_from_cert_chain,
+            verify_cert_chain_cb: crate::crypto::cert_operation::verify_cert_chain,
+            parse_cert_info_cb: crate::crypto::cert_operation::parse_cert_info,
+            check_revocation_cb: Some(|_cert, _issuer| SpdmRevocationStatus::Revoked),
+        };
+
+        let session_id = 0xFFFE_FFFE;
+        let key_update = [0x11, 0xE9, 0x02, 0x01];
+        for (unix_time, revoked, valid) in [
+            // 2021-07-01
+            (1_625_097_600u64, false, true),
+            // 2050-01-01, expired
+            (2_524_608_000, false, false),
+            (1_625_097_600, true, false),
+        ]
+        .iter()
+        {
+            let mut session_guard = context.common.setup_session(session_id).unwrap();
+            session_guard
+                .session()
+                .set_mut_auth_requested(SpdmKeyExchangeMutAuthAttributes::MUT_AUTH_REQ);
+            testlib::establish_test_session(session_guard.session());
+            session_guard.commit();
+            UNIX_TIME.store(*unix_time, Ordering::SeqCst);
+            context
+                .common
+                .config_info
+                .cert_chain_config
+                .get_unix_time_cb = Some(|| Some(UNIX_TIME.load(Ordering::SeqCst)));
+            context.common.crypto_provider.cert_operation = if *revoked {
+                Some(revoked_cert_operation)
+            } else {
+                None
+            };
+            INVALID_SESSION_ID.store(0, Ordering::SeqCst);
+
+            let result = context.dispatch_secured_message(session_id, &key_update);
+            if *valid {
+                assert_eq!(result.status, ProcessStatus::ResponseSent);
+                assert_eq!(INVALID_SESSION_ID.load(Ordering::SeqCst), 0);
+                context
+                    .common
+                    .get_session_via_id(session_id)
+                    .unwrap()
+                    .teardown();
+            } else {
+                assert_eq!(
+                    result.status,
+                    ProcessStatus::ErrorSent(SpdmErrorCode::SpdmErrorUnspecified)
+                );
+                assert_eq!(INVALID_SESSION_ID.load(Ordering::SeqCst), session_id);
+                assert!(context.common.get_session_via_id(session_id).is_none());
+            }
+        }
+
+        // the requester cert chain is gone
+        let mut session_guard = context.common.setup_session(session_id).unwrap();
+        session_guard
+            .session()
+            .set_mut_auth_requested(SpdmKeyExchangeMutAuthAttributes::MUT_AUTH_REQ);
+        testlib::establish_test_session(session_guard.session());
+        session_guard.commit();
+        context
+            .common
+            .peer_info
+            .peer_cert_chain
+            .cert_chain
+            .data_size = 0;
+        let result = context.dispatch_secured_message(session_id, &key_update);
+        assert_eq!(
+            result.status,
+            ProcessStatus::ErrorSent(SpdmErrorCode::SpdmErrorUnspecified)
+        );
+        assert_eq!(INVALID_SESSION_ID.load(Ordering::SeqCst), session_id);
+        assert!(context.common.get_session_via_id(session_id).is_none());
     }
 }
@@ -4,7 +4,6 @@
 
 #![forbid(unsafe_code)]
 
-use crate::crypto;
 use crate::responder::*;
 
 impl<'a> ResponderContext<'a> {
@@ -32,38 +31,46 @@ impl<'a> ResponderContext<'a> {
             return;
         }
 
-        let digest_size = self.common.negotiate_info.base_hash_sel.get_size();
+        // one digest per provisioned slot, in slot order
+        let slot_mask = self.common.get_my_slot_mask();
+        let mut digests = [SpdmDigestStruct::default(); SPDM_MAX_SLOT_NUMBER];
+        let mut slot_count = 0u8;
+        for my_cert_chain in self.common.runtime_info.my_cert_chain.iter().flatten() {
+            let cert_chain_hash = match self.common.crypto_provider.hash_all(
+                self.common.negotiate_info.base_hash_sel,
+                my_cert_chain.as_ref(),
+            ) {
+                Some(cert_chain_hash) => cert_chain_hash,
+                None => {
+                    self.send_crypto_failure(SpdmCryptoOperation::Hash);
+                    return;
+                }
+            };
+            digests[slot_count as usize] = cert_chain_hash;
+            slot_count += 1;
+        }
 
         info!("send spdm digest\n");
         let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
         let mut writer = Writer::init(&mut send_buffer);
         let response = SpdmMessage {
             header: SpdmMessageHeader {
-                version: SpdmVersion::SpdmVersion11,
+                version: self.common.negotiate_info.spdm_version_sel,
                 request_response_code: SpdmResponseResponseCode::SpdmResponseDigests,
             },
             payload: SpdmMessagePayload::SpdmDigestsResponse(SpdmDigestsResponsePayload {
-                slot_mask: 0x1,
-                slot_count: 1u8,
-                digests: [SpdmDigestStruct {
-                    data_size: digest_size as u16,
-                    data: [0xffu8; SPDM_MAX_HASH_SIZE],
-                }; SPDM_MAX_SLOT_NUMBER],
+                slot_mask,
+                slot_count,
+                digests,
             }),
         };
-        response.spdm_encode(&mut self.common, &mut writer);
-        let used = writer.used();
-
-        let my_cert_chain = self.common.provision_info.my_cert_chain.unwrap();
-        let cert_chain_hash = crypto::hash::hash_all(
-            self.common.negotiate_info.base_hash_sel,
-            my_cert_chain.as_ref(),
-        )
-        .unwrap();
-
-        // patch the message before send
-        send_buffer[(used - cert_chain_hash.data_size as usize)..used]
-            .copy_from_slice(cert_chain_hash.as_ref());
+        let used = match response.spdm_encode(&mut self.common, &mut writer) {
+            Ok(used) => used,
+            Err(_) => {
+                self.send_spdm_error(SpdmErrorCode::SpdmErrorUnspecified, 0);
+                return;
+            }
+        };
 
         let _ = self.send_message(&send_buffer[0..used]);
 
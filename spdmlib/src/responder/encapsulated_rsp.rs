@@ -0,0 +1,297 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+#![forbid(unsafe_code)]
+
+use crate::error::SpdmResult;
+use crate::responder::*;
+use crate::session::SpdmSessionState;
+
+impl<'a> ResponderContext<'a> {
+    // KEY_EXCHANGE_RSP asked the requester to fetch the encapsulated requests
+    // and FINISH is not received yet.
+    fn is_encap_mut_auth_pending(&mut self, session_id: u32) -> bool {
+        let session = match self.common.get_session_via_id(session_id) {
+            Some(session) => session,
+            None => return false,
+        };
+        session.get_session_state() == SpdmSessionState::SpdmSessionHandshaking
+            && session
+                .get_mut_auth_requested()
+                .contains(SpdmKeyExchangeMutAuthAttributes::MUT_AUTH_REQ_WITH_ENCAP_REQUEST)
+    }
+
+    // Encapsulated requests are also allowed in an established session, e.g.
+    // to retrieve the requester cert chain again.
+    fn is_encap_request_allowed(&mut self, session_id: u32) -> bool {
+        let session = match self.common.get_session_via_id(session_id) {
+            Some(session) => session,
+            None => return false,
+        };
+        let established = session.get_session_state() == SpdmSessionState::SpdmSessionEstablished;
+        self.is_encap_mut_auth_pending(session_id)
+            || (established && self.common.is_mut_auth_cap_negotiated())
+    }
+
+    // GET_DIGESTS, or GET_CERTIFICATE for the next portion of the requester cert chain.
+    fn encode_encap_request(
+        &mut self,
+        request_code: SpdmResponseResponseCode,
+        buffer: &mut [u8],
+    ) -> SpdmResult<usize> {
+        let payload = match request_code {
+            SpdmResponseResponseCode::SpdmRequestGetDigests => {
+                SpdmMessagePayload::SpdmGetDigestsRequest(SpdmGetDigestsRequestPayload {})
+            }
+            _ => SpdmMessagePayload::SpdmGetCertificateRequest(SpdmGetCertificateRequestPayload {
+                slot_id: self.common.peer_info.peer_cert_chain_slot_id,
+                offset: self.common.peer_info.peer_cert_chain.cert_chain.data_size,
+                length: config::MAX_SPDM_CERT_PORTION_LEN as u16,
+            }),
+        };
+        let mut writer = Writer::init(buffer);
+        let request = SpdmMessage {
+            header: SpdmMessageHeader {
+                version: self.common.negotiate_info.spdm_version_sel,
+                request_response_code: request_code,
+            },
+            payload,
+        };
+        request.spdm_encode(&mut self.common, &mut writer)
+    }
+
+    pub fn handle_spdm_get_encapsulated_request(&mut self, session_id: u32, bytes: &[u8]) {
+        let mut reader = Reader::init(bytes);
+        SpdmMessageHeader::read(&mut reader);
+
+        let get_encap_request =
+            SpdmGetEncapsulatedRequestPayload::spdm_read(&mut self.common, &mut reader);
+        if let Some(get_encap_request) = get_encap_request {
+            debug!("!!! get_encap_request : {:02x?}\n", get_encap_request);
+        } else {
+            error!("!!! get_encap_request : fail !!!\n");
+            self.send_secured_spdm_error(session_id, SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+            return;
+        }
+
+        if !self.is_encap_request_allowed(session_id) {
+            error!("!!! get_encap_request : not allowed !!!\n");
+            self.send_secured_spdm_error(session_id, SpdmErrorCode::SpdmErrorUnexpectedRequest, 0);
+            return;
+        }
+
+        // start over with the requester cert chain
+        self.common.peer_info.peer_cert_chain.cert_chain.data_size = 0;
+        self.common.peer_info.peer_cert_chain_slot_id = 0;
+
+        let request_id = 1u8;
+        let request_code = SpdmResponseResponseCode::SpdmRequestGetDigests;
+        let mut payload = SpdmEncapsulatedRequestPayload {
+            request_id,
+            ..Default::default()
+        };
+        payload.request_size = match self.encode_encap_request(request_code, &mut payload.request) {
+            Ok(request_size) => request_size as u32,
+            Err(_) => {
+                self.send_secured_spdm_error(session_id, SpdmErrorCode::SpdmErrorUnspecified, 0);
+                return;
+            }
+        };
+        self.encap_request = Some((request_id, request_code));
+
+        info!("send spdm encapsulated request\n");
+        let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        let mut writer = Writer::init(&mut send_buffer);
+        let response = SpdmMessage {
+            header: SpdmMessageHeader {
+                version: self.common.negotiate_info.spdm_version_sel,
+                request_response_code: SpdmResponseResponseCode::SpdmResponseEncapsulatedRequest,
+            },
+            payload: SpdmMessagePayload::SpdmEncapsulatedRequest(payload),
+        };
+        let used = match response.spdm_encode(&mut self.common, &mut writer) {
+            Ok(used) => used,
+            Err(_) => {
+                self.send_secured_spdm_error(session_id, SpdmErrorCode::SpdmErrorUnspecified, 0);
+                return;
+            }
+        };
+        let _ = self.send_secured_message(session_id, &send_buffer[0..used]);
+    }
+
+    // Handle the encapsulated DIGESTS / CERTIFICATE and return the next
+    // encapsulated request code, or None if the requester cert chain is complete.
+    fn process_encap_response(
+        &mut self,
+        request_code: SpdmResponseResponseCode,
+        response: &[u8],
+    ) -> SpdmResult<Option<SpdmResponseResponseCode>> {
+        let mut reader = Reader::init(response);
+        let message_header = SpdmMessageHeader::read(&mut reader).ok_or(spdm_err!(EIO))?;
+        match (request_code, message_header.request_response_code) {
+            (
+                SpdmResponseResponseCode::SpdmRequestGetDigests,
+                SpdmResponseResponseCode::SpdmResponseDigests,
+            ) => {
+                let digests = SpdmDigestsResponsePayload::spdm_read(&mut self.common, &mut reader)
+                    .ok_or(spdm_err!(EIO))?;
+                debug!("!!! encap digests : {:02x?}\n", digests);
+                // only the first provisioned slot is used
+                let slot_id = digests.slot_mask.trailing_zeros();
+                if slot_id as usize >= SPDM_MAX_SLOT_NUMBER {
+                    return spdm_result_err!(ENOENT, "no requester cert chain");
+                }
+                self.common.peer_info.peer_cert_chain_slot_id = slot_id as u8;
+                Ok(Some(SpdmResponseResponseCode::SpdmRequestGetCertificate))
+            }
+            (
+                SpdmResponseResponseCode::SpdmRequestGetCertificate,
+                SpdmResponseResponseCode::SpdmResponseCertificate,
+            ) => {
+                let certificate =
+                    SpdmCertificateResponsePayload::spdm_read(&mut self.common, &mut reader)
+                        .ok_or(spdm_err!(EIO))?;
+                debug!("!!! encap certificate : {:02x?}\n", certificate);
+                let peer_info = &mut self.common.peer_info;
+                let offset = peer_info.peer_cert_chain.cert_chain.data_size as usize;
+                let portion_length = certificate.portion_length as usize;
+                if certificate.slot_id != peer_info.peer_cert_chain_slot_id
+                    || portion_length == 0
+                    || offset + portion_length > config::MAX_SPDM_CERT_CHAIN_DATA_SIZE
+                {
+                    return spdm_result_err!(EINVAL);
+                }
+                peer_info.peer_cert_chain.cert_chain.data[offset..(offset + portion_length)]
+                    .copy_from_slice(&certificate.cert_chain[..portion_length]);
+                peer_info.peer_cert_chain.cert_chain.data_size = (offset + portion_length) as u16;
+                if certificate.remainder_length != 0 {
+                    return Ok(Some(SpdmResponseResponseCode::SpdmRequestGetCertificate));
+                }
+
+                self.common.verify_peer_cert_chain()?;
+                Ok(None)
+            }
+            _ => {
+                error!(
+                    "!!! encap response : unexpected {:02x?} !!!\n",
+                    message_header.request_response_code
+                );
+                spdm_result_err!(EINVAL)
+            }
+        }
+    }
+
+    pub fn handle_spdm_deliver_encapsulated_response(&mut self, session_id: u32, bytes: &[u8]) {
+        let mut reader = Reader::init(bytes);
+        SpdmMessageHeader::read(&mut reader);
+
+        let deliver_encap_response =
+            SpdmDeliverEncapsulatedResponsePayload::spdm_read(&mut self.common, &mut reader);
+        let deliver_encap_response = match deliver_encap_response {
+            Some(deliver_encap_response) => deliver_encap_response,
+            None => {
+                error!("!!! deliver_encap_response : fail !!!\n");
+                self.send_secured_spdm_error(session_id, SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+                return;
+            }
+        };
+        debug!(
+            "!!! deliver_encap_response : {:02x?}\n",
+            deliver_encap_response.request_id
+        );
+
+        let mut_auth_pending = self.is_encap_mut_auth_pending(session_id);
+        let encap_request_allowed = self.is_encap_request_allowed(session_id);
+        let (request_id, request_code) = match self.encap_request {
+            Some(encap_request) if encap_request_allowed => encap_request,
+            _ => {
+                error!("!!! deliver_encap_response : no encapsulated request !!!\n");
+                self.send_secured_spdm_error(
+                    session_id,
+                    SpdmErrorCode::SpdmErrorUnexpectedRequest,
+                    0,
+                );
+                return;
+            }
+        };
+        if deliver_encap_response.request_id != request_id {
+            error!("!!! deliver_encap_response : unexpected request id !!!\n");
+            self.send_secured_spdm_error(session_id, SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+            return;
+        }
+
+        let response_size = deliver_encap_response.response_size as usize;
+        let next_request_code = match self.process_encap_response(
+            request_code,
+            &deliver_encap_response.response[..response_size],
+        ) {
+            Ok(next_request_code) => next_request_code,
+            Err(_) => {
+                self.encap_request = None;
+                self.common.peer_info.peer_cert_chain.cert_chain.data_size = 0;
+                self.send_secured_spdm_error(session_id, SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+                return;
+            }
+        };
+
+        let mut payload = SpdmEncapsulatedResponseAckPayload {
+            ack_request_id: request_id,
+            ..Default::default()
+        };
+        if let Some(next_request_code) = next_request_code {
+            let next_request_id = request_id.wrapping_add(1);
+            payload.request_id = next_request_id;
+            payload.payload_type =
+                SpdmEncapsulatedResponseAckPayloadType::SpdmEncapsulatedPayloadPresent;
+            payload.request_size =
+                match self.encode_encap_request(next_request_code, &mut payload.request) {
+                    Ok(request_size) => request_size as u32,
+                    Err(_) => {
+                        self.send_secured_spdm_error(
+                            session_id,
+                            SpdmErrorCode::SpdmErrorUnspecified,
+                            0,
+                        );
+                        return;
+                    }
+                };
+            self.encap_request = Some((next_request_id, next_request_code));
+        } else {
+            self.encap_request = None;
+            // the slot to sign FINISH with is only reported for mutual authentication
+            if mut_auth_pending
+                && self.common.negotiate_info.spdm_version_sel.get_u8()
+                    >= SpdmVersion::SpdmVersion12.get_u8()
+            {
+                payload.payload_type =
+                    SpdmEncapsulatedResponseAckPayloadType::SpdmEncapsulatedPayloadReqSlotNumber;
+                payload.request[0] = self.common.peer_info.peer_cert_chain_slot_id;
+                payload.request_size = 1;
+            } else {
+                payload.payload_type =
+                    SpdmEncapsulatedResponseAckPayloadType::SpdmEncapsulatedPayloadAbsent;
+            }
+        }
+
+        info!("send spdm encapsulated response ack\n");
+        let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        let mut writer = Writer::init(&mut send_buffer);
+        let response = SpdmMessage {
+            header: SpdmMessageHeader {
+                version: self.common.negotiate_info.spdm_version_sel,
+                request_response_code:
+                    SpdmResponseResponseCode::SpdmResponseEncapsulatedResponseAck,
+            },
+            payload: SpdmMessagePayload::SpdmEncapsulatedResponseAck(payload),
+        };
+        let used = match response.spdm_encode(&mut self.common, &mut writer) {
+            Ok(used) => used,
+            Err(_) => {
+                self.send_secured_spdm_error(session_id, SpdmErrorCode::SpdmErrorUnspecified, 0);
+                return;
+            }
+        };
+        let _ = self.send_secured_message(session_id, &send_buffer[0..used]);
+    }
+}
@@ -17,6 +17,7 @@ impl<'a> ResponderContext<'a> {
             debug!("!!! end_session req : {:02x?}\n", end_session_req);
         } else {
             error!("!!! end_session req : fail !!!\n");
+            self.send_secured_spdm_error(session_id, SpdmErrorCode::SpdmErrorInvalidRequest, 0);
             return;
         }
 
@@ -26,13 +27,23 @@ impl<'a> ResponderContext<'a> {
         let mut writer = Writer::init(&mut send_buffer);
         let response = SpdmMessage {
             header: SpdmMessageHeader {
-                version: SpdmVersion::SpdmVersion11,
+                version: self.common.negotiate_info.spdm_version_sel,
                 request_response_code: SpdmResponseResponseCode::SpdmResponseEndSessionAck,
             },
             payload: SpdmMessagePayload::SpdmEndSessionResponse(SpdmEndSessionResponsePayload {}),
         };
-        response.spdm_encode(&mut self.common, &mut writer);
-        let used = writer.used();
+        let used = match response.spdm_encode(&mut self.common, &mut writer) {
+            Ok(used) => used,
+            Err(_) => {
+                self.send_secured_spdm_error(session_id, SpdmErrorCode::SpdmErrorUnspecified, 0);
+                return;
+            }
+        };
         let _ = self.send_secured_message(session_id, &send_buffer[0..used]);
+
+        // END_SESSION_ACK is the last message protected with the session keys
+        if let Some(session) = self.common.get_session_via_id(session_id) {
+            session.teardown();
+        }
     }
 }
@@ -4,28 +4,136 @@
 
 #![forbid(unsafe_code)]
 
+use crate::common::SpdmCryptoFailurePolicy;
 use crate::responder::*;
 
+/// Crypto operations of the responder whose failure is reported with
+/// `crypto_failure_policy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpdmCryptoOperation {
+    Random,
+    Hash,
+    Sign,
+    DheKeyPair,
+    KeySchedule,
+    Hmac,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SpdmCryptoFailureStats {
+    /// Requests answered with an ERROR because the crypto backend failed.
+    pub failures: u32,
+    pub last_operation: Option<SpdmCryptoOperation>,
+}
+
 impl<'a> ResponderContext<'a> {
+    pub fn get_crypto_failure_stats(&self) -> SpdmCryptoFailureStats {
+        self.crypto_failure_stats
+    }
+
+    /// Answer the current request after the crypto backend failed in
+    /// `operation`. The handler cleans up the state it set up so far.
+    pub(crate) fn send_crypto_failure(&mut self, operation: SpdmCryptoOperation) {
+        self.send_crypto_failure_to(None, operation)
+    }
+
+    /// As `send_crypto_failure()` for a request received in `session_id`.
+    pub(crate) fn send_crypto_failure_to(
+        &mut self,
+        session_id: Option<u32>,
+        operation: SpdmCryptoOperation,
+    ) {
+        error!("!!! crypto {:?} : fail !!!\n", operation);
+        let stats = &mut self.crypto_failure_stats;
+        stats.failures = stats.failures.saturating_add(1);
+        stats.last_operation = Some(operation);
+
+        match self.common.config_info.crypto_failure_policy {
+            SpdmCryptoFailurePolicy::Unspecified => {
+                self.send_spdm_error_to(session_id, SpdmErrorCode::SpdmErrorUnspecified, 0)
+            }
+            SpdmCryptoFailurePolicy::Busy => {
+                self.send_spdm_error_to(session_id, SpdmErrorCode::SpdmErrorBusy, 0)
+            }
+            SpdmCryptoFailurePolicy::RequestResynch => {
+                self.send_spdm_error_to(session_id, SpdmErrorCode::SpdmErrorRequestResynch, 0);
+                self.common.reset_runtime_info();
+            }
+        }
+    }
+
     pub fn send_spdm_error(&mut self, error_code: SpdmErrorCode, error_data: u8) {
-        info!("send spdm version\n");
+        self.send_spdm_error_with_ext_data(
+            error_code,
+            error_data,
+            SpdmErrorResponseExtData::SpdmErrorExtDataNone(SpdmErrorResponseNoneExtData {}),
+        );
+    }
+
+    pub fn send_spdm_error_with_ext_data(
+        &mut self,
+        error_code: SpdmErrorCode,
+        error_data: u8,
+        extended_data: SpdmErrorResponseExtData,
+    ) {
+        self.send_spdm_error_in(None, error_code, error_data, extended_data);
+    }
+
+    /// ERROR answering a request received in the session `session_id`,
+    /// sent in that session.
+    pub fn send_secured_spdm_error(
+        &mut self,
+        session_id: u32,
+        error_code: SpdmErrorCode,
+        error_data: u8,
+    ) {
+        self.send_spdm_error_in(
+            Some(session_id),
+            error_code,
+            error_data,
+            SpdmErrorResponseExtData::SpdmErrorExtDataNone(SpdmErrorResponseNoneExtData {}),
+        );
+    }
+
+    /// ERROR for a request received in `session_id`, in the session, or
+    /// outside of a session if None.
+    pub(crate) fn send_spdm_error_to(
+        &mut self,
+        session_id: Option<u32>,
+        error_code: SpdmErrorCode,
+        error_data: u8,
+    ) {
+        self.send_spdm_error_in(
+            session_id,
+            error_code,
+            error_data,
+            SpdmErrorResponseExtData::SpdmErrorExtDataNone(SpdmErrorResponseNoneExtData {}),
+        );
+    }
+
+    fn send_spdm_error_in(
+        &mut self,
+        session_id: Option<u32>,
+        error_code: SpdmErrorCode,
+        error_data: u8,
+        extended_data: SpdmErrorResponseExtData,
+    ) {
+        info!("send spdm error\n");
         let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
         let mut writer = Writer::init(&mut send_buffer);
-        let response = SpdmMessage {
-            header: SpdmMessageHeader {
-                version: SpdmVersion::SpdmVersion11,
-                request_response_code: SpdmResponseResponseCode::SpdmResponseError,
-            },
-            payload: SpdmMessagePayload::SpdmErrorResponse(SpdmErrorResponsePayload {
-                error_code,
-                error_data,
-                extended_data: SpdmErrorResponseExtData::SpdmErrorExtDataNone(
-                    SpdmErrorResponseNoneExtData {},
-                ),
-            }),
+        let response =
+            SpdmMessage::new_error_response(&self.common, error_code, error_data, extended_data);
+        let used = match response.spdm_encode(&mut self.common, &mut writer) {
+            Ok(used) => used,
+            Err(_) => {
+                error!("!!! encode error response : fail !!!\n");
+                return;
+            }
+        };
+        let _ = match session_id {
+            Some(session_id) => self.send_secured_message(session_id, &send_buffer[0..used]),
+            None => self.send_message(&send_buffer[0..used]),
         };
-        response.spdm_encode(&mut self.common, &mut writer);
-        let used = writer.used();
-        let _ = self.send_message(&send_buffer[0..used]);
+        self.last_error_code = Some(error_code);
     }
 }
@@ -6,8 +6,6 @@
 
 use crate::responder::*;
 
-use crate::common::ManagedBuffer;
-
 impl<'a> ResponderContext<'a> {
     pub fn handle_spdm_finish(&mut self, session_id: u32, bytes: &[u8]) {
         let mut reader = Reader::init(bytes);
@@ -24,28 +22,102 @@ impl<'a> ResponderContext<'a> {
         let finish_req = finish_req.unwrap();
         let read_used = reader.used();
 
-        // verify HMAC with finished_key
+        let session = match self.common.get_session_via_id(session_id) {
+            Some(session) => session,
+            None => {
+                self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+                return;
+            }
+        };
+        let message_k = match session.runtime_info.message_k.try_clone() {
+            Ok(message_k) => message_k,
+            Err(_) => {
+                self.send_crypto_failure(SpdmCryptoOperation::Hash);
+                return;
+            }
+        };
+        let mut_auth = !session.get_mut_auth_requested().is_empty();
+        let slot_id = session.get_slot_id();
+        if mut_auth
+            != finish_req
+                .finish_request_attributes
+                .contains(SpdmFinishRequestAttributes::SIGNATURE_INCLUDED)
+            || (mut_auth
+                && (self.encap_request.is_some()
+                    || finish_req.req_slot_id != self.common.peer_info.peer_cert_chain_slot_id))
+        {
+            error!("!!! finish req : mut auth mismatch !!!\n");
+            self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+            return;
+        }
+
+        // the cert slot may have been switched by a CHALLENGE since KEY_EXCHANGE
+        if self.common.set_my_cert_chain_slot_id(slot_id).is_err() {
+            self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+            return;
+        }
+
         let base_hash_size = self.common.negotiate_info.base_hash_sel.get_size() as usize;
-        let temp_used = read_used - base_hash_size;
+        let signature_size = if mut_auth {
+            self.common.get_req_asym_key_size() as usize
+        } else {
+            0
+        };
+        let temp_used = read_used - base_hash_size - signature_size;
 
-        let mut message_f = ManagedBuffer::default();
+        let mut message_f = match self.common.new_message_f(false, mut_auth, &message_k) {
+            Ok(message_f) => message_f,
+            Err(_) => {
+                self.send_crypto_failure(SpdmCryptoOperation::Hash);
+                return;
+            }
+        };
         if message_f.append_message(&bytes[..temp_used]).is_none() {
             self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
             return;
         }
 
-        let session = self.common.get_session_via_id(session_id).unwrap();
-        let message_k = session.runtime_info.message_k;
+        // verify signature with the requester cert chain
+        if mut_auth {
+            if self
+                .common
+                .verify_finish_req_signature(&message_k, &message_f, &finish_req.signature)
+                .is_err()
+            {
+                error!("verify_finish_req_signature fail");
+                self.send_spdm_error(SpdmErrorCode::SpdmErrorDecryptError, 0);
+                if let Some(session) = self.common.get_session_via_id(session_id) {
+                    session.teardown();
+                }
+                return;
+            } else {
+                info!("verify_finish_req_signature pass");
+            }
+            if message_f
+                .append_message(finish_req.signature.as_ref())
+                .is_none()
+            {
+                self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+                return;
+            }
+        }
 
+        // verify HMAC with finished_key
         let transcript_data =
             self.common
-                .calc_rsp_transcript_data(false, &message_k, Some(&message_f));
+                .calc_rsp_transcript_data(false, mut_auth, &message_k, Some(&message_f));
         if transcript_data.is_err() {
             self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
             return;
         }
         let transcript_data = transcript_data.unwrap();
-        let session = self.common.get_session_via_id(session_id).unwrap();
+        let session = match self.common.get_session_via_id(session_id) {
+            Some(session) => session,
+            None => {
+                self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+                return;
+            }
+        };
         if session
             .verify_hmac_with_request_finished_key(
                 transcript_data.as_ref(),
@@ -54,7 +126,8 @@ impl<'a> ResponderContext<'a> {
             .is_err()
         {
             error!("verify_hmac_with_request_finished_key fail");
-            self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+            session.teardown();
+            self.send_spdm_error(SpdmErrorCode::SpdmErrorDecryptError, 0);
             return;
         } else {
             info!("verify_hmac_with_request_finished_key pass");
@@ -84,19 +157,23 @@ impl<'a> ResponderContext<'a> {
         let mut writer = Writer::init(&mut send_buffer);
         let response = SpdmMessage {
             header: SpdmMessageHeader {
-                version: SpdmVersion::SpdmVersion11,
+                version: self.common.negotiate_info.spdm_version_sel,
                 request_response_code: SpdmResponseResponseCode::SpdmResponseFinishRsp,
             },
             payload: SpdmMessagePayload::SpdmFinishResponse(SpdmFinishResponsePayload {
-                verify_data: SpdmDigestStruct {
-                    data_size: self.common.negotiate_info.base_hash_sel.get_size(),
-                    data: [0xcc; SPDM_MAX_HASH_SIZE],
-                },
+                verify_data: SpdmDigestStruct::placeholder(
+                    self.common.negotiate_info.base_hash_sel,
+                ),
             }),
         };
 
-        response.spdm_encode(&mut self.common, &mut writer);
-        let used = writer.used();
+        let used = match response.spdm_encode(&mut self.common, &mut writer) {
+            Ok(used) => used,
+            Err(_) => {
+                self.send_spdm_error(SpdmErrorCode::SpdmErrorUnspecified, 0);
+                return;
+            }
+        };
 
         if in_clear_text {
             // generate HMAC with finished_key
@@ -106,68 +183,88 @@ impl<'a> ResponderContext<'a> {
                 .is_none()
             {
                 self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
-                let session = self.common.get_session_via_id(session_id).unwrap();
-                let _ = session.teardown(session_id);
+                if let Some(session) = self.common.get_session_via_id(session_id) {
+                    session.teardown();
+                }
                 return;
             }
 
             let transcript_data =
                 self.common
-                    .calc_rsp_transcript_data(false, &message_k, Some(&message_f));
+                    .calc_rsp_transcript_data(false, mut_auth, &message_k, Some(&message_f));
             if transcript_data.is_err() {
                 self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
-                let session = self.common.get_session_via_id(session_id).unwrap();
-                let _ = session.teardown(session_id);
+                if let Some(session) = self.common.get_session_via_id(session_id) {
+                    session.teardown();
+                }
                 return;
             }
             let transcript_data = transcript_data.unwrap();
 
-            let session = self.common.get_session_via_id(session_id).unwrap();
+            let session = match self.common.get_session_via_id(session_id) {
+                Some(session) => session,
+                None => {
+                    self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+                    return;
+                }
+            };
             let hmac = session.generate_hmac_with_response_finished_key(transcript_data.as_ref());
             if hmac.is_err() {
-                let _ = session.teardown(session_id);
-                self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+                session.teardown();
+                self.send_crypto_failure(SpdmCryptoOperation::Hmac);
                 return;
             }
             let hmac = hmac.unwrap();
             if message_f.append_message(hmac.as_ref()).is_none() {
-                let _ = session.teardown(session_id);
+                session.teardown();
                 self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
                 return;
             }
-            session.runtime_info.message_f = message_f;
 
             // patch the message before send
             send_buffer[(used - base_hash_size)..used].copy_from_slice(hmac.as_ref());
         } else {
             if message_f.append_message(&send_buffer[..used]).is_none() {
                 self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
-                let session = self.common.get_session_via_id(session_id).unwrap();
-                let _ = session.teardown(session_id);
+                if let Some(session) = self.common.get_session_via_id(session_id) {
+                    session.teardown();
+                }
                 return;
             }
-            let session = self.common.get_session_via_id(session_id).unwrap();
-            session.runtime_info.message_f = message_f;
         }
 
         // generate the data secret
-        let th2 = self
-            .common
-            .calc_rsp_transcript_hash(false, &message_k, Some(&message_f));
+        let th2 =
+            self.common
+                .calc_rsp_transcript_hash(false, mut_auth, &message_k, Some(&message_f));
         if th2.is_err() {
-            self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
-            let session = self.common.get_session_via_id(session_id).unwrap();
-            let _ = session.teardown(session_id);
+            if let Some(session) = self.common.get_session_via_id(session_id) {
+                session.teardown();
+            }
+            self.send_crypto_failure(SpdmCryptoOperation::Hash);
             return;
         }
         let th2 = th2.unwrap();
         debug!("!!! th2 : {:02x?}\n", th2.as_ref());
-        let session = self.common.get_session_via_id(session_id).unwrap();
-        session.generate_data_secret(&th2).unwrap();
+        let session = match self.common.get_session_via_id(session_id) {
+            Some(session) => session,
+            None => {
+                self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+                return;
+            }
+        };
+        session.runtime_info.message_f = message_f;
+        if session.generate_data_secret(&th2).is_err() {
+            session.teardown();
+            self.send_crypto_failure(SpdmCryptoOperation::KeySchedule);
+            return;
+        }
 
         let _ = self.send_secured_message(session_id, &send_buffer[0..used]);
-        let session = self.common.get_session_via_id(session_id).unwrap();
-        // change state after message is sent.
-        session.set_session_state(crate::session::SpdmSessionState::SpdmSessionEstablished);
+        if let Some(session) = self.common.get_session_via_id(session_id) {
+            // change state after message is sent.
+            session.set_session_state(crate::session::SpdmSessionState::SpdmSessionEstablished);
+            self.common.save_resumption_psk(session_id);
+        }
     }
 }
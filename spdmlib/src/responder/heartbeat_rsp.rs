@@ -16,6 +16,12 @@ impl<'a> ResponderContext<'a> {
             debug!("!!! heartbeat req : {:02x?}\n", heartbeat_req);
         } else {
             error!("!!! heartbeat req : fail !!!\n");
+            self.send_secured_spdm_error(session_id, SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+            return;
+        }
+
+        if !self.common.is_heartbeat_cap_negotiated() {
+            self.send_secured_spdm_error(session_id, SpdmErrorCode::SpdmErrorUnsupportedRequest, 0);
             return;
         }
 
@@ -25,13 +31,23 @@ impl<'a> ResponderContext<'a> {
         let mut writer = Writer::init(&mut send_buffer);
         let response = SpdmMessage {
             header: SpdmMessageHeader {
-                version: SpdmVersion::SpdmVersion11,
+                version: self.common.negotiate_info.spdm_version_sel,
                 request_response_code: SpdmResponseResponseCode::SpdmResponseHeartbeatAck,
             },
             payload: SpdmMessagePayload::SpdmHeartbeatResponse(SpdmHeartbeatResponsePayload {}),
         };
-        response.spdm_encode(&mut self.common, &mut writer);
-        let used = writer.used();
-        let _ = self.send_secured_message(session_id, &send_buffer[0..used]);
+        let used = match response.spdm_encode(&mut self.common, &mut writer) {
+            Ok(used) => used,
+            Err(_) => {
+                self.send_secured_spdm_error(session_id, SpdmErrorCode::SpdmErrorUnspecified, 0);
+                return;
+            }
+        };
+        if self
+            .send_secured_message(session_id, &send_buffer[0..used])
+            .is_ok()
+        {
+            self.common.record_heartbeat(session_id);
+        }
     }
 }
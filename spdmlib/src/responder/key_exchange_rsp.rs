@@ -5,10 +5,7 @@
 #![forbid(unsafe_code)]
 
 use crate::responder::*;
-
-use crate::common::ManagedBuffer;
-
-use crate::crypto;
+use core::convert::TryFrom;
 
 impl<'a> ResponderContext<'a> {
     pub fn handle_spdm_key_exchange(&mut self, bytes: &[u8]) {
@@ -35,10 +32,41 @@ impl<'a> ResponderContext<'a> {
             return;
         }
 
+        let slot_id = key_exchange_req.unwrap().slot_id;
+        if self
+            .common
+            .set_my_signing_slot_id(SpdmSigningPurpose::KeyExchange, slot_id)
+            .is_err()
+        {
+            error!("!!! key_exchange req : slot {} not usable !!!\n", slot_id);
+            self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+            return;
+        }
+
         info!("send spdm key_exchange rsp\n");
 
-        let (exchange, key_exchange_context) =
-            crypto::dhe::generate_key_pair(self.common.negotiate_info.dhe_sel).unwrap();
+        let measurement_summary_hash = match self.generate_measurement_summary_hash(
+            key_exchange_req.unwrap().measurement_summary_hash_type,
+        ) {
+            Ok(measurement_summary_hash) => measurement_summary_hash,
+            Err(_) => {
+                error!("!!! measurement_summary_hash : fail !!!\n");
+                self.send_spdm_error(SpdmErrorCode::SpdmErrorUnspecified, 0);
+                return;
+            }
+        };
+
+        let (exchange, key_exchange_context) = match self
+            .common
+            .crypto_provider
+            .dhe_generate_key_pair(self.common.negotiate_info.dhe_sel)
+        {
+            Some(key_pair) => key_pair,
+            None => {
+                self.send_crypto_failure(SpdmCryptoOperation::DheKeyPair);
+                return;
+            }
+        };
 
         debug!("!!! exchange data : {:02x?}\n", exchange);
 
@@ -47,60 +75,86 @@ impl<'a> ResponderContext<'a> {
             &key_exchange_req.unwrap().exchange
         );
 
-        let final_key = key_exchange_context
-            .compute_final_key(&key_exchange_req.unwrap().exchange)
-            .unwrap();
+        // fails on an exchange data that is no valid public key
+        let mut final_key =
+            match key_exchange_context.compute_final_key(&key_exchange_req.unwrap().exchange) {
+                Some(final_key) => final_key,
+                None => {
+                    error!("!!! key_exchange req : bad exchange data !!!\n");
+                    self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+                    return;
+                }
+            };
 
-        debug!("!!! final_key : {:02x?}\n", final_key.as_ref());
+        let mut random = [0u8; SPDM_RANDOM_SIZE];
+        if self.common.crypto_provider.get_random(&mut random).is_err() {
+            self.send_crypto_failure(SpdmCryptoOperation::Random);
+            return;
+        }
 
-        let random = [0xafu8; SPDM_RANDOM_SIZE];
+        let rsp_session_id = match self.common.get_free_half_session_id(0xFFFE, false) {
+            Some(rsp_session_id) => rsp_session_id,
+            None => {
+                error!("!!! too many sessions : fail !!!\n");
+                self.send_spdm_error(SpdmErrorCode::SpdmErrorSessionLimitExceeded, 0);
+                return;
+            }
+        };
 
-        let rsp_session_id = 0xFFFE;
+        // the requester cert chain is retrieved with encapsulated requests before FINISH
+        let mut_auth_req = if self.common.is_mut_auth_cap_negotiated() {
+            SpdmKeyExchangeMutAuthAttributes::MUT_AUTH_REQ_WITH_ENCAP_REQUEST
+        } else {
+            SpdmKeyExchangeMutAuthAttributes::empty()
+        };
 
         let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
         let mut writer = Writer::init(&mut send_buffer);
-        let mut opaque = SpdmOpaqueStruct {
-            data_size: crate::common::OPAQUE_DATA_VERSION_SELECTION.len() as u16,
-            ..Default::default()
-        };
-        opaque.data[..(opaque.data_size as usize)]
-            .copy_from_slice(crate::common::OPAQUE_DATA_VERSION_SELECTION.as_ref());
-        let response = SpdmMessage {
-            header: SpdmMessageHeader {
-                version: SpdmVersion::SpdmVersion11,
-                request_response_code: SpdmResponseResponseCode::SpdmResponseKeyExchangeRsp,
-            },
-            payload: SpdmMessagePayload::SpdmKeyExchangeResponse(SpdmKeyExchangeResponsePayload {
-                heartbeat_period: 0x0,
-                rsp_session_id,
-                mut_auth_req: SpdmKeyExchangeMutAuthAttributes::empty(),
-                req_slot_id: 0x0,
-                random: SpdmRandomStruct { data: random },
-                exchange,
-                measurement_summary_hash: SpdmDigestStruct {
-                    data_size: self.common.negotiate_info.base_hash_sel.get_size(),
-                    data: [0xaa; SPDM_MAX_HASH_SIZE],
-                },
-                opaque,
-                signature: SpdmSignatureStruct {
-                    data_size: self.common.negotiate_info.base_asym_sel.get_size(),
-                    data: [0xbb; SPDM_MAX_ASYM_KEY_SIZE],
-                },
-                verify_data: SpdmDigestStruct {
-                    data_size: self.common.negotiate_info.base_hash_sel.get_size(),
-                    data: [0xcc; SPDM_MAX_HASH_SIZE],
-                },
-            }),
+        let opaque =
+            match SpdmOpaqueStruct::try_from(self.common.get_opaque_data_version_selection()) {
+                Ok(opaque) => opaque,
+                Err(_) => {
+                    self.send_spdm_error(SpdmErrorCode::SpdmErrorUnspecified, 0);
+                    return;
+                }
+            };
+        let response = SpdmKeyExchangeResponseBuilder::new(
+            rsp_session_id,
+            SpdmRandomStruct { data: random },
+            exchange,
+        )
+        .heartbeat_period(self.common.get_heartbeat_period())
+        .mut_auth_req(mut_auth_req, 0x0)
+        .measurement_summary_hash(measurement_summary_hash)
+        .opaque(opaque)
+        .build(&self.common);
+        let response = match response {
+            Ok(response) => response,
+            Err(_) => {
+                self.send_spdm_error(SpdmErrorCode::SpdmErrorUnspecified, 0);
+                return;
+            }
         };
 
-        response.spdm_encode(&mut self.common, &mut writer);
-        let used = writer.used();
+        let used = match response.spdm_encode(&mut self.common, &mut writer) {
+            Ok(used) => used,
+            Err(_) => {
+                self.send_spdm_error(SpdmErrorCode::SpdmErrorUnspecified, 0);
+                return;
+            }
+        };
 
         // generat signature
         let base_asym_size = self.common.negotiate_info.base_asym_sel.get_size() as usize;
         let base_hash_size = self.common.negotiate_info.base_hash_sel.get_size() as usize;
 
-        let mut message_k = ManagedBuffer::default();
+        let mut message_k = match self.common.new_message_k(false, false) {
+            Ok(message_k) => message_k,
+            Err(_) => {
+                self.send_crypto_failure(SpdmCryptoOperation::Hash);
+                return;
+            }
+        };
         if message_k.append_message(&bytes[..reader.used()]).is_none() {
             self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
             return;
@@ -117,7 +171,7 @@ impl<'a> ResponderContext<'a> {
 
         let signature = self.common.generate_key_exchange_rsp_signature(&message_k);
         if signature.is_err() {
-            self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+            self.send_crypto_failure(SpdmCryptoOperation::Sign);
             return;
         }
         let signature = signature.unwrap();
@@ -129,9 +183,9 @@ impl<'a> ResponderContext<'a> {
         // create session - generate the handshake secret (including finished_key)
         let th1 = self
             .common
-            .calc_rsp_transcript_hash(false, &message_k, None);
+            .calc_rsp_transcript_hash(false, false, &message_k, None);
         if th1.is_err() {
-            self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+            self.send_crypto_failure(SpdmCryptoOperation::Hash);
             return;
         }
         let th1 = th1.unwrap();
@@ -140,50 +194,75 @@ impl<'a> ResponderContext<'a> {
         let dhe_algo = self.common.negotiate_info.dhe_sel;
         let aead_algo = self.common.negotiate_info.aead_sel;
         let key_schedule_algo = self.common.negotiate_info.key_schedule_sel;
+        let spdm_version = self.common.negotiate_info.spdm_version_sel;
         let sequence_number_count = self.common.transport_encap.get_sequence_number_count();
-        let max_random_count = self.common.transport_encap.get_max_random_count();
+        let max_random_count = self.common.get_max_random_count();
+        let replay_window_size = self.common.config_info.replay_window_size;
+        let random_padding_policy = self.common.config_info.random_padding_policy;
+        let heartbeat_period = self.common.get_heartbeat_period();
 
-        let session = self.common.get_next_avaiable_session();
-        if session.is_none() {
-            error!("!!! too many sessions : fail !!!\n");
+        let session_id =
+            ((key_exchange_req.unwrap().req_session_id as u32) << 16) + rsp_session_id as u32;
+        if self.common.get_session_via_id(session_id).is_some() {
+            error!("!!! session id in use : fail !!!\n");
             self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
             return;
         }
 
-        let session = session.unwrap();
-        let session_id =
-            ((key_exchange_req.unwrap().req_session_id as u32) << 16) + rsp_session_id as u32;
-        session.setup(session_id).unwrap();
+        // a free slot and an unused session id were checked above
+        let mut session_guard = match self.common.setup_session(session_id) {
+            Ok(session_guard) => session_guard,
+            Err(_) => {
+                error!("!!! setup session : fail !!!\n");
+                return;
+            }
+        };
+        let session = session_guard.session();
         session.set_use_psk(false);
+        session.set_slot_id(slot_id);
+        session.set_mut_auth_requested(mut_auth_req);
+        session.set_heartbeat_period(heartbeat_period);
+        session.set_session_policy(key_exchange_req.unwrap().session_policy);
+        session.set_peer_opaque_data(&key_exchange_req.unwrap().opaque);
         session.set_crypto_param(hash_algo, dhe_algo, aead_algo, key_schedule_algo);
+        session.set_spdm_version(spdm_version);
         session.set_transport_param(sequence_number_count, max_random_count);
-        session.set_dhe_secret(&final_key);
-        session.generate_handshake_secret(&th1).unwrap();
+        session.set_replay_window_size(replay_window_size);
+        session.set_random_padding_policy(random_padding_policy);
+        if session.set_dhe_secret(&mut final_key).is_err()
+            || session.generate_handshake_secret(&th1).is_err()
+        {
+            drop(session_guard);
+            self.send_crypto_failure(SpdmCryptoOperation::KeySchedule);
+            return;
+        }
 
         // generate HMAC with finished_key
-        let transcript_data = self
-            .common
-            .calc_rsp_transcript_data(false, &message_k, None);
+        let transcript_data = session_guard
+            .context()
+            .calc_rsp_transcript_data(false, false, &message_k, None);
         if transcript_data.is_err() {
+            drop(session_guard);
             self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
             return;
         }
         let transcript_data = transcript_data.unwrap();
 
-        let session = self.common.get_session_via_id(session_id).unwrap();
+        let session = session_guard.session();
         let hmac = session.generate_hmac_with_response_finished_key(transcript_data.as_ref());
         if hmac.is_err() {
-            let _ = session.teardown(session_id);
-            self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+            drop(session_guard);
+            self.send_crypto_failure(SpdmCryptoOperation::Hmac);
             return;
         }
         let hmac = hmac.unwrap();
         if message_k.append_message(hmac.as_ref()).is_none() {
-            let _ = session.teardown(session_id);
+            drop(session_guard);
             self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
             return;
         }
         session.runtime_info.message_k = message_k;
+        session_guard.commit();
 
         // patch the message before send
         send_buffer[(used - base_hash_size - base_asym_size)..(used - base_hash_size)]
@@ -191,8 +270,9 @@ impl<'a> ResponderContext<'a> {
         send_buffer[(used - base_hash_size)..used].copy_from_slice(hmac.as_ref());
 
         let _ = self.send_message(&send_buffer[0..used]);
-        let session = self.common.get_session_via_id(session_id).unwrap();
-        // change state after message is sent.
-        session.set_session_state(crate::session::SpdmSessionState::SpdmSessionHandshaking);
+        if let Some(session) = self.common.get_session_via_id(session_id) {
+            // change state after message is sent.
+            session.set_session_state(crate::session::SpdmSessionState::SpdmSessionHandshaking);
+        }
     }
 }
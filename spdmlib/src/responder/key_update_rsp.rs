@@ -16,28 +16,56 @@ impl<'a> ResponderContext<'a> {
             debug!("!!! key_update req : {:02x?}\n", key_update_req);
         } else {
             error!("!!! key_update req : fail !!!\n");
-            self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+            self.send_secured_spdm_error(session_id, SpdmErrorCode::SpdmErrorInvalidRequest, 0);
             return;
         }
         let key_update_req = key_update_req.unwrap();
 
-        let session = self.common.get_session_via_id(session_id).unwrap();
-        match key_update_req.key_update_operation {
+        // the peer cert chain may have expired or been revoked since the session is set up
+        if matches!(
+            key_update_req.key_update_operation,
+            SpdmKeyUpdateOperation::SpdmUpdateSingleKey | SpdmKeyUpdateOperation::SpdmUpdateAllKeys
+        ) && self.common.config_info.reverify_peer_cert_on_key_update
+            && self
+                .common
+                .reverify_session_peer_cert_chain(session_id, false)
+                .is_err()
+        {
+            self.send_secured_spdm_error(session_id, SpdmErrorCode::SpdmErrorUnspecified, 0);
+            if let Some(session) = self.common.get_session_via_id(session_id) {
+                session.teardown();
+            }
+            return;
+        }
+
+        let session = match self.common.get_session_via_id(session_id) {
+            Some(session) => session,
+            None => {
+                self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+                return;
+            }
+        };
+        let result = match key_update_req.key_update_operation {
             SpdmKeyUpdateOperation::SpdmUpdateSingleKey => {
-                let _ = session.create_data_secret_update(true, false);
+                session.create_data_secret_update(true, false)
             }
             SpdmKeyUpdateOperation::SpdmUpdateAllKeys => {
-                let _ = session.create_data_secret_update(true, true);
-                let _ = session.activate_data_secret_update(true, true, true);
+                session.create_data_secret_update(true, true)
             }
+            // received with the new key, the previous ones are not needed anymore
             SpdmKeyUpdateOperation::SpdmVerifyNewKey => {
-                let _ = session.activate_data_secret_update(true, false, true);
+                session.activate_data_secret_update(true, true, true)
             }
             _ => {
                 error!("!!! key_update req : fail !!!\n");
-                self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+                self.send_secured_spdm_error(session_id, SpdmErrorCode::SpdmErrorInvalidRequest, 0);
                 return;
             }
+        };
+        // the keys are left unchanged
+        if result.is_err() {
+            self.send_crypto_failure_to(Some(session_id), SpdmCryptoOperation::KeySchedule);
+            return;
         }
 
         info!("send spdm key_update rsp\n");
@@ -46,7 +74,7 @@ impl<'a> ResponderContext<'a> {
         let mut writer = Writer::init(&mut send_buffer);
         let response = SpdmMessage {
             header: SpdmMessageHeader {
-                version: SpdmVersion::SpdmVersion11,
+                version: self.common.negotiate_info.spdm_version_sel,
                 request_response_code: SpdmResponseResponseCode::SpdmResponseKeyUpdateAck,
             },
             payload: SpdmMessagePayload::SpdmKeyUpdateResponse(SpdmKeyUpdateResponsePayload {
@@ -54,8 +82,13 @@ impl<'a> ResponderContext<'a> {
                 tag: key_update_req.tag,
             }),
         };
-        response.spdm_encode(&mut self.common, &mut writer);
-        let used = writer.used();
+        let used = match response.spdm_encode(&mut self.common, &mut writer) {
+            Ok(used) => used,
+            Err(_) => {
+                self.send_secured_spdm_error(session_id, SpdmErrorCode::SpdmErrorUnspecified, 0);
+                return;
+            }
+        };
         let _ = self.send_secured_message(session_id, &send_buffer[0..used]);
     }
 }
@@ -4,10 +4,17 @@
 
 #![forbid(unsafe_code)]
 
+#[cfg(any(
+    feature = "responder-challenge",
+    feature = "responder-key-exchange",
+    feature = "responder-psk-exchange"
+))]
+use crate::error::SpdmResult;
 use crate::responder::*;
 
 impl<'a> ResponderContext<'a> {
-    pub fn handle_spdm_measurement(&mut self, bytes: &[u8]) {
+    #[cfg(feature = "responder-measurements")]
+    pub fn handle_spdm_measurement(&mut self, session_id: Option<u32>, bytes: &[u8]) {
         let mut reader = Reader::init(bytes);
         SpdmMessageHeader::read(&mut reader);
 
@@ -17,12 +24,59 @@ impl<'a> ResponderContext<'a> {
             debug!("!!! get_measurements : {:02x?}\n", get_measurements);
         } else {
             error!("!!! get_measurements : fail !!!\n");
-            self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+            self.send_spdm_error_to(session_id, SpdmErrorCode::SpdmErrorInvalidRequest, 0);
             return;
         }
         let get_measurements = get_measurements.unwrap();
 
-        let measurement_digest_size = self.common.negotiate_info.measurement_hash_sel.get_size();
+        if get_measurements
+            .measurement_attributes
+            .contains(SpdmMeasurementeAttributes::INCLUDE_SIGNATURE)
+            && !self.is_provisioned()
+        {
+            error!("!!! cert chain not provisioned !!!\n");
+            self.send_spdm_error_to(session_id, SpdmErrorCode::SpdmErrorBusy, 0);
+            return;
+        }
+
+        if get_measurements
+            .measurement_attributes
+            .contains(SpdmMeasurementeAttributes::INCLUDE_SIGNATURE)
+            && self
+                .common
+                .set_my_signing_slot_id(SpdmSigningPurpose::Measurement, get_measurements.slot_id)
+                .is_err()
+        {
+            error!(
+                "!!! get_measurements : slot {} not usable !!!\n",
+                get_measurements.slot_id
+            );
+            self.send_spdm_error_to(session_id, SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+            return;
+        }
+
+        if get_measurements
+            .measurement_attributes
+            .contains(SpdmMeasurementeAttributes::INCLUDE_SIGNATURE)
+        {
+            if let Some(last_nonce) = self.common.runtime_info.last_measurement_nonce {
+                if last_nonce.data == get_measurements.nonce.data {
+                    error!("!!! get_measurements : nonce reused !!!\n");
+                    self.send_spdm_error_to(session_id, SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+                    return;
+                }
+            }
+        }
+
+        let measurement_record =
+            self.get_measurement_record(get_measurements.measurement_operation);
+        if measurement_record.is_none() {
+            error!("!!! measurement_record : fail !!!\n");
+            self.send_spdm_error_to(session_id, SpdmErrorCode::SpdmErrorUnspecified, 0);
+            return;
+        }
+        let (number_of_measurement, measurement_record) = measurement_record.unwrap();
+
         let signature_size = self.common.negotiate_info.base_asym_sel.get_size();
 
         if get_measurements
@@ -41,7 +95,14 @@ impl<'a> ResponderContext<'a> {
             .append_message(&bytes[..reader.used()])
             .is_none()
         {
-            self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+            self.send_spdm_error_to(session_id, SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+            return;
+        }
+
+        let mut nonce = [0u8; SPDM_NONCE_SIZE];
+        if self.common.crypto_provider.get_random(&mut nonce).is_err() {
+            self.common.runtime_info.message_m.reset_message();
+            self.send_crypto_failure_to(session_id, SpdmCryptoOperation::Random);
             return;
         }
 
@@ -49,129 +110,20 @@ impl<'a> ResponderContext<'a> {
         let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
         let mut writer = Writer::init(&mut send_buffer);
 
-        let number_of_measurement = if get_measurements.measurement_operation
-            == SpdmMeasurementOperation::SpdmMeasurementRequestAll
-        {
-            5
-        } else if get_measurements.measurement_operation
-            == SpdmMeasurementOperation::SpdmMeasurementQueryTotalNumber
-        {
-            0
-        } else {
-            1
-        };
-        let measurement_record = if get_measurements.measurement_operation
-            == SpdmMeasurementOperation::SpdmMeasurementRequestAll
-        {
-            SpdmMeasurementRecordStructure {
-                number_of_blocks: 5,
-                record: [
-                    SpdmMeasurementBlockStructure {
-                        index: 1,
-                        measurement_specification: SpdmMeasurementSpecification::DMTF,
-                        measurement_size: 3 + measurement_digest_size as u16,
-                        measurement: SpdmDmtfMeasurementStructure {
-                            r#type: SpdmDmtfMeasurementType::SpdmDmtfMeasurementRom,
-                            representation:
-                                SpdmDmtfMeasurementRepresentation::SpdmDmtfMeasurementDigest,
-                            value_size: measurement_digest_size as u16,
-                            value: [0x5au8; config::MAX_SPDM_MEASUREMENT_VALUE_LEN],
-                        },
-                    },
-                    SpdmMeasurementBlockStructure {
-                        index: 2,
-                        measurement_specification: SpdmMeasurementSpecification::DMTF,
-                        measurement_size: 3 + measurement_digest_size as u16,
-                        measurement: SpdmDmtfMeasurementStructure {
-                            r#type: SpdmDmtfMeasurementType::SpdmDmtfMeasurementFirmware,
-                            representation:
-                                SpdmDmtfMeasurementRepresentation::SpdmDmtfMeasurementDigest,
-                            value_size: SHA384_DIGEST_SIZE as u16,
-                            value: [0x5bu8; config::MAX_SPDM_MEASUREMENT_VALUE_LEN],
-                        },
-                    },
-                    SpdmMeasurementBlockStructure {
-                        index: 3,
-                        measurement_specification: SpdmMeasurementSpecification::DMTF,
-                        measurement_size: 3 + measurement_digest_size as u16,
-                        measurement: SpdmDmtfMeasurementStructure {
-                            r#type: SpdmDmtfMeasurementType::SpdmDmtfMeasurementHardwareConfig,
-                            representation:
-                                SpdmDmtfMeasurementRepresentation::SpdmDmtfMeasurementDigest,
-                            value_size: measurement_digest_size as u16,
-                            value: [0x5cu8; config::MAX_SPDM_MEASUREMENT_VALUE_LEN],
-                        },
-                    },
-                    SpdmMeasurementBlockStructure {
-                        index: 4,
-                        measurement_specification: SpdmMeasurementSpecification::DMTF,
-                        measurement_size: 3 + measurement_digest_size as u16,
-                        measurement: SpdmDmtfMeasurementStructure {
-                            r#type: SpdmDmtfMeasurementType::SpdmDmtfMeasurementFirmwareConfig,
-                            representation:
-                                SpdmDmtfMeasurementRepresentation::SpdmDmtfMeasurementDigest,
-                            value_size: measurement_digest_size as u16,
-                            value: [0x5du8; config::MAX_SPDM_MEASUREMENT_VALUE_LEN],
-                        },
-                    },
-                    SpdmMeasurementBlockStructure {
-                        index: 5,
-                        measurement_specification: SpdmMeasurementSpecification::DMTF,
-                        measurement_size: 3 + config::MAX_SPDM_MEASUREMENT_VALUE_LEN as u16,
-                        measurement: SpdmDmtfMeasurementStructure {
-                            r#type: SpdmDmtfMeasurementType::SpdmDmtfMeasurementManifest,
-                            representation:
-                                SpdmDmtfMeasurementRepresentation::SpdmDmtfMeasurementRawBit,
-                            value_size: config::MAX_SPDM_MEASUREMENT_VALUE_LEN as u16,
-                            value: [0x5eu8; config::MAX_SPDM_MEASUREMENT_VALUE_LEN],
-                        },
-                    },
-                ],
-            }
-        } else if let SpdmMeasurementOperation::Unknown(index) =
-            get_measurements.measurement_operation
-        {
-            SpdmMeasurementRecordStructure {
-                number_of_blocks: 1,
-                record: [
-                    SpdmMeasurementBlockStructure {
-                        index: 1,
-                        measurement_specification: SpdmMeasurementSpecification::DMTF,
-                        measurement_size: 3 + measurement_digest_size as u16,
-                        measurement: SpdmDmtfMeasurementStructure {
-                            r#type: SpdmDmtfMeasurementType::SpdmDmtfMeasurementRom,
-                            representation:
-                                SpdmDmtfMeasurementRepresentation::SpdmDmtfMeasurementDigest,
-                            value_size: measurement_digest_size as u16,
-                            value: [0x5au8 + index; config::MAX_SPDM_MEASUREMENT_VALUE_LEN],
-                        },
-                    },
-                    SpdmMeasurementBlockStructure::default(),
-                    SpdmMeasurementBlockStructure::default(),
-                    SpdmMeasurementBlockStructure::default(),
-                    SpdmMeasurementBlockStructure::default(),
-                ],
-            }
-        } else {
-            SpdmMeasurementRecordStructure::default()
-        };
-
         let response = SpdmMessage {
             header: SpdmMessageHeader {
-                version: SpdmVersion::SpdmVersion11,
+                version: self.common.negotiate_info.spdm_version_sel,
                 request_response_code: SpdmResponseResponseCode::SpdmResponseMeasurements,
             },
             payload: SpdmMessagePayload::SpdmMeasurementsResponse(
                 SpdmMeasurementsResponsePayload {
                     number_of_measurement,
-                    slot_id: 0x1,
+                    slot_id: get_measurements.slot_id,
                     measurement_record,
-                    nonce: SpdmNonceStruct {
-                        data: [0x5fu8; SPDM_NONCE_SIZE],
-                    },
-                    opaque: SpdmOpaqueStruct {
-                        data_size: 0,
-                        data: [0u8; config::MAX_SPDM_OPAQUE_SIZE],
+                    nonce: SpdmNonceStruct { data: nonce },
+                    opaque: match self.get_measurement_generation() {
+                        Some(generation) => self.common.encode_measurement_generation(generation),
+                        None => SpdmOpaqueStruct::default(),
                     },
                     signature: SpdmSignatureStruct {
                         data_size: signature_size as u16,
@@ -180,8 +132,13 @@ impl<'a> ResponderContext<'a> {
                 },
             ),
         };
-        response.spdm_encode(&mut self.common, &mut writer);
-        let used = writer.used();
+        let used = match response.spdm_encode(&mut self.common, &mut writer) {
+            Ok(used) => used,
+            Err(_) => {
+                self.send_spdm_error_to(session_id, SpdmErrorCode::SpdmErrorUnspecified, 0);
+                return;
+            }
+        };
 
         // generat signature
         if get_measurements
@@ -197,13 +154,15 @@ impl<'a> ResponderContext<'a> {
 
             let signature = self.common.generate_measurement_signature();
             if signature.is_err() {
-                self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+                self.common.runtime_info.message_m.reset_message();
+                self.send_crypto_failure_to(session_id, SpdmCryptoOperation::Sign);
                 return;
             }
             let signature = signature.unwrap();
             // patch the message before send
             send_buffer[(used - base_asym_size)..used].copy_from_slice(signature.as_ref());
             self.common.runtime_info.message_m.reset_message();
+            self.common.runtime_info.last_measurement_nonce = Some(get_measurements.nonce);
         } else {
             self.common
                 .runtime_info
@@ -211,6 +170,344 @@ impl<'a> ResponderContext<'a> {
                 .append_message(&send_buffer[..used]);
         }
 
-        let _ = self.send_message(&send_buffer[0..used]);
+        let _ = match session_id {
+            Some(session_id) => self.send_secured_message(session_id, &send_buffer[0..used]),
+            None => self.send_message(&send_buffer[0..used]),
+        };
+    }
+
+    /// Generation of the measurements, if the provider reports one.
+    pub fn get_measurement_generation(&self) -> Option<u32> {
+        Some((self.measurement_provider?.get_generation_cb?)())
+    }
+
+    /// Measurement summary hash for CHALLENGE_AUTH, KEY_EXCHANGE_RSP and
+    /// PSK_EXCHANGE_RSP: the hash of all measurement blocks, or of the
+    /// immutable ROM blocks for the TCB component measurement hash. A
+    /// provider with `get_measurement_summary_hash_cb` computes it instead.
+    ///
+    /// If the provider reports a generation, the hash is kept until the
+    /// generation changes.
+    #[cfg(any(
+        feature = "responder-challenge",
+        feature = "responder-key-exchange",
+        feature = "responder-psk-exchange"
+    ))]
+    pub(crate) fn generate_measurement_summary_hash(
+        &mut self,
+        measurement_summary_hash_type: SpdmMeasurementSummaryHashType,
+    ) -> SpdmResult<SpdmDigestStruct> {
+        let tcb_only = match measurement_summary_hash_type {
+            SpdmMeasurementSummaryHashType::SpdmMeasurementSummaryHashTypeTcb => true,
+            SpdmMeasurementSummaryHashType::SpdmMeasurementSummaryHashTypeAll => false,
+            _ => return Ok(SpdmDigestStruct::default()),
+        };
+
+        let generation = self.get_measurement_generation();
+        let base_hash_sel = self.common.negotiate_info.base_hash_sel;
+        if let Some(cache) = self.measurement_summary_hash_cache {
+            if Some(cache.generation) == generation
+                && cache.base_hash_algo == base_hash_sel
+                && cache.tcb_only == tcb_only
+            {
+                return Ok(cache.measurement_summary_hash);
+            }
+        }
+
+        let measurement_summary_hash =
+            self.calc_measurement_summary_hash(measurement_summary_hash_type, tcb_only)?;
+        self.measurement_summary_hash_cache =
+            generation.map(|generation| SpdmMeasurementSummaryHashCache {
+                generation,
+                base_hash_algo: base_hash_sel,
+                tcb_only,
+                measurement_summary_hash,
+            });
+        Ok(measurement_summary_hash)
+    }
+
+    #[cfg(any(
+        feature = "responder-challenge",
+        feature = "responder-key-exchange",
+        feature = "responder-psk-exchange"
+    ))]
+    fn calc_measurement_summary_hash(
+        &mut self,
+        measurement_summary_hash_type: SpdmMeasurementSummaryHashType,
+        tcb_only: bool,
+    ) -> SpdmResult<SpdmDigestStruct> {
+        if let Some(get_measurement_summary_hash_cb) = self
+            .measurement_provider
+            .and_then(|provider| provider.get_measurement_summary_hash_cb)
+        {
+            return get_measurement_summary_hash_cb(
+                measurement_summary_hash_type,
+                self.common.negotiate_info.base_hash_sel,
+            )
+            .ok_or(spdm_err!(EFAULT));
+        }
+        let (_, measurement_record) = self
+            .get_measurement_record(SpdmMeasurementOperation::SpdmMeasurementRequestAll)
+            .ok_or(spdm_err!(EFAULT))?;
+
+        let mut blocks =
+            [0u8; config::MAX_SPDM_MEASUREMENT_BLOCK_COUNT * SPDM_MAX_MEASUREMENT_BLOCK_SIZE];
+        let mut writer = Writer::init(&mut blocks);
+        for block in measurement_record
+            .record
+            .iter()
+            .take(measurement_record.number_of_blocks as usize)
+        {
+            if tcb_only
+                && (block.measurement_specification != SpdmMeasurementSpecification::DMTF
+                    || block.measurement.r#type != SpdmDmtfMeasurementType::SpdmDmtfMeasurementRom)
+            {
+                continue;
+            }
+            block.spdm_encode(&mut self.common, &mut writer)?;
+        }
+        let used = writer.used();
+
+        self.common
+            .crypto_provider
+            .hash_all(self.common.negotiate_info.base_hash_sel, &blocks[..used])
+            .ok_or(spdm_err!(EFAULT))
     }
+
+    #[cfg(any(
+        feature = "responder-measurements",
+        feature = "responder-challenge",
+        feature = "responder-key-exchange",
+        feature = "responder-psk-exchange"
+    ))]
+    fn get_measurement_record(
+        &self,
+        measurement_operation: SpdmMeasurementOperation,
+    ) -> Option<(u8, SpdmMeasurementRecordStructure)> {
+        let measurement_specification_sel =
+            self.common.negotiate_info.measurement_specification_sel;
+        let (number_of_measurement, measurement_record) = match self.measurement_provider {
+            Some(provider) => {
+                let measurement_hash_sel = self.common.negotiate_info.measurement_hash_sel;
+                if measurement_operation
+                    == SpdmMeasurementOperation::SpdmMeasurementQueryTotalNumber
+                {
+                    let all = (provider.get_measurement_record_cb)(
+                        measurement_specification_sel,
+                        measurement_hash_sel,
+                        SpdmMeasurementOperation::SpdmMeasurementRequestAll,
+                    )?;
+                    (
+                        all.number_of_blocks,
+                        SpdmMeasurementRecordStructure::default(),
+                    )
+                } else {
+                    let measurement_record = (provider.get_measurement_record_cb)(
+                        measurement_specification_sel,
+                        measurement_hash_sel,
+                        measurement_operation,
+                    )?;
+                    (measurement_record.number_of_blocks, measurement_record)
+                }
+            }
+            None if measurement_specification_sel == SpdmMeasurementSpecification::DMTF => {
+                self.get_default_measurement_record(measurement_operation)
+            }
+            None => return None,
+        };
+
+        // every block must echo the negotiated specification
+        if measurement_record.number_of_blocks as usize > config::MAX_SPDM_MEASUREMENT_BLOCK_COUNT
+            || measurement_record
+                .record
+                .iter()
+                .take(measurement_record.number_of_blocks as usize)
+                .any(|block| {
+                    block.measurement_specification != measurement_specification_sel
+                        || !block.is_measurement_size_valid()
+                })
+        {
+            return None;
+        }
+
+        Some((number_of_measurement, measurement_record))
+    }
+
+    #[cfg(any(
+        feature = "responder-measurements",
+        feature = "responder-challenge",
+        feature = "responder-key-exchange",
+        feature = "responder-psk-exchange"
+    ))]
+    fn get_default_measurement_record(
+        &self,
+        measurement_operation: SpdmMeasurementOperation,
+    ) -> (u8, SpdmMeasurementRecordStructure) {
+        let measurement_digest_size = self.common.negotiate_info.measurement_hash_sel.get_size();
+
+        let number_of_measurement = if measurement_operation
+            == SpdmMeasurementOperation::SpdmMeasurementRequestAll
+        {
+            5
+        } else if measurement_operation == SpdmMeasurementOperation::SpdmMeasurementQueryTotalNumber
+        {
+            0
+        } else {
+            1
+        };
+        let measurement_record =
+            if measurement_operation == SpdmMeasurementOperation::SpdmMeasurementRequestAll {
+                SpdmMeasurementRecordStructure {
+                    number_of_blocks: 5,
+                    record: [
+                        SpdmMeasurementBlockStructure {
+                            index: 1,
+                            measurement_specification: SpdmMeasurementSpecification::DMTF,
+                            measurement_size: 3 + measurement_digest_size as u16,
+                            measurement: SpdmDmtfMeasurementStructure {
+                                r#type: SpdmDmtfMeasurementType::SpdmDmtfMeasurementRom,
+                                representation:
+                                    SpdmDmtfMeasurementRepresentation::SpdmDmtfMeasurementDigest,
+                                value_size: measurement_digest_size as u16,
+                                value: [0x5au8; config::MAX_SPDM_MEASUREMENT_VALUE_LEN],
+                            },
+                        },
+                        SpdmMeasurementBlockStructure {
+                            index: 2,
+                            measurement_specification: SpdmMeasurementSpecification::DMTF,
+                            measurement_size: 3 + measurement_digest_size as u16,
+                            measurement: SpdmDmtfMeasurementStructure {
+                                r#type: SpdmDmtfMeasurementType::SpdmDmtfMeasurementFirmware,
+                                representation:
+                                    SpdmDmtfMeasurementRepresentation::SpdmDmtfMeasurementDigest,
+                                value_size: SHA384_DIGEST_SIZE as u16,
+                                value: [0x5bu8; config::MAX_SPDM_MEASUREMENT_VALUE_LEN],
+                            },
+                        },
+                        SpdmMeasurementBlockStructure {
+                            index: 3,
+                            measurement_specification: SpdmMeasurementSpecification::DMTF,
+                            measurement_size: 3 + measurement_digest_size as u16,
+                            measurement: SpdmDmtfMeasurementStructure {
+                                r#type: SpdmDmtfMeasurementType::SpdmDmtfMeasurementHardwareConfig,
+                                representation:
+                                    SpdmDmtfMeasurementRepresentation::SpdmDmtfMeasurementDigest,
+                                value_size: measurement_digest_size as u16,
+                                value: [0x5cu8; config::MAX_SPDM_MEASUREMENT_VALUE_LEN],
+                            },
+                        },
+                        SpdmMeasurementBlockStructure {
+                            index: 4,
+                            measurement_specification: SpdmMeasurementSpecification::DMTF,
+                            measurement_size: 3 + measurement_digest_size as u16,
+                            measurement: SpdmDmtfMeasurementStructure {
+                                r#type: SpdmDmtfMeasurementType::SpdmDmtfMeasurementFirmwareConfig,
+                                representation:
+                                    SpdmDmtfMeasurementRepresentation::SpdmDmtfMeasurementDigest,
+                                value_size: measurement_digest_size as u16,
+                                value: [0x5du8; config::MAX_SPDM_MEASUREMENT_VALUE_LEN],
+                            },
+                        },
+                        SpdmMeasurementBlockStructure {
+                            index: 5,
+                            measurement_specification: SpdmMeasurementSpecification::DMTF,
+                            measurement_size: 3 + config::MAX_SPDM_MEASUREMENT_VALUE_LEN as u16,
+                            measurement: SpdmDmtfMeasurementStructure {
+                                r#type: SpdmDmtfMeasurementType::SpdmDmtfMeasurementManifest,
+                                representation:
+                                    SpdmDmtfMeasurementRepresentation::SpdmDmtfMeasurementRawBit,
+                                value_size: config::MAX_SPDM_MEASUREMENT_VALUE_LEN as u16,
+                                value: [0x5eu8; config::MAX_SPDM_MEASUREMENT_VALUE_LEN],
+                            },
+                        },
+                    ],
+                }
+            } else if let SpdmMeasurementOperation::Unknown(index) = measurement_operation {
+                SpdmMeasurementRecordStructure {
+                    number_of_blocks: 1,
+                    record: [
+                        SpdmMeasurementBlockStructure {
+                            index: 1,
+                            measurement_specification: SpdmMeasurementSpecification::DMTF,
+                            measurement_size: 3 + measurement_digest_size as u16,
+                            measurement: SpdmDmtfMeasurementStructure {
+                                r#type: SpdmDmtfMeasurementType::SpdmDmtfMeasurementRom,
+                                representation:
+                                    SpdmDmtfMeasurementRepresentation::SpdmDmtfMeasurementDigest,
+                                value_size: measurement_digest_size as u16,
+                                value: [0x5au8 + index; config::MAX_SPDM_MEASUREMENT_VALUE_LEN],
+                            },
+                        },
+                        SpdmMeasurementBlockStructure::default(),
+                        SpdmMeasurementBlockStructure::default(),
+                        SpdmMeasurementBlockStructure::default(),
+                        SpdmMeasurementBlockStructure::default(),
+                    ],
+                }
+            } else {
+                SpdmMeasurementRecordStructure::default()
+            };
+
+        (number_of_measurement, measurement_record)
+    }
+}
+
+/// Source of the measurement blocks returned in MEASUREMENTS.
+///
+/// Without a provider the responder serves built-in DMTF blocks only.
+///
+/// The index of a block names the same component across reboots and
+/// firmware updates: a new component gets an index that was not used
+/// before, the index of a removed one is not reused. Requesters compare
+/// blocks by index, e.g. against reference values.
+#[derive(Clone, Copy)]
+pub struct SpdmMeasurementProvider {
+    /// Measurement specifications the provider can format blocks for.
+    pub measurement_specification: SpdmMeasurementSpecification,
+
+    /// Returns the record for the requested operation. Every block must carry
+    /// the negotiated `measurement_specification`; blocks of non-DMTF
+    /// specifications hold their raw `measurement_size` bytes in `measurement.value`.
+    pub get_measurement_record_cb: fn(
+        measurement_specification: SpdmMeasurementSpecification,
+        measurement_hash_algo: SpdmMeasurementHashAlgo,
+        measurement_operation: SpdmMeasurementOperation,
+    ) -> Option<SpdmMeasurementRecordStructure>,
+
+    /// Returns the CoRIM identifier of the block `index`, if the provider
+    /// knows one.
+    pub get_measurement_identifier_cb:
+        Option<fn(index: u8) -> Option<SpdmMeasurementIdentifier<'static>>>,
+
+    /// Returns the measurement summary hash of the TCB or of all
+    /// measurements, hashed with `base_hash_algo`. `None` hashes the blocks
+    /// of `get_measurement_record_cb`.
+    pub get_measurement_summary_hash_cb: Option<
+        fn(
+            measurement_summary_hash_type: SpdmMeasurementSummaryHashType,
+            base_hash_algo: SpdmBaseHashAlgo,
+        ) -> Option<SpdmDigestStruct>,
+    >,
+
+    /// Returns the generation of the measurements, incremented whenever a
+    /// measurement changes, e.g. when a firmware update is activated. It is
+    /// reported in the MEASUREMENTS opaque data and invalidates the
+    /// measurement summary hashes the responder keeps. `None` computes the
+    /// summary hash for every request and reports no generation.
+    pub get_generation_cb: Option<fn() -> u32>,
+}
+
+// Measurement summary hash of the latest CHALLENGE_AUTH, KEY_EXCHANGE_RSP or
+// PSK_EXCHANGE_RSP, reused while the generation stays the same.
+#[cfg(any(
+    feature = "responder-challenge",
+    feature = "responder-key-exchange",
+    feature = "responder-psk-exchange"
+))]
+#[derive(Clone, Copy)]
+pub(crate) struct SpdmMeasurementSummaryHashCache {
+    generation: u32,
+    base_hash_algo: SpdmBaseHashAlgo,
+    tcb_only: bool,
+    measurement_summary_hash: SpdmDigestStruct,
 }
@@ -7,24 +7,49 @@
 mod context;
 
 mod algorithm_rsp;
+mod app_message_rsp;
+mod busy_rsp;
 mod capability_rsp;
 mod certificate_rsp;
+#[cfg(feature = "responder-challenge")]
 mod challenge_rsp;
+mod chunk_rsp;
 mod digest_rsp;
+#[cfg(feature = "responder-encap")]
+mod encapsulated_rsp;
 mod end_session_rsp;
+#[cfg(feature = "responder-key-exchange")]
 mod finish_rsp;
+#[cfg(feature = "responder-heartbeat")]
 mod heartbeat_rsp;
+#[cfg(feature = "responder-key-exchange")]
 mod key_exchange_rsp;
+#[cfg(feature = "responder-key-update")]
 mod key_update_rsp;
 mod measurement_rsp;
+#[cfg(feature = "responder-psk-exchange")]
 mod psk_exchange_rsp;
+#[cfg(feature = "responder-psk-exchange")]
 mod psk_finish_rsp;
+mod vendor_rsp;
 mod version_rsp;
 
 mod error_rsp;
 
-pub use context::ResponderContext;
+pub use app_message_rsp::SpdmAppMessageHandler;
+pub use busy_rsp::SpdmThrottle;
+pub use context::{ProcessResult, ProcessStatus, ResponderContext, SpdmSessionRequiredRequests};
+pub use error_rsp::{SpdmCryptoFailureStats, SpdmCryptoOperation};
+pub use measurement_rsp::SpdmMeasurementProvider;
+pub use vendor_rsp::{SpdmVendorDefinedHandler, MAX_SPDM_VENDOR_DEFINED_HANDLERS};
 
+#[cfg(any(
+    feature = "responder-challenge",
+    feature = "responder-key-exchange",
+    feature = "responder-measurements"
+))]
+use crate::common::SpdmSigningPurpose;
+use crate::common::{SpdmMeasurementIdentifier, SpdmTranscriptSegment};
 use crate::config;
 use crate::msgs::*;
 use codec::{Codec, Reader, Writer};
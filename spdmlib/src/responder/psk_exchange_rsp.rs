@@ -5,11 +5,10 @@
 #![forbid(unsafe_code)]
 
 use config::MAX_SPDM_PSK_CONTEXT_SIZE;
+use core::convert::TryFrom;
 
 use crate::responder::*;
 
-use crate::common::ManagedBuffer;
-
 impl<'a> ResponderContext<'a> {
     pub fn handle_spdm_psk_exchange(&mut self, bytes: &[u8]) {
         let mut reader = Reader::init(bytes);
@@ -37,48 +36,114 @@ impl<'a> ResponderContext<'a> {
 
         info!("send spdm psk_exchange rsp\n");
 
-        let psk_context = [0xbb; MAX_SPDM_PSK_CONTEXT_SIZE];
+        let measurement_summary_hash = match self.generate_measurement_summary_hash(
+            psk_exchange_req.unwrap().measurement_summary_hash_type,
+        ) {
+            Ok(measurement_summary_hash) => measurement_summary_hash,
+            Err(_) => {
+                error!("!!! measurement_summary_hash : fail !!!\n");
+                self.send_spdm_error(SpdmErrorCode::SpdmErrorUnspecified, 0);
+                return;
+            }
+        };
 
-        let rsp_session_id = 0xFFFD;
+        // the resumption PSK of an earlier session, or a provisioned one
+        let psk_hint = psk_exchange_req.unwrap().psk_hint;
+        let psk = match self.common.get_resumption_psk(psk_hint.as_ref()) {
+            Some(resumption_psk) => Some((
+                resumption_psk.psk.clone(),
+                None,
+                resumption_psk.session_policy,
+            )),
+            None => self
+                .common
+                .crypto_provider
+                .psk_lookup(psk_hint.as_ref())
+                .map(|(psk_key, psk_context)| {
+                    (psk_key, psk_context, SpdmKeyExchangeSessionPolicy::empty())
+                }),
+        };
+        let (mut psk_key, psk_context, session_policy) = match psk {
+            Some(psk) => psk,
+            None => {
+                error!("!!! psk_exchange req : unknown psk hint !!!\n");
+                self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+                return;
+            }
+        };
+        let psk_context = match psk_context {
+            Some(psk_context) => psk_context,
+            None => {
+                let mut psk_context = SpdmPskContextStruct {
+                    data_size: self.common.negotiate_info.base_hash_sel.get_size(),
+                    data: [0u8; MAX_SPDM_PSK_CONTEXT_SIZE],
+                };
+                if self
+                    .common
+                    .crypto_provider
+                    .get_random(&mut psk_context.data[..(psk_context.data_size as usize)])
+                    .is_err()
+                {
+                    self.send_crypto_failure(SpdmCryptoOperation::Random);
+                    return;
+                }
+                psk_context
+            }
+        };
+
+        let rsp_session_id = match self.common.get_free_half_session_id(0xFFFD, false) {
+            Some(rsp_session_id) => rsp_session_id,
+            None => {
+                error!("!!! too many sessions : fail !!!\n");
+                self.send_spdm_error(SpdmErrorCode::SpdmErrorSessionLimitExceeded, 0);
+                return;
+            }
+        };
 
         let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
         let mut writer = Writer::init(&mut send_buffer);
-        let mut opaque = SpdmOpaqueStruct {
-            data_size: crate::common::OPAQUE_DATA_VERSION_SELECTION.len() as u16,
-            ..Default::default()
-        };
-        opaque.data[..(opaque.data_size as usize)]
-            .copy_from_slice(crate::common::OPAQUE_DATA_VERSION_SELECTION.as_ref());
+        let opaque =
+            match SpdmOpaqueStruct::try_from(self.common.get_opaque_data_version_selection()) {
+                Ok(opaque) => opaque,
+                Err(_) => {
+                    self.send_spdm_error(SpdmErrorCode::SpdmErrorUnspecified, 0);
+                    return;
+                }
+            };
         let response = SpdmMessage {
             header: SpdmMessageHeader {
-                version: SpdmVersion::SpdmVersion11,
+                version: self.common.negotiate_info.spdm_version_sel,
                 request_response_code: SpdmResponseResponseCode::SpdmResponsePskExchangeRsp,
             },
             payload: SpdmMessagePayload::SpdmPskExchangeResponse(SpdmPskExchangeResponsePayload {
-                heartbeat_period: 0x0,
+                heartbeat_period: self.common.get_heartbeat_period(),
                 rsp_session_id,
-                measurement_summary_hash: SpdmDigestStruct {
-                    data_size: self.common.negotiate_info.base_hash_sel.get_size(),
-                    data: [0xaa; SPDM_MAX_HASH_SIZE],
-                },
-                psk_context: SpdmPskContextStruct {
-                    data_size: self.common.negotiate_info.base_hash_sel.get_size(),
-                    data: psk_context,
-                },
+                measurement_summary_hash,
+                psk_context,
                 opaque,
-                verify_data: SpdmDigestStruct {
-                    data_size: self.common.negotiate_info.base_hash_sel.get_size(),
-                    data: [0xcc; SPDM_MAX_HASH_SIZE],
-                },
+                verify_data: SpdmDigestStruct::placeholder(
+                    self.common.negotiate_info.base_hash_sel,
+                ),
             }),
         };
 
-        response.spdm_encode(&mut self.common, &mut writer);
-        let used = writer.used();
+        let used = match response.spdm_encode(&mut self.common, &mut writer) {
+            Ok(used) => used,
+            Err(_) => {
+                self.send_spdm_error(SpdmErrorCode::SpdmErrorUnspecified, 0);
+                return;
+            }
+        };
 
         let base_hash_size = self.common.negotiate_info.base_hash_sel.get_size() as usize;
 
-        let mut message_k = ManagedBuffer::default();
+        let mut message_k = match self.common.new_message_k(false, true) {
+            Ok(message_k) => message_k,
+            Err(_) => {
+                self.send_crypto_failure(SpdmCryptoOperation::Hash);
+                return;
+            }
+        };
         if message_k.append_message(&bytes[..reader.used()]).is_none() {
             self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
             return;
@@ -94,9 +159,11 @@ impl<'a> ResponderContext<'a> {
         }
 
         // create session - generate the handshake secret (including finished_key)
-        let th1 = self.common.calc_rsp_transcript_hash(true, &message_k, None);
+        let th1 = self
+            .common
+            .calc_rsp_transcript_hash(true, false, &message_k, None);
         if th1.is_err() {
-            self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+            self.send_crypto_failure(SpdmCryptoOperation::Hash);
             return;
         }
         let th1 = th1.unwrap();
@@ -105,60 +172,81 @@ impl<'a> ResponderContext<'a> {
         let dhe_algo = self.common.negotiate_info.dhe_sel;
         let aead_algo = self.common.negotiate_info.aead_sel;
         let key_schedule_algo = self.common.negotiate_info.key_schedule_sel;
+        let spdm_version = self.common.negotiate_info.spdm_version_sel;
         let sequence_number_count = self.common.transport_encap.get_sequence_number_count();
-        let max_random_count = self.common.transport_encap.get_max_random_count();
+        let max_random_count = self.common.get_max_random_count();
+        let replay_window_size = self.common.config_info.replay_window_size;
+        let random_padding_policy = self.common.config_info.random_padding_policy;
+        let heartbeat_period = self.common.get_heartbeat_period();
 
-        let session = self.common.get_next_avaiable_session();
-        if session.is_none() {
-            error!("!!! too many sessions : fail !!!\n");
+        let session_id =
+            ((psk_exchange_req.unwrap().req_session_id as u32) << 16) + rsp_session_id as u32;
+        if self.common.get_session_via_id(session_id).is_some() {
+            error!("!!! session id in use : fail !!!\n");
             self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
             return;
         }
 
-        let session = session.unwrap();
-        let session_id =
-            ((psk_exchange_req.unwrap().req_session_id as u32) << 16) + rsp_session_id as u32;
-        session.setup(session_id).unwrap();
-        session.set_use_psk(true);
-        let mut psk_key = SpdmDheFinalKeyStruct {
-            data_size: b"TestPskData\0".len() as u16,
-            ..Default::default()
+        // a free slot and an unused session id were checked above
+        let mut session_guard = match self.common.setup_session(session_id) {
+            Ok(session_guard) => session_guard,
+            Err(_) => {
+                error!("!!! setup session : fail !!!\n");
+                return;
+            }
         };
-        psk_key.data[0..(psk_key.data_size as usize)].copy_from_slice(b"TestPskData\0");
+        let session = session_guard.session();
+        session.set_use_psk(true);
+        session.set_session_policy(session_policy);
+        session.set_heartbeat_period(heartbeat_period);
+        session.set_peer_opaque_data(&psk_exchange_req.unwrap().opaque);
         session.set_crypto_param(hash_algo, dhe_algo, aead_algo, key_schedule_algo);
+        session.set_spdm_version(spdm_version);
         session.set_transport_param(sequence_number_count, max_random_count);
-        session.set_dhe_secret(&psk_key); // TBD
-        session.generate_handshake_secret(&th1).unwrap();
+        session.set_replay_window_size(replay_window_size);
+        session.set_random_padding_policy(random_padding_policy);
+        if session.set_dhe_secret(&mut psk_key).is_err()
+            || session.generate_handshake_secret(&th1).is_err()
+        {
+            drop(session_guard);
+            self.send_crypto_failure(SpdmCryptoOperation::KeySchedule);
+            return;
+        }
 
         // generate HMAC with finished_key
-        let transcript_data = self.common.calc_rsp_transcript_data(true, &message_k, None);
+        let transcript_data = session_guard
+            .context()
+            .calc_rsp_transcript_data(true, false, &message_k, None);
         if transcript_data.is_err() {
+            drop(session_guard);
             self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
             return;
         }
         let transcript_data = transcript_data.unwrap();
 
-        let session = self.common.get_session_via_id(session_id).unwrap();
+        let session = session_guard.session();
         let hmac = session.generate_hmac_with_response_finished_key(transcript_data.as_ref());
         if hmac.is_err() {
-            let _ = session.teardown(session_id);
-            self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+            drop(session_guard);
+            self.send_crypto_failure(SpdmCryptoOperation::Hmac);
             return;
         }
         let hmac = hmac.unwrap();
         if message_k.append_message(hmac.as_ref()).is_none() {
-            let _ = session.teardown(session_id);
+            drop(session_guard);
             self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
             return;
         }
         session.runtime_info.message_k = message_k;
+        session_guard.commit();
 
         // patch the message before send
         send_buffer[(used - base_hash_size)..used].copy_from_slice(hmac.as_ref());
 
         let _ = self.send_message(&send_buffer[0..used]);
-        let session = self.common.get_session_via_id(session_id).unwrap();
-        // change state after message is sent.
-        session.set_session_state(crate::session::SpdmSessionState::SpdmSessionHandshaking);
+        if let Some(session) = self.common.get_session_via_id(session_id) {
+            // change state after message is sent.
+            session.set_session_state(crate::session::SpdmSessionState::SpdmSessionHandshaking);
+        }
     }
 }
@@ -6,8 +6,6 @@
 
 use crate::responder::*;
 
-use crate::common::ManagedBuffer;
-
 impl<'a> ResponderContext<'a> {
     pub fn handle_spdm_psk_finish(&mut self, session_id: u32, bytes: &[u8]) {
         let mut reader = Reader::init(bytes);
@@ -28,24 +26,47 @@ impl<'a> ResponderContext<'a> {
         let base_hash_size = self.common.negotiate_info.base_hash_sel.get_size() as usize;
         let temp_used = read_used - base_hash_size;
 
-        let mut message_f = ManagedBuffer::default();
+        let session = match self.common.get_session_via_id(session_id) {
+            Some(session) => session,
+            None => {
+                self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+                return;
+            }
+        };
+        let message_k = match session.runtime_info.message_k.try_clone() {
+            Ok(message_k) => message_k,
+            Err(_) => {
+                self.send_crypto_failure(SpdmCryptoOperation::Hash);
+                return;
+            }
+        };
+        let mut message_f = match self.common.new_message_f(false, false, &message_k) {
+            Ok(message_f) => message_f,
+            Err(_) => {
+                self.send_crypto_failure(SpdmCryptoOperation::Hash);
+                return;
+            }
+        };
         if message_f.append_message(&bytes[..temp_used]).is_none() {
             self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
             return;
         }
 
-        let session = self.common.get_session_via_id(session_id).unwrap();
-        let message_k = session.runtime_info.message_k;
-
         let transcript_data =
             self.common
-                .calc_rsp_transcript_data(true, &message_k, Some(&message_f));
+                .calc_rsp_transcript_data(true, false, &message_k, Some(&message_f));
         if transcript_data.is_err() {
             self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
             return;
         }
         let transcript_data = transcript_data.unwrap();
-        let session = self.common.get_session_via_id(session_id).unwrap();
+        let session = match self.common.get_session_via_id(session_id) {
+            Some(session) => session,
+            None => {
+                self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+                return;
+            }
+        };
         if session
             .verify_hmac_with_request_finished_key(
                 transcript_data.as_ref(),
@@ -54,7 +75,8 @@ impl<'a> ResponderContext<'a> {
             .is_err()
         {
             error!("verify_hmac_with_request_finished_key fail");
-            self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+            session.teardown();
+            self.send_spdm_error(SpdmErrorCode::SpdmErrorDecryptError, 0);
             return;
         } else {
             info!("verify_hmac_with_request_finished_key pass");
@@ -73,42 +95,58 @@ impl<'a> ResponderContext<'a> {
         let mut writer = Writer::init(&mut send_buffer);
         let response = SpdmMessage {
             header: SpdmMessageHeader {
-                version: SpdmVersion::SpdmVersion11,
+                version: self.common.negotiate_info.spdm_version_sel,
                 request_response_code: SpdmResponseResponseCode::SpdmResponsePskFinishRsp,
             },
             payload: SpdmMessagePayload::SpdmPskFinishResponse(SpdmPskFinishResponsePayload {}),
         };
 
-        response.spdm_encode(&mut self.common, &mut writer);
-        let used = writer.used();
+        let used = match response.spdm_encode(&mut self.common, &mut writer) {
+            Ok(used) => used,
+            Err(_) => {
+                self.send_spdm_error(SpdmErrorCode::SpdmErrorUnspecified, 0);
+                return;
+            }
+        };
 
         if message_f.append_message(&send_buffer[..used]).is_none() {
             self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
-            let session = self.common.get_session_via_id(session_id).unwrap();
-            let _ = session.teardown(session_id);
+            if let Some(session) = self.common.get_session_via_id(session_id) {
+                session.teardown();
+            }
             return;
         }
-        let session = self.common.get_session_via_id(session_id).unwrap();
-        session.runtime_info.message_f = message_f;
-
         // generate the data secret
         let th2 = self
             .common
-            .calc_rsp_transcript_hash(true, &message_k, Some(&message_f));
+            .calc_rsp_transcript_hash(true, false, &message_k, Some(&message_f));
         if th2.is_err() {
-            self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
-            let session = self.common.get_session_via_id(session_id).unwrap();
-            let _ = session.teardown(session_id);
+            if let Some(session) = self.common.get_session_via_id(session_id) {
+                session.teardown();
+            }
+            self.send_crypto_failure(SpdmCryptoOperation::Hash);
             return;
         }
         let th2 = th2.unwrap();
         debug!("!!! th2 : {:02x?}\n", th2.as_ref());
-        let session = self.common.get_session_via_id(session_id).unwrap();
-        session.generate_data_secret(&th2).unwrap();
+        let session = match self.common.get_session_via_id(session_id) {
+            Some(session) => session,
+            None => {
+                self.send_spdm_error(SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+                return;
+            }
+        };
+        session.runtime_info.message_f = message_f;
+        if session.generate_data_secret(&th2).is_err() {
+            session.teardown();
+            self.send_crypto_failure(SpdmCryptoOperation::KeySchedule);
+            return;
+        }
 
         let _ = self.send_secured_message(session_id, &send_buffer[0..used]);
-        let session = self.common.get_session_via_id(session_id).unwrap();
-        // change state after message is sent.
-        session.set_session_state(crate::session::SpdmSessionState::SpdmSessionEstablished);
+        if let Some(session) = self.common.get_session_via_id(session_id) {
+            // change state after message is sent.
+            session.set_session_state(crate::session::SpdmSessionState::SpdmSessionEstablished);
+        }
     }
 }
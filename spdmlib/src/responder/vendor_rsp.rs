@@ -0,0 +1,157 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+#![forbid(unsafe_code)]
+
+use crate::error::SpdmResult;
+use crate::responder::*;
+
+pub const MAX_SPDM_VENDOR_DEFINED_HANDLERS: usize = 4;
+
+/// Answers the VENDOR_DEFINED_REQUEST messages of one vendor.
+#[derive(Clone, Copy)]
+pub struct SpdmVendorDefinedHandler {
+    pub standard_id: SpdmStandardId,
+    pub vendor_id: SpdmVendorIdStruct,
+
+    /// Returns the payload of VENDOR_DEFINED_RESPONSE for `req_payload`,
+    /// an error is answered with ERROR(Unspecified). `session_id` is `None`
+    /// for requests received outside of a session.
+    pub handle_request_cb: fn(
+        session_id: Option<u32>,
+        req_payload: &[u8],
+    ) -> SpdmResult<SpdmVendorDefinedPayloadStruct>,
+}
+
+impl<'a> ResponderContext<'a> {
+    /// Answer the VENDOR_DEFINED_REQUEST messages for the standard and
+    /// vendor ID of `handler`, replacing the handler registered for them.
+    /// Fails with ENOMEM once MAX_SPDM_VENDOR_DEFINED_HANDLERS vendors are
+    /// registered.
+    pub fn register_vendor_defined_handler(
+        &mut self,
+        handler: SpdmVendorDefinedHandler,
+    ) -> SpdmResult {
+        let slot = match self.find_vendor_defined_handler(handler.standard_id, &handler.vendor_id) {
+            Some(i) => i,
+            None => self
+                .vendor_defined_handlers
+                .iter()
+                .position(|h| h.is_none())
+                .ok_or(spdm_err!(ENOMEM, "too many vendor defined handlers"))?,
+        };
+        self.vendor_defined_handlers[slot] = Some(handler);
+        Ok(())
+    }
+
+    fn find_vendor_defined_handler(
+        &self,
+        standard_id: SpdmStandardId,
+        vendor_id: &SpdmVendorIdStruct,
+    ) -> Option<usize> {
+        self.vendor_defined_handlers.iter().position(|h| match h {
+            Some(h) => {
+                h.standard_id == standard_id && h.vendor_id.as_slice() == vendor_id.as_slice()
+            }
+            None => false,
+        })
+    }
+
+    #[cfg(feature = "responder-vendor-defined")]
+    pub fn handle_spdm_vendor_defined_request(&mut self, session_id: Option<u32>, bytes: &[u8]) {
+        if self.vendor_defined_handlers.iter().all(|h| h.is_none()) {
+            error!("!!! vendor_defined_request : no handler !!!\n");
+            self.send_spdm_error_to(
+                session_id,
+                SpdmErrorCode::SpdmErrorUnsupportedRequest,
+                SpdmResponseResponseCode::SpdmRequestVendorDefinedRequest.get_u8(),
+            );
+            return;
+        }
+
+        let mut reader = Reader::init(bytes);
+        SpdmMessageHeader::read(&mut reader);
+
+        let vendor_defined_request =
+            SpdmVendorDefinedRequestPayload::spdm_read(&mut self.common, &mut reader);
+        let vendor_defined_request = match vendor_defined_request {
+            Some(vendor_defined_request) => vendor_defined_request,
+            None => {
+                error!("!!! vendor_defined_request : fail !!!\n");
+                self.send_spdm_error_to(session_id, SpdmErrorCode::SpdmErrorInvalidRequest, 0);
+                return;
+            }
+        };
+        debug!(
+            "!!! vendor_defined_request : {:02x?} {:02x?}\n",
+            vendor_defined_request.standard_id,
+            vendor_defined_request.vendor_id.as_slice()
+        );
+
+        let handler = match self.find_vendor_defined_handler(
+            vendor_defined_request.standard_id,
+            &vendor_defined_request.vendor_id,
+        ) {
+            Some(i) => self.vendor_defined_handlers[i].unwrap(),
+            None => {
+                error!("!!! vendor_defined_request : unknown vendor !!!\n");
+                self.send_spdm_error_to(
+                    session_id,
+                    SpdmErrorCode::SpdmErrorUnsupportedRequest,
+                    SpdmResponseResponseCode::SpdmRequestVendorDefinedRequest.get_u8(),
+                );
+                return;
+            }
+        };
+
+        let rsp_payload = match (handler.handle_request_cb)(
+            session_id,
+            vendor_defined_request.req_payload.as_slice(),
+        ) {
+            Ok(rsp_payload) => rsp_payload,
+            Err(_) => {
+                error!("!!! vendor_defined_request : handler fail !!!\n");
+                self.send_spdm_error_to(session_id, SpdmErrorCode::SpdmErrorUnspecified, 0);
+                return;
+            }
+        };
+
+        // SPDM header, StandardID, Len, VendorID, RespLength and the payload
+        let response_size = 9 + vendor_defined_request.vendor_id.len() + rsp_payload.len();
+        if response_size > config::MAX_SPDM_TRANSPORT_SIZE {
+            error!("!!! vendor_defined_response : too large !!!\n");
+            self.send_spdm_error_to(session_id, SpdmErrorCode::SpdmErrorUnspecified, 0);
+            return;
+        }
+
+        info!("send spdm vendor_defined_response\n");
+        let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        let mut writer = Writer::init(&mut send_buffer);
+        let response = SpdmMessage {
+            header: SpdmMessageHeader {
+                version: self.common.negotiate_info.spdm_version_sel,
+                request_response_code: SpdmResponseResponseCode::SpdmResponseVendorDefinedResponse,
+            },
+            payload: SpdmMessagePayload::SpdmVendorDefinedResponse(
+                SpdmVendorDefinedResponsePayload {
+                    standard_id: vendor_defined_request.standard_id,
+                    vendor_id: vendor_defined_request.vendor_id,
+                    rsp_payload,
+                },
+            ),
+        };
+        let used = match response.spdm_encode(&mut self.common, &mut writer) {
+            Ok(used) => used,
+            Err(_) => {
+                self.send_spdm_error_to(session_id, SpdmErrorCode::SpdmErrorUnspecified, 0);
+                return;
+            }
+        };
+
+        let _ = match session_id {
+            Some(session_id) => self.send_secured_message(session_id, &send_buffer[0..used]),
+            None => self.send_message(&send_buffer[0..used]),
+        };
+    }
+}
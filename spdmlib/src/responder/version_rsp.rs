@@ -7,6 +7,16 @@
 use crate::responder::*;
 
 impl<'a> ResponderContext<'a> {
+    /// GET_VERSION restarts the connection, which a session can't survive.
+    /// It is rejected in the session, which is terminated afterwards.
+    pub fn handle_spdm_version_in_session(&mut self, session_id: u32) {
+        error!("!!! get_version in session : fail !!!\n");
+        self.send_secured_spdm_error(session_id, SpdmErrorCode::SpdmErrorUnexpectedRequest, 0);
+        if let Some(session) = self.common.get_session_via_id(session_id) {
+            session.teardown();
+        }
+    }
+
     pub fn handle_spdm_version(&mut self, bytes: &[u8]) {
         let mut reader = Reader::init(bytes);
         SpdmMessageHeader::read(&mut reader);
@@ -20,8 +30,10 @@ impl<'a> ResponderContext<'a> {
             return;
         }
 
-        // clear cache data
-        self.common.reset_runtime_info();
+        // the sessions and the exchanges in progress end with the connection
+        self.common.reset_connection();
+        self.chunk_send_ack = None;
+        self.encap_request = None;
 
         if self
             .common
@@ -37,27 +49,21 @@ impl<'a> ResponderContext<'a> {
         info!("send spdm version\n");
         let mut send_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
         let mut writer = Writer::init(&mut send_buffer);
-        let response = SpdmMessage {
-            header: SpdmMessageHeader {
-                version: SpdmVersion::SpdmVersion11,
-                request_response_code: SpdmResponseResponseCode::SpdmResponseVersion,
-            },
-            payload: SpdmMessagePayload::SpdmVersionResponse(SpdmVersionResponsePayload {
-                version_number_entry_count: 2,
-                versions: [
-                    SpdmVersionStruct {
-                        update: 0,
-                        version: self.common.config_info.spdm_version[0],
-                    },
-                    SpdmVersionStruct {
-                        update: 0,
-                        version: self.common.config_info.spdm_version[1],
-                    },
-                ],
-            }),
+        let response =
+            match SpdmMessage::new_version_response(&self.common.config_info.spdm_version) {
+                Ok(response) => response,
+                Err(_) => {
+                    self.send_spdm_error(SpdmErrorCode::SpdmErrorUnspecified, 0);
+                    return;
+                }
+            };
+        let used = match response.spdm_encode(&mut self.common, &mut writer) {
+            Ok(used) => used,
+            Err(_) => {
+                self.send_spdm_error(SpdmErrorCode::SpdmErrorUnspecified, 0);
+                return;
+            }
         };
-        response.spdm_encode(&mut self.common, &mut writer);
-        let used = writer.used();
         let _ = self.send_message(&send_buffer[0..used]);
 
         self.common
@@ -3,17 +3,20 @@
 // SPDX-License-Identifier: BSD-2-Clause-Patent
 #![forbid(unsafe_code)]
 
-use crate::crypto;
+use core::convert::TryFrom;
+
+use crate::crypto::{SpdmCryptoProvider, SpdmExternalKeySchedule, SpdmKeyHandle, SpdmSessionKeyId};
 use crate::msgs::*;
 
 use crate::config;
 use crate::error::SpdmResult;
 use crate::key_schedule::SpdmKeySchedule;
+use crate::time;
 
 use codec::enum_builder;
 use codec::{Codec, Reader, Writer};
 
-use crate::common::ManagedBuffer;
+use crate::common::{self, SpdmRandomPaddingPolicy, SpdmTranscript};
 
 enum_builder! {
     @U8
@@ -37,21 +40,98 @@ pub struct SpdmSessionCryptoParam {
     pub key_schedule_algo: SpdmKeyScheduleAlgo,
 }
 
-#[derive(Debug, Copy, Clone, Default)]
+// The secrets aren't Copy or Clone, so each of them is wiped when it is
+// dropped.
+#[derive(Debug, Default)]
 pub struct SpdmSessionMasterSecret {
     pub dhe_secret: SpdmDheFinalKeyStruct,
     pub handshake_secret: SpdmDigestStruct,
     pub master_secret: SpdmDigestStruct,
 }
 
-#[derive(Debug, Copy, Clone, Default)]
+impl Drop for SpdmSessionMasterSecret {
+    fn drop(&mut self) {
+        common::zeroize(&mut self.dhe_secret.data);
+        common::zeroize(&mut self.handshake_secret.data);
+        common::zeroize(&mut self.master_secret.data);
+    }
+}
+
+#[derive(Debug, Default)]
 pub struct SpdmSessionSecretParam {
     pub encryption_key: SpdmAeadKeyStruct,
     pub salt: SpdmAeadIvStruct,
+    // the next one sent, or the highest one received + 1
     pub sequence_number: u64,
+    // receive only, bit n is set if sequence_number - 1 - n is received
+    pub replay_window: u64,
 }
 
-#[derive(Debug, Copy, Clone, Default)]
+impl Drop for SpdmSessionSecretParam {
+    fn drop(&mut self) {
+        common::zeroize(&mut self.encryption_key.data);
+        common::zeroize(&mut self.salt.data);
+    }
+}
+
+impl SpdmSessionSecretParam {
+    // The full sequence number of a received message carrying the low
+    // sequence_number_count bytes `received`. Fails if the message is
+    // replayed or, without a replay window, out of order.
+    fn check_received_sequence_number(
+        &self,
+        transport_param: &SpdmSessionTransportParam,
+        received: u64,
+    ) -> SpdmResult<u64> {
+        let expected = self.sequence_number;
+        let bits = 8 * transport_param.sequence_number_count as u32;
+        let sequence_number = if bits >= 64 {
+            received
+        } else {
+            // the candidate closest to the expected one
+            let span = 1u64 << bits;
+            let candidate = (expected & !(span - 1)) | received;
+            if candidate.saturating_add(span / 2) < expected {
+                candidate.checked_add(span).unwrap_or(candidate)
+            } else if candidate > expected.saturating_add(span / 2) && candidate >= span {
+                candidate - span
+            } else {
+                candidate
+            }
+        };
+
+        if sequence_number >= expected {
+            if sequence_number != expected && transport_param.replay_window_size == 0 {
+                return spdm_result_err!(EINVAL, "sequence number out of order");
+            }
+            return Ok(sequence_number);
+        }
+        let behind = expected - 1 - sequence_number;
+        if behind >= transport_param.replay_window_size as u64
+            || self.replay_window & (1 << behind) != 0
+        {
+            return spdm_result_err!(EALREADY, "replayed sequence number");
+        }
+        Ok(sequence_number)
+    }
+
+    // Record the sequence number of an authenticated message.
+    fn accept_received_sequence_number(&mut self, sequence_number: u64) {
+        if sequence_number >= self.sequence_number {
+            let shift = sequence_number - self.sequence_number + 1;
+            self.replay_window = if shift >= 64 {
+                1
+            } else {
+                (self.replay_window << shift) | 1
+            };
+            self.sequence_number = sequence_number + 1;
+        } else {
+            self.replay_window |= 1 << (self.sequence_number - 1 - sequence_number);
+        }
+    }
+}
+
+#[derive(Debug, Default)]
 pub struct SpdmSessionHandshakeSecret {
     pub request_handshake_secret: SpdmDigestStruct,
     pub response_handshake_secret: SpdmDigestStruct,
@@ -62,7 +142,18 @@ pub struct SpdmSessionHandshakeSecret {
     pub response_direction: SpdmSessionSecretParam,
 }
 
-#[derive(Debug, Copy, Clone, Default)]
+// the directions wipe themselves
+impl Drop for SpdmSessionHandshakeSecret {
+    fn drop(&mut self) {
+        common::zeroize(&mut self.request_handshake_secret.data);
+        common::zeroize(&mut self.response_handshake_secret.data);
+        common::zeroize(&mut self.export_master_secret.data);
+        common::zeroize(&mut self.request_finished_key.data);
+        common::zeroize(&mut self.response_finished_key.data);
+    }
+}
+
+#[derive(Debug, Default)]
 pub struct SpdmSessionAppliationSecret {
     pub request_data_secret: SpdmDigestStruct,
     pub response_data_secret: SpdmDigestStruct,
@@ -70,31 +161,101 @@ pub struct SpdmSessionAppliationSecret {
     pub response_direction: SpdmSessionSecretParam,
 }
 
-#[derive(Debug, Copy, Clone, Default)]
+impl Drop for SpdmSessionAppliationSecret {
+    fn drop(&mut self) {
+        common::zeroize(&mut self.request_data_secret.data);
+        common::zeroize(&mut self.response_data_secret.data);
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
 pub struct SpdmSessionTransportParam {
     pub sequence_number_count: u8,
     pub max_random_count: u16,
+    pub random_padding_policy: SpdmRandomPaddingPolicy,
+    // received sequence numbers accepted behind the highest one, at most
+    // MAX_REPLAY_WINDOW_SIZE. 0 only accepts messages in order.
+    pub replay_window_size: u8,
 }
 
-#[derive(Debug, Copy, Clone, Default)]
+impl Default for SpdmSessionTransportParam {
+    fn default() -> Self {
+        SpdmSessionTransportParam {
+            sequence_number_count: 0,
+            max_random_count: 0,
+            random_padding_policy: SpdmRandomPaddingPolicy::Random,
+            replay_window_size: 0,
+        }
+    }
+}
+
+pub const MAX_REPLAY_WINDOW_SIZE: u8 = 64;
+
+#[derive(Debug, Default)]
 pub struct SpdmSessionRuntimeInfo {
-    pub message_k: ManagedBuffer,
-    pub message_f: ManagedBuffer,
+    pub message_k: SpdmTranscript,
+    pub message_f: SpdmTranscript,
+    // random bytes padded to the last secured message sent
+    pub random_count: u16,
 }
 
-#[derive(Debug, Copy, Clone)]
+/// HEARTBEAT exchanges of a session, for showing which peers are alive.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SpdmSessionLivenessStats {
+    pub heartbeat_count: u32,
+    // time of the last HEARTBEAT_ACK received or HEARTBEAT answered, in us
+    pub last_heartbeat_time: u64,
+}
+
+/// PSK derived from a KEY_EXCHANGE session, for resuming a session with the
+/// same peer with PSK_EXCHANGE instead of another key exchange.
+#[derive(Debug, Clone)]
+pub struct SpdmResumptionPsk {
+    pub psk: SpdmDheFinalKeyStruct,
+    pub psk_hint: SpdmPskHintStruct,
+    pub base_hash_algo: SpdmBaseHashAlgo,
+    // policy of the session it is derived from, applied to the resumed ones
+    pub session_policy: SpdmKeyExchangeSessionPolicy,
+}
+
+impl Drop for SpdmResumptionPsk {
+    fn drop(&mut self) {
+        common::zeroize(&mut self.psk.data);
+    }
+}
+
+#[derive(Debug)]
 pub struct SpdmSession {
     session_id: u32,
+    spdm_version: SpdmVersion,
     use_psk: bool,
+    // cert slot of the responder the session is authenticated with
+    slot_id: u8,
+    mut_auth_requested: SpdmKeyExchangeMutAuthAttributes,
+    heartbeat_period: u8,
+    // time the session is set up and the last message of the session is
+    // received, in us
+    setup_time: u64,
+    last_receive_time: u64,
+    liveness_stats: SpdmSessionLivenessStats,
+    session_policy: SpdmKeyExchangeSessionPolicy,
+    // opaque data of the peer's KEY_EXCHANGE(_RSP) or PSK_EXCHANGE(_RSP)
+    peer_opaque_data: SpdmOpaqueStruct,
     session_state: SpdmSessionState,
     crypto_param: SpdmSessionCryptoParam,
     master_secret: SpdmSessionMasterSecret,
     handshake_secret: SpdmSessionHandshakeSecret,
     application_secret: SpdmSessionAppliationSecret,
     application_secret_backup: SpdmSessionAppliationSecret,
+    // a key update of the direction is not verified yet
+    request_update_pending: bool,
+    response_update_pending: bool,
+    // secrets retained by the external key schedule, if any
+    key_handle: Option<SpdmKeyHandle>,
     transport_param: SpdmSessionTransportParam,
     pub runtime_info: SpdmSessionRuntimeInfo,
     key_schedule: SpdmKeySchedule,
+    crypto_provider: SpdmCryptoProvider,
 }
 
 impl Default for SpdmSession {
@@ -107,80 +268,241 @@ impl SpdmSession {
     pub fn new() -> Self {
         SpdmSession {
             session_id: 0,
+            spdm_version: SpdmVersion::SpdmVersion11,
             use_psk: false,
+            slot_id: 0,
+            mut_auth_requested: SpdmKeyExchangeMutAuthAttributes::empty(),
+            heartbeat_period: 0,
+            setup_time: 0,
+            last_receive_time: 0,
+            liveness_stats: SpdmSessionLivenessStats::default(),
+            session_policy: SpdmKeyExchangeSessionPolicy::empty(),
+            peer_opaque_data: SpdmOpaqueStruct::default(),
             session_state: SpdmSessionState::default(),
             crypto_param: SpdmSessionCryptoParam::default(),
             master_secret: SpdmSessionMasterSecret::default(),
             handshake_secret: SpdmSessionHandshakeSecret::default(),
             application_secret: SpdmSessionAppliationSecret::default(),
             application_secret_backup: SpdmSessionAppliationSecret::default(),
+            request_update_pending: false,
+            response_update_pending: false,
+            key_handle: None,
             transport_param: SpdmSessionTransportParam::default(),
             runtime_info: SpdmSessionRuntimeInfo::default(),
             key_schedule: SpdmKeySchedule::new(),
+            crypto_provider: SpdmCryptoProvider::default(),
         }
     }
 
+    // also wipes all secrets of the previous session
     fn set_default(&mut self) {
         self.session_id = 0;
+        self.set_spdm_version(SpdmVersion::SpdmVersion11);
         self.use_psk = false;
+        self.slot_id = 0;
+        self.mut_auth_requested = SpdmKeyExchangeMutAuthAttributes::empty();
+        self.heartbeat_period = 0;
+        self.setup_time = 0;
+        self.last_receive_time = 0;
+        self.liveness_stats = SpdmSessionLivenessStats::default();
+        self.session_policy = SpdmKeyExchangeSessionPolicy::empty();
+        self.peer_opaque_data = SpdmOpaqueStruct::default();
         self.session_state = SpdmSessionState::default();
         self.crypto_param = SpdmSessionCryptoParam::default();
         self.master_secret = SpdmSessionMasterSecret::default();
         self.handshake_secret = SpdmSessionHandshakeSecret::default();
         self.application_secret = SpdmSessionAppliationSecret::default();
+        self.application_secret_backup = SpdmSessionAppliationSecret::default();
+        self.request_update_pending = false;
+        self.response_update_pending = false;
+        self.release_key_handle();
+        self.transport_param = SpdmSessionTransportParam::default();
+        self.runtime_info = SpdmSessionRuntimeInfo::default();
+    }
+
+    pub fn set_crypto_provider(&mut self, crypto_provider: SpdmCryptoProvider) {
+        self.crypto_provider = crypto_provider;
+        self.key_schedule.set_crypto_provider(crypto_provider);
+    }
+
+    fn get_external_key_schedule(&self) -> Option<SpdmExternalKeySchedule> {
+        self.crypto_provider.get_external_key_schedule()
+    }
+
+    fn release_key_handle(&mut self) {
+        if let Some(key_handle) = self.key_handle.take() {
+            if let Some(external_key_schedule) = self.get_external_key_schedule() {
+                (external_key_schedule.release_cb)(key_handle);
+            }
+        }
+    }
+
+    // The external key schedule and the handle of this session's secrets.
+    fn get_external_key(&self) -> Option<SpdmResult<(SpdmExternalKeySchedule, SpdmKeyHandle)>> {
+        let external_key_schedule = self.get_external_key_schedule()?;
+        Some(match self.key_handle {
+            Some(key_handle) => Ok((external_key_schedule, key_handle)),
+            None => spdm_result_err!(EFAULT, "no session key handle"),
+        })
+    }
+
+    pub fn set_spdm_version(&mut self, spdm_version: SpdmVersion) {
+        self.spdm_version = spdm_version;
+        self.key_schedule.set_spdm_version(spdm_version);
+    }
+
+    pub fn get_spdm_version(&self) -> SpdmVersion {
+        self.spdm_version
     }
 
-    pub fn get_session_id(&mut self) -> u32 {
+    pub fn get_session_id(&self) -> u32 {
         self.session_id
     }
 
     pub fn setup(&mut self, session_id: u32) -> SpdmResult {
-        if self.session_id == 0 {
-            self.set_default();
-            self.session_id = session_id;
-            Ok(())
-        } else {
-            panic!("setup session occupied!");
+        if session_id == 0 {
+            return spdm_result_err!(EINVAL, "invalid session id");
         }
+        if self.session_id != 0 {
+            return spdm_result_err!(EBUSY, "session slot occupied");
+        }
+        self.set_default();
+        self.session_id = session_id;
+        self.setup_time = time::get_time();
+        self.last_receive_time = self.setup_time;
+        Ok(())
     }
 
-    pub fn teardown(&mut self, session_id: u32) -> SpdmResult {
-        if self.session_id == session_id {
-            self.set_default();
-            Ok(())
-        } else {
-            panic!("teardown session owned by other!");
-        }
+    /// Release the session slot and wipe all session secrets.
+    pub fn teardown(&mut self) {
+        self.set_default();
     }
 
     pub fn set_use_psk(&mut self, use_psk: bool) {
         self.use_psk = use_psk;
     }
 
-    pub fn set_dhe_secret(&mut self, dhe_secret: &SpdmDheFinalKeyStruct) {
-        self.master_secret.dhe_secret = *dhe_secret;
+    pub fn get_use_psk(&self) -> bool {
+        self.use_psk
+    }
+
+    pub fn set_slot_id(&mut self, slot_id: u8) {
+        self.slot_id = slot_id;
+    }
+
+    pub fn get_slot_id(&self) -> u8 {
+        self.slot_id
+    }
+
+    pub fn set_mut_auth_requested(&mut self, mut_auth_requested: SpdmKeyExchangeMutAuthAttributes) {
+        self.mut_auth_requested = mut_auth_requested;
+    }
+
+    pub fn get_mut_auth_requested(&self) -> SpdmKeyExchangeMutAuthAttributes {
+        self.mut_auth_requested
+    }
+
+    pub fn set_heartbeat_period(&mut self, heartbeat_period: u8) {
+        self.heartbeat_period = heartbeat_period;
+    }
+
+    pub fn get_heartbeat_period(&self) -> u8 {
+        self.heartbeat_period
+    }
+
+    pub fn get_liveness_stats(&self) -> SpdmSessionLivenessStats {
+        self.liveness_stats
+    }
+
+    pub(crate) fn record_heartbeat(&mut self) -> SpdmSessionLivenessStats {
+        self.liveness_stats.heartbeat_count = self.liveness_stats.heartbeat_count.wrapping_add(1);
+        self.liveness_stats.last_heartbeat_time = time::get_time();
+        self.liveness_stats
+    }
+
+    fn get_time_since_last_receive(&self) -> u64 {
+        time::get_time().saturating_sub(self.last_receive_time)
+    }
+
+    /// A heartbeat period has passed since the last message is received.
+    pub fn is_heartbeat_due(&self) -> bool {
+        self.heartbeat_period != 0
+            && self.get_time_since_last_receive() >= self.heartbeat_period as u64 * 1_000_000
+    }
+
+    /// Time since the session is set up, in us.
+    pub fn get_time_since_setup(&self) -> u64 {
+        time::get_time().saturating_sub(self.setup_time)
+    }
+
+    /// No message is received within twice the heartbeat period, the
+    /// session is terminated.
+    pub fn is_heartbeat_expired(&self) -> bool {
+        self.heartbeat_period != 0
+            && self.get_time_since_last_receive() > self.heartbeat_period as u64 * 2_000_000
+    }
+
+    pub fn set_session_policy(&mut self, session_policy: SpdmKeyExchangeSessionPolicy) {
+        self.session_policy = session_policy;
+    }
+
+    pub fn get_session_policy(&self) -> SpdmKeyExchangeSessionPolicy {
+        self.session_policy
+    }
+
+    pub fn set_peer_opaque_data(&mut self, peer_opaque_data: &SpdmOpaqueStruct) {
+        self.peer_opaque_data = *peer_opaque_data;
+    }
+
+    /// Opaque data the peer sent while the session was established, e.g.
+    /// vendor defined elements with link parameters for IDE.
+    pub fn get_peer_opaque_data(&self) -> &[u8] {
+        self.peer_opaque_data.as_slice()
+    }
+
+    /// The session keeps its own copy of `dhe_secret`, the one passed in is
+    /// wiped.
+    pub fn set_dhe_secret(&mut self, dhe_secret: &mut SpdmDheFinalKeyStruct) -> SpdmResult {
+        let result = self.import_dhe_secret(dhe_secret);
+        common::zeroize(&mut dhe_secret.data);
+        result
+    }
+
+    fn import_dhe_secret(&mut self, dhe_secret: &SpdmDheFinalKeyStruct) -> SpdmResult {
+        if let Some(external_key_schedule) = self.get_external_key_schedule() {
+            self.release_key_handle();
+            self.key_handle = (external_key_schedule.import_shared_secret_cb)(
+                self.spdm_version,
+                self.crypto_param.base_hash_algo,
+                self.crypto_param.aead_algo,
+                dhe_secret.as_ref(),
+            );
+            if self.key_handle.is_none() {
+                error!("!!! import_shared_secret fail !!!\n");
+                return spdm_result_err!(EFAULT, "import_shared_secret fail");
+            }
+            return Ok(());
+        }
+
+        self.master_secret.dhe_secret = dhe_secret.clone();
         let key = &self.master_secret.dhe_secret.as_ref();
 
         // generate master_secret.handshake_secret and master_secret.master_secret
         let handshake_secret = self
             .key_schedule
             .derive_handshake_secret(self.crypto_param.base_hash_algo, key)
-            .unwrap();
+            .ok_or(spdm_err!(EFAULT))?;
 
         let key = handshake_secret.as_ref();
         let master_secret = self
             .key_schedule
             .derive_master_secret(self.crypto_param.base_hash_algo, key)
-            .unwrap();
+            .ok_or(spdm_err!(EFAULT))?;
 
         self.master_secret.handshake_secret = handshake_secret;
         self.master_secret.master_secret = master_secret;
 
-        debug!(
-            "!!! handshake_secret !!!: {:02x?}\n",
-            handshake_secret.as_ref()
-        );
-        debug!("!!! master_secret !!!: {:02x?}\n", master_secret.as_ref());
+        Ok(())
     }
 
     pub fn set_crypto_param(
@@ -196,18 +518,44 @@ impl SpdmSession {
         self.crypto_param.key_schedule_algo = key_schedule_algo;
     }
 
+    pub fn get_crypto_param(&self) -> SpdmSessionCryptoParam {
+        self.crypto_param
+    }
+
     pub fn set_transport_param(&mut self, sequence_number_count: u8, max_random_count: u16) {
         self.transport_param.sequence_number_count = sequence_number_count;
         self.transport_param.max_random_count = max_random_count;
     }
 
+    /// Pad the secured messages sent with random bytes as the policy says,
+    /// up to the max_random_count of set_transport_param().
+    pub fn set_random_padding_policy(&mut self, random_padding_policy: SpdmRandomPaddingPolicy) {
+        self.transport_param.random_padding_policy = random_padding_policy;
+    }
+
+    /// Accept secured messages up to `replay_window_size` sequence numbers
+    /// behind the highest one received, for transports that may reorder
+    /// messages. Replays are still rejected. It needs the sequence number
+    /// to be sent, see SpdmTransportEncap::get_sequence_number_count().
+    pub fn set_replay_window_size(&mut self, replay_window_size: u8) {
+        self.transport_param.replay_window_size = replay_window_size.min(MAX_REPLAY_WINDOW_SIZE);
+    }
+
     pub fn set_session_state(&mut self, session_state: SpdmSessionState) {
         self.session_state = session_state;
     }
 
+    pub fn get_session_state(&self) -> SpdmSessionState {
+        self.session_state
+    }
+
     pub fn generate_handshake_secret(&mut self, th1: &SpdmDigestStruct) -> SpdmResult {
         // generate key
         info!("!!! generate_handshake_secret !!!:\n");
+        if let Some(external_key) = self.get_external_key() {
+            let (external_key_schedule, key_handle) = external_key?;
+            return (external_key_schedule.derive_handshake_keys_cb)(key_handle, th1.as_ref());
+        }
         let hash_algo = self.crypto_param.base_hash_algo;
         let aead_algo = self.crypto_param.aead_algo;
 
@@ -218,11 +566,7 @@ impl SpdmSession {
                 self.master_secret.handshake_secret.as_ref(),
                 th1.as_ref(),
             )
-            .unwrap();
-        debug!(
-            "!!! request_handshake_secret !!!: {:02x?}\n",
-            self.handshake_secret.request_handshake_secret.as_ref()
-        );
+            .ok_or(spdm_err!(EFAULT))?;
         self.handshake_secret.response_handshake_secret = self
             .key_schedule
             .derive_response_handshake_secret(
@@ -230,33 +574,21 @@ impl SpdmSession {
                 self.master_secret.handshake_secret.as_ref(),
                 th1.as_ref(),
             )
-            .unwrap();
-        debug!(
-            "!!! response_handshake_secret !!!: {:02x?}\n",
-            self.handshake_secret.response_handshake_secret.as_ref()
-        );
+            .ok_or(spdm_err!(EFAULT))?;
         self.handshake_secret.request_finished_key = self
             .key_schedule
             .derive_finished_key(
                 hash_algo,
                 self.handshake_secret.request_handshake_secret.as_ref(),
             )
-            .unwrap();
-        debug!(
-            "!!! request_finished_key !!!: {:02x?}\n",
-            self.handshake_secret.request_finished_key.as_ref()
-        );
+            .ok_or(spdm_err!(EFAULT))?;
         self.handshake_secret.response_finished_key = self
             .key_schedule
             .derive_finished_key(
                 hash_algo,
                 self.handshake_secret.response_handshake_secret.as_ref(),
             )
-            .unwrap();
-        debug!(
-            "!!! response_finished_key !!!: {:02x?}\n",
-            self.handshake_secret.response_finished_key.as_ref()
-        );
+            .ok_or(spdm_err!(EFAULT))?;
 
         let res = self
             .key_schedule
@@ -265,21 +597,10 @@ impl SpdmSession {
                 aead_algo,
                 self.handshake_secret.request_handshake_secret.as_ref(),
             )
-            .unwrap();
+            .ok_or(spdm_err!(EFAULT))?;
 
         self.handshake_secret.request_direction.encryption_key = res.0;
         self.handshake_secret.request_direction.salt = res.1;
-        debug!(
-            "!!! request_direction.encryption_key !!!: {:02x?}\n",
-            self.handshake_secret
-                .request_direction
-                .encryption_key
-                .as_ref()
-        );
-        debug!(
-            "!!! request_direction.salt !!!: {:02x?}\n",
-            self.handshake_secret.request_direction.salt.as_ref()
-        );
 
         let res = self
             .key_schedule
@@ -288,28 +609,14 @@ impl SpdmSession {
                 aead_algo,
                 self.handshake_secret.response_handshake_secret.as_ref(),
             )
-            .unwrap();
+            .ok_or(spdm_err!(EFAULT))?;
         self.handshake_secret.response_direction.encryption_key = res.0;
         self.handshake_secret.response_direction.salt = res.1;
-        debug!(
-            "!!! response_direction.encryption_key !!!: {:02x?}\n",
-            self.handshake_secret
-                .response_direction
-                .encryption_key
-                .as_ref()
-        );
-        debug!(
-            "!!! response_direction.salt !!!: {:02x?}\n",
-            self.handshake_secret.response_direction.salt.as_ref()
-        );
 
         self.handshake_secret.export_master_secret = self
             .key_schedule
-            .derive_export_master_secret(
-                hash_algo,
-                self.handshake_secret.export_master_secret.as_ref(),
-            )
-            .unwrap();
+            .derive_export_master_secret(hash_algo, self.master_secret.master_secret.as_ref())
+            .ok_or(spdm_err!(EFAULT))?;
 
         Ok(())
     }
@@ -317,6 +624,10 @@ impl SpdmSession {
     pub fn generate_data_secret(&mut self, th2: &SpdmDigestStruct) -> SpdmResult {
         // generate key
         info!("!!! generate_data_secret !!!:\n");
+        if let Some(external_key) = self.get_external_key() {
+            let (external_key_schedule, key_handle) = external_key?;
+            return (external_key_schedule.derive_data_keys_cb)(key_handle, th2.as_ref());
+        }
         let hash_algo = self.crypto_param.base_hash_algo;
         let aead_algo = self.crypto_param.aead_algo;
 
@@ -327,7 +638,7 @@ impl SpdmSession {
                 self.master_secret.master_secret.as_ref(),
                 th2.as_ref(),
             )
-            .unwrap();
+            .ok_or(spdm_err!(EFAULT))?;
         self.application_secret.response_data_secret = self
             .key_schedule
             .derive_response_data_secret(
@@ -335,15 +646,7 @@ impl SpdmSession {
                 self.master_secret.master_secret.as_ref(),
                 th2.as_ref(),
             )
-            .unwrap();
-        debug!(
-            "!!! request_data_secret !!!: {:02x?}\n",
-            self.application_secret.request_data_secret.as_ref()
-        );
-        debug!(
-            "!!! response_data_secret !!!: {:02x?}\n",
-            self.application_secret.response_data_secret.as_ref()
-        );
+            .ok_or(spdm_err!(EFAULT))?;
 
         let res = self
             .key_schedule
@@ -352,20 +655,9 @@ impl SpdmSession {
                 aead_algo,
                 self.application_secret.request_data_secret.as_ref(),
             )
-            .unwrap();
+            .ok_or(spdm_err!(EFAULT))?;
         self.application_secret.request_direction.encryption_key = res.0;
         self.application_secret.request_direction.salt = res.1;
-        debug!(
-            "!!! request_direction.encryption_key !!!: {:02x?}\n",
-            self.application_secret
-                .request_direction
-                .encryption_key
-                .as_ref()
-        );
-        debug!(
-            "!!! request_direction.salt !!!: {:02x?}\n",
-            self.application_secret.request_direction.salt.as_ref()
-        );
 
         let res = self
             .key_schedule
@@ -374,20 +666,9 @@ impl SpdmSession {
                 aead_algo,
                 self.application_secret.response_data_secret.as_ref(),
             )
-            .unwrap();
+            .ok_or(spdm_err!(EFAULT))?;
         self.application_secret.response_direction.encryption_key = res.0;
         self.application_secret.response_direction.salt = res.1;
-        debug!(
-            "!!! response_direction.encryption_key !!!: {:02x?}\n",
-            self.application_secret
-                .response_direction
-                .encryption_key
-                .as_ref()
-        );
-        debug!(
-            "!!! response_direction.salt !!!: {:02x?}\n",
-            self.application_secret.response_direction.salt.as_ref()
-        );
 
         Ok(())
     }
@@ -401,150 +682,229 @@ impl SpdmSession {
             "!!! create_data_secret_update {:?} {:?} !!!:\n",
             update_requester, update_responder
         );
-        let hash_algo = self.crypto_param.base_hash_algo;
-        let aead_algo = self.crypto_param.aead_algo;
+        if let Some(external_key) = self.get_external_key() {
+            let (external_key_schedule, key_handle) = external_key?;
+            // both directions are updated before any of them is pending, the
+            // request one is restored if the response one fails
+            if update_requester {
+                (external_key_schedule.update_data_key_cb)(
+                    key_handle,
+                    SpdmSessionKeyId::RequestData,
+                )?;
+            }
+            if update_responder {
+                if let Err(e) = (external_key_schedule.update_data_key_cb)(
+                    key_handle,
+                    SpdmSessionKeyId::ResponseData,
+                ) {
+                    if update_requester {
+                        let _ = (external_key_schedule.activate_data_key_update_cb)(
+                            key_handle,
+                            SpdmSessionKeyId::RequestData,
+                            false,
+                        );
+                    }
+                    return Err(e);
+                }
+            }
 
-        if update_requester {
+            if update_requester {
+                self.application_secret_backup.request_direction =
+                    core::mem::take(&mut self.application_secret.request_direction);
+                self.request_update_pending = true;
+            }
+            if update_responder {
+                self.application_secret_backup.response_direction =
+                    core::mem::take(&mut self.application_secret.response_direction);
+                self.response_update_pending = true;
+            }
+            return Ok(());
+        }
+
+        // both directions are derived before any of them is updated
+        let request_update = if update_requester {
+            Some(self.derive_updated_data_secret(&self.application_secret.request_data_secret)?)
+        } else {
+            None
+        };
+        let response_update = if update_responder {
+            Some(self.derive_updated_data_secret(&self.application_secret.response_data_secret)?)
+        } else {
+            None
+        };
+
+        if let Some((data_secret, encryption_key, salt)) = request_update {
             self.application_secret_backup.request_data_secret =
                 self.application_secret.request_data_secret;
-            self.application_secret_backup.request_direction =
-                self.application_secret.request_direction;
-
-            self.application_secret.request_data_secret = self
-                .key_schedule
-                .derive_update_secret(
-                    hash_algo,
-                    self.application_secret.request_data_secret.as_ref(),
-                )
-                .unwrap();
-            debug!(
-                "!!! request_data_secret !!!: {:02x?}\n",
-                self.application_secret.request_data_secret.as_ref()
+            self.application_secret_backup.request_direction = core::mem::replace(
+                &mut self.application_secret.request_direction,
+                SpdmSessionSecretParam {
+                    encryption_key,
+                    salt,
+                    sequence_number: 0,
+                    replay_window: 0,
+                },
             );
 
-            let res = self
-                .key_schedule
-                .derive_aead_key_iv(
-                    hash_algo,
-                    aead_algo,
-                    self.application_secret.request_data_secret.as_ref(),
-                )
-                .unwrap();
-            self.application_secret.request_direction.encryption_key = res.0;
-            self.application_secret.request_direction.salt = res.1;
-            debug!(
-                "!!! request_direction.encryption_key !!!: {:02x?}\n",
-                self.application_secret
-                    .request_direction
-                    .encryption_key
-                    .as_ref()
-            );
-            debug!(
-                "!!! request_direction.salt !!!: {:02x?}\n",
-                self.application_secret.request_direction.salt.as_ref()
-            );
-            self.application_secret.request_direction.sequence_number = 0;
+            self.application_secret.request_data_secret = data_secret;
+            self.request_update_pending = true;
         }
 
-        if update_responder {
+        if let Some((data_secret, encryption_key, salt)) = response_update {
             self.application_secret_backup.response_data_secret =
                 self.application_secret.response_data_secret;
-            self.application_secret_backup.response_direction =
-                self.application_secret.response_direction;
-
-            self.application_secret.response_data_secret = self
-                .key_schedule
-                .derive_update_secret(
-                    hash_algo,
-                    self.application_secret.response_data_secret.as_ref(),
-                )
-                .unwrap();
-            debug!(
-                "!!! response_data_secret !!!: {:02x?}\n",
-                self.application_secret.response_data_secret.as_ref()
+            self.application_secret_backup.response_direction = core::mem::replace(
+                &mut self.application_secret.response_direction,
+                SpdmSessionSecretParam {
+                    encryption_key,
+                    salt,
+                    sequence_number: 0,
+                    replay_window: 0,
+                },
             );
 
-            let res = self
-                .key_schedule
-                .derive_aead_key_iv(
-                    hash_algo,
-                    aead_algo,
-                    self.application_secret.response_data_secret.as_ref(),
-                )
-                .unwrap();
-            self.application_secret.response_direction.encryption_key = res.0;
-            self.application_secret.response_direction.salt = res.1;
-            debug!(
-                "!!! response_direction.encryption_key !!!: {:02x?}\n",
-                self.application_secret
-                    .response_direction
-                    .encryption_key
-                    .as_ref()
-            );
-            debug!(
-                "!!! response_direction.salt !!!: {:02x?}\n",
-                self.application_secret.response_direction.salt.as_ref()
-            );
-            self.application_secret.response_direction.sequence_number = 0;
+            self.application_secret.response_data_secret = data_secret;
+            self.response_update_pending = true;
         }
         Ok(())
     }
+
+    fn derive_updated_data_secret(
+        &self,
+        data_secret: &SpdmDigestStruct,
+    ) -> SpdmResult<(SpdmDigestStruct, SpdmAeadKeyStruct, SpdmAeadIvStruct)> {
+        let hash_algo = self.crypto_param.base_hash_algo;
+        let data_secret = self
+            .key_schedule
+            .derive_update_secret(hash_algo, data_secret.as_ref())
+            .ok_or(spdm_err!(EFAULT))?;
+        let (encryption_key, salt) = self
+            .key_schedule
+            .derive_aead_key_iv(hash_algo, self.crypto_param.aead_algo, data_secret.as_ref())
+            .ok_or(spdm_err!(EFAULT))?;
+        Ok((data_secret, encryption_key, salt))
+    }
     pub fn activate_data_secret_update(
         &mut self,
         update_requester: bool,
         update_responder: bool,
         use_new_key: bool,
     ) -> SpdmResult {
+        let update_requester = update_requester && self.request_update_pending;
+        let update_responder = update_responder && self.response_update_pending;
+        if let Some(external_key) = self.get_external_key() {
+            let (external_key_schedule, key_handle) = external_key?;
+            if update_requester {
+                (external_key_schedule.activate_data_key_update_cb)(
+                    key_handle,
+                    SpdmSessionKeyId::RequestData,
+                    use_new_key,
+                )?;
+            }
+            if update_responder {
+                (external_key_schedule.activate_data_key_update_cb)(
+                    key_handle,
+                    SpdmSessionKeyId::ResponseData,
+                    use_new_key,
+                )?;
+            }
+        }
+
         if !use_new_key {
             if update_requester {
                 self.application_secret.request_data_secret =
                     self.application_secret_backup.request_data_secret;
-                self.application_secret.request_direction =
-                    self.application_secret_backup.request_direction;
+                core::mem::swap(
+                    &mut self.application_secret.request_direction,
+                    &mut self.application_secret_backup.request_direction,
+                );
             }
             if update_responder {
                 self.application_secret.response_data_secret =
                     self.application_secret_backup.response_data_secret;
-                self.application_secret.response_direction =
-                    self.application_secret_backup.response_direction;
-            }
-        } else {
-            if update_requester {
-                self.application_secret_backup.request_data_secret = SpdmDigestStruct::default();
-                self.application_secret_backup.request_direction =
-                    SpdmSessionSecretParam::default();
-            }
-            if update_responder {
-                self.application_secret_backup.response_data_secret = SpdmDigestStruct::default();
-                self.application_secret_backup.response_direction =
-                    SpdmSessionSecretParam::default();
+                core::mem::swap(
+                    &mut self.application_secret.response_direction,
+                    &mut self.application_secret_backup.response_direction,
+                );
             }
         }
+        // the update is completed either way
+        if update_requester {
+            self.application_secret_backup.request_data_secret = SpdmDigestStruct::default();
+            self.application_secret_backup.request_direction = SpdmSessionSecretParam::default();
+            self.request_update_pending = false;
+        }
+        if update_responder {
+            self.application_secret_backup.response_data_secret = SpdmDigestStruct::default();
+            self.application_secret_backup.response_direction = SpdmSessionSecretParam::default();
+            self.response_update_pending = false;
+        }
         Ok(())
     }
 
-    pub fn generate_hmac_with_response_finished_key(
-        &mut self,
+    fn generate_hmac_with_finished_key(
+        &self,
+        key_id: SpdmSessionKeyId,
         message: &[u8],
     ) -> SpdmResult<SpdmDigestStruct> {
-        crypto::hmac::hmac(
+        if let Some(external_key) = self.get_external_key() {
+            let (external_key_schedule, key_handle) = external_key?;
+            return (external_key_schedule.hmac_cb)(key_handle, key_id, message)
+                .ok_or(spdm_err!(EFAULT));
+        }
+        let finished_key = if key_id == SpdmSessionKeyId::RequestFinished {
+            &self.handshake_secret.request_finished_key
+        } else {
+            &self.handshake_secret.response_finished_key
+        };
+        self.crypto_provider
+            .hmac(
+                self.crypto_param.base_hash_algo,
+                finished_key.as_ref(),
+                message,
+            )
+            .ok_or(spdm_err!(EFAULT))
+    }
+
+    fn verify_hmac_with_finished_key(
+        &self,
+        key_id: SpdmSessionKeyId,
+        message: &[u8],
+        hmac: &SpdmDigestStruct,
+    ) -> SpdmResult {
+        if self.get_external_key_schedule().is_some() {
+            let expected = self.generate_hmac_with_finished_key(key_id, message)?;
+            return if common::constant_time_eq(expected.as_ref(), hmac.as_ref()) {
+                Ok(())
+            } else {
+                spdm_result_err!(EFAULT)
+            };
+        }
+        let finished_key = if key_id == SpdmSessionKeyId::RequestFinished {
+            &self.handshake_secret.request_finished_key
+        } else {
+            &self.handshake_secret.response_finished_key
+        };
+        self.crypto_provider.hmac_verify(
             self.crypto_param.base_hash_algo,
-            self.handshake_secret.response_finished_key.as_ref(),
+            finished_key.as_ref(),
             message,
+            hmac,
         )
-        .ok_or(spdm_err!(EFAULT))
+    }
+
+    pub fn generate_hmac_with_response_finished_key(
+        &mut self,
+        message: &[u8],
+    ) -> SpdmResult<SpdmDigestStruct> {
+        self.generate_hmac_with_finished_key(SpdmSessionKeyId::ResponseFinished, message)
     }
 
     pub fn generate_hmac_with_request_finished_key(
         &mut self,
         message: &[u8],
     ) -> SpdmResult<SpdmDigestStruct> {
-        crypto::hmac::hmac(
-            self.crypto_param.base_hash_algo,
-            self.handshake_secret.request_finished_key.as_ref(),
-            message,
-        )
-        .ok_or(spdm_err!(EFAULT))
+        self.generate_hmac_with_finished_key(SpdmSessionKeyId::RequestFinished, message)
     }
 
     pub fn verify_hmac_with_response_finished_key(
@@ -552,12 +912,7 @@ impl SpdmSession {
         message: &[u8],
         hmac: &SpdmDigestStruct,
     ) -> SpdmResult {
-        crypto::hmac::hmac_verify(
-            self.crypto_param.base_hash_algo,
-            self.handshake_secret.response_finished_key.as_ref(),
-            message,
-            hmac,
-        )
+        self.verify_hmac_with_finished_key(SpdmSessionKeyId::ResponseFinished, message, hmac)
     }
 
     pub fn verify_hmac_with_request_finished_key(
@@ -565,25 +920,99 @@ impl SpdmSession {
         message: &[u8],
         hmac: &SpdmDigestStruct,
     ) -> SpdmResult {
-        crypto::hmac::hmac_verify(
-            self.crypto_param.base_hash_algo,
-            self.handshake_secret.request_finished_key.as_ref(),
-            message,
-            hmac,
-        )
+        self.verify_hmac_with_finished_key(SpdmSessionKeyId::RequestFinished, message, hmac)
+    }
+
+    /// The resumption PSK of an established KEY_EXCHANGE session. None for
+    /// PSK sessions and with an external key schedule, which keeps the
+    /// master secret.
+    pub fn derive_resumption_psk(&self) -> Option<SpdmResumptionPsk> {
+        if self.use_psk
+            || self.key_handle.is_some()
+            || self.session_state != SpdmSessionState::SpdmSessionEstablished
+        {
+            return None;
+        }
+        let hash_algo = self.crypto_param.base_hash_algo;
+        let master_secret = self.master_secret.master_secret.as_ref();
+        let mut psk = self
+            .key_schedule
+            .derive_resumption_psk(hash_algo, master_secret)?;
+        let psk_hint_size = hash_algo
+            .get_size()
+            .min(config::MAX_SPDM_PSK_HINT_SIZE as u16);
+        let psk_hint = self.key_schedule.derive_resumption_psk_hint(
+            hash_algo,
+            master_secret,
+            psk_hint_size,
+        )?;
+        let resumption_psk = SpdmResumptionPsk {
+            psk: SpdmDheFinalKeyStruct::try_from(psk.as_ref()).ok()?,
+            psk_hint: SpdmPskHintStruct::try_from(psk_hint.as_ref()).ok()?,
+            base_hash_algo: hash_algo,
+            session_policy: self.session_policy,
+        };
+        common::zeroize(&mut psk.data);
+        Some(resumption_psk)
     }
 
+    /// Export master secret of an established session, `None` before FINISH
+    /// and with an external key schedule, which keeps it.
+    pub fn get_export_master_secret(&self) -> Option<&SpdmDigestStruct> {
+        if self.key_handle.is_some()
+            || self.session_state != SpdmSessionState::SpdmSessionEstablished
+        {
+            return None;
+        }
+        Some(&self.handshake_secret.export_master_secret)
+    }
+
+    /// Secret of `size` bytes, at most the hash size, bound to this session
+    /// for an application protocol identified by `label` and `context`.
+    pub fn export_secret(
+        &self,
+        label: &[u8],
+        context: &[u8],
+        size: u16,
+    ) -> SpdmResult<SpdmDigestStruct> {
+        if self.key_handle.is_some() {
+            return spdm_result_err!(
+                ENOSYS,
+                "export master secret kept by the external key schedule"
+            );
+        }
+        let export_master_secret = self
+            .get_export_master_secret()
+            .ok_or(spdm_err!(EPERM, "session not established"))?;
+        let hash_algo = self.crypto_param.base_hash_algo;
+        if size == 0 || size > hash_algo.get_size() {
+            return spdm_result_err!(EINVAL, "invalid exported secret size");
+        }
+        self.key_schedule
+            .hkdf_expand_label(
+                hash_algo,
+                export_master_secret.as_ref(),
+                label,
+                Some(context),
+                size,
+            )
+            .ok_or(spdm_err!(EFAULT))
+    }
+
+    /// With an external key schedule only the sequence numbers are exported.
     pub fn export_keys(&mut self) -> (SpdmSessionSecretParam, SpdmSessionSecretParam) {
         (
             SpdmSessionSecretParam {
                 encryption_key: self.application_secret.request_direction.encryption_key,
                 salt: self.application_secret.request_direction.salt,
                 sequence_number: self.application_secret.request_direction.sequence_number,
+                replay_window: self.application_secret.request_direction.replay_window,
             },
             SpdmSessionSecretParam {
                 encryption_key: self.application_secret.response_direction.encryption_key,
                 salt: self.application_secret.response_direction.salt,
                 sequence_number: self.application_secret.response_direction.sequence_number,
+                replay_window: self.application_secret.response_direction.replay_window,
             },
         )
     }
@@ -594,15 +1023,16 @@ impl SpdmSession {
         secured_buffer: &mut [u8],
         is_requester: bool,
     ) -> SpdmResult<usize> {
-        match self.session_state {
-            SpdmSessionState::SpdmSessionNotStarted => spdm_result_err!(EINVAL),
+        let r = match self.session_state {
+            SpdmSessionState::SpdmSessionNotStarted => return spdm_result_err!(EINVAL),
             SpdmSessionState::SpdmSessionHandshaking => {
                 if is_requester {
                     let r = self.encode_msg(
                         app_buffer,
                         secured_buffer,
                         &self.handshake_secret.request_direction,
-                    );
+                        SpdmSessionKeyId::RequestHandshake,
+                    )?;
                     self.handshake_secret.request_direction.sequence_number += 1;
                     r
                 } else {
@@ -610,7 +1040,8 @@ impl SpdmSession {
                         app_buffer,
                         secured_buffer,
                         &self.handshake_secret.response_direction,
-                    );
+                        SpdmSessionKeyId::ResponseHandshake,
+                    )?;
                     self.handshake_secret.response_direction.sequence_number += 1;
                     r
                 }
@@ -621,7 +1052,8 @@ impl SpdmSession {
                         app_buffer,
                         secured_buffer,
                         &self.application_secret.request_direction,
-                    );
+                        SpdmSessionKeyId::RequestData,
+                    )?;
                     self.application_secret.request_direction.sequence_number += 1;
                     r
                 } else {
@@ -629,13 +1061,17 @@ impl SpdmSession {
                         app_buffer,
                         secured_buffer,
                         &self.application_secret.response_direction,
-                    );
+                        SpdmSessionKeyId::ResponseData,
+                    )?;
                     self.application_secret.response_direction.sequence_number += 1;
                     r
                 }
             }
-            _ => panic!("unknown session state"),
-        }
+            _ => return spdm_result_err!(EINVAL, "unknown session state"),
+        };
+        let (used, random_count) = r;
+        self.runtime_info.random_count = random_count as u16;
+        Ok(used)
     }
 
     pub fn decode_spdm_secured_message(
@@ -644,63 +1080,155 @@ impl SpdmSession {
         app_buffer: &mut [u8],
         is_requester: bool,
     ) -> SpdmResult<usize> {
-        match self.session_state {
+        if self.is_heartbeat_expired() {
+            error!(
+                "!!! session {:x} : heartbeat expired !!!\n",
+                self.session_id
+            );
+            self.teardown();
+            return spdm_result_err!(ETIMEDOUT, "heartbeat expired");
+        }
+
+        let result = match self.session_state {
             SpdmSessionState::SpdmSessionNotStarted => spdm_result_err!(EINVAL),
             SpdmSessionState::SpdmSessionHandshaking => {
                 if is_requester {
-                    let r = self.decode_msg(
+                    let (used, sequence_number) = self.decode_msg(
                         secured_buffer,
                         app_buffer,
                         &self.handshake_secret.request_direction,
-                    );
-                    self.handshake_secret.request_direction.sequence_number += 1;
-                    r
+                        SpdmSessionKeyId::RequestHandshake,
+                    )?;
+                    self.handshake_secret
+                        .request_direction
+                        .accept_received_sequence_number(sequence_number);
+                    Ok(used)
                 } else {
-                    let r = self.decode_msg(
+                    let (used, sequence_number) = self.decode_msg(
                         secured_buffer,
                         app_buffer,
                         &self.handshake_secret.response_direction,
-                    );
-                    self.handshake_secret.response_direction.sequence_number += 1;
-                    r
+                        SpdmSessionKeyId::ResponseHandshake,
+                    )?;
+                    self.handshake_secret
+                        .response_direction
+                        .accept_received_sequence_number(sequence_number);
+                    Ok(used)
                 }
             }
             SpdmSessionState::SpdmSessionEstablished => {
-                if is_requester {
-                    let r = self.decode_msg(
-                        secured_buffer,
-                        app_buffer,
-                        &self.application_secret.request_direction,
-                    );
-                    self.application_secret.request_direction.sequence_number += 1;
-                    r
-                } else {
-                    let r = self.decode_msg(
-                        secured_buffer,
-                        app_buffer,
-                        &self.application_secret.response_direction,
-                    );
-                    self.application_secret.response_direction.sequence_number += 1;
-                    r
-                }
+                self.decode_application_msg(secured_buffer, app_buffer, is_requester)
+            }
+            _ => spdm_result_err!(EINVAL, "unknown session state"),
+        };
+        if result.is_ok() {
+            self.last_receive_time = time::get_time();
+        }
+        result
+    }
+
+    // The previous data keys are kept until the key update is verified. If
+    // the peer still uses them, e.g. because KEY_UPDATE_ACK got lost, the key
+    // update of that direction is rolled back.
+    fn decode_application_msg(
+        &mut self,
+        secured_buffer: &[u8],
+        app_buffer: &mut [u8],
+        is_requester: bool,
+    ) -> SpdmResult<usize> {
+        let (secret_param, key_id, backup_secret_param, backup_key_id, update_pending) =
+            if is_requester {
+                (
+                    &self.application_secret.request_direction,
+                    SpdmSessionKeyId::RequestData,
+                    &self.application_secret_backup.request_direction,
+                    SpdmSessionKeyId::PreviousRequestData,
+                    self.request_update_pending,
+                )
+            } else {
+                (
+                    &self.application_secret.response_direction,
+                    SpdmSessionKeyId::ResponseData,
+                    &self.application_secret_backup.response_direction,
+                    SpdmSessionKeyId::PreviousResponseData,
+                    self.response_update_pending,
+                )
+            };
+
+        let mut r = self.decode_msg(secured_buffer, app_buffer, secret_param, key_id);
+        if r.is_err() && update_pending {
+            if let Ok((used, sequence_number)) = self.decode_msg(
+                secured_buffer,
+                app_buffer,
+                backup_secret_param,
+                backup_key_id,
+            ) {
+                info!("!!! peer uses the previous key, key update rolled back !!!\n");
+                // only the direction the message was received in
+                self.activate_data_secret_update(is_requester, !is_requester, false)?;
+                r = Ok((used, sequence_number));
             }
-            _ => panic!("unknown session state"),
         }
+
+        let (used, sequence_number) = r?;
+        if is_requester {
+            self.application_secret
+                .request_direction
+                .accept_received_sequence_number(sequence_number);
+        } else {
+            self.application_secret
+                .response_direction
+                .accept_received_sequence_number(sequence_number);
+        }
+        Ok(used)
     }
 
+    // Returns the size of the secured message and the random bytes it is
+    // padded with.
     fn encode_msg(
         &self,
         app_buffer: &[u8],
         secured_buffer: &mut [u8],
         secret_param: &SpdmSessionSecretParam,
-    ) -> SpdmResult<usize> {
+        key_id: SpdmSessionKeyId,
+    ) -> SpdmResult<(usize, usize)> {
         let session_id = self.session_id;
         let aead_algo = self.crypto_param.aead_algo;
         let transport_param = &self.transport_param;
 
-        let cipher_text_size = app_buffer.len() + 2;
         let tag_size = aead_algo.get_tag_size() as usize;
 
+        // up to max_random_count random bytes after the application data,
+        // as many as fit in the buffers
+        let mut random_count = 0;
+        if transport_param.max_random_count != 0 {
+            match transport_param.random_padding_policy {
+                SpdmRandomPaddingPolicy::Random => {
+                    let mut random = [0u8; 2];
+                    self.crypto_provider.get_random(&mut random)?;
+                    random_count = (u16::from_le_bytes(random) % transport_param.max_random_count)
+                        as usize
+                        + 1;
+                }
+                SpdmRandomPaddingPolicy::Max => {
+                    random_count = transport_param.max_random_count as usize;
+                }
+                SpdmRandomPaddingPolicy::Zero => {}
+            }
+        }
+        let aad_size = 6 + transport_param.sequence_number_count as usize;
+        let max_cipher_text_size = config::MAX_SPDM_MESSAGE_BUFFER_SIZE
+            .min(secured_buffer.len().saturating_sub(aad_size + tag_size));
+        random_count = random_count.min(max_cipher_text_size.saturating_sub(app_buffer.len() + 2));
+        if app_buffer.len() + 2 > max_cipher_text_size {
+            return spdm_result_err!(ENOMEM);
+        }
+        let cipher_text_size = app_buffer.len() + 2 + random_count;
+        let secured_size = aad_size + cipher_text_size + tag_size;
+        if tag_size > SPDM_MAX_AEAD_TAG_SIZE || secured_buffer.len() < secured_size {
+            return spdm_result_err!(ENOMEM);
+        }
+
         let mut aad_buffer = [0u8; 6 + 8];
         let mut writer = Writer::init(&mut aad_buffer);
         let app_length = app_buffer.len() as u16;
@@ -714,56 +1242,71 @@ impl SpdmSession {
             }
         }
         length.encode(&mut writer);
-        let aad_size = writer.used();
-        assert_eq!(aad_size, 6 + transport_param.sequence_number_count as usize);
+        if writer.used() != aad_size {
+            return spdm_result_err!(EFAULT);
+        }
 
         let mut plain_text_buf = [0; config::MAX_SPDM_MESSAGE_BUFFER_SIZE];
         let mut writer = Writer::init(&mut plain_text_buf);
         app_length.encode(&mut writer);
         let head_size = writer.used();
-        assert_eq!(head_size, 2);
+        if head_size != 2 {
+            return spdm_result_err!(EFAULT);
+        }
         plain_text_buf[head_size..(head_size + app_buffer.len())].copy_from_slice(app_buffer);
-
-        let mut tag_buffer = [0u8; 16];
-
-        let mut salt = secret_param.salt.data;
-        let sequence_number = secret_param.sequence_number;
-        salt[0] ^= (sequence_number & 0xFF) as u8;
-        salt[1] ^= ((sequence_number >> 8) & 0xFF) as u8;
-        salt[2] ^= ((sequence_number >> 16) & 0xFF) as u8;
-        salt[3] ^= ((sequence_number >> 24) & 0xFF) as u8;
-        salt[4] ^= ((sequence_number >> 32) & 0xFF) as u8;
-        salt[5] ^= ((sequence_number >> 40) & 0xFF) as u8;
-        salt[6] ^= ((sequence_number >> 48) & 0xFF) as u8;
-        salt[7] ^= ((sequence_number >> 56) & 0xFF) as u8;
-
-        let (ret_cipher_text_size, ret_tag_size) = crypto::aead::encrypt(
-            aead_algo,
-            &secret_param.encryption_key.data[..(aead_algo.get_key_size() as usize)],
-            &salt[..(aead_algo.get_iv_size() as usize)],
-            &aad_buffer[..aad_size],
-            &plain_text_buf[0..cipher_text_size],
-            &mut tag_buffer[0..tag_size],
-            &mut secured_buffer[aad_size..(aad_size + cipher_text_size)],
-        )?;
-        assert_eq!(ret_tag_size, tag_size);
-        assert_eq!(ret_cipher_text_size, cipher_text_size);
+        self.crypto_provider
+            .get_random(&mut plain_text_buf[(head_size + app_buffer.len())..cipher_text_size])?;
+
+        let mut tag_buffer = [0u8; SPDM_MAX_AEAD_TAG_SIZE];
+
+        let (ret_cipher_text_size, ret_tag_size) = match self.get_external_key() {
+            Some(external_key) => {
+                let (external_key_schedule, key_handle) = external_key?;
+                (external_key_schedule.encrypt_cb)(
+                    key_handle,
+                    key_id,
+                    secret_param.sequence_number,
+                    &aad_buffer[..aad_size],
+                    &plain_text_buf[0..cipher_text_size],
+                    &mut tag_buffer[0..tag_size],
+                    &mut secured_buffer[aad_size..(aad_size + cipher_text_size)],
+                )?
+            }
+            None => {
+                let salt = get_aead_iv(secret_param, secret_param.sequence_number);
+                self.crypto_provider.aead_encrypt(
+                    aead_algo,
+                    &secret_param.encryption_key.data[..(aead_algo.get_key_size() as usize)],
+                    &salt[..(aead_algo.get_iv_size() as usize)],
+                    &aad_buffer[..aad_size],
+                    &plain_text_buf[0..cipher_text_size],
+                    &mut tag_buffer[0..tag_size],
+                    &mut secured_buffer[aad_size..(aad_size + cipher_text_size)],
+                )?
+            }
+        };
+        if ret_tag_size != tag_size || ret_cipher_text_size != cipher_text_size {
+            return spdm_result_err!(EFAULT);
+        }
 
         secured_buffer[..aad_size].copy_from_slice(&aad_buffer[..aad_size]);
-        secured_buffer[(aad_size + cipher_text_size)..(aad_size + cipher_text_size + tag_size)]
-            .copy_from_slice(&tag_buffer);
+        secured_buffer[(aad_size + cipher_text_size)..secured_size]
+            .copy_from_slice(&tag_buffer[..tag_size]);
 
-        //debug!("secure_buffer len - {}\n", aad_size + cipher_text_size + tag_size);
+        //debug!("secure_buffer len - {}\n", secured_size);
 
-        Ok(aad_size + cipher_text_size + tag_size)
+        Ok((secured_size, random_count))
     }
 
+    // Returns the size of the application data and the sequence number of
+    // the message, which the caller records once it is decoded.
     fn decode_msg(
         &self,
         secured_buffer: &[u8],
         app_buffer: &mut [u8],
         secret_param: &SpdmSessionSecretParam,
-    ) -> SpdmResult<usize> {
+        key_id: SpdmSessionKeyId,
+    ) -> SpdmResult<(usize, u64)> {
         let session_id = self.session_id;
         let aead_algo = self.crypto_param.aead_algo;
         let transport_param = &self.transport_param;
@@ -775,19 +1318,27 @@ impl SpdmSession {
             error!("session_id mismatch!\n");
             return spdm_result_err!(EINVAL);
         }
+        let mut sequence_number = secret_param.sequence_number;
         if transport_param.sequence_number_count != 0 {
-            let sequence_number = secret_param.sequence_number;
+            let mut received = 0u64;
             for i in 0..transport_param.sequence_number_count {
                 let s = u8::read(&mut reader).ok_or(spdm_err!(EIO))?;
-                if s != ((sequence_number >> (8 * i)) & 0xFF) as u8 {
-                    info!("sequence_num mismatch!\n");
-                    return spdm_result_err!(EINVAL);
-                }
+                received |= (s as u64) << (8 * i);
             }
+            sequence_number =
+                match secret_param.check_received_sequence_number(transport_param, received) {
+                    Ok(sequence_number) => sequence_number,
+                    Err(e) => {
+                        info!("sequence_num mismatch!\n");
+                        return Err(e);
+                    }
+                };
         }
         let length = u16::read(&mut reader).ok_or(spdm_err!(EIO))?;
         let aad_size = reader.used();
-        assert_eq!(aad_size, 6 + transport_param.sequence_number_count as usize);
+        if aad_size != 6 + transport_param.sequence_number_count as usize {
+            return spdm_result_err!(EFAULT);
+        }
 
         //debug!("secure_buffer len - {}\n", secured_buffer.len());
 
@@ -795,39 +1346,753 @@ impl SpdmSession {
         if secured_buffer.len() < length as usize + aad_size as usize {
             return spdm_result_err!(EINVAL);
         }
+        // the peer sets the length
+        if (length as usize) < tag_size {
+            return spdm_result_err!(EINVAL);
+        }
         let cipher_text_size = length as usize - tag_size;
+        if cipher_text_size > config::MAX_SPDM_MESSAGE_BUFFER_SIZE {
+            return spdm_result_err!(EINVAL);
+        }
 
         let mut plain_text_buf = [0; config::MAX_SPDM_MESSAGE_BUFFER_SIZE];
 
-        let mut salt = secret_param.salt.data;
-        let sequence_number = secret_param.sequence_number;
-        salt[0] ^= (sequence_number & 0xFF) as u8;
-        salt[1] ^= ((sequence_number >> 8) & 0xFF) as u8;
-        salt[2] ^= ((sequence_number >> 16) & 0xFF) as u8;
-        salt[3] ^= ((sequence_number >> 24) & 0xFF) as u8;
-        salt[4] ^= ((sequence_number >> 32) & 0xFF) as u8;
-        salt[5] ^= ((sequence_number >> 40) & 0xFF) as u8;
-        salt[6] ^= ((sequence_number >> 48) & 0xFF) as u8;
-        salt[7] ^= ((sequence_number >> 56) & 0xFF) as u8;
-
-        let ret_plain_text_size = crypto::aead::decrypt(
-            aead_algo,
-            &secret_param.encryption_key.data[..(aead_algo.get_key_size() as usize)],
-            &salt[..(aead_algo.get_iv_size() as usize)],
-            &secured_buffer[..aad_size],
-            &secured_buffer[aad_size..(aad_size + cipher_text_size)],
-            &secured_buffer
-                [(aad_size + cipher_text_size)..(aad_size + cipher_text_size + tag_size)],
-            &mut plain_text_buf[..cipher_text_size],
-        )?;
+        let aad = &secured_buffer[..aad_size];
+        let cipher_text = &secured_buffer[aad_size..(aad_size + cipher_text_size)];
+        let tag = &secured_buffer
+            [(aad_size + cipher_text_size)..(aad_size + cipher_text_size + tag_size)];
+        let ret_plain_text_size = match self.get_external_key() {
+            Some(external_key) => {
+                let (external_key_schedule, key_handle) = external_key?;
+                (external_key_schedule.decrypt_cb)(
+                    key_handle,
+                    key_id,
+                    sequence_number,
+                    aad,
+                    cipher_text,
+                    tag,
+                    &mut plain_text_buf[..cipher_text_size],
+                )
+            }
+            None => {
+                let salt = get_aead_iv(secret_param, sequence_number);
+                self.crypto_provider.aead_decrypt(
+                    aead_algo,
+                    &secret_param.encryption_key.data[..(aead_algo.get_key_size() as usize)],
+                    &salt[..(aead_algo.get_iv_size() as usize)],
+                    aad,
+                    cipher_text,
+                    tag,
+                    &mut plain_text_buf[..cipher_text_size],
+                )
+            }
+        }
+        .map_err(|_| spdm_err!(EBADMSG, "secured message decryption failed"))?;
 
         let mut reader = Reader::init(&plain_text_buf);
         let app_length = u16::read(&mut reader).ok_or(spdm_err!(EIO))? as usize;
-        if ret_plain_text_size < app_length + 2 {
+        if app_length + 2 > cipher_text_size
+            || ret_plain_text_size < app_length + 2
+            || app_length > app_buffer.len()
+        {
             return spdm_result_err!(EINVAL);
         }
 
         app_buffer[..app_length].copy_from_slice(&plain_text_buf[2..(app_length + 2)]);
-        Ok(app_length)
+        Ok((app_length, sequence_number))
+    }
+}
+
+// salt XOR sequence number
+fn get_aead_iv(
+    secret_param: &SpdmSessionSecretParam,
+    sequence_number: u64,
+) -> [u8; SPDM_MAX_AEAD_IV_SIZE] {
+    let mut salt = secret_param.salt.data;
+    salt[0] ^= (sequence_number & 0xFF) as u8;
+    salt[1] ^= ((sequence_number >> 8) & 0xFF) as u8;
+    salt[2] ^= ((sequence_number >> 16) & 0xFF) as u8;
+    salt[3] ^= ((sequence_number >> 24) & 0xFF) as u8;
+    salt[4] ^= ((sequence_number >> 32) & 0xFF) as u8;
+    salt[5] ^= ((sequence_number >> 40) & 0xFF) as u8;
+    salt[6] ^= ((sequence_number >> 48) & 0xFF) as u8;
+    salt[7] ^= ((sequence_number >> 56) & 0xFF) as u8;
+    salt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::SpdmErrorNum;
+    use core::convert::TryFrom;
+
+    fn transport_param(replay_window_size: u8) -> SpdmSessionTransportParam {
+        SpdmSessionTransportParam {
+            sequence_number_count: 2,
+            max_random_count: 0,
+            random_padding_policy: SpdmRandomPaddingPolicy::Random,
+            replay_window_size,
+        }
+    }
+
+    #[test]
+    fn test_replay_window() {
+        let transport_param = transport_param(4);
+        let mut secret_param = SpdmSessionSecretParam::default();
+        for sequence_number in [0u64, 2, 1].iter() {
+            let s = secret_param
+                .check_received_sequence_number(&transport_param, *sequence_number)
+                .unwrap();
+            secret_param.accept_received_sequence_number(s);
+        }
+        assert_eq!(secret_param.sequence_number, 3);
+
+        // replayed
+        assert_eq!(
+            secret_param
+                .check_received_sequence_number(&transport_param, 1)
+                .unwrap_err()
+                .num(),
+            SpdmErrorNum::EALREADY
+        );
+
+        // skipped ahead, 3 is still accepted until it leaves the window
+        let s = secret_param
+            .check_received_sequence_number(&transport_param, 6)
+            .unwrap();
+        secret_param.accept_received_sequence_number(s);
+        assert!(secret_param
+            .check_received_sequence_number(&transport_param, 3)
+            .is_ok());
+        let s = secret_param
+            .check_received_sequence_number(&transport_param, 7)
+            .unwrap();
+        secret_param.accept_received_sequence_number(s);
+        assert!(secret_param
+            .check_received_sequence_number(&transport_param, 3)
+            .is_err());
+    }
+
+    #[test]
+    fn test_in_order_only() {
+        let transport_param = transport_param(0);
+        let mut secret_param = SpdmSessionSecretParam::default();
+        secret_param.accept_received_sequence_number(0);
+        assert_eq!(
+            secret_param
+                .check_received_sequence_number(&transport_param, 2)
+                .unwrap_err()
+                .num(),
+            SpdmErrorNum::EINVAL
+        );
+        assert_eq!(
+            secret_param
+                .check_received_sequence_number(&transport_param, 0)
+                .unwrap_err()
+                .num(),
+            SpdmErrorNum::EALREADY
+        );
+    }
+
+    #[test]
+    fn test_sequence_number_wrap() {
+        // only the low 2 bytes are sent
+        let transport_param = transport_param(4);
+        let mut secret_param = SpdmSessionSecretParam {
+            sequence_number: 0x1_0000,
+            ..Default::default()
+        };
+        assert_eq!(
+            secret_param
+                .check_received_sequence_number(&transport_param, 0xFFFE)
+                .unwrap(),
+            0xFFFE
+        );
+        assert_eq!(
+            secret_param
+                .check_received_sequence_number(&transport_param, 0x0001)
+                .unwrap(),
+            0x1_0001
+        );
+        secret_param.accept_received_sequence_number(0xFFFE);
+        assert_eq!(secret_param.replay_window, 0b10);
+    }
+
+    #[test]
+    fn test_liveness_stats() {
+        let mut session = SpdmSession::new();
+        session.setup(0xFFFE_FFFE).unwrap();
+        assert_eq!(session.get_liveness_stats().heartbeat_count, 0);
+
+        session.record_heartbeat();
+        let liveness_stats = session.record_heartbeat();
+        assert_eq!(liveness_stats.heartbeat_count, 2);
+        assert_eq!(session.get_liveness_stats().heartbeat_count, 2);
+
+        session.teardown();
+        assert_eq!(session.get_liveness_stats().heartbeat_count, 0);
+    }
+
+    #[test]
+    fn test_peer_opaque_data() {
+        let mut session = SpdmSession::new();
+        session.setup(0xFFFE_FFFE).unwrap();
+        assert!(session.get_peer_opaque_data().is_empty());
+
+        let opaque = SpdmOpaqueStruct::try_from(&[0x01, 0x00, 0x00, 0x00][..]).unwrap();
+        session.set_peer_opaque_data(&opaque);
+        assert_eq!(session.get_peer_opaque_data(), &[0x01, 0x00, 0x00, 0x00]);
+
+        session.teardown();
+        assert!(session.get_peer_opaque_data().is_empty());
+    }
+
+    fn established_session(use_psk: bool, dhe_secret: &[u8]) -> SpdmSession {
+        let mut session = SpdmSession::new();
+        session.setup(0xFFFE_FFFE).unwrap();
+        session.set_use_psk(use_psk);
+        session.set_crypto_param(
+            SpdmBaseHashAlgo::TPM_ALG_SHA_384,
+            SpdmDheAlgo::SECP_384_R1,
+            SpdmAeadAlgo::AES_256_GCM,
+            SpdmKeyScheduleAlgo::SPDM_KEY_SCHEDULE,
+        );
+        session.set_session_policy(SpdmKeyExchangeSessionPolicy::TERMINATION_POLICY_RUNTIME_UPDATE);
+        session
+            .set_dhe_secret(&mut SpdmDheFinalKeyStruct::try_from(dhe_secret).unwrap())
+            .unwrap();
+        session.set_session_state(SpdmSessionState::SpdmSessionEstablished);
+        session
+    }
+
+    #[test]
+    fn test_zeroize_secrets() {
+        let mut dhe_secret = SpdmDheFinalKeyStruct::try_from(&[0x5au8; 48][..]).unwrap();
+        let mut session = established_session(false, &[0x5a; 48]);
+        session.set_dhe_secret(&mut dhe_secret).unwrap();
+        // the session keeps the only copy
+        assert!(dhe_secret.data.iter().all(|b| *b == 0));
+        assert!(session
+            .master_secret
+            .dhe_secret
+            .as_ref()
+            .iter()
+            .all(|b| *b == 0x5a));
+        assert!(session
+            .master_secret
+            .master_secret
+            .as_ref()
+            .iter()
+            .any(|b| *b != 0));
+
+        session.teardown();
+        assert!(session
+            .master_secret
+            .dhe_secret
+            .data
+            .iter()
+            .all(|b| *b == 0));
+        assert!(session
+            .master_secret
+            .master_secret
+            .data
+            .iter()
+            .all(|b| *b == 0));
+    }
+
+    #[test]
+    fn test_key_update_secrets() {
+        let mut session = crate::testlib::new_peer_session(0xFFFE_FFFE);
+        let key = session.application_secret.request_direction.encryption_key;
+
+        // rolled back
+        session.create_data_secret_update(true, false).unwrap();
+        assert_ne!(
+            session
+                .application_secret
+                .request_direction
+                .encryption_key
+                .as_ref(),
+            key.as_ref()
+        );
+        session
+            .activate_data_secret_update(true, false, false)
+            .unwrap();
+        assert_eq!(
+            session
+                .application_secret
+                .request_direction
+                .encryption_key
+                .as_ref(),
+            key.as_ref()
+        );
+
+        // completed
+        session.create_data_secret_update(true, false).unwrap();
+        session
+            .activate_data_secret_update(true, false, true)
+            .unwrap();
+        assert_ne!(
+            session
+                .application_secret
+                .request_direction
+                .encryption_key
+                .as_ref(),
+            key.as_ref()
+        );
+
+        // the previous keys aren't kept either way
+        let backup = &session.application_secret_backup;
+        assert!(backup.request_data_secret.data.iter().all(|b| *b == 0));
+        assert!(backup
+            .request_direction
+            .encryption_key
+            .data
+            .iter()
+            .all(|b| *b == 0));
+    }
+
+    std::thread_local! {
+        static ACTIVATED_KEYS: core::cell::RefCell<std::vec::Vec<(SpdmSessionKeyId, bool)>> =
+            core::cell::RefCell::new(std::vec::Vec::new());
+    }
+
+    #[test]
+    fn test_external_key_update_failure() {
+        let mut session = SpdmSession::new();
+        session.setup(0xFFFE_FFFE).unwrap();
+        session.set_crypto_provider(SpdmCryptoProvider {
+            external_key_schedule: Some(SpdmExternalKeySchedule {
+                import_shared_secret_cb: |_, _, _, _| Some(1),
+                derive_handshake_keys_cb: |_, _| Ok(()),
+                derive_data_keys_cb: |_, _| Ok(()),
+                // the response key can't be updated
+                update_data_key_cb: |_, key_id| match key_id {
+                    SpdmSessionKeyId::ResponseData => spdm_result_err!(EFAULT),
+                    _ => Ok(()),
+                },
+                activate_data_key_update_cb: |_, key_id, use_new_key| {
+                    ACTIVATED_KEYS.with(|keys| keys.borrow_mut().push((key_id, use_new_key)));
+                    Ok(())
+                },
+                hmac_cb: |_, _, _| None,
+                encrypt_cb: |_, _, _, _, _, _, _| spdm_result_err!(EFAULT),
+                decrypt_cb: |_, _, _, _, _, _, _| spdm_result_err!(EFAULT),
+                release_cb: |_| {},
+            }),
+            ..Default::default()
+        });
+        session.key_handle = Some(1);
+
+        assert_eq!(
+            session
+                .create_data_secret_update(true, true)
+                .unwrap_err()
+                .num(),
+            SpdmErrorNum::EFAULT
+        );
+        // the request key is restored, no update is pending
+        assert_eq!(
+            ACTIVATED_KEYS.with(|keys| keys.borrow().clone()),
+            [(SpdmSessionKeyId::RequestData, false)]
+        );
+        assert!(!session.request_update_pending);
+        assert!(!session.response_update_pending);
+    }
+
+    #[test]
+    fn test_key_update_rollback_direction() {
+        let mut requester_session = crate::testlib::new_peer_session(0xFFFE_FFFE);
+        let mut session = crate::testlib::new_peer_session(0xFFFE_FFFE);
+        session.create_data_secret_update(true, true).unwrap();
+
+        // the request still uses the previous key
+        let mut secured_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        let used = requester_session
+            .encode_spdm_secured_message(&[1, 2, 3, 4], &mut secured_buffer, true)
+            .unwrap();
+        let mut app_buffer = [0u8; 4];
+        assert_eq!(
+            session
+                .decode_spdm_secured_message(&secured_buffer[..used], &mut app_buffer, true)
+                .unwrap(),
+            4
+        );
+
+        // only the request direction is rolled back
+        assert!(!session.request_update_pending);
+        assert!(session.response_update_pending);
+        assert_eq!(
+            session
+                .application_secret
+                .request_direction
+                .encryption_key
+                .as_ref(),
+            requester_session
+                .application_secret
+                .request_direction
+                .encryption_key
+                .as_ref()
+        );
+        assert_ne!(
+            session
+                .application_secret
+                .response_direction
+                .encryption_key
+                .as_ref(),
+            requester_session
+                .application_secret
+                .response_direction
+                .encryption_key
+                .as_ref()
+        );
+    }
+
+    #[test]
+    fn test_encode_failure_sequence_number() {
+        let mut session = crate::testlib::new_peer_session(0xFFFE_FFFE);
+
+        // the secured message doesn't fit
+        let mut secured_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        assert_eq!(
+            session
+                .encode_spdm_secured_message(&[1, 2, 3, 4], &mut secured_buffer[..16], true)
+                .unwrap_err()
+                .num(),
+            SpdmErrorNum::ENOMEM
+        );
+        assert_eq!(
+            session.application_secret.request_direction.sequence_number,
+            0
+        );
+
+        session
+            .encode_spdm_secured_message(&[1, 2, 3, 4], &mut secured_buffer, true)
+            .unwrap();
+        assert_eq!(
+            session.application_secret.request_direction.sequence_number,
+            1
+        );
+    }
+
+    #[test]
+    fn test_resumption_psk() {
+        let resumption_psk = established_session(false, &[0x5a; 48])
+            .derive_resumption_psk()
+            .unwrap();
+        assert_eq!(resumption_psk.psk.data_size, 48);
+        assert_eq!(resumption_psk.psk_hint.data_size, 32);
+        assert_eq!(
+            resumption_psk.base_hash_algo,
+            SpdmBaseHashAlgo::TPM_ALG_SHA_384
+        );
+        assert_eq!(
+            resumption_psk.session_policy,
+            SpdmKeyExchangeSessionPolicy::TERMINATION_POLICY_RUNTIME_UPDATE
+        );
+
+        // the peer derives the same one
+        let peer_resumption_psk = established_session(false, &[0x5a; 48])
+            .derive_resumption_psk()
+            .unwrap();
+        assert_eq!(
+            peer_resumption_psk.psk.as_ref(),
+            resumption_psk.psk.as_ref()
+        );
+        assert_eq!(
+            peer_resumption_psk.psk_hint.as_ref(),
+            resumption_psk.psk_hint.as_ref()
+        );
+        let other_resumption_psk = established_session(false, &[0xa5; 48])
+            .derive_resumption_psk()
+            .unwrap();
+        assert_ne!(
+            other_resumption_psk.psk_hint.as_ref(),
+            resumption_psk.psk_hint.as_ref()
+        );
+
+        // only from established KEY_EXCHANGE sessions
+        assert!(established_session(true, &[0x5a; 48])
+            .derive_resumption_psk()
+            .is_none());
+        let mut session = established_session(false, &[0x5a; 48]);
+        session.set_session_state(SpdmSessionState::SpdmSessionHandshaking);
+        assert!(session.derive_resumption_psk().is_none());
+    }
+
+    #[test]
+    fn test_export_secret() {
+        // HKDF-Expand with the SPDM 1.1 and 1.2 bin_str, computed independently
+        let key = (0u8..48).collect::<Vec<u8>>();
+        let mut key_schedule = SpdmKeySchedule::new();
+        let okm = key_schedule
+            .hkdf_expand_label(
+                SpdmBaseHashAlgo::TPM_ALG_SHA_384,
+                &key,
+                b"exporter",
+                Some(b"ctx"),
+                32,
+            )
+            .unwrap();
+        assert_eq!(
+            okm.as_ref(),
+            &[
+                0xb3, 0xc5, 0x17, 0xb1, 0xeb, 0x74, 0xf6, 0xec, 0x3b, 0xbd, 0xd6, 0x74, 0x35, 0x13,
+                0xb1, 0xe9, 0xb3, 0x79, 0x94, 0xd5, 0xc5, 0x88, 0xa4, 0x70, 0x81, 0x2d, 0xce, 0x78,
+                0x66, 0xcb, 0xf9, 0x92,
+            ][..]
+        );
+        key_schedule.set_spdm_version(SpdmVersion::SpdmVersion12);
+        let okm = key_schedule
+            .hkdf_expand_label(
+                SpdmBaseHashAlgo::TPM_ALG_SHA_384,
+                &key,
+                b"exporter",
+                Some(b"ctx"),
+                32,
+            )
+            .unwrap();
+        assert_eq!(
+            okm.as_ref(),
+            &[
+                0x95, 0xef, 0x2a, 0x20, 0x33, 0x9d, 0xb2, 0x74, 0xe0, 0xf1, 0xd8, 0xb5, 0xab, 0x59,
+                0x70, 0x60, 0x60, 0xb6, 0x00, 0xc7, 0x1a, 0xe7, 0x82, 0xa2, 0xca, 0x01, 0x99, 0x59,
+                0x23, 0x75, 0xea, 0x25,
+            ][..]
+        );
+
+        let th1 = SpdmDigestStruct::try_from(&[0x11u8; 48][..]).unwrap();
+        let handshaking_session = || {
+            let mut session = SpdmSession::new();
+            session.setup(0xFFFE_FFFE).unwrap();
+            session.set_spdm_version(SpdmVersion::SpdmVersion12);
+            session.set_crypto_param(
+                SpdmBaseHashAlgo::TPM_ALG_SHA_384,
+                SpdmDheAlgo::SECP_384_R1,
+                SpdmAeadAlgo::AES_256_GCM,
+                SpdmKeyScheduleAlgo::SPDM_KEY_SCHEDULE,
+            );
+            session
+                .set_dhe_secret(&mut SpdmDheFinalKeyStruct::try_from(&[0x5au8; 48][..]).unwrap())
+                .unwrap();
+            session.set_session_state(SpdmSessionState::SpdmSessionHandshaking);
+            session.generate_handshake_secret(&th1).unwrap();
+            session
+        };
+
+        // only once the session is established
+        let mut session = handshaking_session();
+        assert!(session.get_export_master_secret().is_none());
+        assert_eq!(
+            session
+                .export_secret(b"exporter", b"", 32)
+                .unwrap_err()
+                .num(),
+            SpdmErrorNum::EPERM
+        );
+        session.set_session_state(SpdmSessionState::SpdmSessionEstablished);
+
+        // derived from the master secret
+        let export_master_secret = session
+            .key_schedule
+            .hkdf_expand_label(
+                SpdmBaseHashAlgo::TPM_ALG_SHA_384,
+                session.master_secret.master_secret.as_ref(),
+                b"exp master",
+                None,
+                48,
+            )
+            .unwrap();
+        assert_eq!(
+            session.get_export_master_secret().unwrap().as_ref(),
+            export_master_secret.as_ref()
+        );
+
+        // the peer exports the same secrets
+        let mut peer_session = handshaking_session();
+        peer_session.set_session_state(SpdmSessionState::SpdmSessionEstablished);
+        let secret = session.export_secret(b"exporter", b"ctx", 32).unwrap();
+        assert_eq!(secret.data_size, 32);
+        assert_eq!(
+            peer_session
+                .export_secret(b"exporter", b"ctx", 32)
+                .unwrap()
+                .as_ref(),
+            secret.as_ref()
+        );
+        assert_ne!(
+            session
+                .export_secret(b"exporter", b"other", 32)
+                .unwrap()
+                .as_ref(),
+            secret.as_ref()
+        );
+        assert_eq!(
+            session
+                .export_secret(b"exporter", b"ctx", 49)
+                .unwrap_err()
+                .num(),
+            SpdmErrorNum::EINVAL
+        );
+
+        session.teardown();
+        assert!(session.get_export_master_secret().is_none());
+    }
+
+    #[test]
+    fn test_key_schedule_failure() {
+        let failing_hkdf = SpdmCryptoProvider {
+            hkdf: Some(crate::crypto::SpdmHkdf {
+                hkdf_expand_cb: |_, _, _, _| None,
+            }),
+            ..Default::default()
+        };
+
+        let mut session = SpdmSession::new();
+        session.setup(0xFFFE_FFFE).unwrap();
+        session.set_crypto_provider(failing_hkdf);
+        session.set_crypto_param(
+            SpdmBaseHashAlgo::TPM_ALG_SHA_384,
+            SpdmDheAlgo::SECP_384_R1,
+            SpdmAeadAlgo::AES_256_GCM,
+            SpdmKeyScheduleAlgo::SPDM_KEY_SCHEDULE,
+        );
+        let mut dhe_secret = SpdmDheFinalKeyStruct::try_from(&[0x5au8; 48][..]).unwrap();
+        assert!(session.set_dhe_secret(&mut dhe_secret).is_err());
+        assert!(dhe_secret.data.iter().all(|b| *b == 0));
+        let th = SpdmDigestStruct::try_from(&[0x11u8; 48][..]).unwrap();
+        assert!(session.generate_handshake_secret(&th).is_err());
+        assert!(session.generate_data_secret(&th).is_err());
+
+        // a failed key update leaves the keys as they are
+        let mut session = established_session(false, &[0x5a; 48]);
+        session.generate_handshake_secret(&th).unwrap();
+        session.generate_data_secret(&th).unwrap();
+        let request_data_secret = session.application_secret.request_data_secret;
+        session.set_crypto_provider(failing_hkdf);
+        assert!(session.create_data_secret_update(true, true).is_err());
+        assert_eq!(
+            session.application_secret.request_data_secret.as_ref(),
+            request_data_secret.as_ref()
+        );
+        assert!(!session.request_update_pending);
+        assert!(!session.response_update_pending);
+    }
+
+    #[test]
+    fn test_random_padding_policy() {
+        let mut session = established_session(false, &[0x5a; 48]);
+        let th = SpdmDigestStruct::try_from(&[0x11u8; 48][..]).unwrap();
+        session.generate_handshake_secret(&th).unwrap();
+        session.generate_data_secret(&th).unwrap();
+        session.set_transport_param(2, 16);
+
+        // session id, sequence number and length, then the application data
+        // length, the data, the padding and the tag
+        let secured_size = |random_count: usize| 6 + 2 + 2 + 4 + random_count + 16;
+        let mut secured_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        for (policy, random_count) in [
+            (SpdmRandomPaddingPolicy::Max, 16usize),
+            (SpdmRandomPaddingPolicy::Zero, 0),
+        ]
+        .iter()
+        {
+            session.set_random_padding_policy(*policy);
+            let used = session
+                .encode_spdm_secured_message(&[1, 2, 3, 4], &mut secured_buffer, true)
+                .unwrap();
+            assert_eq!(used, secured_size(*random_count));
+            assert_eq!(session.runtime_info.random_count as usize, *random_count);
+        }
+
+        session.set_random_padding_policy(SpdmRandomPaddingPolicy::Random);
+        for _ in 0..8 {
+            let used = session
+                .encode_spdm_secured_message(&[1, 2, 3, 4], &mut secured_buffer, true)
+                .unwrap();
+            let random_count = session.runtime_info.random_count as usize;
+            assert!((1..=16).contains(&random_count));
+            assert_eq!(used, secured_size(random_count));
+        }
+    }
+
+    #[test]
+    fn test_encode_tag_size() {
+        let mut session = crate::testlib::new_peer_session(0xFFFE_FFFE);
+        let tag_size = session.crypto_param.aead_algo.get_tag_size() as usize;
+        let secured_size = 6 + 2 + 4 + tag_size;
+        let mut secured_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+        assert_eq!(
+            session
+                .encode_spdm_secured_message(
+                    &[1, 2, 3, 4],
+                    &mut secured_buffer[..(secured_size - 1)],
+                    true
+                )
+                .unwrap_err()
+                .num(),
+            SpdmErrorNum::ENOMEM
+        );
+        assert_eq!(
+            session
+                .encode_spdm_secured_message(
+                    &[1, 2, 3, 4],
+                    &mut secured_buffer[..secured_size],
+                    true
+                )
+                .unwrap(),
+            secured_size
+        );
+    }
+
+    #[test]
+    fn test_decode_malformed_length() {
+        let th = SpdmDigestStruct::try_from(&[0x11u8; 48][..]).unwrap();
+        let mut sessions = [
+            established_session(false, &[0x5a; 48]),
+            established_session(false, &[0x5a; 48]),
+        ];
+        for session in sessions.iter_mut() {
+            session.generate_handshake_secret(&th).unwrap();
+            session.generate_data_secret(&th).unwrap();
+            session.set_transport_param(2, 0);
+        }
+        let [mut requester_session, mut session] = sessions;
+
+        let mut secured_buffer = [0u8; 6 + 2 + 0xFFFF];
+        let used = requester_session
+            .encode_spdm_secured_message(&[1, 2, 3, 4], &mut secured_buffer, true)
+            .unwrap();
+        let mut app_buffer = [0u8; 4];
+
+        // the application data doesn't fit
+        assert_eq!(
+            session
+                .decode_spdm_secured_message(&secured_buffer[..used], &mut app_buffer[..2], true)
+                .unwrap_err()
+                .num(),
+            SpdmErrorNum::EINVAL
+        );
+
+        // shorter than the tag, and beyond the message buffers
+        for length in [4u16, 0xFFFF].iter() {
+            let mut malformed = secured_buffer;
+            malformed[6..8].copy_from_slice(&length.to_le_bytes());
+            assert_eq!(
+                session
+                    .decode_spdm_secured_message(&malformed, &mut app_buffer, true)
+                    .unwrap_err()
+                    .num(),
+                SpdmErrorNum::EINVAL
+            );
+        }
+
+        assert_eq!(
+            session
+                .decode_spdm_secured_message(&secured_buffer[..used], &mut app_buffer, true)
+                .unwrap(),
+            4
+        );
+        assert_eq!(app_buffer, [1, 2, 3, 4]);
     }
 }
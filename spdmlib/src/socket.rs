@@ -0,0 +1,192 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+#![forbid(unsafe_code)]
+
+//! `SpdmDeviceIo` over a TCP connection, in the socket format of the DMTF
+//! spdm-emu tools, for tests against spdm_requester_emu and
+//! spdm_responder_emu.
+//!
+//! Each message is preceded by a header of three big-endian u32: the
+//! command, the transport type and the payload size. SPDM messages are
+//! carried with `SPDM_SOCKET_COMMAND_NORMAL`, the other commands control
+//! the emulator.
+
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use crate::common::SpdmDeviceIo;
+use crate::error::SpdmResult;
+
+pub const SPDM_SOCKET_HEADER_SIZE: usize = 12;
+
+pub const SPDM_SOCKET_TRANSPORT_TYPE_NONE: u32 = 0x00;
+pub const SPDM_SOCKET_TRANSPORT_TYPE_MCTP: u32 = 0x01;
+pub const SPDM_SOCKET_TRANSPORT_TYPE_PCI_DOE: u32 = 0x02;
+
+pub const SPDM_SOCKET_COMMAND_NORMAL: u32 = 0x0001;
+pub const SPDM_SOCKET_COMMAND_CONTINUE: u32 = 0xFFFD;
+pub const SPDM_SOCKET_COMMAND_SHUTDOWN: u32 = 0xFFFE;
+pub const SPDM_SOCKET_COMMAND_UNKNOWN: u32 = 0xFFFF;
+pub const SPDM_SOCKET_COMMAND_TEST: u32 = 0xDEAD;
+
+/// `SpdmDeviceIo` on `stream`, whose peer uses the transport
+/// `transport_type`, e.g. `SPDM_SOCKET_TRANSPORT_TYPE_MCTP` for a
+/// context with `MctpTransportEncap`.
+///
+/// A receive that gets another command than `SPDM_SOCKET_COMMAND_NORMAL`
+/// returns `Err(0)` and leaves the command to `take_command()`.
+pub struct SpdmSocketDeviceIo {
+    stream: TcpStream,
+    transport_type: u32,
+    command: Option<u32>,
+}
+
+impl SpdmSocketDeviceIo {
+    pub fn new(stream: TcpStream, transport_type: u32) -> Self {
+        SpdmSocketDeviceIo {
+            stream,
+            transport_type,
+            command: None,
+        }
+    }
+
+    pub fn get_stream(&mut self) -> &mut TcpStream {
+        &mut self.stream
+    }
+
+    /// The last command other than `SPDM_SOCKET_COMMAND_NORMAL` a receive
+    /// got, e.g. `SPDM_SOCKET_COMMAND_SHUTDOWN` from a requester that is
+    /// done.
+    pub fn take_command(&mut self) -> Option<u32> {
+        self.command.take()
+    }
+
+    /// Send `payload` with `command`.
+    pub fn send_command(&mut self, command: u32, payload: &[u8]) -> SpdmResult {
+        let mut header = [0u8; SPDM_SOCKET_HEADER_SIZE];
+        header[0..4].copy_from_slice(&command.to_be_bytes());
+        header[4..8].copy_from_slice(&self.transport_type.to_be_bytes());
+        header[8..12].copy_from_slice(&(payload.len() as u32).to_be_bytes());
+        let result = self
+            .stream
+            .write_all(&header)
+            .and_then(|_| self.stream.write_all(payload))
+            .and_then(|_| self.stream.flush());
+        if result.is_err() {
+            return spdm_result_err!(EIO, "socket write error");
+        }
+        Ok(())
+    }
+
+    /// Receive a message into `payload`, returns its command and size.
+    /// Fails with E2BIG if the payload doesn't fit, the payload is dropped
+    /// so the next message can be received.
+    pub fn receive_command(&mut self, payload: &mut [u8]) -> SpdmResult<(u32, usize)> {
+        let mut header = [0u8; SPDM_SOCKET_HEADER_SIZE];
+        if self.stream.read_exact(&mut header).is_err() {
+            return spdm_result_err!(EIO, "socket read error");
+        }
+        let command = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+        let transport_type = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+        let size = u32::from_be_bytes([header[8], header[9], header[10], header[11]]) as usize;
+
+        if size > payload.len() {
+            let mut rest = (&mut self.stream).take(size as u64);
+            if io::copy(&mut rest, &mut io::sink()).is_err() {
+                return spdm_result_err!(EIO, "socket read error");
+            }
+            return spdm_result_err!(E2BIG, "socket message too big");
+        }
+        if self.stream.read_exact(&mut payload[..size]).is_err() {
+            return spdm_result_err!(EIO, "socket read error");
+        }
+        if command == SPDM_SOCKET_COMMAND_NORMAL && transport_type != self.transport_type {
+            error!("!!! socket : transport type {:x} !!!\n", transport_type);
+            return spdm_result_err!(EINVAL);
+        }
+        Ok((command, size))
+    }
+}
+
+impl SpdmDeviceIo for SpdmSocketDeviceIo {
+    fn send(&mut self, buffer: &[u8]) -> SpdmResult {
+        self.send_command(SPDM_SOCKET_COMMAND_NORMAL, buffer)
+    }
+
+    fn receive(&mut self, buffer: &mut [u8]) -> Result<usize, usize> {
+        match self.receive_command(buffer) {
+            Ok((SPDM_SOCKET_COMMAND_NORMAL, size)) => Ok(size),
+            Ok((command, _)) => {
+                info!("socket command {:x}\n", command);
+                self.command = Some(command);
+                Err(0)
+            }
+            Err(_) => Err(0),
+        }
+    }
+
+    fn receive_with_timeout(
+        &mut self,
+        buffer: &mut [u8],
+        timeout_us: usize,
+    ) -> Result<usize, usize> {
+        // wait for the socket header only, the rest is received blocking
+        let timeout = Duration::from_micros(timeout_us.max(1) as u64);
+        if self.stream.set_read_timeout(Some(timeout)).is_err() {
+            return Err(0);
+        }
+        let mut header = [0u8; 1];
+        let ready = matches!(self.stream.peek(&mut header), Ok(size) if size != 0);
+        if self.stream.set_read_timeout(None).is_err() || !ready {
+            return Err(0);
+        }
+        self.receive(buffer)
+    }
+
+    fn flush_all(&mut self) -> SpdmResult {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_socket_device_io() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let mut peer = listener.accept().unwrap().0;
+        let mut device_io = SpdmSocketDeviceIo::new(stream, SPDM_SOCKET_TRANSPORT_TYPE_MCTP);
+        let mut buffer = [0u8; 8];
+
+        device_io.send(&[0x05, 0x12, 0x84]).unwrap();
+        let mut message = [0u8; 15];
+        peer.read_exact(&mut message).unwrap();
+        assert_eq!(
+            message,
+            [0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 3, 0x05, 0x12, 0x84]
+        );
+
+        // a normal message, one too big for `buffer` and a shutdown
+        peer.write_all(&[0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 2, 0x05, 0x11])
+            .unwrap();
+        peer.write_all(&[0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 9])
+            .unwrap();
+        peer.write_all(&[0u8; 9]).unwrap();
+        peer.write_all(&[0, 0, 0xFF, 0xFE, 0, 0, 0, 1, 0, 0, 0, 0])
+            .unwrap();
+        assert_eq!(device_io.receive(&mut buffer), Ok(2));
+        assert_eq!(&buffer[..2], &[0x05, 0x11]);
+        assert_eq!(device_io.receive(&mut buffer), Err(0));
+        assert_eq!(device_io.take_command(), None);
+        assert_eq!(device_io.receive(&mut buffer), Err(0));
+        assert_eq!(device_io.take_command(), Some(SPDM_SOCKET_COMMAND_SHUTDOWN));
+
+        assert_eq!(device_io.receive_with_timeout(&mut buffer, 1000), Err(0));
+    }
+}
@@ -0,0 +1,243 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+#![forbid(unsafe_code)]
+
+//! Contexts and sessions shared by the unit tests.
+//!
+//! The contexts use `PlainTransportEncap`, which passes the SPDM messages
+//! unchanged, so a test can build the requests by hand and read the
+//! responses as they are sent. The helpers live in the crate rather than
+//! in test-utils as the unit tests can't share the types of another build
+//! of spdmlib.
+
+extern crate alloc;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+use crate::common::{SpdmConfigInfo, SpdmDeviceIo, SpdmProvisionInfo, SpdmTransportEncap};
+use crate::config;
+use crate::error::SpdmResult;
+use crate::loopback::SpdmDeviceIoLoopback;
+use crate::msgs::*;
+use crate::requester::RequesterContext;
+use crate::responder::ResponderContext;
+use crate::session::{SpdmSession, SpdmSessionState};
+use test_utils::TestCertChain;
+
+/// Drops what is sent, never receives anything.
+pub struct NullDeviceIo;
+
+impl SpdmDeviceIo for NullDeviceIo {
+    fn send(&mut self, _buffer: &[u8]) -> SpdmResult {
+        Ok(())
+    }
+
+    fn receive(&mut self, _buffer: &mut [u8]) -> Result<usize, usize> {
+        Err(0)
+    }
+
+    fn flush_all(&mut self) -> SpdmResult {
+        Ok(())
+    }
+}
+
+/// Answers the requests with `responses` in turn.
+pub struct ScriptedDeviceIo<'r> {
+    pub responses: &'r [&'r [u8]],
+    pub sent: usize,
+}
+
+impl SpdmDeviceIo for ScriptedDeviceIo<'_> {
+    fn send(&mut self, _buffer: &[u8]) -> SpdmResult {
+        self.sent += 1;
+        Ok(())
+    }
+
+    fn receive(&mut self, buffer: &mut [u8]) -> Result<usize, usize> {
+        let response = self.responses.get(self.sent - 1).ok_or(0usize)?;
+        buffer[..response.len()].copy_from_slice(response);
+        Ok(response.len())
+    }
+
+    fn flush_all(&mut self) -> SpdmResult {
+        Ok(())
+    }
+}
+
+pub struct PlainTransportEncap;
+
+impl SpdmTransportEncap for PlainTransportEncap {
+    fn encap(
+        &mut self,
+        spdm_buffer: &[u8],
+        transport_buffer: &mut [u8],
+        _secured_message: bool,
+    ) -> SpdmResult<usize> {
+        transport_buffer[..spdm_buffer.len()].copy_from_slice(spdm_buffer);
+        Ok(spdm_buffer.len())
+    }
+
+    fn decap(
+        &mut self,
+        transport_buffer: &[u8],
+        spdm_buffer: &mut [u8],
+    ) -> SpdmResult<(usize, bool)> {
+        spdm_buffer[..transport_buffer.len()].copy_from_slice(transport_buffer);
+        Ok((transport_buffer.len(), false))
+    }
+
+    fn encap_app(&mut self, spdm_buffer: &[u8], app_buffer: &mut [u8]) -> SpdmResult<usize> {
+        app_buffer[..spdm_buffer.len()].copy_from_slice(spdm_buffer);
+        Ok(spdm_buffer.len())
+    }
+
+    fn decap_app(&mut self, app_buffer: &[u8], spdm_buffer: &mut [u8]) -> SpdmResult<usize> {
+        spdm_buffer[..app_buffer.len()].copy_from_slice(app_buffer);
+        Ok(app_buffer.len())
+    }
+
+    fn get_sequence_number_count(&mut self) -> u8 {
+        0
+    }
+
+    fn get_max_random_count(&mut self) -> u16 {
+        0
+    }
+}
+
+/// The hash and asymmetric algorithms the cert chain tests run with.
+pub const CERT_CHAIN_ALGOS: [(SpdmBaseHashAlgo, SpdmBaseAsymAlgo); 4] = [
+    (
+        SpdmBaseHashAlgo::TPM_ALG_SHA_256,
+        SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P256,
+    ),
+    (
+        SpdmBaseHashAlgo::TPM_ALG_SHA_384,
+        SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P384,
+    ),
+    (
+        SpdmBaseHashAlgo::TPM_ALG_SHA_256,
+        SpdmBaseAsymAlgo::TPM_ALG_RSASSA_2048,
+    ),
+    (
+        SpdmBaseHashAlgo::TPM_ALG_SHA_384,
+        SpdmBaseAsymAlgo::TPM_ALG_RSAPSS_3072,
+    ),
+];
+
+/// Root, intermediate and leaf cert generated with test-utils, valid from
+/// 2020 to 2049, the leaf key is a `base_asym_algo` one.
+pub fn generate_cert_chain(
+    base_hash_algo: SpdmBaseHashAlgo,
+    base_asym_algo: SpdmBaseAsymAlgo,
+) -> TestCertChain {
+    test_utils::generate_cert_chain(
+        test_utils::spdmlib::msgs::SpdmBaseHashAlgo::from_bits_truncate(base_hash_algo.bits()),
+        test_utils::spdmlib::msgs::SpdmBaseAsymAlgo::from_bits_truncate(base_asym_algo.bits()),
+    )
+}
+
+/// The cert chain of a slot as provisioned, the DER certs from root to leaf.
+pub fn cert_chain_data(test_cert_chain: &TestCertChain) -> SpdmCertChainData {
+    let cert_chain = test_cert_chain.cert_chain();
+    let mut cert_chain_data = SpdmCertChainData::default();
+    cert_chain_data.data[..cert_chain.len()].copy_from_slice(&cert_chain);
+    cert_chain_data.data_size = cert_chain.len() as u16;
+    cert_chain_data
+}
+
+// zero sized like NullDeviceIo, leaking them doesn't allocate
+fn plain_transport_encap() -> &'static mut PlainTransportEncap {
+    Box::leak(Box::new(PlainTransportEncap))
+}
+
+/// Responder dropping its responses.
+pub fn new_responder(
+    config_info: SpdmConfigInfo,
+    provision_info: SpdmProvisionInfo,
+) -> ResponderContext<'static> {
+    ResponderContext::new(
+        Box::leak(Box::new(NullDeviceIo)),
+        plain_transport_encap(),
+        config_info,
+        provision_info,
+    )
+}
+
+/// Responder and the endpoint receiving its responses.
+pub fn new_listened_responder(
+    config_info: SpdmConfigInfo,
+    provision_info: SpdmProvisionInfo,
+) -> (SpdmDeviceIoLoopback<'static>, ResponderContext<'static>) {
+    let (listener, responder_io) = SpdmDeviceIoLoopback::pair();
+    let responder = ResponderContext::new(
+        Box::leak(Box::new(responder_io)),
+        plain_transport_encap(),
+        config_info,
+        provision_info,
+    );
+    (listener, responder)
+}
+
+pub fn new_requester<'a>(
+    device_io: &'a mut dyn SpdmDeviceIo,
+    config_info: SpdmConfigInfo,
+    provision_info: SpdmProvisionInfo,
+) -> RequesterContext<'a> {
+    RequesterContext::new(
+        device_io,
+        plain_transport_encap(),
+        config_info,
+        provision_info,
+    )
+}
+
+/// Establish `session`, set up with fixed SHA-384, SECP384R1 and
+/// AES-256-GCM keys. Two sessions established this way decode the messages
+/// of each other.
+pub fn establish_test_session(session: &mut SpdmSession) {
+    let th = SpdmDigestStruct::try_from(&[0x5au8; 48][..]).unwrap();
+    let mut dhe_secret = SpdmDheFinalKeyStruct::try_from(&[0x5au8; 48][..]).unwrap();
+    session.set_crypto_param(
+        SpdmBaseHashAlgo::TPM_ALG_SHA_384,
+        SpdmDheAlgo::SECP_384_R1,
+        SpdmAeadAlgo::AES_256_GCM,
+        SpdmKeyScheduleAlgo::SPDM_KEY_SCHEDULE,
+    );
+    session.set_transport_param(0, 0);
+    session.set_dhe_secret(&mut dhe_secret).unwrap();
+    session.generate_handshake_secret(&th).unwrap();
+    session.generate_data_secret(&th).unwrap();
+    session.set_session_state(SpdmSessionState::SpdmSessionEstablished);
+}
+
+/// The requester side of a session established with
+/// `establish_test_session()`.
+pub fn new_peer_session(session_id: u32) -> SpdmSession {
+    let mut session = SpdmSession::new();
+    session.setup(session_id).unwrap();
+    establish_test_session(&mut session);
+    session
+}
+
+/// The next secured message received on `device_io`, decoded with the
+/// requester side `peer_session`.
+pub fn receive_secured(
+    device_io: &mut dyn SpdmDeviceIo,
+    peer_session: &mut SpdmSession,
+) -> SpdmResult<Vec<u8>> {
+    let mut receive_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+    let used = device_io
+        .receive(&mut receive_buffer)
+        .map_err(|_| spdm_err!(ENODEV, "nothing received"))?;
+    let mut app_buffer = [0u8; config::MAX_SPDM_MESSAGE_BUFFER_SIZE];
+    let used = peer_session.decode_spdm_secured_message(
+        &receive_buffer[..used],
+        &mut app_buffer,
+        false,
+    )?;
+    Ok(app_buffer[..used].to_vec())
+}
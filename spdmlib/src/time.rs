@@ -0,0 +1,85 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+#![forbid(unsafe_code)]
+
+use conquer_once::spin::OnceCell;
+
+/// Timer the requester waits with before retrying a request,
+/// e.g. for ERROR(ResponseNotReady), and the clock the session heartbeat
+/// is checked with.
+#[derive(Clone, Copy)]
+pub struct SpdmTime {
+    pub sleep_cb: fn(us: usize),
+    /// Monotonic time in microseconds, from any start. The session
+    /// lifetimes, heartbeats and request rates are measured with it, so it
+    /// must not follow wall clock adjustments.
+    pub get_time_cb: fn() -> u64,
+    /// Wall clock in seconds since the UNIX epoch the certificate validity
+    /// is checked against. `None` if the device has no clock.
+    pub get_unix_time_cb: fn() -> Option<u64>,
+}
+
+static TIME: OnceCell<SpdmTime> = OnceCell::uninit();
+
+// the monotonic time counts from the first use of the clock
+#[cfg(feature = "std")]
+static START: OnceCell<std::time::Instant> = OnceCell::uninit();
+
+#[cfg(feature = "std")]
+static DEFAULT: SpdmTime = SpdmTime {
+    sleep_cb: |us: usize| std::thread::sleep(std::time::Duration::from_micros(us as u64)),
+    get_time_cb: || match START.try_get_or_init(std::time::Instant::now) {
+        Ok(start) => start.elapsed().as_micros() as u64,
+        Err(_) => 0,
+    },
+    get_unix_time_cb: || {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .ok()
+    },
+};
+
+// no timer without std, the request is retried at once and the session
+// heartbeat never lapses, unless a monotonic clock is registered. Cert
+// chains can't be verified without a wall clock.
+#[cfg(not(feature = "std"))]
+static DEFAULT: SpdmTime = SpdmTime {
+    sleep_cb: |_us: usize| {},
+    get_time_cb: || 0,
+    get_unix_time_cb: || None,
+};
+
+pub fn register(context: SpdmTime) -> bool {
+    TIME.try_init_once(|| context).is_ok()
+}
+
+pub fn sleep(us: usize) {
+    if let Ok(time) = TIME.try_get_or_init(|| DEFAULT) {
+        (time.sleep_cb)(us)
+    }
+}
+
+pub fn get_time() -> u64 {
+    match TIME.try_get_or_init(|| DEFAULT) {
+        Ok(time) => (time.get_time_cb)(),
+        Err(_) => 0,
+    }
+}
+
+pub fn get_unix_time() -> Option<u64> {
+    (TIME.try_get_or_init(|| DEFAULT).ok()?.get_unix_time_cb)()
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_monotonic_time() {
+    let start = get_time();
+    sleep(1000);
+    let elapsed = get_time() - start;
+    // from the process start rather than the UNIX epoch
+    assert!((1000..60_000_000).contains(&elapsed));
+    assert!(get_time() < get_unix_time().unwrap() * 1_000_000);
+}
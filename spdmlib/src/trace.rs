@@ -0,0 +1,91 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+#![forbid(unsafe_code)]
+
+//! Sink of the library logs and hook on the SPDM messages, e.g. to capture
+//! the traffic of a device without patching the crate.
+//!
+//! Until a sink is registered the logs go to the `log` crate.
+
+use conquer_once::spin::OnceCell;
+use core::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SpdmLogLevel {
+    Error,
+    Info,
+    Debug,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpdmMessageDirection {
+    Send,
+    Receive,
+}
+
+#[derive(Clone, Copy)]
+pub struct SpdmTrace {
+    pub log_cb: fn(level: SpdmLogLevel, args: fmt::Arguments),
+    /// Every SPDM message sent or received, before it is encrypted or after
+    /// it is decrypted in a session. Chunks and transport headers are not
+    /// included.
+    #[allow(clippy::type_complexity)]
+    pub on_message_cb:
+        Option<fn(direction: SpdmMessageDirection, session_id: Option<u32>, bytes: &[u8])>,
+}
+
+/// Attached to a context to get every transport message it sends or
+/// receives, fragments reassembled, e.g. to write a capture file.
+pub trait SpdmTraceRecorder {
+    fn record(&mut self, direction: SpdmMessageDirection, transport_message: &[u8]);
+}
+
+static TRACE: OnceCell<SpdmTrace> = OnceCell::uninit();
+
+static DEFAULT: SpdmTrace = SpdmTrace {
+    log_cb: |level: SpdmLogLevel, args: fmt::Arguments| match level {
+        SpdmLogLevel::Error => log::error!("{}", args),
+        SpdmLogLevel::Info => log::info!("{}", args),
+        SpdmLogLevel::Debug => log::debug!("{}", args),
+    },
+    on_message_cb: None,
+};
+
+/// Only one sink can be registered, the logs before it go to the `log` crate.
+pub fn register(context: SpdmTrace) -> bool {
+    TRACE.try_init_once(|| context).is_ok()
+}
+
+fn get_trace() -> &'static SpdmTrace {
+    TRACE.get().unwrap_or(&DEFAULT)
+}
+
+pub fn log(level: SpdmLogLevel, args: fmt::Arguments) {
+    (get_trace().log_cb)(level, args)
+}
+
+pub fn on_message(direction: SpdmMessageDirection, session_id: Option<u32>, bytes: &[u8]) {
+    if let Some(on_message_cb) = get_trace().on_message_cb {
+        on_message_cb(direction, session_id, bytes)
+    }
+}
+
+macro_rules! error {
+    ($($arg:tt)+) => {
+        $crate::trace::log($crate::trace::SpdmLogLevel::Error, format_args!($($arg)+))
+    };
+}
+
+macro_rules! info {
+    ($($arg:tt)+) => {
+        $crate::trace::log($crate::trace::SpdmLogLevel::Info, format_args!($($arg)+))
+    };
+}
+
+macro_rules! debug {
+    ($($arg:tt)+) => {
+        $crate::trace::log($crate::trace::SpdmLogLevel::Debug, format_args!($($arg)+))
+    };
+}
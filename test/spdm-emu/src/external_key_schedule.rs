@@ -0,0 +1,277 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+// Software stand-in for an HSM that retains the session secrets.
+
+use std::cell::RefCell;
+
+use spdmlib::crypto::{aead, hmac, SpdmExternalKeySchedule, SpdmKeyHandle, SpdmSessionKeyId};
+use spdmlib::error::SpdmResult;
+use spdmlib::key_schedule::SpdmKeySchedule;
+use spdmlib::msgs::{
+    SpdmAeadAlgo, SpdmAeadIvStruct, SpdmAeadKeyStruct, SpdmBaseHashAlgo, SpdmDigestStruct,
+    SpdmVersion,
+};
+use spdmlib::spdm_err;
+
+pub static EXTERNAL_KEY_SCHEDULE_IMPL: SpdmExternalKeySchedule = SpdmExternalKeySchedule {
+    import_shared_secret_cb: import_shared_secret,
+    derive_handshake_keys_cb: derive_handshake_keys,
+    derive_data_keys_cb: derive_data_keys,
+    update_data_key_cb: update_data_key,
+    activate_data_key_update_cb: activate_data_key_update,
+    hmac_cb: hmac_with_key,
+    encrypt_cb: encrypt,
+    decrypt_cb: decrypt,
+    release_cb: release,
+};
+
+const SESSION_KEY_ID_COUNT: usize = 8;
+
+#[derive(Clone, Copy, Default)]
+struct KeyEntry {
+    secret: SpdmDigestStruct,
+    key: SpdmAeadKeyStruct,
+    iv: SpdmAeadIvStruct,
+}
+
+#[derive(Clone, Copy)]
+struct SessionKeys {
+    key_schedule: SpdmKeySchedule,
+    hash_algo: SpdmBaseHashAlgo,
+    aead_algo: SpdmAeadAlgo,
+    handshake_secret: SpdmDigestStruct,
+    master_secret: SpdmDigestStruct,
+    keys: [KeyEntry; SESSION_KEY_ID_COUNT],
+}
+
+thread_local! {
+    // handle - 1 is the index
+    static KEY_STORE: RefCell<Vec<Option<SessionKeys>>> = RefCell::new(Vec::new());
+}
+
+fn with_session_keys<T>(
+    handle: SpdmKeyHandle,
+    f: impl FnOnce(&mut SessionKeys) -> SpdmResult<T>,
+) -> SpdmResult<T> {
+    KEY_STORE.with(|key_store| {
+        let mut key_store = key_store.borrow_mut();
+        match key_store.get_mut((handle as usize).wrapping_sub(1)) {
+            Some(Some(session_keys)) => f(session_keys),
+            _ => Err(spdm_err!(EINVAL)),
+        }
+    })
+}
+
+fn import_shared_secret(
+    spdm_version: SpdmVersion,
+    base_hash_algo: SpdmBaseHashAlgo,
+    aead_algo: SpdmAeadAlgo,
+    shared_secret: &[u8],
+) -> Option<SpdmKeyHandle> {
+    let mut key_schedule = SpdmKeySchedule::new();
+    key_schedule.set_spdm_version(spdm_version);
+    let handshake_secret = key_schedule.derive_handshake_secret(base_hash_algo, shared_secret)?;
+    let master_secret =
+        key_schedule.derive_master_secret(base_hash_algo, handshake_secret.as_ref())?;
+    let session_keys = SessionKeys {
+        key_schedule,
+        hash_algo: base_hash_algo,
+        aead_algo,
+        handshake_secret,
+        master_secret,
+        keys: [KeyEntry::default(); SESSION_KEY_ID_COUNT],
+    };
+
+    KEY_STORE.with(|key_store| {
+        let mut key_store = key_store.borrow_mut();
+        let index = match key_store.iter().position(|s| s.is_none()) {
+            Some(index) => index,
+            None => {
+                key_store.push(None);
+                key_store.len() - 1
+            }
+        };
+        key_store[index] = Some(session_keys);
+        Some(index as SpdmKeyHandle + 1)
+    })
+}
+
+fn new_key_entry(session_keys: &SessionKeys, secret: SpdmDigestStruct) -> SpdmResult<KeyEntry> {
+    let (key, iv) = session_keys
+        .key_schedule
+        .derive_aead_key_iv(session_keys.hash_algo, session_keys.aead_algo, secret.as_ref())
+        .ok_or(spdm_err!(EFAULT))?;
+    Ok(KeyEntry { secret, key, iv })
+}
+
+fn derive_handshake_keys(handle: SpdmKeyHandle, th1: &[u8]) -> SpdmResult {
+    with_session_keys(handle, |session_keys| {
+        let key_schedule = session_keys.key_schedule;
+        let hash_algo = session_keys.hash_algo;
+        let handshake_secret = session_keys.handshake_secret;
+        let request_secret = key_schedule
+            .derive_request_handshake_secret(hash_algo, handshake_secret.as_ref(), th1)
+            .ok_or(spdm_err!(EFAULT))?;
+        let response_secret = key_schedule
+            .derive_response_handshake_secret(hash_algo, handshake_secret.as_ref(), th1)
+            .ok_or(spdm_err!(EFAULT))?;
+        let request_finished_key = key_schedule
+            .derive_finished_key(hash_algo, request_secret.as_ref())
+            .ok_or(spdm_err!(EFAULT))?;
+        let response_finished_key = key_schedule
+            .derive_finished_key(hash_algo, response_secret.as_ref())
+            .ok_or(spdm_err!(EFAULT))?;
+
+        session_keys.keys[SpdmSessionKeyId::RequestHandshake as usize] =
+            new_key_entry(session_keys, request_secret)?;
+        session_keys.keys[SpdmSessionKeyId::ResponseHandshake as usize] =
+            new_key_entry(session_keys, response_secret)?;
+        session_keys.keys[SpdmSessionKeyId::RequestFinished as usize] = KeyEntry {
+            secret: request_finished_key,
+            ..Default::default()
+        };
+        session_keys.keys[SpdmSessionKeyId::ResponseFinished as usize] = KeyEntry {
+            secret: response_finished_key,
+            ..Default::default()
+        };
+        Ok(())
+    })
+}
+
+fn derive_data_keys(handle: SpdmKeyHandle, th2: &[u8]) -> SpdmResult {
+    with_session_keys(handle, |session_keys| {
+        let key_schedule = session_keys.key_schedule;
+        let hash_algo = session_keys.hash_algo;
+        let master_secret = session_keys.master_secret;
+        let request_secret = key_schedule
+            .derive_request_data_secret(hash_algo, master_secret.as_ref(), th2)
+            .ok_or(spdm_err!(EFAULT))?;
+        let response_secret = key_schedule
+            .derive_response_data_secret(hash_algo, master_secret.as_ref(), th2)
+            .ok_or(spdm_err!(EFAULT))?;
+
+        session_keys.keys[SpdmSessionKeyId::RequestData as usize] =
+            new_key_entry(session_keys, request_secret)?;
+        session_keys.keys[SpdmSessionKeyId::ResponseData as usize] =
+            new_key_entry(session_keys, response_secret)?;
+        Ok(())
+    })
+}
+
+fn get_previous_key_id(key_id: SpdmSessionKeyId) -> SpdmResult<SpdmSessionKeyId> {
+    match key_id {
+        SpdmSessionKeyId::RequestData => Ok(SpdmSessionKeyId::PreviousRequestData),
+        SpdmSessionKeyId::ResponseData => Ok(SpdmSessionKeyId::PreviousResponseData),
+        _ => Err(spdm_err!(EINVAL)),
+    }
+}
+
+fn update_data_key(handle: SpdmKeyHandle, key_id: SpdmSessionKeyId) -> SpdmResult {
+    let previous_key_id = get_previous_key_id(key_id)?;
+    with_session_keys(handle, |session_keys| {
+        let current = session_keys.keys[key_id as usize];
+        let secret = session_keys
+            .key_schedule
+            .derive_update_secret(session_keys.hash_algo, current.secret.as_ref())
+            .ok_or(spdm_err!(EFAULT))?;
+        session_keys.keys[key_id as usize] = new_key_entry(session_keys, secret)?;
+        session_keys.keys[previous_key_id as usize] = current;
+        Ok(())
+    })
+}
+
+fn activate_data_key_update(
+    handle: SpdmKeyHandle,
+    key_id: SpdmSessionKeyId,
+    use_new_key: bool,
+) -> SpdmResult {
+    let previous_key_id = get_previous_key_id(key_id)?;
+    with_session_keys(handle, |session_keys| {
+        if !use_new_key {
+            session_keys.keys[key_id as usize] = session_keys.keys[previous_key_id as usize];
+        }
+        session_keys.keys[previous_key_id as usize] = KeyEntry::default();
+        Ok(())
+    })
+}
+
+fn hmac_with_key(
+    handle: SpdmKeyHandle,
+    key_id: SpdmSessionKeyId,
+    data: &[u8],
+) -> Option<SpdmDigestStruct> {
+    with_session_keys(handle, |session_keys| {
+        let key = session_keys.keys[key_id as usize].secret;
+        hmac::hmac(session_keys.hash_algo, key.as_ref(), data).ok_or(spdm_err!(EFAULT))
+    })
+    .ok()
+}
+
+fn get_iv(key_entry: &KeyEntry, aead_algo: SpdmAeadAlgo, sequence_number: u64) -> Vec<u8> {
+    let mut iv = key_entry.iv.data[..(aead_algo.get_iv_size() as usize)].to_vec();
+    for (i, b) in sequence_number.to_le_bytes().iter().enumerate() {
+        iv[i] ^= b;
+    }
+    iv
+}
+
+fn encrypt(
+    handle: SpdmKeyHandle,
+    key_id: SpdmSessionKeyId,
+    sequence_number: u64,
+    aad: &[u8],
+    plain_text: &[u8],
+    tag: &mut [u8],
+    cipher_text: &mut [u8],
+) -> SpdmResult<(usize, usize)> {
+    with_session_keys(handle, |session_keys| {
+        let aead_algo = session_keys.aead_algo;
+        let key_entry = &session_keys.keys[key_id as usize];
+        aead::encrypt(
+            aead_algo,
+            &key_entry.key.data[..(aead_algo.get_key_size() as usize)],
+            &get_iv(key_entry, aead_algo, sequence_number),
+            aad,
+            plain_text,
+            tag,
+            cipher_text,
+        )
+    })
+}
+
+fn decrypt(
+    handle: SpdmKeyHandle,
+    key_id: SpdmSessionKeyId,
+    sequence_number: u64,
+    aad: &[u8],
+    cipher_text: &[u8],
+    tag: &[u8],
+    plain_text: &mut [u8],
+) -> SpdmResult<usize> {
+    with_session_keys(handle, |session_keys| {
+        let aead_algo = session_keys.aead_algo;
+        let key_entry = &session_keys.keys[key_id as usize];
+        aead::decrypt(
+            aead_algo,
+            &key_entry.key.data[..(aead_algo.get_key_size() as usize)],
+            &get_iv(key_entry, aead_algo, sequence_number),
+            aad,
+            cipher_text,
+            tag,
+            plain_text,
+        )
+    })
+}
+
+fn release(handle: SpdmKeyHandle) {
+    KEY_STORE.with(|key_store| {
+        if let Some(session_keys) = key_store
+            .borrow_mut()
+            .get_mut((handle as usize).wrapping_sub(1))
+        {
+            *session_keys = None;
+        }
+    })
+}
@@ -8,3 +8,4 @@ pub mod spdm_emu;
 pub mod tcp_transport;
 pub mod socket_io_transport;
 pub mod crypto_callback;
+pub mod external_key_schedule;
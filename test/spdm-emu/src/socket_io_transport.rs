@@ -1,63 +1,82 @@
-// Copyright (c) 2020 Intel Corporation
-//
-// SPDX-License-Identifier: BSD-2-Clause-Patent
-
-#![forbid(unsafe_code)]
-
-use crate::spdm_emu::*;
-use std::net::TcpStream;
-
-use spdmlib::common::{SpdmDeviceIo};
-use spdmlib::config;
-use spdmlib::error::SpdmResult;
-
-pub struct SocketIoTransport<'a> {
-    pub data: &'a mut TcpStream,
-    transport_type: u32,
-}
-impl<'a> SocketIoTransport<'a> {
-    pub fn new(stream: &'a mut TcpStream) -> Self {
-        SocketIoTransport {
-            data: stream,
-            transport_type: if USE_PCIDOE {
-                SOCKET_TRANSPORT_TYPE_PCI_DOE
-            } else {
-                SOCKET_TRANSPORT_TYPE_MCTP
-            },
-        }
-    }
-}
-
-
-impl SpdmDeviceIo for SocketIoTransport<'_> {
-    fn receive(&mut self, read_buffer: &mut [u8]) -> Result<usize, usize> {
-        let mut buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
-
-        if let Some((_, command, payload)) = receive_message(self.data, &mut buffer[..]) {
-            // TBD: do we need this?
-            // self.transport_type = transport_type;
-            let used = payload.len();
-            let total = used + SOCKET_HEADER_LEN;
-            if command == SOCKET_SPDM_COMMAND_NORMAL {
-                read_buffer[..used].copy_from_slice(payload);
-                Ok(used)
-            } else {
-                // this commmand need caller to deal.
-                read_buffer[..total].copy_from_slice(&buffer[..total]);
-                Err(total)
-            }
-        } else {
-            // socket header can't be received.
-            Err(0)
-        }
-    }
-
-    fn send(&mut self, buffer: &[u8]) -> SpdmResult {
-        send_message(self.data, self.transport_type, SOCKET_SPDM_COMMAND_NORMAL, buffer);
-        Ok(())
-    }
-
-    fn flush_all(&mut self) -> SpdmResult {
-        Ok(())
-    }
-}
+// Copyright (c) 2020 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+#![forbid(unsafe_code)]
+
+use crate::spdm_emu::*;
+use std::net::TcpStream;
+use std::time::Duration;
+
+use spdmlib::common::{SpdmDeviceIo};
+use spdmlib::config;
+use spdmlib::error::SpdmResult;
+
+pub struct SocketIoTransport<'a> {
+    pub data: &'a mut TcpStream,
+    transport_type: u32,
+}
+impl<'a> SocketIoTransport<'a> {
+    pub fn new(stream: &'a mut TcpStream) -> Self {
+        SocketIoTransport {
+            data: stream,
+            transport_type: if USE_PCIDOE {
+                SOCKET_TRANSPORT_TYPE_PCI_DOE
+            } else {
+                SOCKET_TRANSPORT_TYPE_MCTP
+            },
+        }
+    }
+}
+
+
+impl SpdmDeviceIo for SocketIoTransport<'_> {
+    fn receive(&mut self, read_buffer: &mut [u8]) -> Result<usize, usize> {
+        let mut buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+
+        if let Some((_, command, payload)) = receive_message(self.data, &mut buffer[..]) {
+            // TBD: do we need this?
+            // self.transport_type = transport_type;
+            let used = payload.len();
+            let total = used + SOCKET_HEADER_LEN;
+            if command == SOCKET_SPDM_COMMAND_NORMAL {
+                read_buffer[..used].copy_from_slice(payload);
+                Ok(used)
+            } else {
+                // this commmand need caller to deal.
+                read_buffer[..total].copy_from_slice(&buffer[..total]);
+                Err(total)
+            }
+        } else {
+            // socket header can't be received.
+            Err(0)
+        }
+    }
+
+    fn receive_with_timeout(
+        &mut self,
+        read_buffer: &mut [u8],
+        timeout_us: usize,
+    ) -> Result<usize, usize> {
+        // wait for the socket header only, the rest is received blocking
+        let timeout = Duration::from_micros(timeout_us.max(1) as u64);
+        if self.data.set_read_timeout(Some(timeout)).is_err() {
+            return Err(0);
+        }
+        let mut header = [0u8; 1];
+        let ready = matches!(self.data.peek(&mut header), Ok(size) if size != 0);
+        if self.data.set_read_timeout(None).is_err() || !ready {
+            return Err(0);
+        }
+        self.receive(read_buffer)
+    }
+
+    fn send(&mut self, buffer: &[u8]) -> SpdmResult {
+        send_message(self.data, self.transport_type, SOCKET_SPDM_COMMAND_NORMAL, buffer);
+        Ok(())
+    }
+
+    fn flush_all(&mut self) -> SpdmResult {
+        Ok(())
+    }
+}
@@ -15,6 +15,9 @@ pub const USE_PCIDOE : bool = true;
 pub const USE_ECDSA : bool = true;
 pub const USE_ECDH : bool = true;
 
+pub const TEST_PSK_HINT: &[u8] = b"TestPskHint\0";
+pub const TEST_PSK_DATA: &[u8] = b"TestPskData\0";
+
 pub const SOCKET_TRANSPORT_TYPE_MCTP: u32 = 0x01;
 pub const SOCKET_TRANSPORT_TYPE_PCI_DOE: u32 = 0x02;
 
@@ -58,7 +61,9 @@ pub fn receive_message<'a>(
     let mut buffer_size = 0;
     let mut expected_size = 0;
     loop {
-        let s = stream.read(&mut buffer[buffer_size..]).expect("socket read error!");
+        // don't read beyond this message, the next one may be queued already
+        let read_limit = if expected_size == 0 { SOCKET_HEADER_LEN } else { expected_size };
+        let s = stream.read(&mut buffer[buffer_size..read_limit]).expect("socket read error!");
         buffer_size += s;
         // println!("received: {:?}", s);
         if (expected_size == 0) && (buffer_size >= SOCKET_HEADER_LEN) {
@@ -12,15 +12,34 @@ use simple_logger::SimpleLogger;
 
 use spdmlib::common;
 use spdmlib::config;
+use spdmlib::error::SpdmResult;
 use spdmlib::msgs::*;
 use spdmlib::requester;
 
 use mctp_transport::MctpTransportEncap;
 use pcidoe_transport::PciDoeTransportEncap;
+use spdm_emu::crypto_callback::KEY_STORE_REQUESTER_IMPL;
 use spdm_emu::socket_io_transport::SocketIoTransport;
 use spdm_emu::spdm_emu::*;
+use std::convert::TryFrom;
 use std::net::TcpStream;
 
+// stands in for a TPM PCR extend
+fn log_transcript_segment(segment: common::SpdmTranscriptSegment, data: &[u8]) {
+    info!("transcript {:?} : {} bytes\n", segment, data.len());
+}
+
+// e.g. check the EKU of the responder leaf certificate
+fn verify_cert_chain_policy(slot_id: u8, cert_chain: &[u8], leaf_cert: &[u8]) -> SpdmResult {
+    info!(
+        "cert chain policy : slot {} chain {} bytes leaf {} bytes\n",
+        slot_id,
+        cert_chain.len(),
+        leaf_cert.len()
+    );
+    Ok(())
+}
+
 fn send_receive_hello(
     stream: &mut TcpStream,
     transport_encap: &mut dyn common::SpdmTransportEncap,
@@ -71,19 +90,27 @@ fn test_spdm(
     transport_encap: &mut dyn SpdmTransportEncap,
 ) {
     let config_info = common::SpdmConfigInfo {
-        spdm_version: [SpdmVersion::SpdmVersion10, SpdmVersion::SpdmVersion11],
+        spdm_version: [
+            SpdmVersion::SpdmVersion10,
+            SpdmVersion::SpdmVersion11,
+            SpdmVersion::SpdmVersion12,
+        ],
         req_capabilities: SpdmRequestCapabilityFlags::CERT_CAP
         | SpdmRequestCapabilityFlags::CHAL_CAP
         | SpdmRequestCapabilityFlags::ENCRYPT_CAP
         | SpdmRequestCapabilityFlags::MAC_CAP
-        //| SpdmRequestCapabilityFlags::MUT_AUTH_CAP
+        | SpdmRequestCapabilityFlags::MUT_AUTH_CAP
         | SpdmRequestCapabilityFlags::KEY_EX_CAP
         | SpdmRequestCapabilityFlags::PSK_CAP
         | SpdmRequestCapabilityFlags::ENCAP_CAP
         | SpdmRequestCapabilityFlags::HBEAT_CAP
-        | SpdmRequestCapabilityFlags::KEY_UPD_CAP, // | SpdmRequestCapabilityFlags::HANDSHAKE_IN_THE_CLEAR_CAP
+        | SpdmRequestCapabilityFlags::KEY_UPD_CAP
+        | SpdmRequestCapabilityFlags::CHUNK_CAP, // | SpdmRequestCapabilityFlags::HANDSHAKE_IN_THE_CLEAR_CAP
         // | SpdmRequestCapabilityFlags::PUB_KEY_ID_CAP
         req_ct_exponent: 0,
+        data_transfer_size: config::MAX_SPDM_TRANSPORT_SIZE as u32,
+        max_spdm_msg_size: config::MAX_SPDM_MESSAGE_BUFFER_SIZE as u32,
+        opaque_support: SpdmOpaqueSupport::OPAQUE_DATA_FMT1,
         measurement_specification: SpdmMeasurementSpecification::DMTF,
         base_asym_algo: if USE_ECDSA {
             SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P384
@@ -97,8 +124,22 @@ fn test_spdm(
             SpdmDheAlgo::FFDHE_3072
         },
         aead_algo: SpdmAeadAlgo::AES_256_GCM,
-        req_asym_algo: SpdmReqAsymAlgo::TPM_ALG_RSAPSS_2048,
+        req_asym_algo: if USE_ECDSA {
+            SpdmReqAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P384
+        } else {
+            SpdmReqAsymAlgo::TPM_ALG_RSASSA_3072
+        },
         key_schedule_algo: SpdmKeyScheduleAlgo::SPDM_KEY_SCHEDULE,
+        receive_timeout_us: 10_000_000,
+        receive_retry_count: 1,
+        busy_retry_count: 3,
+        busy_retry_interval_us: 100_000,
+        psk: SpdmDheFinalKeyStruct::try_from(TEST_PSK_DATA).unwrap(),
+        psk_hint: SpdmPskHintStruct::try_from(TEST_PSK_HINT).unwrap(),
+        // the validity of the peer cert chain is checked with the std clock,
+        // a no_std build fails it with "No clock" unless it registers an
+        // SpdmTime or sets get_unix_time_cb here
+        cert_chain_config: spdmlib::crypto::SpdmCertChainConfig::new(),
         ..Default::default()
     };
 
@@ -141,19 +182,56 @@ fn test_spdm(
     peer_cert_chain_data.data[(ca_len + inter_len)..(ca_len + inter_len + leaf_len)]
         .copy_from_slice(leaf_cert.as_ref());
 
+    let mut my_cert_chain_data = SpdmCertChainData {
+        ..Default::default()
+    };
+
+    let bundle_file_path = if USE_ECDSA {
+        "TestKey/EcP384/bundle_requester.certchain.der"
+    } else {
+        "TestKey/Rsa3072/bundle_requester.certchain.der"
+    };
+    let bundle = std::fs::read(bundle_file_path).expect("unable to read requester cert chain!");
+    my_cert_chain_data.data_size = bundle.len() as u16;
+    my_cert_chain_data.data[0..bundle.len()].copy_from_slice(bundle.as_ref());
+
+    let root_cert: &'static [u8] = Box::leak(ca_cert.into_boxed_slice());
+    let root_cert_store: &'static [&'static [u8]] = Box::leak(vec![root_cert].into_boxed_slice());
+
     let provision_info = common::SpdmProvisionInfo {
-        my_cert_chain_data: None,
-        my_cert_chain: None,
+        my_cert_chain_data: [
+            Some(my_cert_chain_data),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        ],
+        my_root_cert: None,
         peer_cert_chain_data: Some(peer_cert_chain_data),
         peer_cert_chain_root_hash: None,
+        peer_root_cert_store: Some(root_cert_store),
+        cert_verification_policy: Some(common::SpdmCertVerificationPolicy {
+            verify_cb: verify_cert_chain_policy,
+        }),
+        signing_slot_ids: common::SpdmSigningSlotIds::default(),
     };
 
+    spdmlib::crypto::asym_sign::register_key_store(KEY_STORE_REQUESTER_IMPL);
+
     let mut context = requester::RequesterContext::new(
         socket_io_transport,
         transport_encap,
         config_info,
         provision_info,
     );
+    context
+        .common
+        .set_transcript_sink(common::SpdmTranscriptSink {
+            segment_cb: log_transcript_segment,
+        });
 
     if context.init_connection().is_err() {
         return;
@@ -166,6 +244,10 @@ fn test_spdm(
     if context.send_receive_spdm_certificate(0).is_err() {
         return;
     }
+    match context.get_peer_leaf_cert(0) {
+        Ok(leaf_cert) => info!("responder leaf cert : {} bytes\n", leaf_cert.len()),
+        Err(_) => return,
+    }
 
     if context
         .send_receive_spdm_challenge(
@@ -177,6 +259,13 @@ fn test_spdm(
         return;
     }
 
+    if context
+        .verify_transcript_coverage(common::SpdmTranscriptCoverage::all())
+        .is_err()
+    {
+        return;
+    }
+
     if context
         .send_receive_spdm_measurement(SpdmMeasurementOperation::SpdmMeasurementQueryTotalNumber, 0)
         .is_err()
@@ -184,6 +273,29 @@ fn test_spdm(
         return;
     }
 
+    match context.send_receive_spdm_all_measurements(0) {
+        Ok(record) => info!("{} signed measurement blocks\n", record.number_of_blocks),
+        Err(_) => return,
+    }
+
+    let references = [requester::SpdmMeasurementReference {
+        index: 1,
+        expected: requester::SpdmMeasurementExpectation::Any,
+        must_be_signed: true,
+    }];
+    let manifest = requester::SpdmMeasurementManifest {
+        references: &references,
+        allow_unlisted: true,
+    };
+    match context.verify_measurements(&manifest) {
+        Ok(report) if report.compliant => info!("measurements compliant\n"),
+        Ok(report) => {
+            info!("measurements not compliant {:?}\n", report.entries());
+            return;
+        }
+        Err(_) => return,
+    }
+
     let result = context.start_session(
         false,
         0,
@@ -194,6 +306,10 @@ fn test_spdm(
         info!("Key Information ...\n");
 
         let session = context.common.get_session_via_id(session_id).unwrap();
+        info!(
+            "responder opaque data {:02x?}\n",
+            session.get_peer_opaque_data()
+        );
         let (request_direction, response_direction) = session.export_keys();
         info!(
             "equest_direction.encryption_key {:0x?}\n",
@@ -216,6 +332,24 @@ fn test_spdm(
             return;
         }
 
+        if context
+            .send_receive_spdm_measurement_in_session(
+                session_id,
+                SpdmMeasurementOperation::SpdmMeasurementRequestAll,
+                0,
+            )
+            .is_err()
+        {
+            return;
+        }
+
+        if context
+            .send_receive_spdm_encapsulated_request(session_id)
+            .is_err()
+        {
+            return;
+        }
+
         if context
             .send_receive_spdm_key_update(session_id, SpdmKeyUpdateOperation::SpdmUpdateAllKeys)
             .is_err()
@@ -223,6 +357,50 @@ fn test_spdm(
             return;
         }
 
+        if context
+            .send_receive_spdm_key_update(session_id, SpdmKeyUpdateOperation::SpdmVerifyNewKey)
+            .is_err()
+        {
+            return;
+        }
+
+        // a second session next to the first one, with its own keys
+        let second_session_id = match context.start_session(
+            false,
+            0,
+            SpdmMeasurementSummaryHashType::SpdmMeasurementSummaryHashTypeNone,
+        ) {
+            Ok(second_session_id) => second_session_id,
+            Err(_) => return,
+        };
+        info!(
+            "second session {:08x} next to {:08x}\n",
+            second_session_id, session_id
+        );
+        if context
+            .send_receive_spdm_key_update(
+                second_session_id,
+                SpdmKeyUpdateOperation::SpdmUpdateAllKeys,
+            )
+            .is_err()
+            || context
+                .send_receive_spdm_key_update(
+                    second_session_id,
+                    SpdmKeyUpdateOperation::SpdmVerifyNewKey,
+                )
+                .is_err()
+            || context
+                .send_receive_spdm_measurement_in_session(
+                    session_id,
+                    SpdmMeasurementOperation::SpdmMeasurementRequestAll,
+                    0,
+                )
+                .is_err()
+            || context.end_session(second_session_id).is_err()
+        {
+            return;
+        }
+
         if context.end_session(session_id).is_err() {
             return;
         }
@@ -273,7 +451,7 @@ fn main() {
         TcpStream::connect("127.0.0.1:2323").expect("Couldn't connect to the server...");
 
     let pcidoe_transport_encap = &mut PciDoeTransportEncap {};
-    let mctp_transport_encap = &mut MctpTransportEncap {};
+    let mctp_transport_encap = &mut MctpTransportEncap::default();
 
     let transport_encap: &mut dyn SpdmTransportEncap = if USE_PCIDOE {
         pcidoe_transport_encap
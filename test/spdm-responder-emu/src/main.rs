@@ -13,11 +13,12 @@ use codec::{Codec, Reader};
 use common::SpdmTransportEncap;
 use mctp_transport::MctpTransportEncap;
 use pcidoe_transport::PciDoeTransportEncap;
-use spdm_emu::crypto_callback::ASYM_SIGN_IMPL;
+use spdm_emu::crypto_callback::{KEY_STORE_IMPL, PSK_IMPL};
+use spdm_emu::external_key_schedule::EXTERNAL_KEY_SCHEDULE_IMPL;
 use spdm_emu::socket_io_transport::SocketIoTransport;
 use spdm_emu::spdm_emu::*;
 use spdmlib::msgs::*;
-use spdmlib::{common, responder};
+use spdmlib::{common, config, responder};
 
 fn process_socket_message(
     stream: &mut TcpStream,
@@ -77,7 +78,7 @@ fn main() {
     println!("server start!");
 
     let pcidoe_transport_encap = &mut PciDoeTransportEncap {};
-    let mctp_transport_encap = &mut MctpTransportEncap {};
+    let mctp_transport_encap = &mut MctpTransportEncap::default();
 
     for stream in listener.incoming() {
         let mut stream = stream.expect("Read stream error!");
@@ -124,21 +125,29 @@ fn handle_message(
     let mut socket_io_transport = SocketIoTransport::new(stream);
 
     let config_info = common::SpdmConfigInfo {
-        spdm_version: [SpdmVersion::SpdmVersion10, SpdmVersion::SpdmVersion11],
+        spdm_version: [
+            SpdmVersion::SpdmVersion10,
+            SpdmVersion::SpdmVersion11,
+            SpdmVersion::SpdmVersion12,
+        ],
         rsp_capabilities: SpdmResponseCapabilityFlags::CERT_CAP
         | SpdmResponseCapabilityFlags::CHAL_CAP
         | SpdmResponseCapabilityFlags::MEAS_CAP_SIG
         | SpdmResponseCapabilityFlags::MEAS_FRESH_CAP
         | SpdmResponseCapabilityFlags::ENCRYPT_CAP
         | SpdmResponseCapabilityFlags::MAC_CAP
-        //| SpdmResponseCapabilityFlags::MUT_AUTH_CAP
+        | SpdmResponseCapabilityFlags::MUT_AUTH_CAP
         | SpdmResponseCapabilityFlags::KEY_EX_CAP
         | SpdmResponseCapabilityFlags::PSK_CAP_WITH_CONTEXT
         | SpdmResponseCapabilityFlags::ENCAP_CAP
         | SpdmResponseCapabilityFlags::HBEAT_CAP
-        | SpdmResponseCapabilityFlags::KEY_UPD_CAP, // | SpdmResponseCapabilityFlags::HANDSHAKE_IN_THE_CLEAR_CAP
+        | SpdmResponseCapabilityFlags::KEY_UPD_CAP
+        | SpdmResponseCapabilityFlags::CHUNK_CAP, // | SpdmResponseCapabilityFlags::HANDSHAKE_IN_THE_CLEAR_CAP
         // | SpdmResponseCapabilityFlags::PUB_KEY_ID_CAP
         rsp_ct_exponent: 0,
+        data_transfer_size: config::MAX_SPDM_TRANSPORT_SIZE as u32,
+        max_spdm_msg_size: config::MAX_SPDM_MESSAGE_BUFFER_SIZE as u32,
+        opaque_support: SpdmOpaqueSupport::OPAQUE_DATA_FMT1,
         measurement_specification: SpdmMeasurementSpecification::DMTF,
         measurement_hash_algo: SpdmMeasurementHashAlgo::TPM_ALG_SHA_384,
         base_asym_algo: if USE_ECDSA {
@@ -153,8 +162,17 @@ fn handle_message(
             SpdmDheAlgo::FFDHE_3072
         },
         aead_algo: SpdmAeadAlgo::AES_256_GCM,
-        req_asym_algo: SpdmReqAsymAlgo::TPM_ALG_RSAPSS_2048,
+        req_asym_algo: if USE_ECDSA {
+            SpdmReqAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P384
+        } else {
+            SpdmReqAsymAlgo::TPM_ALG_RSASSA_3072
+        },
         key_schedule_algo: SpdmKeyScheduleAlgo::SPDM_KEY_SCHEDULE,
+        heartbeat_period: 10,
+        // the validity of the peer cert chain is checked with the std clock,
+        // a no_std build fails it with "No clock" unless it registers an
+        // SpdmTime or sets get_unix_time_cb here
+        cert_chain_config: spdmlib::crypto::SpdmCertChainConfig::new(),
         ..Default::default()
     };
 
@@ -197,14 +215,41 @@ fn handle_message(
     my_cert_chain_data.data[(ca_len + inter_len)..(ca_len + inter_len + leaf_len)]
         .copy_from_slice(leaf_cert.as_ref());
 
+    let mut peer_cert_chain_data = SpdmCertChainData {
+        ..Default::default()
+    };
+
+    let bundle_file_path = if USE_ECDSA {
+        "TestKey/EcP384/bundle_requester.certchain.der"
+    } else {
+        "TestKey/Rsa3072/bundle_requester.certchain.der"
+    };
+    let bundle = std::fs::read(bundle_file_path).expect("unable to read requester cert chain!");
+    peer_cert_chain_data.data_size = bundle.len() as u16;
+    peer_cert_chain_data.data[0..bundle.len()].copy_from_slice(bundle.as_ref());
+
     let provision_info = common::SpdmProvisionInfo {
-        my_cert_chain_data: Some(my_cert_chain_data),
-        my_cert_chain: None,
-        peer_cert_chain_data: None,
+        my_cert_chain_data: [
+            Some(my_cert_chain_data),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        ],
+        my_root_cert: None,
+        peer_cert_chain_data: Some(peer_cert_chain_data),
         peer_cert_chain_root_hash: None,
+        peer_root_cert_store: None,
+        cert_verification_policy: None,
+        signing_slot_ids: common::SpdmSigningSlotIds::default(),
     };
 
-    spdmlib::crypto::asym_sign::register(ASYM_SIGN_IMPL);
+    spdmlib::crypto::asym_sign::register_key_store(KEY_STORE_IMPL);
+    spdmlib::crypto::external_key_schedule::register(EXTERNAL_KEY_SCHEDULE_IMPL);
+    spdmlib::crypto::psk::register(PSK_IMPL);
 
     let mut context = responder::ResponderContext::new(
         &mut socket_io_transport,
@@ -219,7 +264,7 @@ fn handle_message(
         let res = context.process_message();
         match res {
             Ok(spdm_result) => {
-                if spdm_result {
+                if spdm_result.is_handled() {
                     continue;
                 } else {
                     // send unknown spdm command
@@ -0,0 +1,16 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+// cortex-m-rt's link.x, see .cargo/config, includes the memory.x found in the
+// linker search path
+fn main() {
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    fs::copy("memory.x", out_dir.join("memory.x")).unwrap();
+    println!("cargo:rustc-link-search={}", out_dir.display());
+    println!("cargo:rerun-if-changed=memory.x");
+}
@@ -0,0 +1,28 @@
+#!/bin/sh
+#
+# Copyright (c) 2021 Intel Corporation
+#
+# SPDX-License-Identifier: BSD-2-Clause-Patent
+#
+# Build the minimal no_std responder image for thumbv7em-none-eabihf and
+# fail if its text or bss is larger than the budget in budget.txt.
+
+set -e
+cd "$(dirname "$0")"
+
+TARGET=thumbv7em-none-eabihf
+cargo build --release --target "$TARGET" "$@"
+
+# Berkeley format: text data bss dec hex filename
+set -- $(${SIZE:-size} "target/$TARGET/release/spdm-size" | tail -n 1)
+text=$1
+bss=$3
+text_budget=$(awk '$1 == "text" { print $2 }' budget.txt)
+bss_budget=$(awk '$1 == "bss" { print $2 }' budget.txt)
+
+echo "text: $text of $text_budget bytes"
+echo "bss: $bss of $bss_budget bytes"
+if [ "$text" -gt "$text_budget" ] || [ "$bss" -gt "$bss_budget" ]; then
+    echo "size budget exceeded" >&2
+    exit 1
+fi
@@ -0,0 +1,7 @@
+/* A Cortex-M4 large enough to link the image, check_size.sh checks its
+   size against budget.txt. */
+MEMORY
+{
+  FLASH : ORIGIN = 0x08000000, LENGTH = 1024K
+  RAM : ORIGIN = 0x20000000, LENGTH = 256K
+}
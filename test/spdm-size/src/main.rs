@@ -0,0 +1,108 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+#![no_std]
+#![no_main]
+#![feature(alloc_error_handler)]
+
+//! Minimal no_std responder image over MCTP, whose code and data size
+//! check_size.sh compares with the budgets. Of the optional commands it only
+//! has the CHALLENGE and GET_MEASUREMENTS handlers, the responder-* features
+//! of the others are off. The crypto backend is left out, it is provided by
+//! the platform.
+
+use core::alloc::Layout;
+
+use cortex_m_rt::entry;
+use linked_list_allocator::LockedHeap;
+use mctp_transport::MctpTransportEncap;
+use panic_halt as _;
+use spdmlib::common::{SpdmConfigInfo, SpdmDeviceIo, SpdmProvisionInfo};
+use spdmlib::config;
+use spdmlib::error::SpdmResult;
+use spdmlib::msgs::*;
+use spdmlib::responder::ResponderContext;
+
+// for the hash contexts and the heap buffers of spdmlib
+const HEAP_SIZE: usize = 32 * 1024;
+
+#[global_allocator]
+static ALLOCATOR: LockedHeap = LockedHeap::empty();
+
+#[alloc_error_handler]
+fn alloc_error(_layout: Layout) -> ! {
+    panic!("out of heap")
+}
+
+// Stand for the mailbox of the platform. They are exported, the compiler
+// can't predict the requests in them.
+#[no_mangle]
+static mut MAILBOX: [u8; config::MAX_SPDM_TRANSPORT_SIZE] = [0; config::MAX_SPDM_TRANSPORT_SIZE];
+#[no_mangle]
+static mut MAILBOX_SIZE: usize = 0;
+
+// The image has a single thread, nothing else accesses the mailbox while
+// it is used.
+struct MailboxDeviceIo;
+
+impl SpdmDeviceIo for MailboxDeviceIo {
+    fn send(&mut self, buffer: &[u8]) -> SpdmResult {
+        unsafe {
+            let used = buffer.len().min(MAILBOX.len());
+            MAILBOX[..used].copy_from_slice(&buffer[..used]);
+            MAILBOX_SIZE = used;
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self, buffer: &mut [u8]) -> Result<usize, usize> {
+        unsafe {
+            let used = MAILBOX_SIZE.min(MAILBOX.len()).min(buffer.len());
+            buffer[..used].copy_from_slice(&MAILBOX[..used]);
+            Ok(used)
+        }
+    }
+
+    fn flush_all(&mut self) -> SpdmResult {
+        Ok(())
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    static mut HEAP: [u8; HEAP_SIZE] = [0; HEAP_SIZE];
+    // cortex-m-rt hands out the static of the entry function as &'static mut,
+    // the allocator owns it from here on
+    unsafe { ALLOCATOR.lock().init(HEAP.as_mut_ptr() as usize, HEAP_SIZE) };
+
+    let config_info = SpdmConfigInfo {
+        spdm_version: [
+            SpdmVersion::SpdmVersion10,
+            SpdmVersion::SpdmVersion11,
+            SpdmVersion::Unknown(0),
+        ],
+        rsp_capabilities: SpdmResponseCapabilityFlags::CERT_CAP
+            | SpdmResponseCapabilityFlags::CHAL_CAP
+            | SpdmResponseCapabilityFlags::MEAS_CAP_SIG,
+        data_transfer_size: config::MAX_SPDM_TRANSPORT_SIZE as u32,
+        max_spdm_msg_size: config::MAX_SPDM_MESSAGE_BUFFER_SIZE as u32,
+        measurement_specification: SpdmMeasurementSpecification::DMTF,
+        measurement_hash_algo: SpdmMeasurementHashAlgo::TPM_ALG_SHA_384,
+        base_asym_algo: SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P384,
+        base_hash_algo: SpdmBaseHashAlgo::TPM_ALG_SHA_384,
+        ..SpdmConfigInfo::default()
+    };
+
+    let mut device_io = MailboxDeviceIo;
+    let mut transport_encap = MctpTransportEncap::default();
+    let mut context = ResponderContext::new(
+        &mut device_io,
+        &mut transport_encap,
+        config_info,
+        SpdmProvisionInfo::default(),
+    );
+    loop {
+        let _ = context.process_message();
+    }
+}
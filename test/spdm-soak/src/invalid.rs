@@ -0,0 +1,107 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+#![forbid(unsafe_code)]
+
+// Requests a responder has to reject without changing its state, whatever
+// state the connection is in after NEGOTIATE_ALGORITHMS.
+
+use rand::Rng;
+
+// request codes with their minimum size
+const SIZED_REQUESTS: [(u8, usize); 6] = [
+    (0x82, 8),  // GET_CERTIFICATE
+    (0x83, 36), // CHALLENGE
+    (0xE4, 42), // KEY_EXCHANGE
+    (0xE6, 12), // PSK_EXCHANGE
+    (0x85, 12), // CHUNK_SEND
+    (0x86, 6),  // CHUNK_GET
+];
+
+// FINISH, PSK_FINISH, HEARTBEAT, KEY_UPDATE, END_SESSION
+const SECURED_ONLY_REQUESTS: [u8; 5] = [0xE5, 0xE7, 0xE8, 0xE9, 0xEC];
+
+// GET_CAPABILITIES and NEGOTIATE_ALGORITHMS once the algorithms are negotiated
+const OUT_OF_ORDER_REQUESTS: [u8; 2] = [0xE1, 0xE3];
+
+// GET_DIGESTS, CHALLENGE, GET_MEASUREMENTS and RESPOND_IF_READY, sent with a
+// version never negotiated
+const VERSIONED_REQUESTS: [u8; 4] = [0x81, 0x83, 0xE0, 0xFF];
+
+const UNDEFINED_REQUESTS: [u8; 6] = [0x80, 0x87, 0x8F, 0xC0, 0xED, 0xFD];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidKind {
+    Truncated,
+    CertificateOutOfRange,
+    SecuredOnly,
+    OutOfOrder,
+    VersionMismatch,
+    Undefined,
+    NotRequest,
+}
+
+pub const INVALID_KINDS: [InvalidKind; 7] = [
+    InvalidKind::Truncated,
+    InvalidKind::CertificateOutOfRange,
+    InvalidKind::SecuredOnly,
+    InvalidKind::OutOfOrder,
+    InvalidKind::VersionMismatch,
+    InvalidKind::Undefined,
+    InvalidKind::NotRequest,
+];
+
+fn pick<R: Rng>(rng: &mut R, values: &[u8]) -> u8 {
+    values[rng.gen_range(0, values.len())]
+}
+
+fn random_bytes<R: Rng>(rng: &mut R, message: &mut Vec<u8>, size: usize) {
+    while message.len() < size {
+        message.push(rng.gen());
+    }
+}
+
+/// A SPDM message of `kind` for a connection of `version`.
+pub fn invalid_request<R: Rng>(rng: &mut R, kind: InvalidKind, version: u8) -> Vec<u8> {
+    let mut message = Vec::new();
+    match kind {
+        InvalidKind::Truncated => {
+            let (code, min_size) = SIZED_REQUESTS[rng.gen_range(0, SIZED_REQUESTS.len())];
+            message.extend_from_slice(&[version, code]);
+            // still too short once the transport pads it to a multiple of 4
+            let size = rng.gen_range(2, (min_size - 1) / 4 * 4 + 1);
+            random_bytes(rng, &mut message, size);
+        }
+        InvalidKind::CertificateOutOfRange => {
+            // slot 7 is not provisioned, offset 0xFFFF is beyond any chain
+            let (slot_id, offset) = if rng.gen() { (7, 0) } else { (0, 0xFFFFu16) };
+            message.extend_from_slice(&[version, 0x82, slot_id, 0]);
+            message.extend_from_slice(&offset.to_le_bytes());
+            message.extend_from_slice(&0x400u16.to_le_bytes());
+        }
+        InvalidKind::SecuredOnly => {
+            message.extend_from_slice(&[version, pick(rng, &SECURED_ONLY_REQUESTS), 0, 0]);
+        }
+        InvalidKind::OutOfOrder => {
+            message.extend_from_slice(&[version, pick(rng, &OUT_OF_ORDER_REQUESTS)]);
+            random_bytes(rng, &mut message, 32);
+        }
+        InvalidKind::VersionMismatch => {
+            let mut bad_version = rng.gen();
+            while bad_version == version {
+                bad_version = rng.gen();
+            }
+            message.extend_from_slice(&[bad_version, pick(rng, &VERSIONED_REQUESTS), 0, 0]);
+        }
+        InvalidKind::Undefined => {
+            message.extend_from_slice(&[version, pick(rng, &UNDEFINED_REQUESTS), 0, 0]);
+        }
+        InvalidKind::NotRequest => {
+            message.extend_from_slice(&[version, rng.gen_range(0x01, 0x7F)]);
+            let size = rng.gen_range(2, 16);
+            random_bytes(rng, &mut message, size);
+        }
+    }
+    message
+}
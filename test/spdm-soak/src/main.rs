@@ -0,0 +1,505 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+#![forbid(unsafe_code)]
+
+// Soak test: a requester and a responder connected in memory run a long
+// random sequence of valid flows, mixed with requests the responder has to
+// reject. After every step both sides have to agree on the open sessions,
+// the transcript and the session keys.
+
+mod invalid;
+mod peers;
+mod pipe;
+
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use log::LevelFilter;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use simple_logger::SimpleLogger;
+
+use pcidoe_transport::PciDoeTransportEncap;
+use spdmlib::common::SpdmTransportEncap;
+use spdmlib::config;
+use spdmlib::msgs::*;
+use spdmlib::requester::{RequesterContext, SpdmDigestsStatus};
+use spdmlib::responder::{ProcessStatus, ResponderContext};
+use spdmlib::session::SpdmSessionSecretParam;
+
+use invalid::{InvalidKind, INVALID_KINDS};
+use pipe::{Pipe, RequesterPipe, ResponderPipe};
+
+const DEFAULT_ITERATIONS: u64 = 10_000;
+const REPORT_INTERVAL: u64 = 1_000;
+// steps listed when one fails
+const RECENT_STEPS: usize = 16;
+
+// reported by the panic hook
+static SEED: AtomicU64 = AtomicU64::new(0);
+static STEP: AtomicU64 = AtomicU64::new(0);
+
+struct Options {
+    seed: u64,
+    iterations: Option<u64>,
+    duration: Option<Duration>,
+    // percentage of the steps sending an invalid request
+    invalid_rate: u32,
+}
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: spdm-soak [--seed N] [--iterations N] [--duration SECONDS] [--invalid-rate PERCENT]"
+    );
+    std::process::exit(2);
+}
+
+fn parse_options() -> Options {
+    let mut options = Options {
+        seed: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_nanos() as u64)
+            .unwrap_or(0),
+        iterations: None,
+        duration: None,
+        invalid_rate: 10,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next().unwrap_or_else(|| usage());
+        let value: u64 = value.parse().unwrap_or_else(|_| usage());
+        match arg.as_str() {
+            "--seed" => options.seed = value,
+            "--iterations" => options.iterations = Some(value),
+            "--duration" => options.duration = Some(Duration::from_secs(value)),
+            "--invalid-rate" if value <= 100 => options.invalid_rate = value as u32,
+            _ => usage(),
+        }
+    }
+    if options.iterations.is_none() && options.duration.is_none() {
+        options.iterations = Some(DEFAULT_ITERATIONS);
+    }
+    options
+}
+
+// A new logger enables the user to choose log level by setting a `SPDM_LOG` environment variable.
+// Logging is off by default, the invalid requests are logged as errors.
+fn new_logger_from_env() -> SimpleLogger {
+    let level = match std::env::var("SPDM_LOG") {
+        Ok(x) => match x.to_lowercase().as_str() {
+            "trace" => LevelFilter::Trace,
+            "debug" => LevelFilter::Debug,
+            "info" => LevelFilter::Info,
+            "warn" => LevelFilter::Warn,
+            "error" => LevelFilter::Error,
+            _ => LevelFilter::Off,
+        },
+        _ => LevelFilter::Off,
+    };
+
+    SimpleLogger::new().with_level(level)
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Step {
+    Connect,
+    // GET_CERTIFICATE of slot 0 with the partial API, in portions of this size
+    StreamCertificate(u16),
+    Challenge,
+    Measurements,
+    StartSession { use_psk: bool },
+    Heartbeat(u32),
+    MeasurementsInSession(u32),
+    KeyUpdate(u32, SpdmKeyUpdateOperation),
+    EndSession(u32),
+    Invalid(InvalidKind),
+}
+
+// What the requester has set up so far.
+#[derive(Default)]
+struct Model {
+    connected: bool,
+    sessions: Vec<u32>,
+    // request and response direction, as of the last check
+    sequence_numbers: HashMap<u32, (u64, u64)>,
+}
+
+#[derive(Debug, Default)]
+struct Stats {
+    steps: u64,
+    connections: u64,
+    handshakes: u64,
+    psk_handshakes: u64,
+    session_limit_hits: u64,
+    key_updates: u64,
+    invalid_requests: u64,
+}
+
+fn choose_step<R: Rng>(rng: &mut R, model: &Model, invalid_rate: u32) -> Step {
+    if !model.connected {
+        return Step::Connect;
+    }
+    if rng.gen_range(0, 100) < invalid_rate {
+        return Step::Invalid(*INVALID_KINDS.choose(rng).unwrap());
+    }
+
+    let mut steps = vec![
+        // tiny portions fill message_b with request and response headers
+        Step::StreamCertificate(rng.gen_range(64, config::MAX_SPDM_CERT_PORTION_LEN as u16 + 1)),
+        Step::Challenge,
+        Step::Measurements,
+        Step::StartSession { use_psk: rng.gen() },
+        // GET_VERSION ends the open sessions
+        Step::Connect,
+    ];
+    if let Some(&session_id) = model.sessions.choose(rng) {
+        steps.extend_from_slice(&[
+            Step::Heartbeat(session_id),
+            Step::MeasurementsInSession(session_id),
+            Step::KeyUpdate(session_id, SpdmKeyUpdateOperation::SpdmUpdateSingleKey),
+            Step::KeyUpdate(session_id, SpdmKeyUpdateOperation::SpdmUpdateAllKeys),
+            Step::EndSession(session_id),
+        ]);
+    }
+    *steps.choose(rng).unwrap()
+}
+
+struct Soak<'a, 'b> {
+    requester: RequesterContext<'b>,
+    responder: Rc<RefCell<ResponderContext<'a>>>,
+    pipe: Pipe,
+    model: Model,
+    stats: Stats,
+}
+
+impl Soak<'_, '_> {
+    fn run_step<R: Rng>(&mut self, rng: &mut R, step: Step) -> Result<(), String> {
+        let requester = &mut self.requester;
+        match step {
+            Step::Connect => {
+                let digests_status = requester
+                    .reset_connection()
+                    .and_then(|_| requester.send_receive_spdm_digest())
+                    .map_err(|e| format!("connection failed: {:?}", e))?;
+                // the responder keeps its cert chain across connections
+                if self.stats.connections != 0 && digests_status != SpdmDigestsStatus::Unchanged {
+                    return Err(format!("digests after reconnecting: {:?}", digests_status));
+                }
+                requester
+                    .send_receive_spdm_certificate(0)
+                    .map_err(|e| format!("connection failed: {:?}", e))?;
+                self.model.connected = true;
+                self.model.sessions.clear();
+                self.model.sequence_numbers.clear();
+                self.stats.connections += 1;
+            }
+            Step::StreamCertificate(portion_length) => {
+                let mut cert_chain = Vec::new();
+                let mut length = portion_length;
+                while length != 0 {
+                    let certificate = requester
+                        .send_receive_spdm_certificate_partial(0, cert_chain.len() as u16, length)
+                        .map_err(|e| format!("partial GET_CERTIFICATE failed: {:?}", e))?;
+                    cert_chain.extend_from_slice(
+                        &certificate.cert_chain[..certificate.portion_length as usize],
+                    );
+                    length = certificate.remainder_length.min(portion_length);
+                }
+                let verified = requester.common.peer_info.peer_cert_chains[0]
+                    .ok_or_else(|| "no verified cert chain".to_string())?;
+                if cert_chain.as_slice() != verified.as_slice() {
+                    return Err("streamed cert chain differs from the verified one".to_string());
+                }
+                // the portions are in message_b until a CHALLENGE, which
+                // also checks both sides recorded the same transcript
+                requester
+                    .send_receive_spdm_challenge(
+                        0,
+                        SpdmMeasurementSummaryHashType::SpdmMeasurementSummaryHashTypeNone,
+                    )
+                    .map_err(|e| format!("CHALLENGE after streaming failed: {:?}", e))?;
+            }
+            Step::Challenge => requester
+                .send_receive_spdm_challenge(
+                    0,
+                    SpdmMeasurementSummaryHashType::SpdmMeasurementSummaryHashTypeNone,
+                )
+                .map_err(|e| format!("CHALLENGE failed: {:?}", e))?,
+            Step::Measurements => {
+                requester
+                    .send_receive_spdm_all_measurements(0)
+                    .map_err(|e| format!("GET_MEASUREMENTS failed: {:?}", e))?;
+            }
+            Step::StartSession { use_psk } => {
+                let full = self.model.sessions.len() == config::MAX_SPDM_SESSION_COUNT;
+                let result = requester.start_session(
+                    use_psk,
+                    0,
+                    SpdmMeasurementSummaryHashType::SpdmMeasurementSummaryHashTypeNone,
+                );
+                match result {
+                    Ok(session_id) if full => {
+                        return Err(format!(
+                            "session {:08x} set up beyond the limit",
+                            session_id
+                        ))
+                    }
+                    Ok(session_id) => {
+                        self.model.sessions.push(session_id);
+                        self.stats.handshakes += 1;
+                        if use_psk {
+                            self.stats.psk_handshakes += 1;
+                        }
+                    }
+                    Err(_) if full => self.stats.session_limit_hits += 1,
+                    Err(e) => return Err(format!("session setup failed: {:?}", e)),
+                }
+            }
+            Step::Heartbeat(session_id) => requester
+                .send_receive_spdm_heartbeat(session_id)
+                .map_err(|e| format!("HEARTBEAT failed: {:?}", e))?,
+            Step::MeasurementsInSession(session_id) => {
+                requester
+                    .send_receive_spdm_all_measurements_in_session(session_id, 0)
+                    .map_err(|e| format!("GET_MEASUREMENTS in session failed: {:?}", e))?;
+            }
+            Step::KeyUpdate(session_id, operation) => {
+                requester
+                    .send_receive_spdm_key_update(session_id, operation)
+                    .and_then(|_| {
+                        requester.send_receive_spdm_key_update(
+                            session_id,
+                            SpdmKeyUpdateOperation::SpdmVerifyNewKey,
+                        )
+                    })
+                    .map_err(|e| format!("KEY_UPDATE failed: {:?}", e))?;
+                // the sequence numbers start over with the new keys
+                self.model.sequence_numbers.remove(&session_id);
+                self.stats.key_updates += 1;
+            }
+            Step::EndSession(session_id) => {
+                requester
+                    .send_receive_spdm_end_session(session_id)
+                    .map_err(|e| format!("END_SESSION failed: {:?}", e))?;
+                self.model.sessions.retain(|id| *id != session_id);
+                self.model.sequence_numbers.remove(&session_id);
+            }
+            Step::Invalid(kind) => {
+                let version = requester.common.negotiate_info.spdm_version_sel.get_u8();
+                let message = invalid::invalid_request(rng, kind, version);
+                let mut transport_buffer = [0u8; config::MAX_SPDM_TRANSPORT_SIZE];
+                let used = PciDoeTransportEncap {}
+                    .encap(&message, &mut transport_buffer, false)
+                    .map_err(|e| format!("encap failed: {:?}", e))?;
+                let (result, _) = self.pipe.inject(&self.responder, &transport_buffer[..used]);
+                if let Some(result) = result {
+                    if result.status == ProcessStatus::ResponseSent {
+                        return Err(format!("{:?} request {:02x?} answered", kind, message));
+                    }
+                }
+                self.stats.invalid_requests += 1;
+            }
+        }
+        Ok(())
+    }
+
+    // Both sides have to agree after every step.
+    fn check_peers(&mut self) -> Result<(), String> {
+        if self.pipe.pending() != 0 {
+            return Err(format!("{} messages not received", self.pipe.pending()));
+        }
+
+        let mut expected = self.model.sessions.clone();
+        expected.sort_unstable();
+        let mut requester_sessions: Vec<u32> =
+            self.requester.common.get_active_session_ids().collect();
+        requester_sessions.sort_unstable();
+        if requester_sessions != expected {
+            return Err(format!(
+                "requester sessions {:08x?}, expected {:08x?}",
+                requester_sessions, expected
+            ));
+        }
+        let mut responder = self.responder.borrow_mut();
+        let mut responder_sessions: Vec<u32> = responder.get_active_session_ids().collect();
+        responder_sessions.sort_unstable();
+        if responder_sessions != expected {
+            return Err(format!(
+                "responder sessions {:08x?}, expected {:08x?}",
+                responder_sessions, expected
+            ));
+        }
+
+        if self.requester.common.runtime_info.message_a.as_ref()
+            != responder.common.runtime_info.message_a.as_ref()
+        {
+            return Err("VCA transcripts differ".to_string());
+        }
+
+        for session_id in expected {
+            let (requester_request, requester_response) = self
+                .requester
+                .common
+                .get_session_via_id(session_id)
+                .unwrap()
+                .export_keys();
+            let (responder_request, responder_response) = responder
+                .common
+                .get_session_via_id(session_id)
+                .unwrap()
+                .export_keys();
+            if !is_same_secret(&requester_request, &responder_request)
+                || !is_same_secret(&requester_response, &responder_response)
+            {
+                return Err(format!("session {:08x} keys differ", session_id));
+            }
+
+            let sequence_numbers = (
+                requester_request.sequence_number,
+                requester_response.sequence_number,
+            );
+            if let Some(previous) = self
+                .model
+                .sequence_numbers
+                .insert(session_id, sequence_numbers)
+            {
+                if sequence_numbers.0 < previous.0 || sequence_numbers.1 < previous.1 {
+                    return Err(format!(
+                        "session {:08x} sequence numbers {:?} went back from {:?}",
+                        session_id, sequence_numbers, previous
+                    ));
+                }
+            }
+        }
+
+        let crypto_failures = responder.get_crypto_failure_stats().failures;
+        if crypto_failures != 0 {
+            return Err(format!("{} responder crypto failures", crypto_failures));
+        }
+        Ok(())
+    }
+
+    fn end_all_sessions(&mut self) -> Result<(), String> {
+        for session_id in self.model.sessions.clone() {
+            self.run_step(&mut StdRng::seed_from_u64(0), Step::EndSession(session_id))?;
+        }
+        self.check_peers()
+    }
+}
+
+fn is_same_secret(a: &SpdmSessionSecretParam, b: &SpdmSessionSecretParam) -> bool {
+    a.encryption_key.data[..a.encryption_key.data_size as usize]
+        == b.encryption_key.data[..b.encryption_key.data_size as usize]
+        && a.salt.data[..a.salt.data_size as usize] == b.salt.data[..b.salt.data_size as usize]
+        && a.sequence_number == b.sequence_number
+}
+
+fn is_done(options: &Options, steps: u64, start: Instant) -> bool {
+    matches!(options.iterations, Some(iterations) if steps >= iterations)
+        || matches!(options.duration, Some(duration) if start.elapsed() >= duration)
+}
+
+fn run(options: &Options) -> Result<Stats, String> {
+    let peers = peers::setup_peers();
+    let pipe = Pipe::default();
+
+    let mut responder_pipe = ResponderPipe::new(&pipe);
+    let mut responder_transport = PciDoeTransportEncap {};
+    let mut responder = ResponderContext::new(
+        &mut responder_pipe,
+        &mut responder_transport,
+        peers.responder_config,
+        peers.responder_provision,
+    );
+    responder.common.set_crypto_provider(peers.responder_crypto);
+    let responder = Rc::new(RefCell::new(responder));
+
+    let mut requester_pipe = RequesterPipe::new(&pipe, responder.clone());
+    let mut requester_transport = PciDoeTransportEncap {};
+    let mut requester = RequesterContext::new(
+        &mut requester_pipe,
+        &mut requester_transport,
+        peers.requester_config,
+        peers.requester_provision,
+    );
+    requester.common.set_crypto_provider(peers.requester_crypto);
+
+    let mut soak = Soak {
+        requester,
+        responder,
+        pipe,
+        model: Model::default(),
+        stats: Stats::default(),
+    };
+    let mut rng = StdRng::seed_from_u64(options.seed);
+    let mut recent_steps = VecDeque::new();
+    let start = Instant::now();
+    let mut next_report = REPORT_INTERVAL;
+
+    while !is_done(options, soak.stats.steps, start) {
+        STEP.store(soak.stats.steps, Ordering::Relaxed);
+        let step = choose_step(&mut rng, &soak.model, options.invalid_rate);
+        if recent_steps.len() == RECENT_STEPS {
+            recent_steps.pop_front();
+        }
+        recent_steps.push_back(step);
+
+        if let Err(e) = soak
+            .run_step(&mut rng, step)
+            .and_then(|_| soak.check_peers())
+        {
+            return Err(format!(
+                "step {} {:?}: {}\nrecent steps: {:?}",
+                soak.stats.steps, step, e, recent_steps
+            ));
+        }
+        soak.stats.steps += 1;
+
+        if soak.stats.steps == next_report {
+            next_report += REPORT_INTERVAL;
+            println!(
+                "{} steps in {}s: {:?}",
+                soak.stats.steps,
+                start.elapsed().as_secs(),
+                soak.stats
+            );
+        }
+    }
+
+    // no session slot may be left behind
+    soak.end_all_sessions()
+        .map_err(|e| format!("ending the sessions: {}", e))?;
+    Ok(soak.stats)
+}
+
+fn main() {
+    new_logger_from_env().init().unwrap();
+
+    let options = parse_options();
+    SEED.store(options.seed, Ordering::Relaxed);
+    println!("soak test seed {}", options.seed);
+
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        eprintln!(
+            "panic at step {} of seed {}",
+            STEP.load(Ordering::Relaxed),
+            SEED.load(Ordering::Relaxed)
+        );
+        default_hook(info);
+    }));
+
+    match run(&options) {
+        Ok(stats) => println!("soak test passed: {:?}", stats),
+        Err(e) => {
+            eprintln!("soak test failed with seed {}: {}", options.seed, e);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,173 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+#![forbid(unsafe_code)]
+
+// Configuration of the requester and the responder under test. Both sides
+// use ECDSA P-384 cert chains generated at startup, so the run doesn't
+// depend on the TestKey files or their expiry.
+
+use std::convert::TryFrom;
+
+use conquer_once::spin::OnceCell;
+use spdm_emu::crypto_callback::PSK_IMPL;
+use spdm_emu::spdm_emu::{TEST_PSK_DATA, TEST_PSK_HINT};
+use spdmlib::common::{SpdmConfigInfo, SpdmProvisionInfo};
+use spdmlib::config;
+use spdmlib::crypto::{SpdmAsymSign, SpdmCryptoProvider};
+use spdmlib::msgs::*;
+use test_utils::{generate_cert_chain, TestCertChain};
+
+const BASE_HASH_ALGO: SpdmBaseHashAlgo = SpdmBaseHashAlgo::TPM_ALG_SHA_384;
+const BASE_ASYM_ALGO: SpdmBaseAsymAlgo = SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P384;
+
+// The sign callbacks are plain functions, the keys are looked up here.
+static RESPONDER_CERT_CHAIN: OnceCell<TestCertChain> = OnceCell::uninit();
+static REQUESTER_CERT_CHAIN: OnceCell<TestCertChain> = OnceCell::uninit();
+
+fn responder_sign(
+    _slot_id: u8,
+    base_hash_algo: SpdmBaseHashAlgo,
+    _base_asym_algo: SpdmBaseAsymAlgo,
+    data: &[u8],
+) -> Option<SpdmSignatureStruct> {
+    let cert_chain = RESPONDER_CERT_CHAIN.get()?;
+    Some(cert_chain.sign(base_hash_algo, data))
+}
+
+fn requester_sign(
+    _slot_id: u8,
+    base_hash_algo: SpdmBaseHashAlgo,
+    _base_asym_algo: SpdmBaseAsymAlgo,
+    data: &[u8],
+) -> Option<SpdmSignatureStruct> {
+    let cert_chain = REQUESTER_CERT_CHAIN.get()?;
+    Some(cert_chain.sign(base_hash_algo, data))
+}
+
+pub struct SoakPeers {
+    pub requester_config: SpdmConfigInfo,
+    pub requester_provision: SpdmProvisionInfo,
+    pub requester_crypto: SpdmCryptoProvider,
+    pub responder_config: SpdmConfigInfo,
+    pub responder_provision: SpdmProvisionInfo,
+    pub responder_crypto: SpdmCryptoProvider,
+}
+
+fn leak_root_cert_store(cert_chain: &TestCertChain) -> &'static [&'static [u8]] {
+    let root_cert: &'static [u8] = Box::leak(cert_chain.root_cert.clone().into_boxed_slice());
+    Box::leak(vec![root_cert].into_boxed_slice())
+}
+
+/// Generate the cert chains of both sides and set up their configuration.
+/// Only called once, the keys are kept in statics.
+pub fn setup_peers() -> SoakPeers {
+    let responder_cert_chain = generate_cert_chain(BASE_HASH_ALGO, BASE_ASYM_ALGO);
+    let requester_cert_chain = generate_cert_chain(BASE_HASH_ALGO, BASE_ASYM_ALGO);
+
+    let requester_config = SpdmConfigInfo {
+        spdm_version: [
+            SpdmVersion::SpdmVersion10,
+            SpdmVersion::SpdmVersion11,
+            SpdmVersion::SpdmVersion12,
+        ],
+        req_capabilities: SpdmRequestCapabilityFlags::CERT_CAP
+            | SpdmRequestCapabilityFlags::CHAL_CAP
+            | SpdmRequestCapabilityFlags::ENCRYPT_CAP
+            | SpdmRequestCapabilityFlags::MAC_CAP
+            | SpdmRequestCapabilityFlags::MUT_AUTH_CAP
+            | SpdmRequestCapabilityFlags::KEY_EX_CAP
+            | SpdmRequestCapabilityFlags::PSK_CAP
+            | SpdmRequestCapabilityFlags::ENCAP_CAP
+            | SpdmRequestCapabilityFlags::HBEAT_CAP
+            | SpdmRequestCapabilityFlags::KEY_UPD_CAP
+            | SpdmRequestCapabilityFlags::CHUNK_CAP,
+        req_ct_exponent: 0,
+        data_transfer_size: config::MAX_SPDM_TRANSPORT_SIZE as u32,
+        max_spdm_msg_size: config::MAX_SPDM_MESSAGE_BUFFER_SIZE as u32,
+        opaque_support: SpdmOpaqueSupport::OPAQUE_DATA_FMT1,
+        measurement_specification: SpdmMeasurementSpecification::DMTF,
+        base_asym_algo: BASE_ASYM_ALGO,
+        base_hash_algo: BASE_HASH_ALGO,
+        dhe_algo: SpdmDheAlgo::SECP_384_R1,
+        aead_algo: SpdmAeadAlgo::AES_256_GCM,
+        req_asym_algo: SpdmReqAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P384,
+        key_schedule_algo: SpdmKeyScheduleAlgo::SPDM_KEY_SCHEDULE,
+        psk: SpdmDheFinalKeyStruct::try_from(TEST_PSK_DATA).unwrap(),
+        psk_hint: SpdmPskHintStruct::try_from(TEST_PSK_HINT).unwrap(),
+        ..Default::default()
+    };
+
+    let responder_config = SpdmConfigInfo {
+        spdm_version: requester_config.spdm_version,
+        rsp_capabilities: SpdmResponseCapabilityFlags::CERT_CAP
+            | SpdmResponseCapabilityFlags::CHAL_CAP
+            | SpdmResponseCapabilityFlags::MEAS_CAP_SIG
+            | SpdmResponseCapabilityFlags::MEAS_FRESH_CAP
+            | SpdmResponseCapabilityFlags::ENCRYPT_CAP
+            | SpdmResponseCapabilityFlags::MAC_CAP
+            | SpdmResponseCapabilityFlags::MUT_AUTH_CAP
+            | SpdmResponseCapabilityFlags::KEY_EX_CAP
+            | SpdmResponseCapabilityFlags::PSK_CAP_WITH_CONTEXT
+            | SpdmResponseCapabilityFlags::ENCAP_CAP
+            | SpdmResponseCapabilityFlags::HBEAT_CAP
+            | SpdmResponseCapabilityFlags::KEY_UPD_CAP
+            | SpdmResponseCapabilityFlags::CHUNK_CAP,
+        rsp_ct_exponent: 0,
+        data_transfer_size: config::MAX_SPDM_TRANSPORT_SIZE as u32,
+        max_spdm_msg_size: config::MAX_SPDM_MESSAGE_BUFFER_SIZE as u32,
+        opaque_support: SpdmOpaqueSupport::OPAQUE_DATA_FMT1,
+        measurement_specification: SpdmMeasurementSpecification::DMTF,
+        measurement_hash_algo: SpdmMeasurementHashAlgo::TPM_ALG_SHA_384,
+        base_asym_algo: BASE_ASYM_ALGO,
+        base_hash_algo: BASE_HASH_ALGO,
+        dhe_algo: SpdmDheAlgo::SECP_384_R1,
+        aead_algo: SpdmAeadAlgo::AES_256_GCM,
+        req_asym_algo: SpdmReqAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P384,
+        key_schedule_algo: SpdmKeyScheduleAlgo::SPDM_KEY_SCHEDULE,
+        ..Default::default()
+    };
+
+    let responder_cert_chain_data =
+        SpdmCertChainData::try_from(responder_cert_chain.cert_chain().as_slice()).unwrap();
+    let requester_cert_chain_data =
+        SpdmCertChainData::try_from(requester_cert_chain.cert_chain().as_slice()).unwrap();
+
+    let mut requester_provision = SpdmProvisionInfo {
+        peer_cert_chain_data: Some(responder_cert_chain_data),
+        peer_root_cert_store: Some(leak_root_cert_store(&responder_cert_chain)),
+        ..SpdmProvisionInfo::new()
+    };
+    requester_provision.my_cert_chain_data[0] = Some(requester_cert_chain_data);
+
+    let mut responder_provision = SpdmProvisionInfo {
+        peer_cert_chain_data: Some(requester_cert_chain_data),
+        peer_root_cert_store: Some(leak_root_cert_store(&requester_cert_chain)),
+        ..SpdmProvisionInfo::new()
+    };
+    responder_provision.my_cert_chain_data[0] = Some(responder_cert_chain_data);
+
+    RESPONDER_CERT_CHAIN.init_once(|| responder_cert_chain);
+    REQUESTER_CERT_CHAIN.init_once(|| requester_cert_chain);
+
+    SoakPeers {
+        requester_config,
+        requester_provision,
+        requester_crypto: SpdmCryptoProvider {
+            asym_sign: Some(SpdmAsymSign {
+                sign_cb: requester_sign,
+            }),
+            ..Default::default()
+        },
+        responder_config,
+        responder_provision,
+        responder_crypto: SpdmCryptoProvider {
+            asym_sign: Some(SpdmAsymSign {
+                sign_cb: responder_sign,
+            }),
+            psk: Some(PSK_IMPL),
+            ..Default::default()
+        },
+    }
+}
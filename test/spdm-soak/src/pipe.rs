@@ -0,0 +1,117 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+#![forbid(unsafe_code)]
+
+// In-memory connection between a requester and a responder running on the
+// same thread: every request sent by the requester is answered right away.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use spdmlib::common::SpdmDeviceIo;
+use spdmlib::error::SpdmResult;
+use spdmlib::responder::{ProcessResult, ResponderContext};
+
+type MessageQueue = Rc<RefCell<VecDeque<Vec<u8>>>>;
+
+fn pop_message(queue: &MessageQueue, buffer: &mut [u8]) -> Result<usize, usize> {
+    let message = queue.borrow_mut().pop_front().ok_or(0usize)?;
+    if message.len() > buffer.len() {
+        return Err(0);
+    }
+    buffer[..message.len()].copy_from_slice(&message);
+    Ok(message.len())
+}
+
+#[derive(Clone, Default)]
+pub struct Pipe {
+    requests: MessageQueue,
+    responses: MessageQueue,
+}
+
+impl Pipe {
+    /// Transport messages sent and not received yet, in both directions.
+    pub fn pending(&self) -> usize {
+        self.requests.borrow().len() + self.responses.borrow().len()
+    }
+
+    /// Drop the transport messages not received.
+    pub fn clear(&self) {
+        self.requests.borrow_mut().clear();
+        self.responses.borrow_mut().clear();
+    }
+
+    /// Have `responder` process the transport message `request` and return
+    /// the response, if any, bypassing the requester.
+    pub fn inject(
+        &self,
+        responder: &RefCell<ResponderContext>,
+        request: &[u8],
+    ) -> (Option<ProcessResult>, Option<Vec<u8>>) {
+        self.requests.borrow_mut().push_back(request.to_vec());
+        let result = responder.borrow_mut().process_message().ok();
+        self.requests.borrow_mut().clear();
+        let response = self.responses.borrow_mut().pop_front();
+        (result, response)
+    }
+}
+
+pub struct ResponderPipe {
+    pipe: Pipe,
+}
+
+impl ResponderPipe {
+    pub fn new(pipe: &Pipe) -> Self {
+        ResponderPipe { pipe: pipe.clone() }
+    }
+}
+
+impl SpdmDeviceIo for ResponderPipe {
+    fn send(&mut self, buffer: &[u8]) -> SpdmResult {
+        self.pipe.responses.borrow_mut().push_back(buffer.to_vec());
+        Ok(())
+    }
+
+    fn receive(&mut self, buffer: &mut [u8]) -> Result<usize, usize> {
+        pop_message(&self.pipe.requests, buffer)
+    }
+
+    fn flush_all(&mut self) -> SpdmResult {
+        Ok(())
+    }
+}
+
+pub struct RequesterPipe<'a> {
+    pipe: Pipe,
+    responder: Rc<RefCell<ResponderContext<'a>>>,
+}
+
+impl<'a> RequesterPipe<'a> {
+    pub fn new(pipe: &Pipe, responder: Rc<RefCell<ResponderContext<'a>>>) -> Self {
+        RequesterPipe {
+            pipe: pipe.clone(),
+            responder,
+        }
+    }
+}
+
+impl SpdmDeviceIo for RequesterPipe<'_> {
+    fn send(&mut self, buffer: &[u8]) -> SpdmResult {
+        self.pipe.requests.borrow_mut().push_back(buffer.to_vec());
+        // a message the responder can't decode is left unanswered
+        let _ = self.responder.borrow_mut().process_message();
+        Ok(())
+    }
+
+    fn receive(&mut self, buffer: &mut [u8]) -> Result<usize, usize> {
+        pop_message(&self.pipe.responses, buffer)
+    }
+
+    fn flush_all(&mut self) -> SpdmResult {
+        self.pipe.clear();
+        Ok(())
+    }
+}
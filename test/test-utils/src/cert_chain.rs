@@ -0,0 +1,498 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+// Generate root/intermediate/leaf cert chains at test time.
+// The extensions follow TestKey/openssl.cnf (v3_inter and v3_end).
+
+use crate::der;
+use ring::signature::KeyPair as _;
+use rsa::PublicKeyParts;
+use spdmlib::msgs::{
+    SpdmBaseAsymAlgo, SpdmBaseHashAlgo, SpdmSignatureStruct, SPDM_MAX_ASYM_KEY_SIZE,
+};
+
+const OID_EC_PUBLIC_KEY: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+const OID_PRIME256V1: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+const OID_SECP384R1: &[u8] = &[0x2b, 0x81, 0x04, 0x00, 0x22];
+const OID_ECDSA_WITH_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+const OID_ECDSA_WITH_SHA384: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x03];
+const OID_RSA_ENCRYPTION: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+const OID_SHA256_WITH_RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0b];
+const OID_SHA384_WITH_RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0c];
+const OID_SHA512_WITH_RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0d];
+const OID_COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+const OID_KEY_USAGE: &[u8] = &[0x55, 0x1d, 0x0f];
+const OID_SUBJECT_ALT_NAME: &[u8] = &[0x55, 0x1d, 0x11];
+const OID_BASIC_CONSTRAINTS: &[u8] = &[0x55, 0x1d, 0x13];
+const OID_EXT_KEY_USAGE: &[u8] = &[0x55, 0x1d, 0x25];
+const OID_SERVER_AUTH: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x03, 0x01];
+const OID_CLIENT_AUTH: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x03, 0x02];
+const OID_OCSP_SIGNING: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x03, 0x09];
+// 1.3.6.1.4.1.412.274.1 (DMTF otherName)
+const OID_DMTF_OTHER_NAME: &[u8] = &[0x2b, 0x06, 0x01, 0x04, 0x01, 0x83, 0x1c, 0x82, 0x12, 0x01];
+
+const NOT_BEFORE: &str = "200101000000Z";
+const NOT_AFTER: &str = "491231235959Z";
+
+pub struct TestCertChain {
+    pub base_asym_algo: SpdmBaseAsymAlgo,
+    pub root_cert: Vec<u8>,
+    pub inter_cert: Vec<u8>,
+    pub leaf_cert: Vec<u8>,
+    /// PKCS#8 private key of the leaf cert.
+    pub leaf_key: Vec<u8>,
+}
+
+impl TestCertChain {
+    /// DER certs concatenated from root to leaf, as in bundle_responder.certchain.der.
+    pub fn cert_chain(&self) -> Vec<u8> {
+        [
+            self.root_cert.as_slice(),
+            self.inter_cert.as_slice(),
+            self.leaf_cert.as_slice(),
+        ]
+        .concat()
+    }
+
+    /// Sign SPDM data with the leaf key, in the same format as the asym_sign callback.
+    pub fn sign(&self, base_hash_algo: SpdmBaseHashAlgo, data: &[u8]) -> SpdmSignatureStruct {
+        let rng = ring::rand::SystemRandom::new();
+        let mut full_signature = [0u8; SPDM_MAX_ASYM_KEY_SIZE];
+
+        let signature_len = match self.base_asym_algo {
+            SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P256
+            | SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P384 => {
+                let algorithm =
+                    if self.base_asym_algo == SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P256 {
+                        &ring::signature::ECDSA_P256_SHA256_FIXED_SIGNING
+                    } else {
+                        &ring::signature::ECDSA_P384_SHA384_FIXED_SIGNING
+                    };
+                let key_pair =
+                    ring::signature::EcdsaKeyPair::from_pkcs8(algorithm, &self.leaf_key).unwrap();
+                let signature = key_pair.sign(&rng, data).unwrap();
+                let signature = signature.as_ref();
+                full_signature[..signature.len()].copy_from_slice(signature);
+                signature.len()
+            }
+            _ => {
+                let is_pss = self.base_asym_algo == SpdmBaseAsymAlgo::TPM_ALG_RSAPSS_2048
+                    || self.base_asym_algo == SpdmBaseAsymAlgo::TPM_ALG_RSAPSS_3072
+                    || self.base_asym_algo == SpdmBaseAsymAlgo::TPM_ALG_RSAPSS_4096;
+                let padding_alg: &'static dyn ring::signature::RsaEncoding =
+                    match (base_hash_algo, is_pss) {
+                        (SpdmBaseHashAlgo::TPM_ALG_SHA_256, false) => {
+                            &ring::signature::RSA_PKCS1_SHA256
+                        }
+                        (SpdmBaseHashAlgo::TPM_ALG_SHA_384, false) => {
+                            &ring::signature::RSA_PKCS1_SHA384
+                        }
+                        (SpdmBaseHashAlgo::TPM_ALG_SHA_512, false) => {
+                            &ring::signature::RSA_PKCS1_SHA512
+                        }
+                        (SpdmBaseHashAlgo::TPM_ALG_SHA_256, true) => {
+                            &ring::signature::RSA_PSS_SHA256
+                        }
+                        (SpdmBaseHashAlgo::TPM_ALG_SHA_384, true) => {
+                            &ring::signature::RSA_PSS_SHA384
+                        }
+                        (SpdmBaseHashAlgo::TPM_ALG_SHA_512, true) => {
+                            &ring::signature::RSA_PSS_SHA512
+                        }
+                        _ => panic!("unsupported base_hash_algo"),
+                    };
+                let key_pair = ring::signature::RsaKeyPair::from_pkcs8(&self.leaf_key).unwrap();
+                let key_len = key_pair.public_modulus_len();
+                key_pair
+                    .sign(padding_alg, &rng, data, &mut full_signature[..key_len])
+                    .unwrap();
+                key_len
+            }
+        };
+
+        SpdmSignatureStruct {
+            data_size: signature_len as u16,
+            data: full_signature,
+        }
+    }
+}
+
+/// Generate a root -> intermediate -> leaf chain for `base_asym_algo`.
+/// The leaf key matches `base_asym_algo`. The root and intermediate keys use the same
+/// key type (RSA 2048 for RSA algorithms, to keep generation fast).
+/// RSA certs are signed with `base_hash_algo`, ECDSA certs with the curve hash.
+pub fn generate_cert_chain(
+    base_hash_algo: SpdmBaseHashAlgo,
+    base_asym_algo: SpdmBaseAsymAlgo,
+) -> TestCertChain {
+    let ca_asym_algo = match base_asym_algo {
+        SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P256
+        | SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P384 => base_asym_algo,
+        _ => SpdmBaseAsymAlgo::TPM_ALG_RSASSA_2048,
+    };
+
+    let root_key = TestKeyPair::generate(ca_asym_algo);
+    let inter_key = TestKeyPair::generate(ca_asym_algo);
+    let leaf_key = TestKeyPair::generate(base_asym_algo);
+
+    let ca_key_usage = key_usage(1, 0x86); // digitalSignature, keyCertSign, cRLSign
+    let root_cert = build_cert(
+        1,
+        "spdm test CA",
+        &root_key,
+        "spdm test CA",
+        &root_key.public_key_info,
+        &[&basic_constraints(true), &ca_key_usage],
+        base_hash_algo,
+    );
+    let inter_cert = build_cert(
+        2,
+        "spdm test CA",
+        &root_key,
+        "spdm test intermediate cert",
+        &inter_key.public_key_info,
+        &[
+            &basic_constraints(true),
+            &ca_key_usage,
+            &ext_key_usage(&[OID_SERVER_AUTH, OID_CLIENT_AUTH]),
+        ],
+        base_hash_algo,
+    );
+    let leaf_cert = build_cert(
+        3,
+        "spdm test intermediate cert",
+        &inter_key,
+        "spdm test responder cert",
+        &leaf_key.public_key_info,
+        &[
+            &basic_constraints(false),
+            &key_usage(5, 0xe0), // digitalSignature, nonRepudiation, keyEncipherment
+            &subject_alt_name("ACME:WIDGET:1234567890"),
+            &ext_key_usage(&[OID_SERVER_AUTH, OID_CLIENT_AUTH, OID_OCSP_SIGNING]),
+        ],
+        base_hash_algo,
+    );
+
+    TestCertChain {
+        base_asym_algo,
+        root_cert,
+        inter_cert,
+        leaf_cert,
+        leaf_key: leaf_key.pkcs8,
+    }
+}
+
+struct TestKeyPair {
+    base_asym_algo: SpdmBaseAsymAlgo,
+    pkcs8: Vec<u8>,
+    public_key_info: Vec<u8>,
+}
+
+impl TestKeyPair {
+    fn generate(base_asym_algo: SpdmBaseAsymAlgo) -> Self {
+        match base_asym_algo {
+            SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P256
+            | SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P384 => {
+                let (algorithm, curve) =
+                    if base_asym_algo == SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P256 {
+                        (
+                            &ring::signature::ECDSA_P256_SHA256_ASN1_SIGNING,
+                            OID_PRIME256V1,
+                        )
+                    } else {
+                        (
+                            &ring::signature::ECDSA_P384_SHA384_ASN1_SIGNING,
+                            OID_SECP384R1,
+                        )
+                    };
+                let rng = ring::rand::SystemRandom::new();
+                let pkcs8 = ring::signature::EcdsaKeyPair::generate_pkcs8(algorithm, &rng)
+                    .unwrap()
+                    .as_ref()
+                    .to_vec();
+                let key_pair =
+                    ring::signature::EcdsaKeyPair::from_pkcs8(algorithm, &pkcs8).unwrap();
+                let public_key_info = der::sequence(&[
+                    &der::sequence(&[&der::oid(OID_EC_PUBLIC_KEY), &der::oid(curve)]),
+                    &der::bit_string(0, key_pair.public_key().as_ref()),
+                ]);
+                TestKeyPair {
+                    base_asym_algo,
+                    pkcs8,
+                    public_key_info,
+                }
+            }
+            SpdmBaseAsymAlgo::TPM_ALG_RSASSA_2048
+            | SpdmBaseAsymAlgo::TPM_ALG_RSASSA_3072
+            | SpdmBaseAsymAlgo::TPM_ALG_RSASSA_4096
+            | SpdmBaseAsymAlgo::TPM_ALG_RSAPSS_2048
+            | SpdmBaseAsymAlgo::TPM_ALG_RSAPSS_3072
+            | SpdmBaseAsymAlgo::TPM_ALG_RSAPSS_4096 => {
+                let bits = base_asym_algo.get_size() as usize * 8;
+                let mut rng = rand::rngs::OsRng;
+                let key = rsa::RSAPrivateKey::new(&mut rng, bits).unwrap();
+
+                let (mut p, mut q) = (&key.primes()[0], &key.primes()[1]);
+                if p < q {
+                    core::mem::swap(&mut p, &mut q);
+                }
+                let one = rsa::BigUint::from(1u8);
+                let dp = key.d() % (p - &one);
+                let dq = key.d() % (q - &one);
+                // p is prime, so q^-1 mod p is q^(p-2) mod p.
+                let qinv = q.modpow(&(p - &one - &one), p);
+
+                let n = der::integer(&key.n().to_bytes_be());
+                let e = der::integer(&key.e().to_bytes_be());
+                let rsa_private_key = der::sequence(&[
+                    &der::integer(&[0]),
+                    &n,
+                    &e,
+                    &der::integer(&key.d().to_bytes_be()),
+                    &der::integer(&p.to_bytes_be()),
+                    &der::integer(&q.to_bytes_be()),
+                    &der::integer(&dp.to_bytes_be()),
+                    &der::integer(&dq.to_bytes_be()),
+                    &der::integer(&qinv.to_bytes_be()),
+                ]);
+                let algorithm = der::sequence(&[&der::oid(OID_RSA_ENCRYPTION), &der::null()]);
+                let pkcs8 = der::sequence(&[
+                    &der::integer(&[0]),
+                    &algorithm,
+                    &der::octet_string(&rsa_private_key),
+                ]);
+                let public_key_info =
+                    der::sequence(&[&algorithm, &der::bit_string(0, &der::sequence(&[&n, &e]))]);
+                TestKeyPair {
+                    base_asym_algo,
+                    pkcs8,
+                    public_key_info,
+                }
+            }
+            _ => panic!("unsupported base_asym_algo"),
+        }
+    }
+
+    fn signature_algorithm(&self, base_hash_algo: SpdmBaseHashAlgo) -> Vec<u8> {
+        match self.base_asym_algo {
+            SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P256 => {
+                der::sequence(&[&der::oid(OID_ECDSA_WITH_SHA256)])
+            }
+            SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P384 => {
+                der::sequence(&[&der::oid(OID_ECDSA_WITH_SHA384)])
+            }
+            _ => {
+                let (_, sig_oid) = rsa_pkcs1_algorithm(base_hash_algo);
+                der::sequence(&[&der::oid(sig_oid), &der::null()])
+            }
+        }
+    }
+
+    fn sign_cert(&self, base_hash_algo: SpdmBaseHashAlgo, tbs: &[u8]) -> Vec<u8> {
+        let rng = ring::rand::SystemRandom::new();
+        match self.base_asym_algo {
+            SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P256
+            | SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P384 => {
+                let algorithm =
+                    if self.base_asym_algo == SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P256 {
+                        &ring::signature::ECDSA_P256_SHA256_ASN1_SIGNING
+                    } else {
+                        &ring::signature::ECDSA_P384_SHA384_ASN1_SIGNING
+                    };
+                let key_pair =
+                    ring::signature::EcdsaKeyPair::from_pkcs8(algorithm, &self.pkcs8).unwrap();
+                key_pair.sign(&rng, tbs).unwrap().as_ref().to_vec()
+            }
+            _ => {
+                let (padding_alg, _) = rsa_pkcs1_algorithm(base_hash_algo);
+                let key_pair = ring::signature::RsaKeyPair::from_pkcs8(&self.pkcs8).unwrap();
+                let mut signature = vec![0u8; key_pair.public_modulus_len()];
+                key_pair
+                    .sign(padding_alg, &rng, tbs, &mut signature)
+                    .unwrap();
+                signature
+            }
+        }
+    }
+}
+
+fn rsa_pkcs1_algorithm(
+    base_hash_algo: SpdmBaseHashAlgo,
+) -> (&'static dyn ring::signature::RsaEncoding, &'static [u8]) {
+    match base_hash_algo {
+        SpdmBaseHashAlgo::TPM_ALG_SHA_256 => {
+            (&ring::signature::RSA_PKCS1_SHA256, OID_SHA256_WITH_RSA)
+        }
+        SpdmBaseHashAlgo::TPM_ALG_SHA_384 => {
+            (&ring::signature::RSA_PKCS1_SHA384, OID_SHA384_WITH_RSA)
+        }
+        SpdmBaseHashAlgo::TPM_ALG_SHA_512 => {
+            (&ring::signature::RSA_PKCS1_SHA512, OID_SHA512_WITH_RSA)
+        }
+        _ => panic!("unsupported base_hash_algo"),
+    }
+}
+
+fn build_cert(
+    serial: u8,
+    issuer: &str,
+    issuer_key: &TestKeyPair,
+    subject: &str,
+    public_key_info: &[u8],
+    extensions: &[&[u8]],
+    base_hash_algo: SpdmBaseHashAlgo,
+) -> Vec<u8> {
+    let signature_algorithm = issuer_key.signature_algorithm(base_hash_algo);
+    let tbs = der::sequence(&[
+        &der::context(0, &[&der::integer(&[2])]), // v3
+        &der::integer(&[serial]),
+        &signature_algorithm,
+        &name(issuer),
+        &der::sequence(&[&der::utc_time(NOT_BEFORE), &der::utc_time(NOT_AFTER)]),
+        &name(subject),
+        public_key_info,
+        &der::context(3, &[&der::sequence(extensions)]),
+    ]);
+    let signature = issuer_key.sign_cert(base_hash_algo, &tbs);
+    der::sequence(&[&tbs, &signature_algorithm, &der::bit_string(0, &signature)])
+}
+
+fn name(common_name: &str) -> Vec<u8> {
+    der::sequence(&[&der::constructed(
+        der::TAG_SET,
+        &[&der::sequence(&[
+            &der::oid(OID_COMMON_NAME),
+            &der::utf8_string(common_name),
+        ])],
+    )])
+}
+
+fn extension(oid: &[u8], critical: bool, value: &[u8]) -> Vec<u8> {
+    if critical {
+        der::sequence(&[
+            &der::oid(oid),
+            &der::boolean(true),
+            &der::octet_string(value),
+        ])
+    } else {
+        der::sequence(&[&der::oid(oid), &der::octet_string(value)])
+    }
+}
+
+fn basic_constraints(ca: bool) -> Vec<u8> {
+    // cA defaults to FALSE, so DER leaves it out.
+    if ca {
+        extension(
+            OID_BASIC_CONSTRAINTS,
+            true,
+            &der::sequence(&[&der::boolean(true)]),
+        )
+    } else {
+        extension(OID_BASIC_CONSTRAINTS, true, &der::sequence(&[]))
+    }
+}
+
+fn key_usage(unused_bits: u8, bits: u8) -> Vec<u8> {
+    extension(OID_KEY_USAGE, false, &der::bit_string(unused_bits, &[bits]))
+}
+
+fn ext_key_usage(purposes: &[&[u8]]) -> Vec<u8> {
+    let purposes: Vec<Vec<u8>> = purposes.iter().map(|purpose| der::oid(purpose)).collect();
+    let purposes: Vec<&[u8]> = purposes.iter().map(|purpose| purpose.as_slice()).collect();
+    extension(OID_EXT_KEY_USAGE, true, &der::sequence(&purposes))
+}
+
+fn subject_alt_name(value: &str) -> Vec<u8> {
+    // otherName [0] { type-id, [0] EXPLICIT value }
+    let other_name = der::context(
+        0,
+        &[
+            &der::oid(OID_DMTF_OTHER_NAME),
+            &der::context(0, &[&der::utf8_string(value)]),
+        ],
+    );
+    extension(OID_SUBJECT_ALT_NAME, false, &der::sequence(&[&other_name]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spdmlib::crypto;
+
+    fn check_cert_chain(base_hash_algo: SpdmBaseHashAlgo, base_asym_algo: SpdmBaseAsymAlgo) {
+        let test_cert_chain = generate_cert_chain(base_hash_algo, base_asym_algo);
+        let cert_chain = test_cert_chain.cert_chain();
+        let config = crypto::SpdmCertChainConfig::default();
+        assert!(crypto::cert_operation::verify_cert_chain(&cert_chain, &config).is_ok());
+
+        let data = b"spdm test data";
+        let signature = test_cert_chain.sign(base_hash_algo, data);
+        assert_eq!(signature.data_size, base_asym_algo.get_size());
+        assert!(crypto::asym_verify::verify(
+            base_hash_algo,
+            base_asym_algo,
+            &cert_chain,
+            data,
+            &signature
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_cert_chain_config() {
+        let cert_chain = generate_cert_chain(
+            SpdmBaseHashAlgo::TPM_ALG_SHA_384,
+            SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P384,
+        )
+        .cert_chain();
+        let verify = |config: crypto::SpdmCertChainConfig| {
+            crypto::cert_operation::verify_cert_chain(&cert_chain, &config)
+        };
+
+        assert!(verify(crypto::SpdmCertChainConfig::new()).is_ok());
+        assert!(verify(crypto::SpdmCertChainConfig {
+            max_chain_depth: 2,
+            ..crypto::SpdmCertChainConfig::new()
+        })
+        .is_err());
+        // id-kp-codeSigning
+        assert!(verify(crypto::SpdmCertChainConfig {
+            leaf_eku: &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x03, 0x03],
+            ..crypto::SpdmCertChainConfig::new()
+        })
+        .is_err());
+        // no clock
+        assert!(verify(crypto::SpdmCertChainConfig {
+            get_unix_time_cb: Some(|| None),
+            ..crypto::SpdmCertChainConfig::new()
+        })
+        .is_err());
+        // 2050, after NOT_AFTER
+        assert!(verify(crypto::SpdmCertChainConfig {
+            get_unix_time_cb: Some(|| Some(2_524_608_000)),
+            ..crypto::SpdmCertChainConfig::new()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_generate_cert_chain() {
+        check_cert_chain(
+            SpdmBaseHashAlgo::TPM_ALG_SHA_256,
+            SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P256,
+        );
+        check_cert_chain(
+            SpdmBaseHashAlgo::TPM_ALG_SHA_384,
+            SpdmBaseAsymAlgo::TPM_ALG_ECDSA_ECC_NIST_P384,
+        );
+        check_cert_chain(
+            SpdmBaseHashAlgo::TPM_ALG_SHA_256,
+            SpdmBaseAsymAlgo::TPM_ALG_RSASSA_2048,
+        );
+        check_cert_chain(
+            SpdmBaseHashAlgo::TPM_ALG_SHA_384,
+            SpdmBaseAsymAlgo::TPM_ALG_RSAPSS_3072,
+        );
+    }
+}
@@ -0,0 +1,92 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+// Minimal DER writer, only what is needed to build test certificates.
+
+pub const TAG_BOOLEAN: u8 = 0x01;
+pub const TAG_INTEGER: u8 = 0x02;
+pub const TAG_BIT_STRING: u8 = 0x03;
+pub const TAG_OCTET_STRING: u8 = 0x04;
+pub const TAG_NULL: u8 = 0x05;
+pub const TAG_OID: u8 = 0x06;
+pub const TAG_UTF8_STRING: u8 = 0x0c;
+pub const TAG_UTC_TIME: u8 = 0x17;
+pub const TAG_SEQUENCE: u8 = 0x30;
+pub const TAG_SET: u8 = 0x31;
+
+pub fn tlv(tag: u8, value: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    let len = value.len();
+    if len < 0x80 {
+        out.push(len as u8);
+    } else if len <= 0xff {
+        out.push(0x81);
+        out.push(len as u8);
+    } else {
+        assert!(len <= 0xffff);
+        out.push(0x82);
+        out.push((len >> 8) as u8);
+        out.push(len as u8);
+    }
+    out.extend_from_slice(value);
+    out
+}
+
+pub fn constructed(tag: u8, parts: &[&[u8]]) -> Vec<u8> {
+    tlv(tag, &parts.concat())
+}
+
+pub fn sequence(parts: &[&[u8]]) -> Vec<u8> {
+    constructed(TAG_SEQUENCE, parts)
+}
+
+/// context specific, constructed [n]
+pub fn context(n: u8, parts: &[&[u8]]) -> Vec<u8> {
+    constructed(0xa0 | n, parts)
+}
+
+/// unsigned big endian integer
+pub fn integer(value: &[u8]) -> Vec<u8> {
+    let mut start = 0;
+    while start + 1 < value.len() && value[start] == 0 {
+        start += 1;
+    }
+    let mut content = Vec::new();
+    if value[start] & 0x80 != 0 {
+        content.push(0);
+    }
+    content.extend_from_slice(&value[start..]);
+    tlv(TAG_INTEGER, &content)
+}
+
+pub fn boolean(value: bool) -> Vec<u8> {
+    tlv(TAG_BOOLEAN, &[if value { 0xff } else { 0x00 }])
+}
+
+pub fn bit_string(unused_bits: u8, value: &[u8]) -> Vec<u8> {
+    let mut content = vec![unused_bits];
+    content.extend_from_slice(value);
+    tlv(TAG_BIT_STRING, &content)
+}
+
+pub fn octet_string(value: &[u8]) -> Vec<u8> {
+    tlv(TAG_OCTET_STRING, value)
+}
+
+pub fn null() -> Vec<u8> {
+    tlv(TAG_NULL, &[])
+}
+
+/// `value` is the encoded OID body
+pub fn oid(value: &[u8]) -> Vec<u8> {
+    tlv(TAG_OID, value)
+}
+
+pub fn utf8_string(value: &str) -> Vec<u8> {
+    tlv(TAG_UTF8_STRING, value.as_bytes())
+}
+
+pub fn utc_time(value: &str) -> Vec<u8> {
+    tlv(TAG_UTC_TIME, value.as_bytes())
+}
@@ -0,0 +1,13 @@
+// Copyright (c) 2021 Intel Corporation
+//
+// SPDX-License-Identifier: BSD-2-Clause-Patent
+
+#![forbid(unsafe_code)]
+
+pub mod cert_chain;
+mod der;
+
+pub use cert_chain::{generate_cert_chain, TestCertChain};
+// the algorithm types of the spdmlib the chains are generated with, for the
+// unit tests of spdmlib itself, which are built from another copy of it
+pub use spdmlib;